@@ -0,0 +1,66 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! The cyclic exchange on simulated buses of growing size: receive, process
+//! the drivers of all slaves, send.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ethercat::{
+    backend::{Backend, SimMaster, SimSlave},
+    devices::DeviceRegistry,
+    DomainIdx, PdoCfg, PdoEntryIdx, PdoEntryInfo, PdoEntryPos, PdoIdx, SlaveId,
+};
+
+fn pdo(idx: u16, entry: u16, sub: u8, bit_len: u8) -> PdoCfg {
+    let mut pdo = PdoCfg::new(PdoIdx::from(idx));
+    pdo.entries.push(PdoEntryInfo {
+        entry_idx: PdoEntryIdx::new(entry, sub),
+        bit_len,
+        name: String::new(),
+        pos: PdoEntryPos::from(0),
+    });
+    pdo
+}
+
+/// A bus of `groups` times a digital input, a digital output and an analog
+/// output terminal.
+fn bus(groups: usize) -> SimMaster {
+    let slaves = (0..groups)
+        .flat_map(|_| {
+            vec![
+                SimSlave::new("EL1002", SlaveId::new(2, 0x03ea_3052))
+                    .tx_pdo(pdo(0x1A00, 0x6000, 1, 1))
+                    .tx_pdo(pdo(0x1A01, 0x6010, 1, 1)),
+                SimSlave::new("EL2004", SlaveId::new(2, 0x07d4_3052))
+                    .rx_pdo(pdo(0x1600, 0x7000, 1, 4)),
+                SimSlave::new("EL4132", SlaveId::new(2, 0x1024_3052))
+                    .rx_pdo(pdo(0x1600, 0x7000, 0x11, 16))
+                    .rx_pdo(pdo(0x1601, 0x7010, 0x11, 16)),
+            ]
+        })
+        .collect();
+    SimMaster::new(slaves)
+}
+
+fn cycle(c: &mut Criterion) {
+    let domain = DomainIdx::new(0);
+    let mut group = c.benchmark_group("cycle");
+    for groups in [1, 10, 100] {
+        let mut master = bus(groups);
+        master.activate().unwrap();
+        let mut devices = DeviceRegistry::with_builtin().bind(&mut master).unwrap();
+        group.throughput(Throughput::Elements(3 * groups as u64));
+        group.bench_function(BenchmarkId::from_parameter(3 * groups), |b| {
+            b.iter(|| {
+                master.receive().unwrap();
+                let mut view = master.domain_view(domain).unwrap();
+                devices.process(domain, view.data_mut());
+                master.send().unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, cycle);
+criterion_main!(benches);
@@ -0,0 +1,130 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Access to single PDO entries of a process image, as done for every
+//! signal in every cycle.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use ethercat::{
+    devices::ChannelValue, AnyField, DomainIdx, DomainView, Field, FieldSet, Offset, PdoData,
+    ScaledField,
+};
+
+/// A user type spanning several entries, e.g. the statusword and the mode
+/// display of a drive.
+#[derive(Debug, Clone, Copy)]
+struct DriveStatus {
+    statusword: u16,
+    mode: i8,
+}
+
+impl PdoData for DriveStatus {
+    const BITS: u32 = 24;
+
+    fn from_raw(raw: u64) -> Self {
+        Self {
+            statusword: raw as u16,
+            mode: (raw >> 16) as i8,
+        }
+    }
+
+    fn to_raw(self) -> u64 {
+        u64::from(self.statusword) | u64::from(self.mode as u8) << 16
+    }
+}
+
+const DOMAIN: DomainIdx = DomainIdx::new(0);
+
+const fn at(byte: usize, bit: u32) -> Offset {
+    Offset { byte, bit }
+}
+
+fn field_get(c: &mut Criterion) {
+    let data = [0x5a; 64];
+    let mut group = c.benchmark_group("Field::get");
+    group.bench_function("u8", |b| {
+        let field = Field::<u8>::new(DOMAIN, at(7, 0));
+        b.iter(|| black_box(&field).get(black_box(&data)))
+    });
+    group.bench_function("i32 aligned", |b| {
+        let field = Field::<i32>::new(DOMAIN, at(8, 0));
+        b.iter(|| black_box(&field).get(black_box(&data)))
+    });
+    group.bench_function("i32 shifted", |b| {
+        let field = Field::<i32>::new(DOMAIN, at(9, 3));
+        b.iter(|| black_box(&field).get(black_box(&data)))
+    });
+    group.bench_function("bool", |b| {
+        let field = Field::<bool>::new(DOMAIN, at(3, 5));
+        b.iter(|| black_box(&field).get(black_box(&data)))
+    });
+    group.bench_function("struct", |b| {
+        let field = Field::<DriveStatus>::new(DOMAIN, at(12, 0));
+        b.iter(|| black_box(&field).get(black_box(&data)))
+    });
+    group.bench_function("scaled i16", |b| {
+        let field = ScaledField::new(Field::<i16>::new(DOMAIN, at(16, 0)), 10.0 / 32767.0, 0.0);
+        b.iter(|| black_box(&field).get(black_box(&data)))
+    });
+    group.finish();
+}
+
+fn field_set(c: &mut Criterion) {
+    let mut data = [0x5a; 64];
+    let mut group = c.benchmark_group("Field::set");
+    group.bench_function("u8", |b| {
+        let field = Field::<u8>::new(DOMAIN, at(7, 0));
+        b.iter(|| black_box(&field).set(black_box(&mut data), 0x42))
+    });
+    group.bench_function("i32 aligned", |b| {
+        let field = Field::<i32>::new(DOMAIN, at(8, 0));
+        b.iter(|| black_box(&field).set(black_box(&mut data), -1_000_000))
+    });
+    group.bench_function("i32 shifted", |b| {
+        let field = Field::<i32>::new(DOMAIN, at(9, 3));
+        b.iter(|| black_box(&field).set(black_box(&mut data), -1_000_000))
+    });
+    group.bench_function("bool", |b| {
+        let field = Field::<bool>::new(DOMAIN, at(3, 5));
+        b.iter(|| black_box(&field).set(black_box(&mut data), true))
+    });
+    group.bench_function("struct", |b| {
+        let field = Field::<DriveStatus>::new(DOMAIN, at(12, 0));
+        let status = DriveStatus {
+            statusword: 0x0237,
+            mode: 8,
+        };
+        b.iter(|| black_box(&field).set(black_box(&mut data), status))
+    });
+    group.bench_function("scaled i16", |b| {
+        let field = ScaledField::new(Field::<i16>::new(DOMAIN, at(16, 0)), 10.0 / 32767.0, 0.0);
+        b.iter(|| black_box(&field).set(black_box(&mut data), 2.5))
+    });
+    group.finish();
+}
+
+/// Many signals of a domain through a view, as a monitoring loop does.
+fn domain_view(c: &mut Criterion) {
+    let mut data = vec![0; 1024];
+    let fields: Vec<_> = (0..256)
+        .map(|i| Field::<i16>::new(DOMAIN, at(4 * i, (i % 8) as u32)))
+        .collect();
+    c.bench_function("DomainView 256 fields", |b| {
+        b.iter(|| {
+            let mut view = DomainView::new(DOMAIN, &mut data);
+            for field in &fields {
+                let value = view.get(field);
+                view.set(field, value.wrapping_add(1));
+            }
+        })
+    });
+    c.bench_function("DomainView::read_many 256 fields", |b| {
+        let fields: Vec<&dyn AnyField> = fields.iter().rev().map(|f| f as &dyn AnyField).collect();
+        let fields = FieldSet::new(&fields);
+        let mut values = vec![ChannelValue::Integer(0); fields.len()];
+        b.iter(|| DomainView::new(DOMAIN, &mut data).read_many(&fields, &mut values))
+    });
+}
+
+criterion_group!(benches, field_get, field_set, domain_view);
+criterion_main!(benches);
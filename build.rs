@@ -0,0 +1,35 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+fn main() {
+    #[cfg(feature = "grpc")]
+    grpc_service();
+}
+
+/// Generate the server and client of the gRPC service described in
+/// `proto/ethercat.proto`. The messages are defined in `src/grpc.rs`, so
+/// building does not need `protoc`.
+#[cfg(feature = "grpc")]
+fn grpc_service() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    let method = |name: &str, route: &str, message: &str| {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::{}Request", message))
+            .output_type(format!("crate::grpc::{}Response", message))
+            .codec_path("tonic::codec::ProstCodec")
+            .build()
+    };
+    let service = Service::builder()
+        .name("Master")
+        .package("ethercat")
+        .method(method("read_fields", "ReadFields", "ReadFields"))
+        .method(method("sdo_read", "SdoRead", "SdoRead"))
+        .method(method("sdo_write", "SdoWrite", "SdoWrite"))
+        .method(method("command", "Command", "Command"))
+        .build();
+    Builder::new().compile(&[service]);
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,78 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Program the station alias of a slave.
+
+#[cfg(target_os = "linux")]
+const USAGE: &str = "\
+Usage: ethercat-alias [--master N] POSITION ALIAS
+
+Write ALIAS into the SII of the slave at POSITION, with the checksum of the
+configuration area updated, and read it back from the EEPROM.  The slave uses the new alias
+after the master rescanned the bus or after a power cycle.
+
+A warning is printed if another slave on the bus already has the alias.
+An alias of 0 removes the alias.
+
+Options:
+  --master N  use master N (default 0)";
+
+#[cfg(target_os = "linux")]
+use ethercat::{diagnostics::update_sii_checksum, MasterAccess, SlavePos};
+#[cfg(target_os = "linux")]
+use ethercat_tools::Args;
+
+/// SII word of the configured station alias.
+#[cfg(target_os = "linux")]
+const ALIAS_WORD: usize = 0x04;
+/// Words of the configuration area, including its checksum.
+#[cfg(target_os = "linux")]
+const CONFIG_WORDS: usize = 0x08;
+
+#[cfg(target_os = "linux")]
+fn main() {
+    ethercat_tools::run(USAGE, || {
+        let mut args = Args::from_env(USAGE);
+        let mut master = ethercat_tools::master(&mut args, MasterAccess::ReadWrite)?;
+        let slave = SlavePos::from(args.next::<u16>("position")?);
+        let alias: u16 = args.next("alias")?;
+        args.finish()?;
+
+        if alias != 0 {
+            for i in 0..master.get_info()?.slave_count as u16 {
+                let other = master.get_slave_info(SlavePos::from(i))?;
+                if i != u16::from(slave) && other.alias == alias {
+                    eprintln!(
+                        "Warning: slave {} ({}) already has the alias {}",
+                        i, other.name, alias
+                    );
+                }
+            }
+        }
+
+        let mut words = [0; CONFIG_WORDS];
+        master.read_sii(slave, 0, &mut words)?;
+        let old = words[ALIAS_WORD];
+        words[ALIAS_WORD] = alias;
+        update_sii_checksum(&mut words);
+        master.write_sii(slave, 0, &words)?;
+
+        let mut written = [0; CONFIG_WORDS];
+        ethercat_tools::read_eeprom(&mut master, slave, 0, &mut written)?;
+        if written != words {
+            return Err("the alias read back differs from the one written".into());
+        }
+        println!(
+            "Slave {}: alias {} -> {}",
+            u16::from(slave),
+            old,
+            written[ALIAS_WORD]
+        );
+        Ok(())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
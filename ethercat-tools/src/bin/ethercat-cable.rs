@@ -0,0 +1,177 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Watch the error counters of the bus over a long time to find bad cables.
+
+#[cfg(target_os = "linux")]
+const USAGE: &str = "\
+Usage: ethercat-cable [--master N] [--interval SECONDS] [--hours H]
+                      [--csv FILE]
+
+Sample the port error counters of all slaves every SECONDS seconds (default
+10) for H hours (default 1), and locate the cables where errors originate.
+Each finding is printed when it happens, and every hour and at the end a
+report lists the links with errors, the worst first.  The master is opened
+read-only, so this can run next to the application.
+
+Options:
+  --master N          use master N (default 0)
+  --interval SECONDS  time between two samples (default 10)
+  --hours H           duration of the measurement, e.g. 0.5 (default 1)
+  --csv FILE          append the findings to FILE, as
+                      seconds,slave,port,peer slave,peer port,rx errors,lost links";
+
+#[cfg(target_os = "linux")]
+use ethercat::{
+    diagnostics::{CableFault, CableMonitor},
+    port_name, Link, MasterMonitor, SlavePos, Topology,
+};
+#[cfg(target_os = "linux")]
+use ethercat_tools::{Args, Result};
+#[cfg(target_os = "linux")]
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::Write,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(target_os = "linux")]
+const HOUR: Duration = Duration::from_secs(3600);
+
+/// Errors of one link, accumulated over the measurement.
+#[cfg(target_os = "linux")]
+#[derive(Default)]
+struct Segment {
+    peer: Option<Link>,
+    rx_errors: u64,
+    lost_links: u64,
+    /// Number of samples with errors.
+    samples: u64,
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    ethercat_tools::run(USAGE, || {
+        let mut args = Args::from_env(USAGE);
+        let master = MasterMonitor::open(args.option("master")?.unwrap_or(0))?;
+        let interval = Duration::from_secs(args.option("interval")?.unwrap_or(10));
+        let hours: f64 = args.option("hours")?.unwrap_or(1.0);
+        let csv: Option<String> = args.option("csv")?;
+        args.finish()?;
+        if !hours.is_finite() || hours <= 0.0 {
+            return Err("the duration must be positive".into());
+        }
+        let duration = Duration::from_secs_f64(hours * 3600.0);
+        let mut csv = match csv {
+            Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+            None => None,
+        };
+
+        let slaves = (0..master.get_info()?.slave_count as u16)
+            .map(|i| master.get_slave_info(SlavePos::from(i)))
+            .collect::<ethercat::Result<Vec<_>>>()?;
+        let topology = Topology::from_slaves(&slaves);
+        let links = topology
+            .nodes()
+            .iter()
+            .filter(|n| n.parent.is_some())
+            .count();
+        let mut monitor = CableMonitor::new(topology);
+        let mut segments = BTreeMap::new();
+
+        println!(
+            "Sampling {} slaves with {} links every {} s",
+            slaves.len(),
+            links,
+            interval.as_secs()
+        );
+        let start = Instant::now();
+        let mut next_report = HOUR;
+        monitor.sample(&master)?;
+        while start.elapsed() < duration {
+            thread::sleep(interval);
+            let elapsed = start.elapsed();
+            for fault in monitor.sample(&master)? {
+                println!("{:8.0} s  {}", elapsed.as_secs_f64(), fault);
+                if let Some(file) = &mut csv {
+                    write_csv(file, elapsed, &fault)?;
+                }
+                let segment: &mut Segment = segments
+                    .entry((u16::from(fault.receiver.slave), fault.receiver.port))
+                    .or_default();
+                segment.peer = fault.peer;
+                segment.rx_errors += u64::from(fault.rx_errors);
+                segment.lost_links += u64::from(fault.lost_links);
+                segment.samples += 1;
+            }
+            if elapsed >= next_report {
+                report(&segments, links, elapsed);
+                next_report += HOUR;
+            }
+        }
+        report(&segments, links, start.elapsed());
+        Ok(())
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn write_csv(file: &mut File, elapsed: Duration, fault: &CableFault) -> Result<()> {
+    let (peer_slave, peer_port) = match fault.peer {
+        Some(peer) => (
+            u16::from(peer.slave).to_string(),
+            port_name(peer.port).to_string(),
+        ),
+        None => (String::new(), String::new()),
+    };
+    writeln!(
+        file,
+        "{:.0},{},{},{},{},{},{}",
+        elapsed.as_secs_f64(),
+        u16::from(fault.receiver.slave),
+        port_name(fault.receiver.port),
+        peer_slave,
+        peer_port,
+        fault.rx_errors,
+        fault.lost_links
+    )?;
+    Ok(())
+}
+
+/// Print the links with errors, the highest error rate first.
+#[cfg(target_os = "linux")]
+fn report(segments: &BTreeMap<(u16, usize), Segment>, links: usize, elapsed: Duration) {
+    let hours = elapsed.as_secs_f64() / 3600.0;
+    println!();
+    println!(
+        "Report after {:.2} h: {} of {} links with errors",
+        hours,
+        segments.len(),
+        links
+    );
+    let mut worst: Vec<_> = segments.iter().collect();
+    worst.sort_by_key(|(_, s)| std::cmp::Reverse(s.rx_errors + s.lost_links));
+    for (i, ((slave, port), segment)) in worst.into_iter().enumerate() {
+        let peer = match segment.peer {
+            Some(peer) => format!("{}{}", u16::from(peer.slave), port_name(peer.port)),
+            None => "-".to_owned(),
+        };
+        println!(
+            "  {:>5} - {:5}  {:8} rx errors  {:6} lost links  {:8.1} errors/h  in {} samples{}",
+            format!("{}{}", slave, port_name(*port)),
+            peer,
+            segment.rx_errors,
+            segment.lost_links,
+            (segment.rx_errors + segment.lost_links) as f64 / hours,
+            segment.samples,
+            if i == 0 { "  <- worst" } else { "" }
+        );
+    }
+    println!();
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
@@ -0,0 +1,109 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Show the distributed clock state of the slaves.
+
+#[cfg(target_os = "linux")]
+const USAGE: &str = "\
+Usage: ethercat-dc [--master N] [--samples COUNT]
+
+For each slave, print whether it supports DC, and for DC slaves the
+propagation delay, the activated sync signals with their cycle times and
+the system time difference.  The first DC slave is the reference clock.
+
+With --samples, the system time difference is read COUNT times, 100 ms
+apart, and its minimum, mean and maximum are printed.  The master is
+opened read-only.
+
+Options:
+  --master N       use master N (default 0)
+  --samples COUNT  sample the system time difference COUNT times";
+
+#[cfg(target_os = "linux")]
+use ethercat::{
+    diagnostics::{DcQualityMonitor, DcStatus},
+    port_name, MasterMonitor, SlavePos,
+};
+#[cfg(target_os = "linux")]
+use ethercat_tools::Args;
+#[cfg(target_os = "linux")]
+use std::{thread, time::Duration};
+
+#[cfg(target_os = "linux")]
+fn main() {
+    ethercat_tools::run(USAGE, || {
+        let mut args = Args::from_env(USAGE);
+        let master = MasterMonitor::open(args.option("master")?.unwrap_or(0))?;
+        let samples: u32 = args.option("samples")?.unwrap_or(0);
+        args.finish()?;
+
+        let info = master.get_info()?;
+        println!("Application time: {} ns", info.app_time);
+        let mut reference = None;
+        for i in 0..info.slave_count as u16 {
+            let slave = SlavePos::from(i);
+            let info = master.get_slave_info(slave)?;
+            if !info.has_dc_system_time {
+                println!("{:3}  no DC  {}", i, info.name);
+                continue;
+            }
+            let status = DcStatus::read(&master, slave)?;
+            let role = if reference.is_none() {
+                reference = Some(slave);
+                "reference"
+            } else {
+                "DC"
+            };
+            println!(
+                "{:3}  {:9}  {}  delay {} ns  difference {} ns",
+                i, role, info.name, status.system_time_delay, status.difference
+            );
+            let delays: Vec<_> = info
+                .ports
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| p.link.link_up)
+                .map(|(i, p)| format!("{}: {} ns", port_name(i), p.delay_to_next_dc))
+                .collect();
+            println!("       port delays  {}", delays.join(", "));
+            if status.cyclic {
+                print!("       cyclic from {} ns", status.start_time);
+                if status.sync0 {
+                    print!(", SYNC0 every {} ns", status.sync0_cycle);
+                }
+                if status.sync1 {
+                    print!(", SYNC1 {} ns after SYNC0", status.sync1_cycle);
+                }
+                println!();
+            } else {
+                println!("       sync signals off");
+            }
+        }
+
+        if samples > 0 {
+            let mut monitor = DcQualityMonitor::for_bus(&master, u32::MAX)?;
+            for n in 0..samples {
+                if n > 0 {
+                    thread::sleep(Duration::from_millis(100));
+                }
+                monitor.sample(&master)?;
+            }
+            println!("System time difference over {} samples:", samples);
+            for dev in monitor.deviations() {
+                println!(
+                    "{:3}  min {} ns  mean {:.0} ns  max {} ns",
+                    u16::from(dev.slave),
+                    dev.min,
+                    dev.mean,
+                    dev.max
+                );
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
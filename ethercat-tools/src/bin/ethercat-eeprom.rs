@@ -0,0 +1,142 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Back up and write the SII (EEPROM) image of a slave.
+
+#[cfg(target_os = "linux")]
+const USAGE: &str = "\
+Usage: ethercat-eeprom [--master N] dump POSITION FILE
+       ethercat-eeprom [--master N] flash POSITION FILE [--fix-checksum]
+                       [--force]
+
+Dump the SII image of the slave at POSITION into FILE, or write the image
+in FILE to the slave.  Images are stored as little endian words, like the
+`ethercat sii_read` tool of the IgH master does.
+
+Before writing, the image is checked, including its checksum; it must be
+for a device with the vendor ID and product code of the slave.  After
+writing, the EEPROM is read back through the ESC and compared.
+
+Options:
+  --master N      use master N (default 0)
+  --fix-checksum  recompute the checksum of the image before checking it
+  --force         write even if the image is damaged or the identity differs";
+
+#[cfg(target_os = "linux")]
+use ethercat::{
+    diagnostics::{sii_identity, update_sii_checksum, EepromReport},
+    Master, MasterAccess, SlavePos,
+};
+#[cfg(target_os = "linux")]
+use ethercat_tools::{usage_error, Args, Result};
+#[cfg(target_os = "linux")]
+use std::fs;
+
+#[cfg(target_os = "linux")]
+fn main() {
+    ethercat_tools::run(USAGE, || {
+        let mut args = Args::from_env(USAGE);
+        let mut master = ethercat_tools::master(&mut args, MasterAccess::ReadWrite)?;
+        let force = args.flag("force");
+        let fix_checksum = args.flag("fix-checksum");
+        let command: String = args.next("command")?;
+        let slave = SlavePos::from(args.next::<u16>("position")?);
+        let file: String = args.next("file")?;
+        args.finish()?;
+        match command.as_str() {
+            "dump" => {
+                let words = read_image(&master, slave)?;
+                let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+                fs::write(&file, bytes)?;
+                println!("{} words written to {}", words.len(), file);
+                Ok(())
+            }
+            "flash" => flash(&mut master, slave, &file, fix_checksum, force),
+            _ => usage_error(format!("unknown command: {}", command)),
+        }
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn read_image(master: &Master, slave: SlavePos) -> Result<Vec<u16>> {
+    let mut words = vec![0; master.get_slave_info(slave)?.sii_nwords as usize];
+    if words.is_empty() {
+        return Err("the slave has no SII image".into());
+    }
+    master.read_sii(slave, 0, &mut words)?;
+    Ok(words)
+}
+
+#[cfg(target_os = "linux")]
+fn flash(
+    master: &mut Master,
+    slave: SlavePos,
+    file: &str,
+    fix_checksum: bool,
+    force: bool,
+) -> Result<()> {
+    let bytes = fs::read(file)?;
+    if bytes.len() % 2 != 0 {
+        return Err(format!("{} has an odd number of bytes", file).into());
+    }
+    let mut words: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|w| u16::from_le_bytes([w[0], w[1]]))
+        .collect();
+    if fix_checksum {
+        // word 7 holds the checksum of the configuration area
+        let stored = words.get(7).copied();
+        update_sii_checksum(&mut words);
+        if stored != words.get(7).copied() {
+            println!("Checksum of the image corrected");
+        }
+    }
+
+    let mut refuse = false;
+    let report = EepromReport::check(slave, &words);
+    if !report.is_ok() {
+        eprintln!("Image: {}", report);
+        refuse = true;
+    }
+    let info = master.get_slave_info(slave)?;
+    match sii_identity(&words) {
+        Some((id, rev)) if id == info.id => {
+            if rev.revision_number != info.rev.revision_number {
+                println!(
+                    "Revision changes from 0x{:08x} to 0x{:08x}",
+                    info.rev.revision_number, rev.revision_number
+                );
+            }
+        }
+        Some((id, _)) => {
+            eprintln!(
+                "Image is for 0x{:08x}:0x{:08x}, the slave is 0x{:08x}:0x{:08x}",
+                id.vendor_id, id.product_code, info.id.vendor_id, info.id.product_code
+            );
+            refuse = true;
+        }
+        None => refuse = true,
+    }
+    if refuse && !force {
+        return Err("not writing the image, use --force to write it anyway".into());
+    }
+
+    master.write_sii(slave, 0, &words)?;
+    // read_sii returns the copy of the master, not the EEPROM
+    let mut written = vec![0; words.len()];
+    ethercat_tools::read_eeprom(master, slave, 0, &mut written)?;
+    if written != words {
+        return Err("the image read back differs from the one written".into());
+    }
+    println!(
+        "{} words written to slave {}",
+        words.len(),
+        u16::from(slave)
+    );
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
@@ -0,0 +1,284 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Read files from slaves and update their firmware over FoE.
+
+#[cfg(target_os = "linux")]
+const USAGE: &str = "\
+Usage: ethercat-foe [--master N] read POSITION NAME FILE
+       ethercat-foe [--master N] write FILE POSITION... [--name NAME]
+                    [--password P] [--no-boot] [--timeout SECONDS]
+
+Read the file NAME from the slave at POSITION into FILE, or write FILE to
+each of the slaves at POSITION, one after the other.
+
+For writing, each slave is switched to BOOT first and back to INIT when
+done.  Then the tool waits until the slave is back on the bus with the same
+vendor ID, product code and serial number, and prints its revision.  The
+exit code is 1 if any slave failed.
+
+Options:
+  --master N         use master N (default 0)
+  --name NAME        file name on the slave (default: the name of FILE)
+  --password P       FoE password (needs the sncn feature)
+  --no-boot          write in the current state of the slave
+  --timeout SECONDS  time for state changes and the restart (default 30)";
+
+#[cfg(target_os = "linux")]
+use ethercat::{AlState, Master, MasterAccess, SlaveInfo, SlavePos};
+#[cfg(target_os = "linux")]
+use ethercat_tools::{al_state_name, usage_error, Args, Result};
+#[cfg(target_os = "linux")]
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(target_os = "linux")]
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[cfg(target_os = "linux")]
+struct Update {
+    name: String,
+    password: u32,
+    boot: bool,
+    timeout: Duration,
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    ethercat_tools::run(USAGE, || {
+        let mut args = Args::from_env(USAGE);
+        let mut master = ethercat_tools::master(&mut args, MasterAccess::ReadWrite)?;
+        let name: Option<String> = args.option("name")?;
+        let password = args.option("password")?.unwrap_or(0);
+        let boot = !args.flag("no-boot");
+        let timeout = Duration::from_secs(args.option("timeout")?.unwrap_or(30));
+        let command: String = args.next("command")?;
+        match command.as_str() {
+            "read" => {
+                let slave = SlavePos::from(args.next::<u16>("position")?);
+                let name: String = args.next("name")?;
+                let file: String = args.next("file")?;
+                args.finish()?;
+                let data = master.foe_read(slave, &name)?;
+                fs::write(&file, &data)?;
+                println!("{} bytes read into {}", data.len(), file);
+                Ok(())
+            }
+            "write" => {
+                let file: String = args.next("file")?;
+                let mut slaves = vec![SlavePos::from(args.next::<u16>("position")?)];
+                while let Some(pos) = args.next_opt::<u16>("position")? {
+                    slaves.push(SlavePos::from(pos));
+                }
+                args.finish()?;
+                if password != 0 && !cfg!(feature = "sncn") {
+                    return Err("FoE passwords need the sncn feature".into());
+                }
+                let name = match name {
+                    Some(name) => name,
+                    None => Path::new(&file)
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                };
+                let update = Update {
+                    name,
+                    password,
+                    boot,
+                    timeout,
+                };
+                let data = fs::read(&file)?;
+                write_all(master, &slaves, &update, data)
+            }
+            _ => usage_error(format!("unknown command: {}", command)),
+        }
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn write_all(
+    mut master: Master,
+    slaves: &[SlavePos],
+    update: &Update,
+    data: Vec<u8>,
+) -> Result<()> {
+    let mut failed = 0;
+    for (i, &slave) in slaves.iter().enumerate() {
+        println!(
+            "[{}/{}] slave {}: {} ({} bytes)",
+            i + 1,
+            slaves.len(),
+            u16::from(slave),
+            update.name,
+            data.len()
+        );
+        let (m, res) = write_one(master, slave, update, data.clone())?;
+        master = m;
+        match res {
+            Ok(info) => println!(
+                "        updated, revision 0x{:08x}, {}",
+                info.rev.revision_number,
+                al_state_name(info.al_state)
+            ),
+            Err(e) => {
+                println!("        failed: {}", e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} updates failed", failed, slaves.len()).into());
+    }
+    Ok(())
+}
+
+/// Update one slave; the master is moved into a thread for the transfer to
+/// show the progress meanwhile, and returned unless the thread failed.
+#[cfg(target_os = "linux")]
+fn write_one(
+    mut master: Master,
+    slave: SlavePos,
+    update: &Update,
+    data: Vec<u8>,
+) -> Result<(Master, Result<SlaveInfo>)> {
+    let before = match master.get_slave_info(slave) {
+        Ok(info) => info,
+        Err(e) => return Ok((master, Err(e.into()))),
+    };
+    if update.boot {
+        if let Err(e) = change_state(&mut master, slave, AlState::Boot, update.timeout) {
+            return Ok((master, Err(e)));
+        }
+    }
+
+    let (name, password) = (update.name.clone(), update.password);
+    let size = data.len();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let res = foe_write(&mut master, slave, &name, password, &data);
+        let _ = tx.send((master, res));
+    });
+    let start = Instant::now();
+    let (mut master, res) = loop {
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(done) => break done,
+            Err(RecvTimeoutError::Timeout) => progress(start.elapsed(), size),
+            Err(RecvTimeoutError::Disconnected) => {
+                println!();
+                return Err("the transfer thread failed, the master is lost".into());
+            }
+        }
+    };
+    println!();
+    if let Err(e) = res {
+        return Ok((master, Err(e.into())));
+    }
+
+    if update.boot {
+        if let Err(e) = master.request_state(slave, AlState::Init) {
+            return Ok((master, Err(e.into())));
+        }
+    }
+    let res = wait_for_restart(&master, slave, &before, update.timeout);
+    Ok((master, res))
+}
+
+#[cfg(target_os = "linux")]
+fn progress(elapsed: Duration, size: usize) {
+    // the master does not report the progress of a transfer, so the bar
+    // only moves to show that it is running
+    const WIDTH: usize = 20;
+    let pos = (elapsed.as_millis() / POLL_INTERVAL.as_millis()) as usize % WIDTH;
+    let bar: String = (0..WIDTH)
+        .map(|i| if i == pos { '#' } else { '-' })
+        .collect();
+    print!(
+        "\r        [{}] {:.1} s, {} bytes",
+        bar,
+        elapsed.as_secs_f64(),
+        size
+    );
+    let _ = io::stdout().flush();
+}
+
+#[cfg(all(target_os = "linux", feature = "sncn"))]
+fn foe_write(
+    master: &mut Master,
+    slave: SlavePos,
+    name: &str,
+    password: u32,
+    data: &[u8],
+) -> ethercat::Result<()> {
+    master.foe_write_with_password(slave, name, password, data)
+}
+
+#[cfg(all(target_os = "linux", not(feature = "sncn")))]
+fn foe_write(
+    master: &mut Master,
+    slave: SlavePos,
+    name: &str,
+    _password: u32,
+    data: &[u8],
+) -> ethercat::Result<()> {
+    master.foe_write(slave, name, data)
+}
+
+#[cfg(target_os = "linux")]
+fn change_state(
+    master: &mut Master,
+    slave: SlavePos,
+    state: AlState,
+    timeout: Duration,
+) -> Result<()> {
+    master.request_state(slave, state)?;
+    let start = Instant::now();
+    while master.get_slave_info(slave)?.al_state != state {
+        if start.elapsed() > timeout {
+            return Err(format!("slave did not reach {}", al_state_name(state)).into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+/// Wait until the slave is back with the identity it had before the
+/// update.
+#[cfg(target_os = "linux")]
+fn wait_for_restart(
+    master: &Master,
+    slave: SlavePos,
+    before: &SlaveInfo,
+    timeout: Duration,
+) -> Result<SlaveInfo> {
+    let start = Instant::now();
+    loop {
+        // the slave is gone while it restarts
+        if let Ok(info) = master.get_slave_info(slave) {
+            if info.al_state != AlState::Boot {
+                if info.id != before.id || info.rev.serial_number != before.rev.serial_number {
+                    return Err(format!(
+                        "slave came back as 0x{:08x}:0x{:08x} serial {}",
+                        info.id.vendor_id, info.id.product_code, info.rev.serial_number
+                    )
+                    .into());
+                }
+                return Ok(info);
+            }
+        }
+        if start.elapsed() > timeout {
+            return Err("slave did not come back after the update".into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
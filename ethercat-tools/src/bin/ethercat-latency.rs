@@ -0,0 +1,200 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Measure the timing of the cyclic thread on this machine.
+
+#[cfg(target_os = "linux")]
+const USAGE: &str = "\
+Usage: ethercat-latency [--master N] [--period US] [--minutes N] [--bucket US]
+                        [--dc]
+
+Run empty cycles with the runtime::Executor for N minutes (default 1), then
+print histograms of the wakeup latency, i.e. how late the cyclic thread
+woke up, and of the time between cycles.  With --dc, distributed clocks are
+synchronized as well and the DC deviation is recorded.
+
+No slaves are configured, but the master is activated, so the bus must not
+be used by an application meanwhile.  Run the tool with the scheduling
+policy of the application, e.g. `chrt -f 80 ethercat-latency`.
+
+Options:
+  --master N    use master N (default 0)
+  --period US   cycle time in us (default 1000)
+  --minutes N   duration of the measurement (default 1)
+  --bucket US   width of the histogram buckets in us (default 10)
+  --dc          synchronize distributed clocks and record their deviation";
+
+#[cfg(target_os = "linux")]
+use ethercat::{runtime::Executor, MasterAccess};
+#[cfg(target_os = "linux")]
+use ethercat_tools::Args;
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+
+/// Number of buckets of a histogram, without the outliers.
+#[cfg(target_os = "linux")]
+const BUCKETS: u64 = 100;
+
+/// Width of the buckets of the DC deviation in ns.
+#[cfg(target_os = "linux")]
+const DC_BUCKET: u64 = 100;
+
+#[cfg(target_os = "linux")]
+const BAR_WIDTH: u64 = 40;
+
+#[cfg(target_os = "linux")]
+fn main() {
+    ethercat_tools::run(USAGE, || {
+        let mut args = Args::from_env(USAGE);
+        let master = ethercat_tools::master(&mut args, MasterAccess::ReadWrite)?;
+        let period = Duration::from_micros(args.option("period")?.unwrap_or(1000));
+        let minutes: u64 = args.option("minutes")?.unwrap_or(1);
+        let bucket = args.option::<u64>("bucket")?.unwrap_or(10).max(1) * 1000;
+        let dc = args.flag("dc");
+        args.finish()?;
+        if period.as_nanos() == 0 {
+            return Err("the period must not be zero".into());
+        }
+
+        let period_ns = period.as_nanos() as u64;
+        let mut latency = Histogram::new(0, bucket);
+        let mut cycle_time = Histogram::new(period_ns.saturating_sub(bucket * BUCKETS / 2), bucket);
+        let mut deviation = Histogram::new(0, DC_BUCKET);
+        let cycles = minutes * 60_000_000_000 / period_ns;
+        let per_minute = (60_000_000_000 / period_ns).max(1);
+
+        master.reserve()?;
+        let mut executor = Executor::builder(master, period)
+            .distributed_clocks(dc)
+            .build()?;
+        println!(
+            "Running {} cycles of {} us, {} minute(s)",
+            cycles,
+            period.as_micros(),
+            minutes
+        );
+        let mut prev: Option<Duration> = None;
+        for n in 0..cycles {
+            executor.run_cycle(|ctx| {
+                let wakeup = ctx.wakeup_latency();
+                latency.record(wakeup.as_nanos() as u64);
+                if let Some(prev) = prev {
+                    // the cycles are scheduled exactly one period apart
+                    let ns = (period + wakeup).saturating_sub(prev).as_nanos() as u64;
+                    cycle_time.record(ns);
+                }
+                prev = Some(wakeup);
+                if let Some(dev) = ctx.dc_deviation() {
+                    deviation.record(u64::from(dev));
+                }
+                Ok(())
+            })?;
+            if (n + 1) % per_minute == 0 {
+                println!(
+                    "minute {}: max latency {} us",
+                    (n + 1) / per_minute,
+                    latency.max / 1000
+                );
+            }
+        }
+
+        latency.print("Wakeup latency", 1000, "us");
+        cycle_time.print("Cycle time", 1000, "us");
+        if dc {
+            deviation.print("DC deviation", 1, "ns");
+        }
+        Ok(())
+    })
+}
+
+/// Counts of values in buckets of equal width, starting at `start`.
+#[cfg(target_os = "linux")]
+struct Histogram {
+    start: u64,
+    width: u64,
+    counts: Vec<u64>,
+    below: u64,
+    above: u64,
+    min: u64,
+    max: u64,
+    sum: u128,
+    len: u64,
+}
+
+#[cfg(target_os = "linux")]
+impl Histogram {
+    fn new(start: u64, width: u64) -> Self {
+        Self {
+            start,
+            width,
+            counts: vec![0; BUCKETS as usize],
+            below: 0,
+            above: 0,
+            min: u64::MAX,
+            max: 0,
+            sum: 0,
+            len: 0,
+        }
+    }
+
+    fn record(&mut self, value: u64) {
+        if value < self.start {
+            self.below += 1;
+        } else {
+            match self
+                .counts
+                .get_mut(((value - self.start) / self.width) as usize)
+            {
+                Some(count) => *count += 1,
+                None => self.above += 1,
+            }
+        }
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += u128::from(value);
+        self.len += 1;
+    }
+
+    /// Print the non-empty buckets, with the values divided by `scale`.
+    fn print(&self, title: &str, scale: u64, unit: &str) {
+        println!();
+        if self.len == 0 {
+            println!("{}: no samples", title);
+            return;
+        }
+        println!(
+            "{}: min {} {unit}, mean {} {unit}, max {} {unit}",
+            title,
+            self.min / scale,
+            (self.sum / u128::from(self.len)) as u64 / scale,
+            self.max / scale,
+            unit = unit
+        );
+        let end = self.start + self.width * BUCKETS;
+        let most = self
+            .counts
+            .iter()
+            .fold(self.below.max(self.above), |a, &b| a.max(b));
+        let bar = |count: u64| "#".repeat(((count * BAR_WIDTH + most - 1) / most) as usize);
+        if self.below > 0 {
+            let label = format!("< {}", self.start / scale);
+            println!("  {:>15} {:10} {}", label, self.below, bar(self.below));
+        }
+        for (i, &count) in self.counts.iter().enumerate() {
+            if count > 0 {
+                let from = self.start + i as u64 * self.width;
+                let label = format!("{} - {}", from / scale, (from + self.width) / scale);
+                println!("  {:>15} {:10} {}", label, count, bar(count));
+            }
+        }
+        if self.above > 0 {
+            let label = format!(">= {}", end / scale);
+            println!("  {:>15} {:10} {}", label, self.above, bar(self.above));
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
@@ -0,0 +1,167 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Extract and plot recorded process data.
+
+const USAGE: &str = "\
+Usage: ethercat-log [--list] FILE...
+       ethercat-log [--fields NAMES] [--output FILE] [--plot] FILE...
+
+Read CSV or MCAP logs written by the loggers of ethercat::logging, and
+print the selected fields as CSV, aligned on a common time base: there is a
+row for each time stamp of any field, with the last value of each field.
+With several files, the fields are named FILE:NAME.
+
+Options:
+  --list           list the fields of the logs with their time range
+  --fields NAMES   comma separated fields to extract (default all)
+  --output FILE    write the CSV to FILE instead of printing it
+  --plot           draw a plot of each field in the terminal";
+
+use ethercat::logging::{align, read_log, Series};
+use ethercat_tools::{usage_error, Args, Result};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+const PLOT_WIDTH: usize = 72;
+const PLOT_HEIGHT: usize = 12;
+
+fn main() {
+    ethercat_tools::run(USAGE, || {
+        let mut args = Args::from_env(USAGE);
+        let list = args.flag("list");
+        let plot = args.flag("plot");
+        let fields: Option<String> = args.option("fields")?;
+        let output: Option<String> = args.option("output")?;
+        let mut files = vec![args.next::<String>("file")?];
+        while let Some(file) = args.next_opt("file")? {
+            files.push(file);
+        }
+        args.finish()?;
+
+        let mut series = vec![];
+        for file in &files {
+            for mut s in read_log(file).map_err(|e| format!("{}: {}", file, e))? {
+                if files.len() > 1 {
+                    s.name = format!("{}:{}", file, s.name);
+                }
+                series.push(s);
+            }
+        }
+        if list {
+            for s in &series {
+                let (first, last) = match (s.points.first(), s.points.last()) {
+                    (Some(first), Some(last)) => (first.0, last.0),
+                    _ => (0, 0),
+                };
+                println!(
+                    "{}  {} values, {:.3} s from DC time {} ns",
+                    s.name,
+                    s.points.len(),
+                    (last - first) as f64 * 1e-9,
+                    first
+                );
+            }
+            return Ok(());
+        }
+
+        let selected: Vec<&Series> = match &fields {
+            Some(fields) => fields
+                .split(',')
+                .map(|name| match series.iter().find(|s| s.name == name) {
+                    Some(s) => Ok(s),
+                    None => usage_error(format!("no field {} in the logs", name)),
+                })
+                .collect::<Result<_>>()?,
+            None => series.iter().collect(),
+        };
+        match output {
+            Some(path) => write_csv(BufWriter::new(File::create(path)?), &selected)?,
+            None if !plot => write_csv(io::stdout().lock(), &selected)?,
+            None => (),
+        }
+        if plot {
+            for s in &selected {
+                draw(s);
+            }
+        }
+        Ok(())
+    })
+}
+
+fn write_csv(mut out: impl Write, series: &[&Series]) -> Result<()> {
+    write!(out, "dc_time")?;
+    for s in series {
+        if s.name.contains(&[',', '"', '\n'][..]) {
+            write!(out, ",\"{}\"", s.name.replace('"', "\"\""))?;
+        } else {
+            write!(out, ",{}", s.name)?;
+        }
+    }
+    writeln!(out)?;
+    for (time, values) in align(series) {
+        write!(out, "{}", time)?;
+        for value in values {
+            match value {
+                Some(value) => write!(out, ",{}", value)?,
+                None => write!(out, ",")?,
+            }
+        }
+        writeln!(out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Draw the series as points in a character grid, time to the right.
+fn draw(series: &Series) {
+    println!();
+    println!("{}", series.name);
+    let finite = series.points.iter().filter(|p| p.1.is_finite());
+    let (start, end) = match (series.points.first(), series.points.last()) {
+        (Some(first), Some(last)) => (first.0, last.0),
+        _ => {
+            println!("  no values");
+            return;
+        }
+    };
+    let (min, max) = finite.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), p| {
+        (min.min(p.1), max.max(p.1))
+    });
+    if min > max {
+        println!("  no finite values");
+        return;
+    }
+    let mut grid = vec![[' '; PLOT_WIDTH]; PLOT_HEIGHT];
+    for &(time, value) in series.points.iter().filter(|p| p.1.is_finite()) {
+        let x = scale((time - start) as f64, (end - start) as f64, PLOT_WIDTH);
+        let y = scale(value - min, max - min, PLOT_HEIGHT);
+        grid[PLOT_HEIGHT - 1 - y][x] = '*';
+    }
+    for (i, row) in grid.iter().enumerate() {
+        let label = match i {
+            0 => format!("{:>12.6}", max),
+            _ if i == PLOT_HEIGHT - 1 => format!("{:>12.6}", min),
+            _ => " ".repeat(12),
+        };
+        println!("{} |{}", label, row.iter().collect::<String>());
+    }
+    println!(
+        "{} +{}\n{:>14}{:>width$}",
+        " ".repeat(12),
+        "-".repeat(PLOT_WIDTH),
+        "0 s",
+        format!("{:.3} s", (end - start) as f64 * 1e-9),
+        width = PLOT_WIDTH
+    );
+}
+
+/// Index of `value` in `0..len` cells spanning `0..=range`.
+fn scale(value: f64, range: f64, len: usize) -> usize {
+    if range <= 0.0 {
+        return len / 2;
+    }
+    ((value / range * (len - 1) as f64).round() as usize).min(len - 1)
+}
@@ -0,0 +1,181 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Show the sync managers and the PDO mapping of the slaves.
+
+#[cfg(target_os = "linux")]
+const USAGE: &str = "\
+Usage: ethercat-pdos [--master N] [--esi DIR] [POSITION]
+
+Print the sync managers of all slaves, or only of the slave at POSITION,
+with the PDOs currently assigned to them and their entries.  The offsets
+are in bytes and bits from the start of the sync manager.
+
+Names the slave does not provide are taken from its dictionary, or from the
+ESI files in DIR.
+
+Options:
+  --master N  use master N (default 0)
+  --esi DIR   look up names in the ESI files in DIR (needs the esi feature)";
+
+#[cfg(target_os = "linux")]
+use ethercat::{
+    Master, MasterAccess, PdoEntryPos, PdoPos, SdoEntryAddr, SdoIdx, SlaveInfo, SlavePos, SmIdx,
+};
+#[cfg(target_os = "linux")]
+use ethercat_tools::{Args, Result};
+
+/// Control register bits of the direction: ECAT writes, i.e. outputs.
+#[cfg(target_os = "linux")]
+const SM_DIRECTION_WRITE: u8 = 0b0100;
+/// Control register bits of the operation mode: mailbox.
+#[cfg(target_os = "linux")]
+const SM_MODE_MAILBOX: u8 = 0b0010;
+
+#[cfg(target_os = "linux")]
+fn main() {
+    ethercat_tools::run(USAGE, || {
+        let mut args = Args::from_env(USAGE);
+        let mut master = ethercat_tools::master(&mut args, MasterAccess::ReadOnly)?;
+        let esi = Esi::load(args.option("esi")?)?;
+        let position: Option<u16> = args.next_opt("position")?;
+        args.finish()?;
+        let positions = match position {
+            Some(pos) => pos..pos + 1,
+            None => 0..master.get_info()?.slave_count as u16,
+        };
+        for pos in positions {
+            print_slave(&mut master, SlavePos::from(pos), &esi)?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn print_slave(master: &mut Master, slave: SlavePos, esi: &Esi) -> Result<()> {
+    let info = master.get_slave_info(slave)?;
+    println!("Slave {}: {}", u16::from(slave), info.name);
+    for sm in 0..info.sync_count {
+        let sync = master.get_sync(slave, SmIdx::from(sm))?;
+        let kind = if sync.control_register & SM_MODE_MAILBOX != 0 {
+            "mailbox"
+        } else if sync.control_register & SM_DIRECTION_WRITE != 0 {
+            "outputs"
+        } else {
+            "inputs"
+        };
+        println!(
+            "  SM{}  0x{:04x}  {} bytes  control 0x{:02x}  {}{}",
+            sm,
+            sync.start_addr,
+            sync.default_size,
+            sync.control_register,
+            kind,
+            if sync.enable { "" } else { " (disabled)" }
+        );
+        let mut offset = 0usize;
+        for p in 0..sync.pdo_count {
+            let pdo = master.get_pdo(slave, SmIdx::from(sm), PdoPos::from(p))?;
+            let idx = u16::from(pdo.idx);
+            let name = if pdo.name.is_empty() {
+                esi.pdo_name(&info, idx).unwrap_or_default()
+            } else {
+                pdo.name
+            };
+            println!("    PDO 0x{:04x}  {}", idx, name);
+            for e in 0..pdo.entry_count {
+                let entry = master.get_pdo_entry(
+                    slave,
+                    SmIdx::from(sm),
+                    PdoPos::from(p),
+                    PdoEntryPos::from(e),
+                )?;
+                let entry_idx = entry.entry_idx;
+                let sdo = SdoIdx {
+                    idx: entry_idx.idx,
+                    sub_idx: entry_idx.sub_idx,
+                };
+                let name = if u16::from(entry_idx.idx) == 0 {
+                    "(gap)".to_owned()
+                } else if !entry.name.is_empty() {
+                    entry.name
+                } else {
+                    entry_name(master, &info, sdo, esi)
+                };
+                println!(
+                    "      {:4}.{}  {:2} bit  0x{:04x}:{:02x}  {}",
+                    offset / 8,
+                    offset % 8,
+                    entry.bit_len,
+                    u16::from(entry_idx.idx),
+                    u8::from(entry_idx.sub_idx),
+                    name
+                );
+                offset += entry.bit_len as usize;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The name of an entry from the dictionary of the slave, or else from the
+/// ESI files.
+#[cfg(target_os = "linux")]
+fn entry_name(master: &mut Master, info: &SlaveInfo, sdo: SdoIdx, esi: &Esi) -> String {
+    let slave = info.ring_pos;
+    match master.get_sdo_entry(slave, SdoEntryAddr::ByIdx(sdo)) {
+        Ok(entry) if !entry.description.is_empty() => entry.description,
+        _ => esi.entry_name(info, sdo).unwrap_or_default(),
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "esi"))]
+struct Esi(Option<ethercat::esi::EsiDatabase>);
+
+#[cfg(all(target_os = "linux", feature = "esi"))]
+impl Esi {
+    fn load(dir: Option<String>) -> Result<Self> {
+        match dir {
+            Some(dir) => Ok(Esi(Some(ethercat::esi::EsiDatabase::load_dir(dir)?))),
+            None => Ok(Esi(None)),
+        }
+    }
+
+    fn pdo_name(&self, info: &SlaveInfo, idx: u16) -> Option<String> {
+        let device = self.0.as_ref()?.lookup(info)?;
+        let mut pdos = device.rx_pdos.iter().chain(&device.tx_pdos);
+        pdos.find(|pdo| pdo.index == idx)
+            .map(|pdo| pdo.name.clone())
+    }
+
+    fn entry_name(&self, info: &SlaveInfo, sdo: SdoIdx) -> Option<String> {
+        let device = self.0.as_ref()?.lookup(info)?;
+        device.object_name(sdo).map(Into::into)
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "esi")))]
+struct Esi;
+
+#[cfg(all(target_os = "linux", not(feature = "esi")))]
+impl Esi {
+    fn load(dir: Option<String>) -> Result<Self> {
+        match dir {
+            Some(_) => Err("--esi needs the esi feature".into()),
+            None => Ok(Esi),
+        }
+    }
+
+    fn pdo_name(&self, _info: &SlaveInfo, _idx: u16) -> Option<String> {
+        None
+    }
+
+    fn entry_name(&self, _info: &SlaveInfo, _sdo: SdoIdx) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
@@ -0,0 +1,152 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Read and write the ESC registers of a slave.
+
+#[cfg(target_os = "linux")]
+const USAGE: &str = "\
+Usage: ethercat-reg [--master N] read POSITION REGISTER [LENGTH]
+       ethercat-reg [--master N] write POSITION REGISTER VALUE [--length N]
+       ethercat-reg list
+
+Read LENGTH bytes of the registers of the slave at POSITION, starting at
+REGISTER, or write the integer VALUE there as LENGTH little endian bytes.
+REGISTER is an address, e.g. 0x0110, or one of the names shown by `list`,
+whose length is the default LENGTH; for addresses it is 1.
+
+Values of up to 8 bytes are also shown as little endian integers.
+
+Options:
+  --master N  use master N (default 0)
+  --length N  number of bytes to write, at most 8";
+
+#[cfg(target_os = "linux")]
+use ethercat::{MasterAccess, SlavePos};
+#[cfg(target_os = "linux")]
+use ethercat_tools::{hex_bytes, usage_error, Args, FromArg, Result};
+
+/// Named registers: name, address, length in bytes and description.
+#[cfg(target_os = "linux")]
+const REGISTERS: &[(&str, u16, usize, &str)] = &[
+    ("type", 0x0000, 1, "ESC type"),
+    ("revision", 0x0001, 1, "ESC revision"),
+    ("build", 0x0002, 2, "ESC build"),
+    ("fmmus", 0x0004, 1, "number of FMMUs"),
+    ("syncs", 0x0005, 1, "number of sync managers"),
+    ("ram-size", 0x0006, 1, "process data RAM size in KiB"),
+    ("port-descriptor", 0x0007, 1, "types of the ports"),
+    ("features", 0x0008, 2, "supported ESC features"),
+    ("station-address", 0x0010, 2, "configured station address"),
+    ("station-alias", 0x0012, 2, "configured station alias"),
+    ("dl-control", 0x0100, 4, "data link control, loop settings"),
+    ("dl-status", 0x0110, 2, "data link status"),
+    ("al-control", 0x0120, 2, "requested AL state"),
+    ("al-status", 0x0130, 2, "current AL state and error flag"),
+    ("al-status-code", 0x0134, 2, "AL status code"),
+    ("pdi-control", 0x0140, 1, "process data interface type"),
+    ("esc-config", 0x0141, 1, "ESC configuration"),
+    ("rx-errors", 0x0300, 8, "RX error counters of ports 0-3"),
+    ("fwd-rx-errors", 0x0308, 4, "forwarded errors of ports 0-3"),
+    ("pu-errors", 0x030C, 1, "processing unit error counter"),
+    ("pdi-errors", 0x030D, 1, "PDI error counter"),
+    ("lost-links", 0x0310, 4, "lost link counters of ports 0-3"),
+    ("wd-divider", 0x0400, 2, "watchdog divider"),
+    ("wd-pdi", 0x0410, 2, "PDI watchdog time"),
+    ("wd-sm", 0x0420, 2, "process data watchdog time"),
+    ("wd-sm-status", 0x0440, 2, "process data watchdog status"),
+    ("wd-sm-counter", 0x0442, 1, "process data watchdog count"),
+    ("wd-pdi-counter", 0x0443, 1, "PDI watchdog count"),
+    ("dc-receive-times", 0x0900, 16, "receive times of ports 0-3"),
+    ("dc-system-time", 0x0910, 8, "copy of the system time"),
+    ("dc-receive-time", 0x0918, 8, "receive time of the ESC"),
+    ("dc-offset", 0x0920, 8, "system time offset"),
+    ("dc-delay", 0x0928, 4, "system time delay"),
+    ("dc-difference", 0x092C, 4, "system time difference"),
+    ("dc-speed-start", 0x0930, 2, "speed counter start"),
+    ("dc-speed-diff", 0x0932, 2, "speed counter difference"),
+    ("dc-filter-depth", 0x0934, 1, "system time filter depth"),
+    ("dc-speed-depth", 0x0935, 1, "speed counter filter depth"),
+    ("dc-cyclic-control", 0x0980, 1, "cyclic unit control"),
+    ("dc-activation", 0x0981, 1, "activation of the sync signals"),
+    ("dc-sync0-status", 0x098E, 1, "SYNC0 status"),
+    ("dc-sync1-status", 0x098F, 1, "SYNC1 status"),
+    ("dc-start-time", 0x0990, 8, "start time of the sync signals"),
+    ("dc-sync0-cycle", 0x09A0, 4, "SYNC0 cycle time"),
+    ("dc-sync1-cycle", 0x09A4, 4, "SYNC1 cycle time"),
+];
+
+#[cfg(target_os = "linux")]
+fn main() {
+    ethercat_tools::run(USAGE, || {
+        let mut args = Args::from_env(USAGE);
+        let command: String = args.next("command")?;
+        match command.as_str() {
+            "list" => {
+                args.finish()?;
+                for (name, address, len, desc) in REGISTERS {
+                    println!("0x{:04x}  {:2}  {:22} {}", address, len, name, desc);
+                }
+                Ok(())
+            }
+            "read" => {
+                let master = ethercat_tools::master(&mut args, MasterAccess::ReadOnly)?;
+                let slave = SlavePos::from(args.next::<u16>("position")?);
+                let (address, len) = register(&args.next::<String>("register")?)?;
+                let len = args.next_opt("length")?.unwrap_or(len);
+                args.finish()?;
+                let mut data = vec![0; len];
+                master.read_register(slave, address, &mut data)?;
+                match le_value(&data) {
+                    Some(value) => println!("{}  ({}, 0x{:x})", hex_bytes(&data), value, value),
+                    None => println!("{}", hex_bytes(&data)),
+                }
+                Ok(())
+            }
+            "write" => {
+                let mut master = ethercat_tools::master(&mut args, MasterAccess::ReadWrite)?;
+                let length: Option<usize> = args.option("length")?;
+                let slave = SlavePos::from(args.next::<u16>("position")?);
+                let (address, len) = register(&args.next::<String>("register")?)?;
+                let value: u64 = args.next("value")?;
+                args.finish()?;
+                let len = length.unwrap_or(len);
+                if len == 0 || len > 8 {
+                    return usage_error(format!("cannot write {} bytes at once", len));
+                }
+                if len < 8 && value >> (8 * len) != 0 {
+                    return usage_error(format!("{} does not fit into {} bytes", value, len));
+                }
+                master.write_register(slave, address, &value.to_le_bytes()[..len])?;
+                Ok(())
+            }
+            _ => usage_error(format!("unknown command: {}", command)),
+        }
+    })
+}
+
+/// Address and default length of a register given by name or address.
+#[cfg(target_os = "linux")]
+fn register(arg: &str) -> Result<(u16, usize)> {
+    if let Some((_, address, len, _)) = REGISTERS.iter().find(|r| r.0 == arg) {
+        return Ok((*address, *len));
+    }
+    match u16::from_arg(arg) {
+        Some(address) => Ok((address, 1)),
+        None => usage_error(format!("unknown register: {}", arg)),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn le_value(data: &[u8]) -> Option<u64> {
+    if data.is_empty() || data.len() > 8 {
+        return None;
+    }
+    let mut bytes = [0; 8];
+    bytes[..data.len()].copy_from_slice(data);
+    Some(u64::from_le_bytes(bytes))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
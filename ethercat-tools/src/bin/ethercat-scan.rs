@@ -0,0 +1,124 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! List the masters and their slaves, optionally with the object
+//! dictionary of each slave.
+
+#[cfg(target_os = "linux")]
+const USAGE: &str = "\
+Usage: ethercat-scan [--master N] [--dict]
+
+List the slaves of all masters, or only of master N.
+
+Options:
+  --master N  scan only master N
+  --dict      also list the object dictionary of each slave";
+
+#[cfg(target_os = "linux")]
+use ethercat::{
+    port_name, Master, MasterAccess, SdoEntryAddr, SdoIdx, SdoPos, SlavePortType, SlavePos, SubIdx,
+};
+#[cfg(target_os = "linux")]
+use ethercat_tools::{access_flags, al_state_name, Args, Result};
+
+#[cfg(target_os = "linux")]
+fn main() {
+    ethercat_tools::run(USAGE, || {
+        let mut args = Args::from_env(USAGE);
+        let master = args.option("master")?;
+        let dict = args.flag("dict");
+        args.finish()?;
+        let masters = match master {
+            Some(idx) => vec![idx],
+            None => (0..Master::master_count()? as u32).collect(),
+        };
+        for idx in masters {
+            scan(idx, dict)?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn scan(idx: u32, dict: bool) -> Result<()> {
+    let mut master = Master::open(idx, MasterAccess::ReadOnly)?;
+    let info = master.get_info()?;
+    println!(
+        "Master {}: {} slaves, link {}",
+        idx,
+        info.slave_count,
+        if info.link_up { "up" } else { "down" }
+    );
+    for pos in 0..info.slave_count as u16 {
+        let slave = master.get_slave_info(SlavePos::from(pos))?;
+        println!(
+            "  {:3}  {:5}  0x{:08x}:0x{:08x} rev 0x{:08x}  {:6}  {}",
+            pos,
+            slave.alias,
+            slave.id.vendor_id,
+            slave.id.product_code,
+            slave.rev.revision_number,
+            al_state_name(slave.al_state),
+            slave.name
+        );
+        let ports: Vec<_> = slave
+            .ports
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| !matches!(p.desc, SlavePortType::NotImplemented))
+            .map(|(i, p)| {
+                let desc = match p.desc {
+                    SlavePortType::NotImplemented => "N/A",
+                    SlavePortType::NotConfigured => "N/C",
+                    SlavePortType::EBus => "EBUS",
+                    SlavePortType::MII => "MII",
+                };
+                if p.link.link_up {
+                    format!("{}: {} -> {}", port_name(i), desc, p.next_slave)
+                } else {
+                    format!("{}: {} down", port_name(i), desc)
+                }
+            })
+            .collect();
+        println!("       ports  {}", ports.join(", "));
+        if dict {
+            print_dict(&mut master, SlavePos::from(pos), slave.sdo_count)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn print_dict(master: &mut Master, slave: SlavePos, sdo_count: u16) -> Result<()> {
+    for i in 0..sdo_count {
+        let sdo = master.get_sdo(slave, SdoPos::from(i))?;
+        let idx = u16::from(sdo.idx);
+        println!("       SDO 0x{:04x} {}", idx, sdo.name);
+        for sub in 0..=u8::from(sdo.max_sub_idx) {
+            let addr = SdoEntryAddr::ByIdx(SdoIdx {
+                idx: sdo.idx,
+                sub_idx: SubIdx::from(sub),
+            });
+            // gaps in the subindices are normal
+            let entry = match master.get_sdo_entry(slave, addr) {
+                Ok(entry) if entry.bit_len > 0 => entry,
+                _ => continue,
+            };
+            println!(
+                "         0x{:04x}:{:02x}  {}  {:<12} {:3} bit  {}",
+                idx,
+                sub,
+                access_flags(&entry.access),
+                format!("{:?}", entry.data_type),
+                entry.bit_len,
+                entry.description
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
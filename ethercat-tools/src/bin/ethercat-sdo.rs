@@ -0,0 +1,139 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Read and write SDOs of a slave.
+
+#[cfg(target_os = "linux")]
+const USAGE: &str = "\
+Usage: ethercat-sdo [--master N] upload POSITION INDEX:SUB [--type TYPE] [--hex]
+       ethercat-sdo [--master N] upload POSITION INDEX --complete
+       ethercat-sdo [--master N] download POSITION INDEX:SUB VALUE [--type TYPE]
+       ethercat-sdo [--master N] download POSITION INDEX --complete BYTES
+
+Read or write an SDO of the slave at POSITION.  Numbers can be given in
+decimal or with a 0x prefix.  The data type is taken from the dictionary of
+the slave unless given with --type.
+
+Options:
+  --master N    use master N (default 0)
+  --type TYPE   bool, int8, int16, int32, int64, uint8, uint16, uint32,
+                uint64, float, double, string, octet_string or unicode_string
+  --hex         print integers in hex
+  --complete    access all subindices of the object at once; BYTES are
+                the hex bytes of the whole object, e.g. 0201001a";
+
+#[cfg(target_os = "linux")]
+use ethercat::{DataType, Master, MasterAccess, SdoEntryAddr, SdoIdx, SlavePos};
+#[cfg(target_os = "linux")]
+use ethercat_tools::{format_value, hex_bytes, typed_entry, usage_error, Args, Result};
+
+/// Largest SDO that can be uploaded.
+#[cfg(target_os = "linux")]
+const SDO_BUFFER: usize = 4096;
+
+#[cfg(target_os = "linux")]
+fn main() {
+    ethercat_tools::run(USAGE, || {
+        let mut args = Args::from_env(USAGE);
+        let mut master = ethercat_tools::master(&mut args, MasterAccess::ReadWrite)?;
+        let data_type = args.option("type")?;
+        let hex = args.flag("hex");
+        let complete = args.flag("complete");
+        let command: String = args.next("command")?;
+        let slave = SlavePos::from(args.next::<u16>("position")?);
+        let sdo: SdoIdx = args.next("SDO index")?;
+        match (command.as_str(), complete) {
+            ("upload", false) => {
+                args.finish()?;
+                let data_type = entry_type(&mut master, slave, sdo, data_type)?;
+                let mut buf = [0; SDO_BUFFER];
+                let data = master.sdo_upload(slave, sdo, false, &mut buf)?;
+                let value = typed_entry(data_type).decode(data)?;
+                println!("{}", format_value(&value, hex));
+            }
+            ("upload", true) => {
+                args.finish()?;
+                upload_complete(&mut master, slave, sdo, hex)?;
+            }
+            ("download", false) => {
+                let text: String = args.next("value")?;
+                args.finish()?;
+                let data_type = entry_type(&mut master, slave, sdo, data_type)?;
+                let entry = typed_entry(data_type);
+                let data = entry.encode(&entry.parse(&text)?)?;
+                master.sdo_download(slave, sdo, false, &data.as_slice())?;
+            }
+            ("download", true) => {
+                let text: String = args.next("bytes")?;
+                args.finish()?;
+                let entry = typed_entry(DataType::U8Array);
+                let data = entry.encode(&entry.parse(&text)?)?;
+                master.sdo_download(slave, sdo, true, &data.as_slice())?;
+            }
+            _ => return usage_error(format!("unknown command: {}", command)),
+        }
+        Ok(())
+    })
+}
+
+/// The given data type, or the one from the dictionary of the slave.
+#[cfg(target_os = "linux")]
+fn entry_type(
+    master: &mut Master,
+    slave: SlavePos,
+    sdo: SdoIdx,
+    data_type: Option<DataType>,
+) -> Result<DataType> {
+    if let Some(data_type) = data_type {
+        return Ok(data_type);
+    }
+    match master.get_sdo_entry(slave, SdoEntryAddr::ByIdx(sdo)) {
+        Ok(entry) => Ok(entry.data_type),
+        Err(e) => Err(format!("no data type in the dictionary ({}), use --type", e).into()),
+    }
+}
+
+/// Print an object with all subindices.
+///
+/// With the `sncn` feature the object is uploaded with complete access and
+/// printed as bytes; the IgH master only supports complete access for
+/// downloads, so otherwise every subindex is read on its own.
+#[cfg(all(target_os = "linux", feature = "sncn"))]
+fn upload_complete(master: &mut Master, slave: SlavePos, sdo: SdoIdx, _hex: bool) -> Result<()> {
+    let mut buf = [0; SDO_BUFFER];
+    let data = master.sdo_upload(slave, SdoIdx::new(u16::from(sdo.idx), 0), true, &mut buf)?;
+    for (i, line) in data.chunks(16).enumerate() {
+        println!("{:04x}  {}", i * 16, hex_bytes(line));
+    }
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", not(feature = "sncn")))]
+fn upload_complete(master: &mut Master, slave: SlavePos, sdo: SdoIdx, hex: bool) -> Result<()> {
+    let idx = u16::from(sdo.idx);
+    let mut buf = [0; SDO_BUFFER];
+    let count = master
+        .sdo_upload(slave, SdoIdx::new(idx, 0), false, &mut buf)?
+        .first()
+        .copied()
+        .ok_or_else(|| format!("0x{:04x}:00 is empty, no subindex count", idx))?;
+    println!("0x{:04x}:00  {}", idx, count);
+    for sub in 1..=count {
+        let sdo = SdoIdx::new(idx, sub);
+        let line = match entry_type(master, slave, sdo, None) {
+            Ok(data_type) => {
+                let data = master.sdo_upload(slave, sdo, false, &mut buf)?;
+                format_value(&typed_entry(data_type).decode(data)?, hex)
+            }
+            // without a dictionary show the bytes
+            Err(_) => hex_bytes(master.sdo_upload(slave, sdo, false, &mut buf)?),
+        };
+        println!("0x{:04x}:{:02x}  {}", idx, sub, line);
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
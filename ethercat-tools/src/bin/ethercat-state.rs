@@ -0,0 +1,119 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Request AL states of slaves.
+
+#[cfg(target_os = "linux")]
+const USAGE: &str = "\
+Usage: ethercat-state [--master N] [--timeout SECONDS] POSITION STATE
+       ethercat-state [--master N] [--timeout SECONDS] --all STATE
+
+Request STATE (init, preop, boot, safeop or op) for the slave at POSITION,
+or for all slaves, and wait until they reached it.  If a slave refuses the
+state, its AL status code and the meaning of the code are printed and the
+exit code is 1.
+
+Note that the application owning the master may request other states.
+
+Options:
+  --master N         use master N (default 0)
+  --all              request the state for all slaves
+  --timeout SECONDS  time to wait for the state change (default 10)";
+
+#[cfg(target_os = "linux")]
+use ethercat::{diagnostics::AlStatus, AlState, Master, MasterAccess, SlavePos};
+#[cfg(target_os = "linux")]
+use ethercat_tools::{al_state_name, Args, Result};
+#[cfg(target_os = "linux")]
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// AL status register, followed by the AL status code at 0x0134.
+#[cfg(target_os = "linux")]
+const AL_STATUS: u16 = 0x0130;
+
+#[cfg(target_os = "linux")]
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[cfg(target_os = "linux")]
+fn main() {
+    ethercat_tools::run(USAGE, || {
+        let mut args = Args::from_env(USAGE);
+        let mut master = ethercat_tools::master(&mut args, MasterAccess::ReadWrite)?;
+        let all = args.flag("all");
+        let timeout = Duration::from_secs(args.option("timeout")?.unwrap_or(10));
+        let slaves: Vec<_> = if all {
+            (0..master.get_info()?.slave_count as u16)
+                .map(SlavePos::from)
+                .collect()
+        } else {
+            vec![SlavePos::from(args.next::<u16>("position")?)]
+        };
+        let state: AlState = args.next("state")?;
+        args.finish()?;
+
+        // request all states first, so the slaves change in parallel
+        for &slave in &slaves {
+            master.request_state(slave, state)?;
+        }
+        let start = Instant::now();
+        let mut failed = 0;
+        for &slave in &slaves {
+            let remaining = timeout.checked_sub(start.elapsed()).unwrap_or_default();
+            match wait_for_state(&master, slave, state, remaining)? {
+                None => println!("{:3}  {}", u16::from(slave), al_state_name(state)),
+                Some(status) => {
+                    let reason = if status.error { "refused" } else { "timed out" };
+                    println!("{:3}  {}: {}", u16::from(slave), reason, status);
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(format!(
+                "{} of {} slaves did not reach {}",
+                failed,
+                slaves.len(),
+                al_state_name(state)
+            )
+            .into());
+        }
+        Ok(())
+    })
+}
+
+/// Wait until the slave is in `state`; returns its AL status if it refused
+/// the state or did not reach it in time.
+#[cfg(target_os = "linux")]
+fn wait_for_state(
+    master: &Master,
+    slave: SlavePos,
+    state: AlState,
+    timeout: Duration,
+) -> Result<Option<AlStatus>> {
+    let start = Instant::now();
+    loop {
+        let status = al_status(master, slave)?;
+        if status.state == Some(state) && !status.error {
+            return Ok(None);
+        }
+        if status.error || start.elapsed() >= timeout {
+            return Ok(Some(status));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn al_status(master: &Master, slave: SlavePos) -> Result<AlStatus> {
+    let mut regs = [0; 6];
+    master.read_register(slave, AL_STATUS, &mut regs)?;
+    Ok(AlStatus::from_registers(regs))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
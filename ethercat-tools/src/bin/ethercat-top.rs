@@ -0,0 +1,332 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Live view of the bus in the terminal, next to a running application.
+
+#[cfg(target_os = "linux")]
+const USAGE: &str = "\
+Usage: ethercat-top [--master N] [--interval MS] [--dc-limit NS]
+                    [--shm NAME [--fields NAME,...]]
+
+Show the AL states of the slaves, the working counters of the domains, the
+DC deviations and the recent AL state changes, refreshed every MS
+milliseconds (default 500).  The master is opened read-only, so this can
+run alongside the application.  Quit with q or Esc.
+
+Options:
+  --master N        use master N (default 0)
+  --interval MS     refresh interval in ms (default 500)
+  --dc-limit NS     highlight DC deviations beyond NS ns (default 1000)
+  --shm NAME        show the process data the application publishes as
+                    shared image NAME (see runtime::SharedImage)
+  --fields NAMES    show only these comma separated fields of the image";
+
+#[cfg(target_os = "linux")]
+use crossterm::{
+    event::{self, Event, KeyCode},
+    execute,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+#[cfg(target_os = "linux")]
+use ethercat::{
+    diagnostics::{DcQualityMonitor, EventLog},
+    runtime::{LayoutEntry, SharedImageReader},
+    AlState, DomainIdx, MasterMonitor, SlaveInfo, SlavePos,
+};
+#[cfg(target_os = "linux")]
+use ethercat_tools::{al_state_name, Args, Result};
+#[cfg(target_os = "linux")]
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders, Cell, List, ListItem, Paragraph, Row, Table},
+    Frame, Terminal,
+};
+#[cfg(target_os = "linux")]
+use std::{io, time::Duration};
+
+/// Number of AL state changes kept.
+#[cfg(target_os = "linux")]
+const EVENTS: usize = 100;
+
+#[cfg(target_os = "linux")]
+struct Monitor {
+    master: MasterMonitor,
+    dc: DcQualityMonitor,
+    events: EventLog,
+    image: Option<(SharedImageReader, Vec<LayoutEntry>)>,
+}
+
+/// Everything shown in one refresh.
+#[cfg(target_os = "linux")]
+struct View {
+    link_up: bool,
+    responding: u32,
+    domains: Vec<(u16, u16)>,
+    slaves: Vec<SlaveInfo>,
+    /// Last DC deviation of the slaves with a DC system time.
+    dc: Vec<(SlavePos, i32)>,
+    dc_limit: u32,
+    /// Sequence number and fields of the shared image.
+    image: Option<(u64, Vec<(String, String)>)>,
+    events: Vec<String>,
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    ethercat_tools::run(USAGE, || {
+        let mut args = Args::from_env(USAGE);
+        let idx = args.option("master")?.unwrap_or(0);
+        let interval = Duration::from_millis(args.option("interval")?.unwrap_or(500));
+        let dc_limit = args.option("dc-limit")?.unwrap_or(1000);
+        let shm: Option<String> = args.option("shm")?;
+        let fields: Option<String> = args.option("fields")?;
+        args.finish()?;
+
+        let master = MasterMonitor::open(idx)?;
+        let image = match shm {
+            Some(name) => {
+                let reader = SharedImageReader::open(&name)?;
+                let layout = reader
+                    .layout()
+                    .iter()
+                    .filter(|e| match &fields {
+                        Some(fields) => fields.split(',').any(|f| f == e.name),
+                        None => true,
+                    })
+                    .cloned()
+                    .collect();
+                Some((reader, layout))
+            }
+            None => None,
+        };
+        let mut monitor = Monitor {
+            dc: DcQualityMonitor::for_bus(&master, dc_limit)?,
+            master,
+            events: EventLog::new(EVENTS),
+            image,
+        };
+
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+        let res = run(&mut terminal, &mut monitor, interval);
+        // restore the terminal before reporting errors
+        terminal::disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.show_cursor()?;
+        res
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn run<B: Backend>(
+    terminal: &mut Terminal<B>,
+    monitor: &mut Monitor,
+    interval: Duration,
+) -> Result<()> {
+    loop {
+        let view = monitor.sample()?;
+        terminal.draw(|f| draw(f, &view))?;
+        if event::poll(interval)? {
+            if let Event::Key(key) = event::read()? {
+                if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Monitor {
+    fn sample(&mut self) -> Result<View> {
+        let state = self.master.state()?;
+        let info = self.master.get_info()?;
+        let domains = (0..info.domain_count as usize)
+            .map(|i| {
+                let domain = self.master.domain_info(DomainIdx::from(i))?;
+                Ok((domain.working_counter, domain.expected_working_counter))
+            })
+            .collect::<Result<_>>()?;
+        let slaves = (0..info.slave_count as u16)
+            .map(|i| self.master.get_slave_info(SlavePos::from(i)))
+            .collect::<ethercat::Result<_>>()?;
+        self.dc.sample(&self.master)?;
+        self.events.poll_al_states(&self.master)?;
+        let image = self.image.as_ref().map(|(reader, layout)| {
+            let snapshot = reader.read();
+            let fields = layout
+                .iter()
+                .map(|e| (e.name.clone(), field_value(e, &snapshot.data)))
+                .collect();
+            (snapshot.seq, fields)
+        });
+        Ok(View {
+            link_up: state.link_up,
+            responding: state.slaves_responding,
+            domains,
+            slaves,
+            dc: self
+                .dc
+                .deviations()
+                .iter()
+                .map(|d| (d.slave, d.last))
+                .collect(),
+            dc_limit: self.dc.limit(),
+            image,
+            events: {
+                let mut events: Vec<_> = self.events.entries().map(|e| e.to_string()).collect();
+                events.reverse();
+                events
+            },
+        })
+    }
+}
+
+/// Format a field of the shared image according to its type name.
+#[cfg(target_os = "linux")]
+fn field_value(entry: &LayoutEntry, data: &[u8]) -> String {
+    let bits = entry.bit_len.min(64);
+    let mut raw = 0u64;
+    for i in 0..bits {
+        let bit = entry.offset.bit + i;
+        let byte = entry.offset.byte + bit as usize / 8;
+        match data.get(byte) {
+            Some(b) if b & (1 << (bit % 8)) != 0 => raw |= 1 << i,
+            Some(_) => (),
+            None => return "?".into(),
+        }
+    }
+    let signed = || {
+        let shift = 64 - bits;
+        ((raw << shift) as i64 >> shift).to_string()
+    };
+    match entry.data_type.as_str() {
+        "bool" => (raw != 0).to_string(),
+        "f32" => f32::from_bits(raw as u32).to_string(),
+        "f64" => f64::from_bits(raw).to_string(),
+        t if t.starts_with('i') => signed(),
+        _ => raw.to_string(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn draw<B: Backend>(f: &mut Frame<B>, view: &View) {
+    let fields = view.image.as_ref().map_or(0, |(_, fields)| fields.len());
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(8),
+        ])
+        .split(f.size());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(if fields > 0 {
+            [Constraint::Percentage(60), Constraint::Percentage(40)]
+        } else {
+            [Constraint::Percentage(100), Constraint::Percentage(0)]
+        })
+        .split(rows[1]);
+
+    draw_summary(f, rows[0], view);
+    draw_slaves(f, columns[0], view);
+    if let Some((seq, fields)) = &view.image {
+        let rows = fields
+            .iter()
+            .map(|(name, value)| Row::new(vec![name.clone(), value.clone()]));
+        let title = format!("Process data (cycle {})", seq);
+        let table = Table::new(rows)
+            .header(header(&["Field", "Value"]))
+            .block(block(&title))
+            .widths(&[Constraint::Percentage(60), Constraint::Percentage(40)]);
+        f.render_widget(table, columns[1]);
+    }
+    let events: Vec<_> = view
+        .events
+        .iter()
+        .map(|e| ListItem::new(e.as_str()))
+        .collect();
+    f.render_widget(List::new(events).block(block("Events")), rows[2]);
+}
+
+#[cfg(target_os = "linux")]
+fn draw_summary<B: Backend>(f: &mut Frame<B>, area: Rect, view: &View) {
+    let domains: Vec<_> = view
+        .domains
+        .iter()
+        .enumerate()
+        .map(|(i, (wc, expected))| format!("domain {}: WC {}/{}", i, wc, expected))
+        .collect();
+    let text = format!(
+        "link {}   {} of {} slaves responding   {}",
+        if view.link_up { "up" } else { "DOWN" },
+        view.responding,
+        view.slaves.len(),
+        domains.join("   ")
+    );
+    let bad = !view.link_up || view.domains.iter().any(|(wc, exp)| wc != exp);
+    let style = Style::default().fg(if bad { Color::Red } else { Color::Green });
+    f.render_widget(Paragraph::new(text).style(style).block(block("Bus")), area);
+}
+
+#[cfg(target_os = "linux")]
+fn draw_slaves<B: Backend>(f: &mut Frame<B>, area: Rect, view: &View) {
+    let rows = view.slaves.iter().map(|s| {
+        let pos = s.ring_pos;
+        let dc = view.dc.iter().find(|(slave, _)| *slave == pos);
+        let state_color = match (s.error_flag, s.al_state) {
+            (0, AlState::Op) => Color::Green,
+            (0, _) => Color::Yellow,
+            _ => Color::Red,
+        };
+        let dc_cell = match dc {
+            Some((_, dev)) if dev.unsigned_abs() > view.dc_limit => {
+                Cell::from(format!("{} ns", dev)).style(Style::default().fg(Color::Red))
+            }
+            Some((_, dev)) => Cell::from(format!("{} ns", dev)),
+            None => Cell::from("-"),
+        };
+        let state = format!(
+            "{}{}",
+            al_state_name(s.al_state),
+            if s.error_flag != 0 { " E" } else { "" }
+        );
+        Row::new(vec![
+            Cell::from(u16::from(s.ring_pos).to_string()),
+            Cell::from(s.alias.to_string()),
+            Cell::from(state).style(Style::default().fg(state_color)),
+            dc_cell,
+            Cell::from(s.name.clone()),
+        ])
+    });
+    let table = Table::new(rows)
+        .header(header(&["Pos", "Alias", "State", "DC", "Name"]))
+        .block(block("Slaves"))
+        .widths(&[
+            Constraint::Length(5),
+            Constraint::Length(6),
+            Constraint::Length(9),
+            Constraint::Length(12),
+            Constraint::Min(10),
+        ]);
+    f.render_widget(table, area);
+}
+
+#[cfg(target_os = "linux")]
+fn header(titles: &[&'static str]) -> Row<'static> {
+    Row::new(titles.to_vec()).style(Style::default().add_modifier(Modifier::BOLD))
+}
+
+#[cfg(target_os = "linux")]
+fn block(title: &str) -> Block<'_> {
+    Block::default().borders(Borders::ALL).title(title)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
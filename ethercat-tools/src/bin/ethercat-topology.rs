@@ -0,0 +1,76 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Draw the bus topology with Graphviz.
+
+#[cfg(target_os = "linux")]
+const USAGE: &str = "\
+Usage: ethercat-topology [--master N] [--svg FILE | --output FILE]
+
+Print the topology of the bus in the DOT language of Graphviz: the slaves
+with their names and aliases, junctions in bold, and the links with the
+ports they connect, the port types and the DC propagation delays.
+
+Options:
+  --master N       use master N (default 0)
+  --output FILE    write the DOT file to FILE instead of printing it
+  --svg FILE       render FILE as SVG, with the `dot` program of Graphviz";
+
+#[cfg(target_os = "linux")]
+use ethercat::{MasterMonitor, SlavePos, Topology};
+#[cfg(target_os = "linux")]
+use ethercat_tools::{usage_error, Args, Result};
+#[cfg(target_os = "linux")]
+use std::{
+    fs,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+#[cfg(target_os = "linux")]
+fn main() {
+    ethercat_tools::run(USAGE, || {
+        let mut args = Args::from_env(USAGE);
+        let master = MasterMonitor::open(args.option("master")?.unwrap_or(0))?;
+        let output: Option<String> = args.option("output")?;
+        let svg: Option<String> = args.option("svg")?;
+        args.finish()?;
+
+        let slaves = (0..master.get_info()?.slave_count as u16)
+            .map(|i| master.get_slave_info(SlavePos::from(i)))
+            .collect::<ethercat::Result<Vec<_>>>()?;
+        let dot = Topology::from_slaves(&slaves).to_dot(&slaves);
+        match (output, svg) {
+            (Some(_), Some(_)) => usage_error("--output and --svg exclude each other".into()),
+            (Some(file), None) => Ok(fs::write(file, dot)?),
+            (None, Some(file)) => render_svg(&dot, &file),
+            (None, None) => {
+                print!("{}", dot);
+                Ok(())
+            }
+        }
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn render_svg(dot: &str, file: &str) -> Result<()> {
+    let mut child = Command::new("dot")
+        .args(["-Tsvg", "-o", file])
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("cannot run dot, is Graphviz installed? {}", e))?;
+    if let Some(stdin) = &mut child.stdin {
+        stdin.write_all(dot.as_bytes())?;
+    }
+    // close stdin, so that dot sees the end of the input
+    drop(child.stdin.take());
+    if !child.wait()?.success() {
+        return Err("dot failed".into());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
@@ -0,0 +1,361 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Shared helpers of the `ethercat-*` command-line tools.
+//!
+//! The tools keep their dependencies to the `ethercat` crate itself, so the
+//! command line is parsed by the small [`Args`] parser here.
+
+use ethercat::{eds::EdsEntry, Access, AlState, DataType, SdoEntryAccess, SdoIdx, Value};
+use std::{env, fmt, process, str::FromStr};
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// A wrong command line; reported with the usage text.
+#[derive(Debug)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+pub fn usage_error<T>(msg: String) -> Result<T> {
+    Err(Box::new(UsageError(msg)))
+}
+
+/// Values that can be given on the command line.
+pub trait FromArg: Sized {
+    fn from_arg(arg: &str) -> Option<Self>;
+}
+
+macro_rules! impl_from_arg_int {
+    ($($t:ty),*) => {$(
+        impl FromArg for $t {
+            fn from_arg(arg: &str) -> Option<Self> {
+                match arg.strip_prefix("0x").or_else(|| arg.strip_prefix("0X")) {
+                    Some(hex) => <$t>::from_str_radix(hex, 16).ok(),
+                    None => arg.parse().ok(),
+                }
+            }
+        }
+    )*};
+}
+
+impl_from_arg_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64);
+
+impl FromArg for f64 {
+    fn from_arg(arg: &str) -> Option<Self> {
+        f64::from_str(arg).ok()
+    }
+}
+
+impl FromArg for String {
+    fn from_arg(arg: &str) -> Option<Self> {
+        Some(arg.to_owned())
+    }
+}
+
+impl FromArg for AlState {
+    fn from_arg(arg: &str) -> Option<Self> {
+        match arg.to_ascii_lowercase().as_str() {
+            "init" => Some(AlState::Init),
+            "preop" => Some(AlState::PreOp),
+            "boot" => Some(AlState::Boot),
+            "safeop" => Some(AlState::SafeOp),
+            "op" => Some(AlState::Op),
+            _ => None,
+        }
+    }
+}
+
+/// An SDO address as `index:subindex`, e.g. `0x6040:0`; the subindex
+/// defaults to 0.
+impl FromArg for SdoIdx {
+    fn from_arg(arg: &str) -> Option<Self> {
+        let mut parts = arg.splitn(2, ':');
+        let idx = u16::from_arg(parts.next()?)?;
+        let sub = match parts.next() {
+            Some(sub) => u8::from_arg(sub)?,
+            None => 0,
+        };
+        Some(SdoIdx::new(idx, sub))
+    }
+}
+
+/// Data types by the names of the `ethercat` tool of the IgH master.
+const TYPE_NAMES: &[(&str, DataType)] = &[
+    ("bool", DataType::Bool),
+    ("int8", DataType::I8),
+    ("int16", DataType::I16),
+    ("int32", DataType::I32),
+    ("int64", DataType::I64),
+    ("uint8", DataType::U8),
+    ("uint16", DataType::U16),
+    ("uint32", DataType::U32),
+    ("uint64", DataType::U64),
+    ("float", DataType::F32),
+    ("double", DataType::F64),
+    ("string", DataType::String),
+    ("octet_string", DataType::U8Array),
+    ("unicode_string", DataType::U16Array),
+];
+
+impl FromArg for DataType {
+    fn from_arg(arg: &str) -> Option<Self> {
+        let arg = arg.to_ascii_lowercase();
+        TYPE_NAMES
+            .iter()
+            .find(|(name, _)| *name == arg)
+            .map(|(_, data_type)| *data_type)
+    }
+}
+
+/// The name of a data type for `--type`, or its debug name if there is none.
+pub fn type_name(data_type: DataType) -> String {
+    match TYPE_NAMES.iter().find(|(_, t)| *t == data_type) {
+        Some((name, _)) => (*name).to_owned(),
+        None => format!("{:?}", data_type),
+    }
+}
+
+/// An entry without limits to decode, parse and encode values of a type.
+pub fn typed_entry(data_type: DataType) -> EdsEntry {
+    EdsEntry {
+        sub_index: 0,
+        name: String::new(),
+        data_type,
+        access: Access::ReadWrite,
+        pdo_mapping: false,
+        low_limit: None,
+        high_limit: None,
+        default: None,
+        value: None,
+    }
+}
+
+/// Bytes as space separated hex.
+pub fn hex_bytes(data: &[u8]) -> String {
+    let hex: Vec<_> = data.iter().map(|b| format!("{:02x}", b)).collect();
+    hex.join(" ")
+}
+
+/// Display a value, integers in hex if `hex` is set.
+pub fn format_value(value: &Value, hex: bool) -> String {
+    macro_rules! int {
+        ($v:expr, $width:expr) => {
+            if hex {
+                format!("0x{:01$x}", $v, $width)
+            } else {
+                $v.to_string()
+            }
+        };
+    }
+    match value {
+        Value::Bool(v)
+        | Value::Bit1(v)
+        | Value::Bit2(v)
+        | Value::Bit3(v)
+        | Value::Bit4(v)
+        | Value::Bit5(v)
+        | Value::Bit6(v)
+        | Value::Bit7(v)
+        | Value::Bit8(v) => v.to_string(),
+        Value::Byte(v) | Value::U8(v) => int!(v, 2),
+        Value::I8(v) => int!(v, 2),
+        Value::I16(v) => int!(v, 4),
+        Value::I32(v) => int!(v, 8),
+        Value::I64(v) => int!(v, 16),
+        Value::U16(v) => int!(v, 4),
+        Value::U32(v) => int!(v, 8),
+        Value::U64(v) => int!(v, 16),
+        Value::F32(v) => v.to_string(),
+        Value::F64(v) => v.to_string(),
+        Value::String(v) => format!("{:?}", v),
+        Value::U8Array(v) | Value::Raw(v) => hex_bytes(v),
+        Value::U16Array(v) => String::from_utf16_lossy(v),
+    }
+}
+
+/// The display name of an AL state, as used by the `ethercat` tool of the
+/// IgH master.
+pub fn al_state_name(state: AlState) -> &'static str {
+    match state {
+        AlState::Init => "INIT",
+        AlState::PreOp => "PREOP",
+        AlState::Boot => "BOOT",
+        AlState::SafeOp => "SAFEOP",
+        AlState::Op => "OP",
+    }
+}
+
+/// The access of an SDO entry in PREOP, SAFEOP and OP as `rwrwrw`.
+pub fn access_flags(access: &SdoEntryAccess) -> String {
+    [access.pre_op, access.safe_op, access.op]
+        .iter()
+        .map(|a| match a {
+            Access::ReadOnly => "r-",
+            Access::WriteOnly => "-w",
+            Access::ReadWrite => "rw",
+            Access::Unknown => "??",
+        })
+        .collect()
+}
+
+/// A minimal command line parser: options are taken out by name, the
+/// remaining arguments are positionals.
+#[derive(Debug)]
+pub struct Args {
+    args: Vec<String>,
+}
+
+impl Args {
+    /// Take the arguments of the process; prints `usage` and exits on
+    /// `-h`/`--help`.
+    pub fn from_env(usage: &str) -> Self {
+        let args = Self::new(env::args().skip(1));
+        if args.args.iter().any(|a| a == "-h" || a == "--help") {
+            println!("{}", usage);
+            process::exit(0);
+        }
+        args
+    }
+
+    pub fn new<I: IntoIterator<Item = String>>(args: I) -> Self {
+        Self {
+            args: args.into_iter().collect(),
+        }
+    }
+
+    /// Whether the flag `--name` is given.
+    pub fn flag(&mut self, name: &str) -> bool {
+        let opt = format!("--{}", name);
+        let len = self.args.len();
+        self.args.retain(|a| *a != opt);
+        self.args.len() != len
+    }
+
+    /// The value of the option `--name`, given as `--name value` or
+    /// `--name=value`.
+    pub fn option<T: FromArg>(&mut self, name: &str) -> Result<Option<T>> {
+        let opt = format!("--{}", name);
+        let prefix = format!("{}=", opt);
+        let (pos, value) = match self.args.iter().position(|a| *a == opt) {
+            Some(pos) if pos + 1 < self.args.len() => {
+                let value = self.args.remove(pos + 1);
+                (pos, value)
+            }
+            Some(_) => return usage_error(format!("{} needs a value", opt)),
+            None => match self.args.iter().position(|a| a.starts_with(&prefix)) {
+                Some(pos) => (pos, self.args[pos][prefix.len()..].to_owned()),
+                None => return Ok(None),
+            },
+        };
+        self.args.remove(pos);
+        match T::from_arg(&value) {
+            Some(v) => Ok(Some(v)),
+            None => usage_error(format!("invalid value for {}: {}", opt, value)),
+        }
+    }
+
+    /// The next positional argument, if any.
+    pub fn next_opt<T: FromArg>(&mut self, what: &str) -> Result<Option<T>> {
+        match self.args.iter().position(|a| !a.starts_with("--")) {
+            Some(pos) => {
+                let arg = self.args.remove(pos);
+                match T::from_arg(&arg) {
+                    Some(v) => Ok(Some(v)),
+                    None => usage_error(format!("invalid {}: {}", what, arg)),
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The next positional argument.
+    pub fn next<T: FromArg>(&mut self, what: &str) -> Result<T> {
+        match self.next_opt(what)? {
+            Some(v) => Ok(v),
+            None => usage_error(format!("missing {}", what)),
+        }
+    }
+
+    /// Check that all arguments have been used.
+    pub fn finish(self) -> Result<()> {
+        match self.args.first() {
+            Some(arg) => usage_error(format!("unexpected argument: {}", arg)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Open the master selected with `--master` (default 0).
+#[cfg(target_os = "linux")]
+pub fn master(args: &mut Args, access: ethercat::MasterAccess) -> Result<ethercat::Master> {
+    let idx = args.option("master")?.unwrap_or(0);
+    Ok(ethercat::Master::open(idx, access)?)
+}
+
+/// Read words of the EEPROM of a slave through its ESC, e.g. to verify a
+/// write: the SII read through the master is the copy of its last scan.
+#[cfg(target_os = "linux")]
+pub fn read_eeprom(
+    master: &mut ethercat::Master,
+    slave: ethercat::SlavePos,
+    offset: u16,
+    target: &mut [u16],
+) -> Result<()> {
+    // every register access waits for the master
+    let timeout = std::time::Duration::from_secs(1);
+    Ok(ethercat::backend::read_eeprom(
+        master, slave, offset, target, timeout,
+    )?)
+}
+
+/// Run the tool's `main`, reporting errors and setting the exit code:
+/// 2 for a wrong command line, 1 for other errors.
+pub fn run<F: FnOnce() -> Result<()>>(usage: &str, main: F) {
+    if let Err(e) = main() {
+        eprintln!("Error: {}", e);
+        if e.is::<UsageError>() {
+            eprintln!("\n{}", usage);
+            process::exit(2);
+        }
+        process::exit(1);
+    }
+}
+
+#[test]
+fn test_args() {
+    let mut args = Args::new(
+        [
+            "--master=1",
+            "3",
+            "0x6040:1",
+            "--dict",
+            "--timeout",
+            "500",
+            "op",
+        ]
+        .iter()
+        .map(|s| s.to_string()),
+    );
+    assert!(args.flag("dict"));
+    assert!(!args.flag("dict"));
+    assert_eq!(args.option::<u32>("master").unwrap(), Some(1));
+    assert_eq!(args.option::<u64>("timeout").unwrap(), Some(500));
+    assert_eq!(args.option::<u64>("other").unwrap(), None);
+    assert_eq!(args.next::<u16>("position").unwrap(), 3);
+    assert_eq!(args.next::<SdoIdx>("SDO").unwrap(), SdoIdx::new(0x6040, 1));
+    assert!(args.next::<u8>("value").is_err());
+    let mut args = Args::new(vec!["op".into(), "uint16".into(), "x".into()]);
+    assert_eq!(args.next::<AlState>("state").unwrap(), AlState::Op);
+    let data_type = args.next::<DataType>("type").unwrap();
+    let value = typed_entry(data_type).parse("0x1234").unwrap();
+    assert_eq!(format_value(&value, true), "0x1234");
+    assert_eq!(format_value(&value, false), "4660");
+    assert!(args.finish().is_err());
+}
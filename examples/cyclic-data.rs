@@ -1,11 +1,12 @@
+#[cfg(target_os = "linux")]
 use ethercat::{
     AlState, DomainIdx as DomainIndex, Idx, Master, MasterAccess, Offset, PdoCfg, PdoEntryIdx,
-    PdoEntryIdx as PdoEntryIndex, PdoEntryInfo, PdoEntryPos, PdoIdx, SlaveAddr, SlaveId, SlavePos,
-    SmCfg, SubIdx,
+    PdoEntryInfo, PdoEntryPos, PdoIdx, SlaveAddr, SlaveId, SlavePos, SmCfg, SubIdx,
 };
+#[cfg(target_os = "linux")]
 use ethercat_esi::EtherCatInfo;
+#[cfg(target_os = "linux")]
 use std::{
-    collections::HashMap,
     env,
     fs::File,
     io::{self, prelude::*},
@@ -13,8 +14,17 @@ use std::{
     time::Duration,
 };
 
-type BitLen = u8;
+/// A PDO entry located in the domain when configuring the slave, so the
+/// cycle reads it at a fixed offset.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct Entry {
+    idx: PdoEntryIdx,
+    bit_len: u8,
+    offset: Offset,
+}
 
+#[cfg(target_os = "linux")]
 pub fn main() -> Result<(), io::Error> {
     env_logger::init();
     let args: Vec<_> = env::args().collect();
@@ -31,21 +41,21 @@ pub fn main() -> Result<(), io::Error> {
     let mut esi_xml_string = String::new();
     esi_file.read_to_string(&mut esi_xml_string)?;
     let esi = EtherCatInfo::from_xml_str(&esi_xml_string)?;
-    let (mut master, domain_idx, offsets) = init_master(&esi, 0_u32)?;
-    for (s, o) in &offsets {
+    let (master, domain_idx, slaves) = init_master(&esi, 0_u32)?;
+    for (s, entries) in &slaves {
         log::info!("PDO offsets of Slave {}:", u16::from(*s));
-        for (pdo, (bit_len, offset)) in o {
+        for e in entries {
             log::info!(
                 " - {:X}:{:X} - {:?}, bit length: {}",
-                u16::from(pdo.idx),
-                u8::from(pdo.sub_idx),
-                offset,
-                bit_len
+                u16::from(e.idx.idx),
+                u8::from(e.idx.sub_idx),
+                e.offset,
+                e.bit_len
             );
         }
     }
     let cycle_time = Duration::from_micros(50_000);
-    master.activate()?;
+    let mut master = master.activate()?;
 
     loop {
         master.receive()?;
@@ -57,30 +67,29 @@ pub fn main() -> Result<(), io::Error> {
         log::debug!("Master state: {:?}", m_state);
         log::debug!("Domain state: {:?}", d_state);
         if m_state.link_up && m_state.al_states == 8 {
-            let raw_data = master.domain_data(domain_idx);
-            log::debug!("{:?}", raw_data);
+            let view = master.domain_view(domain_idx)?;
+            for (s, entries) in &slaves {
+                for e in entries.iter().filter(|e| e.bit_len <= 64) {
+                    let value = view.read(e.offset, u32::from(e.bit_len));
+                    log::debug!("Slave {} {:?}: {:#x}", u16::from(*s), e.idx, value);
+                }
+            }
         }
         thread::sleep(cycle_time);
     }
 }
 
+#[cfg(target_os = "linux")]
 pub fn init_master(
     esi: &EtherCatInfo,
     idx: u32,
-) -> Result<
-    (
-        Master,
-        DomainIndex,
-        HashMap<SlavePos, HashMap<PdoEntryIndex, (BitLen, Offset)>>,
-    ),
-    io::Error,
-> {
+) -> Result<(Master, DomainIndex, Vec<(SlavePos, Vec<Entry>)>), io::Error> {
     let mut master = Master::open(idx, MasterAccess::ReadWrite)?;
     log::debug!("Reserve master");
     master.reserve()?;
     log::debug!("Create domain");
     let domain_idx = master.create_domain()?;
-    let mut offsets: HashMap<SlavePos, HashMap<PdoEntryIndex, (u8, Offset)>> = HashMap::new();
+    let mut slaves = vec![];
 
     for (dev_nr, dev) in esi.description.devices.iter().enumerate() {
         let slave_pos = SlavePos::from(dev_nr as u16);
@@ -94,7 +103,7 @@ pub fn init_master(
             product_code: dev.product_code,
         };
         let mut config = master.configure_slave(slave_addr, slave_id)?;
-        let mut entry_offsets: HashMap<PdoEntryIndex, (u8, Offset)> = HashMap::new();
+        let mut entries = vec![];
 
         let rx_pdos: Vec<PdoCfg> = dev
             .rx_pdo
@@ -150,16 +159,22 @@ pub fn init_master(
             // Positions of RX PDO
             log::debug!("Positions of RX PDO 0x{:X}:", u16::from(pdo.idx));
             for entry in &pdo.entries {
-                let offset = config.register_pdo_entry(entry.entry_idx, domain_idx)?;
-                entry_offsets.insert(entry.entry_idx, (entry.bit_len, offset));
+                entries.push(Entry {
+                    idx: entry.entry_idx,
+                    bit_len: entry.bit_len,
+                    offset: config.register_pdo_entry(entry.entry_idx, domain_idx)?,
+                });
             }
         }
         for pdo in &tx_pdos {
             // Positions of TX PDO
             log::debug!("Positions of TX PDO 0x{:X}:", u16::from(pdo.idx));
             for entry in &pdo.entries {
-                let offset = config.register_pdo_entry(entry.entry_idx, domain_idx)?;
-                entry_offsets.insert(entry.entry_idx, (entry.bit_len, offset));
+                entries.push(Entry {
+                    idx: entry.entry_idx,
+                    bit_len: entry.bit_len,
+                    offset: config.register_pdo_entry(entry.entry_idx, domain_idx)?,
+                });
             }
         }
 
@@ -172,7 +187,12 @@ pub fn init_master(
                 "Unable to configure slave",
             ));
         }
-        offsets.insert(slave_pos, entry_offsets);
+        slaves.push((slave_pos, entries));
     }
-    Ok((master, domain_idx, offsets))
+    Ok((master, domain_idx, slaves))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
 }
@@ -1,5 +1,7 @@
+#[cfg(target_os = "linux")]
 use ethercat::{Master, MasterAccess};
 
+#[cfg(target_os = "linux")]
 fn main() -> Result<(), std::io::Error> {
     let mut master = Master::open(0, MasterAccess::ReadWrite)?;
 
@@ -20,3 +22,8 @@ fn main() -> Result<(), std::io::Error> {
     println!("FoE data: {:x?}, {} bytes", res, res.len());
     Ok(())
 }
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
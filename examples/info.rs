@@ -1,8 +1,15 @@
+#[cfg(target_os = "linux")]
 use ethercat::{Master, MasterAccess};
 
+#[cfg(target_os = "linux")]
 pub fn main() -> Result<(), std::io::Error> {
     let master = Master::open(0, MasterAccess::ReadWrite)?;
     let info = master.get_info();
     println!("EtherCAT Master: {:#?}", info);
     Ok(())
 }
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
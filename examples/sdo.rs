@@ -1,5 +1,7 @@
+#[cfg(target_os = "linux")]
 use ethercat::{AlState, Master, MasterAccess, SdoEntryAddr, SdoIdx, SdoPos, SlavePos, SubIdx};
 
+#[cfg(target_os = "linux")]
 pub fn main() -> Result<(), std::io::Error> {
     let slave_pos = SlavePos::from(0);
     let mut master = Master::open(0, MasterAccess::ReadWrite)?;
@@ -24,3 +26,8 @@ pub fn main() -> Result<(), std::io::Error> {
     }
     Ok(())
 }
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The IgH master is only available on Linux");
+}
@@ -0,0 +1,409 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Alarms raised by drivers and monitors, handled like on a PLC.
+//!
+//! An alarm is identified by its source and kind; raising it again while it
+//! is pending only updates it. It stays in the [`AlarmTable`] until its
+//! condition is gone *and* it has been acknowledged, so short incidents are
+//! not missed by the operator.
+
+use crate::{
+    diagnostics::{CableFault, DcAlarm, Emergency},
+    runtime::WcAnomaly,
+    types::*,
+};
+use std::{fmt, time::SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+/// The part of the bus an alarm relates to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmSource {
+    Master,
+    Slave(SlavePos),
+    Config(SlaveConfigIdx),
+    Domain(DomainIdx),
+    /// Raised by the application or a driver.
+    Application(&'static str),
+}
+
+impl fmt::Display for AlarmSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlarmSource::Master => write!(f, "master"),
+            AlarmSource::Slave(slave) => write!(f, "slave {}", u16::from(*slave)),
+            AlarmSource::Config(config) => write!(f, "config {}", config),
+            AlarmSource::Domain(domain) => write!(f, "domain {}", usize::from(*domain)),
+            AlarmSource::Application(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmKind {
+    /// An emergency with the given error code.
+    Emergency(u16),
+    CableFault,
+    DcDeviation,
+    WorkingCounter,
+    SlaveOffline,
+    /// A slave is not in the requested AL state.
+    AlState,
+    /// Application defined alarm code.
+    Custom(u32),
+}
+
+/// A condition to be signaled to the operator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alarm {
+    pub source: AlarmSource,
+    pub kind: AlarmKind,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Alarm {
+    pub fn new(
+        source: AlarmSource,
+        kind: AlarmKind,
+        severity: Severity,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            source,
+            kind,
+            severity,
+            message: message.into(),
+        }
+    }
+
+    pub fn emergency(config: SlaveConfigIdx, emergency: &Emergency) -> Self {
+        Self::new(
+            AlarmSource::Config(config),
+            AlarmKind::Emergency(emergency.error_code),
+            Severity::Error,
+            emergency.to_string(),
+        )
+    }
+
+    /// The alarm for a working counter anomaly, `None` if the domain is
+    /// complete.
+    pub fn working_counter(domain: DomainIdx, anomaly: &WcAnomaly) -> Option<Self> {
+        let (severity, message) = match *anomaly {
+            WcAnomaly::None => return None,
+            WcAnomaly::SlaveMissing { slave } => (
+                Severity::Error,
+                format!("slave {} missing", u16::from(slave)),
+            ),
+            WcAnomaly::SegmentDown { first } => (
+                Severity::Error,
+                format!("slaves from {} on missing", u16::from(first)),
+            ),
+            WcAnomaly::SlavesMissing { deficit } => (
+                Severity::Error,
+                format!("working counter {} short", deficit),
+            ),
+            WcAnomaly::Intermittent { dropouts } => (
+                Severity::Warning,
+                format!("intermittent working counter, {} dropouts", dropouts),
+            ),
+        };
+        Some(Self::new(
+            AlarmSource::Domain(domain),
+            AlarmKind::WorkingCounter,
+            severity,
+            message,
+        ))
+    }
+}
+
+impl From<&DcAlarm> for Alarm {
+    fn from(alarm: &DcAlarm) -> Self {
+        Self::new(
+            AlarmSource::Slave(alarm.slave),
+            AlarmKind::DcDeviation,
+            Severity::Warning,
+            alarm.to_string(),
+        )
+    }
+}
+
+impl From<&CableFault> for Alarm {
+    fn from(fault: &CableFault) -> Self {
+        Self::new(
+            AlarmSource::Slave(fault.receiver.slave),
+            AlarmKind::CableFault,
+            Severity::Warning,
+            fault.to_string(),
+        )
+    }
+}
+
+impl fmt::Display for Alarm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}: {}", self.severity, self.source, self.message)
+    }
+}
+
+/// Identifies an entry of an [`AlarmTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AlarmId(u64);
+
+/// An alarm in an [`AlarmTable`].
+#[derive(Debug, Clone)]
+pub struct AlarmEntry {
+    pub id: AlarmId,
+    pub alarm: Alarm,
+    /// When the alarm was (last) raised while not pending.
+    pub raised: SystemTime,
+    /// How often the alarm was raised while pending.
+    pub count: u32,
+    /// The condition is still present.
+    pub active: bool,
+    pub acknowledged: bool,
+}
+
+/// A change of an alarm, passed to the listeners of an [`AlarmTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmTransition {
+    Raised,
+    Acknowledged,
+    /// The condition is gone, but the alarm still awaits acknowledgment.
+    Cleared,
+    /// The alarm is cleared and acknowledged and has left the table.
+    Removed,
+}
+
+type Listener = Box<dyn FnMut(&AlarmEntry, AlarmTransition) + Send>;
+
+/// The pending alarms of an application.
+///
+/// Like the other monitors, the table is owned by one thread; share it in an
+/// `Arc<Mutex<_>>` to raise alarms from several places.
+#[derive(Default)]
+pub struct AlarmTable {
+    entries: Vec<AlarmEntry>,
+    next_id: u64,
+    listeners: Vec<Listener>,
+}
+
+impl AlarmTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a function called on every transition of an alarm.
+    pub fn add_listener<F>(&mut self, listener: F)
+    where
+        F: FnMut(&AlarmEntry, AlarmTransition) + Send + 'static,
+    {
+        self.listeners.push(Box::new(listener));
+    }
+
+    /// Raise an alarm, or update it if it is already pending.
+    pub fn raise(&mut self, alarm: Alarm) -> AlarmId {
+        let now = SystemTime::now();
+        let pos = self
+            .entries
+            .iter()
+            .position(|e| e.alarm.source == alarm.source && e.alarm.kind == alarm.kind);
+        let pos = match pos {
+            Some(pos) => {
+                let entry = &mut self.entries[pos];
+                let reraised = !entry.active;
+                entry.alarm = alarm;
+                entry.count += 1;
+                if !reraised {
+                    return entry.id;
+                }
+                entry.active = true;
+                entry.acknowledged = false;
+                entry.raised = now;
+                pos
+            }
+            None => {
+                self.next_id += 1;
+                self.entries.push(AlarmEntry {
+                    id: AlarmId(self.next_id),
+                    alarm,
+                    raised: now,
+                    count: 1,
+                    active: true,
+                    acknowledged: false,
+                });
+                self.entries.len() - 1
+            }
+        };
+        self.notify(pos, AlarmTransition::Raised);
+        self.entries[pos].id
+    }
+
+    /// Signal that the condition of an alarm is gone.
+    pub fn clear(&mut self, source: AlarmSource, kind: AlarmKind) {
+        if let Some(pos) = self
+            .entries
+            .iter()
+            .position(|e| e.active && e.alarm.source == source && e.alarm.kind == kind)
+        {
+            self.entries[pos].active = false;
+            self.notify(pos, AlarmTransition::Cleared);
+            self.remove_if_done(pos);
+        }
+    }
+
+    /// Acknowledge an alarm, returns false if it is not pending.
+    pub fn acknowledge(&mut self, id: AlarmId) -> bool {
+        match self
+            .entries
+            .iter()
+            .position(|e| e.id == id && !e.acknowledged)
+        {
+            Some(pos) => {
+                self.entries[pos].acknowledged = true;
+                self.notify(pos, AlarmTransition::Acknowledged);
+                self.remove_if_done(pos);
+                true
+            }
+            None => self.get(id).is_some(),
+        }
+    }
+
+    pub fn acknowledge_all(&mut self) {
+        let ids = self
+            .entries
+            .iter()
+            .filter(|e| !e.acknowledged)
+            .map(|e| e.id)
+            .collect::<Vec<_>>();
+        for id in ids {
+            self.acknowledge(id);
+        }
+    }
+
+    /// Pending alarms, in the order they were first raised.
+    pub fn alarms(&self) -> impl Iterator<Item = &AlarmEntry> + '_ {
+        self.entries.iter()
+    }
+
+    /// Alarms whose condition is still present.
+    pub fn active(&self) -> impl Iterator<Item = &AlarmEntry> + '_ {
+        self.entries.iter().filter(|e| e.active)
+    }
+
+    pub fn unacknowledged(&self) -> impl Iterator<Item = &AlarmEntry> + '_ {
+        self.entries.iter().filter(|e| !e.acknowledged)
+    }
+
+    pub fn get(&self, id: AlarmId) -> Option<&AlarmEntry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    /// Highest severity of the active alarms.
+    pub fn severity(&self) -> Option<Severity> {
+        self.active().map(|e| e.alarm.severity).max()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn notify(&mut self, pos: usize, transition: AlarmTransition) {
+        let entry = &self.entries[pos];
+        for listener in &mut self.listeners {
+            listener(entry, transition);
+        }
+    }
+
+    fn remove_if_done(&mut self, pos: usize) {
+        let entry = &self.entries[pos];
+        if !entry.active && entry.acknowledged {
+            self.notify(pos, AlarmTransition::Removed);
+            self.entries.remove(pos);
+        }
+    }
+}
+
+impl fmt::Debug for AlarmTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AlarmTable")
+            .field("entries", &self.entries)
+            .field("listeners", &self.listeners.len())
+            .finish()
+    }
+}
+
+#[test]
+fn test_alarm_table() {
+    use std::sync::{Arc, Mutex};
+
+    let transitions = Arc::new(Mutex::new(vec![]));
+    let seen = transitions.clone();
+    let mut table = AlarmTable::new();
+    table.add_listener(move |entry, transition| {
+        seen.lock().unwrap().push((entry.id, transition));
+    });
+
+    let dc = DcAlarm {
+        slave: SlavePos::from(2),
+        deviation: -1500,
+        limit: 1000,
+    };
+    let a = table.raise(Alarm::from(&dc));
+    assert_eq!(table.raise(Alarm::from(&dc)), a);
+    let wc = Alarm::working_counter(
+        DomainIdx::from(0),
+        &WcAnomaly::SlaveMissing {
+            slave: SlavePos::from(3),
+        },
+    )
+    .unwrap();
+    assert_eq!(wc.to_string(), "Error: domain 0: slave 3 missing");
+    let b = table.raise(wc);
+    assert_eq!(table.get(a).unwrap().count, 2);
+    assert_eq!(table.severity(), Some(Severity::Error));
+
+    // acknowledged but still active
+    assert!(table.acknowledge(b));
+    assert_eq!(table.len(), 2);
+    // cleared but not yet acknowledged
+    table.clear(
+        AlarmSource::Slave(SlavePos::from(2)),
+        AlarmKind::DcDeviation,
+    );
+    assert_eq!(table.active().count(), 1);
+    assert_eq!(table.severity(), Some(Severity::Error));
+    table.acknowledge_all();
+    assert_eq!(table.len(), 1);
+    table.clear(
+        AlarmSource::Domain(DomainIdx::from(0)),
+        AlarmKind::WorkingCounter,
+    );
+    assert!(table.is_empty());
+    assert!(!table.acknowledge(a));
+
+    use AlarmTransition::*;
+    assert_eq!(
+        *transitions.lock().unwrap(),
+        [
+            (a, Raised),
+            (b, Raised),
+            (b, Acknowledged),
+            (a, Cleared),
+            (a, Acknowledged),
+            (a, Removed),
+            (b, Cleared),
+            (b, Removed),
+        ]
+    );
+}
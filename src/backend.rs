@@ -0,0 +1,429 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Master implementations behind a common interface.
+//!
+//! [`Backend`] covers what an application needs once the bus is configured:
+//! slave discovery, AL states, mailbox and register access and the cyclic
+//! exchange of the process image. The IgH [`Master`](crate::Master)
+//! implements it on Linux; other backends make the same application code run
+//! without the kernel module, also on Windows and macOS.
+
+mod batch;
+mod frame;
+mod playback;
+mod pool;
+mod raw;
+mod sim;
+#[cfg(feature = "soem")]
+mod soem;
+
+#[cfg(feature = "soem")]
+pub use self::soem::SoemMaster;
+pub use self::{
+    batch::SdoBatch,
+    playback::{PlaybackMaster, PlaybackRecord},
+    pool::WorkerPool,
+    raw::RawMaster,
+    sim::{SimMaster, SimSlave},
+};
+
+#[cfg(target_os = "linux")]
+use crate::master::Master;
+use crate::{field::DomainView, types::*};
+use std::{
+    convert::TryFrom,
+    io,
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+/// Location of the process data of a slave in the image of a domain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlaveImage {
+    pub domain: DomainIdx,
+    /// Bytes written by the master, if any.
+    pub outputs: Option<Range<usize>>,
+    /// Bytes read by the master, if any.
+    pub inputs: Option<Range<usize>>,
+}
+
+/// The operations of an EtherCAT master.
+///
+/// Slaves are addressed by their ring position, starting at 0.
+pub trait Backend {
+    fn slave_count(&mut self) -> Result<usize>;
+
+    fn slave_info(&mut self, slave: SlavePos) -> Result<SlaveInfo>;
+
+    /// Request an AL state; the transition happens asynchronously.
+    fn request_state(&mut self, slave: SlavePos, state: AlState) -> Result<()>;
+
+    fn read_register(&mut self, slave: SlavePos, address: u16, target: &mut [u8]) -> Result<()>;
+
+    fn write_register(&mut self, slave: SlavePos, address: u16, data: &[u8]) -> Result<()>;
+
+    /// Read `target.len()` words of the SII (EEPROM) starting at word `offset`.
+    fn read_sii(&mut self, slave: SlavePos, offset: u16, target: &mut [u16]) -> Result<()>;
+
+    /// Upload an SDO into `target` and return its size.
+    fn sdo_upload(
+        &mut self,
+        slave: SlavePos,
+        index: SdoIdx,
+        complete_access: bool,
+        target: &mut [u8],
+    ) -> Result<usize>;
+
+    fn sdo_download(
+        &mut self,
+        slave: SlavePos,
+        index: SdoIdx,
+        complete_access: bool,
+        data: &[u8],
+    ) -> Result<()>;
+
+    /// Read an IDN of a SoE slave into `target` and return its size.
+    ///
+    /// Not every backend speaks SoE; the default implementation fails.
+    fn soe_read(
+        &mut self,
+        slave: SlavePos,
+        drive_no: u8,
+        idn: u16,
+        target: &mut [u8],
+    ) -> Result<usize> {
+        let _ = (slave, drive_no, idn, target);
+        Err(soe_unsupported())
+    }
+
+    /// Write an IDN of a SoE slave.
+    fn soe_write(&mut self, slave: SlavePos, drive_no: u8, idn: u16, data: &[u8]) -> Result<()> {
+        let _ = (slave, drive_no, idn, data);
+        Err(soe_unsupported())
+    }
+
+    /// Map the process data and start the cyclic operation.
+    fn activate(&mut self) -> Result<()>;
+
+    fn deactivate(&mut self) -> Result<()>;
+
+    /// Where the process data of a slave is located, after activation.
+    fn slave_image(&mut self, slave: SlavePos) -> Result<SlaveImage>;
+
+    fn domain_data(&mut self, domain: DomainIdx) -> Result<&mut [u8]>;
+
+    /// Typed access to the process data of a domain, see [`DomainView`].
+    fn domain_view(&mut self, domain: DomainIdx) -> Result<DomainView<'_>> {
+        Ok(DomainView::new(domain, self.domain_data(domain)?))
+    }
+
+    /// State of a domain as of the last [`receive`](Self::receive).
+    fn domain_state(&mut self, domain: DomainIdx) -> Result<DomainState>;
+
+    /// Receive the process data of all domains.
+    fn receive(&mut self) -> Result<()>;
+
+    /// Send the process data of all domains.
+    fn send(&mut self) -> Result<()>;
+}
+
+/// The IgH master in either phase. Its phase only changes by value, see
+/// [`Master::activate`], so [`activate`](Backend::activate) merely checks that
+/// the master is activated, and the process data of a master deactivated here
+/// is no longer available.
+#[cfg(target_os = "linux")]
+impl<S> Backend for Master<S> {
+    fn slave_count(&mut self) -> Result<usize> {
+        Ok(self.get_info()?.slave_count as usize)
+    }
+
+    fn slave_info(&mut self, slave: SlavePos) -> Result<SlaveInfo> {
+        self.get_slave_info(slave)
+    }
+
+    fn request_state(&mut self, slave: SlavePos, state: AlState) -> Result<()> {
+        Master::request_state(self, slave, state)
+    }
+
+    fn read_register(&mut self, slave: SlavePos, address: u16, target: &mut [u8]) -> Result<()> {
+        Master::read_register(self, slave, address, target)
+    }
+
+    fn write_register(&mut self, slave: SlavePos, address: u16, data: &[u8]) -> Result<()> {
+        Master::write_register(self, slave, address, data)
+    }
+
+    fn read_sii(&mut self, slave: SlavePos, offset: u16, target: &mut [u16]) -> Result<()> {
+        Master::read_sii(self, slave, offset, target)
+    }
+
+    fn sdo_upload(
+        &mut self,
+        slave: SlavePos,
+        index: SdoIdx,
+        complete_access: bool,
+        target: &mut [u8],
+    ) -> Result<usize> {
+        Master::sdo_upload(self, slave, index, complete_access, target).map(|data| data.len())
+    }
+
+    fn sdo_download(
+        &mut self,
+        slave: SlavePos,
+        index: SdoIdx,
+        complete_access: bool,
+        data: &[u8],
+    ) -> Result<()> {
+        Master::sdo_download(self, slave, index, complete_access, &data)
+    }
+
+    fn soe_read(
+        &mut self,
+        slave: SlavePos,
+        drive_no: u8,
+        idn: u16,
+        target: &mut [u8],
+    ) -> Result<usize> {
+        Master::soe_read(self, slave, drive_no, idn, target).map(|data| data.len())
+    }
+
+    fn soe_write(&mut self, slave: SlavePos, drive_no: u8, idn: u16, data: &[u8]) -> Result<()> {
+        Master::soe_write(self, slave, drive_no, idn, data)
+    }
+
+    fn activate(&mut self) -> Result<()> {
+        if self.is_active() {
+            Ok(())
+        } else {
+            Err(Error::NotActivated)
+        }
+    }
+
+    fn deactivate(&mut self) -> Result<()> {
+        self.stop()
+    }
+
+    fn slave_image(&mut self, slave: SlavePos) -> Result<SlaveImage> {
+        let info = self.get_info()?;
+        let mut config = None;
+        for idx in 0..info.config_count {
+            let c = self.get_config_info(idx)?;
+            if c.slave_position == Some(slave) {
+                config = Some(c);
+                break;
+            }
+        }
+        let config = config.ok_or(Error::NoDomain)?;
+        for d in 0..info.domain_count as usize {
+            let domain = self.domain(DomainIdx::from(d));
+            let domain_info = domain.info()?;
+            let mut image = SlaveImage {
+                domain: DomainIdx::from(d),
+                outputs: None,
+                inputs: None,
+            };
+            for i in 0..domain_info.fmmu_count {
+                let fmmu = domain.fmmu(i)?;
+                if (fmmu.slave_config_alias, fmmu.slave_config_position)
+                    != (config.alias, config.position)
+                {
+                    continue;
+                }
+                let start = (fmmu.logical_address - domain_info.logical_base_address) as usize;
+                let range = start..start + fmmu.data_size;
+                match fmmu.direction {
+                    SyncDirection::Output => image.outputs = Some(range),
+                    SyncDirection::Input => image.inputs = Some(range),
+                    _ => (),
+                }
+            }
+            if image.outputs.is_some() || image.inputs.is_some() {
+                return Ok(image);
+            }
+        }
+        Err(Error::NoDomain)
+    }
+
+    fn domain_data(&mut self, domain: DomainIdx) -> Result<&mut [u8]> {
+        self.process_data(domain)
+    }
+
+    fn domain_state(&mut self, domain: DomainIdx) -> Result<DomainState> {
+        self.domain(domain).state()
+    }
+
+    fn receive(&mut self) -> Result<()> {
+        self.receive_frames()?;
+        for d in 0..self.get_info()?.domain_count as usize {
+            self.domain(DomainIdx::from(d)).process()?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self) -> Result<()> {
+        for d in 0..self.get_info()?.domain_count as usize {
+            self.domain(DomainIdx::from(d)).queue()?;
+        }
+        self.send_frames().map(|_| ())
+    }
+}
+
+/// ESC registers used by backends that access the slaves directly.
+pub(crate) mod reg {
+    pub const FEATURES: u16 = 0x0008;
+    pub const STATION_ADDRESS: u16 = 0x0010;
+    pub const STATION_ALIAS: u16 = 0x0012;
+    pub const DL_STATUS: u16 = 0x0110;
+    pub const AL_CONTROL: u16 = 0x0120;
+    pub const AL_STATUS: u16 = 0x0130;
+    pub const EEPROM_CONFIG: u16 = 0x0500;
+    pub const EEPROM_CONTROL: u16 = 0x0502;
+    pub const EEPROM_DATA: u16 = 0x0508;
+    pub const FMMU: u16 = 0x0600;
+    pub const FMMU_COUNT: u16 = 16;
+}
+
+fn soe_unsupported() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::Other,
+        "SoE is not supported by this backend",
+    ))
+}
+
+const EEPROM_BUSY: u16 = 0x8000;
+const EEPROM_ERRORS: u16 = 0x7800;
+const EEPROM_READ: u16 = 0x0100;
+
+/// SII word addresses of the identity.
+const SII_VENDOR_ID: u16 = 0x08;
+const SII_MAILBOX_PROTOCOL: u16 = 0x1C;
+
+/// Build the [`SlaveInfo`] of a slave from its registers and SII, for
+/// backends without a slave database of their own.
+///
+/// The topology is not known this way, so all ports report no neighbour.
+pub fn read_slave_info<B: Backend + ?Sized>(backend: &mut B, slave: SlavePos) -> Result<SlaveInfo> {
+    let mut ident = [0; 8];
+    backend.read_sii(slave, SII_VENDOR_ID, &mut ident)?;
+    let long = |i: usize| u32::from(ident[i]) | u32::from(ident[i + 1]) << 16;
+    let mut mailbox = [0];
+    backend.read_sii(slave, SII_MAILBOX_PROTOCOL, &mut mailbox)?;
+
+    let mut reg = [0; 2];
+    backend.read_register(slave, reg::FEATURES, &mut reg)?;
+    let features = u16::from_le_bytes(reg);
+    backend.read_register(slave, reg::STATION_ALIAS, &mut reg)?;
+    let alias = u16::from_le_bytes(reg);
+    backend.read_register(slave, reg::DL_STATUS, &mut reg)?;
+    let dl_status = u16::from_le_bytes(reg);
+    backend.read_register(slave, reg::AL_STATUS, &mut reg)?;
+    let al_status = reg[0];
+    let al_state =
+        AlState::try_from(al_status & 0x0F).map_err(|_| Error::InvalidAlState(al_status))?;
+
+    let mut ports = [SlavePortInfo::default(); MAX_PORTS];
+    for (i, port) in ports.iter_mut().enumerate() {
+        port.desc = SlavePortType::EBus;
+        port.link = SlavePortLink {
+            link_up: dl_status & (1 << (4 + i)) != 0,
+            loop_closed: dl_status & (1 << (8 + 2 * i)) != 0,
+            signal_detected: dl_status & (1 << (9 + 2 * i)) != 0,
+        };
+        port.next_slave = 0xFFFF;
+    }
+    Ok(SlaveInfo {
+        name: String::new(),
+        ring_pos: slave,
+        id: SlaveId::new(long(0), long(2)),
+        rev: SlaveRev::new(long(4), long(6)),
+        alias,
+        current_on_ebus: 0,
+        al_state,
+        error_flag: al_status >> 4 & 1,
+        sync_count: 0,
+        sdo_count: 0,
+        ports,
+        // DC supported and 64 bit wide
+        has_dc_system_time: features & 0b1100 == 0b1100,
+        sii_nwords: 0,
+        mailbox_protocols: mailbox[0],
+    })
+}
+
+/// Locate the process data of a slave from its FMMU registers, for backends
+/// that map the process image from logical address 0.
+pub fn read_slave_image<B: Backend + ?Sized>(
+    backend: &mut B,
+    slave: SlavePos,
+) -> Result<SlaveImage> {
+    let mut image = SlaveImage {
+        domain: DomainIdx::from(0),
+        outputs: None,
+        inputs: None,
+    };
+    for i in 0..reg::FMMU_COUNT {
+        let mut fmmu = [0; 16];
+        backend.read_register(slave, reg::FMMU + 16 * i, &mut fmmu)?;
+        if fmmu[12] & 1 == 0 {
+            continue;
+        }
+        let start = u32::from_le_bytes([fmmu[0], fmmu[1], fmmu[2], fmmu[3]]) as usize;
+        let len = u16::from_le_bytes([fmmu[4], fmmu[5]]) as usize;
+        // bit 0: read (inputs), bit 1: write (outputs)
+        if fmmu[11] & 1 != 0 {
+            image.inputs = Some(start..start + len);
+        }
+        if fmmu[11] & 2 != 0 {
+            image.outputs = Some(start..start + len);
+        }
+    }
+    Ok(image)
+}
+
+/// Read `target.len()` words of the EEPROM of a slave through the EEPROM
+/// interface of its ESC, starting at word `offset`.
+///
+/// Unlike [`Master::read_sii`](crate::Master::read_sii), which returns the
+/// copy the IgH master read when scanning the bus, this reads the EEPROM
+/// itself, e.g. to verify a write. `timeout` applies to every read of two
+/// words.
+pub fn read_eeprom<B: Backend + ?Sized>(
+    backend: &mut B,
+    slave: SlavePos,
+    offset: u16,
+    target: &mut [u16],
+    timeout: Duration,
+) -> Result<()> {
+    // take the EEPROM from the PDI
+    backend.write_register(slave, reg::EEPROM_CONFIG, &[0])?;
+    for (i, pair) in target.chunks_mut(2).enumerate() {
+        let address = u32::from(offset) + 2 * i as u32;
+        let mut command = [0; 6];
+        command[..2].copy_from_slice(&EEPROM_READ.to_le_bytes());
+        command[2..].copy_from_slice(&address.to_le_bytes());
+        backend.write_register(slave, reg::EEPROM_CONTROL, &command)?;
+
+        let start = Instant::now();
+        loop {
+            let mut status = [0; 2];
+            backend.read_register(slave, reg::EEPROM_CONTROL, &mut status)?;
+            let status = u16::from_le_bytes(status);
+            if status & EEPROM_BUSY == 0 {
+                if status & EEPROM_ERRORS != 0 {
+                    return Err(Error::RequestFailed);
+                }
+                break;
+            }
+            if start.elapsed() > timeout {
+                return Err(Error::Io(io::ErrorKind::TimedOut.into()));
+            }
+        }
+        let mut data = [0; 4];
+        backend.read_register(slave, reg::EEPROM_DATA, &mut data)?;
+        for (word, bytes) in pair.iter_mut().zip(data.chunks(2)) {
+            *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,244 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{Backend, WorkerPool};
+use crate::types::*;
+use std::{io, sync::Arc};
+
+#[derive(Debug, Clone)]
+enum Kind {
+    /// Upload at most this many bytes.
+    Upload(usize),
+    Download(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+struct Transfer {
+    /// Number of the transfer in the results.
+    number: usize,
+    slave: SlavePos,
+    index: SdoIdx,
+    complete_access: bool,
+    kind: Kind,
+}
+
+/// A list of SDO transfers, e.g. the startup parameters of all slaves, run
+/// in one go.
+///
+/// The transfers of a slave run in the order they were queued, and after
+/// one of them failed the following ones are skipped. With
+/// [`run_parallel`](Self::run_parallel), the transfers of different slaves
+/// run concurrently on a [`WorkerPool`], so the bring-up time is set by the
+/// slowest slaves rather than the sum of all of them:
+///
+/// ```ignore
+/// let mut batch = SdoBatch::new();
+/// for pos in 0..40 {
+///     batch.download(SlavePos::from(pos), SdoIdx::new(0x8010, 0x01), false, &[0x10, 0x00]);
+/// }
+/// let handles = (0..4)
+///     .map(|_| Master::open(0, MasterAccess::ReadWrite))
+///     .collect::<Result<Vec<_>>>()?;
+/// for result in batch.run_parallel(&mut WorkerPool::new(handles)) {
+///     result?;
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SdoBatch {
+    transfers: Vec<Transfer>,
+}
+
+impl SdoBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.transfers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transfers.is_empty()
+    }
+
+    /// Queue an upload of at most `size` bytes, and return its number in
+    /// the results.
+    pub fn upload(
+        &mut self,
+        slave: SlavePos,
+        index: SdoIdx,
+        complete_access: bool,
+        size: usize,
+    ) -> usize {
+        self.push(slave, index, complete_access, Kind::Upload(size))
+    }
+
+    /// Queue a download, and return its number in the results.
+    pub fn download(
+        &mut self,
+        slave: SlavePos,
+        index: SdoIdx,
+        complete_access: bool,
+        data: &[u8],
+    ) -> usize {
+        self.push(slave, index, complete_access, Kind::Download(data.to_vec()))
+    }
+
+    /// The transfers of one slave, in their order.
+    pub(crate) fn of_slave(&self, slave: SlavePos) -> Self {
+        let mut batch = Self::new();
+        for t in self.transfers.iter().filter(|t| t.slave == slave) {
+            batch.push(t.slave, t.index, t.complete_access, t.kind.clone());
+        }
+        batch
+    }
+
+    fn push(&mut self, slave: SlavePos, index: SdoIdx, complete_access: bool, kind: Kind) -> usize {
+        let number = self.transfers.len();
+        self.transfers.push(Transfer {
+            number,
+            slave,
+            index,
+            complete_access,
+            kind,
+        });
+        number
+    }
+
+    /// Run all transfers one after the other.
+    ///
+    /// Returns the result of every transfer in the order they were queued:
+    /// the uploaded data, or no data for a download.
+    pub fn run<B: Backend + ?Sized>(&self, backend: &mut B) -> Vec<Result<Vec<u8>>> {
+        execute(backend, &self.transfers)
+            .into_iter()
+            .map(|(_, result)| result)
+            .collect()
+    }
+
+    /// Run the transfers of different slaves concurrently on the backends
+    /// of a pool.
+    ///
+    /// All transfers of a slave run in order on the same backend. Returns
+    /// the results like [`run`](Self::run).
+    pub fn run_parallel<B>(&self, pool: &mut WorkerPool<B>) -> Vec<Result<Vec<u8>>>
+    where
+        B: Backend + Send + 'static,
+    {
+        let mut slaves: Vec<(SlavePos, Vec<Transfer>)> = vec![];
+        for transfer in &self.transfers {
+            match slaves.iter_mut().find(|(s, _)| *s == transfer.slave) {
+                Some((_, transfers)) => transfers.push(transfer.clone()),
+                None => slaves.push((transfer.slave, vec![transfer.clone()])),
+            }
+        }
+        let positions: Vec<_> = slaves.iter().map(|(s, _)| *s).collect();
+        let slaves = Arc::new(slaves);
+        let done = pool.run(&positions, move |backend, slave| {
+            let transfers = slaves.iter().find(|(s, _)| *s == slave).map(|(_, t)| t);
+            Ok(execute(backend, transfers.map_or(&[], |t| &t[..])))
+        });
+
+        let mut results: Vec<Option<Result<Vec<u8>>>> =
+            (0..self.transfers.len()).map(|_| None).collect();
+        for (number, result) in done.into_iter().flatten().flatten() {
+            results[number] = Some(result);
+        }
+        // without backends, the pool does not run any transfer
+        results
+            .into_iter()
+            .map(|r| r.unwrap_or(Err(Error::NoDevices)))
+            .collect()
+    }
+}
+
+/// Run transfers in order, skipping those of slaves with a failed transfer.
+fn execute<B: Backend + ?Sized>(
+    backend: &mut B,
+    transfers: &[Transfer],
+) -> Vec<(usize, Result<Vec<u8>>)> {
+    let mut failed = vec![];
+    transfers
+        .iter()
+        .map(|t| {
+            if failed.contains(&t.slave) {
+                return (t.number, Err(skipped()));
+            }
+            let result = match &t.kind {
+                Kind::Upload(size) => {
+                    let mut data = vec![0; *size];
+                    backend
+                        .sdo_upload(t.slave, t.index, t.complete_access, &mut data)
+                        .map(|len| {
+                            data.truncate(len);
+                            data
+                        })
+                }
+                Kind::Download(data) => backend
+                    .sdo_download(t.slave, t.index, t.complete_access, data)
+                    .map(|_| vec![]),
+            };
+            if let Err(e) = &result {
+                log::warn!(
+                    "SDO {:#06x}:{:02x} of slave {} failed: {}",
+                    u16::from(t.index.idx),
+                    u8::from(t.index.sub_idx),
+                    u16::from(t.slave),
+                    e
+                );
+                failed.push(t.slave);
+            }
+            (t.number, result)
+        })
+        .collect()
+}
+
+fn skipped() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::Other,
+        "skipped after a failed SDO transfer of the slave",
+    ))
+}
+
+#[test]
+fn test_sdo_batch() {
+    use super::{SimMaster, SimSlave};
+
+    let bus = || {
+        SimMaster::new(
+            (0..3)
+                .map(|i| {
+                    SimSlave::new("EL3102", SlaveId::new(2, 0x0c1e_3052))
+                        .object(SdoIdx::new(0x8000, 0x06), &[i])
+                        .object(SdoIdx::new(0x8010, 0x06), &[i, 0])
+                })
+                .collect(),
+        )
+    };
+    let mut batch = SdoBatch::new();
+    for pos in 0..3 {
+        let slave = SlavePos::from(pos);
+        batch.download(slave, SdoIdx::new(0x8000, 0x06), false, &[0x10 + pos as u8]);
+        batch.upload(slave, SdoIdx::new(0x8000, 0x06), false, 4);
+    }
+    // wrong size, then skipped
+    let failing = batch.download(SlavePos::from(1), SdoIdx::new(0x8010, 0x06), false, &[1]);
+    let skipped = batch.upload(SlavePos::from(1), SdoIdx::new(0x8010, 0x06), false, 2);
+    let other = batch.upload(SlavePos::from(2), SdoIdx::new(0x8010, 0x06), false, 2);
+    assert_eq!(batch.len(), 9);
+
+    let check = |results: Vec<Result<Vec<u8>>>| {
+        for pos in 0..3 {
+            assert!(results[2 * pos].as_ref().unwrap().is_empty());
+            assert_eq!(results[2 * pos + 1].as_ref().unwrap(), &[0x10 + pos as u8]);
+        }
+        assert!(matches!(results[failing], Err(Error::RequestFailed)));
+        assert!(matches!(results[skipped], Err(Error::Io(_))));
+        assert_eq!(results[other].as_ref().unwrap(), &[2, 0]);
+    };
+    check(batch.run(&mut bus()));
+    check(batch.run_parallel(&mut WorkerPool::new(vec![bus(), bus()])));
+    check(batch.run_parallel(&mut WorkerPool::new(vec![bus(), bus(), bus(), bus()])));
+    let results = batch.run_parallel(&mut WorkerPool::<SimMaster>::new(vec![]));
+    assert!(results.iter().all(|r| matches!(r, Err(Error::NoDevices))));
+}
@@ -0,0 +1,200 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Encoding and decoding of EtherCAT frames.
+
+use std::convert::TryFrom;
+
+pub const ETHERTYPE: u16 = 0x88A4;
+const ETH_HEADER: usize = 14;
+const ETH_MIN_FRAME: usize = 60;
+const ETH_MAX_PAYLOAD: usize = 1500;
+const HEADER: usize = 2;
+const DATAGRAM_HEADER: usize = 10;
+const WKC: usize = 2;
+/// Frame type of EtherCAT commands in the EtherCAT header.
+const TYPE_COMMANDS: u16 = 1;
+const MORE_FOLLOWS: u16 = 0x8000;
+
+/// Largest payload of a single datagram.
+pub const MAX_DATA: usize = ETH_MAX_PAYLOAD - HEADER - DATAGRAM_HEADER - WKC;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Command {
+    /// Auto increment physical read
+    Aprd = 1,
+    /// Auto increment physical write
+    Apwr = 2,
+    /// Configured address physical read
+    Fprd = 4,
+    /// Configured address physical write
+    Fpwr = 5,
+    /// Broadcast read
+    Brd = 7,
+    /// Broadcast write
+    Bwr = 8,
+    /// Logical memory read
+    Lrd = 10,
+    /// Logical memory write
+    Lwr = 11,
+    /// Logical memory read write
+    Lrw = 12,
+}
+
+impl TryFrom<u8> for Command {
+    type Error = u8;
+
+    fn try_from(cmd: u8) -> Result<Self, u8> {
+        Ok(match cmd {
+            1 => Command::Aprd,
+            2 => Command::Apwr,
+            4 => Command::Fprd,
+            5 => Command::Fpwr,
+            7 => Command::Brd,
+            8 => Command::Bwr,
+            10 => Command::Lrd,
+            11 => Command::Lwr,
+            12 => Command::Lrw,
+            _ => return Err(cmd),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Datagram {
+    pub command: Command,
+    /// Chosen by the master to match the returning datagram.
+    pub index: u8,
+    /// Logical address, or slave address in the low and register in the
+    /// high word.
+    pub address: u32,
+    pub data: Vec<u8>,
+    pub working_counter: u16,
+}
+
+impl Datagram {
+    /// A datagram addressing a register of a slave by its ring position.
+    pub fn positional(
+        command: Command,
+        index: u8,
+        slave: u16,
+        register: u16,
+        data: Vec<u8>,
+    ) -> Self {
+        // every slave increments the address, the one reading 0 is addressed
+        let adp = 0u16.wrapping_sub(slave);
+        Self {
+            command,
+            index,
+            address: u32::from(adp) | u32::from(register) << 16,
+            data,
+            working_counter: 0,
+        }
+    }
+
+    pub fn logical(command: Command, index: u8, address: u32, data: Vec<u8>) -> Self {
+        Self {
+            command,
+            index,
+            address,
+            data,
+            working_counter: 0,
+        }
+    }
+}
+
+/// Build an Ethernet frame carrying the datagrams, which must fit into it.
+pub fn encode(source: [u8; 6], datagrams: &[Datagram]) -> Vec<u8> {
+    let len: usize = datagrams
+        .iter()
+        .map(|d| DATAGRAM_HEADER + d.data.len() + WKC)
+        .sum();
+    debug_assert!(HEADER + len <= ETH_MAX_PAYLOAD);
+
+    let mut frame = Vec::with_capacity(ETH_HEADER + HEADER + len);
+    frame.extend_from_slice(&[0xFF; 6]);
+    frame.extend_from_slice(&source);
+    frame.extend_from_slice(&ETHERTYPE.to_be_bytes());
+    frame.extend_from_slice(&(len as u16 | TYPE_COMMANDS << 12).to_le_bytes());
+    for (i, d) in datagrams.iter().enumerate() {
+        let more = if i + 1 < datagrams.len() {
+            MORE_FOLLOWS
+        } else {
+            0
+        };
+        frame.push(d.command as u8);
+        frame.push(d.index);
+        frame.extend_from_slice(&d.address.to_le_bytes());
+        frame.extend_from_slice(&(d.data.len() as u16 | more).to_le_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(&d.data);
+        frame.extend_from_slice(&d.working_counter.to_le_bytes());
+    }
+    if frame.len() < ETH_MIN_FRAME {
+        frame.resize(ETH_MIN_FRAME, 0);
+    }
+    frame
+}
+
+/// Parse the datagrams of an Ethernet frame, `None` if it is not a valid
+/// EtherCAT frame.
+pub fn decode(frame: &[u8]) -> Option<Vec<Datagram>> {
+    let u16_at = |pos: usize| Some(u16::from_le_bytes([*frame.get(pos)?, *frame.get(pos + 1)?]));
+    if frame.len() < ETH_HEADER + HEADER || frame[12..14] != ETHERTYPE.to_be_bytes() {
+        return None;
+    }
+    let header = u16_at(ETH_HEADER)?;
+    if header >> 12 != TYPE_COMMANDS {
+        return None;
+    }
+    let end = ETH_HEADER + HEADER + (header & 0x07FF) as usize;
+    let mut pos = ETH_HEADER + HEADER;
+    let mut datagrams = vec![];
+    loop {
+        let command = Command::try_from(*frame.get(pos)?).ok()?;
+        let len_field = u16_at(pos + 6)?;
+        let data_start = pos + DATAGRAM_HEADER;
+        let data_end = data_start + (len_field & 0x07FF) as usize;
+        if data_end + WKC > end.min(frame.len()) {
+            return None;
+        }
+        datagrams.push(Datagram {
+            command,
+            index: frame[pos + 1],
+            address: u32::from_le_bytes([
+                frame[pos + 2],
+                frame[pos + 3],
+                frame[pos + 4],
+                frame[pos + 5],
+            ]),
+            data: frame[data_start..data_end].to_vec(),
+            working_counter: u16_at(data_end)?,
+        });
+        pos = data_end + WKC;
+        if len_field & MORE_FOLLOWS == 0 {
+            return Some(datagrams);
+        }
+    }
+}
+
+#[test]
+fn test_frame() {
+    let datagrams = vec![
+        Datagram::positional(Command::Aprd, 1, 2, 0x0130, vec![0; 2]),
+        Datagram::logical(Command::Lrw, 2, 0x1000, vec![1, 2, 3]),
+    ];
+    let frame = encode([2, 0, 0, 0, 0, 1], &datagrams);
+    assert_eq!(frame.len(), ETH_MIN_FRAME);
+    assert_eq!(frame[12..18], [0x88, 0xA4, 29, 0x10, 1, 1]);
+    // ADP of the third slave is -2
+    assert_eq!(frame[18..22], [0xFE, 0xFF, 0x30, 0x01]);
+    assert_eq!(frame[22..24], [2, 0x80]);
+    assert_eq!(decode(&frame).unwrap(), datagrams);
+
+    let mut reply = frame;
+    reply[43] = 3;
+    assert_eq!(decode(&reply).unwrap()[1].working_counter, 3);
+    reply[14] = 20;
+    assert_eq!(decode(&reply), None);
+}
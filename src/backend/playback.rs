@@ -0,0 +1,393 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Playback of recorded process data.
+//!
+//! Recordings of the [`CsvLogger`](crate::logging::CsvLogger) with an image
+//! column and pcap captures, e.g. from
+//! [`FrameCapture`](crate::diagnostics::FrameCapture), can be replayed. MCAP
+//! recordings only contain the decoded values of the logged entries, so the
+//! process image cannot be restored from them.
+
+use super::{
+    frame::{self, Command},
+    Backend, SlaveImage,
+};
+use crate::types::*;
+use std::{
+    io::{self, BufRead, Read},
+    thread,
+    time::{Duration, Instant},
+};
+
+const PCAP_MAGIC_US: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NS: u32 = 0xA1B2_3C4D;
+const LINKTYPE_ETHERNET: u32 = 1;
+
+fn invalid(msg: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+fn unsupported() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::Other,
+        "not available when playing back a recording",
+    ))
+}
+
+/// The process image of one recorded cycle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaybackRecord {
+    /// Time of the cycle, relative to an arbitrary origin.
+    pub time: Duration,
+    pub image: Vec<u8>,
+    /// The working counter, if recorded.
+    pub working_counter: Option<u32>,
+}
+
+/// A master replaying recorded cycles as domain 0, as if they were live.
+///
+/// Each [`receive`](Backend::receive) moves to the next record and returns
+/// an `UnexpectedEof` error after the last one, unless looping. Data written
+/// by the application is overwritten by the recording; slave access is not
+/// available.
+#[derive(Debug, Clone)]
+pub struct PlaybackMaster {
+    records: Vec<PlaybackRecord>,
+    next: usize,
+    image: Vec<u8>,
+    working_counter: Option<u32>,
+    realtime: bool,
+    looping: bool,
+    start: Option<(Instant, Duration)>,
+}
+
+impl PlaybackMaster {
+    pub fn new(records: Vec<PlaybackRecord>) -> Self {
+        Self {
+            records,
+            next: 0,
+            image: vec![],
+            working_counter: None,
+            realtime: false,
+            looping: false,
+            start: None,
+        }
+    }
+
+    /// Read a CSV file written by the [`CsvLogger`](crate::logging::CsvLogger),
+    /// which must contain the image column. The DC time is used as the time.
+    pub fn from_csv<R: BufRead>(input: R) -> Result<Self> {
+        let mut lines = input.lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let header = split_csv(&header);
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|c| c == name)
+                .ok_or_else(|| invalid(format!("no {} column", name)))
+        };
+        let (dc_time, image) = (column("dc_time")?, column("image")?);
+        let mut records = vec![];
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            let row = split_csv(&line);
+            let bad = || invalid(format!("invalid row {}", i + 1));
+            let time = row
+                .get(dc_time)
+                .and_then(|t| t.parse().ok())
+                .ok_or_else(bad)?;
+            let hex = row.get(image).ok_or_else(bad)?;
+            if hex.len() % 2 != 0 {
+                return Err(bad());
+            }
+            let image = (0..hex.len())
+                .step_by(2)
+                .map(|j| u8::from_str_radix(&hex[j..j + 2], 16).map_err(|_| bad()))
+                .collect::<Result<_>>()?;
+            records.push(PlaybackRecord {
+                time: Duration::from_nanos(time),
+                image,
+                working_counter: None,
+            });
+        }
+        Ok(Self::new(records))
+    }
+
+    /// Read a pcap capture and build one record of every frame returned by
+    /// the slaves that carries logical datagrams.
+    ///
+    /// Returned frames are told apart by the locally administered bit the
+    /// slaves set in the source address. The image spans all logical
+    /// addresses in the capture, from the lowest one.
+    pub fn from_pcap<R: Read>(mut input: R) -> Result<Self> {
+        let mut header = [0; 24];
+        input.read_exact(&mut header)?;
+        let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let (swapped, nanos) = match magic {
+            PCAP_MAGIC_US => (false, false),
+            PCAP_MAGIC_NS => (false, true),
+            m if m.swap_bytes() == PCAP_MAGIC_US => (true, false),
+            m if m.swap_bytes() == PCAP_MAGIC_NS => (true, true),
+            _ => return Err(invalid("not a pcap file".into())),
+        };
+        let u32_at = |b: &[u8], i: usize| {
+            let v = u32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]]);
+            if swapped {
+                v.swap_bytes()
+            } else {
+                v
+            }
+        };
+        if u32_at(&header, 20) != LINKTYPE_ETHERNET {
+            return Err(invalid("not an Ethernet capture".into()));
+        }
+
+        let mut frames = vec![];
+        let (mut start, mut end) = (u32::MAX, 0);
+        let mut packet = [0; 16];
+        loop {
+            match input.read_exact(&mut packet) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                res => res?,
+            }
+            let (secs, frac) = (u32_at(&packet, 0), u32_at(&packet, 4));
+            let mut data = vec![0; u32_at(&packet, 8) as usize];
+            input.read_exact(&mut data)?;
+            if data.len() < 12 || data[6] & 0x02 == 0 {
+                continue;
+            }
+            let datagrams = match frame::decode(&data) {
+                Some(datagrams) => datagrams,
+                None => continue,
+            };
+            let logical = datagrams
+                .into_iter()
+                .filter(|d| matches!(d.command, Command::Lrd | Command::Lwr | Command::Lrw))
+                .collect::<Vec<_>>();
+            if logical.is_empty() {
+                continue;
+            }
+            for d in &logical {
+                start = start.min(d.address);
+                end = end.max(d.address as usize + d.data.len());
+            }
+            let time = if nanos {
+                Duration::new(secs.into(), frac)
+            } else {
+                Duration::new(secs.into(), frac * 1000)
+            };
+            frames.push((time, logical));
+        }
+
+        let records = frames
+            .into_iter()
+            .map(|(time, datagrams)| {
+                let mut image = vec![0; end.saturating_sub(start as usize)];
+                let mut working_counter = 0;
+                for d in datagrams {
+                    let offset = (d.address - start) as usize;
+                    image[offset..offset + d.data.len()].copy_from_slice(&d.data);
+                    working_counter += u32::from(d.working_counter);
+                }
+                PlaybackRecord {
+                    time,
+                    image,
+                    working_counter: Some(working_counter),
+                }
+            })
+            .collect();
+        Ok(Self::new(records))
+    }
+
+    /// Pace [`receive`](Backend::receive) by the recorded times instead of
+    /// returning the next record right away.
+    pub fn realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    /// Start over after the last record.
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn records(&self) -> &[PlaybackRecord] {
+        &self.records
+    }
+
+    /// The record currently in the image, if any.
+    pub fn current(&self) -> Option<&PlaybackRecord> {
+        self.next.checked_sub(1).and_then(|i| self.records.get(i))
+    }
+
+    /// Continue with record `index`.
+    pub fn seek(&mut self, index: usize) {
+        self.next = index.min(self.records.len());
+        self.start = None;
+    }
+}
+
+impl Backend for PlaybackMaster {
+    fn slave_count(&mut self) -> Result<usize> {
+        Ok(0)
+    }
+
+    fn slave_info(&mut self, _: SlavePos) -> Result<SlaveInfo> {
+        Err(unsupported())
+    }
+
+    fn request_state(&mut self, _: SlavePos, _: AlState) -> Result<()> {
+        Err(unsupported())
+    }
+
+    fn read_register(&mut self, _: SlavePos, _: u16, _: &mut [u8]) -> Result<()> {
+        Err(unsupported())
+    }
+
+    fn write_register(&mut self, _: SlavePos, _: u16, _: &[u8]) -> Result<()> {
+        Err(unsupported())
+    }
+
+    fn read_sii(&mut self, _: SlavePos, _: u16, _: &mut [u16]) -> Result<()> {
+        Err(unsupported())
+    }
+
+    fn sdo_upload(&mut self, _: SlavePos, _: SdoIdx, _: bool, _: &mut [u8]) -> Result<usize> {
+        Err(unsupported())
+    }
+
+    fn sdo_download(&mut self, _: SlavePos, _: SdoIdx, _: bool, _: &[u8]) -> Result<()> {
+        Err(unsupported())
+    }
+
+    fn activate(&mut self) -> Result<()> {
+        let size = self
+            .records
+            .iter()
+            .map(|r| r.image.len())
+            .max()
+            .unwrap_or(0);
+        self.image = vec![0; size];
+        self.seek(0);
+        Ok(())
+    }
+
+    fn deactivate(&mut self) -> Result<()> {
+        self.image.clear();
+        Ok(())
+    }
+
+    fn slave_image(&mut self, _: SlavePos) -> Result<SlaveImage> {
+        Err(unsupported())
+    }
+
+    fn domain_data(&mut self, domain: DomainIdx) -> Result<&mut [u8]> {
+        if usize::from(domain) != 0 {
+            return Err(Error::NoDomain);
+        }
+        Ok(&mut self.image)
+    }
+
+    /// Recordings without working counters report complete domains.
+    fn domain_state(&mut self, domain: DomainIdx) -> Result<DomainState> {
+        if usize::from(domain) != 0 {
+            return Err(Error::NoDomain);
+        }
+        let (working_counter, wc_state) = match self.working_counter {
+            Some(0) => (0, WcState::Zero),
+            Some(wc) => (wc, WcState::Complete),
+            None => (0, WcState::Complete),
+        };
+        Ok(DomainState {
+            working_counter,
+            wc_state,
+            redundancy_active: false,
+        })
+    }
+
+    fn receive(&mut self) -> Result<()> {
+        if self.next == self.records.len() && self.looping {
+            self.seek(0);
+        }
+        let record = self
+            .records
+            .get(self.next)
+            .ok_or_else(|| Error::Io(io::ErrorKind::UnexpectedEof.into()))?;
+        if self.realtime {
+            let (start, origin) = *self.start.get_or_insert((Instant::now(), record.time));
+            let due = start + record.time.saturating_sub(origin);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+        }
+        self.image[..record.image.len()].copy_from_slice(&record.image);
+        self.working_counter = record.working_counter;
+        self.next += 1;
+        Ok(())
+    }
+
+    fn send(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Split a CSV line, unquoting fields.
+fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+#[test]
+fn test_playback() {
+    use crate::diagnostics::PcapWriter;
+    use frame::Datagram;
+
+    let csv = "cycle,dc_time,\"ready, drive 1\",image\n1,1000,true,feff08\n2,2000,false,0100\n";
+    let mut master = PlaybackMaster::from_csv(csv.as_bytes()).unwrap();
+    master.activate().unwrap();
+    let domain = DomainIdx::from(0);
+    master.receive().unwrap();
+    assert_eq!(master.domain_data(domain).unwrap(), [0xFE, 0xFF, 0x08]);
+    master.receive().unwrap();
+    assert_eq!(master.domain_data(domain).unwrap(), [0x01, 0x00, 0x08]);
+    assert_eq!(master.current().unwrap().time, Duration::from_nanos(2000));
+    assert!(master.receive().is_err());
+
+    let mut pcap = PcapWriter::new(vec![]).unwrap();
+    let mut lrw = Datagram::logical(Command::Lrw, 1, 0x1000, vec![1, 2]);
+    let lrd = Datagram::logical(Command::Lrd, 2, 0x1002, vec![3]);
+    // sent frame, then returned frame with the locally administered bit
+    pcap.write_frame(
+        Duration::from_secs(1),
+        &frame::encode([0; 6], &[lrw.clone()]),
+    )
+    .unwrap();
+    lrw.working_counter = 3;
+    let returned = frame::encode([2, 0, 0, 0, 0, 0], &[lrw, lrd]);
+    pcap.write_frame(Duration::from_secs(2), &returned).unwrap();
+    let data = pcap.into_inner();
+    let master = PlaybackMaster::from_pcap(&data[..]).unwrap();
+    assert_eq!(
+        master.records(),
+        [PlaybackRecord {
+            time: Duration::from_secs(2),
+            image: vec![1, 2, 3],
+            working_counter: Some(3),
+        }]
+    );
+}
@@ -0,0 +1,157 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::Backend;
+use crate::types::*;
+use std::{
+    panic,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+/// A bounded pool of backends to work on independent slaves concurrently,
+/// e.g. to fetch their dictionaries and download their startup SDOs.
+///
+/// Mailbox transfers block until the slave answers, so configuring a large
+/// bus one slave after the other takes as long as all slaves together. The
+/// pool runs as many slaves at a time as it has backends, each in its own
+/// thread. The backends should be separate handles of the same bus, e.g. a
+/// [`Master`](crate::Master) opened several times:
+///
+/// ```ignore
+/// let handles = (0..8)
+///     .map(|_| Master::open(0, MasterAccess::ReadWrite))
+///     .collect::<Result<Vec<_>>>()?;
+/// let mut pool = WorkerPool::new(handles);
+/// let slaves: Vec<_> = (0..master.get_info()?.slave_count as u16)
+///     .map(SlavePos::from)
+///     .collect();
+/// for result in pool.run(&slaves, |master, slave| master.dict_upload(slave)) {
+///     result?;
+/// }
+/// ```
+///
+/// The PDO configuration of the IgH master is only recorded by the kernel
+/// and written to the slaves on activation, where it is already done for
+/// all slaves in parallel, so there is no need to spread it.
+#[derive(Debug)]
+pub struct WorkerPool<B> {
+    backends: Vec<B>,
+}
+
+impl<B: Backend + Send + 'static> WorkerPool<B> {
+    /// Without any backend, all tasks fail with [`Error::NoDevices`].
+    pub fn new(backends: Vec<B>) -> Self {
+        Self { backends }
+    }
+
+    /// Number of slaves handled at the same time.
+    pub fn size(&self) -> usize {
+        self.backends.len()
+    }
+
+    /// Run `task` once for each of `slaves`, on the next free backend.
+    ///
+    /// Returns the results in the order of `slaves`. A panic of a task is
+    /// propagated once all workers are done, and the pool loses the backend
+    /// of the task.
+    pub fn run<T, F>(&mut self, slaves: &[SlavePos], task: F) -> Vec<Result<T>>
+    where
+        T: Send + 'static,
+        F: Fn(&mut B, SlavePos) -> Result<T> + Send + Sync + 'static,
+    {
+        let slaves: Arc<[SlavePos]> = slaves.into();
+        let task = Arc::new(task);
+        let next = Arc::new(AtomicUsize::new(0));
+        // no more workers than slaves
+        let idle = self
+            .backends
+            .split_off(self.backends.len().min(slaves.len()));
+        let workers: Vec<_> = self
+            .backends
+            .drain(..)
+            .map(|mut backend| {
+                let (slaves, task, next) = (slaves.clone(), task.clone(), next.clone());
+                thread::spawn(move || {
+                    let mut done = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        match slaves.get(i) {
+                            Some(slave) => done.push((i, task(&mut backend, *slave))),
+                            None => return (backend, done),
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let mut results: Vec<Option<Result<T>>> = (0..slaves.len()).map(|_| None).collect();
+        let mut panicked = None;
+        for worker in workers {
+            match worker.join() {
+                Ok((backend, done)) => {
+                    self.backends.push(backend);
+                    for (i, result) in done {
+                        results[i] = Some(result);
+                    }
+                }
+                Err(payload) => panicked = Some(payload),
+            }
+        }
+        self.backends.extend(idle);
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+        results
+            .into_iter()
+            .map(|r| r.unwrap_or(Err(Error::NoDevices)))
+            .collect()
+    }
+
+    pub fn into_backends(self) -> Vec<B> {
+        self.backends
+    }
+}
+
+#[test]
+fn test_worker_pool() {
+    use super::{SimMaster, SimSlave};
+
+    let bus = || {
+        SimMaster::new(
+            (0..5)
+                .map(|i| {
+                    SimSlave::new("EL3102", SlaveId::new(2, 0x0c1e_3052))
+                        .object(SdoIdx::new(0x1018, 0x04), &[i, 0, 0, 0])
+                })
+                .collect(),
+        )
+    };
+    let mut pool = WorkerPool::new(vec![bus(), bus(), bus()]);
+    let slaves: Vec<_> = (0..5).chain(Some(7)).map(SlavePos::from).collect();
+    let serial = |master: &mut SimMaster, slave| {
+        let mut data = [0; 4];
+        master.sdo_upload(slave, SdoIdx::new(0x1018, 0x04), false, &mut data)?;
+        Ok(u32::from_le_bytes(data))
+    };
+    let results = pool.run(&slaves, serial);
+    for (i, result) in results[..5].iter().enumerate() {
+        assert_eq!(*result.as_ref().unwrap(), i as u32);
+    }
+    assert!(results[5].is_err());
+
+    // the backends are kept, also those without work
+    assert_eq!(pool.run(&slaves[..1], serial).len(), 1);
+    assert_eq!(pool.size(), 3);
+    assert!(pool.run(&[], serial).is_empty());
+    assert_eq!(pool.into_backends().len(), 3);
+
+    let mut empty = WorkerPool::<SimMaster>::new(vec![]);
+    assert!(matches!(
+        empty.run(&slaves, serial)[0],
+        Err(Error::NoDevices)
+    ));
+}
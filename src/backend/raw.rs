@@ -0,0 +1,614 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Experimental master speaking EtherCAT over a raw socket.
+//!
+//! Slaves are addressed by position (APRD/APWR), the process image is
+//! exchanged with a single LRW (or LRD) datagram. The process data layout
+//! is taken from the SII of the slaves, PDO assignments configured over CoE
+//! are not taken into account. SDO transfers must fit into one mailbox.
+
+mod socket;
+
+use self::socket::Socket;
+use super::{
+    frame::{self, Command, Datagram},
+    read_eeprom, read_slave_image, read_slave_info, reg, Backend, SlaveImage,
+};
+use crate::types::*;
+use std::{
+    io, thread,
+    time::{Duration, Instant},
+};
+
+const TIMEOUT: Duration = Duration::from_millis(10);
+const EEPROM_TIMEOUT: Duration = Duration::from_millis(20);
+const MAILBOX_TIMEOUT: Duration = Duration::from_millis(700);
+const STATE_TIMEOUT: Duration = Duration::from_secs(2);
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+// ESC registers not needed by other backends
+const SM: u16 = 0x0800;
+const SM_COUNT: u16 = 16;
+/// Offset of the status byte in the registers of a sync manager.
+const SM_STATUS: u16 = 5;
+
+const SM_MAILBOX_FULL: u8 = 0x08;
+
+// SII words and categories
+const SII_STD_MAILBOX: u16 = 0x18;
+const SII_FIRST_CATEGORY: u16 = 0x40;
+const SII_CATEGORY_SYNC_MANAGER: u16 = 41;
+const SII_CATEGORY_TXPDO: u16 = 50;
+const SII_CATEGORY_RXPDO: u16 = 51;
+const SII_CATEGORY_END: u16 = 0xFFFF;
+/// Stop walking the category list of a corrupt SII here.
+const SII_MAX_WORDS: u16 = 0x4000;
+
+const SM_TYPE_OUTPUTS: u8 = 3;
+const SM_TYPE_INPUTS: u8 = 4;
+const SM_CONTROL_MAILBOX_OUT: u8 = 0x26;
+const SM_CONTROL_MAILBOX_IN: u8 = 0x22;
+
+const FMMU_READ: u8 = 1;
+const FMMU_WRITE: u8 = 2;
+
+const MAILBOX_HEADER: usize = 6;
+const MAILBOX_TYPE_COE: u8 = 3;
+const COE_SDO_REQUEST: u16 = 2;
+const COE_SDO_RESPONSE: u16 = 3;
+const SDO_ABORT: u8 = 0x80;
+
+fn timeout() -> Error {
+    Error::Io(io::ErrorKind::TimedOut.into())
+}
+
+fn unsupported(msg: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::Other, msg))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Mailbox {
+    /// Sync manager written by the master: start and length.
+    rx: (u16, u16),
+    /// Sync manager read by the master.
+    tx: (u16, u16),
+    counter: u8,
+}
+
+/// A sync manager for process data found in the SII.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SyncManager {
+    index: u16,
+    start: u16,
+    length: u16,
+    control: u8,
+    kind: u8,
+}
+
+/// A master sending frames over a raw socket on a network interface.
+///
+/// Linux only, needs `CAP_NET_RAW`; on other systems [`open`](Self::open)
+/// fails. On open, all slaves are put into
+/// PreOp with their standard mailbox configured; the process image of all
+/// slaves is domain 0.
+pub struct RawMaster {
+    socket: Socket,
+    mac: [u8; 6],
+    index: u8,
+    slave_count: usize,
+    mailboxes: Vec<Option<Mailbox>>,
+    image: Option<Vec<u8>>,
+    has_outputs: bool,
+    pending: Option<u8>,
+    expected_working_counter: u32,
+    working_counter: u32,
+}
+
+impl RawMaster {
+    pub fn open(ifname: &str) -> Result<Self> {
+        let (socket, mac) = Socket::open(ifname, frame::ETHERTYPE, TIMEOUT)?;
+        let mut master = Self {
+            socket,
+            mac,
+            index: 0,
+            slave_count: 0,
+            mailboxes: vec![],
+            image: None,
+            has_outputs: false,
+            pending: None,
+            expected_working_counter: 0,
+            working_counter: 0,
+        };
+        master.scan()?;
+        Ok(master)
+    }
+
+    /// Count the slaves, reset them to Init and configure their mailboxes.
+    fn scan(&mut self) -> Result<()> {
+        let reply = self.transact(Datagram::logical(Command::Brd, 0, 0, vec![0]))?;
+        self.slave_count = reply.working_counter as usize;
+        if self.slave_count == 0 {
+            return Err(Error::NoDevices);
+        }
+        let slaves = (0..self.slave_count as u16)
+            .map(SlavePos::from)
+            .collect::<Vec<_>>();
+        for &slave in &slaves {
+            self.request_state(slave, AlState::Init)?;
+        }
+        self.wait_state(&slaves, AlState::Init)?;
+        for &slave in &slaves {
+            self.write_register(slave, reg::FMMU, &[0; 16 * reg::FMMU_COUNT as usize])?;
+            self.write_register(slave, SM, &[0; 8 * SM_COUNT as usize])?;
+            let mut words = [0; 4];
+            self.read_sii(slave, SII_STD_MAILBOX, &mut words)?;
+            let mailbox = if words[1] > 0 && words[3] > 0 {
+                let (rx, tx) = ((words[0], words[1]), (words[2], words[3]));
+                self.write_sm(slave, 0, rx.0, rx.1, SM_CONTROL_MAILBOX_OUT)?;
+                self.write_sm(slave, 1, tx.0, tx.1, SM_CONTROL_MAILBOX_IN)?;
+                Some(Mailbox { rx, tx, counter: 0 })
+            } else {
+                None
+            };
+            self.mailboxes.push(mailbox);
+            self.request_state(slave, AlState::PreOp)?;
+        }
+        self.wait_state(&slaves, AlState::PreOp)
+    }
+
+    fn next_index(&mut self) -> u8 {
+        self.index = self.index.wrapping_add(1);
+        self.index
+    }
+
+    fn send_datagram(&mut self, datagram: &Datagram) -> Result<()> {
+        let frame = frame::encode(self.mac, std::slice::from_ref(datagram));
+        self.socket.send(&frame)
+    }
+
+    /// Receive the datagram with `index`, returns `None` after the timeout.
+    fn recv_datagram(&mut self, index: u8, timeout: Duration) -> Result<Option<Datagram>> {
+        let start = Instant::now();
+        let mut buf = [0u8; 1518];
+        while start.elapsed() < timeout {
+            let len = match self.socket.recv(&mut buf)? {
+                Some(len) => len,
+                None => continue,
+            };
+            if let Some(datagrams) = frame::decode(&buf[..len]) {
+                if let Some(d) = datagrams.into_iter().find(|d| d.index == index) {
+                    return Ok(Some(d));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn transact(&mut self, mut datagram: Datagram) -> Result<Datagram> {
+        datagram.index = self.next_index();
+        self.send_datagram(&datagram)?;
+        self.recv_datagram(datagram.index, TIMEOUT)?
+            .ok_or_else(timeout)
+    }
+
+    fn write_sm(
+        &mut self,
+        slave: SlavePos,
+        index: u16,
+        start: u16,
+        length: u16,
+        control: u8,
+    ) -> Result<()> {
+        let mut regs = [0; 8];
+        regs[..2].copy_from_slice(&start.to_le_bytes());
+        regs[2..4].copy_from_slice(&length.to_le_bytes());
+        regs[4] = control;
+        regs[6] = 1; // enable
+        self.write_register(slave, SM + 8 * index, &regs)
+    }
+
+    fn write_fmmu(
+        &mut self,
+        slave: SlavePos,
+        index: u16,
+        logical: u32,
+        sm: &SyncManager,
+        kind: u8,
+    ) -> Result<()> {
+        let mut regs = [0; 16];
+        regs[..4].copy_from_slice(&logical.to_le_bytes());
+        regs[4..6].copy_from_slice(&sm.length.to_le_bytes());
+        regs[7] = 7; // logical end bit
+        regs[8..10].copy_from_slice(&sm.start.to_le_bytes());
+        regs[11] = kind;
+        regs[12] = 1; // activate
+        self.write_register(slave, reg::FMMU + 16 * index, &regs)
+    }
+
+    fn wait_state(&mut self, slaves: &[SlavePos], state: AlState) -> Result<()> {
+        let start = Instant::now();
+        for &slave in slaves {
+            loop {
+                let mut status = [0; 2];
+                self.read_register(slave, reg::AL_STATUS, &mut status)?;
+                if status[0] & 0x10 != 0 {
+                    return Err(Error::InvalidAlState(status[0]));
+                }
+                if status[0] & 0x0F == u8::from(state) {
+                    break;
+                }
+                if start.elapsed() > STATE_TIMEOUT {
+                    return Err(timeout());
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait until the status of a sync manager has the mailbox full flag
+    /// as given.
+    fn wait_mailbox(&mut self, slave: SlavePos, sm: u16, full: bool) -> Result<()> {
+        let start = Instant::now();
+        loop {
+            let mut status = [0];
+            self.read_register(slave, SM + 8 * sm + SM_STATUS, &mut status)?;
+            if (status[0] & SM_MAILBOX_FULL != 0) == full {
+                return Ok(());
+            }
+            if start.elapsed() > MAILBOX_TIMEOUT {
+                return Err(timeout());
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Send an SDO request and return the SDO part of the response.
+    fn sdo_transfer(&mut self, slave: SlavePos, sdo: &[u8]) -> Result<Vec<u8>> {
+        let mut mailbox = self.mailboxes[u16::from(slave) as usize]
+            .ok_or_else(|| unsupported("slave has no mailbox"))?;
+        let len = 2 + sdo.len();
+        if MAILBOX_HEADER + len > mailbox.rx.1 as usize {
+            return Err(unsupported("segmented SDO transfers are not supported"));
+        }
+        mailbox.counter = mailbox.counter % 7 + 1;
+        self.mailboxes[u16::from(slave) as usize] = Some(mailbox);
+
+        let mut request = vec![0; mailbox.rx.1 as usize];
+        request[..2].copy_from_slice(&(len as u16).to_le_bytes());
+        request[5] = MAILBOX_TYPE_COE | mailbox.counter << 4;
+        request[6..8].copy_from_slice(&(COE_SDO_REQUEST << 12).to_le_bytes());
+        request[8..8 + sdo.len()].copy_from_slice(sdo);
+        self.wait_mailbox(slave, 0, false)?;
+        self.write_register(slave, mailbox.rx.0, &request)?;
+
+        loop {
+            self.wait_mailbox(slave, 1, true)?;
+            let mut response = vec![0; mailbox.tx.1 as usize];
+            self.read_register(slave, mailbox.tx.0, &mut response)?;
+            let len = u16::from_le_bytes([response[0], response[1]]) as usize;
+            let coe = u16::from_le_bytes([response[6], response[7]]);
+            // emergencies may arrive in between
+            if response[5] & 0x0F == MAILBOX_TYPE_COE
+                && coe >> 12 == COE_SDO_RESPONSE
+                && len >= 2 + 8
+                && MAILBOX_HEADER + len <= response.len()
+            {
+                return Ok(response[8..MAILBOX_HEADER + len].to_vec());
+            }
+        }
+    }
+
+    /// Read the SII categories needed for the process data layout: the
+    /// sync managers, and the PDOs assigned to each.
+    fn read_sync_managers(&mut self, slave: SlavePos) -> Result<Vec<SyncManager>> {
+        let mut sms = vec![];
+        let mut pdo_bits = vec![];
+        let mut pos = SII_FIRST_CATEGORY;
+        while pos < SII_MAX_WORDS {
+            let mut header = [0; 2];
+            self.read_sii(slave, pos, &mut header)?;
+            let (category, size) = (header[0], header[1]);
+            if category == SII_CATEGORY_END {
+                break;
+            }
+            if [
+                SII_CATEGORY_SYNC_MANAGER,
+                SII_CATEGORY_TXPDO,
+                SII_CATEGORY_RXPDO,
+            ]
+            .contains(&category)
+            {
+                let mut words = vec![0; size as usize];
+                self.read_sii(slave, pos + 2, &mut words)?;
+                let bytes = words
+                    .iter()
+                    .flat_map(|w| w.to_le_bytes())
+                    .collect::<Vec<_>>();
+                if category == SII_CATEGORY_SYNC_MANAGER {
+                    for (i, sm) in bytes.chunks_exact(8).enumerate() {
+                        let kind = sm[7];
+                        if kind == SM_TYPE_OUTPUTS || kind == SM_TYPE_INPUTS {
+                            sms.push(SyncManager {
+                                index: i as u16,
+                                start: u16::from_le_bytes([sm[0], sm[1]]),
+                                length: u16::from_le_bytes([sm[2], sm[3]]),
+                                control: sm[4],
+                                kind,
+                            });
+                        }
+                    }
+                } else {
+                    pdo_bits.extend(pdo_sizes(&bytes));
+                }
+            }
+            pos += 2 + size;
+        }
+        // sync managers without a default length get the size of their PDOs
+        for sm in &mut sms {
+            if sm.length == 0 {
+                let bits: u32 = pdo_bits
+                    .iter()
+                    .filter(|(idx, _)| u16::from(*idx) == sm.index)
+                    .map(|(_, bits)| bits)
+                    .sum();
+                sm.length = ((bits + 7) / 8) as u16;
+            }
+        }
+        sms.retain(|sm| sm.length > 0);
+        Ok(sms)
+    }
+}
+
+/// The sync manager and size in bits of the PDOs in a TxPDO or RxPDO
+/// category.
+fn pdo_sizes(bytes: &[u8]) -> Vec<(u8, u32)> {
+    let mut pdos = vec![];
+    let mut pos = 0;
+    while pos + 8 <= bytes.len() {
+        let entries = bytes[pos + 2] as usize;
+        let sm = bytes[pos + 3];
+        let end = (pos + 8 + 8 * entries).min(bytes.len());
+        let bits = bytes[pos + 8..end]
+            .chunks_exact(8)
+            .map(|e| u32::from(e[5]))
+            .sum();
+        pdos.push((sm, bits));
+        pos = end;
+    }
+    pdos
+}
+
+impl Backend for RawMaster {
+    fn slave_count(&mut self) -> Result<usize> {
+        Ok(self.slave_count)
+    }
+
+    fn slave_info(&mut self, slave: SlavePos) -> Result<SlaveInfo> {
+        read_slave_info(self, slave)
+    }
+
+    fn request_state(&mut self, slave: SlavePos, state: AlState) -> Result<()> {
+        self.write_register(slave, reg::AL_CONTROL, &[u8::from(state), 0])
+    }
+
+    fn read_register(&mut self, slave: SlavePos, address: u16, target: &mut [u8]) -> Result<()> {
+        if target.len() > frame::MAX_DATA {
+            return Err(Error::Io(io::ErrorKind::InvalidInput.into()));
+        }
+        let request = Datagram::positional(
+            Command::Aprd,
+            0,
+            slave.into(),
+            address,
+            vec![0; target.len()],
+        );
+        let reply = self.transact(request)?;
+        if reply.working_counter != 1 {
+            return Err(Error::RequestFailed);
+        }
+        target.copy_from_slice(&reply.data);
+        Ok(())
+    }
+
+    fn write_register(&mut self, slave: SlavePos, address: u16, data: &[u8]) -> Result<()> {
+        if data.len() > frame::MAX_DATA {
+            return Err(Error::Io(io::ErrorKind::InvalidInput.into()));
+        }
+        let request = Datagram::positional(Command::Apwr, 0, slave.into(), address, data.to_vec());
+        if self.transact(request)?.working_counter != 1 {
+            return Err(Error::RequestFailed);
+        }
+        Ok(())
+    }
+
+    fn read_sii(&mut self, slave: SlavePos, offset: u16, target: &mut [u16]) -> Result<()> {
+        read_eeprom(self, slave, offset, target, EEPROM_TIMEOUT)
+    }
+
+    fn sdo_upload(
+        &mut self,
+        slave: SlavePos,
+        index: SdoIdx,
+        complete_access: bool,
+        target: &mut [u8],
+    ) -> Result<usize> {
+        let mut sdo = [0; 8];
+        sdo[0] = 0x40 | (complete_access as u8) << 4;
+        sdo[1..3].copy_from_slice(&u16::from(index.idx).to_le_bytes());
+        sdo[3] = index.sub_idx.into();
+        let response = self.sdo_transfer(slave, &sdo)?;
+        let cmd = response[0];
+        if cmd == SDO_ABORT || cmd >> 5 != 2 {
+            return Err(Error::RequestFailed);
+        }
+        let data = if cmd & 0x02 != 0 {
+            // expedited, with the number of unused bytes if size indicated
+            let unused = if cmd & 0x01 != 0 { cmd >> 2 & 3 } else { 0 };
+            &response[4..8 - unused as usize]
+        } else {
+            let size = u32::from_le_bytes([response[4], response[5], response[6], response[7]]);
+            if response.len() < 8 + size as usize {
+                return Err(unsupported("segmented SDO transfers are not supported"));
+            }
+            &response[8..8 + size as usize]
+        };
+        if data.len() > target.len() {
+            return Err(Error::Io(io::ErrorKind::InvalidInput.into()));
+        }
+        target[..data.len()].copy_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn sdo_download(
+        &mut self,
+        slave: SlavePos,
+        index: SdoIdx,
+        complete_access: bool,
+        data: &[u8],
+    ) -> Result<()> {
+        let mut sdo = vec![0; 8];
+        sdo[1..3].copy_from_slice(&u16::from(index.idx).to_le_bytes());
+        sdo[3] = index.sub_idx.into();
+        if data.len() <= 4 {
+            sdo[0] = 0x23 | ((4 - data.len()) as u8) << 2;
+            sdo[4..4 + data.len()].copy_from_slice(data);
+        } else {
+            sdo[0] = 0x21;
+            sdo[4..8].copy_from_slice(&(data.len() as u32).to_le_bytes());
+            sdo.extend_from_slice(data);
+        }
+        sdo[0] |= (complete_access as u8) << 4;
+        let response = self.sdo_transfer(slave, &sdo)?;
+        if response[0] == SDO_ABORT || response[0] >> 5 != 3 {
+            return Err(Error::RequestFailed);
+        }
+        Ok(())
+    }
+
+    fn activate(&mut self) -> Result<()> {
+        let mut logical = 0u32;
+        let mut expected = 0;
+        let mut has_outputs = false;
+        let slaves = (0..self.slave_count as u16)
+            .map(SlavePos::from)
+            .collect::<Vec<_>>();
+        for &slave in &slaves {
+            let sms = self.read_sync_managers(slave)?;
+            // outputs first, as SOEM and IgH lay them out
+            for (kind, fmmu, fmmu_type) in [
+                (SM_TYPE_OUTPUTS, 0, FMMU_WRITE),
+                (SM_TYPE_INPUTS, 1, FMMU_READ),
+            ] {
+                if let Some(sm) = sms.iter().find(|sm| sm.kind == kind) {
+                    self.write_sm(slave, sm.index, sm.start, sm.length, sm.control)?;
+                    self.write_fmmu(slave, fmmu, logical, sm, fmmu_type)?;
+                    logical += u32::from(sm.length);
+                    expected += if fmmu_type == FMMU_WRITE { 2 } else { 1 };
+                    has_outputs |= fmmu_type == FMMU_WRITE;
+                }
+            }
+        }
+        if logical as usize > frame::MAX_DATA {
+            return Err(unsupported("process image does not fit into one frame"));
+        }
+        self.image = Some(vec![0; logical as usize]);
+        self.has_outputs = has_outputs;
+        self.expected_working_counter = expected;
+
+        for &slave in &slaves {
+            self.request_state(slave, AlState::SafeOp)?;
+        }
+        self.wait_state(&slaves, AlState::SafeOp)?;
+        // slaves need valid outputs before going to Op
+        self.send()?;
+        self.receive()?;
+        for &slave in &slaves {
+            self.request_state(slave, AlState::Op)?;
+        }
+        Ok(())
+    }
+
+    fn deactivate(&mut self) -> Result<()> {
+        for i in 0..self.slave_count {
+            self.request_state(SlavePos::from(i as u16), AlState::PreOp)?;
+        }
+        self.image = None;
+        self.pending = None;
+        Ok(())
+    }
+
+    fn slave_image(&mut self, slave: SlavePos) -> Result<SlaveImage> {
+        read_slave_image(self, slave)
+    }
+
+    fn domain_data(&mut self, domain: DomainIdx) -> Result<&mut [u8]> {
+        if usize::from(domain) != 0 {
+            return Err(Error::NoDomain);
+        }
+        self.image.as_deref_mut().ok_or(Error::NotActivated)
+    }
+
+    fn domain_state(&mut self, domain: DomainIdx) -> Result<DomainState> {
+        if usize::from(domain) != 0 {
+            return Err(Error::NoDomain);
+        }
+        let wc_state = if self.working_counter == 0 {
+            WcState::Zero
+        } else if self.working_counter < self.expected_working_counter {
+            WcState::Incomplete
+        } else {
+            WcState::Complete
+        };
+        Ok(DomainState {
+            working_counter: self.working_counter,
+            wc_state,
+            redundancy_active: false,
+        })
+    }
+
+    fn receive(&mut self) -> Result<()> {
+        if self.image.is_none() {
+            return Err(Error::NotActivated);
+        }
+        self.working_counter = 0;
+        let index = match self.pending.take() {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        // a lost frame shows as a working counter of zero
+        if let Some(reply) = self.recv_datagram(index, TIMEOUT)? {
+            let image = self.image.as_mut().ok_or(Error::NotActivated)?;
+            if reply.data.len() == image.len() {
+                image.copy_from_slice(&reply.data);
+                self.working_counter = reply.working_counter.into();
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&mut self) -> Result<()> {
+        let data = self.image.clone().ok_or(Error::NotActivated)?;
+        let command = if self.has_outputs {
+            Command::Lrw
+        } else {
+            Command::Lrd
+        };
+        let index = self.next_index();
+        self.send_datagram(&Datagram::logical(command, index, 0, data))?;
+        self.pending = Some(index);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_pdo_sizes() {
+    // 0x1A00 with two entries of 16 and 8 bits on SM 3, 0x1A01 without
+    // entries on SM 3
+    let mut bytes = vec![0x00, 0x1A, 2, 3, 0, 0, 0, 0];
+    bytes.extend_from_slice(&[0x00, 0x60, 1, 0, 0, 16, 0, 0]);
+    bytes.extend_from_slice(&[0x00, 0x60, 2, 0, 0, 8, 0, 0]);
+    bytes.extend_from_slice(&[0x01, 0x1A, 0, 3, 0, 0, 0, 0]);
+    assert_eq!(pdo_sizes(&bytes), [(3, 24), (3, 0)]);
+}
@@ -0,0 +1,156 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Raw Ethernet socket for the frames of a [`RawMaster`](super::RawMaster).
+//!
+//! Only Linux (`AF_PACKET`) is supported; elsewhere opening fails, so that
+//! the rest of the backend still builds.
+
+use crate::types::*;
+use std::{io, time::Duration};
+
+#[cfg(target_os = "linux")]
+pub(super) use self::linux::Socket;
+#[cfg(not(target_os = "linux"))]
+pub(super) use self::unsupported::Socket;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::*;
+    use std::{
+        mem,
+        os::raw::{c_int, c_void},
+    };
+
+    /// `sll_pkttype` of frames sent by this host.
+    const PACKET_OUTGOING: u8 = 4;
+
+    fn check(res: c_int) -> Result<c_int> {
+        if res < 0 {
+            Err(Error::Io(io::Error::last_os_error()))
+        } else {
+            Ok(res)
+        }
+    }
+
+    pub(in super::super) struct Socket {
+        fd: c_int,
+    }
+
+    impl Socket {
+        /// Open a socket for frames with `ethertype` on interface `ifname`,
+        /// returns it with the hardware address of the interface.
+        pub fn open(ifname: &str, ethertype: u16, timeout: Duration) -> Result<(Self, [u8; 6])> {
+            if ifname.len() >= libc::IFNAMSIZ {
+                return Err(Error::Io(io::ErrorKind::InvalidInput.into()));
+            }
+            let protocol = ethertype.to_be();
+            let fd =
+                check(unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, protocol.into()) })?;
+            let socket = Self { fd };
+
+            // interface index, then hardware address from the `ifreq` union
+            let mut ifreq = [0u8; 40];
+            ifreq[..ifname.len()].copy_from_slice(ifname.as_bytes());
+            check(unsafe { libc::ioctl(fd, libc::SIOCGIFINDEX as _, ifreq.as_mut_ptr()) })?;
+            let ifindex = c_int::from_ne_bytes([ifreq[16], ifreq[17], ifreq[18], ifreq[19]]);
+            check(unsafe { libc::ioctl(fd, libc::SIOCGIFHWADDR as _, ifreq.as_mut_ptr()) })?;
+            let mut mac = [0; 6];
+            mac.copy_from_slice(&ifreq[18..24]);
+
+            let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+            addr.sll_family = libc::AF_PACKET as u16;
+            addr.sll_protocol = protocol;
+            addr.sll_ifindex = ifindex;
+            check(unsafe {
+                libc::bind(
+                    fd,
+                    &addr as *const _ as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_ll>() as u32,
+                )
+            })?;
+            // tv_usec must stay below one second
+            let tv = libc::timeval {
+                tv_sec: timeout.as_secs() as _,
+                tv_usec: timeout.subsec_micros() as _,
+            };
+            check(unsafe {
+                libc::setsockopt(
+                    fd,
+                    libc::SOL_SOCKET,
+                    libc::SO_RCVTIMEO,
+                    &tv as *const _ as *const c_void,
+                    mem::size_of::<libc::timeval>() as u32,
+                )
+            })?;
+            Ok((socket, mac))
+        }
+
+        pub fn send(&self, frame: &[u8]) -> Result<()> {
+            check(
+                unsafe { libc::send(self.fd, frame.as_ptr() as *const c_void, frame.len(), 0) }
+                    as c_int,
+            )?;
+            Ok(())
+        }
+
+        /// Receive a frame into `buf` and return its length, or `None` if
+        /// there was none within the timeout.
+        pub fn recv(&self, buf: &mut [u8]) -> Result<Option<usize>> {
+            let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+            let mut addr_len = mem::size_of::<libc::sockaddr_ll>() as u32;
+            let len = unsafe {
+                libc::recvfrom(
+                    self.fd,
+                    buf.as_mut_ptr() as *mut c_void,
+                    buf.len(),
+                    0,
+                    &mut addr as *mut _ as *mut libc::sockaddr,
+                    &mut addr_len,
+                )
+            };
+            if len < 0 {
+                let err = io::Error::last_os_error();
+                return match err.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => Ok(None),
+                    _ => Err(Error::Io(err)),
+                };
+            }
+            // the socket also sees our own frames going out
+            if addr.sll_pkttype == PACKET_OUTGOING {
+                return Ok(None);
+            }
+            Ok(Some(len as usize))
+        }
+    }
+
+    impl Drop for Socket {
+        fn drop(&mut self) {
+            unsafe { libc::close(self.fd) };
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod unsupported {
+    use super::*;
+
+    pub(in super::super) enum Socket {}
+
+    impl Socket {
+        pub fn open(_ifname: &str, _ethertype: u16, _timeout: Duration) -> Result<(Self, [u8; 6])> {
+            Err(Error::Io(io::Error::new(
+                io::ErrorKind::Other,
+                "raw sockets are only supported on Linux, use the SOEM backend",
+            )))
+        }
+
+        pub fn send(&self, _frame: &[u8]) -> Result<()> {
+            match *self {}
+        }
+
+        pub fn recv(&self, _buf: &mut [u8]) -> Result<Option<usize>> {
+            match *self {}
+        }
+    }
+}
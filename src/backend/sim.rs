@@ -0,0 +1,790 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! A simulated bus, for testing applications without hardware.
+//!
+//! Slaves are described in code with their identity, object dictionary and
+//! PDOs. Each has a register memory holding the AL status and FMMUs like a
+//! real ESC, so the generic helpers of the backends work unchanged.
+
+use super::{read_slave_image, read_slave_info, reg, Backend, SlaveImage};
+use crate::types::*;
+use std::{collections::BTreeMap, convert::TryFrom, fmt, io, time::Duration};
+
+const REGISTER_SIZE: usize = 0x1000;
+/// Physical addresses of the process data sync managers.
+const OUTPUTS_START: u16 = 0x1100;
+const INPUTS_START: u16 = 0x1400;
+const MBOX_COE: u16 = 0x04;
+const DEFAULT_MAILBOX_SIZE: u16 = 128;
+
+const DEFAULT_PERIOD: Duration = Duration::from_millis(1);
+
+const AL_ERROR_ACK: u8 = 0x10;
+const CODE_INVALID_STATE_CHANGE: u16 = 0x0011;
+const CODE_UNKNOWN_STATE: u16 = 0x0012;
+
+type BehaviorFn = dyn FnMut(&mut SimSlave, Duration) + Send;
+
+/// Firmware of a simulated slave, see [`SimSlave::behavior`].
+struct Behavior(Box<BehaviorFn>);
+
+impl fmt::Debug for Behavior {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Behavior")
+    }
+}
+
+/// A slave on a [`SimMaster`] bus.
+#[derive(Debug)]
+pub struct SimSlave {
+    name: String,
+    id: SlaveId,
+    rev: SlaveRev,
+    alias: u16,
+    dc: bool,
+    dictionary: BTreeMap<(u16, u8), Vec<u8>>,
+    rx_pdos: Vec<PdoCfg>,
+    tx_pdos: Vec<PdoCfg>,
+    refused: Vec<(AlState, u16)>,
+    state: AlState,
+    error: bool,
+    registers: Vec<u8>,
+    outputs: Vec<u8>,
+    inputs: Vec<u8>,
+    behavior: Option<Behavior>,
+}
+
+impl SimSlave {
+    /// A slave in PreOp without objects or PDOs.
+    pub fn new(name: &str, id: SlaveId) -> Self {
+        Self {
+            name: name.into(),
+            id,
+            rev: SlaveRev::new(0, 0),
+            alias: 0,
+            dc: false,
+            dictionary: BTreeMap::new(),
+            rx_pdos: vec![],
+            tx_pdos: vec![],
+            refused: vec![],
+            state: AlState::PreOp,
+            error: false,
+            registers: vec![0; REGISTER_SIZE],
+            outputs: vec![],
+            inputs: vec![],
+            behavior: None,
+        }
+    }
+
+    pub fn rev(mut self, rev: SlaveRev) -> Self {
+        self.rev = rev;
+        self
+    }
+
+    pub fn alias(mut self, alias: u16) -> Self {
+        self.alias = alias;
+        self
+    }
+
+    /// Whether the slave supports distributed clocks.
+    pub fn dc(mut self, supported: bool) -> Self {
+        self.dc = supported;
+        self
+    }
+
+    /// Add an entry to the object dictionary, which makes the slave support
+    /// CoE.
+    pub fn object(mut self, idx: SdoIdx, data: &[u8]) -> Self {
+        self.dictionary.insert(key(idx), data.to_vec());
+        self
+    }
+
+    /// Add a PDO written by the master.
+    pub fn rx_pdo(mut self, pdo: PdoCfg) -> Self {
+        self.rx_pdos.push(pdo);
+        self.outputs = vec![0; pdo_bytes(&self.rx_pdos)];
+        self
+    }
+
+    /// Add a PDO read by the master.
+    pub fn tx_pdo(mut self, pdo: PdoCfg) -> Self {
+        self.tx_pdos.push(pdo);
+        self.inputs = vec![0; pdo_bytes(&self.tx_pdos)];
+        self
+    }
+
+    /// Refuse requests for `state` with the AL status `code`.
+    pub fn refuse(mut self, state: AlState, code: u16) -> Self {
+        self.refused.push((state, code));
+        self
+    }
+
+    /// Run `behavior` on every exchange of process data, after the slave
+    /// took its outputs and before its inputs are returned to the master.
+    ///
+    /// It gets the simulated time since the last exchange, which is the
+    /// [`period`](SimMaster::period) of the master, and can read the
+    /// outputs, write the inputs and objects or [`fault`](Self::fault) the
+    /// slave, e.g. to model a drive following velocity commands.
+    pub fn behavior<F>(mut self, behavior: F) -> Self
+    where
+        F: FnMut(&mut SimSlave, Duration) + Send + 'static,
+    {
+        self.behavior = Some(Behavior(Box::new(behavior)));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn state(&self) -> AlState {
+        self.state
+    }
+
+    /// Whether the slave indicates an AL error.
+    pub fn error(&self) -> bool {
+        self.error
+    }
+
+    pub fn object_data(&self, idx: SdoIdx) -> Option<&[u8]> {
+        self.dictionary.get(&key(idx)).map(|d| &d[..])
+    }
+
+    /// Change an existing object, returns false if there is none.
+    pub fn set_object(&mut self, idx: SdoIdx, data: &[u8]) -> bool {
+        match self.dictionary.get_mut(&key(idx)) {
+            Some(object) => {
+                object.clear();
+                object.extend_from_slice(data);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Signal a local error with the AL status `code`, e.g. a sync manager
+    /// watchdog (0x001B). A slave in Op falls back to SafeOp, and stays in
+    /// error until the master acknowledges it.
+    pub fn fault(&mut self, code: u16) {
+        if self.state == AlState::Op {
+            self.state = AlState::SafeOp;
+        }
+        self.error = true;
+        self.update_al_status(code);
+    }
+
+    /// The outputs as last received from the master.
+    pub fn outputs(&self) -> &[u8] {
+        &self.outputs
+    }
+
+    pub fn inputs(&self) -> &[u8] {
+        &self.inputs
+    }
+
+    /// The inputs sent to the master with the next exchange.
+    pub fn inputs_mut(&mut self) -> &mut [u8] {
+        &mut self.inputs
+    }
+
+    /// Position of a mapped PDO entry in the outputs or inputs.
+    pub fn entry_offset(&self, idx: PdoEntryIdx) -> Option<Offset> {
+        for pdos in &[&self.rx_pdos, &self.tx_pdos] {
+            let mut bits = 0;
+            for entry in pdos.iter().flat_map(|pdo| &pdo.entries) {
+                if entry.entry_idx == idx {
+                    return Some(Offset {
+                        byte: bits / 8,
+                        bit: (bits % 8) as u32,
+                    });
+                }
+                bits += entry.bit_len as usize;
+            }
+        }
+        None
+    }
+
+    /// The SII image: identity and mailbox, without categories.
+    fn sii(&self) -> Vec<u16> {
+        let mut words = vec![0; 0x41];
+        let longs = [
+            self.id.vendor_id,
+            self.id.product_code,
+            self.rev.revision_number,
+            self.rev.serial_number,
+        ];
+        for (i, long) in longs.iter().enumerate() {
+            words[0x08 + 2 * i] = *long as u16;
+            words[0x09 + 2 * i] = (*long >> 16) as u16;
+        }
+        if !self.dictionary.is_empty() {
+            words[0x18..0x1C].copy_from_slice(&[
+                0x1000,
+                DEFAULT_MAILBOX_SIZE,
+                0x1000 + DEFAULT_MAILBOX_SIZE,
+                DEFAULT_MAILBOX_SIZE,
+            ]);
+            words[0x1C] = MBOX_COE;
+        }
+        words[0x40] = 0xFFFF;
+        words
+    }
+
+    fn reset_registers(&mut self, last: bool) {
+        self.registers.iter_mut().for_each(|r| *r = 0);
+        // DC supported and 64 bit wide
+        let features: u16 = if self.dc { 0b1100 } else { 0 };
+        self.registers[reg::FEATURES as usize..][..2].copy_from_slice(&features.to_le_bytes());
+        self.registers[reg::STATION_ALIAS as usize..][..2]
+            .copy_from_slice(&self.alias.to_le_bytes());
+        // link and communication on port 0, and on port 1 unless last
+        let mut dl_status: u16 = 0x0010 | 0x0200;
+        if last {
+            dl_status |= 0x0400;
+        } else {
+            dl_status |= 0x0020 | 0x0800;
+        }
+        self.registers[reg::DL_STATUS as usize..][..2].copy_from_slice(&dl_status.to_le_bytes());
+        self.update_al_status(0);
+    }
+
+    fn update_al_status(&mut self, code: u16) {
+        let status = u8::from(self.state) | if self.error { 0x10 } else { 0 };
+        let regs = &mut self.registers[reg::AL_STATUS as usize..];
+        regs[0] = status;
+        regs[4..6].copy_from_slice(&code.to_le_bytes());
+    }
+
+    /// Handle a write to the AL control register, like the ESC firmware.
+    fn control(&mut self, control: u8) {
+        if self.error {
+            if control & AL_ERROR_ACK == 0 {
+                return;
+            }
+            self.error = false;
+        }
+        let code = match AlState::try_from(control & 0x0F) {
+            Err(_) => CODE_UNKNOWN_STATE,
+            Ok(state) if state == self.state => 0,
+            Ok(state) => match self.refused.iter().find(|(s, _)| *s == state) {
+                Some((_, code)) => *code,
+                None if valid_transition(self.state, state) => {
+                    self.state = state;
+                    0
+                }
+                None => CODE_INVALID_STATE_CHANGE,
+            },
+        };
+        self.error = code != 0;
+        self.update_al_status(code);
+    }
+
+    fn has_mailbox(&self) -> bool {
+        self.state != AlState::Init && self.state != AlState::Boot
+    }
+}
+
+fn key(idx: SdoIdx) -> (u16, u8) {
+    (idx.idx.into(), idx.sub_idx.into())
+}
+
+fn pdo_bytes(pdos: &[PdoCfg]) -> usize {
+    let bits: usize = pdos
+        .iter()
+        .flat_map(|pdo| &pdo.entries)
+        .map(|e| e.bit_len as usize)
+        .sum();
+    (bits + 7) / 8
+}
+
+fn valid_transition(from: AlState, to: AlState) -> bool {
+    use AlState::*;
+    matches!(
+        (from, to),
+        (_, Init)
+            | (Init, PreOp)
+            | (Init, Boot)
+            | (PreOp, SafeOp)
+            | (SafeOp, PreOp)
+            | (SafeOp, Op)
+            | (Op, PreOp)
+            | (Op, SafeOp)
+    )
+}
+
+fn not_found() -> Error {
+    Error::Io(io::ErrorKind::NotFound.into())
+}
+
+/// A master driving a simulated bus.
+///
+/// Slaves start in PreOp; activation lays out the outputs followed by the
+/// inputs of every slave in domain 0 and requests Op. Only slaves in Op
+/// take their outputs and only slaves in SafeOp or Op provide inputs, which
+/// is reflected in the working counter.
+#[derive(Debug)]
+pub struct SimMaster {
+    slaves: Vec<SimSlave>,
+    period: Duration,
+    image: Option<Vec<u8>>,
+    layout: Vec<SlaveImage>,
+    sent: bool,
+    expected_working_counter: u32,
+    working_counter: u32,
+}
+
+impl SimMaster {
+    pub fn new(slaves: Vec<SimSlave>) -> Self {
+        let mut master = Self {
+            slaves,
+            period: DEFAULT_PERIOD,
+            image: None,
+            layout: vec![],
+            sent: false,
+            expected_working_counter: 0,
+            working_counter: 0,
+        };
+        let count = master.slaves.len();
+        for (i, slave) in master.slaves.iter_mut().enumerate() {
+            slave.reset_registers(i + 1 == count);
+        }
+        master
+    }
+
+    /// Simulated time between two exchanges, passed to the behaviors of
+    /// the slaves. Defaults to 1 ms.
+    pub fn period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    pub fn slaves(&self) -> &[SimSlave] {
+        &self.slaves
+    }
+
+    pub fn slave(&self, slave: SlavePos) -> Option<&SimSlave> {
+        self.slaves.get(u16::from(slave) as usize)
+    }
+
+    pub fn slave_mut(&mut self, slave: SlavePos) -> Option<&mut SimSlave> {
+        self.slaves.get_mut(u16::from(slave) as usize)
+    }
+
+    fn get(&mut self, slave: SlavePos) -> Result<&mut SimSlave> {
+        self.slave_mut(slave).ok_or_else(not_found)
+    }
+
+    fn check_mailbox(&mut self, slave: SlavePos) -> Result<&mut SimSlave> {
+        let slave = self.get(slave)?;
+        if !slave.has_mailbox() {
+            return Err(Error::RequestFailed);
+        }
+        Ok(slave)
+    }
+}
+
+impl Backend for SimMaster {
+    fn slave_count(&mut self) -> Result<usize> {
+        Ok(self.slaves.len())
+    }
+
+    fn slave_info(&mut self, slave: SlavePos) -> Result<SlaveInfo> {
+        let mut info = read_slave_info(self, slave)?;
+        info.name = self.get(slave)?.name.clone();
+        info.sdo_count = self.get(slave)?.dictionary.len() as u16;
+        Ok(info)
+    }
+
+    fn request_state(&mut self, slave: SlavePos, state: AlState) -> Result<()> {
+        self.write_register(slave, reg::AL_CONTROL, &[u8::from(state), 0])
+    }
+
+    fn read_register(&mut self, slave: SlavePos, address: u16, target: &mut [u8]) -> Result<()> {
+        let slave = self.get(slave)?;
+        let regs = slave
+            .registers
+            .get(address as usize..address as usize + target.len())
+            .ok_or(Error::RequestFailed)?;
+        target.copy_from_slice(regs);
+        Ok(())
+    }
+
+    fn write_register(&mut self, slave: SlavePos, address: u16, data: &[u8]) -> Result<()> {
+        let slave = self.get(slave)?;
+        let range = address as usize..address as usize + data.len();
+        slave
+            .registers
+            .get_mut(range.clone())
+            .ok_or(Error::RequestFailed)?
+            .copy_from_slice(data);
+        if range.contains(&(reg::AL_CONTROL as usize)) {
+            slave.control(data[(reg::AL_CONTROL - address) as usize]);
+        }
+        Ok(())
+    }
+
+    fn read_sii(&mut self, slave: SlavePos, offset: u16, target: &mut [u16]) -> Result<()> {
+        let sii = self.get(slave)?.sii();
+        for (i, word) in target.iter_mut().enumerate() {
+            *word = sii.get(offset as usize + i).copied().unwrap_or(0xFFFF);
+        }
+        Ok(())
+    }
+
+    fn sdo_upload(
+        &mut self,
+        slave: SlavePos,
+        index: SdoIdx,
+        complete_access: bool,
+        target: &mut [u8],
+    ) -> Result<usize> {
+        let slave = self.check_mailbox(slave)?;
+        let (idx, sub) = key(index);
+        let data = if complete_access {
+            slave
+                .dictionary
+                .range((idx, sub)..=(idx, u8::MAX))
+                .flat_map(|(_, d)| d.iter().copied())
+                .collect()
+        } else {
+            slave
+                .dictionary
+                .get(&(idx, sub))
+                .cloned()
+                .ok_or(Error::RequestFailed)?
+        };
+        if data.is_empty() && complete_access {
+            return Err(Error::RequestFailed);
+        }
+        target
+            .get_mut(..data.len())
+            .ok_or_else(|| Error::Io(io::ErrorKind::InvalidInput.into()))?
+            .copy_from_slice(&data);
+        Ok(data.len())
+    }
+
+    fn sdo_download(
+        &mut self,
+        slave: SlavePos,
+        index: SdoIdx,
+        complete_access: bool,
+        data: &[u8],
+    ) -> Result<()> {
+        let slave = self.check_mailbox(slave)?;
+        let (idx, sub) = key(index);
+        let entries = if complete_access {
+            slave
+                .dictionary
+                .range_mut((idx, sub)..=(idx, u8::MAX))
+                .map(|(_, d)| d)
+                .collect::<Vec<_>>()
+        } else {
+            slave.dictionary.get_mut(&(idx, sub)).into_iter().collect()
+        };
+        // the sizes of the objects are fixed
+        if entries.is_empty() || entries.iter().map(|d| d.len()).sum::<usize>() != data.len() {
+            return Err(Error::RequestFailed);
+        }
+        let mut rest = data;
+        for entry in entries {
+            let (head, tail) = rest.split_at(entry.len());
+            entry.copy_from_slice(head);
+            rest = tail;
+        }
+        Ok(())
+    }
+
+    fn activate(&mut self) -> Result<()> {
+        if self.image.is_some() {
+            return Ok(());
+        }
+        let mut logical = 0usize;
+        let mut expected = 0;
+        self.layout.clear();
+        for slave in &mut self.slaves {
+            let mut image = SlaveImage {
+                domain: DomainIdx::from(0),
+                outputs: None,
+                inputs: None,
+            };
+            let fmmus = [
+                (slave.outputs.len(), OUTPUTS_START, 2),
+                (slave.inputs.len(), INPUTS_START, 1),
+            ];
+            for (fmmu, (len, start, kind)) in fmmus.iter().enumerate() {
+                if *len == 0 {
+                    continue;
+                }
+                let mut regs = [0; 16];
+                regs[..4].copy_from_slice(&(logical as u32).to_le_bytes());
+                regs[4..6].copy_from_slice(&(*len as u16).to_le_bytes());
+                regs[7] = 7;
+                regs[8..10].copy_from_slice(&start.to_le_bytes());
+                regs[11] = *kind;
+                regs[12] = 1;
+                slave.registers[reg::FMMU as usize + 16 * fmmu..][..16].copy_from_slice(&regs);
+                let range = Some(logical..logical + len);
+                if *kind == 2 {
+                    image.outputs = range;
+                } else {
+                    image.inputs = range;
+                }
+                logical += len;
+                expected += *kind as u32;
+            }
+            self.layout.push(image);
+        }
+        self.image = Some(vec![0; logical]);
+        self.expected_working_counter = expected;
+        for state in &[AlState::SafeOp, AlState::Op] {
+            for i in 0..self.slaves.len() {
+                self.request_state(SlavePos::from(i as u16), *state)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn deactivate(&mut self) -> Result<()> {
+        for i in 0..self.slaves.len() {
+            self.request_state(SlavePos::from(i as u16), AlState::PreOp)?;
+        }
+        self.image = None;
+        self.sent = false;
+        Ok(())
+    }
+
+    fn slave_image(&mut self, slave: SlavePos) -> Result<SlaveImage> {
+        read_slave_image(self, slave)
+    }
+
+    fn domain_data(&mut self, domain: DomainIdx) -> Result<&mut [u8]> {
+        if usize::from(domain) != 0 {
+            return Err(Error::NoDomain);
+        }
+        self.image.as_deref_mut().ok_or(Error::NotActivated)
+    }
+
+    fn domain_state(&mut self, domain: DomainIdx) -> Result<DomainState> {
+        if usize::from(domain) != 0 {
+            return Err(Error::NoDomain);
+        }
+        let wc_state = if self.working_counter == 0 {
+            WcState::Zero
+        } else if self.working_counter < self.expected_working_counter {
+            WcState::Incomplete
+        } else {
+            WcState::Complete
+        };
+        Ok(DomainState {
+            working_counter: self.working_counter,
+            wc_state,
+            redundancy_active: false,
+        })
+    }
+
+    fn receive(&mut self) -> Result<()> {
+        let image = self.image.as_mut().ok_or(Error::NotActivated)?;
+        self.working_counter = 0;
+        if !std::mem::take(&mut self.sent) {
+            return Ok(());
+        }
+        for (slave, layout) in self.slaves.iter().zip(&self.layout) {
+            if let Some(range) = &layout.inputs {
+                if slave.state == AlState::SafeOp || slave.state == AlState::Op {
+                    image[range.clone()].copy_from_slice(&slave.inputs);
+                    self.working_counter += 1;
+                }
+            }
+            if layout.outputs.is_some() && slave.state == AlState::Op {
+                self.working_counter += 2;
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&mut self) -> Result<()> {
+        let image = self.image.as_ref().ok_or(Error::NotActivated)?;
+        for (slave, layout) in self.slaves.iter_mut().zip(&self.layout) {
+            if let Some(range) = &layout.outputs {
+                if slave.state == AlState::Op {
+                    slave.outputs.copy_from_slice(&image[range.clone()]);
+                }
+            }
+            if let Some(mut behavior) = slave.behavior.take() {
+                (behavior.0)(slave, self.period);
+                slave.behavior.get_or_insert(behavior);
+            }
+        }
+        self.sent = true;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_sim_master() {
+    use crate::diagnostics::AlStatus;
+
+    let entry = |idx, sub, bit_len| PdoEntryInfo {
+        entry_idx: PdoEntryIdx::new(idx, sub),
+        bit_len,
+        name: String::new(),
+        pos: PdoEntryPos::from(0),
+    };
+    let mut rx = PdoCfg::new(PdoIdx::from(0x1600));
+    rx.entries = smallvec::smallvec![entry(0x6040, 0, 16), entry(0x60FF, 0, 32)];
+    let mut tx = PdoCfg::new(PdoIdx::from(0x1A00));
+    tx.entries = smallvec::smallvec![entry(0x6041, 0, 16)];
+    let drive = SimSlave::new("drive", SlaveId::new(2, 0x1234))
+        .object(SdoIdx::new(0x1000, 0), &[0x92, 0x01, 0x02, 0x00])
+        .object(SdoIdx::new(0x1018, 1), &[2, 0, 0, 0])
+        .object(SdoIdx::new(0x1018, 2), &[0x34, 0x12, 0, 0])
+        .rx_pdo(rx)
+        .tx_pdo(tx);
+    let coupler = SimSlave::new("coupler", SlaveId::new(2, 0x044c_2c52)).refuse(AlState::Op, 0x1D);
+    let mut master = SimMaster::new(vec![drive, coupler]);
+    let pos = SlavePos::from;
+
+    let info = master.slave_info(pos(0)).unwrap();
+    assert_eq!(
+        (info.name.as_str(), info.id),
+        ("drive", SlaveId::new(2, 0x1234))
+    );
+    assert_eq!(info.mailbox_protocols, MBOX_COE);
+    assert_eq!(info.al_state, AlState::PreOp);
+
+    let mut buf = [0; 8];
+    let n = master
+        .sdo_upload(pos(0), SdoIdx::new(0x1018, 1), true, &mut buf)
+        .unwrap();
+    assert_eq!(buf[..n], [2, 0, 0, 0, 0x34, 0x12, 0, 0]);
+    master
+        .sdo_download(pos(0), SdoIdx::new(0x1018, 2), false, &[1, 0, 0, 0])
+        .unwrap();
+    assert!(master
+        .sdo_download(pos(0), SdoIdx::new(0x1018, 2), false, &[1])
+        .is_err());
+    assert_eq!(
+        master
+            .slave(pos(0))
+            .unwrap()
+            .object_data(SdoIdx::new(0x1018, 2)),
+        Some(&[1, 0, 0, 0][..])
+    );
+    assert!(master
+        .sdo_upload(pos(1), SdoIdx::new(0x1000, 0), false, &mut buf)
+        .is_err());
+
+    master.activate().unwrap();
+    let image = master.slave_image(pos(0)).unwrap();
+    assert_eq!((image.outputs, image.inputs), (Some(0..6), Some(6..8)));
+    assert_eq!(
+        master
+            .slave(pos(0))
+            .unwrap()
+            .entry_offset(PdoEntryIdx::new(0x60FF, 0)),
+        Some(Offset { byte: 2, bit: 0 })
+    );
+
+    master
+        .slave_mut(pos(0))
+        .unwrap()
+        .inputs_mut()
+        .copy_from_slice(&[0x37, 0x02]);
+    master.domain_data(DomainIdx::from(0)).unwrap()[..2].copy_from_slice(&[0x0F, 0]);
+    master.send().unwrap();
+    master.receive().unwrap();
+    assert_eq!(master.slave(pos(0)).unwrap().outputs()[..2], [0x0F, 0]);
+    assert_eq!(
+        master.domain_data(DomainIdx::from(0)).unwrap()[6..],
+        [0x37, 0x02]
+    );
+    let state = master.domain_state(DomainIdx::from(0)).unwrap();
+    assert_eq!(
+        (state.working_counter, state.wc_state),
+        (3, WcState::Complete)
+    );
+
+    // the coupler refused Op, it stays in SafeOp with the code set
+    let mut regs = [0; 6];
+    master
+        .read_register(pos(1), reg::AL_STATUS, &mut regs)
+        .unwrap();
+    let status = AlStatus::from_registers(regs);
+    assert_eq!(
+        (status.state, status.error, status.code),
+        (Some(AlState::SafeOp), true, 0x1D)
+    );
+    master.request_state(pos(1), AlState::PreOp).unwrap();
+    assert_eq!(master.slave(pos(1)).unwrap().state(), AlState::SafeOp);
+    master
+        .write_register(
+            pos(1),
+            reg::AL_CONTROL,
+            &[u8::from(AlState::PreOp) | AL_ERROR_ACK],
+        )
+        .unwrap();
+    assert_eq!(master.slave(pos(1)).unwrap().state(), AlState::PreOp);
+    assert!(!master.slave(pos(1)).unwrap().error());
+}
+
+#[test]
+fn test_sim_behavior() {
+    let entry = |idx, bit_len| PdoEntryInfo {
+        entry_idx: PdoEntryIdx::new(idx, 0),
+        bit_len,
+        name: String::new(),
+        pos: PdoEntryPos::from(0),
+    };
+    let mut rx = PdoCfg::new(PdoIdx::from(0x1600));
+    rx.entries = smallvec::smallvec![entry(0x60FF, 32)];
+    let mut tx = PdoCfg::new(PdoIdx::from(0x1A00));
+    tx.entries = smallvec::smallvec![entry(0x6064, 32)];
+
+    // integrates the target velocity (per s) into the actual position, and
+    // loses sync after 5 cycles
+    let mut cycles = 0;
+    let drive = SimSlave::new("drive", SlaveId::new(2, 0x1234))
+        .object(SdoIdx::new(0x603F, 0), &[0, 0])
+        .rx_pdo(rx)
+        .tx_pdo(tx)
+        .behavior(move |slave, dt| {
+            cycles += 1;
+            if cycles == 5 {
+                slave.set_object(SdoIdx::new(0x603F, 0), &[0x10, 0x87]);
+                slave.fault(0x2C);
+            }
+            let mut velocity = [0; 4];
+            velocity.copy_from_slice(slave.outputs());
+            let mut position = [0; 4];
+            position.copy_from_slice(slave.inputs());
+            let position = i32::from_le_bytes(position)
+                + (i32::from_le_bytes(velocity) as f64 * dt.as_secs_f64()) as i32;
+            slave.inputs_mut().copy_from_slice(&position.to_le_bytes());
+        });
+    let mut master = SimMaster::new(vec![drive]).period(Duration::from_millis(10));
+    master.activate().unwrap();
+
+    let domain = DomainIdx::from(0);
+    master.domain_data(domain).unwrap()[..4].copy_from_slice(&1000i32.to_le_bytes());
+    let mut positions = vec![];
+    for _ in 0..6 {
+        master.send().unwrap();
+        master.receive().unwrap();
+        let mut position = [0; 4];
+        position.copy_from_slice(&master.domain_data(domain).unwrap()[4..]);
+        positions.push(i32::from_le_bytes(position));
+    }
+    // in SafeOp the outputs are no longer taken, but inputs still come
+    assert_eq!(positions, [10, 20, 30, 40, 50, 60]);
+    let slave = master.slave(SlavePos::from(0)).unwrap();
+    assert_eq!((slave.state(), slave.error()), (AlState::SafeOp, true));
+    let state = master.domain_state(domain).unwrap();
+    assert_eq!(state.wc_state, WcState::Incomplete);
+    let mut code = [0; 2];
+    master
+        .sdo_upload(SlavePos::from(0), SdoIdx::new(0x603F, 0), false, &mut code)
+        .unwrap();
+    assert_eq!(u16::from_le_bytes(code), 0x8710);
+}
@@ -0,0 +1,307 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Backend on top of SOEM, the Simple Open EtherCAT Master.
+//!
+//! Only the function API of SOEM is used, so the bindings do not depend on
+//! the layout of its slave and group structures, which varies with its build
+//! configuration.
+
+use super::{read_slave_image, read_slave_info, reg, Backend, SlaveImage};
+use crate::types::*;
+use std::{
+    convert::TryFrom,
+    ffi::CString,
+    io,
+    os::raw::{c_char, c_int, c_void},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+#[link(name = "soem")]
+extern "C" {
+    fn ec_init(ifname: *const c_char) -> c_int;
+    fn ec_close();
+    fn ec_config_init(usetable: u8) -> c_int;
+    fn ec_config_map(io_map: *mut c_void) -> c_int;
+    fn ec_configdc() -> u8;
+    fn ec_statecheck(slave: u16, reqstate: u16, timeout: c_int) -> u16;
+    fn ec_send_processdata() -> c_int;
+    fn ec_receive_processdata(timeout: c_int) -> c_int;
+    fn ec_readeeprom(slave: u16, eeproma: u16, timeout: c_int) -> u32;
+    fn ec_FPRD(adp: u16, ado: u16, length: u16, data: *mut c_void, timeout: c_int) -> c_int;
+    fn ec_FPWR(adp: u16, ado: u16, length: u16, data: *mut c_void, timeout: c_int) -> c_int;
+    fn ec_SDOread(
+        slave: u16,
+        index: u16,
+        subindex: u8,
+        ca: u8,
+        psize: *mut c_int,
+        p: *mut c_void,
+        timeout: c_int,
+    ) -> c_int;
+    fn ec_SDOwrite(
+        slave: u16,
+        index: u16,
+        subindex: u8,
+        ca: u8,
+        psize: c_int,
+        p: *const c_void,
+        timeout: c_int,
+    ) -> c_int;
+}
+
+/// Timeouts of SOEM in µs.
+const TIMEOUT_RET: c_int = 2_000;
+const TIMEOUT_EEP: c_int = 20_000;
+const TIMEOUT_RXM: c_int = 700_000;
+const TIMEOUT_STATE: c_int = 2_000_000;
+
+/// SOEM assigns the station addresses from this offset, starting with 1.
+const NODE_OFFSET: u16 = 0x1000;
+
+/// SOEM keeps its state in globals, so only one instance can exist.
+static OPEN: AtomicBool = AtomicBool::new(false);
+
+/// A master using SOEM on a network interface.
+///
+/// SOEM maps the outputs of all slaves followed by their inputs into a single
+/// process image, which is domain 0.
+pub struct SoemMaster {
+    slave_count: usize,
+    // SOEM writes the mapping without bounds checks
+    image: Box<[u8]>,
+    mapped: Option<usize>,
+    expected_working_counter: u32,
+    working_counter: u32,
+}
+
+impl SoemMaster {
+    /// Open `ifname` and scan the bus. `image_size` must hold the process
+    /// data of all slaves.
+    pub fn open(ifname: &str, image_size: usize) -> Result<Self> {
+        let name = CString::new(ifname)
+            .map_err(|e| Error::Io(io::Error::new(io::ErrorKind::InvalidInput, e)))?;
+        if OPEN.swap(true, Ordering::AcqRel) {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "SOEM is already in use",
+            )));
+        }
+        if unsafe { ec_init(name.as_ptr()) } <= 0 {
+            OPEN.store(false, Ordering::Release);
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+        let slave_count = unsafe { ec_config_init(0) };
+        if slave_count <= 0 {
+            unsafe { ec_close() };
+            OPEN.store(false, Ordering::Release);
+            return Err(Error::NoDevices);
+        }
+        Ok(Self {
+            slave_count: slave_count as usize,
+            image: vec![0; image_size].into_boxed_slice(),
+            mapped: None,
+            expected_working_counter: 0,
+            working_counter: 0,
+        })
+    }
+
+    fn station(&self, slave: SlavePos) -> Result<u16> {
+        let pos = u16::from(slave);
+        if pos as usize >= self.slave_count {
+            return Err(Error::Io(io::ErrorKind::NotFound.into()));
+        }
+        Ok(NODE_OFFSET + pos + 1)
+    }
+}
+
+impl Drop for SoemMaster {
+    fn drop(&mut self) {
+        unsafe { ec_close() };
+        OPEN.store(false, Ordering::Release);
+    }
+}
+
+impl Backend for SoemMaster {
+    fn slave_count(&mut self) -> Result<usize> {
+        Ok(self.slave_count)
+    }
+
+    fn slave_info(&mut self, slave: SlavePos) -> Result<SlaveInfo> {
+        read_slave_info(self, slave)
+    }
+
+    fn request_state(&mut self, slave: SlavePos, state: AlState) -> Result<()> {
+        self.write_register(slave, reg::AL_CONTROL, &[u8::from(state), 0])
+    }
+
+    fn read_register(&mut self, slave: SlavePos, address: u16, target: &mut [u8]) -> Result<()> {
+        let station = self.station(slave)?;
+        let len = u16::try_from(target.len()).map_err(|_| Error::RequestFailed)?;
+        let wkc = unsafe {
+            ec_FPRD(
+                station,
+                address,
+                len,
+                target.as_mut_ptr() as *mut _,
+                TIMEOUT_RET,
+            )
+        };
+        if wkc != 1 {
+            return Err(Error::RequestFailed);
+        }
+        Ok(())
+    }
+
+    fn write_register(&mut self, slave: SlavePos, address: u16, data: &[u8]) -> Result<()> {
+        let station = self.station(slave)?;
+        let len = u16::try_from(data.len()).map_err(|_| Error::RequestFailed)?;
+        // SOEM does not modify the data, but its signature is not const
+        let wkc = unsafe { ec_FPWR(station, address, len, data.as_ptr() as *mut _, TIMEOUT_RET) };
+        if wkc != 1 {
+            return Err(Error::RequestFailed);
+        }
+        Ok(())
+    }
+
+    fn read_sii(&mut self, slave: SlavePos, offset: u16, target: &mut [u16]) -> Result<()> {
+        self.station(slave)?;
+        // ec_readeeprom returns two words at a time
+        for (i, pair) in target.chunks_mut(2).enumerate() {
+            let addr = offset + 2 * i as u16;
+            let data = unsafe { ec_readeeprom(u16::from(slave) + 1, addr, TIMEOUT_EEP) };
+            pair[0] = data as u16;
+            if let Some(word) = pair.get_mut(1) {
+                *word = (data >> 16) as u16;
+            }
+        }
+        Ok(())
+    }
+
+    fn sdo_upload(
+        &mut self,
+        slave: SlavePos,
+        index: SdoIdx,
+        complete_access: bool,
+        target: &mut [u8],
+    ) -> Result<usize> {
+        self.station(slave)?;
+        let mut size = target.len() as c_int;
+        let wkc = unsafe {
+            ec_SDOread(
+                u16::from(slave) + 1,
+                u16::from(index.idx),
+                u8::from(index.sub_idx),
+                complete_access as u8,
+                &mut size,
+                target.as_mut_ptr() as *mut _,
+                TIMEOUT_RXM,
+            )
+        };
+        if wkc <= 0 {
+            return Err(Error::RequestFailed);
+        }
+        Ok(size as usize)
+    }
+
+    fn sdo_download(
+        &mut self,
+        slave: SlavePos,
+        index: SdoIdx,
+        complete_access: bool,
+        data: &[u8],
+    ) -> Result<()> {
+        self.station(slave)?;
+        let wkc = unsafe {
+            ec_SDOwrite(
+                u16::from(slave) + 1,
+                u16::from(index.idx),
+                u8::from(index.sub_idx),
+                complete_access as u8,
+                data.len() as c_int,
+                data.as_ptr() as *const _,
+                TIMEOUT_RXM,
+            )
+        };
+        if wkc <= 0 {
+            return Err(Error::RequestFailed);
+        }
+        Ok(())
+    }
+
+    fn activate(&mut self) -> Result<()> {
+        // maps the PDOs and requests SafeOp
+        let size = unsafe { ec_config_map(self.image.as_mut_ptr() as *mut _) };
+        if size < 0 || size as usize > self.image.len() {
+            return Err(Error::RequestFailed);
+        }
+        self.mapped = Some(size as usize);
+        unsafe { ec_configdc() };
+        unsafe { ec_statecheck(0, u8::from(AlState::SafeOp).into(), TIMEOUT_STATE) };
+
+        self.expected_working_counter = 0;
+        for i in 0..self.slave_count {
+            let image = self.slave_image(SlavePos::from(i as u16))?;
+            self.expected_working_counter += 2 * image.outputs.is_some() as u32;
+            self.expected_working_counter += image.inputs.is_some() as u32;
+        }
+        // slaves need valid outputs before going to Op
+        self.send()?;
+        self.receive()?;
+        for i in 0..self.slave_count {
+            self.request_state(SlavePos::from(i as u16), AlState::Op)?;
+        }
+        Ok(())
+    }
+
+    fn deactivate(&mut self) -> Result<()> {
+        for i in 0..self.slave_count {
+            self.request_state(SlavePos::from(i as u16), AlState::PreOp)?;
+        }
+        self.mapped = None;
+        Ok(())
+    }
+
+    fn slave_image(&mut self, slave: SlavePos) -> Result<SlaveImage> {
+        read_slave_image(self, slave)
+    }
+
+    fn domain_data(&mut self, domain: DomainIdx) -> Result<&mut [u8]> {
+        if usize::from(domain) != 0 {
+            return Err(Error::NoDomain);
+        }
+        let size = self.mapped.ok_or(Error::NotActivated)?;
+        Ok(&mut self.image[..size])
+    }
+
+    fn domain_state(&mut self, domain: DomainIdx) -> Result<DomainState> {
+        if usize::from(domain) != 0 {
+            return Err(Error::NoDomain);
+        }
+        let wc_state = if self.working_counter == 0 {
+            WcState::Zero
+        } else if self.working_counter < self.expected_working_counter {
+            WcState::Incomplete
+        } else {
+            WcState::Complete
+        };
+        Ok(DomainState {
+            working_counter: self.working_counter,
+            wc_state,
+            redundancy_active: false,
+        })
+    }
+
+    fn receive(&mut self) -> Result<()> {
+        self.mapped.ok_or(Error::NotActivated)?;
+        let wkc = unsafe { ec_receive_processdata(TIMEOUT_RET) };
+        self.working_counter = wkc.max(0) as u32;
+        Ok(())
+    }
+
+    fn send(&mut self) -> Result<()> {
+        self.mapped.ok_or(Error::NotActivated)?;
+        unsafe { ec_send_processdata() };
+        Ok(())
+    }
+}
@@ -0,0 +1,394 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Drivers for common slaves.
+//!
+//! A driver knows the PDO mapping of a family of terminals and gives typed
+//! access to their channels. It is created either while configuring the IgH
+//! master, by registering its PDO entries on the
+//! [`SlaveConfig`](crate::SlaveConfig) of the slave, or after the activation
+//! of any [`Backend`](crate::backend::Backend), from the
+//! [`SlaveImage`](crate::backend::SlaveImage) of a slave with its default
+//! mapping.
+//!
+//! Drivers keep the values of the current cycle: call `process` with the
+//! domain data once per cycle, after receiving and before sending, and use
+//! the accessors in between.
+
+mod ek1100;
+mod el1xxx;
+mod el2xxx;
+mod el3356;
+mod el3xxx;
+mod el4xxx;
+mod el5xxx;
+mod el6224;
+mod el70xx;
+mod el9xxx;
+mod fsoe;
+mod registry;
+mod serial;
+mod servo;
+mod temperature;
+mod valves;
+
+#[cfg(target_os = "linux")]
+pub use self::servo::configure_servo;
+pub use self::{
+    ek1100::{
+        coupler_groups, is_coupler, read_id_switch, verify_islands, CouplerGroup, LayoutMismatch,
+    },
+    el1xxx::DigitalInputs,
+    el2xxx::DigitalOutputs,
+    el3356::{LoadCell, LoadCellFilter, LoadCellSettings, Weight},
+    el3xxx::{AnalogInputs, AnalogValue},
+    el4xxx::{AnalogOutputs, WatchdogBehavior},
+    el5xxx::Encoder,
+    el6224::{IoLinkMaster, PortConfig, PortMode, PortState},
+    el70xx::{Stepper, StepperMode, StepperSettings, StepperStatus, MICROSTEPS},
+    el9xxx::{CurrentProtection, PowerSupply},
+    fsoe::{FsoeState, SafetyTerminal},
+    registry::{DeviceRegistry, Devices},
+    serial::{Framing, SerialErrors, SerialTerminal, StreamLink},
+    servo::{ServoPreset, SERVO_PRESETS},
+    temperature::{Sensor, Temperature, TemperatureInputs},
+    valves::{FailSafe, ValveTerminal, FESTO, SMC},
+};
+
+#[cfg(target_os = "linux")]
+use crate::SlaveConfig;
+use crate::{
+    backend::{Backend, SlaveImage},
+    field::Field,
+    types::*,
+};
+use std::{fmt, io, ops::Range};
+
+/// Vendor ID of Beckhoff Automation.
+pub const BECKHOFF: u32 = 0x0000_0002;
+
+/// Physical unit of the values of a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unit {
+    Volt,
+    Milliampere,
+    Celsius,
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Unit::Volt => "V",
+            Unit::Milliampere => "mA",
+            Unit::Celsius => "°C",
+        })
+    }
+}
+
+/// Conversion of the values of a channel to engineering units, e.g. of a
+/// 4-20 mA pressure transmitter to bar.
+///
+/// The engineering value is `value * scale + offset`, with `value` in the
+/// [`Unit`] of the terminal.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelScaling {
+    /// Name of the engineering unit, e.g. "bar".
+    pub unit: String,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl ChannelScaling {
+    pub fn new(unit: &str, scale: f64, offset: f64) -> Self {
+        Self {
+            unit: unit.to_owned(),
+            scale,
+            offset,
+        }
+    }
+
+    /// The scaling mapping the values `from` of the terminal to the values
+    /// `to` in engineering units, e.g. (4.0, 20.0) mA to (0.0, 10.0) bar.
+    pub fn linear(unit: &str, from: (f64, f64), to: (f64, f64)) -> Self {
+        let scale = (to.1 - to.0) / (from.1 - from.0);
+        Self::new(unit, scale, to.0 - from.0 * scale)
+    }
+
+    pub fn to_engineering(&self, value: f64) -> f64 {
+        value * self.scale + self.offset
+    }
+
+    pub fn from_engineering(&self, value: f64) -> f64 {
+        (value - self.offset) / self.scale
+    }
+
+    /// Convert the limits of a channel, keeping the minimum first.
+    fn limits(&self, (min, max): (f64, f64)) -> (f64, f64) {
+        let (a, b) = (self.to_engineering(min), self.to_engineering(max));
+        (a.min(b), a.max(b))
+    }
+}
+
+/// Whether a channel reads from or writes to the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+/// Value of a channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelValue {
+    Bool(bool),
+    Integer(i64),
+    Real(f64),
+}
+
+impl From<bool> for ChannelValue {
+    fn from(value: bool) -> Self {
+        ChannelValue::Bool(value)
+    }
+}
+
+impl From<i64> for ChannelValue {
+    fn from(value: i64) -> Self {
+        ChannelValue::Integer(value)
+    }
+}
+
+impl From<f64> for ChannelValue {
+    fn from(value: f64) -> Self {
+        ChannelValue::Real(value)
+    }
+}
+
+/// A channel of a slave, with its value as of the last `process` of the
+/// driver.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IoChannel {
+    pub name: String,
+    pub direction: Direction,
+    /// Symbol of the unit, e.g. "mA", or the name of an engineering unit.
+    pub unit: Option<String>,
+    /// Minimum and maximum value, e.g. for the scale of a display.
+    pub limits: Option<(f64, f64)>,
+    pub value: ChannelValue,
+    /// The value is valid, e.g. not out of range or from a broken wire.
+    pub valid: bool,
+}
+
+impl IoChannel {
+    pub fn input<V: Into<ChannelValue>>(name: String, value: V) -> Self {
+        Self {
+            name,
+            direction: Direction::Input,
+            unit: None,
+            limits: None,
+            value: value.into(),
+            valid: true,
+        }
+    }
+
+    pub fn output<V: Into<ChannelValue>>(name: String, value: V) -> Self {
+        Self {
+            direction: Direction::Output,
+            ..Self::input(name, value)
+        }
+    }
+
+    pub fn with_unit<U: fmt::Display>(mut self, unit: U) -> Self {
+        self.unit = Some(unit.to_string());
+        self
+    }
+
+    pub fn with_limits(mut self, min: f64, max: f64) -> Self {
+        self.limits = Some((min, max));
+        self
+    }
+
+    pub fn with_valid(mut self, valid: bool) -> Self {
+        self.valid = valid;
+        self
+    }
+}
+
+/// The channels of a driver, see [`SlaveDriver::channels`].
+pub type Channels<'a> = Box<dyn Iterator<Item = IoChannel> + 'a>;
+
+/// The lifecycle shared by the drivers, for code handling slaves of any
+/// kind.
+///
+/// A driver is created for a slave it [`matches`](Self::matches), either by
+/// [`configure`](Self::configure) while configuring the IgH master, or by
+/// [`bind`](Self::bind) to the process image of the slave on any backend
+/// after activation. It then [`process`](Self::process)es the domain data
+/// once per cycle. Drivers of third-party devices implement it the same
+/// way.
+pub trait SlaveDriver: Sized {
+    /// Parameters of the driver, e.g. the ports of an IO-Link master.
+    type Settings;
+
+    /// Returns `true` if the driver supports slaves with this identity.
+    fn matches(id: SlaveId) -> bool;
+
+    /// Parameterize the slave with startup SDOs and register its process
+    /// data in `domain`.
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        settings: &Self::Settings,
+    ) -> Result<Self>;
+
+    /// Locate the process data in the image of a slave with its default
+    /// mapping. Settings written by startup SDOs must already be in effect.
+    fn bind(id: SlaveId, image: &SlaveImage, settings: &Self::Settings) -> Result<Self>;
+
+    /// Exchange the values of the driver with the domain data.
+    fn process(&mut self, data: &mut [u8]);
+
+    /// Parameterize the slave again when it comes back on the bus, e.g.
+    /// after it was replaced under power.
+    ///
+    /// The IgH master applies the startup SDOs of [`configure`](Self::configure)
+    /// again by itself, but not the settings written with SDOs after the
+    /// activation, which drivers write again here.
+    fn restore(
+        &mut self,
+        _backend: &mut dyn Backend,
+        _slave: SlavePos,
+        _settings: &Self::Settings,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Command the safe state of the outputs, for drivers which have one.
+    fn shutdown(&mut self) {}
+
+    /// The channels of the slave, for code enumerating the IO of any slave.
+    /// Drivers of slaves without scalar channels, like gateways, have none.
+    fn channels(&self) -> Channels<'_> {
+        Box::new(std::iter::empty())
+    }
+}
+
+/// The scalings of the channels of a terminal, from driver settings with
+/// at most `count` channels.
+fn channel_scalings(
+    count: usize,
+    scalings: &[Option<ChannelScaling>],
+) -> Result<Vec<Option<ChannelScaling>>> {
+    if scalings.len() > count {
+        return Err(Error::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "the terminal has {} channels, {} scalings given",
+                count,
+                scalings.len()
+            ),
+        )));
+    }
+    if let Some(s) = scalings
+        .iter()
+        .flatten()
+        .find(|s| !s.scale.is_normal() || !s.offset.is_finite())
+    {
+        return Err(Error::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid scaling of {} to {:?}", s.scale, s.unit),
+        )));
+    }
+    let mut scalings = scalings.to_vec();
+    scalings.resize(count, None);
+    Ok(scalings)
+}
+
+/// Number of a Beckhoff EL terminal, e.g. 1008 for an EL1008.
+///
+/// The product codes of EL terminals hold the number in the high word.
+pub fn beckhoff_terminal(id: SlaveId) -> Option<u32> {
+    if id.vendor_id == BECKHOFF && id.product_code & 0xFFFF == 0x3052 {
+        Some(id.product_code >> 16)
+    } else {
+        None
+    }
+}
+
+/// Register the entry with subindex 1 of the objects `base + 0x10 * i` of
+/// `count` channels, as used by the Beckhoff terminals.
+#[cfg(target_os = "linux")]
+fn register_channels(
+    config: &mut SlaveConfig,
+    base: u16,
+    count: usize,
+    domain: DomainIdx,
+) -> Result<Vec<Field<bool>>> {
+    (0..count)
+        .map(|i| config.register_field(PdoEntryIdx::new(base + 0x10 * i as u16, 1), domain))
+        .collect()
+}
+
+/// `count` bits packed from the start of a range of the process image.
+fn packed_bits(
+    domain: DomainIdx,
+    range: Option<Range<usize>>,
+    count: usize,
+) -> Result<Vec<Field<bool>>> {
+    let range = range.ok_or_else(image_too_small)?;
+    if range.len() * 8 < count {
+        return Err(image_too_small());
+    }
+    Ok((0..count)
+        .map(|i| {
+            let offset = Offset {
+                byte: range.start + i / 8,
+                bit: (i % 8) as u32,
+            };
+            Field::new(domain, offset)
+        })
+        .collect())
+}
+
+fn unsupported(id: SlaveId) -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "unsupported slave {:#x}:{:#010x}",
+            id.vendor_id, id.product_code
+        ),
+    ))
+}
+
+fn image_too_small() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "the process image of the slave does not match its default mapping",
+    ))
+}
+
+#[test]
+fn test_slave_driver() {
+    fn bind<D: SlaveDriver>(id: SlaveId, image: &SlaveImage, settings: &D::Settings) -> Option<D> {
+        if D::matches(id) {
+            D::bind(id, image, settings).ok()
+        } else {
+            None
+        }
+    }
+
+    let el2004 = SlaveId::new(2, 0x07d4_3052);
+    let image = SlaveImage {
+        domain: DomainIdx::from(0),
+        outputs: Some(0..1),
+        inputs: None,
+    };
+    assert!(bind::<DigitalInputs>(el2004, &image, &()).is_none());
+    assert!(bind::<ValveTerminal>(el2004, &image, &4).is_none());
+    let mut outputs = bind::<DigitalOutputs>(el2004, &image, &()).unwrap();
+    outputs.set(2, true);
+    let mut data = [0];
+    SlaveDriver::process(&mut outputs, &mut data);
+    assert_eq!(data, [0b100]);
+}
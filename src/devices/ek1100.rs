@@ -0,0 +1,277 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::BECKHOFF;
+use crate::{backend::Backend, topology::Topology, types::*};
+use std::{
+    fmt, io, thread,
+    time::{Duration, Instant},
+};
+
+/// Couplers from EtherCAT to the E-bus, by number, e.g. 1100 for an EK1100.
+const COUPLERS: &[u32] = &[1100, 1101, 1501, 1814, 1818, 1828];
+
+const AL_CONTROL: u16 = 0x0120;
+const AL_STATUS: u16 = 0x0130;
+const AL_STATUS_CODE: u16 = 0x0134;
+/// Bit of AL control requesting the explicit device ID, and of AL status
+/// signaling it is loaded into the AL status code.
+const ID_REQUEST: u8 = 0x20;
+
+/// Returns `true` for Beckhoff EK couplers, like the EK1100 or EK1101.
+pub fn is_coupler(id: SlaveId) -> bool {
+    id.vendor_id == BECKHOFF
+        && id.product_code & 0xFFFF == 0x2c52
+        && COUPLERS.contains(&(id.product_code >> 16))
+}
+
+/// A coupler and the terminals on its E-bus, an IO island of the machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CouplerGroup {
+    pub coupler: SlavePos,
+    pub id: SlaveId,
+    /// Station alias from the SII.
+    pub alias: u16,
+    /// The terminals in processing order.
+    pub terminals: Vec<SlavePos>,
+}
+
+/// Group the slaves under their couplers.
+///
+/// The terminals of a coupler are the slaves reached over E-bus ports from
+/// it, up to the next coupler. Slaves before the first coupler, e.g. drives
+/// connected by cable, are in no group.
+pub fn coupler_groups(topology: &Topology, slaves: &[SlaveInfo]) -> Vec<CouplerGroup> {
+    let info = |slave: SlavePos| slaves.iter().find(|s| s.ring_pos == slave);
+    let mut groups = vec![];
+    for node in topology.nodes() {
+        let coupler = match info(node.slave) {
+            Some(coupler) if is_coupler(coupler.id) => coupler,
+            _ => continue,
+        };
+        let mut terminals = vec![];
+        let mut stack = vec![node.slave];
+        while let Some(slave) = stack.pop() {
+            let node = match topology.node(slave) {
+                Some(node) => node,
+                None => continue,
+            };
+            for link in &node.children {
+                let on_ebus = matches!(node.ports[link.port].desc, SlavePortType::EBus);
+                let is_terminal = info(link.slave).map_or(false, |s| !is_coupler(s.id));
+                if on_ebus && is_terminal && !terminals.contains(&link.slave) {
+                    terminals.push(link.slave);
+                    stack.push(link.slave);
+                }
+            }
+        }
+        // ring positions follow the processing order
+        terminals.sort_by_key(|slave| u16::from(*slave));
+        groups.push(CouplerGroup {
+            coupler: node.slave,
+            id: coupler.id,
+            alias: coupler.alias,
+            terminals,
+        });
+    }
+    groups
+}
+
+/// A difference between an IO island and its expected layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutMismatch {
+    /// The terminal at `index` of the island is not the expected one.
+    Wrong {
+        coupler: SlavePos,
+        index: usize,
+        expected: SlaveId,
+        found: SlaveId,
+    },
+    /// The island ends before the expected terminal at `index`.
+    Missing {
+        coupler: SlavePos,
+        index: usize,
+        expected: SlaveId,
+    },
+    /// The island has more terminals than expected.
+    Unexpected {
+        coupler: SlavePos,
+        index: usize,
+        found: SlaveId,
+    },
+}
+
+impl fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let id = |id: &SlaveId| format!("{:#x}:{:#010x}", id.vendor_id, id.product_code);
+        match self {
+            LayoutMismatch::Wrong {
+                coupler,
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "coupler {}, terminal {}: expected {}, found {}",
+                u16::from(*coupler),
+                index,
+                id(expected),
+                id(found)
+            ),
+            LayoutMismatch::Missing {
+                coupler,
+                index,
+                expected,
+            } => write!(
+                f,
+                "coupler {}, terminal {}: expected {}, found none",
+                u16::from(*coupler),
+                index,
+                id(expected)
+            ),
+            LayoutMismatch::Unexpected {
+                coupler,
+                index,
+                found,
+            } => write!(
+                f,
+                "coupler {}, terminal {}: unexpected {}",
+                u16::from(*coupler),
+                index,
+                id(found)
+            ),
+        }
+    }
+}
+
+impl CouplerGroup {
+    /// Compare the terminals with the expected ones, in order.
+    ///
+    /// Revisions are not compared, so that spare parts of a newer revision
+    /// are accepted.
+    pub fn verify(&self, slaves: &[SlaveInfo], expected: &[SlaveId]) -> Vec<LayoutMismatch> {
+        let found: Vec<SlaveId> = self
+            .terminals
+            .iter()
+            .filter_map(|t| slaves.iter().find(|s| s.ring_pos == *t))
+            .map(|s| s.id)
+            .collect();
+        let coupler = self.coupler;
+        let mut mismatches = vec![];
+        for index in 0..found.len().max(expected.len()) {
+            match (expected.get(index), found.get(index)) {
+                (Some(&expected), Some(&found)) if expected != found => {
+                    mismatches.push(LayoutMismatch::Wrong {
+                        coupler,
+                        index,
+                        expected,
+                        found,
+                    })
+                }
+                (Some(&expected), None) => mismatches.push(LayoutMismatch::Missing {
+                    coupler,
+                    index,
+                    expected,
+                }),
+                (None, Some(&found)) => mismatches.push(LayoutMismatch::Unexpected {
+                    coupler,
+                    index,
+                    found,
+                }),
+                _ => (),
+            }
+        }
+        mismatches
+    }
+}
+
+/// Verify that every IO island has the same `expected` terminals, for
+/// machines built from identical islands.
+pub fn verify_islands(
+    groups: &[CouplerGroup],
+    slaves: &[SlaveInfo],
+    expected: &[SlaveId],
+) -> Vec<LayoutMismatch> {
+    groups
+        .iter()
+        .flat_map(|group| group.verify(slaves, expected))
+        .collect()
+}
+
+/// Read the ID switch of a coupler like the EK1101, with the explicit
+/// device identification of the AL control register.
+///
+/// This should be done before activating the master, as the AL control
+/// register is written directly.
+pub fn read_id_switch<B: Backend + ?Sized>(backend: &mut B, coupler: SlavePos) -> Result<u16> {
+    let mut status = [0; 2];
+    backend.read_register(coupler, AL_STATUS, &mut status)?;
+    let state = status[0] & 0x0F;
+    backend.write_register(coupler, AL_CONTROL, &[state | ID_REQUEST, 0])?;
+    let start = Instant::now();
+    let result = loop {
+        backend.read_register(coupler, AL_STATUS, &mut status)?;
+        if status[0] & ID_REQUEST != 0 {
+            let mut code = [0; 2];
+            backend.read_register(coupler, AL_STATUS_CODE, &mut code)?;
+            break Ok(u16::from_le_bytes(code));
+        }
+        if start.elapsed() > Duration::from_millis(100) {
+            break Err(Error::Io(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the slave does not support the explicit device ID",
+            )));
+        }
+        thread::sleep(Duration::from_millis(1));
+    };
+    backend.write_register(coupler, AL_CONTROL, &[state, 0])?;
+    result
+}
+
+#[test]
+fn test_coupler_groups() {
+    use crate::topology::test_slave;
+
+    const N: u16 = 0xFFFF;
+    let el1008 = SlaveId::new(2, 0x03f0_3052);
+    let el2004 = SlaveId::new(2, 0x07d4_3052);
+    let el2008 = SlaveId::new(2, 0x07d8_3052);
+    // coupler 0 with terminals 1 and 2 on port D, cable from port B to
+    // coupler 3 with terminals 4 and 5
+    let mut slaves = vec![
+        test_slave(0, [N, 3, N, 1]),
+        test_slave(1, [0, 2, N, N]),
+        test_slave(2, [1, N, N, N]),
+        test_slave(3, [0, N, N, 4]),
+        test_slave(4, [3, 5, N, N]),
+        test_slave(5, [4, N, N, N]),
+    ];
+    slaves[0].ports[1].desc = SlavePortType::MII;
+    slaves[3].alias = 7;
+    for (slave, id) in [(1, el1008), (2, el2004), (4, el1008), (5, el2008)] {
+        slaves[slave].id = id;
+    }
+    let groups = coupler_groups(&Topology::from_slaves(&slaves), &slaves);
+    let pos = SlavePos::from;
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].terminals, [pos(1), pos(2)]);
+    assert_eq!(
+        (groups[1].alias, &groups[1].terminals[..]),
+        (7, &[pos(4), pos(5)][..])
+    );
+
+    let mismatches = verify_islands(&groups, &slaves, &[el1008, el2004]);
+    assert_eq!(
+        mismatches,
+        [LayoutMismatch::Wrong {
+            coupler: pos(3),
+            index: 1,
+            expected: el2004,
+            found: el2008
+        }]
+    );
+    assert_eq!(
+        groups[0].verify(&slaves, &[el1008])[0].to_string(),
+        "coupler 0, terminal 1: unexpected 0x2:0x07d43052"
+    );
+}
@@ -0,0 +1,170 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+#[cfg(target_os = "linux")]
+use super::register_channels;
+use super::{beckhoff_terminal, packed_bits, unsupported, Channels, IoChannel, SlaveDriver};
+#[cfg(target_os = "linux")]
+use crate::SlaveConfig;
+use crate::{backend::SlaveImage, field::Field, types::*};
+
+/// Supported terminals and their number of channels.
+const TERMINALS: &[(u32, usize)] = &[
+    (1002, 2),
+    (1004, 4),
+    (1008, 8),
+    (1012, 2),
+    (1014, 4),
+    (1018, 8),
+    (1024, 4),
+    (1034, 4),
+    (1084, 4),
+    (1088, 8),
+    (1094, 4),
+    (1098, 8),
+    (1104, 4),
+    (1114, 4),
+    (1124, 4),
+    (1144, 4),
+    (1202, 2),
+    (1809, 16),
+    (1819, 16),
+    (1862, 16),
+    (1872, 16),
+    (1889, 16),
+];
+
+/// Beckhoff EL1xxx digital input terminals, like the EL1008 or EL1018.
+///
+/// Channel `i` is mapped from the object 0x6000 + 0x10 * `i`, subindex 1.
+#[derive(Debug, Clone)]
+pub struct DigitalInputs {
+    fields: Vec<Field<bool>>,
+    state: u32,
+}
+
+impl DigitalInputs {
+    /// Number of channels of a supported terminal, `None` for other slaves.
+    pub fn detect(id: SlaveId) -> Option<usize> {
+        let number = beckhoff_terminal(id)?;
+        TERMINALS
+            .iter()
+            .find(|(n, _)| *n == number)
+            .map(|(_, channels)| *channels)
+    }
+
+    /// Register the channels of the terminal in `domain`.
+    #[cfg(target_os = "linux")]
+    pub fn configure(config: &mut SlaveConfig, id: SlaveId, domain: DomainIdx) -> Result<Self> {
+        let channels = Self::detect(id).ok_or_else(|| unsupported(id))?;
+        let fields = register_channels(config, 0x6000, channels, domain)?;
+        Ok(Self { fields, state: 0 })
+    }
+
+    /// Locate the channels in the inputs of a slave with the default mapping,
+    /// where they are packed in consecutive bits.
+    pub fn from_image(id: SlaveId, image: &SlaveImage) -> Result<Self> {
+        let channels = Self::detect(id).ok_or_else(|| unsupported(id))?;
+        let fields = packed_bits(image.domain, image.inputs.clone(), channels)?;
+        Ok(Self { fields, state: 0 })
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Read the inputs from the domain data.
+    pub fn process(&mut self, data: &mut [u8]) {
+        self.state = self
+            .fields
+            .iter()
+            .enumerate()
+            .fold(0, |state, (i, field)| state | (field.get(data) as u32) << i);
+    }
+
+    /// State of channel `i`, counted from 0, as of the last
+    /// [`process`](Self::process).
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no channel `i`.
+    pub fn channel(&self, i: usize) -> bool {
+        assert!(i < self.fields.len(), "no input channel {}", i);
+        self.state & 1 << i != 0
+    }
+
+    /// The states of all channels, channel 0 in the lowest bit.
+    pub const fn bits(&self) -> u32 {
+        self.state
+    }
+}
+
+impl SlaveDriver for DigitalInputs {
+    type Settings = ();
+
+    fn matches(id: SlaveId) -> bool {
+        Self::detect(id).is_some()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        _settings: &(),
+    ) -> Result<Self> {
+        Self::configure(config, id, domain)
+    }
+
+    fn bind(id: SlaveId, image: &SlaveImage, _settings: &()) -> Result<Self> {
+        Self::from_image(id, image)
+    }
+
+    fn process(&mut self, data: &mut [u8]) {
+        self.process(data)
+    }
+
+    fn channels(&self) -> Channels<'_> {
+        Box::new(
+            (0..self.channel_count())
+                .map(move |i| IoChannel::input(format!("Input {}", i + 1), self.channel(i))),
+        )
+    }
+}
+
+#[test]
+fn test_digital_inputs() {
+    use crate::backend::{Backend, SimMaster, SimSlave};
+
+    let el1008 = SlaveId::new(2, 0x03f0_3052);
+    assert_eq!(DigitalInputs::detect(el1008), Some(8));
+    assert_eq!(DigitalInputs::detect(SlaveId::new(2, 0x044c_2c52)), None);
+
+    let mut tx = vec![];
+    for i in 0..8 {
+        let mut pdo = PdoCfg::new(PdoIdx::from(0x1A00 + i));
+        pdo.entries = smallvec::smallvec![PdoEntryInfo {
+            entry_idx: PdoEntryIdx::new(0x6000 + 0x10 * i, 1),
+            bit_len: 1,
+            name: String::new(),
+            pos: PdoEntryPos::from(0),
+        }];
+        tx.push(pdo);
+    }
+    let slave = tx
+        .into_iter()
+        .fold(SimSlave::new("EL1008", el1008), |s, pdo| s.tx_pdo(pdo));
+    let mut master = SimMaster::new(vec![slave]);
+    master.activate().unwrap();
+    let image = master.slave_image(SlavePos::from(0)).unwrap();
+    let mut inputs = DigitalInputs::from_image(el1008, &image).unwrap();
+    assert_eq!(inputs.channel_count(), 8);
+
+    master.slave_mut(SlavePos::from(0)).unwrap().inputs_mut()[0] = 0b1000_0101;
+    master.send().unwrap();
+    master.receive().unwrap();
+    inputs.process(master.domain_data(DomainIdx::from(0)).unwrap());
+    assert!(inputs.channel(0) && !inputs.channel(1) && inputs.channel(2));
+    assert!(inputs.channel(7));
+    assert_eq!(inputs.bits(), 0x85);
+}
@@ -0,0 +1,240 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+#[cfg(target_os = "linux")]
+use super::register_channels;
+use super::{beckhoff_terminal, packed_bits, unsupported, Channels, IoChannel, SlaveDriver};
+#[cfg(target_os = "linux")]
+use crate::SlaveConfig;
+use crate::{backend::SlaveImage, field::Field, types::*};
+
+/// Supported terminals and their number of channels.
+const TERMINALS: &[(u32, usize)] = &[
+    (2002, 2),
+    (2004, 4),
+    (2008, 8),
+    (2022, 2),
+    (2024, 4),
+    (2034, 4),
+    (2042, 2),
+    (2084, 4),
+    (2088, 8),
+    (2124, 4),
+    (2202, 2),
+    (2602, 2),
+    (2612, 2),
+    (2622, 2),
+    (2624, 4),
+    (2634, 4),
+    (2809, 16),
+    (2872, 16),
+    (2889, 16),
+];
+
+/// Beckhoff EL2xxx digital output terminals, like the EL2004 or EL2008.
+///
+/// Channel `i` is mapped from the object 0x7000 + 0x10 * `i`, subindex 1.
+/// All outputs are off until set.
+#[derive(Debug, Clone)]
+pub struct DigitalOutputs {
+    fields: Vec<Field<bool>>,
+    state: u32,
+    safe_state: Option<u32>,
+}
+
+impl DigitalOutputs {
+    /// Number of channels of a supported terminal, `None` for other slaves.
+    pub fn detect(id: SlaveId) -> Option<usize> {
+        let number = beckhoff_terminal(id)?;
+        TERMINALS
+            .iter()
+            .find(|(n, _)| *n == number)
+            .map(|(_, channels)| *channels)
+    }
+
+    /// Register the channels of the terminal in `domain`.
+    #[cfg(target_os = "linux")]
+    pub fn configure(config: &mut SlaveConfig, id: SlaveId, domain: DomainIdx) -> Result<Self> {
+        let channels = Self::detect(id).ok_or_else(|| unsupported(id))?;
+        Ok(Self::new(register_channels(
+            config, 0x7000, channels, domain,
+        )?))
+    }
+
+    /// Locate the channels in the outputs of a slave with the default
+    /// mapping, where they are packed in consecutive bits.
+    pub fn from_image(id: SlaveId, image: &SlaveImage) -> Result<Self> {
+        let channels = Self::detect(id).ok_or_else(|| unsupported(id))?;
+        Ok(Self::new(packed_bits(
+            image.domain,
+            image.outputs.clone(),
+            channels,
+        )?))
+    }
+
+    const fn new(fields: Vec<Field<bool>>) -> Self {
+        Self {
+            fields,
+            state: 0,
+            safe_state: None,
+        }
+    }
+
+    /// States applied by [`shutdown`](Self::shutdown), channel 0 in the
+    /// lowest bit.
+    ///
+    /// Without a safe state, the outputs keep their last state on shutdown,
+    /// until the slave leaves OP.
+    pub fn with_safe_state(mut self, bits: u32) -> Self {
+        self.safe_state = Some(bits & self.mask());
+        self
+    }
+
+    pub const fn safe_state(&self) -> Option<u32> {
+        self.safe_state
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Write the commanded states into the domain data.
+    pub fn process(&mut self, data: &mut [u8]) {
+        for (i, field) in self.fields.iter().enumerate() {
+            field.set(data, self.state & 1 << i != 0);
+        }
+    }
+
+    /// Command channel `i`, counted from 0.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no channel `i`.
+    pub fn set(&mut self, i: usize, on: bool) {
+        assert!(i < self.fields.len(), "no output channel {}", i);
+        if on {
+            self.state |= 1 << i;
+        } else {
+            self.state &= !(1 << i);
+        }
+    }
+
+    /// Commanded state of channel `i`.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no channel `i`.
+    pub fn channel(&self, i: usize) -> bool {
+        assert!(i < self.fields.len(), "no output channel {}", i);
+        self.state & 1 << i != 0
+    }
+
+    /// Command all channels, channel 0 in the lowest bit.
+    pub fn set_bits(&mut self, bits: u32) {
+        self.state = bits & self.mask();
+    }
+
+    /// The commanded states of all channels, channel 0 in the lowest bit.
+    pub const fn bits(&self) -> u32 {
+        self.state
+    }
+
+    /// Command the safe state, if any.
+    ///
+    /// Call this in the cycle where
+    /// [`CycleContext::is_stopping`](crate::runtime::CycleContext::is_stopping)
+    /// is `true`, before [`process`](Self::process), so that the safe state
+    /// is sent before the executor stops.
+    pub fn shutdown(&mut self) {
+        if let Some(bits) = self.safe_state {
+            self.state = bits;
+        }
+    }
+
+    fn mask(&self) -> u32 {
+        (1_u64 << self.fields.len()) as u32 - 1
+    }
+}
+
+impl SlaveDriver for DigitalOutputs {
+    type Settings = ();
+
+    fn matches(id: SlaveId) -> bool {
+        Self::detect(id).is_some()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        _settings: &(),
+    ) -> Result<Self> {
+        Self::configure(config, id, domain)
+    }
+
+    fn bind(id: SlaveId, image: &SlaveImage, _settings: &()) -> Result<Self> {
+        Self::from_image(id, image)
+    }
+
+    fn process(&mut self, data: &mut [u8]) {
+        self.process(data)
+    }
+
+    fn shutdown(&mut self) {
+        self.shutdown()
+    }
+
+    fn channels(&self) -> Channels<'_> {
+        Box::new(
+            (0..self.channel_count())
+                .map(move |i| IoChannel::output(format!("Output {}", i + 1), self.channel(i))),
+        )
+    }
+}
+
+#[test]
+fn test_digital_outputs() {
+    use crate::backend::{Backend, SimMaster, SimSlave};
+
+    let el2004 = SlaveId::new(2, 0x07d4_3052);
+    assert_eq!(DigitalOutputs::detect(el2004), Some(4));
+
+    let mut rx = PdoCfg::new(PdoIdx::from(0x1600));
+    rx.entries = (0..4)
+        .map(|i| PdoEntryInfo {
+            entry_idx: PdoEntryIdx::new(0x7000 + 0x10 * i, 1),
+            bit_len: 1,
+            name: String::new(),
+            pos: PdoEntryPos::from(i as u8),
+        })
+        .collect();
+    let mut master = SimMaster::new(vec![SimSlave::new("EL2004", el2004).rx_pdo(rx)]);
+    master.activate().unwrap();
+    let image = master.slave_image(SlavePos::from(0)).unwrap();
+    let mut outputs = DigitalOutputs::from_image(el2004, &image)
+        .unwrap()
+        .with_safe_state(0b1_0010);
+    assert_eq!(outputs.safe_state(), Some(0b0010));
+
+    outputs.set(0, true);
+    outputs.set(3, true);
+    assert!(outputs.channel(3) && !outputs.channel(1));
+    outputs.process(master.domain_data(DomainIdx::from(0)).unwrap());
+    master.send().unwrap();
+    master.receive().unwrap();
+    assert_eq!(
+        master.slave(SlavePos::from(0)).unwrap().outputs()[0],
+        0b1001
+    );
+
+    outputs.shutdown();
+    assert_eq!(outputs.bits(), 0b0010);
+    outputs.process(master.domain_data(DomainIdx::from(0)).unwrap());
+    master.send().unwrap();
+    master.receive().unwrap();
+    assert_eq!(
+        master.slave(SlavePos::from(0)).unwrap().outputs()[0],
+        0b0010
+    );
+}
@@ -0,0 +1,386 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{beckhoff_terminal, image_too_small, unsupported, Channels, IoChannel, SlaveDriver};
+#[cfg(target_os = "linux")]
+use crate::SlaveConfig;
+use crate::{
+    backend::{Backend, SlaveImage},
+    field::{Field, ScaledField},
+    types::*,
+};
+
+/// Supported terminals.
+const TERMINALS: &[u32] = &[3356];
+
+/// Settings object of the terminal.
+const SETTINGS: u16 = 0x8000;
+const FILTER: u8 = 0x11;
+const NOMINAL_CHARACTERISTIC: u8 = 0x23;
+const ZERO_BALANCE: u8 = 0x24;
+const NOMINAL_LOAD: u8 = 0x25;
+const GRAVITY: u8 = 0x26;
+const SCALE_FACTOR: u8 = 0x27;
+
+/// Filter of the measured values, object 0x8000:11.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadCellFilter {
+    /// Notch filter for 50 Hz mains.
+    Fir50Hz = 0,
+    /// Notch filter for 60 Hz mains.
+    Fir60Hz = 1,
+    /// IIR low pass filters, from the weakest at 1 to the strongest at 8.
+    Iir1 = 2,
+    Iir2 = 3,
+    Iir3 = 4,
+    Iir4 = 5,
+    Iir5 = 6,
+    Iir6 = 7,
+    Iir7 = 8,
+    Iir8 = 9,
+    /// Switches between IIR filters depending on the rate of change.
+    DynamicIir = 10,
+}
+
+/// Calibration of the load cell and the scaling of the weight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoadCellSettings {
+    /// Output of the cell at nominal load in mV/V, from its data sheet.
+    pub nominal_characteristic: f32,
+    /// Output of the cell without load in mV/V.
+    pub zero_balance: f32,
+    /// Nominal load of the cell, in the unit of the weight.
+    pub nominal_load: f32,
+    /// Acceleration of gravity in m/s², to weigh in force units.
+    pub gravity: f32,
+    /// Counts of the process value per unit of weight.
+    pub scale_factor: f32,
+    pub filter: LoadCellFilter,
+}
+
+impl Default for LoadCellSettings {
+    fn default() -> Self {
+        Self {
+            nominal_characteristic: 2.0,
+            zero_balance: 0.0,
+            nominal_load: 5.0,
+            gravity: 9.806_65,
+            scale_factor: 1000.0,
+            filter: LoadCellFilter::Fir50Hz,
+        }
+    }
+}
+
+/// Weight and status of a load cell.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Weight {
+    /// The weight in the unit of the nominal load.
+    pub value: f64,
+    pub raw: i32,
+    pub underrange: bool,
+    pub overrange: bool,
+    /// The value is not valid, e.g. during a calibration.
+    pub invalid: bool,
+    pub error: bool,
+    /// The terminal is calibrating its ADC.
+    pub calibrating: bool,
+    /// The value stayed within the steady state window.
+    pub steady: bool,
+}
+
+impl Weight {
+    pub const fn is_valid(&self) -> bool {
+        !(self.underrange || self.overrange || self.invalid || self.error || self.calibrating)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Fields {
+    underrange: Field<bool>,
+    overrange: Field<bool>,
+    invalid: Field<bool>,
+    error: Field<bool>,
+    calibrating: Field<bool>,
+    steady: Field<bool>,
+    value: ScaledField<i32>,
+    calibrate: Field<bool>,
+    tare: Field<bool>,
+}
+
+/// Beckhoff EL3356 load cell terminal.
+///
+/// The status and the value are mapped from the object 0x6000, subindices
+/// 1 to 9 and 0x11, and the control bits from 0x7000. The terminal computes
+/// the weight from its [`LoadCellSettings`] in the object 0x8000, which is
+/// written with startup SDOs or [`write_settings`](Self::write_settings).
+#[derive(Debug, Clone)]
+pub struct LoadCell {
+    fields: Fields,
+    weight: Weight,
+    /// Control bits raised for one cycle.
+    tare: bool,
+    calibrate: bool,
+}
+
+impl LoadCell {
+    /// Returns `true` for supported terminals.
+    pub fn detect(id: SlaveId) -> bool {
+        beckhoff_terminal(id).map_or(false, |n| TERMINALS.contains(&n))
+    }
+
+    /// Write the settings with startup SDOs and register the process data
+    /// in `domain`.
+    #[cfg(target_os = "linux")]
+    pub fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        settings: &LoadCellSettings,
+    ) -> Result<Self> {
+        if !Self::detect(id) {
+            return Err(unsupported(id));
+        }
+        for (sub, value) in float_settings(settings) {
+            config.add_sdo(SdoIdx::new(SETTINGS, sub), &value)?;
+        }
+        config.add_sdo(SdoIdx::new(SETTINGS, FILTER), &(settings.filter as u16))?;
+        let value = config.register_field(PdoEntryIdx::new(0x6000, 0x11), domain)?;
+        let fields = Fields {
+            underrange: config.register_field(PdoEntryIdx::new(0x6000, 0x01), domain)?,
+            overrange: config.register_field(PdoEntryIdx::new(0x6000, 0x02), domain)?,
+            invalid: config.register_field(PdoEntryIdx::new(0x6000, 0x04), domain)?,
+            error: config.register_field(PdoEntryIdx::new(0x6000, 0x07), domain)?,
+            calibrating: config.register_field(PdoEntryIdx::new(0x6000, 0x08), domain)?,
+            steady: config.register_field(PdoEntryIdx::new(0x6000, 0x09), domain)?,
+            value: weight(value, settings),
+            calibrate: config.register_field(PdoEntryIdx::new(0x7000, 0x01), domain)?,
+            tare: config.register_field(PdoEntryIdx::new(0x7000, 0x05), domain)?,
+        };
+        Ok(Self::new(fields))
+    }
+
+    /// Locate the process data of a slave with the default mapping: the
+    /// status word and the 32 bit value in the inputs, and the control word
+    /// in the outputs.
+    ///
+    /// `settings` must be the ones of the terminal, for the scale factor of
+    /// the value.
+    pub fn from_image(
+        id: SlaveId,
+        image: &SlaveImage,
+        settings: &LoadCellSettings,
+    ) -> Result<Self> {
+        if !Self::detect(id) {
+            return Err(unsupported(id));
+        }
+        let inputs = image.inputs.clone().ok_or_else(image_too_small)?;
+        let outputs = image.outputs.clone().ok_or_else(image_too_small)?;
+        if inputs.len() < 6 || outputs.len() < 2 {
+            return Err(image_too_small());
+        }
+        let domain = image.domain;
+        let (i, o) = (inputs.start, outputs.start);
+        let bit = |byte, bit| Field::new(domain, Offset { byte, bit });
+        let value = Field::new(
+            domain,
+            Offset {
+                byte: i + 2,
+                bit: 0,
+            },
+        );
+        let fields = Fields {
+            underrange: bit(i, 0),
+            overrange: bit(i, 1),
+            invalid: bit(i, 3),
+            error: bit(i, 6),
+            calibrating: bit(i, 7),
+            steady: bit(i + 1, 0),
+            value: weight(value, settings),
+            calibrate: bit(o, 0),
+            tare: bit(o, 4),
+        };
+        Ok(Self::new(fields))
+    }
+
+    fn new(fields: Fields) -> Self {
+        Self {
+            fields,
+            weight: Weight::default(),
+            tare: false,
+            calibrate: false,
+        }
+    }
+
+    /// Write the settings to the terminal, e.g. after a calibration with a
+    /// reference weight, and adapt the scaling of the value.
+    pub fn write_settings<B: Backend + ?Sized>(
+        &mut self,
+        backend: &mut B,
+        slave: SlavePos,
+        settings: &LoadCellSettings,
+    ) -> Result<()> {
+        for (sub, value) in float_settings(settings) {
+            backend.sdo_download(
+                slave,
+                SdoIdx::new(SETTINGS, sub),
+                false,
+                &value.to_le_bytes(),
+            )?;
+        }
+        let filter = (settings.filter as u16).to_le_bytes();
+        backend.sdo_download(slave, SdoIdx::new(SETTINGS, FILTER), false, &filter)?;
+        self.fields.value = weight(self.fields.value.field, settings);
+        Ok(())
+    }
+
+    /// Read the weight from and write the control bits into the domain
+    /// data.
+    pub fn process(&mut self, data: &mut [u8]) {
+        let f = &self.fields;
+        self.weight = Weight {
+            value: f.value.get(data),
+            raw: f.value.field.get(data),
+            underrange: f.underrange.get(data),
+            overrange: f.overrange.get(data),
+            invalid: f.invalid.get(data),
+            error: f.error.get(data),
+            calibrating: f.calibrating.get(data),
+            steady: f.steady.get(data),
+        };
+        f.tare.set(data, self.tare);
+        f.calibrate.set(data, self.calibrate);
+        self.tare = false;
+        self.calibrate = false;
+    }
+
+    /// The weight as of the last [`process`](Self::process).
+    pub const fn weight(&self) -> Weight {
+        self.weight
+    }
+
+    /// The conversion of the raw value.
+    pub const fn scaling(&self) -> &ScaledField<i32> {
+        &self.fields.value
+    }
+
+    /// Set the current weight as zero, with the next
+    /// [`process`](Self::process).
+    ///
+    /// The terminal keeps the tare until it is switched off.
+    pub fn tare(&mut self) {
+        self.tare = true;
+    }
+
+    /// Start a calibration of the ADC of the terminal, with the next
+    /// [`process`](Self::process).
+    ///
+    /// The values are invalid while [`Weight::calibrating`] is set.
+    pub fn calibrate(&mut self) {
+        self.calibrate = true;
+    }
+}
+
+impl SlaveDriver for LoadCell {
+    type Settings = LoadCellSettings;
+
+    fn matches(id: SlaveId) -> bool {
+        Self::detect(id)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        settings: &LoadCellSettings,
+    ) -> Result<Self> {
+        Self::configure(config, id, domain, settings)
+    }
+
+    fn bind(id: SlaveId, image: &SlaveImage, settings: &LoadCellSettings) -> Result<Self> {
+        Self::from_image(id, image, settings)
+    }
+
+    fn process(&mut self, data: &mut [u8]) {
+        self.process(data)
+    }
+
+    fn restore(
+        &mut self,
+        backend: &mut dyn Backend,
+        slave: SlavePos,
+        settings: &LoadCellSettings,
+    ) -> Result<()> {
+        self.write_settings(backend, slave, settings)
+    }
+
+    fn channels(&self) -> Channels<'_> {
+        let weight = self.weight;
+        Box::new(std::iter::once(
+            IoChannel::input("Weight".to_owned(), weight.value).with_valid(weight.is_valid()),
+        ))
+    }
+}
+
+fn float_settings(settings: &LoadCellSettings) -> [(u8, f32); 5] {
+    [
+        (NOMINAL_CHARACTERISTIC, settings.nominal_characteristic),
+        (ZERO_BALANCE, settings.zero_balance),
+        (NOMINAL_LOAD, settings.nominal_load),
+        (GRAVITY, settings.gravity),
+        (SCALE_FACTOR, settings.scale_factor),
+    ]
+}
+
+fn weight(field: Field<i32>, settings: &LoadCellSettings) -> ScaledField<i32> {
+    ScaledField::new(field, 1.0 / f64::from(settings.scale_factor), 0.0)
+}
+
+#[test]
+fn test_load_cell() {
+    use crate::backend::{SimMaster, SimSlave};
+
+    let el3356 = SlaveId::new(2, 0x0d1c_3052);
+    assert!(LoadCell::detect(el3356));
+    let image = SlaveImage {
+        domain: DomainIdx::from(0),
+        outputs: Some(0..2),
+        inputs: Some(2..8),
+    };
+    let settings = LoadCellSettings::default();
+    let mut cell = LoadCell::from_image(el3356, &image, &settings).unwrap();
+    let mut data = [0_u8; 8];
+    data[3] = 0b1;
+    data[4..8].copy_from_slice(&2500_i32.to_le_bytes());
+    cell.tare();
+    cell.process(&mut data);
+    let weight = cell.weight();
+    assert_eq!((weight.value, weight.raw), (2.5, 2500));
+    assert!(weight.steady && weight.is_valid());
+    assert_eq!(data[0], 0b1_0000);
+    cell.process(&mut data);
+    assert_eq!(data[0], 0);
+
+    let mut slave = SimSlave::new("EL3356", el3356).object(SdoIdx::new(SETTINGS, FILTER), &[0; 2]);
+    for (sub, _) in float_settings(&settings) {
+        slave = slave.object(SdoIdx::new(SETTINGS, sub), &[0; 4]);
+    }
+    let mut master = SimMaster::new(vec![slave]);
+    let pos = SlavePos::from(0);
+    let settings = LoadCellSettings {
+        scale_factor: 100.0,
+        filter: LoadCellFilter::Iir4,
+        ..settings
+    };
+    cell.write_settings(&mut master, pos, &settings).unwrap();
+    assert_eq!(cell.scaling().scale, 0.01);
+    let mut buf = [0; 4];
+    master
+        .sdo_upload(pos, SdoIdx::new(SETTINGS, SCALE_FACTOR), false, &mut buf)
+        .unwrap();
+    assert_eq!(f32::from_le_bytes(buf), 100.0);
+    master
+        .sdo_upload(pos, SdoIdx::new(SETTINGS, FILTER), false, &mut buf)
+        .unwrap();
+    assert_eq!(buf[..2], [5, 0]);
+}
@@ -0,0 +1,406 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{
+    beckhoff_terminal, channel_scalings, image_too_small, unsupported, ChannelScaling, Channels,
+    IoChannel, SlaveDriver, Unit,
+};
+#[cfg(target_os = "linux")]
+use crate::SlaveConfig;
+use crate::{
+    backend::{Backend, SlaveImage},
+    field::{Field, ScaledField},
+    types::*,
+};
+use std::io;
+
+/// Supported terminals: number, channels, and the values at the raw counts
+/// 0 and 0x7FFF.
+const TERMINALS: &[(u32, usize, f64, f64, Unit)] = &[
+    (3001, 1, 0.0, 10.0, Unit::Volt),
+    (3002, 2, 0.0, 10.0, Unit::Volt),
+    (3004, 4, 0.0, 10.0, Unit::Volt),
+    (3008, 8, 0.0, 10.0, Unit::Volt),
+    (3011, 1, 0.0, 20.0, Unit::Milliampere),
+    (3012, 2, 0.0, 20.0, Unit::Milliampere),
+    (3014, 4, 0.0, 20.0, Unit::Milliampere),
+    (3021, 1, 4.0, 20.0, Unit::Milliampere),
+    (3022, 2, 4.0, 20.0, Unit::Milliampere),
+    (3024, 4, 4.0, 20.0, Unit::Milliampere),
+    (3041, 1, 0.0, 20.0, Unit::Milliampere),
+    (3042, 2, 0.0, 20.0, Unit::Milliampere),
+    (3044, 4, 0.0, 20.0, Unit::Milliampere),
+    (3048, 8, 0.0, 20.0, Unit::Milliampere),
+    (3051, 1, 4.0, 20.0, Unit::Milliampere),
+    (3052, 2, 4.0, 20.0, Unit::Milliampere),
+    (3054, 4, 4.0, 20.0, Unit::Milliampere),
+    (3058, 8, 4.0, 20.0, Unit::Milliampere),
+    (3061, 1, 0.0, 10.0, Unit::Volt),
+    (3062, 2, 0.0, 10.0, Unit::Volt),
+    (3064, 4, 0.0, 10.0, Unit::Volt),
+    (3068, 8, 0.0, 10.0, Unit::Volt),
+    (3101, 1, 0.0, 10.0, Unit::Volt),
+    (3102, 2, 0.0, 10.0, Unit::Volt),
+    (3104, 4, 0.0, 10.0, Unit::Volt),
+    (3111, 1, 0.0, 20.0, Unit::Milliampere),
+    (3112, 2, 0.0, 20.0, Unit::Milliampere),
+    (3114, 4, 0.0, 20.0, Unit::Milliampere),
+    (3121, 1, 4.0, 20.0, Unit::Milliampere),
+    (3122, 2, 4.0, 20.0, Unit::Milliampere),
+    (3124, 4, 4.0, 20.0, Unit::Milliampere),
+    (3141, 1, 0.0, 20.0, Unit::Milliampere),
+    (3142, 2, 0.0, 20.0, Unit::Milliampere),
+    (3144, 4, 0.0, 20.0, Unit::Milliampere),
+    (3151, 1, 4.0, 20.0, Unit::Milliampere),
+    (3152, 2, 4.0, 20.0, Unit::Milliampere),
+    (3154, 4, 4.0, 20.0, Unit::Milliampere),
+    (3161, 1, 0.0, 10.0, Unit::Volt),
+    (3162, 2, 0.0, 10.0, Unit::Volt),
+    (3164, 4, 0.0, 10.0, Unit::Volt),
+];
+
+/// Full scale of the standard presentation of the values.
+const FULL_SCALE: f64 = 32767.0;
+
+/// Bytes of the standard PDO of a channel: status word and value.
+const CHANNEL_BYTES: usize = 4;
+
+/// Value and status of an analog input channel.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AnalogValue {
+    /// The value in the [`Unit`] of the terminal, or in engineering units
+    /// for a channel with a [`ChannelScaling`].
+    pub value: f64,
+    pub raw: i16,
+    pub underrange: bool,
+    pub overrange: bool,
+    /// Underrange, overrange or broken wire.
+    pub error: bool,
+}
+
+impl AnalogValue {
+    pub const fn is_valid(&self) -> bool {
+        !(self.underrange || self.overrange || self.error)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Channel {
+    underrange: Field<bool>,
+    overrange: Field<bool>,
+    error: Field<bool>,
+    value: ScaledField<i16>,
+    engineering: Option<ChannelScaling>,
+}
+
+/// Beckhoff EL30xx and EL31xx analog input terminals, like the EL3004.
+///
+/// Channel `i` is mapped from the object 0x6000 + 0x10 * `i`: the status
+/// bits in subindices 1, 2 and 7 and the value in subindex 0x11. The values
+/// are converted to volts or milliamperes with the nominal range of the
+/// terminal; call [`read_scaling`](Self::read_scaling) to take a user scale
+/// of the terminal into account.
+#[derive(Debug, Clone)]
+pub struct AnalogInputs {
+    unit: Unit,
+    channels: Vec<Channel>,
+    values: Vec<AnalogValue>,
+}
+
+impl AnalogInputs {
+    /// Number of channels of a supported terminal, `None` for other slaves.
+    pub fn detect(id: SlaveId) -> Option<usize> {
+        terminal(id).map(|t| t.1)
+    }
+
+    /// Register the channels of the terminal in `domain`.
+    #[cfg(target_os = "linux")]
+    pub fn configure(config: &mut SlaveConfig, id: SlaveId, domain: DomainIdx) -> Result<Self> {
+        let terminal = terminal(id).ok_or_else(|| unsupported(id))?;
+        let mut channels = vec![];
+        for i in 0..terminal.1 as u16 {
+            let idx = 0x6000 + 0x10 * i;
+            let value = config.register_field(PdoEntryIdx::new(idx, 0x11), domain)?;
+            channels.push(Channel {
+                underrange: config.register_field(PdoEntryIdx::new(idx, 0x01), domain)?,
+                overrange: config.register_field(PdoEntryIdx::new(idx, 0x02), domain)?,
+                error: config.register_field(PdoEntryIdx::new(idx, 0x07), domain)?,
+                value: nominal(value, terminal),
+                engineering: None,
+            });
+        }
+        Ok(Self::new(terminal.4, channels))
+    }
+
+    /// Locate the channels in the inputs of a slave with the default
+    /// mapping, the standard PDO of each channel one after the other.
+    pub fn from_image(id: SlaveId, image: &SlaveImage) -> Result<Self> {
+        let terminal = terminal(id).ok_or_else(|| unsupported(id))?;
+        let inputs = image.inputs.clone().ok_or_else(image_too_small)?;
+        if inputs.len() < terminal.1 * CHANNEL_BYTES {
+            return Err(image_too_small());
+        }
+        let channels = (0..terminal.1)
+            .map(|i| {
+                let byte = inputs.start + i * CHANNEL_BYTES;
+                let bit = |bit| Field::new(image.domain, Offset { byte, bit });
+                let value = Field::new(
+                    image.domain,
+                    Offset {
+                        byte: byte + 2,
+                        bit: 0,
+                    },
+                );
+                Channel {
+                    underrange: bit(0),
+                    overrange: bit(1),
+                    error: bit(6),
+                    value: nominal(value, terminal),
+                    engineering: None,
+                }
+            })
+            .collect();
+        Ok(Self::new(terminal.4, channels))
+    }
+
+    fn new(unit: Unit, channels: Vec<Channel>) -> Self {
+        Self {
+            unit,
+            values: vec![AnalogValue::default(); channels.len()],
+            channels,
+        }
+    }
+
+    /// Adapt the conversion of the values to the user scale of the terminal,
+    /// from the objects 0x80n0 of each channel.
+    ///
+    /// With the user scale enabled in subindex 1, the terminal outputs
+    /// `raw * gain + offset`, with the offset in subindex 0x11 and the gain
+    /// in 1/65536 in subindex 0x12.
+    pub fn read_scaling<B: Backend + ?Sized>(
+        &mut self,
+        backend: &mut B,
+        slave: SlavePos,
+    ) -> Result<()> {
+        for (i, channel) in self.channels.iter_mut().enumerate() {
+            let idx = 0x8000 + 0x10 * i as u16;
+            let mut buf = [0; 4];
+            backend.sdo_upload(slave, SdoIdx::new(idx, 0x01), false, &mut buf)?;
+            if buf[0] & 1 == 0 {
+                continue;
+            }
+            backend.sdo_upload(slave, SdoIdx::new(idx, 0x11), false, &mut buf)?;
+            let offset = f64::from(i16::from_le_bytes([buf[0], buf[1]]));
+            backend.sdo_upload(slave, SdoIdx::new(idx, 0x12), false, &mut buf)?;
+            let gain = f64::from(i32::from_le_bytes(buf)) / 65536.0;
+            if gain == 0.0 {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("user scale of channel {} has a gain of 0", i),
+                )));
+            }
+            // undo the user scale, then apply the nominal one
+            let value = &mut channel.value;
+            let nominal_offset = value.offset;
+            value.scale /= gain;
+            value.offset = nominal_offset - offset * value.scale;
+        }
+        Ok(())
+    }
+
+    /// Convert the values of channel `i`, counted from 0, to engineering
+    /// units.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no channel `i`.
+    pub fn with_scaling(mut self, i: usize, scaling: ChannelScaling) -> Self {
+        self.channels[i].engineering = Some(scaling);
+        self
+    }
+
+    fn with_scalings(mut self, scalings: &[Option<ChannelScaling>]) -> Result<Self> {
+        let scalings = channel_scalings(self.channels.len(), scalings)?;
+        for (channel, scaling) in self.channels.iter_mut().zip(scalings) {
+            channel.engineering = scaling;
+        }
+        Ok(self)
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// The unit of the terminal, of the channels without a scaling to
+    /// engineering units.
+    pub const fn unit(&self) -> Unit {
+        self.unit
+    }
+
+    /// Read the inputs from the domain data.
+    pub fn process(&mut self, data: &mut [u8]) {
+        for (channel, value) in self.channels.iter().zip(&mut self.values) {
+            let nominal = channel.value.get(data);
+            *value = AnalogValue {
+                value: match &channel.engineering {
+                    Some(scaling) => scaling.to_engineering(nominal),
+                    None => nominal,
+                },
+                raw: channel.value.field.get(data),
+                underrange: channel.underrange.get(data),
+                overrange: channel.overrange.get(data),
+                error: channel.error.get(data),
+            };
+        }
+    }
+
+    /// Value of channel `i`, counted from 0, as of the last
+    /// [`process`](Self::process).
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no channel `i`.
+    pub fn channel(&self, i: usize) -> AnalogValue {
+        self.values[i]
+    }
+
+    /// The conversion of the raw value of channel `i`.
+    pub fn scaling(&self, i: usize) -> Option<&ScaledField<i16>> {
+        self.channels.get(i).map(|c| &c.value)
+    }
+}
+
+impl SlaveDriver for AnalogInputs {
+    /// Scalings of the first channels to engineering units.
+    type Settings = Vec<Option<ChannelScaling>>;
+
+    fn matches(id: SlaveId) -> bool {
+        Self::detect(id).is_some()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        settings: &Vec<Option<ChannelScaling>>,
+    ) -> Result<Self> {
+        Self::configure(config, id, domain)?.with_scalings(settings)
+    }
+
+    fn bind(
+        id: SlaveId,
+        image: &SlaveImage,
+        settings: &Vec<Option<ChannelScaling>>,
+    ) -> Result<Self> {
+        Self::from_image(id, image)?.with_scalings(settings)
+    }
+
+    fn process(&mut self, data: &mut [u8]) {
+        self.process(data)
+    }
+
+    fn channels(&self) -> Channels<'_> {
+        let channels = self.channels.iter().zip(&self.values).enumerate();
+        Box::new(channels.map(move |(i, (channel, value))| {
+            let nominal = &channel.value;
+            let limits = (nominal.offset, nominal.offset + nominal.scale * FULL_SCALE);
+            let channel = match &channel.engineering {
+                Some(scaling) => {
+                    let (min, max) = scaling.limits(limits);
+                    IoChannel::input(format!("Channel {}", i + 1), value.value)
+                        .with_unit(&scaling.unit)
+                        .with_limits(min, max)
+                }
+                None => IoChannel::input(format!("Channel {}", i + 1), value.value)
+                    .with_unit(self.unit)
+                    .with_limits(limits.0, limits.1),
+            };
+            channel.with_valid(value.is_valid())
+        }))
+    }
+}
+
+fn terminal(id: SlaveId) -> Option<(u32, usize, f64, f64, Unit)> {
+    let number = beckhoff_terminal(id)?;
+    TERMINALS.iter().find(|t| t.0 == number).copied()
+}
+
+fn nominal(field: Field<i16>, terminal: (u32, usize, f64, f64, Unit)) -> ScaledField<i16> {
+    let (_, _, zero, full, _) = terminal;
+    ScaledField::new(field, (full - zero) / FULL_SCALE, zero)
+}
+
+#[test]
+fn test_analog_inputs() {
+    use crate::backend::{SimMaster, SimSlave};
+
+    let el3024 = SlaveId::new(2, 0x0bd0_3052);
+    assert_eq!(AnalogInputs::detect(el3024), Some(4));
+
+    let entry = |idx, sub, bit_len| PdoEntryInfo {
+        entry_idx: PdoEntryIdx::new(idx, sub),
+        bit_len,
+        name: String::new(),
+        pos: PdoEntryPos::from(0),
+    };
+    let mut slave = SimSlave::new("EL3024", el3024);
+    for i in 0..4 {
+        let idx = 0x6000 + 0x10 * i;
+        let mut pdo = PdoCfg::new(PdoIdx::from(0x1A00 + 2 * i));
+        pdo.entries = smallvec::smallvec![
+            entry(idx, 0x01, 1),
+            entry(idx, 0x02, 1),
+            entry(idx, 0x03, 2),
+            entry(idx, 0x05, 2),
+            entry(idx, 0x07, 1),
+            entry(0, 0, 7),
+            entry(idx, 0x0F, 1),
+            entry(idx, 0x10, 1),
+            entry(idx, 0x11, 16),
+        ];
+        slave = slave
+            .tx_pdo(pdo)
+            .object(SdoIdx::new(0x8000 + 0x10 * i, 0x01), &[u8::from(i == 1)]);
+    }
+    // channel 1 scaled by 2 with an offset of 100
+    let slave = slave
+        .object(SdoIdx::new(0x8010, 0x11), &100_i16.to_le_bytes())
+        .object(SdoIdx::new(0x8010, 0x12), &0x2_0000_i32.to_le_bytes());
+    let mut master = SimMaster::new(vec![slave]);
+    let pos = SlavePos::from(0);
+    master.activate().unwrap();
+    let image = master.slave_image(pos).unwrap();
+    let pressure = ChannelScaling::linear("bar", (4.0, 20.0), (0.0, 10.0));
+    let mut inputs = AnalogInputs::from_image(el3024, &image)
+        .unwrap()
+        .with_scaling(3, pressure);
+    inputs.read_scaling(&mut master, pos).unwrap();
+    assert_eq!(inputs.unit(), Unit::Milliampere);
+
+    let raw = master.slave_mut(pos).unwrap().inputs_mut();
+    raw[2..4].copy_from_slice(&0x7FFF_i16.to_le_bytes());
+    raw[6..8].copy_from_slice(&(0x2000_i16 * 2 + 100).to_le_bytes());
+    raw[8] = 0b0100_0001;
+    raw[14..16].copy_from_slice(&0x4000_i16.to_le_bytes());
+    master.send().unwrap();
+    master.receive().unwrap();
+    inputs.process(master.domain_data(DomainIdx::from(0)).unwrap());
+
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-3;
+    assert!(close(inputs.channel(0).value, 20.0) && inputs.channel(0).is_valid());
+    assert!(close(inputs.channel(1).value, 8.0));
+    let broken = inputs.channel(2);
+    assert!(broken.underrange && broken.error && !broken.overrange);
+    assert!(close(broken.value, 4.0));
+
+    let channels: Vec<_> = SlaveDriver::channels(&inputs).collect();
+    let (min, max) = channels[0].limits.unwrap();
+    assert!(close(min, 4.0) && close(max, 20.0));
+    assert_eq!(channels[2].unit.as_deref(), Some("mA"));
+    assert!(channels[0].valid && !channels[2].valid);
+    assert!(close(inputs.channel(3).value, 5.0));
+    assert_eq!(channels[3].unit.as_deref(), Some("bar"));
+    let (min, max) = channels[3].limits.unwrap();
+    assert!(close(min, 0.0) && close(max, 10.0));
+}
@@ -0,0 +1,344 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{
+    beckhoff_terminal, channel_scalings, image_too_small, unsupported, ChannelScaling, Channels,
+    IoChannel, SlaveDriver, Unit,
+};
+#[cfg(target_os = "linux")]
+use crate::SlaveConfig;
+use crate::{
+    backend::SlaveImage,
+    field::{Field, PdoNumber, ScaledField},
+    types::*,
+};
+
+/// Supported terminals: number, channels, and the range of the outputs.
+const TERMINALS: &[(u32, usize, f64, f64, Unit)] = &[
+    (4001, 1, 0.0, 10.0, Unit::Volt),
+    (4002, 2, 0.0, 10.0, Unit::Volt),
+    (4004, 4, 0.0, 10.0, Unit::Volt),
+    (4008, 8, 0.0, 10.0, Unit::Volt),
+    (4011, 1, 0.0, 20.0, Unit::Milliampere),
+    (4012, 2, 0.0, 20.0, Unit::Milliampere),
+    (4014, 4, 0.0, 20.0, Unit::Milliampere),
+    (4018, 8, 0.0, 20.0, Unit::Milliampere),
+    (4021, 1, 4.0, 20.0, Unit::Milliampere),
+    (4022, 2, 4.0, 20.0, Unit::Milliampere),
+    (4024, 4, 4.0, 20.0, Unit::Milliampere),
+    (4028, 8, 4.0, 20.0, Unit::Milliampere),
+    (4031, 1, -10.0, 10.0, Unit::Volt),
+    (4032, 2, -10.0, 10.0, Unit::Volt),
+    (4034, 4, -10.0, 10.0, Unit::Volt),
+    (4038, 8, -10.0, 10.0, Unit::Volt),
+    (4102, 2, 0.0, 10.0, Unit::Volt),
+    (4104, 4, 0.0, 10.0, Unit::Volt),
+    (4112, 2, 0.0, 20.0, Unit::Milliampere),
+    (4114, 4, 0.0, 20.0, Unit::Milliampere),
+    (4122, 2, 4.0, 20.0, Unit::Milliampere),
+    (4132, 2, -10.0, 10.0, Unit::Volt),
+    (4134, 4, -10.0, 10.0, Unit::Volt),
+];
+
+/// Full scale of the standard presentation of the values.
+const FULL_SCALE: f64 = 32767.0;
+
+/// What the outputs do when the sync manager watchdog of the terminal
+/// expires, e.g. because the master stopped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchdogBehavior {
+    /// Switch to a value, in the unit of the terminal.
+    Value(f64),
+    /// Ramp to a value with the given slope, in raw counts per ms.
+    Ramp { value: f64, counts_per_ms: u16 },
+    /// Keep the last value.
+    Hold,
+}
+
+/// Beckhoff EL4xxx analog output terminals, like the EL4004 or EL4134.
+///
+/// Channel `i` is mapped from the object 0x7000 + 0x10 * `i`, subindex
+/// 0x11. Values are set in volts or milliamperes and saturated to the range
+/// of the terminal.
+#[derive(Debug, Clone)]
+pub struct AnalogOutputs {
+    unit: Unit,
+    range: (f64, f64),
+    fields: Vec<ScaledField<i16>>,
+    scalings: Vec<Option<ChannelScaling>>,
+    /// Commanded values in the unit of the terminal.
+    values: Vec<f64>,
+}
+
+impl AnalogOutputs {
+    /// Number of channels of a supported terminal, `None` for other slaves.
+    pub fn detect(id: SlaveId) -> Option<usize> {
+        terminal(id).map(|t| t.1)
+    }
+
+    /// Register the channels of the terminal in `domain`.
+    #[cfg(target_os = "linux")]
+    pub fn configure(config: &mut SlaveConfig, id: SlaveId, domain: DomainIdx) -> Result<Self> {
+        let terminal = terminal(id).ok_or_else(|| unsupported(id))?;
+        let fields = (0..terminal.1 as u16)
+            .map(|i| config.register_field(PdoEntryIdx::new(0x7000 + 0x10 * i, 0x11), domain))
+            .collect::<Result<_>>()?;
+        Ok(Self::new(terminal, fields))
+    }
+
+    /// Locate the channels in the outputs of a slave with the default
+    /// mapping, one 16 bit value per channel.
+    pub fn from_image(id: SlaveId, image: &SlaveImage) -> Result<Self> {
+        let terminal = terminal(id).ok_or_else(|| unsupported(id))?;
+        let outputs = image.outputs.clone().ok_or_else(image_too_small)?;
+        if outputs.len() < terminal.1 * 2 {
+            return Err(image_too_small());
+        }
+        let fields = (0..terminal.1)
+            .map(|i| {
+                let byte = outputs.start + 2 * i;
+                Field::new(image.domain, Offset { byte, bit: 0 })
+            })
+            .collect();
+        Ok(Self::new(terminal, fields))
+    }
+
+    fn new(terminal: (u32, usize, f64, f64, Unit), fields: Vec<Field<i16>>) -> Self {
+        let (_, _, min, max, unit) = terminal;
+        // bipolar outputs are symmetric around 0
+        let zero = min.max(0.0);
+        let fields: Vec<_> = fields
+            .into_iter()
+            .map(|field| ScaledField::new(field, (max - zero) / FULL_SCALE, zero))
+            .collect();
+        Self {
+            unit,
+            range: (min, max),
+            values: vec![zero; fields.len()],
+            scalings: vec![None; fields.len()],
+            fields,
+        }
+    }
+
+    /// Set the behavior of all channels on watchdog expiry, with SDOs
+    /// written when the slave is configured.
+    #[cfg(target_os = "linux")]
+    pub fn configure_watchdog(
+        &self,
+        config: &mut SlaveConfig,
+        behavior: WatchdogBehavior,
+    ) -> Result<()> {
+        for i in 0..self.fields.len() {
+            let idx = 0x8000 + 0x10 * i as u16;
+            let (mode, value, ramp) = match behavior {
+                WatchdogBehavior::Value(value) => (0_u8, Some(value), None),
+                WatchdogBehavior::Ramp {
+                    value,
+                    counts_per_ms,
+                } => (1, Some(value), Some(counts_per_ms)),
+                WatchdogBehavior::Hold => (2, None, None),
+            };
+            config.add_sdo(SdoIdx::new(idx, 0x05), &mode)?;
+            if let Some(value) = value {
+                config.add_sdo(SdoIdx::new(idx, 0x13), &self.raw(i, value))?;
+            }
+            if let Some(ramp) = ramp {
+                config.add_sdo(SdoIdx::new(idx, 0x14), &ramp)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Command channel `i`, counted from 0, in engineering units.
+    /// Values are still saturated to the range of the terminal.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no channel `i`.
+    pub fn with_scaling(mut self, i: usize, scaling: ChannelScaling) -> Self {
+        self.scalings[i] = Some(scaling);
+        self
+    }
+
+    fn with_scalings(mut self, scalings: &[Option<ChannelScaling>]) -> Result<Self> {
+        self.scalings = channel_scalings(self.fields.len(), scalings)?;
+        Ok(self)
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// The unit of the terminal, of the channels without a scaling to
+    /// engineering units.
+    pub const fn unit(&self) -> Unit {
+        self.unit
+    }
+
+    /// Minimum and maximum value of the outputs, in the unit of the
+    /// terminal.
+    pub const fn range(&self) -> (f64, f64) {
+        self.range
+    }
+
+    /// Minimum and maximum value of channel `i`, in its units.
+    pub fn limits(&self, i: usize) -> (f64, f64) {
+        match &self.scalings[i] {
+            Some(scaling) => scaling.limits(self.range),
+            None => self.range,
+        }
+    }
+
+    /// Write the commanded values into the domain data.
+    pub fn process(&mut self, data: &mut [u8]) {
+        for (field, value) in self.fields.iter().zip(&self.values) {
+            field.set(data, *value);
+        }
+    }
+
+    /// Command channel `i`, counted from 0, and return the value after
+    /// saturation to the range of the terminal. NaN commands zero.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no channel `i`.
+    pub fn set(&mut self, i: usize, value: f64) -> f64 {
+        let value = if value.is_nan() { 0.0 } else { value };
+        let (min, max) = self.range;
+        self.values[i] = self.terminal_value(i, value).clamp(min, max);
+        self.channel(i)
+    }
+
+    /// Commanded value of channel `i`.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no channel `i`.
+    pub fn channel(&self, i: usize) -> f64 {
+        match &self.scalings[i] {
+            Some(scaling) => scaling.to_engineering(self.values[i]),
+            None => self.values[i],
+        }
+    }
+
+    /// Raw counts written for a value of channel `i`, after saturation.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no channel `i`.
+    pub fn to_raw(&self, i: usize, value: f64) -> i16 {
+        self.raw(i, self.terminal_value(i, value))
+    }
+
+    fn terminal_value(&self, i: usize, value: f64) -> f64 {
+        match &self.scalings[i] {
+            Some(scaling) => scaling.from_engineering(value),
+            None => value,
+        }
+    }
+
+    /// Raw counts of a value in the unit of the terminal.
+    fn raw(&self, i: usize, value: f64) -> i16 {
+        let (min, max) = self.range;
+        let field = &self.fields[i];
+        i16::from_f64((value.clamp(min, max) - field.offset) / field.scale)
+    }
+}
+
+impl SlaveDriver for AnalogOutputs {
+    /// Scalings of the first channels to engineering units.
+    type Settings = Vec<Option<ChannelScaling>>;
+
+    fn matches(id: SlaveId) -> bool {
+        Self::detect(id).is_some()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        settings: &Vec<Option<ChannelScaling>>,
+    ) -> Result<Self> {
+        Self::configure(config, id, domain)?.with_scalings(settings)
+    }
+
+    fn bind(
+        id: SlaveId,
+        image: &SlaveImage,
+        settings: &Vec<Option<ChannelScaling>>,
+    ) -> Result<Self> {
+        Self::from_image(id, image)?.with_scalings(settings)
+    }
+
+    fn process(&mut self, data: &mut [u8]) {
+        self.process(data)
+    }
+
+    fn channels(&self) -> Channels<'_> {
+        Box::new((0..self.channel_count()).map(move |i| {
+            let (min, max) = self.limits(i);
+            let channel = IoChannel::output(format!("Channel {}", i + 1), self.channel(i))
+                .with_limits(min, max);
+            match &self.scalings[i] {
+                Some(scaling) => channel.with_unit(&scaling.unit),
+                None => channel.with_unit(self.unit),
+            }
+        }))
+    }
+}
+
+fn terminal(id: SlaveId) -> Option<(u32, usize, f64, f64, Unit)> {
+    let number = beckhoff_terminal(id)?;
+    TERMINALS.iter().find(|t| t.0 == number).copied()
+}
+
+#[test]
+fn test_analog_outputs() {
+    use crate::backend::{Backend, SimMaster, SimSlave};
+
+    let el4132 = SlaveId::new(2, 0x1024_3052);
+    assert_eq!(AnalogOutputs::detect(el4132), Some(2));
+
+    let mut slave = SimSlave::new("EL4132", el4132);
+    for i in 0..2 {
+        let mut pdo = PdoCfg::new(PdoIdx::from(0x1600 + i));
+        pdo.entries = smallvec::smallvec![PdoEntryInfo {
+            entry_idx: PdoEntryIdx::new(0x7000 + 0x10 * i, 0x11),
+            bit_len: 16,
+            name: String::new(),
+            pos: PdoEntryPos::from(0),
+        }];
+        slave = slave.rx_pdo(pdo);
+    }
+    let mut master = SimMaster::new(vec![slave]);
+    let pos = SlavePos::from(0);
+    master.activate().unwrap();
+    let image = master.slave_image(pos).unwrap();
+    let mut outputs = AnalogOutputs::from_image(el4132, &image).unwrap();
+    assert_eq!(
+        (outputs.unit(), outputs.range()),
+        (Unit::Volt, (-10.0, 10.0))
+    );
+
+    assert_eq!(outputs.set(0, -5.0), -5.0);
+    assert_eq!(outputs.set(1, 12.0), 10.0);
+    assert_eq!(outputs.channel(1), 10.0);
+    assert_eq!(outputs.to_raw(0, -20.0), -32767);
+    outputs.process(master.domain_data(DomainIdx::from(0)).unwrap());
+    master.send().unwrap();
+    master.receive().unwrap();
+    let raw = master.slave(pos).unwrap().outputs();
+    assert_eq!(i16::from_le_bytes([raw[0], raw[1]]), -16384);
+    assert_eq!(i16::from_le_bytes([raw[2], raw[3]]), 32767);
+    assert_eq!(outputs.set(1, f64::NAN), 0.0);
+
+    // a valve positioner taking 0..100 % on 0..10 V
+    let mut outputs =
+        outputs.with_scaling(0, ChannelScaling::linear("%", (0.0, 10.0), (0.0, 100.0)));
+    assert_eq!(outputs.set(0, 50.0), 50.0);
+    assert_eq!(outputs.to_raw(0, 100.0), 32767);
+    let channel = SlaveDriver::channels(&outputs).next().unwrap();
+    assert_eq!(channel.unit.as_deref(), Some("%"));
+    assert_eq!(channel.limits, Some((-100.0, 100.0)));
+}
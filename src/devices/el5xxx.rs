@@ -0,0 +1,332 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{beckhoff_terminal, image_too_small, unsupported, Channels, IoChannel, SlaveDriver};
+#[cfg(target_os = "linux")]
+use crate::SlaveConfig;
+use crate::{
+    backend::SlaveImage,
+    field::{Field, PdoData},
+    types::*,
+};
+use std::time::Duration;
+
+/// Supported terminals: number, channels, and whether they latch the
+/// counter on the index pulse.
+const TERMINALS: &[(u32, usize, bool)] = &[(5101, 1, true), (5151, 1, true), (5152, 2, false)];
+
+#[derive(Debug, Clone)]
+struct Channel {
+    latch_valid: Option<Field<bool>>,
+    set_done: Field<bool>,
+    counter: Field<u32>,
+    latch: Option<Field<u32>>,
+    enable_latch: Option<Field<bool>>,
+    set_counter: Field<bool>,
+    set_value: Field<u32>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    started: bool,
+    raw: u32,
+    position: i64,
+    latch: Option<i64>,
+    latch_enabled: bool,
+    set_request: Option<u32>,
+    frequency: Option<f64>,
+    gate_start: i64,
+    gate_cycles: u32,
+}
+
+/// Beckhoff EL5101, EL5151 and EL5152 incremental encoder terminals.
+///
+/// The 32 bit counters of the terminals are extended to 64 bit positions,
+/// so that they do not overflow as long as [`process`](Self::process) is
+/// called at least once per 2^31 counts. Channel `i` is mapped from the
+/// objects 0x6000 + 0x10 * `i` and 0x7000 + 0x10 * `i`.
+#[derive(Debug, Clone)]
+pub struct Encoder {
+    channels: Vec<Channel>,
+    states: Vec<State>,
+    /// Cycle time and number of cycles of the frequency measurement.
+    gate: Option<(Duration, u32)>,
+}
+
+impl Encoder {
+    /// Number of channels of a supported terminal, `None` for other slaves.
+    pub fn detect(id: SlaveId) -> Option<usize> {
+        terminal(id).map(|t| t.1)
+    }
+
+    /// Register the channels of the terminal in `domain`.
+    #[cfg(target_os = "linux")]
+    pub fn configure(config: &mut SlaveConfig, id: SlaveId, domain: DomainIdx) -> Result<Self> {
+        let (_, channels, has_latch) = terminal(id).ok_or_else(|| unsupported(id))?;
+        let mut channels_cfg = vec![];
+        for i in 0..channels as u16 {
+            let (inputs, outputs) = (0x6000 + 0x10 * i, 0x7000 + 0x10 * i);
+            let mut latch_valid = None;
+            let mut latch = None;
+            let mut enable_latch = None;
+            if has_latch {
+                latch_valid = Some(config.register_field(PdoEntryIdx::new(inputs, 0x01), domain)?);
+                latch = Some(config.register_field(PdoEntryIdx::new(inputs, 0x12), domain)?);
+                enable_latch =
+                    Some(config.register_field(PdoEntryIdx::new(outputs, 0x01), domain)?);
+            }
+            channels_cfg.push(Channel {
+                latch_valid,
+                set_done: config.register_field(PdoEntryIdx::new(inputs, 0x03), domain)?,
+                counter: config.register_field(PdoEntryIdx::new(inputs, 0x11), domain)?,
+                latch,
+                enable_latch,
+                set_counter: config.register_field(PdoEntryIdx::new(outputs, 0x03), domain)?,
+                set_value: config.register_field(PdoEntryIdx::new(outputs, 0x11), domain)?,
+            });
+        }
+        Ok(Self::new(channels_cfg))
+    }
+
+    /// Locate the channels in the process data of a slave with the default
+    /// mapping: status word, counter and, if supported, latch value in the
+    /// inputs, and control word and counter value to set in the outputs.
+    pub fn from_image(id: SlaveId, image: &SlaveImage) -> Result<Self> {
+        let (_, channels, has_latch) = terminal(id).ok_or_else(|| unsupported(id))?;
+        let inputs = image.inputs.clone().ok_or_else(image_too_small)?;
+        let outputs = image.outputs.clone().ok_or_else(image_too_small)?;
+        let input_bytes = if has_latch { 10 } else { 6 };
+        if inputs.len() < channels * input_bytes || outputs.len() < channels * 6 {
+            return Err(image_too_small());
+        }
+        let domain = image.domain;
+        let channels = (0..channels)
+            .map(|i| {
+                let input = inputs.start + i * input_bytes;
+                let output = outputs.start + i * 6;
+                Channel {
+                    latch_valid: has_latch.then(|| field(domain, input, 0)),
+                    set_done: field(domain, input, 2),
+                    counter: field(domain, input + 2, 0),
+                    latch: has_latch.then(|| field(domain, input + 6, 0)),
+                    enable_latch: has_latch.then(|| field(domain, output, 0)),
+                    set_counter: field(domain, output, 2),
+                    set_value: field(domain, output + 2, 0),
+                }
+            })
+            .collect();
+        Ok(Self::new(channels))
+    }
+
+    fn new(channels: Vec<Channel>) -> Self {
+        Self {
+            states: vec![State::default(); channels.len()],
+            channels,
+            gate: None,
+        }
+    }
+
+    /// Measure the frequency of the counts over `gate`, with
+    /// [`process`](Self::process) called every `cycle`.
+    ///
+    /// The frequency is computed from the positions, so it is only as exact
+    /// as the cycle time of the application.
+    pub fn with_frequency(mut self, cycle: Duration, gate: Duration) -> Self {
+        let cycles = (gate.as_nanos() / cycle.as_nanos().max(1)).max(1);
+        self.gate = Some((cycle, cycles.min(u128::from(u32::MAX)) as u32));
+        self
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Read the counters from and write the commands into the domain data.
+    pub fn process(&mut self, data: &mut [u8]) {
+        for (channel, state) in self.channels.iter().zip(&mut self.states) {
+            let raw = channel.counter.get(data);
+            if !state.started {
+                state.started = true;
+                state.position = i64::from(raw);
+                state.gate_start = state.position;
+            } else if state.set_request.is_some() && channel.set_done.get(data) {
+                // the counter jumped to the new value
+                state.set_request = None;
+                state.position = i64::from(raw);
+                state.gate_start = state.position;
+                state.gate_cycles = 0;
+            } else {
+                state.position += i64::from(raw.wrapping_sub(state.raw) as i32);
+            }
+            state.raw = raw;
+
+            state.latch = match (channel.latch_valid, channel.latch) {
+                (Some(valid), Some(latch)) if state.latch_enabled && valid.get(data) => {
+                    let offset = latch.get(data).wrapping_sub(raw) as i32;
+                    Some(state.position + i64::from(offset))
+                }
+                _ => None,
+            };
+
+            if let Some((cycle, cycles)) = self.gate {
+                state.gate_cycles += 1;
+                if state.gate_cycles >= cycles {
+                    let time = cycle.as_secs_f64() * f64::from(state.gate_cycles);
+                    state.frequency = Some((state.position - state.gate_start) as f64 / time);
+                    state.gate_start = state.position;
+                    state.gate_cycles = 0;
+                }
+            }
+
+            if let Some(enable) = channel.enable_latch {
+                enable.set(data, state.latch_enabled);
+            }
+            channel.set_counter.set(data, state.set_request.is_some());
+            channel
+                .set_value
+                .set(data, state.set_request.unwrap_or_default());
+        }
+    }
+
+    /// Extended position of channel `i`, counted from 0, as of the last
+    /// [`process`](Self::process).
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no channel `i`.
+    pub fn position(&self, i: usize) -> i64 {
+        self.states[i].position
+    }
+
+    /// The raw 32 bit counter value of channel `i`.
+    pub fn counter(&self, i: usize) -> u32 {
+        self.states[i].raw
+    }
+
+    /// Counts per second of channel `i`, once a gate time has passed.
+    ///
+    /// Only measured if enabled with [`with_frequency`](Self::with_frequency).
+    pub fn frequency(&self, i: usize) -> Option<f64> {
+        self.states[i].frequency
+    }
+
+    /// Set the counter of channel `i` to `value`.
+    ///
+    /// The terminal takes the value within a few cycles, and the extended
+    /// position restarts from it.
+    pub fn reset(&mut self, i: usize, value: u32) {
+        self.states[i].set_request = Some(value);
+    }
+
+    /// Returns `true` while a [`reset`](Self::reset) of channel `i` is not
+    /// yet done.
+    pub fn is_resetting(&self, i: usize) -> bool {
+        self.states[i].set_request.is_some()
+    }
+
+    /// Latch the position of channel `i` on the index pulse of the encoder.
+    ///
+    /// Does nothing on terminals without latch.
+    pub fn enable_latch(&mut self, i: usize, enable: bool) {
+        let state = &mut self.states[i];
+        state.latch_enabled = enable && self.channels[i].enable_latch.is_some();
+        if !enable {
+            state.latch = None;
+        }
+    }
+
+    /// Extended position of channel `i` at the last index pulse, while the
+    /// latch is enabled.
+    pub fn latch(&self, i: usize) -> Option<i64> {
+        self.states[i].latch
+    }
+}
+
+impl SlaveDriver for Encoder {
+    type Settings = ();
+
+    fn matches(id: SlaveId) -> bool {
+        Self::detect(id).is_some()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        _settings: &(),
+    ) -> Result<Self> {
+        Self::configure(config, id, domain)
+    }
+
+    fn bind(id: SlaveId, image: &SlaveImage, _settings: &()) -> Result<Self> {
+        Self::from_image(id, image)
+    }
+
+    fn process(&mut self, data: &mut [u8]) {
+        self.process(data)
+    }
+
+    fn channels(&self) -> Channels<'_> {
+        Box::new(
+            (0..self.channel_count())
+                .map(move |i| IoChannel::input(format!("Position {}", i + 1), self.position(i))),
+        )
+    }
+}
+
+fn field<T: PdoData>(domain: DomainIdx, byte: usize, bit: u32) -> Field<T> {
+    Field::new(domain, Offset { byte, bit })
+}
+
+fn terminal(id: SlaveId) -> Option<(u32, usize, bool)> {
+    let number = beckhoff_terminal(id)?;
+    TERMINALS.iter().find(|t| t.0 == number).copied()
+}
+
+#[test]
+fn test_encoder() {
+    let domain = DomainIdx::from(0);
+    let el5101 = SlaveId::new(2, 0x13ed_3052);
+    assert_eq!(Encoder::detect(el5101), Some(1));
+    let image = SlaveImage {
+        domain,
+        outputs: Some(0..6),
+        inputs: Some(6..16),
+    };
+    let mut encoder = Encoder::from_image(el5101, &image)
+        .unwrap()
+        .with_frequency(Duration::from_millis(1), Duration::from_millis(2));
+    let mut data = [0_u8; 16];
+    let mut cycle = |encoder: &mut Encoder, status: u8, counter: u32, latch: u32| {
+        data[6] = status;
+        data[8..12].copy_from_slice(&counter.to_le_bytes());
+        data[12..16].copy_from_slice(&latch.to_le_bytes());
+        encoder.process(&mut data);
+        data
+    };
+
+    cycle(&mut encoder, 0, u32::MAX - 10, 0);
+    assert_eq!(encoder.position(0), i64::from(u32::MAX) - 10);
+    cycle(&mut encoder, 0, 9, 0);
+    assert_eq!(encoder.position(0), i64::from(u32::MAX) + 10);
+    assert_eq!(encoder.frequency(0), Some(10_000.0));
+    cycle(&mut encoder, 0, 5, 0);
+    assert_eq!(encoder.position(0), i64::from(u32::MAX) + 6);
+
+    encoder.enable_latch(0, true);
+    let out = cycle(&mut encoder, 0b001, 7, 3);
+    assert_eq!(out[0], 0b001);
+    assert_eq!(encoder.latch(0), Some(i64::from(u32::MAX) + 4));
+
+    encoder.reset(0, 1000);
+    let out = cycle(&mut encoder, 0, 8, 3);
+    assert_eq!(out[0], 0b101);
+    assert_eq!(u32::from_le_bytes([out[2], out[3], out[4], out[5]]), 1000);
+    cycle(&mut encoder, 0b100, 1000, 3);
+    assert!(!encoder.is_resetting(0));
+    assert_eq!(encoder.position(0), 1000);
+    let out = cycle(&mut encoder, 0, 1003, 3);
+    assert_eq!(out[0], 0b001);
+    assert_eq!(encoder.position(0), 1003);
+}
@@ -0,0 +1,396 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{beckhoff_terminal, image_too_small, unsupported, SlaveDriver};
+#[cfg(target_os = "linux")]
+use crate::SlaveConfig;
+use crate::{
+    backend::{Backend, SlaveImage},
+    field::Field,
+    types::*,
+};
+use std::io;
+
+/// Supported terminals and their number of ports.
+const TERMINALS: &[(u32, usize)] = &[(6224, 4)];
+
+/// Maximum length of the process data of an IO-Link device.
+const MAX_DATA: usize = 32;
+
+/// Operating mode of an IO-Link port, as in the master control object
+/// 0x80n0:28.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortMode {
+    Deactivated = 0,
+    /// IO-Link communication with the device configured in the terminal.
+    IoLink = 1,
+    /// IO-Link communication with any device.
+    IoLinkAutostart = 2,
+    /// Standard digital input on the C/Q line.
+    DigitalInput = 3,
+    /// Standard digital output on the C/Q line.
+    DigitalOutput = 4,
+}
+
+/// Configuration of an IO-Link port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortConfig {
+    pub mode: PortMode,
+    /// Length of the process data from the device in bytes.
+    pub inputs: usize,
+    /// Length of the process data to the device in bytes.
+    pub outputs: usize,
+}
+
+impl PortConfig {
+    pub const fn new(mode: PortMode, inputs: usize, outputs: usize) -> Self {
+        Self {
+            mode,
+            inputs,
+            outputs,
+        }
+    }
+
+    pub const fn deactivated() -> Self {
+        Self::new(PortMode::Deactivated, 0, 0)
+    }
+}
+
+/// The state of an IO-Link port, from the object 0xF100.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PortState(pub u8);
+
+impl PortState {
+    /// 0 for a disabled port, 1 and 2 for digital input and output, 3 for
+    /// IO-Link communication in operation and 4 when stopped.
+    pub const fn state(self) -> u8 {
+        self.0 & 0x0F
+    }
+
+    /// The device exchanges valid process data.
+    pub const fn is_operational(self) -> bool {
+        self.state() == 3 && self.0 & 0xF0 == 0
+    }
+
+    pub const fn watchdog(self) -> bool {
+        self.0 & 0x10 != 0
+    }
+
+    pub const fn internal_error(self) -> bool {
+        self.0 & 0x20 != 0
+    }
+
+    /// The connected device is not the one configured.
+    pub const fn wrong_device(self) -> bool {
+        self.0 & 0xC0 != 0
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Port {
+    config: PortConfig,
+    state: Field<u8>,
+    /// Start of the input and output data in the domain.
+    inputs: usize,
+    outputs: usize,
+    input_data: [u8; MAX_DATA],
+    output_data: [u8; MAX_DATA],
+    last_state: PortState,
+}
+
+/// Beckhoff EL6224 IO-Link master terminal.
+///
+/// The process data of port `n`, counted from 0, is mapped from the objects
+/// 0x6000 + 0x10 * `n` and 0x7000 + 0x10 * `n` and its state from 0xF100,
+/// subindex `n` + 1. Parameters of the devices are accessed with ISDU
+/// requests, tunneled through CoE by [`read_isdu`](Self::read_isdu) and
+/// [`write_isdu`](Self::write_isdu).
+#[derive(Debug, Clone)]
+pub struct IoLinkMaster {
+    ports: Vec<Port>,
+}
+
+impl IoLinkMaster {
+    /// Number of ports of a supported terminal, `None` for other slaves.
+    pub fn detect(id: SlaveId) -> Option<usize> {
+        let number = beckhoff_terminal(id)?;
+        TERMINALS
+            .iter()
+            .find(|(n, _)| *n == number)
+            .map(|(_, ports)| *ports)
+    }
+
+    /// Configure the ports with startup SDOs and register their process data
+    /// in `domain`.
+    ///
+    /// The data of a port is registered from the first entry of its object,
+    /// so the bytes must be mapped one after the other.
+    #[cfg(target_os = "linux")]
+    pub fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        ports: &[PortConfig],
+    ) -> Result<Self> {
+        check_ports(id, ports)?;
+        for (idx, value) in port_sdos(ports) {
+            config.add_sdo(idx, &value)?;
+        }
+        let mut configured = vec![];
+        for (n, port) in ports.iter().enumerate() {
+            let n = n as u16;
+            let state = config.register_field(PdoEntryIdx::new(0xF100, n as u8 + 1), domain)?;
+            let mut inputs = 0;
+            if port.inputs > 0 {
+                inputs = config
+                    .register_pdo_entry(PdoEntryIdx::new(0x6000 + 0x10 * n, 1), domain)?
+                    .byte;
+            }
+            let mut outputs = 0;
+            if port.outputs > 0 {
+                outputs = config
+                    .register_pdo_entry(PdoEntryIdx::new(0x7000 + 0x10 * n, 1), domain)?
+                    .byte;
+            }
+            configured.push(Port::new(*port, state, inputs, outputs));
+        }
+        Ok(Self { ports: configured })
+    }
+
+    /// Locate the ports in the process data of a slave with the default
+    /// assignment for the given port configuration: the inputs of the ports
+    /// one after the other followed by a state byte per port, and the
+    /// outputs of the ports one after the other.
+    pub fn from_image(id: SlaveId, image: &SlaveImage, ports: &[PortConfig]) -> Result<Self> {
+        check_ports(id, ports)?;
+        let input_len: usize = ports.iter().map(|p| p.inputs).sum();
+        let output_len: usize = ports.iter().map(|p| p.outputs).sum();
+        let inputs = image.inputs.clone().ok_or_else(image_too_small)?;
+        let outputs = image.outputs.clone().unwrap_or(0..0);
+        if inputs.len() < input_len + ports.len() || outputs.len() < output_len {
+            return Err(image_too_small());
+        }
+        let (mut input, mut output) = (inputs.start, outputs.start);
+        let mut configured = vec![];
+        for (n, port) in ports.iter().enumerate() {
+            let state = Field::new(
+                image.domain,
+                Offset {
+                    byte: inputs.start + input_len + n,
+                    bit: 0,
+                },
+            );
+            configured.push(Port::new(*port, state, input, output));
+            input += port.inputs;
+            output += port.outputs;
+        }
+        Ok(Self { ports: configured })
+    }
+
+    pub fn port_count(&self) -> usize {
+        self.ports.len()
+    }
+
+    /// Read the inputs and states from and write the outputs into the
+    /// domain data.
+    pub fn process(&mut self, data: &mut [u8]) {
+        for port in &mut self.ports {
+            port.last_state = PortState(port.state.get(data));
+            let (inputs, outputs) = (port.config.inputs, port.config.outputs);
+            port.input_data[..inputs].copy_from_slice(&data[port.inputs..port.inputs + inputs]);
+            data[port.outputs..port.outputs + outputs]
+                .copy_from_slice(&port.output_data[..outputs]);
+        }
+    }
+
+    /// State of port `n` as of the last [`process`](Self::process).
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no port `n`.
+    pub fn state(&self, n: usize) -> PortState {
+        self.ports[n].last_state
+    }
+
+    /// Process data of the device on port `n`, only valid if the port is
+    /// [operational](PortState::is_operational).
+    pub fn inputs(&self, n: usize) -> &[u8] {
+        let port = &self.ports[n];
+        &port.input_data[..port.config.inputs]
+    }
+
+    /// Process data sent to the device on port `n` with the next
+    /// [`process`](Self::process).
+    pub fn outputs_mut(&mut self, n: usize) -> &mut [u8] {
+        let port = &mut self.ports[n];
+        &mut port.output_data[..port.config.outputs]
+    }
+
+    /// Read the parameter `index`, `sub_index` of the device on `port` into
+    /// `target` and return its length.
+    ///
+    /// The terminal maps the ISDU indices 0 to 0xFF of a port to the CoE
+    /// objects 0x3000 + 0x100 * `port` + `index`.
+    pub fn read_isdu<B: Backend + ?Sized>(
+        backend: &mut B,
+        slave: SlavePos,
+        port: usize,
+        index: u16,
+        sub_index: u8,
+        target: &mut [u8],
+    ) -> Result<usize> {
+        backend.sdo_upload(slave, isdu_sdo(port, index, sub_index)?, false, target)
+    }
+
+    /// Write the parameter `index`, `sub_index` of the device on `port`.
+    pub fn write_isdu<B: Backend + ?Sized>(
+        backend: &mut B,
+        slave: SlavePos,
+        port: usize,
+        index: u16,
+        sub_index: u8,
+        data: &[u8],
+    ) -> Result<()> {
+        backend.sdo_download(slave, isdu_sdo(port, index, sub_index)?, false, data)
+    }
+}
+
+impl Port {
+    const fn new(config: PortConfig, state: Field<u8>, inputs: usize, outputs: usize) -> Self {
+        Self {
+            config,
+            state,
+            inputs,
+            outputs,
+            input_data: [0; MAX_DATA],
+            output_data: [0; MAX_DATA],
+            last_state: PortState(0),
+        }
+    }
+}
+
+impl SlaveDriver for IoLinkMaster {
+    type Settings = Vec<PortConfig>;
+
+    fn matches(id: SlaveId) -> bool {
+        Self::detect(id).is_some()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        settings: &Vec<PortConfig>,
+    ) -> Result<Self> {
+        Self::configure(config, id, domain, settings)
+    }
+
+    fn bind(id: SlaveId, image: &SlaveImage, settings: &Vec<PortConfig>) -> Result<Self> {
+        Self::from_image(id, image, settings)
+    }
+
+    fn process(&mut self, data: &mut [u8]) {
+        self.process(data)
+    }
+
+    fn restore(
+        &mut self,
+        backend: &mut dyn Backend,
+        slave: SlavePos,
+        settings: &Vec<PortConfig>,
+    ) -> Result<()> {
+        for (idx, value) in port_sdos(settings) {
+            backend.sdo_download(slave, idx, false, &[value])?;
+        }
+        Ok(())
+    }
+}
+
+fn check_ports(id: SlaveId, ports: &[PortConfig]) -> Result<()> {
+    let count = IoLinkMaster::detect(id).ok_or_else(|| unsupported(id))?;
+    if ports.len() != count {
+        return Err(invalid(format!(
+            "the terminal has {} ports, {} configured",
+            count,
+            ports.len()
+        )));
+    }
+    if ports
+        .iter()
+        .any(|p| p.inputs > MAX_DATA || p.outputs > MAX_DATA)
+    {
+        return Err(invalid(format!(
+            "IO-Link process data is limited to {} bytes",
+            MAX_DATA
+        )));
+    }
+    Ok(())
+}
+
+/// The mode and process data lengths of the ports, in the objects 0x80n0.
+fn port_sdos(ports: &[PortConfig]) -> Vec<(SdoIdx, u8)> {
+    let mut sdos = vec![];
+    for (n, port) in ports.iter().enumerate() {
+        let settings = 0x8000 + 0x10 * n as u16;
+        sdos.push((SdoIdx::new(settings, 0x28), port.mode as u8));
+        sdos.push((SdoIdx::new(settings, 0x25), port.inputs as u8));
+        sdos.push((SdoIdx::new(settings, 0x26), port.outputs as u8));
+    }
+    sdos
+}
+
+fn isdu_sdo(port: usize, index: u16, sub_index: u8) -> Result<SdoIdx> {
+    if port >= 4 || index > 0xFF {
+        return Err(invalid(format!(
+            "no CoE object for the ISDU {:#x} of port {}",
+            index, port
+        )));
+    }
+    Ok(SdoIdx::new(0x3000 + 0x100 * port as u16 + index, sub_index))
+}
+
+fn invalid(msg: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidInput, msg))
+}
+
+#[test]
+fn test_io_link_master() {
+    use crate::backend::{SimMaster, SimSlave};
+
+    let el6224 = SlaveId::new(2, 0x1850_3052);
+    let image = SlaveImage {
+        domain: DomainIdx::from(0),
+        outputs: Some(0..2),
+        inputs: Some(2..11),
+    };
+    let ports = [
+        PortConfig::new(PortMode::IoLink, 2, 0),
+        PortConfig::new(PortMode::IoLink, 3, 2),
+        PortConfig::deactivated(),
+        PortConfig::new(PortMode::DigitalInput, 0, 0),
+    ];
+    assert!(IoLinkMaster::from_image(el6224, &image, &ports[..3]).is_err());
+    let mut master = IoLinkMaster::from_image(el6224, &image, &ports).unwrap();
+
+    let mut data = [0_u8; 11];
+    data[2..7].copy_from_slice(&[1, 2, 3, 4, 5]);
+    data[7..11].copy_from_slice(&[0x03, 0x13, 0x00, 0x01]);
+    master.outputs_mut(1).copy_from_slice(&[0xAA, 0xBB]);
+    master.process(&mut data);
+    assert_eq!(
+        (master.inputs(0), master.inputs(1)),
+        (&[1, 2][..], &[3, 4, 5][..])
+    );
+    assert!(master.state(0).is_operational());
+    assert!(master.state(1).watchdog() && !master.state(1).is_operational());
+    assert_eq!(data[..2], [0xAA, 0xBB]);
+
+    // product name of the device on port 1 in ISDU 0x12
+    let sim = SimSlave::new("EL6224", el6224).object(SdoIdx::new(0x3112, 0), b"sensor");
+    let mut sim = SimMaster::new(vec![sim]);
+    let mut name = [0; 16];
+    let n = IoLinkMaster::read_isdu(&mut sim, SlavePos::from(0), 1, 0x12, 0, &mut name).unwrap();
+    assert_eq!(&name[..n], b"sensor");
+    assert!(IoLinkMaster::read_isdu(&mut sim, SlavePos::from(0), 1, 0x100, 0, &mut name).is_err());
+}
@@ -0,0 +1,464 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{beckhoff_terminal, image_too_small, unsupported, Channels, IoChannel, SlaveDriver};
+#[cfg(target_os = "linux")]
+use crate::SlaveConfig;
+use crate::{
+    backend::{Backend, SlaveImage},
+    field::{Field, PdoData},
+    types::*,
+};
+
+/// Supported terminals.
+const TERMINALS: &[u32] = &[7031, 7041, 7047];
+
+/// Microsteps per full step of the terminals. The resolution is fixed, so
+/// a revolution of the motor is `MICROSTEPS` times its
+/// [full steps](StepperSettings::full_steps).
+pub const MICROSTEPS: u32 = 64;
+
+/// How the motor is commanded, selected by the PDO assignment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepperMode {
+    /// A velocity in 1/32767 of the maximum velocity.
+    Velocity,
+    /// Travel commands of the positioning interface of the terminal.
+    Positioning,
+}
+
+/// Motor parameters written when the slave is configured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepperSettings {
+    /// Maximal coil current in mA, object 0x8010:01.
+    pub max_current: u16,
+    /// Coil current at standstill with reduced torque in mA, 0x8010:02.
+    pub reduced_current: u16,
+    /// Supply voltage of the motor in 10 mV, 0x8010:03.
+    pub nominal_voltage: u16,
+    /// Full steps per revolution of the motor, 0x8010:06.
+    pub full_steps: u16,
+}
+
+/// Inputs of a stepper terminal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StepperStatus {
+    pub ready_to_enable: bool,
+    pub ready: bool,
+    pub warning: bool,
+    pub error: bool,
+    pub moving_positive: bool,
+    pub moving_negative: bool,
+    pub torque_reduced: bool,
+    /// A travel command of the positioning interface is running.
+    pub busy: bool,
+    /// The last travel command reached its target.
+    pub in_target: bool,
+    /// Position in microsteps, from the encoder counter in velocity mode and
+    /// from the positioning interface otherwise.
+    pub position: u32,
+}
+
+/// Outputs of a stepper terminal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Command {
+    enable: bool,
+    reset: bool,
+    reduce_torque: bool,
+    velocity: i16,
+    execute: bool,
+    emergency_stop: bool,
+    target: u32,
+    start_type: u16,
+    acceleration: u16,
+    deceleration: u16,
+}
+
+#[derive(Debug, Clone)]
+struct Fields {
+    enable: Field<bool>,
+    reset: Field<bool>,
+    reduce_torque: Field<bool>,
+    ready_to_enable: Field<bool>,
+    ready: Field<bool>,
+    warning: Field<bool>,
+    error: Field<bool>,
+    moving_positive: Field<bool>,
+    moving_negative: Field<bool>,
+    torque_reduced: Field<bool>,
+    counter: Field<u32>,
+    velocity: Option<Field<i16>>,
+    positioning: Option<Positioning>,
+}
+
+#[derive(Debug, Clone)]
+struct Positioning {
+    execute: Field<bool>,
+    emergency_stop: Field<bool>,
+    target: Field<u32>,
+    velocity: Field<i16>,
+    start_type: Field<u16>,
+    acceleration: Field<u16>,
+    deceleration: Field<u16>,
+    busy: Field<bool>,
+    in_target: Field<bool>,
+    position: Field<u32>,
+}
+
+/// Beckhoff EL7031, EL7041 and EL7047 stepper motor terminals.
+///
+/// These terminals have their own control and status words instead of the
+/// CiA 402 state machine: the motor is powered as long as `enable` is set,
+/// and errors are acknowledged with a `reset`. The objects used are 0x6000
+/// and 0x7000 of the encoder, 0x6010 and 0x7010 of the motor, and 0x6020
+/// and 0x7020 of the positioning interface.
+#[derive(Debug, Clone)]
+pub struct Stepper {
+    mode: StepperMode,
+    fields: Fields,
+    command: Command,
+    status: StepperStatus,
+}
+
+impl Stepper {
+    /// Returns `true` for supported terminals.
+    pub fn detect(id: SlaveId) -> bool {
+        beckhoff_terminal(id).map_or(false, |n| TERMINALS.contains(&n))
+    }
+
+    /// Write the motor settings and the operation mode with startup SDOs
+    /// and register the process data in `domain`.
+    ///
+    /// The PDO assignment of the terminal must match `mode`, e.g. the
+    /// predefined "Velocity control" or "Positioning interface".
+    #[cfg(target_os = "linux")]
+    pub fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        mode: StepperMode,
+        settings: &StepperSettings,
+    ) -> Result<Self> {
+        if !Self::detect(id) {
+            return Err(unsupported(id));
+        }
+        for (idx, data) in startup_sdos(mode, settings) {
+            config.add_sdo(idx, &data.as_slice())?;
+        }
+
+        let mut fields = Fields {
+            enable: config.register_field(PdoEntryIdx::new(0x7010, 0x01), domain)?,
+            reset: config.register_field(PdoEntryIdx::new(0x7010, 0x02), domain)?,
+            reduce_torque: config.register_field(PdoEntryIdx::new(0x7010, 0x03), domain)?,
+            ready_to_enable: config.register_field(PdoEntryIdx::new(0x6010, 0x01), domain)?,
+            ready: config.register_field(PdoEntryIdx::new(0x6010, 0x02), domain)?,
+            warning: config.register_field(PdoEntryIdx::new(0x6010, 0x03), domain)?,
+            error: config.register_field(PdoEntryIdx::new(0x6010, 0x04), domain)?,
+            moving_positive: config.register_field(PdoEntryIdx::new(0x6010, 0x05), domain)?,
+            moving_negative: config.register_field(PdoEntryIdx::new(0x6010, 0x06), domain)?,
+            torque_reduced: config.register_field(PdoEntryIdx::new(0x6010, 0x07), domain)?,
+            counter: config.register_field(PdoEntryIdx::new(0x6000, 0x11), domain)?,
+            velocity: None,
+            positioning: None,
+        };
+        match mode {
+            StepperMode::Velocity => {
+                fields.velocity =
+                    Some(config.register_field(PdoEntryIdx::new(0x7010, 0x21), domain)?);
+            }
+            StepperMode::Positioning => {
+                fields.positioning = Some(Positioning {
+                    execute: config.register_field(PdoEntryIdx::new(0x7020, 0x01), domain)?,
+                    emergency_stop: config
+                        .register_field(PdoEntryIdx::new(0x7020, 0x02), domain)?,
+                    target: config.register_field(PdoEntryIdx::new(0x7020, 0x11), domain)?,
+                    velocity: config.register_field(PdoEntryIdx::new(0x7020, 0x21), domain)?,
+                    start_type: config.register_field(PdoEntryIdx::new(0x7020, 0x22), domain)?,
+                    acceleration: config.register_field(PdoEntryIdx::new(0x7020, 0x23), domain)?,
+                    deceleration: config.register_field(PdoEntryIdx::new(0x7020, 0x24), domain)?,
+                    busy: config.register_field(PdoEntryIdx::new(0x6020, 0x01), domain)?,
+                    in_target: config.register_field(PdoEntryIdx::new(0x6020, 0x02), domain)?,
+                    position: config.register_field(PdoEntryIdx::new(0x6020, 0x11), domain)?,
+                })
+            }
+        }
+        Ok(Self::new(mode, fields))
+    }
+
+    /// Locate the process data of a slave with the predefined PDO
+    /// assignment "Velocity control" or "Positioning interface".
+    ///
+    /// The outputs are the encoder control (6 bytes), the motor control
+    /// (2 bytes) and either the velocity (2 bytes) or the positioning
+    /// control (14 bytes). The inputs are the encoder status (10 bytes), the
+    /// motor status (2 bytes) and in positioning mode the positioning
+    /// status (12 bytes).
+    pub fn from_image(id: SlaveId, image: &SlaveImage, mode: StepperMode) -> Result<Self> {
+        if !Self::detect(id) {
+            return Err(unsupported(id));
+        }
+        let (output_len, input_len) = match mode {
+            StepperMode::Velocity => (10, 12),
+            StepperMode::Positioning => (22, 24),
+        };
+        let outputs = image.outputs.clone().ok_or_else(image_too_small)?;
+        let inputs = image.inputs.clone().ok_or_else(image_too_small)?;
+        if outputs.len() < output_len || inputs.len() < input_len {
+            return Err(image_too_small());
+        }
+        let domain = image.domain;
+        let (o, i) = (outputs.start, inputs.start);
+        let mut fields = Fields {
+            enable: field(domain, o + 6, 0),
+            reset: field(domain, o + 6, 1),
+            reduce_torque: field(domain, o + 6, 2),
+            ready_to_enable: field(domain, i + 10, 0),
+            ready: field(domain, i + 10, 1),
+            warning: field(domain, i + 10, 2),
+            error: field(domain, i + 10, 3),
+            moving_positive: field(domain, i + 10, 4),
+            moving_negative: field(domain, i + 10, 5),
+            torque_reduced: field(domain, i + 10, 6),
+            counter: field(domain, i + 2, 0),
+            velocity: None,
+            positioning: None,
+        };
+        match mode {
+            StepperMode::Velocity => fields.velocity = Some(field(domain, o + 8, 0)),
+            StepperMode::Positioning => {
+                fields.positioning = Some(Positioning {
+                    execute: field(domain, o + 8, 0),
+                    emergency_stop: field(domain, o + 8, 1),
+                    target: field(domain, o + 10, 0),
+                    velocity: field(domain, o + 14, 0),
+                    start_type: field(domain, o + 16, 0),
+                    acceleration: field(domain, o + 18, 0),
+                    deceleration: field(domain, o + 20, 0),
+                    busy: field(domain, i + 12, 0),
+                    in_target: field(domain, i + 12, 1),
+                    position: field(domain, i + 14, 0),
+                })
+            }
+        }
+        Ok(Self::new(mode, fields))
+    }
+
+    fn new(mode: StepperMode, fields: Fields) -> Self {
+        Self {
+            mode,
+            fields,
+            command: Command::default(),
+            status: StepperStatus::default(),
+        }
+    }
+
+    pub const fn mode(&self) -> StepperMode {
+        self.mode
+    }
+
+    /// Read the status from and write the commands into the domain data.
+    pub fn process(&mut self, data: &mut [u8]) {
+        let f = &self.fields;
+        let c = &self.command;
+        self.status = StepperStatus {
+            ready_to_enable: f.ready_to_enable.get(data),
+            ready: f.ready.get(data),
+            warning: f.warning.get(data),
+            error: f.error.get(data),
+            moving_positive: f.moving_positive.get(data),
+            moving_negative: f.moving_negative.get(data),
+            torque_reduced: f.torque_reduced.get(data),
+            busy: false,
+            in_target: false,
+            position: f.counter.get(data),
+        };
+        f.enable.set(data, c.enable);
+        f.reset.set(data, c.reset);
+        f.reduce_torque.set(data, c.reduce_torque);
+        if let Some(velocity) = f.velocity {
+            velocity.set(data, c.velocity);
+        }
+        if let Some(p) = &f.positioning {
+            self.status.busy = p.busy.get(data);
+            self.status.in_target = p.in_target.get(data);
+            self.status.position = p.position.get(data);
+            p.execute.set(data, c.execute);
+            p.emergency_stop.set(data, c.emergency_stop);
+            p.target.set(data, c.target);
+            p.velocity.set(data, c.velocity);
+            p.start_type.set(data, c.start_type);
+            p.acceleration.set(data, c.acceleration);
+            p.deceleration.set(data, c.deceleration);
+        }
+    }
+
+    /// The status as of the last [`process`](Self::process).
+    pub const fn status(&self) -> &StepperStatus {
+        &self.status
+    }
+
+    /// Power the motor. Disabling it lets the motor turn freely.
+    pub fn enable(&mut self, enable: bool) {
+        self.command.enable = enable;
+    }
+
+    /// Acknowledge an error; keep it set until the error is cleared.
+    pub fn reset(&mut self, reset: bool) {
+        self.command.reset = reset;
+    }
+
+    /// Reduce the coil current to the reduced current of the settings.
+    pub fn reduce_torque(&mut self, reduce: bool) {
+        self.command.reduce_torque = reduce;
+    }
+
+    /// Velocity in 1/32767 of the maximum velocity of the terminal, in
+    /// velocity mode, or of travel commands in positioning mode.
+    pub fn set_velocity(&mut self, velocity: i16) {
+        self.command.velocity = velocity;
+    }
+
+    /// Travel to an absolute position in microsteps, in positioning mode.
+    ///
+    /// The command runs as long as it is not [stopped](Self::stop); the
+    /// accelerations are in the units of the terminal settings.
+    pub fn move_to(&mut self, target: u32, acceleration: u16, deceleration: u16) {
+        self.start(1, target, acceleration, deceleration);
+    }
+
+    /// Travel by a distance in microsteps from the current target.
+    pub fn move_by(&mut self, distance: i32, acceleration: u16, deceleration: u16) {
+        self.start(2, distance as u32, acceleration, deceleration);
+    }
+
+    fn start(&mut self, start_type: u16, target: u32, acceleration: u16, deceleration: u16) {
+        let c = &mut self.command;
+        c.execute = true;
+        c.emergency_stop = false;
+        c.start_type = start_type;
+        c.target = target;
+        c.acceleration = acceleration;
+        c.deceleration = deceleration;
+    }
+
+    /// End the travel command, with the deceleration of the command, or as
+    /// fast as possible in an emergency.
+    pub fn stop(&mut self, emergency: bool) {
+        self.command.execute = false;
+        self.command.emergency_stop = emergency;
+    }
+}
+
+impl SlaveDriver for Stepper {
+    type Settings = (StepperMode, StepperSettings);
+
+    fn matches(id: SlaveId) -> bool {
+        Self::detect(id)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        settings: &(StepperMode, StepperSettings),
+    ) -> Result<Self> {
+        Self::configure(config, id, domain, settings.0, &settings.1)
+    }
+
+    fn bind(
+        id: SlaveId,
+        image: &SlaveImage,
+        settings: &(StepperMode, StepperSettings),
+    ) -> Result<Self> {
+        Self::from_image(id, image, settings.0)
+    }
+
+    fn process(&mut self, data: &mut [u8]) {
+        self.process(data)
+    }
+
+    fn restore(
+        &mut self,
+        backend: &mut dyn Backend,
+        slave: SlavePos,
+        settings: &(StepperMode, StepperSettings),
+    ) -> Result<()> {
+        for (idx, data) in startup_sdos(settings.0, &settings.1) {
+            backend.sdo_download(slave, idx, false, &data)?;
+        }
+        Ok(())
+    }
+
+    /// Disable the motor and drop the commands.
+    fn shutdown(&mut self) {
+        self.command = Command::default();
+    }
+
+    fn channels(&self) -> Channels<'_> {
+        let position = i64::from(self.status.position);
+        let velocity = i64::from(self.command.velocity);
+        Box::new(
+            vec![
+                IoChannel::input("Position".to_owned(), position),
+                IoChannel::output("Velocity".to_owned(), velocity),
+            ]
+            .into_iter(),
+        )
+    }
+}
+
+/// The motor settings and the operation mode, as SDOs with their data.
+fn startup_sdos(mode: StepperMode, settings: &StepperSettings) -> Vec<(SdoIdx, Vec<u8>)> {
+    // velocity direct or position controller
+    let operation_mode: u8 = match mode {
+        StepperMode::Velocity => 1,
+        StepperMode::Positioning => 3,
+    };
+    let motor = |sub, value: u16| (SdoIdx::new(0x8010, sub), value.to_le_bytes().to_vec());
+    vec![
+        motor(0x01, settings.max_current),
+        motor(0x02, settings.reduced_current),
+        motor(0x03, settings.nominal_voltage),
+        motor(0x06, settings.full_steps),
+        (SdoIdx::new(0x8012, 0x01), vec![operation_mode]),
+    ]
+}
+
+fn field<T: PdoData>(domain: DomainIdx, byte: usize, bit: u32) -> Field<T> {
+    Field::new(domain, Offset { byte, bit })
+}
+
+#[test]
+fn test_stepper() {
+    let el7041 = SlaveId::new(2, 0x1b81_3052);
+    assert!(Stepper::detect(el7041));
+    let image = SlaveImage {
+        domain: DomainIdx::from(0),
+        outputs: Some(0..22),
+        inputs: Some(22..46),
+    };
+    assert!(Stepper::from_image(el7041, &image, StepperMode::Velocity).is_ok());
+    let mut stepper = Stepper::from_image(el7041, &image, StepperMode::Positioning).unwrap();
+    let mut data = [0_u8; 46];
+    data[32] = 0b0000_0011;
+    data[34] = 0b01;
+    data[36..40].copy_from_slice(&1234_u32.to_le_bytes());
+
+    stepper.enable(true);
+    stepper.set_velocity(1000);
+    stepper.move_to(6400, 100, 200);
+    stepper.process(&mut data);
+    let status = stepper.status();
+    assert!(status.ready_to_enable && status.ready && !status.error);
+    assert!(status.busy && !status.in_target);
+    assert_eq!(status.position, 1234);
+    assert_eq!(data[6], 0b001);
+    assert_eq!(data[8], 0b01);
+    assert_eq!(data[10..14], 6400_u32.to_le_bytes());
+    assert_eq!(data[14..22], [0xE8, 0x03, 1, 0, 100, 0, 200, 0]);
+
+    stepper.stop(true);
+    stepper.process(&mut data);
+    assert_eq!(data[8], 0b10);
+}
@@ -0,0 +1,439 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{
+    beckhoff_terminal, image_too_small, packed_bits, unsupported, Channels, IoChannel, SlaveDriver,
+    Unit,
+};
+#[cfg(target_os = "linux")]
+use crate::SlaveConfig;
+use crate::{
+    backend::SlaveImage,
+    field::{Field, ScaledField},
+    types::*,
+};
+
+/// Power supply terminals: number, and whether they only refresh the E-bus
+/// supply and report undervoltages of Us and Up, or supply the power
+/// contacts and report power OK and overload.
+const SUPPLIES: &[(u32, bool)] = &[
+    (9410, true),
+    (9411, true),
+    (9505, false),
+    (9508, false),
+    (9510, false),
+    (9512, false),
+    (9515, false),
+    (9560, false),
+];
+
+/// Electronic overcurrent protection terminals and their channels.
+const PROTECTIONS: &[(u32, usize)] = &[(9221, 1), (9222, 2), (9227, 2)];
+
+/// Bytes of a channel of a protection terminal: status word and current.
+const CHANNEL_BYTES: usize = 4;
+
+/// Beckhoff EL9410/EL9411 E-bus power supply and EL95xx power supply
+/// terminals.
+///
+/// Their two status bits are mapped from the object 0x6000, subindices 1
+/// and 2.
+#[derive(Debug, Clone)]
+pub struct PowerSupply {
+    refresh: bool,
+    status: Vec<Field<bool>>,
+    bits: [bool; 2],
+}
+
+impl PowerSupply {
+    /// Returns `true` for supported terminals.
+    pub fn detect(id: SlaveId) -> bool {
+        supply(id).is_some()
+    }
+
+    /// Register the status bits of the terminal in `domain`.
+    #[cfg(target_os = "linux")]
+    pub fn configure(config: &mut SlaveConfig, id: SlaveId, domain: DomainIdx) -> Result<Self> {
+        let refresh = supply(id).ok_or_else(|| unsupported(id))?;
+        let status = (1..=2)
+            .map(|sub| config.register_field(PdoEntryIdx::new(0x6000, sub), domain))
+            .collect::<Result<_>>()?;
+        Ok(Self::new(refresh, status))
+    }
+
+    /// Locate the status bits at the start of the inputs of a slave.
+    pub fn from_image(id: SlaveId, image: &SlaveImage) -> Result<Self> {
+        let refresh = supply(id).ok_or_else(|| unsupported(id))?;
+        let status = packed_bits(image.domain, image.inputs.clone(), 2)?;
+        Ok(Self::new(refresh, status))
+    }
+
+    fn new(refresh: bool, status: Vec<Field<bool>>) -> Self {
+        Self {
+            refresh,
+            status,
+            bits: [false; 2],
+        }
+    }
+
+    /// Read the status from the domain data.
+    pub fn process(&mut self, data: &[u8]) {
+        for (bit, field) in self.bits.iter_mut().zip(&self.status) {
+            *bit = field.get(data);
+        }
+    }
+
+    /// Faults as of the last [`process`](Self::process): the supply as
+    /// channel, 0 for Us and 1 for Up on E-bus supplies, 0 on the others.
+    pub fn faults(&self) -> Vec<(usize, PowerFaultKind)> {
+        let [first, second] = self.bits;
+        let mut faults = vec![];
+        if self.refresh {
+            if first {
+                faults.push((0, PowerFaultKind::Undervoltage));
+            }
+            if second {
+                faults.push((1, PowerFaultKind::Undervoltage));
+            }
+        } else {
+            // the first bit is "power OK"
+            if !first {
+                faults.push((0, PowerFaultKind::Undervoltage));
+            }
+            if second {
+                faults.push((0, PowerFaultKind::Overload));
+            }
+        }
+        faults
+    }
+
+    /// Add the faults of the terminal at `slave` to a health summary.
+    pub fn report(&self, slave: SlavePos, health: &mut BusHealth) {
+        report(slave, self.faults(), health);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Channel {
+    on: Field<bool>,
+    warning: Field<bool>,
+    tripped: Field<bool>,
+    current: ScaledField<u16>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ChannelState {
+    on: bool,
+    warning: bool,
+    tripped: bool,
+    current: f64,
+}
+
+/// Beckhoff EL9221, EL9222 and EL9227 electronic overcurrent protection
+/// terminals.
+///
+/// Channel `i` is mapped from the object 0x6000 + 0x10 * `i`: the status
+/// bits in subindices 1 to 3 and the current in 10 mA in subindex 0x11.
+/// The supply voltage in 10 mV is mapped from 0xF600:11.
+#[derive(Debug, Clone)]
+pub struct CurrentProtection {
+    channels: Vec<Channel>,
+    states: Vec<ChannelState>,
+    voltage_field: ScaledField<u16>,
+    voltage: f64,
+}
+
+impl CurrentProtection {
+    /// Number of channels of a supported terminal, `None` for other slaves.
+    pub fn detect(id: SlaveId) -> Option<usize> {
+        let number = beckhoff_terminal(id)?;
+        PROTECTIONS
+            .iter()
+            .find(|(n, _)| *n == number)
+            .map(|(_, channels)| *channels)
+    }
+
+    /// Register the channels and the supply voltage in `domain`.
+    #[cfg(target_os = "linux")]
+    pub fn configure(config: &mut SlaveConfig, id: SlaveId, domain: DomainIdx) -> Result<Self> {
+        let count = Self::detect(id).ok_or_else(|| unsupported(id))?;
+        let mut channels = vec![];
+        for i in 0..count as u16 {
+            let idx = 0x6000 + 0x10 * i;
+            let current = config.register_field(PdoEntryIdx::new(idx, 0x11), domain)?;
+            channels.push(Channel {
+                on: config.register_field(PdoEntryIdx::new(idx, 0x01), domain)?,
+                warning: config.register_field(PdoEntryIdx::new(idx, 0x02), domain)?,
+                tripped: config.register_field(PdoEntryIdx::new(idx, 0x03), domain)?,
+                current: milliamperes(current),
+            });
+        }
+        let voltage = config.register_field(PdoEntryIdx::new(0xF600, 0x11), domain)?;
+        Ok(Self::new(channels, volts(voltage)))
+    }
+
+    /// Locate the channels in the inputs of a slave with the default
+    /// mapping: status word and current of each channel, followed by the
+    /// supply voltage.
+    pub fn from_image(id: SlaveId, image: &SlaveImage) -> Result<Self> {
+        let count = Self::detect(id).ok_or_else(|| unsupported(id))?;
+        let inputs = image.inputs.clone().ok_or_else(image_too_small)?;
+        if inputs.len() < count * CHANNEL_BYTES + 2 {
+            return Err(image_too_small());
+        }
+        let domain = image.domain;
+        let channels = (0..count)
+            .map(|i| {
+                let byte = inputs.start + i * CHANNEL_BYTES;
+                let bit = |bit| Field::new(domain, Offset { byte, bit });
+                Channel {
+                    on: bit(0),
+                    warning: bit(1),
+                    tripped: bit(2),
+                    current: milliamperes(Field::new(
+                        domain,
+                        Offset {
+                            byte: byte + 2,
+                            bit: 0,
+                        },
+                    )),
+                }
+            })
+            .collect();
+        let byte = inputs.start + count * CHANNEL_BYTES;
+        let voltage = volts(Field::new(domain, Offset { byte, bit: 0 }));
+        Ok(Self::new(channels, voltage))
+    }
+
+    fn new(channels: Vec<Channel>, voltage_field: ScaledField<u16>) -> Self {
+        Self {
+            states: vec![ChannelState::default(); channels.len()],
+            channels,
+            voltage_field,
+            voltage: 0.0,
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Read the channels and the supply voltage from the domain data.
+    pub fn process(&mut self, data: &[u8]) {
+        for (channel, state) in self.channels.iter().zip(&mut self.states) {
+            *state = ChannelState {
+                on: channel.on.get(data),
+                warning: channel.warning.get(data),
+                tripped: channel.tripped.get(data),
+                current: channel.current.get(data),
+            };
+        }
+        self.voltage = self.voltage_field.get(data);
+    }
+
+    /// Supply voltage in V as of the last [`process`](Self::process).
+    pub const fn voltage(&self) -> f64 {
+        self.voltage
+    }
+
+    /// Current of channel `i`, counted from 0, in mA.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no channel `i`.
+    pub fn current(&self, i: usize) -> f64 {
+        self.states[i].current
+    }
+
+    /// The output of channel `i` is switched on.
+    pub fn is_on(&self, i: usize) -> bool {
+        self.states[i].on
+    }
+
+    /// The current of channel `i` is close to its nominal current.
+    pub fn warning(&self, i: usize) -> bool {
+        self.states[i].warning
+    }
+
+    /// Channel `i` was switched off by overcurrent or short circuit.
+    pub fn tripped(&self, i: usize) -> bool {
+        self.states[i].tripped
+    }
+
+    /// Add the tripped channels of the terminal at `slave` to a health
+    /// summary.
+    pub fn report(&self, slave: SlavePos, health: &mut BusHealth) {
+        let faults = (0..self.states.len())
+            .filter(|i| self.states[*i].tripped)
+            .map(|i| (i, PowerFaultKind::Tripped))
+            .collect();
+        report(slave, faults, health);
+    }
+}
+
+impl SlaveDriver for PowerSupply {
+    type Settings = ();
+
+    fn matches(id: SlaveId) -> bool {
+        Self::detect(id)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        _settings: &(),
+    ) -> Result<Self> {
+        Self::configure(config, id, domain)
+    }
+
+    fn bind(id: SlaveId, image: &SlaveImage, _settings: &()) -> Result<Self> {
+        Self::from_image(id, image)
+    }
+
+    fn process(&mut self, data: &mut [u8]) {
+        self.process(data)
+    }
+
+    fn channels(&self) -> Channels<'_> {
+        let names: &[&str] = if self.refresh {
+            &["Us undervoltage", "Up undervoltage"]
+        } else {
+            &["Power OK", "Overload"]
+        };
+        Box::new(
+            names
+                .iter()
+                .zip(self.bits)
+                .map(|(name, bit)| IoChannel::input(name.to_string(), bit)),
+        )
+    }
+}
+
+impl SlaveDriver for CurrentProtection {
+    type Settings = ();
+
+    fn matches(id: SlaveId) -> bool {
+        Self::detect(id).is_some()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        _settings: &(),
+    ) -> Result<Self> {
+        Self::configure(config, id, domain)
+    }
+
+    fn bind(id: SlaveId, image: &SlaveImage, _settings: &()) -> Result<Self> {
+        Self::from_image(id, image)
+    }
+
+    fn process(&mut self, data: &mut [u8]) {
+        self.process(data)
+    }
+
+    fn channels(&self) -> Channels<'_> {
+        let currents = self.states.iter().enumerate().map(|(i, state)| {
+            IoChannel::input(format!("Current {}", i + 1), state.current)
+                .with_unit(Unit::Milliampere)
+                .with_valid(!state.tripped)
+        });
+        let voltage =
+            IoChannel::input("Supply voltage".to_owned(), self.voltage).with_unit(Unit::Volt);
+        Box::new(currents.chain(std::iter::once(voltage)))
+    }
+}
+
+fn report(slave: SlavePos, faults: Vec<(usize, PowerFaultKind)>, health: &mut BusHealth) {
+    health
+        .power_faults
+        .extend(faults.into_iter().map(|(channel, kind)| PowerFault {
+            slave,
+            channel,
+            kind,
+        }));
+}
+
+fn supply(id: SlaveId) -> Option<bool> {
+    let number = beckhoff_terminal(id)?;
+    SUPPLIES
+        .iter()
+        .find(|(n, _)| *n == number)
+        .map(|(_, refresh)| *refresh)
+}
+
+fn milliamperes(field: Field<u16>) -> ScaledField<u16> {
+    ScaledField::new(field, 10.0, 0.0)
+}
+
+fn volts(field: Field<u16>) -> ScaledField<u16> {
+    ScaledField::new(field, 0.01, 0.0)
+}
+
+#[test]
+fn test_power_terminals() {
+    let domain = DomainIdx::from(0);
+    let mut health = BusHealth {
+        link_up: true,
+        slaves_responding: 3,
+        slaves_configured: 3,
+        worst_al_state: Some(AlState::Op),
+        domains: vec![],
+        dc_deviation: None,
+        emergency_overruns: 0,
+        power_faults: vec![],
+    };
+    assert!(health.is_ok());
+
+    let el9410 = SlaveId::new(2, 0x24c2_3052);
+    let image = SlaveImage {
+        domain,
+        outputs: None,
+        inputs: Some(0..1),
+    };
+    let mut supply = PowerSupply::from_image(el9410, &image).unwrap();
+    supply.process(&[0b10]);
+    assert_eq!(supply.faults(), [(1, PowerFaultKind::Undervoltage)]);
+    supply.report(SlavePos::from(1), &mut health);
+
+    let el9227 = SlaveId::new(2, 0x240b_3052);
+    assert_eq!(CurrentProtection::detect(el9227), Some(2));
+    let image = SlaveImage {
+        domain,
+        outputs: None,
+        inputs: Some(0..10),
+    };
+    let mut protection = CurrentProtection::from_image(el9227, &image).unwrap();
+    let mut data = [0_u8; 10];
+    data[0] = 0b011;
+    data[2..4].copy_from_slice(&150_u16.to_le_bytes());
+    data[4] = 0b100;
+    data[8..10].copy_from_slice(&2400_u16.to_le_bytes());
+    protection.process(&data);
+    assert_eq!(protection.current(0), 1500.0);
+    assert!(protection.is_on(0) && protection.warning(0));
+    assert!(!protection.is_on(1) && protection.tripped(1));
+    assert!((protection.voltage() - 24.0).abs() < 1e-9);
+    protection.report(SlavePos::from(2), &mut health);
+
+    assert!(!health.is_ok());
+    assert_eq!(
+        health.power_faults,
+        [
+            PowerFault {
+                slave: SlavePos::from(1),
+                channel: 1,
+                kind: PowerFaultKind::Undervoltage
+            },
+            PowerFault {
+                slave: SlavePos::from(2),
+                channel: 1,
+                kind: PowerFaultKind::Tripped
+            }
+        ]
+    );
+}
@@ -0,0 +1,277 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{
+    beckhoff_terminal, image_too_small, unsupported, Channels, Direction, IoChannel, SlaveDriver,
+};
+#[cfg(target_os = "linux")]
+use crate::SlaveConfig;
+use crate::{backend::SlaveImage, types::*};
+use std::{io, ops::Range};
+
+/// Supported terminals: number, safe channels, and whether the channels
+/// are outputs.
+const TERMINALS: &[(u32, usize, bool)] = &[(1904, 4, false), (2904, 4, true)];
+
+/// Length of an FSoE frame with one byte of safe data: command, data, CRC
+/// and connection ID.
+const FRAME_BYTES: usize = 6;
+
+/// State of the FSoE connection, from the command of the last frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsoeState {
+    Reset,
+    Session,
+    Connection,
+    Parameter,
+    /// Exchanging safe process data.
+    ProcessData,
+    /// Exchanging fail-safe data: all safe channels are off.
+    FailSafeData,
+    /// A command not defined by FSoE, e.g. before the first frame.
+    Unknown(u8),
+}
+
+impl From<u8> for FsoeState {
+    fn from(command: u8) -> Self {
+        match command {
+            0x2A => FsoeState::Reset,
+            0x4E => FsoeState::Session,
+            0x64 => FsoeState::Connection,
+            0x52 => FsoeState::Parameter,
+            0x36 => FsoeState::ProcessData,
+            0x08 => FsoeState::FailSafeData,
+            c => FsoeState::Unknown(c),
+        }
+    }
+}
+
+/// Beckhoff EL1904 and EL2904 TwinSAFE terminals, observed read-only.
+///
+/// The safe data travels in FSoE frames between the terminal and a safety
+/// controller, which the application must never alter. This driver only
+/// reads the frames and the standard inputs, and has no way to write to the
+/// domain. Use [`check_writable`](Self::check_writable) to refuse writable
+/// fields on the safe objects elsewhere.
+///
+/// The channel states of an EL1904 are the safe data of its frame in the
+/// object 0x6000, and its standard inputs 0x6001:01 to :04 repeat them
+/// without safety. The channel states of an EL2904 are the safe data of the
+/// frame from the safety controller in the object 0x7000.
+#[derive(Debug, Clone)]
+pub struct SafetyTerminal {
+    channels: usize,
+    outputs: bool,
+    /// Frame holding the channel states.
+    frame: usize,
+    standard: Option<usize>,
+    state: FsoeState,
+    safe_bits: u8,
+    standard_bits: u8,
+}
+
+impl SafetyTerminal {
+    /// Number of safe channels of a supported terminal, `None` for other
+    /// slaves.
+    pub fn detect(id: SlaveId) -> Option<usize> {
+        terminal(id).map(|t| t.1)
+    }
+
+    /// Register the frame with the channel states, and for an EL1904 its
+    /// standard inputs, in `domain`.
+    ///
+    /// Registering the frame of an EL2904 only gives the offset to read;
+    /// the frame must still be copied from the safety controller.
+    #[cfg(target_os = "linux")]
+    pub fn configure(config: &mut SlaveConfig, id: SlaveId, domain: DomainIdx) -> Result<Self> {
+        let (_, channels, outputs) = terminal(id).ok_or_else(|| unsupported(id))?;
+        let object = if outputs { 0x7000 } else { 0x6000 };
+        let frame = config
+            .register_pdo_entry(PdoEntryIdx::new(object, 0x01), domain)?
+            .byte;
+        let mut standard = None;
+        if !outputs {
+            standard = Some(
+                config
+                    .register_pdo_entry(PdoEntryIdx::new(0x6001, 0x01), domain)?
+                    .byte,
+            );
+        }
+        Ok(Self::new(channels, outputs, frame, standard))
+    }
+
+    /// Locate the frames in the process data of a slave with the default
+    /// mapping: the frame of the terminal in the inputs, followed by the
+    /// standard inputs of an EL1904, and the frame of the safety controller
+    /// in the outputs.
+    pub fn from_image(id: SlaveId, image: &SlaveImage) -> Result<Self> {
+        let (_, channels, outputs) = terminal(id).ok_or_else(|| unsupported(id))?;
+        let check = |range: Option<Range<usize>>, len| match range {
+            Some(range) if range.len() >= len => Ok(range.start),
+            _ => Err(image_too_small()),
+        };
+        if outputs {
+            let frame = check(image.outputs.clone(), FRAME_BYTES)?;
+            Ok(Self::new(channels, outputs, frame, None))
+        } else {
+            let frame = check(image.inputs.clone(), FRAME_BYTES + 1)?;
+            let standard = Some(frame + FRAME_BYTES);
+            Ok(Self::new(channels, outputs, frame, standard))
+        }
+    }
+
+    const fn new(channels: usize, outputs: bool, frame: usize, standard: Option<usize>) -> Self {
+        Self {
+            channels,
+            outputs,
+            frame,
+            standard,
+            state: FsoeState::Unknown(0),
+            safe_bits: 0,
+            standard_bits: 0,
+        }
+    }
+
+    /// Refuse to map a writable field on a safe object of the terminal:
+    /// the FSoE frames and the standard outputs of the EL2904, which are
+    /// combined with the safe ones.
+    pub fn check_writable(id: SlaveId, entry: PdoEntryIdx) -> Result<()> {
+        let idx = u16::from(entry.idx);
+        if Self::detect(id).is_some() && (idx == 0x7000 || idx == 0x7001) {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{:#06x} is an output of a safety terminal", idx),
+            )));
+        }
+        Ok(())
+    }
+
+    pub const fn channel_count(&self) -> usize {
+        self.channels
+    }
+
+    /// Returns `true` for safe outputs, `false` for safe inputs.
+    pub const fn is_output(&self) -> bool {
+        self.outputs
+    }
+
+    /// Read the frame and the standard inputs from the domain data.
+    pub fn process(&mut self, data: &[u8]) {
+        self.state = FsoeState::from(data[self.frame]);
+        self.safe_bits = data[self.frame + 1];
+        if let Some(standard) = self.standard {
+            self.standard_bits = data[standard];
+        }
+    }
+
+    /// State of the FSoE connection as of the last
+    /// [`process`](Self::process).
+    pub const fn state(&self) -> FsoeState {
+        self.state
+    }
+
+    /// State of safe channel `i`, counted from 0, while the connection
+    /// exchanges process data. Channels are off in any other state.
+    ///
+    /// This is for monitoring only: the state has not passed the safety
+    /// checks of the FSoE protocol.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no channel `i`.
+    pub fn channel(&self, i: usize) -> bool {
+        assert!(i < self.channels, "no safe channel {}", i);
+        self.state == FsoeState::ProcessData && self.safe_bits & 1 << i != 0
+    }
+
+    /// The standard input `i` of an EL1904, `None` for an EL2904.
+    pub fn standard_input(&self, i: usize) -> Option<bool> {
+        assert!(i < self.channels, "no safe channel {}", i);
+        self.standard.map(|_| self.standard_bits & 1 << i != 0)
+    }
+}
+
+impl SlaveDriver for SafetyTerminal {
+    type Settings = ();
+
+    fn matches(id: SlaveId) -> bool {
+        Self::detect(id).is_some()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        _settings: &(),
+    ) -> Result<Self> {
+        Self::configure(config, id, domain)
+    }
+
+    fn bind(id: SlaveId, image: &SlaveImage, _settings: &()) -> Result<Self> {
+        Self::from_image(id, image)
+    }
+
+    fn process(&mut self, data: &mut [u8]) {
+        self.process(data)
+    }
+
+    fn channels(&self) -> Channels<'_> {
+        let (kind, direction) = if self.outputs {
+            ("output", Direction::Output)
+        } else {
+            ("input", Direction::Input)
+        };
+        let valid = self.state == FsoeState::ProcessData;
+        Box::new((0..self.channels).map(move |i| {
+            IoChannel {
+                direction,
+                ..IoChannel::input(format!("Safe {} {}", kind, i + 1), self.channel(i))
+                    .with_valid(valid)
+            }
+        }))
+    }
+}
+
+fn terminal(id: SlaveId) -> Option<(u32, usize, bool)> {
+    let number = beckhoff_terminal(id)?;
+    TERMINALS.iter().find(|t| t.0 == number).copied()
+}
+
+#[test]
+fn test_safety_terminal() {
+    let el1904 = SlaveId::new(2, 0x0770_3052);
+    let el2904 = SlaveId::new(2, 0x0b58_3052);
+    assert_eq!(SafetyTerminal::detect(el1904), Some(4));
+    let image = SlaveImage {
+        domain: DomainIdx::from(0),
+        outputs: Some(0..6),
+        inputs: Some(6..13),
+    };
+
+    let mut inputs = SafetyTerminal::from_image(el1904, &image).unwrap();
+    let mut data = [0_u8; 13];
+    data[6..13].copy_from_slice(&[0x36, 0b0101, 0xAB, 0xCD, 0x01, 0x00, 0b0111]);
+    inputs.process(&data);
+    assert_eq!(inputs.state(), FsoeState::ProcessData);
+    assert!(inputs.channel(0) && !inputs.channel(1) && inputs.channel(2));
+    assert_eq!(inputs.standard_input(1), Some(true));
+
+    data[6] = 0x08;
+    inputs.process(&data);
+    assert_eq!(inputs.state(), FsoeState::FailSafeData);
+    assert!(!inputs.channel(0));
+
+    let mut outputs = SafetyTerminal::from_image(el2904, &image).unwrap();
+    data[..2].copy_from_slice(&[0x36, 0b1000]);
+    outputs.process(&data);
+    assert!(outputs.is_output() && outputs.channel(3));
+    assert_eq!(outputs.standard_input(0), None);
+
+    let entry = PdoEntryIdx::new;
+    assert!(SafetyTerminal::check_writable(el2904, entry(0x7000, 1)).is_err());
+    assert!(SafetyTerminal::check_writable(el1904, entry(0x7001, 1)).is_err());
+    assert!(SafetyTerminal::check_writable(el2904, entry(0x6001, 1)).is_ok());
+    let el2004 = SlaveId::new(2, 0x07d4_3052);
+    assert!(SafetyTerminal::check_writable(el2004, entry(0x7000, 1)).is_ok());
+}
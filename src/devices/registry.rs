@@ -0,0 +1,458 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{
+    AnalogInputs, AnalogOutputs, Channels, CurrentProtection, DigitalInputs, DigitalOutputs,
+    Encoder, PowerSupply, SafetyTerminal, SlaveDriver,
+};
+use crate::{
+    backend::{Backend, SlaveImage},
+    types::*,
+};
+#[cfg(target_os = "linux")]
+use crate::{Master, SlaveConfig};
+use std::{any::Any, fmt, ops::RangeInclusive, rc::Rc};
+
+/// A driver of any type, with its settings.
+trait AnyDriver {
+    fn process(&mut self, data: &mut [u8]);
+    fn restore(&mut self, backend: &mut dyn Backend, slave: SlavePos) -> Result<()>;
+    fn shutdown(&mut self);
+    fn channels(&self) -> Channels<'_>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn type_name(&self) -> &'static str;
+}
+
+struct Bound<D: SlaveDriver> {
+    driver: D,
+    settings: Rc<D::Settings>,
+}
+
+impl<D> AnyDriver for Bound<D>
+where
+    D: SlaveDriver + 'static,
+    D::Settings: 'static,
+{
+    fn process(&mut self, data: &mut [u8]) {
+        self.driver.process(data)
+    }
+
+    fn restore(&mut self, backend: &mut dyn Backend, slave: SlavePos) -> Result<()> {
+        self.driver.restore(backend, slave, &self.settings)
+    }
+
+    fn shutdown(&mut self) {
+        self.driver.shutdown()
+    }
+
+    fn channels(&self) -> Channels<'_> {
+        self.driver.channels()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.driver
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.driver
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<D>()
+    }
+}
+
+type Matcher = Box<dyn Fn(SlaveId, u32) -> bool>;
+type Binder = Box<dyn Fn(SlaveId, &SlaveImage) -> Result<Box<dyn AnyDriver>>>;
+#[cfg(target_os = "linux")]
+type Configurator = Box<dyn Fn(&mut SlaveConfig, SlaveId, DomainIdx) -> Result<Box<dyn AnyDriver>>>;
+
+struct Registration {
+    /// Matches the identity and revision number of a slave.
+    matches: Matcher,
+    bind: Binder,
+    #[cfg(target_os = "linux")]
+    configure: Configurator,
+}
+
+/// The drivers to bring up the slaves of a bus with.
+///
+/// Each slave found on the bus is handed to the last registered driver
+/// matching its identity and revision, so that drivers registered later
+/// override earlier ones, e.g. the built-in drivers. Slaves matched by no
+/// driver are left alone.
+///
+/// ```ignore
+/// let mut devices = DeviceRegistry::with_builtin()
+///     .register::<ValveTerminal>(16)
+///     .bind(master)?;
+/// let domain = DomainIdx::from(0);
+/// loop {
+///     master.receive()?;
+///     if let Some(valves) = devices.get_mut::<ValveTerminal>(SlavePos::from(3)) {
+///         valves.set_coil(0, true);
+///     }
+///     devices.process(domain, master.domain_data(domain)?);
+///     master.send()?;
+/// }
+/// ```
+#[derive(Default)]
+pub struct DeviceRegistry {
+    drivers: Vec<Registration>,
+}
+
+impl DeviceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry with the built-in drivers that take no settings.
+    pub fn with_builtin() -> Self {
+        Self::new()
+            .register::<DigitalInputs>(())
+            .register::<DigitalOutputs>(())
+            .register::<AnalogInputs>(Vec::new())
+            .register::<AnalogOutputs>(Vec::new())
+            .register::<Encoder>(())
+            .register::<PowerSupply>(())
+            .register::<CurrentProtection>(())
+            .register::<SafetyTerminal>(())
+    }
+
+    /// Register a driver for all the slaves it supports.
+    pub fn register<D>(self, settings: D::Settings) -> Self
+    where
+        D: SlaveDriver + 'static,
+        D::Settings: 'static,
+    {
+        self.register_revisions::<D>(0..=u32::MAX, settings)
+    }
+
+    /// Register a driver for the slaves it supports with a revision number
+    /// in `revisions`, e.g. when a firmware update changed their mapping.
+    pub fn register_revisions<D>(
+        mut self,
+        revisions: RangeInclusive<u32>,
+        settings: D::Settings,
+    ) -> Self
+    where
+        D: SlaveDriver + 'static,
+        D::Settings: 'static,
+    {
+        let settings = Rc::new(settings);
+        let bound = |driver, settings: &Rc<D::Settings>| {
+            let settings = settings.clone();
+            Box::new(Bound::<D> { driver, settings }) as Box<dyn AnyDriver>
+        };
+        #[cfg(target_os = "linux")]
+        let configure = {
+            let settings = settings.clone();
+            Box::new(move |config: &mut SlaveConfig, id, domain| {
+                D::configure(config, id, domain, &settings).map(|d| bound(d, &settings))
+            })
+        };
+        self.drivers.push(Registration {
+            matches: Box::new(move |id, revision| D::matches(id) && revisions.contains(&revision)),
+            bind: Box::new(move |id, image: &SlaveImage| {
+                D::bind(id, image, &settings).map(|d| bound(d, &settings))
+            }),
+            #[cfg(target_os = "linux")]
+            configure,
+        });
+        self
+    }
+
+    fn find(&self, info: &SlaveInfo) -> Option<&Registration> {
+        self.drivers
+            .iter()
+            .rev()
+            .find(|r| (r.matches)(info.id, info.rev.revision_number))
+    }
+
+    /// Scan the bus of an activated master and bind a driver to each
+    /// matched slave, from its default mapping.
+    pub fn bind<B: Backend + ?Sized>(&self, backend: &mut B) -> Result<Devices> {
+        let mut devices = Devices::default();
+        for i in 0..backend.slave_count()? {
+            let slave = SlavePos::from(i as u16);
+            let info = backend.slave_info(slave)?;
+            if let Some(registration) = self.find(&info) {
+                let image = backend.slave_image(slave)?;
+                let driver = (registration.bind)(info.id, &image)?;
+                log::debug!("Bound {} to slave {}", driver.type_name(), i);
+                devices
+                    .drivers
+                    .push(Entry::new(slave, info.id, image.domain, driver));
+            }
+        }
+        Ok(devices)
+    }
+
+    /// Scan the bus and configure each matched slave, registering its
+    /// process data in `domain`, before the activation of the master.
+    #[cfg(target_os = "linux")]
+    pub fn configure(&self, master: &mut Master, domain: DomainIdx) -> Result<Devices> {
+        let mut devices = Devices::default();
+        for i in 0..master.get_info()?.slave_count as u16 {
+            let slave = SlavePos::from(i);
+            let info = master.get_slave_info(slave)?;
+            if let Some(registration) = self.find(&info) {
+                let mut config = master.configure_slave(SlaveAddr::ByPos(i), info.id)?;
+                let driver = (registration.configure)(&mut config, info.id, domain)?;
+                log::debug!("Configured {} for slave {}", driver.type_name(), i);
+                devices
+                    .drivers
+                    .push(Entry::new(slave, info.id, domain, driver));
+            }
+        }
+        Ok(devices)
+    }
+}
+
+impl fmt::Debug for DeviceRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DeviceRegistry")
+            .field("drivers", &self.drivers.len())
+            .finish()
+    }
+}
+
+struct Entry {
+    slave: SlavePos,
+    id: SlaveId,
+    domain: DomainIdx,
+    driver: Box<dyn AnyDriver>,
+    online: bool,
+    restore_outputs: bool,
+}
+
+impl Entry {
+    fn new(slave: SlavePos, id: SlaveId, domain: DomainIdx, driver: Box<dyn AnyDriver>) -> Self {
+        Self {
+            slave,
+            id,
+            domain,
+            driver,
+            online: true,
+            restore_outputs: false,
+        }
+    }
+}
+
+/// The drivers brought up by a [`DeviceRegistry`], by slave position.
+#[derive(Default)]
+pub struct Devices {
+    drivers: Vec<Entry>,
+}
+
+impl Devices {
+    pub fn len(&self) -> usize {
+        self.drivers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.drivers.is_empty()
+    }
+
+    /// The positions of the slaves with a driver, in ring order.
+    pub fn slaves(&self) -> impl Iterator<Item = SlavePos> + '_ {
+        self.drivers.iter().map(|e| e.slave)
+    }
+
+    fn entry(&self, slave: SlavePos) -> Option<&Entry> {
+        self.drivers.iter().find(|e| e.slave == slave)
+    }
+
+    fn entry_mut(&mut self, slave: SlavePos) -> Option<&mut Entry> {
+        self.drivers.iter_mut().find(|e| e.slave == slave)
+    }
+
+    /// The driver of a slave, `None` if it has none or one of another type.
+    pub fn get<D: 'static>(&self, slave: SlavePos) -> Option<&D> {
+        self.entry(slave)
+            .and_then(|e| e.driver.as_any().downcast_ref())
+    }
+
+    /// The driver of a slave, `None` if it has none or one of another type.
+    pub fn get_mut<D: 'static>(&mut self, slave: SlavePos) -> Option<&mut D> {
+        self.entry_mut(slave)
+            .and_then(|e| e.driver.as_any_mut().downcast_mut())
+    }
+
+    /// All the drivers of a type, with the positions of their slaves.
+    pub fn all<D: 'static>(&self) -> impl Iterator<Item = (SlavePos, &D)> {
+        self.drivers.iter().filter_map(|e| {
+            e.driver
+                .as_any()
+                .downcast_ref()
+                .map(|driver| (e.slave, driver))
+        })
+    }
+
+    /// The channels of a slave, `None` if it has no driver.
+    pub fn channels(&self, slave: SlavePos) -> Option<Channels<'_>> {
+        self.entry(slave).map(|e| e.driver.channels())
+    }
+
+    /// The type name of the driver of a slave, for diagnostics.
+    pub fn driver_name(&self, slave: SlavePos) -> Option<&'static str> {
+        self.entry(slave).map(|e| e.driver.type_name())
+    }
+
+    /// Process the drivers of the slaves in `domain` with its data.
+    pub fn process(&mut self, domain: DomainIdx, data: &mut [u8]) {
+        for entry in &mut self.drivers {
+            if entry.domain == domain {
+                entry.driver.process(data);
+            }
+        }
+    }
+
+    /// Keep the commanded outputs of a slave when it comes back on the bus,
+    /// instead of commanding their safe state. Ignored for slaves without a
+    /// driver.
+    pub fn restore_outputs(&mut self, slave: SlavePos, restore: bool) {
+        if let Some(entry) = self.entry_mut(slave) {
+            entry.restore_outputs = restore;
+        }
+    }
+
+    /// The slave is on the bus, as of the last
+    /// [`check_presence`](Self::check_presence).
+    pub fn is_online(&self, slave: SlavePos) -> bool {
+        self.entry(slave).map_or(false, |e| e.online)
+    }
+
+    /// Check that the slaves with a driver are on the bus, and restore the
+    /// ones coming back, e.g. after a terminal was replaced under power.
+    ///
+    /// A slave is on the bus if a slave with its identity is at its
+    /// position, out of INIT. On its return, the driver
+    /// [restores](SlaveDriver::restore) its settings, and commands the
+    /// [safe state](SlaveDriver::shutdown) of the outputs unless
+    /// [`restore_outputs`](Self::restore_outputs) is set. If restoring
+    /// fails, the slave stays offline and is restored by the next call.
+    ///
+    /// This scans the bus, so call it every few hundred cycles rather than
+    /// in every cycle. Returns the slaves whose presence changed.
+    pub fn check_presence(&mut self, backend: &mut dyn Backend) -> Result<Vec<(SlavePos, bool)>> {
+        let count = backend.slave_count()?;
+        let mut changes = vec![];
+        for entry in &mut self.drivers {
+            let online = usize::from(u16::from(entry.slave)) < count && {
+                let info = backend.slave_info(entry.slave)?;
+                info.id == entry.id && !matches!(info.al_state, AlState::Init | AlState::Boot)
+            };
+            if online == entry.online {
+                continue;
+            }
+            if online {
+                log::info!("Restoring slave {}", u16::from(entry.slave));
+                entry.driver.restore(backend, entry.slave)?;
+                if !entry.restore_outputs {
+                    entry.driver.shutdown();
+                }
+            } else {
+                log::warn!("Slave {} left the bus", u16::from(entry.slave));
+            }
+            entry.online = online;
+            changes.push((entry.slave, online));
+        }
+        Ok(changes)
+    }
+}
+
+impl fmt::Debug for Devices {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.drivers.iter().map(|e| (e.slave, e.driver.type_name())))
+            .finish()
+    }
+}
+
+#[test]
+fn test_device_registry() {
+    use super::ChannelValue;
+    use crate::backend::{SimMaster, SimSlave};
+
+    let pdo = |idx: u16, entry: u16, bit_len| {
+        let mut pdo = PdoCfg::new(PdoIdx::from(idx));
+        pdo.entries = smallvec::smallvec![PdoEntryInfo {
+            entry_idx: PdoEntryIdx::new(entry, 1),
+            bit_len,
+            name: String::new(),
+            pos: PdoEntryPos::from(0),
+        }];
+        pdo
+    };
+    let el1002 = SimSlave::new("EL1002", SlaveId::new(2, 0x03ea_3052))
+        .tx_pdo(pdo(0x1A00, 0x6000, 1))
+        .tx_pdo(pdo(0x1A01, 0x6010, 1))
+        .rev(SlaveRev::new(0x0010_0000, 0));
+    let el2004 =
+        SimSlave::new("EL2004", SlaveId::new(2, 0x07d4_3052)).rx_pdo(pdo(0x1600, 0x7000, 4));
+    let ek1100 = SimSlave::new("EK1100", SlaveId::new(2, 0x044c_2c52));
+    let mut master = SimMaster::new(vec![ek1100, el1002, el2004]);
+    master.activate().unwrap();
+
+    let mut devices = DeviceRegistry::with_builtin().bind(&mut master).unwrap();
+    assert_eq!(devices.len(), 2);
+    let (inputs, outputs) = (SlavePos::from(1), SlavePos::from(2));
+    assert_eq!(devices.slaves().collect::<Vec<_>>(), [inputs, outputs]);
+    assert!(devices.get::<DigitalOutputs>(inputs).is_none());
+    assert_eq!(devices.all::<DigitalInputs>().count(), 1);
+
+    let domain = DomainIdx::from(0);
+    master.slave_mut(inputs).unwrap().inputs_mut()[0] = 0b10;
+    master.send().unwrap();
+    master.receive().unwrap();
+    devices
+        .get_mut::<DigitalOutputs>(outputs)
+        .unwrap()
+        .set(3, true);
+    devices.process(domain, master.domain_data(domain).unwrap());
+    assert!(devices.get::<DigitalInputs>(inputs).unwrap().channel(1));
+    master.send().unwrap();
+    assert_eq!(master.slave_mut(outputs).unwrap().outputs()[0], 0b1000);
+    let channels: Vec<_> = devices.channels(outputs).unwrap().collect();
+    assert_eq!(channels.len(), 4);
+    assert_eq!(channels[3].name, "Output 4");
+    assert_eq!(channels[3].value, ChannelValue::Bool(true));
+
+    // the terminal is replaced: its outputs come back in their safe state
+    let driver = devices.get_mut::<DigitalOutputs>(outputs).unwrap();
+    *driver = driver.clone().with_safe_state(0b0001);
+    master.request_state(outputs, AlState::Init).unwrap();
+    let changes = devices.check_presence(&mut master).unwrap();
+    assert_eq!(changes, [(outputs, false)]);
+    assert!(!devices.is_online(outputs) && devices.is_online(inputs));
+    master.request_state(outputs, AlState::PreOp).unwrap();
+    let changes = devices.check_presence(&mut master).unwrap();
+    assert_eq!(changes, [(outputs, true)]);
+    assert_eq!(
+        devices.get::<DigitalOutputs>(outputs).unwrap().bits(),
+        0b0001
+    );
+
+    devices.restore_outputs(outputs, true);
+    devices
+        .get_mut::<DigitalOutputs>(outputs)
+        .unwrap()
+        .set(3, true);
+    master.request_state(outputs, AlState::Init).unwrap();
+    devices.check_presence(&mut master).unwrap();
+    master.request_state(outputs, AlState::PreOp).unwrap();
+    devices.check_presence(&mut master).unwrap();
+    assert_eq!(
+        devices.get::<DigitalOutputs>(outputs).unwrap().bits(),
+        0b1001
+    );
+
+    let devices = DeviceRegistry::new()
+        .register_revisions::<DigitalInputs>(0x0011_0000..=u32::MAX, ())
+        .register::<DigitalOutputs>(())
+        .bind(&mut master)
+        .unwrap();
+    assert_eq!(devices.slaves().collect::<Vec<_>>(), [outputs]);
+}
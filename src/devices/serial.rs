@@ -0,0 +1,437 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{beckhoff_terminal, image_too_small, unsupported, SlaveDriver};
+#[cfg(target_os = "linux")]
+use crate::SlaveConfig;
+use crate::{
+    backend::{Backend, SlaveImage},
+    types::*,
+};
+#[cfg(target_os = "linux")]
+use std::io;
+use std::{collections::VecDeque, mem};
+
+/// Supported terminals and their number of channels.
+const TERMINALS: &[(u32, usize)] = &[(6001, 1), (6002, 2), (6021, 1), (6022, 2)];
+
+/// Length of the control or status word and data of a channel in the
+/// default mapping.
+const REGION: usize = 24;
+
+const TRANSMIT: u8 = 0x01;
+const RECEIVE: u8 = 0x02;
+const INIT: u8 = 0x04;
+const BUFFER_FULL: u8 = 0x08;
+const PARITY_ERROR: u8 = 0x10;
+const FRAMING_ERROR: u8 = 0x20;
+const OVERRUN_ERROR: u8 = 0x40;
+
+/// How the received byte stream is cut into frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// No framing: the bytes are handed over as they arrive.
+    Stream,
+    /// Frames end with this byte, which is part of the frame.
+    Delimiter(u8),
+    /// Frames have this length, which must not be 0.
+    Fixed(usize),
+    /// The byte at `offset` gives the length of the payload following it,
+    /// and `trailer` bytes, e.g. a checksum, end the frame.
+    LengthPrefixed { offset: usize, trailer: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Init {
+    Request,
+    Release,
+    Done,
+}
+
+/// The handshake of gateways exchanging a byte stream through the cyclic
+/// data, with the reassembly of the received frames.
+///
+/// The process data of such a gateway has a region in each direction: a
+/// control or status byte, a length byte, and the data. The toggle bits of
+/// the control and status bytes acknowledge each chunk of data, so a chunk
+/// spans as many cycles as the gateway needs:
+///
+/// | bit | control                | status               |
+/// |-----|------------------------|----------------------|
+/// | 0   | transmit request       | transmit accepted    |
+/// | 1   | receive accepted       | receive request      |
+/// | 2   | init request           | init accepted        |
+///
+/// Bits 3 to 7 of the status are specific to the gateway.
+#[derive(Debug, Clone)]
+pub struct StreamLink {
+    framing: Framing,
+    init: Init,
+    transmit: bool,
+    receive: bool,
+    status: u8,
+    tx: VecDeque<u8>,
+    rx: Vec<u8>,
+    frames: VecDeque<Vec<u8>>,
+}
+
+impl StreamLink {
+    /// A link which starts with the init handshake.
+    pub fn new(framing: Framing) -> Self {
+        Self {
+            framing,
+            init: Init::Request,
+            transmit: false,
+            receive: false,
+            status: 0,
+            tx: VecDeque::new(),
+            rx: vec![],
+            frames: VecDeque::new(),
+        }
+    }
+
+    /// Restart the init handshake, dropping the data in transit.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.framing);
+    }
+
+    /// The init handshake is done and data is exchanged.
+    pub fn is_ready(&self) -> bool {
+        self.init == Init::Done
+    }
+
+    /// The status byte as of the last [`exchange`](Self::exchange).
+    pub const fn status(&self) -> u8 {
+        self.status
+    }
+
+    /// Queue bytes for transmission.
+    pub fn write(&mut self, bytes: &[u8]) {
+        self.tx.extend(bytes);
+    }
+
+    /// Number of queued bytes not handed to the gateway yet.
+    pub fn pending(&self) -> usize {
+        self.tx.len()
+    }
+
+    /// The next received frame.
+    pub fn read(&mut self) -> Option<Vec<u8>> {
+        self.frames.pop_front()
+    }
+
+    /// Received bytes which do not form a complete frame yet.
+    pub fn partial(&self) -> &[u8] {
+        &self.rx
+    }
+
+    /// Take a chunk from the input region, and write the next one into the
+    /// output region. Both regions start with the control or status byte.
+    pub fn exchange(&mut self, outputs: &mut [u8], inputs: &[u8]) {
+        self.status = inputs[0];
+        match self.init {
+            Init::Request => {
+                self.transmit = false;
+                self.receive = false;
+                if self.status & INIT != 0 {
+                    self.init = Init::Release;
+                }
+            }
+            Init::Release => {
+                if self.status & INIT == 0 {
+                    self.init = Init::Done;
+                }
+            }
+            Init::Done => {
+                if (self.status & RECEIVE != 0) != self.receive {
+                    let len = usize::from(inputs[1]).min(inputs.len() - 2);
+                    self.rx.extend_from_slice(&inputs[2..2 + len]);
+                    self.receive = !self.receive;
+                    self.reassemble();
+                }
+                if (self.status & TRANSMIT != 0) == self.transmit && !self.tx.is_empty() {
+                    let len = self.tx.len().min(outputs.len() - 2);
+                    for (byte, data) in outputs[2..].iter_mut().zip(self.tx.drain(..len)) {
+                        *byte = data;
+                    }
+                    outputs[1] = len as u8;
+                    self.transmit = !self.transmit;
+                }
+            }
+        }
+        let init = if self.init == Init::Request { INIT } else { 0 };
+        outputs[0] = u8::from(self.transmit) | u8::from(self.receive) << 1 | init;
+    }
+
+    fn reassemble(&mut self) {
+        loop {
+            let len = match self.framing {
+                Framing::Stream => (!self.rx.is_empty()).then(|| self.rx.len()),
+                Framing::Delimiter(end) => self.rx.iter().position(|b| *b == end).map(|i| i + 1),
+                Framing::Fixed(len) => (len > 0 && self.rx.len() >= len).then(|| len),
+                Framing::LengthPrefixed { offset, trailer } => self
+                    .rx
+                    .get(offset)
+                    .map(|len| offset + 1 + usize::from(*len) + trailer)
+                    .filter(|len| *len <= self.rx.len()),
+            };
+            match len {
+                Some(len) if len == self.rx.len() => self.frames.push_back(mem::take(&mut self.rx)),
+                Some(len) => self.frames.push_back(self.rx.drain(..len).collect()),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Errors reported by a serial terminal in its status.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SerialErrors {
+    /// The receive buffer of the terminal is full, data is lost.
+    pub buffer_full: bool,
+    pub parity: bool,
+    pub framing: bool,
+    pub overrun: bool,
+}
+
+impl SerialErrors {
+    pub const fn any(&self) -> bool {
+        self.buffer_full || self.parity || self.framing || self.overrun
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Channel {
+    link: StreamLink,
+    /// Start of the control and status regions in the domain.
+    outputs: usize,
+    inputs: usize,
+}
+
+/// Beckhoff EL6001, EL6002 (RS232) and EL6021, EL6022 (RS422/RS485) serial
+/// interface terminals.
+///
+/// Channel `n`, counted from 0, is mapped from the objects
+/// 0x7000 + 0x10 * `n` and 0x6000 + 0x10 * `n`, with 22 data bytes in each
+/// direction. The baud rate and data frame are set in the objects
+/// 0x8000 + 0x10 * `n`, e.g. with startup SDOs. Protocols of the serial
+/// line are tunneled by writing their frames with [`send`](Self::send) and
+/// reading the frames reassembled according to the [`Framing`] with
+/// [`recv`](Self::recv).
+#[derive(Debug, Clone)]
+pub struct SerialTerminal {
+    channels: Vec<Channel>,
+}
+
+impl SerialTerminal {
+    /// Number of channels of a supported terminal, `None` for other slaves.
+    pub fn detect(id: SlaveId) -> Option<usize> {
+        let number = beckhoff_terminal(id)?;
+        TERMINALS
+            .iter()
+            .find(|(n, _)| *n == number)
+            .map(|(_, channels)| *channels)
+    }
+
+    /// Register the control and status words and data of the channels in
+    /// `domain`. They must be mapped one after the other, as by default.
+    #[cfg(target_os = "linux")]
+    pub fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        framing: Framing,
+    ) -> Result<Self> {
+        let count = Self::detect(id).ok_or_else(|| unsupported(id))?;
+        let mut channels = vec![];
+        for n in 0..count as u16 {
+            let mut region = |object| -> Result<usize> {
+                let start = config.register_pdo_entry(PdoEntryIdx::new(object, 0x01), domain)?;
+                let data = config.register_pdo_entry(PdoEntryIdx::new(object, 0x11), domain)?;
+                if start.bit != 0 || data.byte != start.byte + 2 {
+                    return Err(Error::Io(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "the data of {:#06x} does not follow its control word",
+                            object
+                        ),
+                    )));
+                }
+                Ok(start.byte)
+            };
+            let outputs = region(0x7000 + 0x10 * n)?;
+            let inputs = region(0x6000 + 0x10 * n)?;
+            channels.push(Channel::new(framing, outputs, inputs));
+        }
+        Ok(Self { channels })
+    }
+
+    /// Locate the channels in the process data of a slave with the default
+    /// mapping.
+    pub fn from_image(id: SlaveId, image: &SlaveImage, framing: Framing) -> Result<Self> {
+        let count = Self::detect(id).ok_or_else(|| unsupported(id))?;
+        let outputs = image.outputs.clone().ok_or_else(image_too_small)?;
+        let inputs = image.inputs.clone().ok_or_else(image_too_small)?;
+        if outputs.len() < count * REGION || inputs.len() < count * REGION {
+            return Err(image_too_small());
+        }
+        let channels = (0..count)
+            .map(|n| {
+                let offset = n * REGION;
+                Channel::new(framing, outputs.start + offset, inputs.start + offset)
+            })
+            .collect();
+        Ok(Self { channels })
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Exchange the next chunks of data with the domain data.
+    pub fn process(&mut self, data: &mut [u8]) {
+        for channel in &mut self.channels {
+            let mut inputs = [0; REGION];
+            inputs.copy_from_slice(&data[channel.inputs..channel.inputs + REGION]);
+            let outputs = &mut data[channel.outputs..channel.outputs + REGION];
+            channel.link.exchange(outputs, &inputs);
+        }
+    }
+
+    /// Queue bytes for transmission on channel `n`.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no channel `n`.
+    pub fn send(&mut self, n: usize, bytes: &[u8]) {
+        self.channels[n].link.write(bytes);
+    }
+
+    /// The next frame received on channel `n`.
+    pub fn recv(&mut self, n: usize) -> Option<Vec<u8>> {
+        self.channels[n].link.read()
+    }
+
+    /// Errors of channel `n` as of the last [`process`](Self::process).
+    pub fn errors(&self, n: usize) -> SerialErrors {
+        let status = self.channels[n].link.status();
+        SerialErrors {
+            buffer_full: status & BUFFER_FULL != 0,
+            parity: status & PARITY_ERROR != 0,
+            framing: status & FRAMING_ERROR != 0,
+            overrun: status & OVERRUN_ERROR != 0,
+        }
+    }
+
+    /// The link of channel `n`, e.g. to [`reset`](StreamLink::reset) it.
+    pub fn link_mut(&mut self, n: usize) -> &mut StreamLink {
+        &mut self.channels[n].link
+    }
+
+    pub fn link(&self, n: usize) -> &StreamLink {
+        &self.channels[n].link
+    }
+}
+
+impl Channel {
+    fn new(framing: Framing, outputs: usize, inputs: usize) -> Self {
+        Self {
+            link: StreamLink::new(framing),
+            outputs,
+            inputs,
+        }
+    }
+}
+
+impl SlaveDriver for SerialTerminal {
+    type Settings = Framing;
+
+    fn matches(id: SlaveId) -> bool {
+        Self::detect(id).is_some()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        settings: &Framing,
+    ) -> Result<Self> {
+        Self::configure(config, id, domain, *settings)
+    }
+
+    fn bind(id: SlaveId, image: &SlaveImage, settings: &Framing) -> Result<Self> {
+        Self::from_image(id, image, *settings)
+    }
+
+    fn process(&mut self, data: &mut [u8]) {
+        self.process(data)
+    }
+
+    fn restore(
+        &mut self,
+        _backend: &mut dyn Backend,
+        _slave: SlavePos,
+        _settings: &Framing,
+    ) -> Result<()> {
+        for channel in &mut self.channels {
+            channel.link.reset();
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_serial_terminal() {
+    let el6021 = SlaveId::new(2, 0x1785_3052);
+    assert_eq!(SerialTerminal::detect(el6021), Some(1));
+    let image = SlaveImage {
+        domain: DomainIdx::from(0),
+        outputs: Some(0..24),
+        inputs: Some(24..48),
+    };
+    let mut serial = SerialTerminal::from_image(el6021, &image, Framing::Delimiter(b'\n')).unwrap();
+    let mut data = [0_u8; 48];
+
+    // init handshake
+    serial.process(&mut data);
+    assert_eq!(data[0], INIT);
+    data[24] = INIT;
+    serial.process(&mut data);
+    assert_eq!(data[0], 0);
+    data[24] = 0;
+    serial.process(&mut data);
+    assert!(serial.link(0).is_ready());
+
+    // a frame longer than a chunk takes two
+    serial.send(0, b"0123456789abcdefghijklmn\n");
+    serial.process(&mut data);
+    assert_eq!(data[..3], [TRANSMIT, 22, b'0']);
+    serial.process(&mut data);
+    assert_eq!(serial.link(0).pending(), 3);
+    data[24] = TRANSMIT;
+    serial.process(&mut data);
+    assert_eq!(data[..5], [0, 3, b'm', b'n', b'\n']);
+
+    data[24..30].copy_from_slice(&[RECEIVE | PARITY_ERROR, 4, b'o', b'k', b'\n', b'e']);
+    serial.process(&mut data);
+    assert_eq!(data[0], RECEIVE);
+    assert_eq!(serial.recv(0).unwrap(), b"ok\n");
+    assert!(serial.recv(0).is_none());
+    assert!(serial.errors(0).parity && serial.errors(0).any());
+    // the same toggle is not a new chunk
+    serial.process(&mut data);
+    assert_eq!(serial.link(0).partial(), b"e");
+    data[24..28].copy_from_slice(&[0, 2, b'r', b'\n']);
+    serial.process(&mut data);
+    assert_eq!(serial.recv(0).unwrap(), b"er\n");
+
+    let mut link = StreamLink::new(Framing::LengthPrefixed {
+        offset: 1,
+        trailer: 1,
+    });
+    link.rx = vec![0xAA, 2, 1, 2, 0xCC, 0xAA, 0];
+    link.reassemble();
+    assert_eq!(link.read().unwrap(), [0xAA, 2, 1, 2, 0xCC]);
+    assert_eq!(link.partial(), [0xAA, 0]);
+}
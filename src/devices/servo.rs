@@ -0,0 +1,198 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use crate::types::*;
+#[cfg(target_os = "linux")]
+use crate::{devices::unsupported, SlaveConfig};
+use std::{io, time::Duration};
+
+/// A PDO entry: index, subindex and bit length.
+type Entry = (u16, u8, u8);
+
+/// Controlword and target position, for cyclic synchronous position.
+const CSP_OUTPUTS: &[Entry] = &[(0x6040, 0, 16), (0x607A, 0, 32)];
+
+/// Statusword, actual position and velocity, and error code.
+const CSP_INPUTS: &[Entry] = &[
+    (0x6041, 0, 16),
+    (0x6064, 0, 32),
+    (0x606C, 0, 32),
+    (0x603F, 0, 16),
+];
+
+/// Recommended configuration of a family of CiA 402 servo drives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServoPreset {
+    pub vendor: &'static str,
+    pub model: &'static str,
+    pub vendor_id: u32,
+    /// Product codes of the family, any product of the vendor if empty.
+    pub products: &'static [u32],
+    /// RxPDO and its entries, assigned to sync manager 2.
+    pub outputs: (u16, &'static [Entry]),
+    /// TxPDO and its entries, assigned to sync manager 3.
+    pub inputs: (u16, &'static [Entry]),
+    /// AssignActivate word of the DC configuration.
+    pub assign_activate: u16,
+    /// Shortest cycle time the drive synchronizes to.
+    pub min_cycle: Duration,
+}
+
+/// The presets, in the order they are matched.
+pub const SERVO_PRESETS: &[ServoPreset] = &[
+    ServoPreset {
+        vendor: "Delta",
+        model: "ASDA-A2-E",
+        vendor_id: 0x0000_01DD,
+        products: &[0x1030_5070],
+        outputs: (0x1600, CSP_OUTPUTS),
+        inputs: (0x1A00, CSP_INPUTS),
+        assign_activate: 0x0300,
+        min_cycle: Duration::from_millis(1),
+    },
+    ServoPreset {
+        vendor: "Yaskawa",
+        model: "Sigma-5/Sigma-7",
+        vendor_id: 0x0000_0539,
+        products: &[0x0220_0001, 0x0220_0301],
+        outputs: (0x1600, CSP_OUTPUTS),
+        inputs: (0x1A00, CSP_INPUTS),
+        assign_activate: 0x0300,
+        min_cycle: Duration::from_micros(125),
+    },
+    ServoPreset {
+        vendor: "Panasonic",
+        model: "MINAS A5B/A6B",
+        vendor_id: 0x0000_066F,
+        products: &[],
+        outputs: (0x1600, CSP_OUTPUTS),
+        inputs: (0x1A00, CSP_INPUTS),
+        assign_activate: 0x0300,
+        min_cycle: Duration::from_micros(125),
+    },
+    ServoPreset {
+        vendor: "Kollmorgen",
+        model: "AKD",
+        vendor_id: 0x0000_006A,
+        products: &[0x0041_4B44],
+        outputs: (0x1600, CSP_OUTPUTS),
+        inputs: (0x1A00, CSP_INPUTS),
+        assign_activate: 0x0300,
+        min_cycle: Duration::from_micros(250),
+    },
+];
+
+impl ServoPreset {
+    /// The preset for a slave, if its drive family is known.
+    pub fn find(id: SlaveId) -> Option<&'static ServoPreset> {
+        SERVO_PRESETS.iter().find(|p| p.matches(id))
+    }
+
+    pub fn matches(&self, id: SlaveId) -> bool {
+        id.vendor_id == self.vendor_id
+            && (self.products.is_empty() || self.products.contains(&id.product_code))
+    }
+
+    /// The RxPDO with its mapping.
+    pub fn output_pdo(&self) -> PdoCfg {
+        pdo(self.outputs)
+    }
+
+    /// The TxPDO with its mapping.
+    pub fn input_pdo(&self) -> PdoCfg {
+        pdo(self.inputs)
+    }
+
+    /// Interpolation time period of the drive, object 0x60C2: a value and
+    /// a power of ten exponent giving the cycle time in seconds.
+    pub fn interpolation_period(&self, cycle: Duration) -> Result<(u8, i8)> {
+        if cycle < self.min_cycle {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} {} drives need a cycle of at least {:?}, not {:?}",
+                    self.vendor, self.model, self.min_cycle, cycle
+                ),
+            )));
+        }
+        interpolation_period(cycle)
+    }
+
+    /// Map the PDOs, set cyclic synchronous position mode and the
+    /// interpolation period with startup SDOs, and synchronize the drive
+    /// to SYNC0 with period `cycle`.
+    #[cfg(target_os = "linux")]
+    pub fn configure(&self, config: &mut SlaveConfig, cycle: Duration) -> Result<()> {
+        let (period, exponent) = self.interpolation_period(cycle)?;
+        config.config_sm_pdos(SmCfg::output(2.into()), &[self.output_pdo()])?;
+        config.config_sm_pdos(SmCfg::input(3.into()), &[self.input_pdo()])?;
+        // mode of operation: cyclic synchronous position
+        config.add_sdo(SdoIdx::new(0x6060, 0), &8_i8)?;
+        config.add_sdo(SdoIdx::new(0x60C2, 1), &period)?;
+        config.add_sdo(SdoIdx::new(0x60C2, 2), &exponent)?;
+        config.config_dc(self.assign_activate, cycle.as_nanos() as u32, 0, 0, 0)
+    }
+}
+
+/// Configure a servo drive with the preset of its family.
+#[cfg(target_os = "linux")]
+pub fn configure_servo(config: &mut SlaveConfig, id: SlaveId, cycle: Duration) -> Result<()> {
+    ServoPreset::find(id)
+        .ok_or_else(|| unsupported(id))?
+        .configure(config, cycle)
+}
+
+/// Find the coarsest exponent from milliseconds to microseconds that
+/// represents `cycle` exactly in a byte.
+fn interpolation_period(cycle: Duration) -> Result<(u8, i8)> {
+    let micros = cycle.as_nanos() / 1000;
+    if cycle.as_nanos() % 1000 == 0 {
+        for (exponent, unit) in [(-3, 1000), (-4, 100), (-5, 10), (-6, 1)] {
+            if micros % unit == 0 && micros / unit <= 0xFF {
+                return Ok(((micros / unit) as u8, exponent));
+            }
+        }
+    }
+    Err(Error::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("no interpolation period for a cycle of {:?}", cycle),
+    )))
+}
+
+fn pdo((idx, entries): (u16, &[Entry])) -> PdoCfg {
+    let mut pdo = PdoCfg::new(PdoIdx::from(idx));
+    pdo.entries = entries
+        .iter()
+        .enumerate()
+        .map(|(pos, &(idx, sub, bit_len))| PdoEntryInfo {
+            entry_idx: PdoEntryIdx::new(idx, sub),
+            bit_len,
+            name: String::new(),
+            pos: PdoEntryPos::from(pos as u8),
+        })
+        .collect();
+    pdo
+}
+
+#[test]
+fn test_servo_presets() {
+    let akd = SlaveId::new(0x6A, 0x0041_4B44);
+    let a6b = SlaveId::new(0x66F, 0x6038_0004);
+    assert_eq!(ServoPreset::find(akd).unwrap().vendor, "Kollmorgen");
+    assert_eq!(ServoPreset::find(a6b).unwrap().model, "MINAS A5B/A6B");
+    assert!(ServoPreset::find(SlaveId::new(0x6A, 1)).is_none());
+
+    let preset = ServoPreset::find(akd).unwrap();
+    let inputs = preset.input_pdo();
+    assert_eq!(u16::from(inputs.idx), 0x1A00);
+    assert_eq!(inputs.entries.len(), 4);
+    assert_eq!(inputs.entries[1].entry_idx, PdoEntryIdx::new(0x6064, 0));
+
+    let ms = Duration::from_millis;
+    let us = Duration::from_micros;
+    assert_eq!(preset.interpolation_period(ms(1)).unwrap(), (1, -3));
+    assert_eq!(preset.interpolation_period(us(250)).unwrap(), (25, -5));
+    assert!(preset.interpolation_period(ms(300)).is_err());
+    assert!(preset.interpolation_period(us(125)).is_err());
+    assert!(interpolation_period(Duration::from_nanos(1500)).is_err());
+}
@@ -0,0 +1,361 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{
+    beckhoff_terminal, image_too_small, unsupported, Channels, IoChannel, SlaveDriver, Unit,
+};
+#[cfg(target_os = "linux")]
+use crate::SlaveConfig;
+use crate::{
+    backend::{Backend, SlaveImage},
+    field::{Field, ScaledField},
+    types::*,
+};
+use std::io;
+
+/// Supported terminals: number, channels, and whether they are for RTDs
+/// rather than thermocouples.
+const TERMINALS: &[(u32, usize, bool)] = &[
+    (3201, 1, true),
+    (3202, 2, true),
+    (3204, 4, true),
+    (3208, 8, true),
+    (3214, 4, true),
+    (3311, 1, false),
+    (3312, 2, false),
+    (3314, 4, false),
+    (3318, 8, false),
+];
+
+/// Bytes of the standard PDO of a channel: status word and value.
+const CHANNEL_BYTES: usize = 4;
+
+/// Subindex of the sensor type in the settings object 0x80n0.
+const SENSOR_TYPE: u8 = 0x19;
+
+/// Temperature sensors, resistance thermometers or thermocouples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sensor {
+    Pt100,
+    Ni100,
+    Pt1000,
+    Pt500,
+    Pt200,
+    Ni1000,
+    TypeK,
+    TypeJ,
+    TypeL,
+    TypeE,
+    TypeT,
+    TypeN,
+    TypeU,
+    TypeB,
+    TypeR,
+    TypeS,
+    TypeC,
+}
+
+impl Sensor {
+    /// Value of the sensor type setting, if the sensor is of the kind of
+    /// the terminal.
+    fn code(self, rtd: bool) -> Option<u16> {
+        use Sensor::*;
+        let (code, is_rtd) = match self {
+            Pt100 => (0, true),
+            Ni100 => (1, true),
+            Pt1000 => (2, true),
+            Pt500 => (3, true),
+            Pt200 => (4, true),
+            Ni1000 => (5, true),
+            TypeK => (0, false),
+            TypeJ => (1, false),
+            TypeL => (2, false),
+            TypeE => (3, false),
+            TypeT => (4, false),
+            TypeN => (5, false),
+            TypeU => (6, false),
+            TypeB => (7, false),
+            TypeR => (8, false),
+            TypeS => (9, false),
+            TypeC => (10, false),
+        };
+        (is_rtd == rtd).then(|| code)
+    }
+}
+
+/// Temperature and status of a channel.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Temperature {
+    /// The temperature in °C.
+    pub celsius: f64,
+    /// The temperature in 0.1 °C.
+    pub raw: i16,
+    pub underrange: bool,
+    pub overrange: bool,
+    /// The sensor is not connected or its wire is broken.
+    pub open_circuit: bool,
+}
+
+impl Temperature {
+    pub const fn is_valid(&self) -> bool {
+        !(self.underrange || self.overrange || self.open_circuit)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Channel {
+    underrange: Field<bool>,
+    overrange: Field<bool>,
+    error: Field<bool>,
+    value: ScaledField<i16>,
+}
+
+/// Beckhoff EL32xx RTD and EL331x thermocouple input terminals.
+///
+/// Channel `i` is mapped from the object 0x6000 + 0x10 * `i` like for the
+/// other analog inputs, with the temperature in 0.1 °C in subindex 0x11.
+/// The sensor type is set in the object 0x80n0:19.
+#[derive(Debug, Clone)]
+pub struct TemperatureInputs {
+    rtd: bool,
+    channels: Vec<Channel>,
+    values: Vec<Temperature>,
+}
+
+impl TemperatureInputs {
+    /// Number of channels of a supported terminal, `None` for other slaves.
+    pub fn detect(id: SlaveId) -> Option<usize> {
+        terminal(id).map(|t| t.1)
+    }
+
+    /// Select the sensors of the channels with startup SDOs, and register
+    /// the channels in `domain`.
+    ///
+    /// Channels beyond `sensors` keep the sensor type of the terminal.
+    #[cfg(target_os = "linux")]
+    pub fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        sensors: &[Sensor],
+    ) -> Result<Self> {
+        let (_, count, rtd) = terminal(id).ok_or_else(|| unsupported(id))?;
+        for (i, code) in sensor_codes(count, rtd, sensors)?.into_iter().enumerate() {
+            config.add_sdo(SdoIdx::new(0x8000 + 0x10 * i as u16, SENSOR_TYPE), &code)?;
+        }
+        let mut channels = vec![];
+        for i in 0..count as u16 {
+            let idx = 0x6000 + 0x10 * i;
+            let value = config.register_field(PdoEntryIdx::new(idx, 0x11), domain)?;
+            channels.push(Channel {
+                underrange: config.register_field(PdoEntryIdx::new(idx, 0x01), domain)?,
+                overrange: config.register_field(PdoEntryIdx::new(idx, 0x02), domain)?,
+                error: config.register_field(PdoEntryIdx::new(idx, 0x07), domain)?,
+                value: celsius(value),
+            });
+        }
+        Ok(Self::new(rtd, channels))
+    }
+
+    /// Locate the channels in the inputs of a slave with the default
+    /// mapping, the standard PDO of each channel one after the other.
+    pub fn from_image(id: SlaveId, image: &SlaveImage) -> Result<Self> {
+        let (_, count, rtd) = terminal(id).ok_or_else(|| unsupported(id))?;
+        let inputs = image.inputs.clone().ok_or_else(image_too_small)?;
+        if inputs.len() < count * CHANNEL_BYTES {
+            return Err(image_too_small());
+        }
+        let channels = (0..count)
+            .map(|i| {
+                let byte = inputs.start + i * CHANNEL_BYTES;
+                let bit = |bit| Field::new(image.domain, Offset { byte, bit });
+                let value = Field::new(
+                    image.domain,
+                    Offset {
+                        byte: byte + 2,
+                        bit: 0,
+                    },
+                );
+                Channel {
+                    underrange: bit(0),
+                    overrange: bit(1),
+                    error: bit(6),
+                    value: celsius(value),
+                }
+            })
+            .collect();
+        Ok(Self::new(rtd, channels))
+    }
+
+    fn new(rtd: bool, channels: Vec<Channel>) -> Self {
+        Self {
+            rtd,
+            values: vec![Temperature::default(); channels.len()],
+            channels,
+        }
+    }
+
+    /// Select the sensors of the channels with SDOs, e.g. in PreOp.
+    pub fn write_sensors<B: Backend + ?Sized>(
+        &self,
+        backend: &mut B,
+        slave: SlavePos,
+        sensors: &[Sensor],
+    ) -> Result<()> {
+        let codes = sensor_codes(self.channels.len(), self.rtd, sensors)?;
+        for (i, code) in codes.into_iter().enumerate() {
+            let idx = SdoIdx::new(0x8000 + 0x10 * i as u16, SENSOR_TYPE);
+            backend.sdo_download(slave, idx, false, &code.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Returns `true` for RTD terminals, `false` for thermocouple ones.
+    pub const fn is_rtd(&self) -> bool {
+        self.rtd
+    }
+
+    /// Read the temperatures from the domain data.
+    pub fn process(&mut self, data: &[u8]) {
+        for (channel, value) in self.channels.iter().zip(&mut self.values) {
+            *value = Temperature {
+                celsius: channel.value.get(data),
+                raw: channel.value.field.get(data),
+                underrange: channel.underrange.get(data),
+                overrange: channel.overrange.get(data),
+                open_circuit: channel.error.get(data),
+            };
+        }
+    }
+
+    /// Temperature of channel `i`, counted from 0, as of the last
+    /// [`process`](Self::process).
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no channel `i`.
+    pub fn channel(&self, i: usize) -> Temperature {
+        self.values[i]
+    }
+}
+
+impl SlaveDriver for TemperatureInputs {
+    type Settings = Vec<Sensor>;
+
+    fn matches(id: SlaveId) -> bool {
+        Self::detect(id).is_some()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        settings: &Vec<Sensor>,
+    ) -> Result<Self> {
+        Self::configure(config, id, domain, settings)
+    }
+
+    fn bind(id: SlaveId, image: &SlaveImage, _settings: &Vec<Sensor>) -> Result<Self> {
+        Self::from_image(id, image)
+    }
+
+    fn process(&mut self, data: &mut [u8]) {
+        self.process(data)
+    }
+
+    fn restore(
+        &mut self,
+        backend: &mut dyn Backend,
+        slave: SlavePos,
+        settings: &Vec<Sensor>,
+    ) -> Result<()> {
+        self.write_sensors(backend, slave, settings)
+    }
+
+    fn channels(&self) -> Channels<'_> {
+        Box::new(self.values.iter().enumerate().map(|(i, value)| {
+            IoChannel::input(format!("Channel {}", i + 1), value.celsius)
+                .with_unit(Unit::Celsius)
+                .with_valid(value.is_valid())
+        }))
+    }
+}
+
+fn terminal(id: SlaveId) -> Option<(u32, usize, bool)> {
+    let number = beckhoff_terminal(id)?;
+    TERMINALS.iter().find(|t| t.0 == number).copied()
+}
+
+fn sensor_codes(count: usize, rtd: bool, sensors: &[Sensor]) -> Result<Vec<u16>> {
+    if sensors.len() > count {
+        return Err(invalid(format!(
+            "the terminal has {} channels, {} sensors given",
+            count,
+            sensors.len()
+        )));
+    }
+    sensors
+        .iter()
+        .map(|s| {
+            s.code(rtd).ok_or_else(|| {
+                let kind = if rtd { "an RTD" } else { "a thermocouple" };
+                invalid(format!("{:?} is not supported by {} terminal", s, kind))
+            })
+        })
+        .collect()
+}
+
+fn celsius(field: Field<i16>) -> ScaledField<i16> {
+    ScaledField::new(field, 0.1, 0.0)
+}
+
+fn invalid(msg: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidInput, msg))
+}
+
+#[test]
+fn test_temperature_inputs() {
+    use crate::backend::{SimMaster, SimSlave};
+
+    let el3202 = SlaveId::new(2, 0x0c82_3052);
+    assert_eq!(TemperatureInputs::detect(el3202), Some(2));
+    let image = SlaveImage {
+        domain: DomainIdx::from(0),
+        outputs: None,
+        inputs: Some(0..8),
+    };
+    let mut inputs = TemperatureInputs::from_image(el3202, &image).unwrap();
+    assert!(inputs.is_rtd());
+    let mut data = [0_u8; 8];
+    data[2..4].copy_from_slice(&(-125_i16).to_le_bytes());
+    data[4] = 0b0100_0010;
+    data[6..8].copy_from_slice(&0x7FFF_i16.to_le_bytes());
+    inputs.process(&data);
+    let t = inputs.channel(0);
+    assert!(t.is_valid() && (t.celsius + 12.5).abs() < 1e-9);
+    let t = inputs.channel(1);
+    assert!(t.open_circuit && t.overrange && !t.is_valid());
+
+    let mut slave = SimSlave::new("EL3202", el3202);
+    for i in 0..2 {
+        slave = slave.object(SdoIdx::new(0x8000 + 0x10 * i, SENSOR_TYPE), &[0; 2]);
+    }
+    let mut master = SimMaster::new(vec![slave]);
+    let pos = SlavePos::from(0);
+    inputs
+        .write_sensors(&mut master, pos, &[Sensor::Pt100, Sensor::Pt1000])
+        .unwrap();
+    let mut buf = [0; 2];
+    master
+        .sdo_upload(pos, SdoIdx::new(0x8010, SENSOR_TYPE), false, &mut buf)
+        .unwrap();
+    assert_eq!(u16::from_le_bytes(buf), 2);
+    assert!(inputs
+        .write_sensors(&mut master, pos, &[Sensor::TypeK])
+        .is_err());
+}
@@ -0,0 +1,265 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{packed_bits, unsupported, Channels, IoChannel, SlaveDriver};
+#[cfg(target_os = "linux")]
+use crate::SlaveConfig;
+use crate::{backend::SlaveImage, field::Field, types::*};
+#[cfg(target_os = "linux")]
+use std::io;
+
+/// Vendor ID of Festo.
+pub const FESTO: u32 = 0x0000_001D;
+
+/// Vendor ID of SMC.
+pub const SMC: u32 = 0x0000_0114;
+
+/// What a solenoid does on [`shutdown`](ValveTerminal::shutdown).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailSafe {
+    Off,
+    On,
+    /// Keep the last state, until the slave leaves OP.
+    Hold,
+}
+
+#[derive(Debug, Clone)]
+struct Valve {
+    name: String,
+    coil: usize,
+    fail_safe: FailSafe,
+}
+
+/// Festo and SMC valve terminals, like the Festo CPX or SMC EX260.
+///
+/// The layout of these terminals depends on their modules, so the driver
+/// is given the number of solenoid coils, which are packed in consecutive
+/// bits from the start of the outputs. Coils are then given names with
+/// [`valve`](Self::valve); a double solenoid valve takes two coils.
+#[derive(Debug, Clone)]
+pub struct ValveTerminal {
+    fields: Vec<Field<bool>>,
+    valves: Vec<Valve>,
+    state: Vec<bool>,
+}
+
+impl ValveTerminal {
+    /// Returns `true` for slaves of the supported vendors.
+    pub fn detect(id: SlaveId) -> bool {
+        id.vendor_id == FESTO || id.vendor_id == SMC
+    }
+
+    /// Register `coils` solenoids from the first entry of the first RxPDO,
+    /// which must map them in consecutive bits, in `domain`.
+    #[cfg(target_os = "linux")]
+    pub fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        coils: usize,
+    ) -> Result<Self> {
+        if !Self::detect(id) {
+            return Err(unsupported(id));
+        }
+        let start = config.register_pdo_entry_by_position(SmIdx::from(2), 0, 0, domain)?;
+        if start.bit != 0 {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the solenoids do not start on a byte",
+            )));
+        }
+        let bytes = (coils + 7) / 8;
+        Ok(Self::new(packed_bits(
+            domain,
+            Some(start.byte..start.byte + bytes),
+            coils,
+        )?))
+    }
+
+    /// Locate `coils` solenoids at the start of the outputs of a slave.
+    pub fn from_image(id: SlaveId, image: &SlaveImage, coils: usize) -> Result<Self> {
+        if !Self::detect(id) {
+            return Err(unsupported(id));
+        }
+        Ok(Self::new(packed_bits(
+            image.domain,
+            image.outputs.clone(),
+            coils,
+        )?))
+    }
+
+    fn new(fields: Vec<Field<bool>>) -> Self {
+        Self {
+            state: vec![false; fields.len()],
+            fields,
+            valves: vec![],
+        }
+    }
+
+    /// Name the solenoid `coil`, counted from 0, and set its fail-safe
+    /// state.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no solenoid `coil`, or if the name is taken.
+    pub fn valve(mut self, name: &str, coil: usize, fail_safe: FailSafe) -> Self {
+        assert!(coil < self.fields.len(), "no solenoid {}", coil);
+        assert!(
+            self.index(name).is_none(),
+            "valve {:?} is already defined",
+            name
+        );
+        self.valves.push(Valve {
+            name: name.to_owned(),
+            coil,
+            fail_safe,
+        });
+        self
+    }
+
+    pub fn coil_count(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// The names of the valves, in the order they were defined.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.valves.iter().map(|v| v.name.as_str())
+    }
+
+    /// Write the commanded states into the domain data.
+    pub fn process(&mut self, data: &mut [u8]) {
+        for (field, on) in self.fields.iter().zip(&self.state) {
+            field.set(data, *on);
+        }
+    }
+
+    /// Command a valve.
+    ///
+    /// # Panics
+    ///
+    /// If there is no valve `name`.
+    pub fn set(&mut self, name: &str, on: bool) {
+        let coil = self.coil(name);
+        self.state[coil] = on;
+    }
+
+    /// Commanded state of a valve.
+    ///
+    /// # Panics
+    ///
+    /// If there is no valve `name`.
+    pub fn get(&self, name: &str) -> bool {
+        self.state[self.coil(name)]
+    }
+
+    /// Command a solenoid by number, named or not.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal has no solenoid `coil`.
+    pub fn set_coil(&mut self, coil: usize, on: bool) {
+        self.state[coil] = on;
+    }
+
+    /// Command the fail-safe states of the valves.
+    ///
+    /// Call this in the cycle where
+    /// [`CycleContext::is_stopping`](crate::runtime::CycleContext::is_stopping)
+    /// is `true`, before [`process`](Self::process). Unnamed solenoids keep
+    /// their state.
+    pub fn shutdown(&mut self) {
+        for valve in &self.valves {
+            match valve.fail_safe {
+                FailSafe::Off => self.state[valve.coil] = false,
+                FailSafe::On => self.state[valve.coil] = true,
+                FailSafe::Hold => (),
+            }
+        }
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.valves.iter().position(|v| v.name == name)
+    }
+
+    fn coil(&self, name: &str) -> usize {
+        match self.index(name) {
+            Some(i) => self.valves[i].coil,
+            None => panic!("no valve {:?}", name),
+        }
+    }
+}
+
+impl SlaveDriver for ValveTerminal {
+    type Settings = usize;
+
+    fn matches(id: SlaveId) -> bool {
+        Self::detect(id)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn configure(
+        config: &mut SlaveConfig,
+        id: SlaveId,
+        domain: DomainIdx,
+        settings: &usize,
+    ) -> Result<Self> {
+        Self::configure(config, id, domain, *settings)
+    }
+
+    fn bind(id: SlaveId, image: &SlaveImage, settings: &usize) -> Result<Self> {
+        Self::from_image(id, image, *settings)
+    }
+
+    fn process(&mut self, data: &mut [u8]) {
+        self.process(data)
+    }
+
+    fn shutdown(&mut self) {
+        self.shutdown()
+    }
+
+    fn channels(&self) -> Channels<'_> {
+        Box::new(self.state.iter().enumerate().map(move |(coil, on)| {
+            let name = match self.valves.iter().find(|v| v.coil == coil) {
+                Some(valve) => valve.name.clone(),
+                None => format!("Coil {}", coil + 1),
+            };
+            IoChannel::output(name, *on)
+        }))
+    }
+}
+
+#[test]
+fn test_valve_terminal() {
+    let cpx = SlaveId::new(FESTO, 0x0000_0001);
+    assert!(!ValveTerminal::detect(SlaveId::new(2, 0x07d4_3052)));
+    let image = SlaveImage {
+        domain: DomainIdx::from(0),
+        outputs: Some(1..3),
+        inputs: None,
+    };
+    assert!(ValveTerminal::from_image(cpx, &image, 24).is_err());
+    let mut valves = ValveTerminal::from_image(cpx, &image, 12)
+        .unwrap()
+        .valve("clamp", 0, FailSafe::Hold)
+        .valve("gripper open", 8, FailSafe::Off)
+        .valve("gripper close", 9, FailSafe::On);
+    assert_eq!(
+        valves.names().collect::<Vec<_>>(),
+        ["clamp", "gripper open", "gripper close"]
+    );
+
+    let mut data = [0_u8; 3];
+    valves.set("clamp", true);
+    valves.set("gripper open", true);
+    valves.set_coil(3, true);
+    assert!(valves.get("gripper open") && !valves.get("gripper close"));
+    valves.process(&mut data);
+    assert_eq!(data, [0, 0b1001, 0b01]);
+    let channels: Vec<_> = SlaveDriver::channels(&valves).map(|c| c.name).collect();
+    assert_eq!(channels[..4], ["clamp", "Coil 2", "Coil 3", "Coil 4"]);
+
+    valves.shutdown();
+    valves.process(&mut data);
+    assert_eq!(data, [0, 0b1001, 0b10]);
+}
@@ -0,0 +1,45 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Bus diagnostics based on the ESC registers of the slaves and the frames
+//! on the wire.
+//!
+//! The functions here only query the master and are meant to be called
+//! periodically from a lower-priority thread with a
+//! [`MasterMonitor`](crate::MasterMonitor).
+//!
+//! Reading from the master is Linux only; elsewhere the monitors can still
+//! be fed with recorded register values, e.g. to develop analysis tools.
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+mod al_status;
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+mod cable;
+#[cfg(target_os = "linux")]
+mod capture;
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) mod dc;
+mod eeprom;
+mod emergency;
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+mod events;
+mod pcap;
+mod presence;
+#[cfg_attr(not(target_os = "linux"), allow(dead_code, unused_imports))]
+pub(crate) mod selftest;
+mod snapshot;
+
+#[cfg(target_os = "linux")]
+pub use self::capture::{CaptureHandle, FrameCapture};
+pub use self::{
+    al_status::{AlStatus, AlStatusHistory, AlStatusRecord},
+    cable::{CableFault, CableMonitor, ErrorCounters, PortErrorCounters},
+    dc::{DcAlarm, DcDeviation, DcQualityMonitor, DcStatus},
+    eeprom::{sii_identity, update_sii_checksum, EepromProblem, EepromReport},
+    emergency::Emergency,
+    events::{BusEvent, EventLog, LoggedEvent},
+    pcap::PcapWriter,
+    presence::{PresenceChange, PresenceTracker, SlavePresence},
+    selftest::{SelfTestCheck, SelfTestReport, SelfTestResult},
+    snapshot::DiagnosticSnapshot,
+};
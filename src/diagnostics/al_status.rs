@@ -0,0 +1,248 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+#[cfg(target_os = "linux")]
+use crate::master::MasterMonitor;
+use crate::types::*;
+use std::{collections::VecDeque, convert::TryFrom, fmt, time::SystemTime};
+
+/// AL status register, followed by the AL status code at 0x0134.
+pub(super) const AL_STATUS: u16 = 0x0130;
+
+/// AL status codes and their meaning.
+const STATUS_CODES: &[(u16, &str)] = &[
+    (0x0000, "no error"),
+    (0x0001, "unspecified error"),
+    (0x0002, "no memory"),
+    (0x0011, "invalid requested state change"),
+    (0x0012, "unknown requested state"),
+    (0x0013, "bootstrap not supported"),
+    (0x0014, "no valid firmware"),
+    (0x0015, "invalid mailbox configuration (Boot)"),
+    (0x0016, "invalid mailbox configuration (PreOp)"),
+    (0x0017, "invalid sync manager configuration"),
+    (0x0018, "no valid inputs available"),
+    (0x0019, "no valid outputs"),
+    (0x001A, "synchronization error"),
+    (0x001B, "sync manager watchdog"),
+    (0x001C, "invalid sync manager types"),
+    (0x001D, "invalid output configuration"),
+    (0x001E, "invalid input configuration"),
+    (0x001F, "invalid watchdog configuration"),
+    (0x0020, "slave needs cold start"),
+    (0x0021, "slave needs Init"),
+    (0x0022, "slave needs PreOp"),
+    (0x0023, "slave needs SafeOp"),
+    (0x0024, "invalid input mapping"),
+    (0x0025, "invalid output mapping"),
+    (0x0026, "inconsistent settings"),
+    (0x0027, "free run not supported"),
+    (0x0028, "synchronization not supported"),
+    (0x0029, "free run needs 3 buffer mode"),
+    (0x002A, "background watchdog"),
+    (0x002B, "no valid inputs and outputs"),
+    (0x002C, "fatal sync error"),
+    (0x002D, "no sync error"),
+    (0x0030, "invalid DC SYNC configuration"),
+    (0x0031, "invalid DC latch configuration"),
+    (0x0032, "PLL error"),
+    (0x0033, "DC sync IO error"),
+    (0x0034, "DC sync timeout error"),
+    (0x0035, "DC invalid sync cycle time"),
+    (0x0036, "DC invalid sync0 cycle time"),
+    (0x0037, "DC invalid sync1 cycle time"),
+    (0x0041, "mailbox AoE"),
+    (0x0042, "mailbox EoE"),
+    (0x0043, "mailbox CoE"),
+    (0x0044, "mailbox FoE"),
+    (0x0045, "mailbox SoE"),
+    (0x004F, "mailbox VoE"),
+    (0x0050, "EEPROM no access"),
+    (0x0051, "EEPROM error"),
+    (0x0060, "slave restarted locally"),
+    (0x0061, "device identification value updated"),
+    (0x00F0, "application controller available"),
+];
+
+/// Content of the AL status registers of a slave.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlStatus {
+    /// Current state, `None` if the slave reports an undefined state.
+    pub state: Option<AlState>,
+    /// The slave refused or left the requested state.
+    pub error: bool,
+    pub code: u16,
+}
+
+impl AlStatus {
+    /// Read the AL status and AL status code registers of a slave.
+    #[cfg(target_os = "linux")]
+    pub fn read(master: &MasterMonitor, slave: SlavePos) -> Result<Self> {
+        let mut regs = [0; 6];
+        master.read_register(slave, AL_STATUS, &mut regs)?;
+        Ok(Self::from_registers(regs))
+    }
+
+    /// Decode the registers 0x0130 to 0x0135.
+    pub fn from_registers(regs: [u8; 6]) -> Self {
+        Self {
+            state: AlState::try_from(regs[0] & 0x0F).ok(),
+            error: regs[0] & 0x10 != 0,
+            code: u16::from_le_bytes([regs[4], regs[5]]),
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match STATUS_CODES.iter().find(|(code, _)| *code == self.code) {
+            Some((_, desc)) => desc,
+            None if self.code >= 0x8000 => "vendor specific",
+            None => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for AlStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.state {
+            Some(state) => write!(f, "{:?}", state)?,
+            None => write!(f, "undefined state")?,
+        }
+        if self.error {
+            write!(f, " + error")?;
+        }
+        write!(f, ", code 0x{:04x} ({})", self.code, self.description())
+    }
+}
+
+/// An [`AlStatus`] with the wall clock time it was read at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlStatusRecord {
+    pub time: SystemTime,
+    pub status: AlStatus,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SlaveHistory {
+    last_state: Option<AlState>,
+    last_error: bool,
+    records: VecDeque<AlStatusRecord>,
+}
+
+/// Keeps the last AL status codes of every slave.
+///
+/// The status is captured when a slave raises its error flag or falls back
+/// to a lower state, so excursions to e.g. SafeOp + error that recover
+/// before anyone looks can still be diagnosed. [`sample`](Self::sample) is
+/// meant to be called periodically; after a failed state request,
+/// [`capture`](Self::capture) records the status right away.
+#[derive(Debug, Clone)]
+pub struct AlStatusHistory {
+    capacity: usize,
+    slaves: Vec<SlaveHistory>,
+}
+
+impl AlStatusHistory {
+    /// Keep up to `capacity` records per slave.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            slaves: vec![],
+        }
+    }
+
+    /// Check the AL state of all slaves and capture the status codes of the
+    /// ones in error or that regressed.
+    #[cfg(target_os = "linux")]
+    pub fn sample(&mut self, master: &MasterMonitor) -> Result<()> {
+        let count = master.get_info()?.slave_count as usize;
+        for i in 0..count {
+            let slave = SlavePos::from(i as u16);
+            let info = master.get_slave_info(slave)?;
+            if self.observe(slave, info.al_state, info.error_flag != 0) {
+                self.capture(master, slave)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read and record the AL status of a slave.
+    #[cfg(target_os = "linux")]
+    pub fn capture(&mut self, master: &MasterMonitor, slave: SlavePos) -> Result<AlStatus> {
+        let status = AlStatus::read(master, slave)?;
+        self.record(slave, status);
+        Ok(status)
+    }
+
+    /// Update the known state of a slave, returns whether its status should
+    /// be captured.
+    fn observe(&mut self, slave: SlavePos, state: AlState, error: bool) -> bool {
+        let history = self.slave_mut(slave);
+        let regressed = history
+            .last_state
+            .map_or(false, |last| (state as u8) < (last as u8));
+        let new_error = error && !history.last_error;
+        history.last_state = Some(state);
+        history.last_error = error;
+        regressed || new_error
+    }
+
+    pub fn record(&mut self, slave: SlavePos, status: AlStatus) {
+        let capacity = self.capacity;
+        let history = self.slave_mut(slave);
+        if history.records.len() == capacity {
+            history.records.pop_front();
+        }
+        history.records.push_back(AlStatusRecord {
+            time: SystemTime::now(),
+            status,
+        });
+    }
+
+    /// The recorded status codes of a slave, oldest first.
+    pub fn history(&self, slave: SlavePos) -> impl Iterator<Item = &AlStatusRecord> + '_ {
+        self.slaves
+            .get(u16::from(slave) as usize)
+            .into_iter()
+            .flat_map(|h| h.records.iter())
+    }
+
+    pub fn clear(&mut self) {
+        for history in &mut self.slaves {
+            history.records.clear();
+        }
+    }
+
+    fn slave_mut(&mut self, slave: SlavePos) -> &mut SlaveHistory {
+        let idx = u16::from(slave) as usize;
+        if self.slaves.len() <= idx {
+            self.slaves.resize_with(idx + 1, SlaveHistory::default);
+        }
+        &mut self.slaves[idx]
+    }
+}
+
+#[test]
+fn test_al_status_history() {
+    let status = AlStatus::from_registers([0x14, 0, 0, 0, 0x1B, 0]);
+    assert_eq!(
+        status.to_string(),
+        "SafeOp + error, code 0x001b (sync manager watchdog)"
+    );
+
+    let slave = SlavePos::from(1);
+    let mut history = AlStatusHistory::new(2);
+    assert!(!history.observe(slave, AlState::PreOp, false));
+    assert!(!history.observe(slave, AlState::Op, false));
+    assert!(history.observe(slave, AlState::SafeOp, true));
+    assert!(!history.observe(slave, AlState::SafeOp, true));
+    assert!(!history.observe(slave, AlState::Op, false));
+    history.record(slave, status);
+    history.record(slave, AlStatus::from_registers([0x02, 0, 0, 0, 0x1A, 0]));
+    history.record(slave, AlStatus::from_registers([0x08, 0, 0, 0, 0, 0]));
+    let codes = history
+        .history(slave)
+        .map(|r| r.status.code)
+        .collect::<Vec<_>>();
+    assert_eq!(codes, [0x1A, 0]);
+    assert_eq!(history.history(SlavePos::from(5)).count(), 0);
+}
@@ -0,0 +1,224 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+#[cfg(target_os = "linux")]
+use crate::master::MasterMonitor;
+use crate::{
+    topology::{port_name, Link, Topology},
+    types::*,
+};
+use std::fmt;
+
+/// First ESC error counter register.
+const ERROR_COUNTERS: u16 = 0x0300;
+/// Size of the error counter block (0x0300 to 0x0313).
+const ERROR_COUNTERS_SIZE: usize = 0x14;
+
+/// Error counters of one port of a slave controller.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PortErrorCounters {
+    pub invalid_frames: u8,
+    pub rx_errors: u8,
+    /// Errors already detected by a previous slave.
+    pub forwarded_rx_errors: u8,
+    pub lost_links: u8,
+}
+
+/// Error counters of a slave controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCounters {
+    pub slave: SlavePos,
+    pub ports: [PortErrorCounters; 4],
+    pub processing_unit_errors: u8,
+    pub pdi_errors: u8,
+}
+
+impl ErrorCounters {
+    #[cfg(target_os = "linux")]
+    pub fn read(master: &MasterMonitor, slave: SlavePos) -> Result<Self> {
+        let mut regs = [0; ERROR_COUNTERS_SIZE];
+        master.read_register(slave, ERROR_COUNTERS, &mut regs)?;
+        Ok(Self::from_registers(slave, &regs))
+    }
+
+    fn from_registers(slave: SlavePos, regs: &[u8; ERROR_COUNTERS_SIZE]) -> Self {
+        let mut ports = [PortErrorCounters::default(); 4];
+        for (i, port) in ports.iter_mut().enumerate() {
+            *port = PortErrorCounters {
+                invalid_frames: regs[2 * i],
+                rx_errors: regs[2 * i + 1],
+                forwarded_rx_errors: regs[0x08 + i],
+                lost_links: regs[0x10 + i],
+            };
+        }
+        Self {
+            slave,
+            ports,
+            processing_unit_errors: regs[0x0C],
+            pdi_errors: regs[0x0D],
+        }
+    }
+}
+
+/// A link where errors originate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CableFault {
+    /// The port that received the erroneous frames.
+    pub receiver: Link,
+    /// The other end of the cable, if a slave is connected there.
+    pub peer: Option<Link>,
+    /// New errors detected at the receiver since the last sample.
+    pub rx_errors: u32,
+    /// Link losses at the receiver since the last sample.
+    pub lost_links: u32,
+}
+
+impl fmt::Display for CableFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (a, b) = match self.peer {
+            Some(peer) if u16::from(peer.slave) < u16::from(self.receiver.slave) => {
+                (peer, Some(self.receiver))
+            }
+            Some(peer) => (self.receiver, Some(peer)),
+            None => (self.receiver, None),
+        };
+        let slave = |l: Link| format!("slave {} port {}", u16::from(l.slave), port_name(l.port));
+        match b {
+            Some(b) => write!(f, "check cable between {} and {}", slave(a), slave(b))?,
+            None => write!(f, "check cable at {}", slave(a))?,
+        }
+        write!(
+            f,
+            " ({} rx errors, {} lost links)",
+            self.rx_errors, self.lost_links
+        )
+    }
+}
+
+/// Localizes cable faults from the change of the error counters between
+/// periodic samples.
+///
+/// A frame corrupted on a cable is flagged by the first slave receiving it,
+/// which counts a RX error; all following slaves count it as a forwarded
+/// error. The faulty cable is thus the one connected to a port with more RX
+/// errors than forwarded ones. Faults are reported in processing order, so
+/// the first one is the furthest upstream.
+pub struct CableMonitor {
+    topology: Topology,
+    previous: Vec<ErrorCounters>,
+}
+
+impl CableMonitor {
+    pub fn new(topology: Topology) -> Self {
+        Self {
+            topology,
+            previous: vec![],
+        }
+    }
+
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    /// Read the error counters of all slaves and return the suspected faults
+    /// since the last call.
+    ///
+    /// The first call only records a baseline.
+    #[cfg(target_os = "linux")]
+    pub fn sample(&mut self, master: &MasterMonitor) -> Result<Vec<CableFault>> {
+        let counters = self
+            .topology
+            .nodes()
+            .iter()
+            .map(|n| ErrorCounters::read(master, n.slave))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(self.update(counters))
+    }
+
+    /// Like [`sample`](Self::sample), with counters read elsewhere.
+    pub fn update(&mut self, counters: Vec<ErrorCounters>) -> Vec<CableFault> {
+        let mut faults = vec![];
+        for current in &counters {
+            let previous = match self.previous.iter().find(|c| c.slave == current.slave) {
+                Some(previous) => previous,
+                None => continue,
+            };
+            for (port, (cur, prev)) in current.ports.iter().zip(&previous.ports).enumerate() {
+                let received = delta(cur.rx_errors, prev.rx_errors)
+                    + delta(cur.invalid_frames, prev.invalid_frames);
+                let forwarded = delta(cur.forwarded_rx_errors, prev.forwarded_rx_errors);
+                let rx_errors = received.saturating_sub(forwarded);
+                let lost_links = delta(cur.lost_links, prev.lost_links);
+                if rx_errors == 0 && lost_links == 0 {
+                    continue;
+                }
+                faults.push(CableFault {
+                    receiver: Link {
+                        slave: current.slave,
+                        port,
+                    },
+                    peer: self.peer(current.slave, port),
+                    rx_errors,
+                    lost_links,
+                });
+            }
+        }
+        self.previous = counters;
+        faults.sort_by_key(|f| self.topology.path(f.receiver.slave).len());
+        faults
+    }
+
+    fn peer(&self, slave: SlavePos, port: usize) -> Option<Link> {
+        if port == 0 {
+            return self.topology.upstream(slave);
+        }
+        self.topology
+            .children(slave)
+            .iter()
+            .find(|l| l.port == port)
+            .map(|l| Link {
+                slave: l.slave,
+                port: 0,
+            })
+    }
+}
+
+/// Counter increase, assuming a reset if the counter went down.
+fn delta(current: u8, previous: u8) -> u32 {
+    if current >= previous {
+        (current - previous) as u32
+    } else {
+        current as u32
+    }
+}
+
+#[test]
+fn test_cable_fault() {
+    use crate::topology::test_slave;
+
+    const N: u16 = 0xFFFF;
+    let slaves = [
+        test_slave(0, [N, 1, N, N]),
+        test_slave(1, [0, 2, N, N]),
+        test_slave(2, [1, N, N, N]),
+    ];
+    let mut monitor = CableMonitor::new(Topology::from_slaves(&slaves));
+    let counters = |rx: [u8; 3], fwd: [u8; 3]| {
+        (0..3)
+            .map(|i| {
+                let mut regs = [0; ERROR_COUNTERS_SIZE];
+                regs[1] = rx[i];
+                regs[8] = fwd[i];
+                ErrorCounters::from_registers(SlavePos::from(i as u16), &regs)
+            })
+            .collect::<Vec<_>>()
+    };
+    assert!(monitor.update(counters([0; 3], [0; 3])).is_empty());
+    // the cable between 0 and 1 corrupts frames, 2 sees them as forwarded
+    let faults = monitor.update(counters([0, 4, 4], [0, 0, 4]));
+    assert_eq!(faults.len(), 1);
+    assert_eq!(
+        faults[0].to_string(),
+        "check cable between slave 0 port B and slave 1 port A (4 rx errors, 0 lost links)"
+    );
+}
@@ -0,0 +1,183 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::pcap::{PcapWriter, SNAPLEN};
+use crate::types::*;
+use std::{
+    ffi::CString,
+    fs::File,
+    io::{self, Read, Write},
+    mem,
+    os::unix::io::{AsRawFd, FromRawFd},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Captures the frames of a master from its debug interface.
+///
+/// The master must be built with `--enable-debug-if`, which creates a
+/// network interface `ecdbgm<idx>` mirroring all sent and received frames.
+/// The interface must be up (`ip link set ecdbgm0 up`), and capturing needs
+/// the `CAP_NET_RAW` capability.
+pub struct FrameCapture {
+    socket: File,
+    buf: Vec<u8>,
+}
+
+impl FrameCapture {
+    /// Open the debug interface of master `idx`.
+    pub fn open(idx: MasterIdx) -> Result<Self> {
+        Self::open_interface(&format!("ecdbgm{}", idx))
+    }
+
+    /// Capture all frames of a network interface.
+    pub fn open_interface(name: &str) -> Result<Self> {
+        let protocol = (libc::ETH_P_ALL as u16).to_be();
+        let name =
+            CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if ifindex == 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, protocol as i32) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        // closes the socket on errors below
+        let socket = unsafe { File::from_raw_fd(fd) };
+        let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        addr.sll_family = libc::AF_PACKET as u16;
+        addr.sll_protocol = protocol;
+        addr.sll_ifindex = ifindex as i32;
+        let res = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        // wake up regularly to allow stopping a capture thread
+        let timeout = libc::timeval {
+            tv_sec: 0,
+            tv_usec: 100_000,
+        };
+        let res = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const _ as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(Self {
+            socket,
+            buf: vec![0; SNAPLEN],
+        })
+    }
+
+    /// Wait up to 100 ms for the next frame and return it with its time
+    /// since the Unix epoch.
+    pub fn next_frame(&mut self) -> Result<Option<(Duration, &[u8])>> {
+        match self.socket.read(&mut self.buf) {
+            Ok(n) => {
+                let time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                Ok(Some((time, &self.buf[..n])))
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write all frames to `out` in pcap format from a separate thread.
+    pub fn spawn<W: Write + Send + 'static>(mut self, out: W) -> Result<CaptureHandle> {
+        let mut pcap = PcapWriter::new(out)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let frames = Arc::new(AtomicU64::new(0));
+        let thread = {
+            let stop = stop.clone();
+            let frames = frames.clone();
+            thread::Builder::new()
+                .name("ethercat-capture".into())
+                .spawn(move || {
+                    while !stop.load(Ordering::Acquire) {
+                        if let Some((time, frame)) = self.next_frame()? {
+                            pcap.write_frame(time, frame)?;
+                            frames.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    pcap.flush()?;
+                    Ok(())
+                })?
+        };
+        Ok(CaptureHandle {
+            stop,
+            frames,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl AsRawFd for FrameCapture {
+    fn as_raw_fd(&self) -> i32 {
+        self.socket.as_raw_fd()
+    }
+}
+
+/// A running capture thread; stopped when dropped.
+pub struct CaptureHandle {
+    stop: Arc<AtomicBool>,
+    frames: Arc<AtomicU64>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl CaptureHandle {
+    /// Number of frames captured so far.
+    pub fn frames(&self) -> u64 {
+        self.frames.load(Ordering::Relaxed)
+    }
+
+    /// Stop capturing and return the result of the capture thread.
+    pub fn finish(mut self) -> Result<u64> {
+        self.stop_thread()?;
+        Ok(self.frames())
+    }
+
+    fn stop_thread(&mut self) -> Result<()> {
+        self.stop.store(true, Ordering::Release);
+        match self.thread.take() {
+            Some(thread) => thread.join().unwrap_or_else(|_| {
+                Err(io::Error::new(io::ErrorKind::Other, "capture thread panicked").into())
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for CaptureHandle {
+    fn drop(&mut self) {
+        if let Err(err) = self.stop_thread() {
+            log::error!("Frame capture: {}", err);
+        }
+    }
+}
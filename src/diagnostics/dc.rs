@@ -0,0 +1,249 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+#[cfg(target_os = "linux")]
+use crate::master::MasterMonitor;
+use crate::types::*;
+use std::fmt;
+
+/// DC system time difference register.
+pub(crate) const SYSTEM_TIME_DIFFERENCE: u16 = 0x092C;
+/// DC system time offset register, followed by the delay and difference.
+const SYSTEM_TIME_OFFSET: u16 = 0x0920;
+/// DC cyclic unit control register, followed by the activation, the pulse
+/// length, the start time and the cycle times.
+const CYCLIC_UNIT_CONTROL: u16 = 0x0980;
+const SYNC_REGISTERS: usize = 0x28;
+
+/// Statistics of the system time difference of one slave.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DcDeviation {
+    pub slave: SlavePos,
+    /// Last deviation in ns, negative if the local time is behind.
+    pub last: i32,
+    pub min: i32,
+    pub max: i32,
+    pub mean: f64,
+    pub samples: u64,
+}
+
+impl DcDeviation {
+    fn new(slave: SlavePos) -> Self {
+        Self {
+            slave,
+            last: 0,
+            min: i32::MAX,
+            max: i32::MIN,
+            mean: 0.0,
+            samples: 0,
+        }
+    }
+
+    fn record(&mut self, deviation: i32) {
+        self.last = deviation;
+        self.min = self.min.min(deviation);
+        self.max = self.max.max(deviation);
+        self.samples += 1;
+        self.mean += (deviation as f64 - self.mean) / self.samples as f64;
+    }
+}
+
+/// A slave whose system time drifted beyond the configured limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DcAlarm {
+    pub slave: SlavePos,
+    pub deviation: i32,
+    pub limit: u32,
+}
+
+impl fmt::Display for DcAlarm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "slave {}: DC deviation of {} ns exceeds {} ns",
+            u16::from(self.slave),
+            self.deviation,
+            self.limit
+        )
+    }
+}
+
+/// Monitors the synchronization quality of the DC slaves by reading their
+/// system time difference register.
+///
+/// [`sample`](Self::sample) is meant to be called periodically, e.g. once a
+/// second, from a monitoring thread.
+pub struct DcQualityMonitor {
+    limit: u32,
+    slaves: Vec<DcDeviation>,
+}
+
+impl DcQualityMonitor {
+    /// Monitor the given slaves, alarming beyond `limit` ns.
+    pub fn new(slaves: impl IntoIterator<Item = SlavePos>, limit: u32) -> Self {
+        Self {
+            limit,
+            slaves: slaves.into_iter().map(DcDeviation::new).collect(),
+        }
+    }
+
+    /// Monitor all slaves of the bus with a DC system time.
+    #[cfg(target_os = "linux")]
+    pub fn for_bus(master: &MasterMonitor, limit: u32) -> Result<Self> {
+        let count = master.get_info()?.slave_count as u16;
+        let mut slaves = vec![];
+        for i in 0..count {
+            let pos = SlavePos::from(i);
+            if master.get_slave_info(pos)?.has_dc_system_time {
+                slaves.push(pos);
+            }
+        }
+        Ok(Self::new(slaves, limit))
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    /// Read the deviation of all monitored slaves and return those beyond the
+    /// limit.
+    #[cfg(target_os = "linux")]
+    pub fn sample(&mut self, master: &MasterMonitor) -> Result<Vec<DcAlarm>> {
+        let mut deviations = Vec::with_capacity(self.slaves.len());
+        for s in &self.slaves {
+            let mut reg = [0; 4];
+            master.read_register(s.slave, SYSTEM_TIME_DIFFERENCE, &mut reg)?;
+            deviations.push(decode_difference(u32::from_le_bytes(reg)));
+        }
+        Ok(self.update(&deviations))
+    }
+
+    /// Like [`sample`](Self::sample), with the deviations (in ns, in the
+    /// order of the monitored slaves) read elsewhere.
+    pub fn update(&mut self, deviations: &[i32]) -> Vec<DcAlarm> {
+        let limit = self.limit;
+        self.slaves
+            .iter_mut()
+            .zip(deviations)
+            .filter_map(|(s, &deviation)| {
+                s.record(deviation);
+                if deviation.unsigned_abs() > limit {
+                    Some(DcAlarm {
+                        slave: s.slave,
+                        deviation,
+                        limit,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Statistics since creation or the last [`reset`](Self::reset).
+    pub fn deviations(&self) -> &[DcDeviation] {
+        &self.slaves
+    }
+
+    pub fn reset(&mut self) {
+        for s in &mut self.slaves {
+            *s = DcDeviation::new(s.slave);
+        }
+    }
+}
+
+/// The DC state of a slave as read from its registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DcStatus {
+    pub slave: SlavePos,
+    /// Offset of the local time to the system time in ns.
+    pub system_time_offset: u64,
+    /// Propagation delay from the reference clock in ns.
+    pub system_time_delay: u32,
+    /// Current system time difference in ns, negative if the local time is
+    /// behind.
+    pub difference: i32,
+    /// Cyclic operation is enabled.
+    pub cyclic: bool,
+    pub sync0: bool,
+    pub sync1: bool,
+    /// Start time of the cyclic operation in ns of system time.
+    pub start_time: u64,
+    pub sync0_cycle: u32,
+    /// Time from SYNC0 to SYNC1, in ns.
+    pub sync1_cycle: u32,
+}
+
+impl DcStatus {
+    /// Read the DC registers of a slave; it must support DC.
+    #[cfg(target_os = "linux")]
+    pub fn read(master: &MasterMonitor, slave: SlavePos) -> Result<Self> {
+        let mut time = [0; 16];
+        master.read_register(slave, SYSTEM_TIME_OFFSET, &mut time)?;
+        let mut sync = [0; SYNC_REGISTERS];
+        master.read_register(slave, CYCLIC_UNIT_CONTROL, &mut sync)?;
+        Ok(Self::from_registers(slave, &time, &sync))
+    }
+
+    /// Decode the registers from 0x0920 and 0x0980 on.
+    pub fn from_registers(slave: SlavePos, time: &[u8; 16], sync: &[u8; SYNC_REGISTERS]) -> Self {
+        let u32_at = |regs: &[u8], i: usize| {
+            u32::from_le_bytes([regs[i], regs[i + 1], regs[i + 2], regs[i + 3]])
+        };
+        let u64_at = |regs: &[u8], i: usize| {
+            u64::from(u32_at(regs, i)) | u64::from(u32_at(regs, i + 4)) << 32
+        };
+        let activation = sync[0x01];
+        Self {
+            slave,
+            system_time_offset: u64_at(time, 0x00),
+            system_time_delay: u32_at(time, 0x08),
+            difference: decode_difference(u32_at(time, 0x0C)),
+            cyclic: activation & 0x01 != 0,
+            sync0: activation & 0x02 != 0,
+            sync1: activation & 0x04 != 0,
+            start_time: u64_at(sync, 0x10),
+            sync0_cycle: u32_at(sync, 0x20),
+            sync1_cycle: u32_at(sync, 0x24),
+        }
+    }
+}
+
+/// The register holds the magnitude in bits 0-30 and sets bit 31 if the
+/// local copy of the system time is smaller than the received one.
+pub(crate) fn decode_difference(raw: u32) -> i32 {
+    let magnitude = (raw & 0x7FFF_FFFF) as i32;
+    if raw & 0x8000_0000 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+#[test]
+fn test_dc_quality() {
+    assert_eq!(decode_difference(0x0000_0010), 16);
+    assert_eq!(decode_difference(0x8000_0010), -16);
+
+    let mut monitor = DcQualityMonitor::new(vec![SlavePos::from(1), SlavePos::from(4)], 100);
+    assert!(monitor.update(&[10, -50]).is_empty());
+    let alarms = monitor.update(&[30, -150]);
+    assert_eq!(alarms.len(), 1);
+    assert_eq!(
+        alarms[0].to_string(),
+        "slave 4: DC deviation of -150 ns exceeds 100 ns"
+    );
+    let dev = monitor.deviations()[0];
+    assert_eq!((dev.min, dev.max, dev.mean, dev.samples), (10, 30, 20.0, 2));
+
+    let mut time = [0; 16];
+    time[0x08] = 0x40;
+    time[0x0C..].copy_from_slice(&0x8000_0005u32.to_le_bytes());
+    let mut sync = [0; SYNC_REGISTERS];
+    sync[0x01] = 0x03;
+    sync[0x20..0x24].copy_from_slice(&1_000_000u32.to_le_bytes());
+    let status = DcStatus::from_registers(SlavePos::from(2), &time, &sync);
+    assert_eq!((status.system_time_delay, status.difference), (0x40, -5));
+    assert!(status.cyclic && status.sync0 && !status.sync1);
+    assert_eq!(status.sync0_cycle, 1_000_000);
+}
@@ -0,0 +1,259 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+#[cfg(target_os = "linux")]
+use crate::master::MasterMonitor;
+use crate::types::*;
+use std::fmt;
+
+/// Word holding the checksum of the configuration area.
+const CHECKSUM_WORD: usize = 0x07;
+/// First word of the identity: vendor ID, product code, revision and
+/// serial number.
+const IDENTITY_WORD: usize = 0x08;
+/// Word holding the supported mailbox protocols.
+const MAILBOX_PROTOCOL_WORD: usize = 0x1C;
+/// First word of the category list.
+const FIRST_CATEGORY_WORD: usize = 0x40;
+
+const CATEGORY_GENERAL: u16 = 30;
+const CATEGORY_SYNC_MANAGER: u16 = 41;
+const CATEGORY_END: u16 = 0xFFFF;
+
+fn category_name(category: u16) -> &'static str {
+    match category {
+        10 => "Strings",
+        20 => "DataTypes",
+        30 => "General",
+        40 => "FMMU",
+        41 => "SyncM",
+        50 => "TxPDO",
+        51 => "RxPDO",
+        60 => "DC",
+        _ => "vendor specific",
+    }
+}
+
+/// A defect of the SII (EEPROM) image of a slave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EepromProblem {
+    /// The image ends before the category list.
+    TooShort { words: usize },
+    /// The checksum of the configuration area does not match.
+    Checksum { stored: u8, computed: u8 },
+    /// A category extends beyond the end of the image.
+    Truncated { category: u16 },
+    /// The category list is not terminated.
+    NoEnd,
+    /// A category required for this slave is missing.
+    MissingCategory(u16),
+}
+
+impl fmt::Display for EepromProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EepromProblem::TooShort { words } => write!(f, "image of only {} words", words),
+            EepromProblem::Checksum { stored, computed } => write!(
+                f,
+                "checksum 0x{:02x} does not match computed 0x{:02x}",
+                stored, computed
+            ),
+            EepromProblem::Truncated { category } => write!(
+                f,
+                "category {} ({}) exceeds the image",
+                category,
+                category_name(category)
+            ),
+            EepromProblem::NoEnd => write!(f, "category list is not terminated"),
+            EepromProblem::MissingCategory(category) => write!(
+                f,
+                "missing category {} ({})",
+                category,
+                category_name(category)
+            ),
+        }
+    }
+}
+
+/// Result of checking the SII image of a slave.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EepromReport {
+    pub slave: SlavePos,
+    /// Types of the categories found, in order.
+    pub categories: Vec<u16>,
+    pub problems: Vec<EepromProblem>,
+}
+
+impl EepromReport {
+    /// Read the SII image of a slave from the master and check it.
+    ///
+    /// The master keeps a copy of the image read during the bus scan, so
+    /// this does not access the bus.
+    #[cfg(target_os = "linux")]
+    pub fn read(master: &MasterMonitor, slave: SlavePos) -> Result<Self> {
+        let nwords = master.get_slave_info(slave)?.sii_nwords as usize;
+        let mut words = vec![0; nwords];
+        if nwords > 0 {
+            master.read_sii(slave, 0, &mut words)?;
+        }
+        Ok(Self::check(slave, &words))
+    }
+
+    /// Check the checksum of the configuration area and the category list
+    /// of an SII image.
+    ///
+    /// The General category is required for all slaves, the SyncM category
+    /// for slaves with a mailbox.
+    pub fn check(slave: SlavePos, words: &[u16]) -> Self {
+        let mut report = Self {
+            slave,
+            categories: vec![],
+            problems: vec![],
+        };
+        if words.len() < FIRST_CATEGORY_WORD {
+            report
+                .problems
+                .push(EepromProblem::TooShort { words: words.len() });
+            return report;
+        }
+        let stored = words[CHECKSUM_WORD] as u8;
+        let computed = sii_checksum(&words[..CHECKSUM_WORD]);
+        if stored != computed {
+            report
+                .problems
+                .push(EepromProblem::Checksum { stored, computed });
+        }
+
+        let mut pos = FIRST_CATEGORY_WORD;
+        loop {
+            let category = match words.get(pos) {
+                Some(&CATEGORY_END) => break,
+                Some(&category) => category,
+                None => {
+                    report.problems.push(EepromProblem::NoEnd);
+                    break;
+                }
+            };
+            report.categories.push(category);
+            let size = words.get(pos + 1).copied().unwrap_or(0) as usize;
+            pos += 2 + size;
+            if pos > words.len() {
+                report.problems.push(EepromProblem::Truncated { category });
+                break;
+            }
+        }
+
+        let mut required = vec![CATEGORY_GENERAL];
+        if words[MAILBOX_PROTOCOL_WORD] != 0 {
+            required.push(CATEGORY_SYNC_MANAGER);
+        }
+        for category in required {
+            if !report.categories.contains(&category) {
+                report
+                    .problems
+                    .push(EepromProblem::MissingCategory(category));
+            }
+        }
+        report
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Check the SII images of all slaves on the bus, e.g. at startup.
+    #[cfg(target_os = "linux")]
+    pub fn read_all(master: &MasterMonitor) -> Result<Vec<Self>> {
+        let count = master.get_info()?.slave_count as u16;
+        (0..count)
+            .map(|i| Self::read(master, SlavePos::from(i)))
+            .collect()
+    }
+}
+
+/// The identity stored in an SII image, `None` if it is too short.
+pub fn sii_identity(words: &[u16]) -> Option<(SlaveId, SlaveRev)> {
+    let ident = words.get(IDENTITY_WORD..IDENTITY_WORD + 8)?;
+    let long = |i: usize| u32::from(ident[i]) | u32::from(ident[i + 1]) << 16;
+    Some((
+        SlaveId::new(long(0), long(2)),
+        SlaveRev::new(long(4), long(6)),
+    ))
+}
+
+/// Recompute the checksum of the configuration area of an SII image, e.g.
+/// after changing the alias.
+pub fn update_sii_checksum(words: &mut [u16]) {
+    if words.len() > CHECKSUM_WORD {
+        words[CHECKSUM_WORD] = sii_checksum(&words[..CHECKSUM_WORD]).into();
+    }
+}
+
+impl fmt::Display for EepromReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "slave {}: EEPROM ", u16::from(self.slave))?;
+        if self.is_ok() {
+            return write!(f, "ok");
+        }
+        for (i, problem) in self.problems.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// CRC-8 (polynomial 0x07, initial value 0xFF) over the little endian bytes
+/// of the configuration area.
+fn sii_checksum(words: &[u16]) -> u8 {
+    let mut crc = 0xFFu8;
+    for byte in words.iter().flat_map(|w| w.to_le_bytes()) {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[test]
+fn test_eeprom_check() {
+    let slave = SlavePos::from(4);
+    let mut words = vec![0; FIRST_CATEGORY_WORD];
+    words[..7].copy_from_slice(&[0x0c80, 0x6e00, 0x0000, 0x0000, 0x1234, 0, 0]);
+    update_sii_checksum(&mut words);
+    words[MAILBOX_PROTOCOL_WORD] = 0x0004;
+    words[IDENTITY_WORD..IDENTITY_WORD + 4].copy_from_slice(&[2, 0, 0x0c20, 0x044c]);
+    assert_eq!(
+        sii_identity(&words).unwrap().0,
+        SlaveId::new(2, 0x044c_0c20)
+    );
+    // Strings, General, end
+    words.extend_from_slice(&[10, 2, 0x6101, 0x0062, 30, 1, 0, CATEGORY_END]);
+
+    let report = EepromReport::check(slave, &words);
+    assert_eq!(report.categories, [10, 30]);
+    assert_eq!(
+        report.problems,
+        [EepromProblem::MissingCategory(CATEGORY_SYNC_MANAGER)]
+    );
+
+    words[4] = 0x1235;
+    words.truncate(words.len() - 2);
+    let report = EepromReport::check(slave, &words);
+    assert!(matches!(report.problems[0], EepromProblem::Checksum { .. }));
+    assert_eq!(
+        report.problems[1],
+        EepromProblem::Truncated { category: 30 }
+    );
+    assert_eq!(
+        report.to_string().split(", ").nth(1),
+        Some("category 30 (General) exceeds the image")
+    );
+}
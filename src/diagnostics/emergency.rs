@@ -0,0 +1,123 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use std::fmt;
+
+/// A CoE emergency message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Emergency {
+    pub error_code: u16,
+    pub error_register: u8,
+    /// Manufacturer specific data.
+    pub data: [u8; 5],
+}
+
+const ERROR_REGISTER_BITS: [&str; 8] = [
+    "generic",
+    "current",
+    "voltage",
+    "temperature",
+    "communication",
+    "device profile",
+    "reserved",
+    "manufacturer",
+];
+
+/// Error code ranges and their meaning, most specific first.
+const ERROR_CODES: &[(u16, u16, &str)] = &[
+    (0x0000, 0x0000, "error reset or no error"),
+    (0x1000, 0x1FFF, "generic error"),
+    (0x2100, 0x21FF, "current, device input side"),
+    (0x2200, 0x22FF, "current inside the device"),
+    (0x2300, 0x23FF, "current, device output side"),
+    (0x2000, 0x2FFF, "current"),
+    (0x3100, 0x31FF, "mains voltage"),
+    (0x3200, 0x32FF, "voltage inside the device"),
+    (0x3300, 0x33FF, "output voltage"),
+    (0x3000, 0x3FFF, "voltage"),
+    (0x4100, 0x41FF, "ambient temperature"),
+    (0x4200, 0x42FF, "device temperature"),
+    (0x4000, 0x4FFF, "temperature"),
+    (0x5000, 0x5FFF, "device hardware"),
+    (0x6100, 0x61FF, "internal software"),
+    (0x6200, 0x62FF, "user software"),
+    (0x6300, 0x63FF, "data set"),
+    (0x6000, 0x6FFF, "device software"),
+    (0x7000, 0x7FFF, "additional modules"),
+    (0x8110, 0x8110, "CAN overrun"),
+    (0x8700, 0x8700, "sync controller"),
+    (0x8100, 0x81FF, "communication"),
+    (0x8200, 0x82FF, "protocol error"),
+    (0x8300, 0x83FF, "torque control"),
+    (0x8400, 0x84FF, "velocity speed controller"),
+    (0x8500, 0x85FF, "position controller"),
+    (0x8600, 0x86FF, "positioning controller"),
+    (0x8000, 0x8FFF, "monitoring"),
+    (0x9000, 0x9FFF, "external error"),
+    (
+        0xA000,
+        0xA000,
+        "transition from PreOp to SafeOp was not successful",
+    ),
+    (
+        0xA001,
+        0xA001,
+        "transition from SafeOp to Op was not successful",
+    ),
+    (0xA000, 0xAFFF, "EtherCAT state machine"),
+    (0xF000, 0xFEFF, "additional functions"),
+    (0xFF00, 0xFFFF, "device specific"),
+];
+
+impl Emergency {
+    /// Decode the 8 bytes popped from the emergency ring of a slave config.
+    pub fn from_bytes(raw: [u8; 8]) -> Self {
+        let mut data = [0; 5];
+        data.copy_from_slice(&raw[3..]);
+        Self {
+            error_code: u16::from_le_bytes([raw[0], raw[1]]),
+            error_register: raw[2],
+            data,
+        }
+    }
+
+    /// Meaning of the error code according to CiA 301 and ETG.1000.6.
+    pub fn description(&self) -> &'static str {
+        ERROR_CODES
+            .iter()
+            .find(|(lo, hi, _)| (*lo..=*hi).contains(&self.error_code))
+            .map_or("unknown", |(_, _, desc)| desc)
+    }
+}
+
+impl fmt::Display for Emergency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "emergency {:#06x} ({})",
+            self.error_code,
+            self.description()
+        )?;
+        let flags = ERROR_REGISTER_BITS
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.error_register & (1 << i) != 0)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>();
+        if !flags.is_empty() {
+            write!(f, ", register: {}", flags.join(", "))?;
+        }
+        write!(f, ", data: {:02x?}", self.data)
+    }
+}
+
+#[test]
+fn test_emergency() {
+    let emcy = Emergency::from_bytes([0x10, 0x43, 0x09, 1, 2, 3, 4, 5]);
+    assert_eq!(emcy.error_code, 0x4310);
+    assert_eq!(
+        emcy.to_string(),
+        "emergency 0x4310 (temperature), register: generic, temperature, \
+         data: [01, 02, 03, 04, 05]"
+    );
+}
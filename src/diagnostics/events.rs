@@ -0,0 +1,277 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::emergency::Emergency;
+#[cfg(target_os = "linux")]
+use crate::master::{Master, MasterMonitor};
+use crate::{
+    runtime::{ExecutorEvent, WcAnomaly},
+    types::*,
+};
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// An incident recorded in an [`EventLog`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BusEvent {
+    Emergency {
+        config: SlaveConfigIdx,
+        emergency: Emergency,
+    },
+    AlStateChange {
+        slave: SlavePos,
+        from: Option<AlState>,
+        to: AlState,
+    },
+    WorkingCounter {
+        cycle: u64,
+        domain: DomainIdx,
+        working_counter: u32,
+        anomaly: WcAnomaly,
+    },
+}
+
+impl fmt::Display for BusEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BusEvent::Emergency { config, emergency } => {
+                write!(f, "config {}: {}", config, emergency)
+            }
+            BusEvent::AlStateChange { slave, from, to } => {
+                write!(f, "slave {}: AL state ", u16::from(*slave))?;
+                match from {
+                    Some(from) => write!(f, "{:?} -> {:?}", from, to),
+                    None => write!(f, "{:?}", to),
+                }
+            }
+            BusEvent::WorkingCounter {
+                cycle,
+                domain,
+                working_counter,
+                anomaly,
+            } => write!(
+                f,
+                "domain {}: working counter {} in cycle {} ({})",
+                usize::from(*domain),
+                working_counter,
+                cycle,
+                describe_anomaly(anomaly)
+            ),
+        }
+    }
+}
+
+fn describe_anomaly(anomaly: &WcAnomaly) -> String {
+    match anomaly {
+        WcAnomaly::None => "complete".into(),
+        WcAnomaly::SlaveMissing { slave } => format!("slave {} missing", u16::from(*slave)),
+        WcAnomaly::SegmentDown { first } => {
+            format!("slaves from {} on missing", u16::from(*first))
+        }
+        WcAnomaly::SlavesMissing { deficit } => format!("{} short", deficit),
+        WcAnomaly::Intermittent { dropouts } => format!("intermittent, {} dropouts", dropouts),
+    }
+}
+
+/// A [`BusEvent`] with the wall clock time it was recorded at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoggedEvent {
+    pub time: SystemTime,
+    pub event: BusEvent,
+}
+
+impl fmt::Display for LoggedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let time = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        write!(
+            f,
+            "[{}.{:03}] {}",
+            time.as_secs(),
+            time.subsec_millis(),
+            self.event
+        )
+    }
+}
+
+/// A bounded log of emergencies, AL state changes and working counter
+/// incidents, to be inspected after a fault.
+///
+/// The oldest entries are discarded when the log is full. Executor events can
+/// be recorded from a hook, e.g. with the log in an `Arc<Mutex<_>>`:
+///
+/// ```ignore
+/// let log = Arc::new(Mutex::new(EventLog::new(1000)));
+/// let hook_log = log.clone();
+/// let executor = Executor::builder(master, period)
+///     .hook(move |event| hook_log.lock().unwrap().record_executor_event(event))
+///     .build()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    capacity: usize,
+    entries: VecDeque<LoggedEvent>,
+    discarded: u64,
+    al_states: Vec<Option<AlState>>,
+}
+
+impl EventLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: VecDeque::with_capacity(capacity),
+            discarded: 0,
+            al_states: vec![],
+        }
+    }
+
+    pub fn push(&mut self, event: BusEvent) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+            self.discarded += 1;
+        }
+        self.entries.push_back(LoggedEvent {
+            time: SystemTime::now(),
+            event,
+        });
+    }
+
+    /// Record working counter incidents reported by the executor.
+    pub fn record_executor_event(&mut self, event: &ExecutorEvent) {
+        if let ExecutorEvent::WorkingCounter {
+            cycle,
+            domain,
+            working_counter,
+            anomaly,
+            ..
+        } = *event
+        {
+            self.push(BusEvent::WorkingCounter {
+                cycle,
+                domain,
+                working_counter,
+                anomaly,
+            });
+        }
+    }
+
+    /// Record all pending emergencies of a slave config and return their
+    /// number.
+    ///
+    /// Needs the application's master handle; the emergency ring must have
+    /// been enabled with [`SlaveConfig::set_emerg_size`](crate::SlaveConfig::set_emerg_size).
+    #[cfg(target_os = "linux")]
+    pub fn poll_emergencies<S>(
+        &mut self,
+        master: &Master<S>,
+        config: SlaveConfigIdx,
+    ) -> Result<usize> {
+        let mut sc = master.slave_config(config);
+        let mut count = 0;
+        loop {
+            let mut raw = [0; 8];
+            match sc.pop_emerg(&mut raw) {
+                Ok(()) => (),
+                Err(e) if e.io_error().map(io::Error::kind) == Some(io::ErrorKind::NotFound) => {
+                    return Ok(count)
+                }
+                Err(e) => return Err(e),
+            }
+            self.push(BusEvent::Emergency {
+                config,
+                emergency: Emergency::from_bytes(raw),
+            });
+            count += 1;
+        }
+    }
+
+    /// Record the AL states of all slaves that changed since the last call.
+    #[cfg(target_os = "linux")]
+    pub fn poll_al_states(&mut self, master: &MasterMonitor) -> Result<()> {
+        let count = master.get_info()?.slave_count as usize;
+        self.al_states.resize(count, None);
+        for i in 0..count {
+            let slave = SlavePos::from(i as u16);
+            let to = master.get_slave_info(slave)?.al_state;
+            let from = self.al_states[i];
+            if from != Some(to) {
+                self.push(BusEvent::AlStateChange { slave, from, to });
+                self.al_states[i] = Some(to);
+            }
+        }
+        Ok(())
+    }
+
+    /// Recorded events, oldest first.
+    pub fn entries(&self) -> impl Iterator<Item = &LoggedEvent> + '_ {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of events discarded because the log was full.
+    pub fn discarded(&self) -> u64 {
+        self.discarded
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Write one line per event.
+    pub fn dump(&self, out: &mut dyn Write) -> io::Result<()> {
+        if self.discarded > 0 {
+            writeln!(out, "({} older events discarded)", self.discarded)?;
+        }
+        for entry in &self.entries {
+            writeln!(out, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_event_log() {
+    let mut log = EventLog::new(2);
+    log.push(BusEvent::AlStateChange {
+        slave: SlavePos::from(1),
+        from: None,
+        to: AlState::PreOp,
+    });
+    log.push(BusEvent::AlStateChange {
+        slave: SlavePos::from(1),
+        from: Some(AlState::PreOp),
+        to: AlState::Op,
+    });
+    log.record_executor_event(&ExecutorEvent::WorkingCounter {
+        cycle: 7,
+        domain: DomainIdx::from(0),
+        working_counter: 3,
+        wc_state: WcState::Incomplete,
+        anomaly: WcAnomaly::SlaveMissing {
+            slave: SlavePos::from(2),
+        },
+    });
+    assert_eq!(log.len(), 2);
+    assert_eq!(log.discarded(), 1);
+    let lines = log
+        .entries()
+        .map(|e| e.event.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            "slave 1: AL state PreOp -> Op",
+            "domain 0: working counter 3 in cycle 7 (slave 2 missing)"
+        ]
+    );
+}
@@ -0,0 +1,63 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+/// pcap magic number for nanosecond timestamps.
+const PCAP_MAGIC_NS: u32 = 0xA1B2_3C4D;
+const LINKTYPE_ETHERNET: u32 = 1;
+pub(super) const SNAPLEN: usize = 0xFFFF;
+
+/// Writes Ethernet frames in the pcap format, readable by Wireshark.
+pub struct PcapWriter<W> {
+    out: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Write the file header.
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(&PCAP_MAGIC_NS.to_le_bytes())?;
+        out.write_all(&2_u16.to_le_bytes())?;
+        out.write_all(&4_u16.to_le_bytes())?;
+        // time zone offset and timestamp accuracy
+        out.write_all(&[0; 8])?;
+        out.write_all(&(SNAPLEN as u32).to_le_bytes())?;
+        out.write_all(&LINKTYPE_ETHERNET.to_le_bytes())?;
+        Ok(Self { out })
+    }
+
+    /// Write a frame received at `time` since the Unix epoch.
+    pub fn write_frame(&mut self, time: Duration, frame: &[u8]) -> io::Result<()> {
+        let len = frame.len().min(SNAPLEN);
+        self.out.write_all(&(time.as_secs() as u32).to_le_bytes())?;
+        self.out.write_all(&time.subsec_nanos().to_le_bytes())?;
+        self.out.write_all(&(len as u32).to_le_bytes())?;
+        self.out.write_all(&(frame.len() as u32).to_le_bytes())?;
+        self.out.write_all(&frame[..len])
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+#[test]
+fn test_pcap_writer() {
+    let mut pcap = PcapWriter::new(vec![]).unwrap();
+    pcap.write_frame(Duration::new(2, 5), &[0xAA; 3]).unwrap();
+    let data = pcap.into_inner();
+    assert_eq!(data.len(), 24 + 16 + 3);
+    assert_eq!(data[..4], [0x4D, 0x3C, 0xB2, 0xA1]);
+    assert_eq!(data[20..24], [1, 0, 0, 0]);
+    assert_eq!(
+        data[24..40],
+        [2, 0, 0, 0, 5, 0, 0, 0, 3, 0, 0, 0, 3, 0, 0, 0]
+    );
+}
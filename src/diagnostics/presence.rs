@@ -0,0 +1,173 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+#[cfg(target_os = "linux")]
+use crate::master::MasterMonitor;
+use crate::types::*;
+use std::{collections::VecDeque, time::SystemTime};
+
+/// Transitions kept per slave.
+const HISTORY_LEN: usize = 32;
+
+/// A slave appearing on or disappearing from the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresenceChange {
+    pub time: SystemTime,
+    /// Application (DC) time of the master at the change, if set.
+    pub dc_time: Option<u64>,
+    pub online: bool,
+}
+
+/// Presence statistics of an expected slave.
+#[derive(Debug, Clone)]
+pub struct SlavePresence {
+    pub slave: SlavePos,
+    pub id: SlaveId,
+    pub serial_number: u32,
+    pub online: bool,
+    /// Number of times the slave disappeared.
+    pub dropouts: u64,
+    /// Number of times the slave came back.
+    pub reconnects: u64,
+    pub last_seen: Option<SystemTime>,
+    pub last_seen_dc: Option<u64>,
+    history: VecDeque<PresenceChange>,
+}
+
+impl SlavePresence {
+    /// The last transitions, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &PresenceChange> + '_ {
+        self.history.iter()
+    }
+}
+
+/// Records when the expected slaves appear and disappear.
+///
+/// A slave counts as present if a slave with the same identity and serial
+/// number is found at its position. Like the other diagnostics,
+/// [`sample`](Self::sample) is meant to be called periodically.
+#[derive(Debug, Clone)]
+pub struct PresenceTracker {
+    slaves: Vec<SlavePresence>,
+}
+
+impl PresenceTracker {
+    /// Expect the given slaves, which are considered online.
+    pub fn new(expected: &[SlaveInfo]) -> Self {
+        Self {
+            slaves: expected
+                .iter()
+                .map(|s| SlavePresence {
+                    slave: s.ring_pos,
+                    id: s.id,
+                    serial_number: s.rev.serial_number,
+                    online: true,
+                    dropouts: 0,
+                    reconnects: 0,
+                    last_seen: Some(SystemTime::now()),
+                    last_seen_dc: None,
+                    history: VecDeque::new(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Expect the slaves currently on the bus.
+    #[cfg(target_os = "linux")]
+    pub fn from_bus(master: &MasterMonitor) -> Result<Self> {
+        Ok(Self::new(&scan(master)?))
+    }
+
+    /// Scan the bus and update the presence of all expected slaves.
+    ///
+    /// Returns the slaves whose presence changed.
+    #[cfg(target_os = "linux")]
+    pub fn sample(&mut self, master: &MasterMonitor) -> Result<Vec<(SlavePos, bool)>> {
+        let app_time = master.get_info()?.app_time;
+        let dc_time = if app_time == 0 { None } else { Some(app_time) };
+        let present = scan(master)?;
+        Ok(self.update(&present, SystemTime::now(), dc_time))
+    }
+
+    /// Like [`sample`](Self::sample), with the slaves scanned elsewhere.
+    pub fn update(
+        &mut self,
+        present: &[SlaveInfo],
+        time: SystemTime,
+        dc_time: Option<u64>,
+    ) -> Vec<(SlavePos, bool)> {
+        let mut changes = vec![];
+        for s in &mut self.slaves {
+            let online = present.iter().any(|p| {
+                p.ring_pos == s.slave && p.id == s.id && p.rev.serial_number == s.serial_number
+            });
+            if online {
+                s.last_seen = Some(time);
+                s.last_seen_dc = dc_time;
+            }
+            if online == s.online {
+                continue;
+            }
+            if online {
+                s.reconnects += 1;
+            } else {
+                s.dropouts += 1;
+            }
+            s.online = online;
+            if s.history.len() == HISTORY_LEN {
+                s.history.pop_front();
+            }
+            s.history.push_back(PresenceChange {
+                time,
+                dc_time,
+                online,
+            });
+            changes.push((s.slave, online));
+        }
+        changes
+    }
+
+    pub fn slaves(&self) -> &[SlavePresence] {
+        &self.slaves
+    }
+
+    pub fn get(&self, slave: SlavePos) -> Option<&SlavePresence> {
+        self.slaves.iter().find(|s| s.slave == slave)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn scan(master: &MasterMonitor) -> Result<Vec<SlaveInfo>> {
+    let count = master.get_info()?.slave_count as u16;
+    (0..count)
+        .map(|i| master.get_slave_info(SlavePos::from(i)))
+        .collect()
+}
+
+#[test]
+fn test_presence() {
+    use crate::topology::test_slave;
+
+    const N: u16 = 0xFFFF;
+    let bus = [test_slave(0, [N, 1, N, N]), test_slave(1, [0, N, N, N])];
+    let mut tracker = PresenceTracker::new(&bus);
+    let t = SystemTime::now();
+    assert!(tracker.update(&bus, t, Some(100)).is_empty());
+    assert_eq!(
+        tracker.update(&bus[..1], t, Some(200)),
+        [(SlavePos::from(1), false)]
+    );
+    assert_eq!(
+        tracker.update(&bus, t, Some(300)),
+        [(SlavePos::from(1), true)]
+    );
+    let s = tracker.get(SlavePos::from(1)).unwrap();
+    assert_eq!(
+        (s.dropouts, s.reconnects, s.last_seen_dc),
+        (1, 1, Some(300))
+    );
+    assert_eq!(
+        s.history().map(|c| c.dc_time).collect::<Vec<_>>(),
+        [Some(200), Some(300)]
+    );
+}
@@ -0,0 +1,313 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::al_status::{AlStatus, AL_STATUS};
+#[cfg(target_os = "linux")]
+use crate::master::Master;
+use crate::types::*;
+use std::{
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+/// Mailbox protocol bit of CoE.
+const MBOX_COE: u16 = 0x04;
+/// Device type object, present on every CoE slave.
+const DEVICE_TYPE: u16 = 0x1000;
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTestCheck {
+    /// The slave found at a configured position has the configured identity.
+    Identity,
+    /// The slave goes from PreOp to SafeOp and back.
+    StateTransition,
+    /// The slave supports DC if its configuration uses it.
+    DistributedClocks,
+    /// The CoE mailbox answers an SDO upload.
+    Mailbox,
+}
+
+/// Outcome of one check of the self-test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestResult {
+    pub check: SelfTestCheck,
+    /// The slave checked, `None` if no slave was found for a configuration.
+    pub slave: Option<SlavePos>,
+    pub config: Option<SlaveConfigIdx>,
+    pub passed: bool,
+    pub detail: String,
+}
+
+impl fmt::Display for SelfTestResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {:?}",
+            if self.passed { "PASS" } else { "FAIL" },
+            self.check
+        )?;
+        if let Some(slave) = self.slave {
+            write!(f, " slave {}", u16::from(slave))?;
+        }
+        if let Some(config) = self.config {
+            write!(f, " config {}", config)?;
+        }
+        write!(f, ": {}", self.detail)
+    }
+}
+
+/// Results of [`Master::selftest`](crate::Master::selftest).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelfTestReport {
+    pub results: Vec<SelfTestResult>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|r| r.passed)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &SelfTestResult> + '_ {
+        self.results.iter().filter(|r| !r.passed)
+    }
+
+    fn push(
+        &mut self,
+        check: SelfTestCheck,
+        slave: Option<SlavePos>,
+        config: Option<SlaveConfigIdx>,
+        passed: bool,
+        detail: String,
+    ) {
+        self.results.push(SelfTestResult {
+            check,
+            slave,
+            config,
+            passed,
+            detail,
+        });
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for result in &self.results {
+            writeln!(f, "{}", result)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn run<S>(master: &mut Master<S>, timeout: Duration) -> Result<SelfTestReport> {
+    let info = master.get_info()?;
+    let slaves = (0..info.slave_count as u16)
+        .map(|i| master.get_slave_info(SlavePos::from(i)))
+        .collect::<Result<Vec<_>>>()?;
+    let mut report = SelfTestReport::default();
+
+    for idx in 0..info.config_count {
+        let config = master.get_config_info(idx)?;
+        check_config(&mut report, idx, &config, &slaves);
+    }
+
+    for slave in slaves
+        .iter()
+        .filter(|s| s.mailbox_protocols & MBOX_COE != 0)
+    {
+        let pos = slave.ring_pos;
+        let mut buf = [0; 4];
+        let (passed, detail) =
+            match master.sdo_upload(pos, SdoIdx::new(DEVICE_TYPE, 0), false, &mut buf) {
+                Ok(_) => (
+                    true,
+                    format!("device type 0x{:08x}", u32::from_le_bytes(buf)),
+                ),
+                Err(e) => (false, format!("SDO upload of 0x1000 failed: {}", e)),
+            };
+        report.push(SelfTestCheck::Mailbox, Some(pos), None, passed, detail);
+    }
+
+    let mut candidates = vec![];
+    for slave in &slaves {
+        let pos = slave.ring_pos;
+        if slave.al_state == AlState::PreOp {
+            candidates.push(pos);
+        } else {
+            report.push(
+                SelfTestCheck::StateTransition,
+                Some(pos),
+                None,
+                false,
+                format!("slave is in {:?}, expected PreOp", slave.al_state),
+            );
+        }
+    }
+    // slaves refusing SafeOp are also sent back, to clear their error
+    let mut failed = transition(master, &candidates, AlState::SafeOp, timeout)?;
+    failed.extend(transition(master, &candidates, AlState::PreOp, timeout)?);
+    for pos in candidates {
+        let (passed, detail) = match failed.iter().find(|(f, _)| *f == pos) {
+            Some((_, detail)) => (false, detail.clone()),
+            None => (true, "PreOp -> SafeOp -> PreOp".into()),
+        };
+        report.push(
+            SelfTestCheck::StateTransition,
+            Some(pos),
+            None,
+            passed,
+            detail,
+        );
+    }
+    Ok(report)
+}
+
+/// Check the identity and DC capability of the slave of a configuration.
+fn check_config(
+    report: &mut SelfTestReport,
+    idx: SlaveConfigIdx,
+    config: &ConfigInfo,
+    slaves: &[SlaveInfo],
+) {
+    let expected = config.id;
+    let slave = match config.slave_position {
+        Some(pos) => slaves.get(u16::from(pos) as usize),
+        None => find_slave(slaves, config.alias, config.position),
+    };
+    let slave = match slave {
+        Some(slave) => slave,
+        None => {
+            report.push(
+                SelfTestCheck::Identity,
+                None,
+                Some(idx),
+                false,
+                format!(
+                    "no slave at alias {} position {}",
+                    config.alias, config.position
+                ),
+            );
+            return;
+        }
+    };
+    let pos = Some(slave.ring_pos);
+    let id = |id: SlaveId| format!("0x{:08x}:0x{:08x}", id.vendor_id, id.product_code);
+    if slave.id == expected {
+        report.push(SelfTestCheck::Identity, pos, Some(idx), true, id(slave.id));
+    } else {
+        report.push(
+            SelfTestCheck::Identity,
+            pos,
+            Some(idx),
+            false,
+            format!("expected {}, found {}", id(expected), id(slave.id)),
+        );
+    }
+    if config.dc_assign_activate != 0 {
+        let (passed, detail) = if slave.has_dc_system_time {
+            (true, "DC supported".to_string())
+        } else {
+            (false, "DC configured but not supported".to_string())
+        };
+        report.push(
+            SelfTestCheck::DistributedClocks,
+            pos,
+            Some(idx),
+            passed,
+            detail,
+        );
+    }
+}
+
+/// The slave addressed by an alias and a position relative to it.
+fn find_slave(slaves: &[SlaveInfo], alias: u16, position: u16) -> Option<&SlaveInfo> {
+    let base = if alias == 0 {
+        0
+    } else {
+        slaves.iter().position(|s| s.alias == alias)?
+    };
+    slaves.get(base + position as usize)
+}
+
+/// Request `state` for the slaves and wait until they reach it, returns the
+/// ones that did not with the reason.
+#[cfg(target_os = "linux")]
+fn transition<S>(
+    master: &mut Master<S>,
+    slaves: &[SlavePos],
+    state: AlState,
+    timeout: Duration,
+) -> Result<Vec<(SlavePos, String)>> {
+    for slave in slaves {
+        master.request_state(*slave, state)?;
+    }
+    let start = Instant::now();
+    let mut pending = slaves.to_vec();
+    let mut failed = vec![];
+    while !pending.is_empty() {
+        let mut still = vec![];
+        for slave in pending {
+            let info = master.get_slave_info(slave)?;
+            if info.error_flag != 0 {
+                let mut regs = [0; 6];
+                master.read_register(slave, AL_STATUS, &mut regs)?;
+                let status = AlStatus::from_registers(regs);
+                failed.push((slave, format!("refused {:?}: {}", state, status)));
+            } else if info.al_state != state {
+                still.push(slave);
+            }
+        }
+        pending = still;
+        if start.elapsed() > timeout {
+            failed.extend(
+                pending
+                    .drain(..)
+                    .map(|slave| (slave, format!("timeout waiting for {:?}", state))),
+            );
+        }
+        if !pending.is_empty() {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    Ok(failed)
+}
+
+#[test]
+fn test_check_config() {
+    use crate::topology::test_slave;
+
+    const N: u16 = 0xFFFF;
+    let mut slaves = vec![test_slave(0, [N, 1, N, N]), test_slave(1, [0, N, N, N])];
+    slaves[1].alias = 7;
+    slaves[1].id = SlaveId::new(2, 0x07d8_3052);
+    slaves[1].has_dc_system_time = false;
+    let config = |alias, position, dc_assign_activate| ConfigInfo {
+        alias,
+        position,
+        id: SlaveId::new(2, 0x044c_2c52),
+        slave_position: None,
+        sdo_count: 0,
+        idn_count: 0,
+        dc_assign_activate,
+        syncs: vec![],
+    };
+
+    let mut report = SelfTestReport::default();
+    check_config(&mut report, 0, &config(0, 0, 0x300), &slaves);
+    check_config(&mut report, 1, &config(7, 0, 0x300), &slaves);
+    check_config(&mut report, 2, &config(7, 1, 0), &slaves);
+    let lines = report.to_string();
+    assert_eq!(
+        lines.lines().collect::<Vec<_>>(),
+        [
+            "PASS Identity slave 0 config 0: 0x00000002:0x044c2c52",
+            "PASS DistributedClocks slave 0 config 0: DC supported",
+            "FAIL Identity slave 1 config 1: expected 0x00000002:0x044c2c52, \
+             found 0x00000002:0x07d83052",
+            "FAIL DistributedClocks slave 1 config 1: DC configured but not supported",
+            "FAIL Identity config 2: no slave at alias 7 position 1",
+        ]
+    );
+    assert_eq!(report.failures().count(), 3);
+}
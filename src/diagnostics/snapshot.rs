@@ -0,0 +1,275 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::events::{EventLog, LoggedEvent};
+#[cfg(target_os = "linux")]
+use crate::master::Master;
+use crate::{
+    json,
+    runtime::{CycleStats, Phase, PhaseSummary},
+    topology::{port_name, Link, Topology},
+    types::*,
+};
+use std::{
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The state of the master and the bus at one point in time, to be attached
+/// to support tickets.
+///
+/// Events and timing statistics are owned by the application and can be
+/// added with [`with_events`](Self::with_events) and
+/// [`with_timing`](Self::with_timing).
+#[derive(Debug, Clone)]
+pub struct DiagnosticSnapshot {
+    pub time: SystemTime,
+    pub info: MasterInfo,
+    pub health: BusHealth,
+    pub slaves: Vec<SlaveInfo>,
+    pub configs: Vec<ConfigInfo>,
+    pub topology: Topology,
+    pub events: Vec<LoggedEvent>,
+    pub timing: Option<CycleStats>,
+}
+
+impl DiagnosticSnapshot {
+    #[cfg(target_os = "linux")]
+    pub(crate) fn read<S>(master: &Master<S>, health: BusHealth) -> Result<Self> {
+        let info = master.get_info()?;
+        let slaves = (0..info.slave_count as u16)
+            .map(|i| master.get_slave_info(SlavePos::from(i)))
+            .collect::<Result<Vec<_>>>()?;
+        let configs = (0..info.config_count)
+            .map(|i| master.get_config_info(i))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            time: SystemTime::now(),
+            health,
+            topology: Topology::from_slaves(&slaves),
+            info,
+            slaves,
+            configs,
+            events: vec![],
+            timing: None,
+        })
+    }
+
+    /// Include the entries of an event log.
+    pub fn with_events(mut self, log: &EventLog) -> Self {
+        self.events = log.entries().copied().collect();
+        self
+    }
+
+    /// Include the cycle timing statistics of an executor.
+    pub fn with_timing(mut self, stats: &CycleStats) -> Self {
+        self.timing = Some(stats.clone());
+        self
+    }
+
+    pub fn write_json(&self, out: &mut dyn Write) -> io::Result<()> {
+        let info = &self.info;
+        write!(out, "{{\"time\":{},", unix_time(self.time))?;
+        write!(
+            out,
+            "\"master\":{{\"slave_count\":{},\"config_count\":{},\"domain_count\":{},\
+             \"link_up\":{},\"scan_busy\":{},\"app_time\":{}}},",
+            info.slave_count,
+            info.config_count,
+            info.domain_count,
+            info.link_up,
+            info.scan_busy,
+            info.app_time
+        )?;
+
+        write!(out, "\"health\":")?;
+        json::write_health(out, &self.health)?;
+        write!(out, ",\"slaves\":[")?;
+
+        for (i, slave) in self.slaves.iter().enumerate() {
+            separator(out, i)?;
+            write!(
+                out,
+                "{{\"position\":{},\"name\":",
+                u16::from(slave.ring_pos)
+            )?;
+            json::write_str(out, &slave.name)?;
+            write!(
+                out,
+                ",\"alias\":{},\"vendor_id\":{},\"product_code\":{},\
+                 \"revision_number\":{},\"serial_number\":{},\"al_state\":\"{:?}\",\
+                 \"error_flag\":{},\"current_on_ebus\":{},\"dc_system_time\":{},\
+                 \"sii_nwords\":{},\"ports\":[",
+                slave.alias,
+                slave.id.vendor_id,
+                slave.id.product_code,
+                slave.rev.revision_number,
+                slave.rev.serial_number,
+                slave.al_state,
+                slave.error_flag,
+                slave.current_on_ebus,
+                slave.has_dc_system_time,
+                slave.sii_nwords
+            )?;
+            for (p, port) in slave.ports.iter().enumerate() {
+                separator(out, p)?;
+                write!(
+                    out,
+                    "{{\"type\":\"{:?}\",\"link_up\":{},\"loop_closed\":{},\
+                     \"signal_detected\":{},\"receive_time\":{}}}",
+                    port.desc,
+                    port.link.link_up,
+                    port.link.loop_closed,
+                    port.link.signal_detected,
+                    port.receive_time
+                )?;
+            }
+            write!(out, "]}}")?;
+        }
+        write!(out, "],\"configs\":[")?;
+
+        for (i, config) in self.configs.iter().enumerate() {
+            separator(out, i)?;
+            write!(
+                out,
+                "{{\"alias\":{},\"position\":{},\"vendor_id\":{},\"product_code\":{},\
+                 \"slave_position\":",
+                config.alias, config.position, config.id.vendor_id, config.id.product_code
+            )?;
+            match config.slave_position {
+                Some(pos) => write!(out, "{}", u16::from(pos))?,
+                None => write!(out, "null")?,
+            }
+            write!(
+                out,
+                ",\"sdo_count\":{},\"idn_count\":{}}}",
+                config.sdo_count, config.idn_count
+            )?;
+        }
+        write!(out, "],\"topology\":[")?;
+
+        for (i, node) in self.topology.nodes().iter().enumerate() {
+            separator(out, i)?;
+            write!(out, "{{\"slave\":{},\"parent\":", u16::from(node.slave))?;
+            match node.parent {
+                Some(parent) => write_link(out, parent)?,
+                None => write!(out, "null")?,
+            }
+            write!(out, ",\"children\":[")?;
+            for (c, child) in node.children.iter().enumerate() {
+                separator(out, c)?;
+                write_link(out, *child)?;
+            }
+            write!(out, "]}}")?;
+        }
+        write!(out, "],\"events\":[")?;
+
+        for (i, entry) in self.events.iter().enumerate() {
+            separator(out, i)?;
+            write!(out, "{{\"time\":{},\"event\":", unix_time(entry.time))?;
+            json::write_str(out, &entry.event.to_string())?;
+            write!(out, "}}")?;
+        }
+        write!(out, "],\"timing\":")?;
+
+        match &self.timing {
+            Some(stats) => {
+                write!(out, "{{\"cycles\":{}", stats.len())?;
+                for phase in Phase::ALL.iter() {
+                    let name = format!("{:?}", phase).to_lowercase();
+                    write!(out, ",\"{}\":", name)?;
+                    write_summary(out, &stats.phase(*phase))?;
+                }
+                write!(out, ",\"total\":")?;
+                write_summary(out, &stats.total())?;
+                write!(out, "}}")?;
+            }
+            None => write!(out, "null")?,
+        }
+        write!(out, "}}")
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = vec![];
+        self.write_json(&mut out)
+            .expect("writing to a Vec does not fail");
+        String::from_utf8(out).expect("JSON output is UTF-8")
+    }
+}
+
+fn separator(out: &mut dyn Write, i: usize) -> io::Result<()> {
+    if i > 0 {
+        write!(out, ",")?;
+    }
+    Ok(())
+}
+
+fn unix_time(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+fn write_link(out: &mut dyn Write, link: Link) -> io::Result<()> {
+    write!(
+        out,
+        "{{\"slave\":{},\"port\":\"{}\"}}",
+        u16::from(link.slave),
+        port_name(link.port)
+    )
+}
+
+fn write_summary(out: &mut dyn Write, summary: &PhaseSummary) -> io::Result<()> {
+    write!(
+        out,
+        "{{\"last_ns\":{},\"min_ns\":{},\"max_ns\":{},\"mean_ns\":{}}}",
+        summary.last.as_nanos(),
+        summary.min.as_nanos(),
+        summary.max.as_nanos(),
+        summary.mean.as_nanos()
+    )
+}
+
+#[test]
+fn test_diagnostic_snapshot_json() {
+    use crate::topology::test_slave;
+
+    const NONE: u16 = 0xFFFF;
+    let mut slaves = vec![
+        test_slave(0, [NONE, 1, NONE, NONE]),
+        test_slave(1, [0, NONE, NONE, NONE]),
+    ];
+    slaves[1].name = "EL\"1008\"".into();
+    let snapshot = DiagnosticSnapshot {
+        time: UNIX_EPOCH,
+        info: MasterInfo {
+            slave_count: 2,
+            config_count: 0,
+            domain_count: 0,
+            link_up: true,
+            scan_busy: false,
+            app_time: 0,
+        },
+        health: BusHealth {
+            link_up: true,
+            slaves_responding: 2,
+            slaves_configured: 0,
+            worst_al_state: Some(AlState::PreOp),
+            domains: vec![],
+            dc_deviation: None,
+            emergency_overruns: 0,
+            power_faults: vec![],
+        },
+        topology: Topology::from_slaves(&slaves),
+        slaves,
+        configs: vec![],
+        events: vec![],
+        timing: Some(CycleStats::new(10)),
+    };
+    let json = snapshot.to_json();
+    assert!(json.starts_with("{\"time\":0,\"master\":{\"slave_count\":2,"));
+    assert!(json.contains("\"worst_al_state\":\"PreOp\",\"dc_deviation\":null"));
+    assert!(json.contains("\"name\":\"EL\\\"1008\\\"\""));
+    assert!(json.contains("{\"slave\":1,\"parent\":{\"slave\":0,\"port\":\"B\"},\"children\":[]}"));
+    assert!(json.ends_with("\"events\":[],\"timing\":{\"cycles\":0,\"receive\":{\"last_ns\":0,\"min_ns\":0,\"max_ns\":0,\"mean_ns\":0},\"process\":{\"last_ns\":0,\"min_ns\":0,\"max_ns\":0,\"mean_ns\":0},\"user\":{\"last_ns\":0,\"min_ns\":0,\"max_ns\":0,\"mean_ns\":0},\"queue\":{\"last_ns\":0,\"min_ns\":0,\"max_ns\":0,\"mean_ns\":0},\"send\":{\"last_ns\":0,\"min_ns\":0,\"max_ns\":0,\"mean_ns\":0},\"total\":{\"last_ns\":0,\"min_ns\":0,\"max_ns\":0,\"mean_ns\":0}}}"));
+}
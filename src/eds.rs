@@ -0,0 +1,680 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! CANopen Electronic Data Sheets (EDS) and Device Configuration Files (DCF).
+//!
+//! CoE devices often ship an EDS with the object dictionary that their ESI
+//! file lacks. An [`Eds`] holds the data type, access rights, limits and
+//! default value of every entry, to decode and check SDO values without
+//! asking the device for its dictionary; the configured values of a DCF are
+//! read as well.
+
+use crate::{backend::Backend, types::*};
+use num_traits::FromPrimitive;
+use std::{cmp::Ordering, collections::BTreeMap, convert::TryFrom, fs, io, path::Path};
+
+/// Largest SDO of variable size that can be uploaded.
+const SDO_BUFFER: usize = 4096;
+
+const OBJECT_VAR: u8 = 7;
+
+/// An entry of an object, or a variable as its only entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdsEntry {
+    pub sub_index: u8,
+    pub name: String,
+    pub data_type: DataType,
+    pub access: Access,
+    /// The entry can be mapped into a PDO.
+    pub pdo_mapping: bool,
+    pub low_limit: Option<Value>,
+    pub high_limit: Option<Value>,
+    pub default: Option<Value>,
+    /// Value configured in a DCF.
+    pub value: Option<Value>,
+}
+
+/// An object of the dictionary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdsObject {
+    pub index: u16,
+    pub name: String,
+    /// 7 for variables, 8 for arrays and 9 for records.
+    pub object_code: u8,
+    pub entries: Vec<EdsEntry>,
+}
+
+impl EdsObject {
+    pub fn entry(&self, sub_index: u8) -> Option<&EdsEntry> {
+        self.entries.iter().find(|e| e.sub_index == sub_index)
+    }
+}
+
+/// The device description and dictionary of an EDS or DCF file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Eds {
+    pub vendor_id: u32,
+    pub product_code: u32,
+    pub revision: u32,
+    pub product_name: String,
+    objects: BTreeMap<u16, EdsObject>,
+}
+
+fn invalid_data(msg: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+fn invalid_input(msg: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidInput, msg))
+}
+
+/// Sections of an INI file with lower-case keys.
+type Sections = BTreeMap<String, BTreeMap<String, String>>;
+
+fn parse_ini(text: &str) -> Result<Sections> {
+    let mut sections = Sections::new();
+    let mut current = None;
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let name = name.trim().to_ascii_lowercase();
+            sections.entry(name.clone()).or_default();
+            current = Some(name);
+            continue;
+        }
+        let (key, value) = match (line.split_once('='), &current) {
+            (Some(kv), Some(_)) => kv,
+            _ => return Err(invalid_data(format!("EDS line {}: {:?}", n + 1, line))),
+        };
+        let section = sections.get_mut(current.as_ref().unwrap()).unwrap();
+        section.insert(key.trim().to_ascii_lowercase(), value.trim().into());
+    }
+    Ok(sections)
+}
+
+/// Parse a decimal, `0x` prefixed hexadecimal or (with a leading 0) octal
+/// number.
+fn parse_int(s: &str) -> Option<i128> {
+    let s = s.trim();
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let value = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        i128::from_str_radix(hex, 16).ok()?
+    } else if s.len() > 1 && s.starts_with('0') {
+        i128::from_str_radix(&s[1..], 8).ok()?
+    } else {
+        s.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Parse a value of an EDS key; values relative to `$NODEID` are not known.
+fn parse_value(data_type: DataType, s: &str) -> Option<Value> {
+    let s = s.trim();
+    if s.is_empty() || s.to_ascii_uppercase().contains("$NODEID") {
+        return None;
+    }
+    let int = || parse_int(s);
+    Some(match data_type {
+        DataType::Bool => Value::Bool(int()? != 0),
+        DataType::Byte => Value::Byte(u8::try_from(int()?).ok()?),
+        DataType::I8 => Value::I8(i8::try_from(int()?).ok()?),
+        DataType::I16 => Value::I16(i16::try_from(int()?).ok()?),
+        DataType::I24 | DataType::I32 => Value::I32(i32::try_from(int()?).ok()?),
+        DataType::I40 | DataType::I48 | DataType::I56 | DataType::I64 => {
+            Value::I64(i64::try_from(int()?).ok()?)
+        }
+        DataType::U8 => Value::U8(u8::try_from(int()?).ok()?),
+        DataType::U16 => Value::U16(u16::try_from(int()?).ok()?),
+        DataType::U24 | DataType::U32 => Value::U32(u32::try_from(int()?).ok()?),
+        DataType::U40 | DataType::U48 | DataType::U56 | DataType::U64 => {
+            Value::U64(u64::try_from(int()?).ok()?)
+        }
+        DataType::F32 => Value::F32(s.parse().ok()?),
+        DataType::F64 => Value::F64(s.parse().ok()?),
+        DataType::String => Value::String(s.into()),
+        DataType::U8Array => Value::U8Array(
+            (0..s.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+                .collect::<Option<_>>()?,
+        ),
+        _ => return None,
+    })
+}
+
+/// Size in bits of the values of a data type, `None` for strings and
+/// domains.
+fn bit_len(data_type: DataType) -> Option<u16> {
+    Some(match data_type {
+        DataType::Bool => 1,
+        DataType::Bit1 => 1,
+        DataType::Bit2 => 2,
+        DataType::Bit3 => 3,
+        DataType::Bit4 => 4,
+        DataType::Bit5 => 5,
+        DataType::Bit6 => 6,
+        DataType::Bit7 => 7,
+        DataType::Bit8 | DataType::Byte | DataType::I8 | DataType::U8 => 8,
+        DataType::I16 | DataType::U16 => 16,
+        DataType::I24 | DataType::U24 => 24,
+        DataType::I32 | DataType::U32 | DataType::F32 => 32,
+        DataType::I40 | DataType::U40 => 40,
+        DataType::I48 | DataType::U48 | DataType::TimeOfDay | DataType::TimeDifference => 48,
+        DataType::I56 | DataType::U56 => 56,
+        DataType::I64 | DataType::U64 | DataType::F64 => 64,
+        DataType::String
+        | DataType::U8Array
+        | DataType::U16Array
+        | DataType::Domain
+        | DataType::Raw => return None,
+    })
+}
+
+fn parse_access(s: &str) -> Access {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "ro" | "const" => Access::ReadOnly,
+        "wo" => Access::WriteOnly,
+        "rw" | "rwr" | "rww" => Access::ReadWrite,
+        _ => Access::Unknown,
+    }
+}
+
+fn parse_entry(sub_index: u8, keys: &BTreeMap<String, String>) -> Result<EdsEntry> {
+    let get = |key: &str| keys.get(key).map(String::as_str).unwrap_or_default();
+    let code = parse_int(get("datatype")).ok_or_else(|| invalid_data("no DataType".into()))?;
+    let data_type = u16::from_i128(code)
+        .and_then(DataType::from_u16)
+        .unwrap_or(DataType::Raw);
+    let value = |key| parse_value(data_type, get(key));
+    Ok(EdsEntry {
+        sub_index,
+        name: get("parametername").into(),
+        data_type,
+        access: parse_access(get("accesstype")),
+        pdo_mapping: parse_int(get("pdomapping")).map_or(false, |m| m != 0),
+        low_limit: value("lowlimit"),
+        high_limit: value("highlimit"),
+        default: value("defaultvalue"),
+        value: value("parametervalue"),
+    })
+}
+
+fn parse_object(index: u16, sections: &Sections) -> Result<EdsObject> {
+    let keys = &sections[&format!("{:04x}", index)];
+    let get = |key: &str| keys.get(key).and_then(|v| parse_int(v));
+    let object_code = get("objecttype").unwrap_or(OBJECT_VAR as i128) as u8;
+    let context = |e: Error| invalid_data(format!("EDS object {:#06x}: {}", index, e));
+    let mut entries = vec![];
+    if object_code == OBJECT_VAR {
+        entries.push(parse_entry(0, keys).map_err(context)?);
+    } else {
+        let prefix = format!("{:04x}sub", index);
+        for (name, keys) in sections.range(prefix.clone()..) {
+            let sub = match name.strip_prefix(&prefix) {
+                Some(sub) => u8::from_str_radix(sub, 16)
+                    .map_err(|_| invalid_data(format!("EDS section [{}]", name)))?,
+                None => break,
+            };
+            entries.push(parse_entry(sub, keys).map_err(context)?);
+        }
+        // arrays may only give the number of their entries
+        let compact = get("compactsubobj").unwrap_or(0);
+        if compact > 0 && entries.is_empty() {
+            let mut entry = parse_entry(1, keys).map_err(context)?;
+            entries.push(EdsEntry {
+                sub_index: 0,
+                name: "Number of entries".into(),
+                data_type: DataType::U8,
+                access: Access::ReadOnly,
+                pdo_mapping: false,
+                low_limit: None,
+                high_limit: None,
+                default: Some(Value::U8(compact as u8)),
+                value: None,
+            });
+            entry.default = None;
+            for sub in 1..=compact as u8 {
+                entries.push(EdsEntry {
+                    sub_index: sub,
+                    name: format!("{} {}", keys.get("parametername").map_or("", |n| n), sub),
+                    ..entry.clone()
+                });
+            }
+        }
+    }
+    Ok(EdsObject {
+        index,
+        name: keys.get("parametername").cloned().unwrap_or_default(),
+        object_code,
+        entries,
+    })
+}
+
+/// Numeric value for comparisons with the limits.
+enum Number {
+    Int(i128),
+    Float(f64),
+}
+
+impl Number {
+    fn of(value: &Value) -> Option<Self> {
+        Some(match *value {
+            Value::Byte(v) | Value::U8(v) => Number::Int(v.into()),
+            Value::I8(v) => Number::Int(v.into()),
+            Value::I16(v) => Number::Int(v.into()),
+            Value::I32(v) => Number::Int(v.into()),
+            Value::I64(v) => Number::Int(v.into()),
+            Value::U16(v) => Number::Int(v.into()),
+            Value::U32(v) => Number::Int(v.into()),
+            Value::U64(v) => Number::Int(v.into()),
+            Value::F32(v) => Number::Float(v.into()),
+            Value::F64(v) => Number::Float(v),
+            _ => return None,
+        })
+    }
+
+    fn compare(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Int(a), Number::Int(b)) => Some(a.cmp(b)),
+            _ => self.to_f64().partial_cmp(&other.to_f64()),
+        }
+    }
+
+    fn to_f64(&self) -> f64 {
+        match *self {
+            Number::Int(v) => v as f64,
+            Number::Float(v) => v,
+        }
+    }
+}
+
+impl EdsEntry {
+    /// Size of the values in bits, `None` for strings and domains.
+    pub fn bit_len(&self) -> Option<u16> {
+        bit_len(self.data_type)
+    }
+
+    /// The entry as reported by [`Master::get_sdo_entry`](crate::Master::get_sdo_entry).
+    pub fn info(&self) -> SdoEntryInfo {
+        SdoEntryInfo {
+            data_type: self.data_type,
+            bit_len: self.bit_len().unwrap_or(0),
+            access: SdoEntryAccess {
+                pre_op: self.access,
+                safe_op: self.access,
+                op: self.access,
+            },
+            description: self.name.clone(),
+        }
+    }
+
+    /// Decode an uploaded value.
+    pub fn decode(&self, data: &[u8]) -> Result<Value> {
+        let size = self.bit_len().map(|bits| (bits as usize + 7) / 8);
+        if size.map_or(false, |size| data.len() < size) {
+            return Err(invalid_data(format!(
+                "{} bytes for a value of type {:?}",
+                data.len(),
+                self.data_type
+            )));
+        }
+        let int = |signed: bool| {
+            let size = size.unwrap_or(0);
+            let mut raw = [0; 8];
+            raw[..size].copy_from_slice(&data[..size]);
+            let value = u64::from_le_bytes(raw);
+            let shift = 64 - 8 * size as u32;
+            if signed {
+                ((value << shift) as i64 >> shift) as i128
+            } else {
+                value as i128
+            }
+        };
+        Ok(match self.data_type {
+            DataType::Bool => Value::Bool(data[0] & 1 != 0),
+            DataType::Byte => Value::Byte(data[0]),
+            DataType::I8 => Value::I8(data[0] as i8),
+            DataType::I16 => Value::I16(int(true) as i16),
+            DataType::I24 | DataType::I32 => Value::I32(int(true) as i32),
+            DataType::I40 | DataType::I48 | DataType::I56 | DataType::I64 => {
+                Value::I64(int(true) as i64)
+            }
+            DataType::U8 => Value::U8(data[0]),
+            DataType::U16 => Value::U16(int(false) as u16),
+            DataType::U24 | DataType::U32 => Value::U32(int(false) as u32),
+            DataType::U40 | DataType::U48 | DataType::U56 | DataType::U64 => {
+                Value::U64(int(false) as u64)
+            }
+            DataType::F32 => Value::F32(f32::from_bits(int(false) as u32)),
+            DataType::F64 => Value::F64(f64::from_bits(int(false) as u64)),
+            DataType::String => {
+                let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+                Value::String(String::from_utf8_lossy(&data[..end]).into())
+            }
+            DataType::U8Array => Value::U8Array(data.to_vec()),
+            DataType::U16Array => Value::U16Array(
+                data.chunks_exact(2)
+                    .map(|w| u16::from_le_bytes([w[0], w[1]]))
+                    .collect(),
+            ),
+            _ => Value::Raw(data.to_vec()),
+        })
+    }
+
+    /// Check a value against the data type and the limits of the entry.
+    pub fn check(&self, value: &Value) -> Result<()> {
+        let matches = matches!(
+            (self.data_type, value),
+            (DataType::Bool, Value::Bool(_))
+                | (DataType::Byte, Value::Byte(_))
+                | (DataType::I8, Value::I8(_))
+                | (DataType::I16, Value::I16(_))
+                | (DataType::I24, Value::I32(_))
+                | (DataType::I32, Value::I32(_))
+                | (DataType::I40, Value::I64(_))
+                | (DataType::I48, Value::I64(_))
+                | (DataType::I56, Value::I64(_))
+                | (DataType::I64, Value::I64(_))
+                | (DataType::U8, Value::U8(_))
+                | (DataType::U16, Value::U16(_))
+                | (DataType::U24, Value::U32(_))
+                | (DataType::U32, Value::U32(_))
+                | (DataType::U40, Value::U64(_))
+                | (DataType::U48, Value::U64(_))
+                | (DataType::U56, Value::U64(_))
+                | (DataType::U64, Value::U64(_))
+                | (DataType::F32, Value::F32(_))
+                | (DataType::F64, Value::F64(_))
+                | (DataType::String, Value::String(_))
+                | (DataType::U8Array, Value::U8Array(_))
+                | (DataType::U16Array, Value::U16Array(_))
+                | (_, Value::Raw(_))
+        );
+        if !matches {
+            return Err(invalid_input(format!(
+                "{:?} for an entry of type {:?}",
+                value, self.data_type
+            )));
+        }
+        if let (Some(bits), Some(Number::Int(v))) = (self.bit_len(), Number::of(value)) {
+            // the 24, 40, 48 and 56 bit types are held in wider values
+            let (min, max) = if is_signed(self.data_type) {
+                (-(1 << (bits - 1)), (1 << (bits - 1)) - 1)
+            } else {
+                (0, (1 << bits) - 1)
+            };
+            if v < min || v > max {
+                return Err(invalid_input(format!(
+                    "{} out of range for type {:?}",
+                    v, self.data_type
+                )));
+            }
+        }
+        if let Some(number) = Number::of(value) {
+            let outside = |limit: &Option<Value>, ordering| {
+                limit
+                    .as_ref()
+                    .and_then(Number::of)
+                    .and_then(|limit| number.compare(&limit))
+                    == Some(ordering)
+            };
+            if outside(&self.low_limit, Ordering::Less)
+                || outside(&self.high_limit, Ordering::Greater)
+            {
+                return Err(invalid_input(format!(
+                    "{:?} outside of the limits of {:?}",
+                    value, self.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse and check a value written like in an EDS, e.g. given on a
+    /// command line.
+    pub fn parse(&self, text: &str) -> Result<Value> {
+        let value = parse_value(self.data_type, text).ok_or_else(|| {
+            invalid_input(format!(
+                "{:?} is no value of type {:?}",
+                text, self.data_type
+            ))
+        })?;
+        self.check(&value)?;
+        Ok(value)
+    }
+
+    /// Check and encode a value for a download.
+    pub fn encode(&self, value: &Value) -> Result<Vec<u8>> {
+        self.check(value)?;
+        let size = self.bit_len().map_or(0, |bits| (bits as usize + 7) / 8);
+        Ok(match value {
+            Value::Bool(v) => vec![*v as u8],
+            Value::Byte(v) | Value::U8(v) => vec![*v],
+            Value::I8(v) => vec![*v as u8],
+            Value::I16(v) => v.to_le_bytes().to_vec(),
+            Value::I32(v) => v.to_le_bytes()[..size].to_vec(),
+            Value::I64(v) => v.to_le_bytes()[..size].to_vec(),
+            Value::U16(v) => v.to_le_bytes().to_vec(),
+            Value::U32(v) => v.to_le_bytes()[..size].to_vec(),
+            Value::U64(v) => v.to_le_bytes()[..size].to_vec(),
+            Value::F32(v) => v.to_le_bytes().to_vec(),
+            Value::F64(v) => v.to_le_bytes().to_vec(),
+            Value::String(v) => v.as_bytes().to_vec(),
+            Value::U8Array(v) | Value::Raw(v) => v.clone(),
+            Value::U16Array(v) => v.iter().flat_map(|w| w.to_le_bytes()).collect(),
+            _ => {
+                return Err(invalid_input(format!(
+                    "{:?} for an entry of type {:?}",
+                    value, self.data_type
+                )))
+            }
+        })
+    }
+}
+
+fn is_signed(data_type: DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::I8
+            | DataType::I16
+            | DataType::I24
+            | DataType::I32
+            | DataType::I40
+            | DataType::I48
+            | DataType::I56
+            | DataType::I64
+    )
+}
+
+impl Eds {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let bytes = fs::read(path)?;
+        // like ESI files, often encoded in ISO-8859-1
+        let text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(e) => e.into_bytes().iter().map(|&b| char::from(b)).collect(),
+        };
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let sections = parse_ini(text)?;
+        let info = sections.get("deviceinfo");
+        let number = |key| {
+            info.and_then(|i| i.get(key))
+                .and_then(|v| parse_int(v))
+                .unwrap_or(0) as u32
+        };
+        let mut objects = BTreeMap::new();
+        for name in sections.keys() {
+            if name.len() != 4 {
+                continue;
+            }
+            if let Ok(index) = u16::from_str_radix(name, 16) {
+                objects.insert(index, parse_object(index, &sections)?);
+            }
+        }
+        Ok(Self {
+            vendor_id: number("vendornumber"),
+            product_code: number("productnumber"),
+            revision: number("revisionnumber"),
+            product_name: info
+                .and_then(|i| i.get("productname"))
+                .cloned()
+                .unwrap_or_default(),
+            objects,
+        })
+    }
+
+    pub fn id(&self) -> SlaveId {
+        SlaveId::new(self.vendor_id, self.product_code)
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = &EdsObject> + '_ {
+        self.objects.values()
+    }
+
+    pub fn object(&self, index: u16) -> Option<&EdsObject> {
+        self.objects.get(&index)
+    }
+
+    pub fn entry(&self, idx: SdoIdx) -> Option<&EdsEntry> {
+        self.object(idx.idx.into())?.entry(idx.sub_idx.into())
+    }
+
+    fn entry_or_err(&self, idx: SdoIdx) -> Result<&EdsEntry> {
+        self.entry(idx).ok_or_else(|| {
+            invalid_input(format!(
+                "no entry {:#06x}:{} in the EDS",
+                u16::from(idx.idx),
+                u8::from(idx.sub_idx)
+            ))
+        })
+    }
+
+    /// Upload an SDO and decode it with the type of its entry.
+    pub fn upload<B: Backend + ?Sized>(
+        &self,
+        backend: &mut B,
+        slave: SlavePos,
+        idx: SdoIdx,
+    ) -> Result<Value> {
+        let entry = self.entry_or_err(idx)?;
+        let size = entry
+            .bit_len()
+            .map_or(SDO_BUFFER, |bits| (bits as usize + 7) / 8);
+        let mut buf = vec![0; size];
+        let len = backend.sdo_upload(slave, idx, false, &mut buf)?;
+        entry.decode(&buf[..len])
+    }
+
+    /// Check a value against the type and limits of its entry and download
+    /// it.
+    pub fn download<B: Backend + ?Sized>(
+        &self,
+        backend: &mut B,
+        slave: SlavePos,
+        idx: SdoIdx,
+        value: &Value,
+    ) -> Result<()> {
+        let entry = self.entry_or_err(idx)?;
+        if entry.access == Access::ReadOnly {
+            return Err(invalid_input(format!("{:?} is read only", entry.name)));
+        }
+        let data = entry.encode(value)?;
+        backend.sdo_download(slave, idx, false, &data)
+    }
+}
+
+#[test]
+fn test_eds() {
+    use crate::backend::{SimMaster, SimSlave};
+
+    let eds = Eds::parse(
+        "; a DCF
+[DeviceInfo]
+VendorNumber=0x0000009A
+ProductNumber=0x00030924
+RevisionNumber=0x00010000
+ProductName=Gold Drive
+
+[1018]
+ParameterName=Identity
+ObjectType=0x9
+SubNumber=2
+
+[1018sub0]
+ParameterName=Number of entries
+DataType=0x0005
+AccessType=ro
+DefaultValue=1
+
+[1018sub1]
+ParameterName=Vendor ID
+DataType=0x0007
+AccessType=ro
+DefaultValue=0x9A
+
+[1600]
+ParameterName=RPDO mapping
+ObjectType=0x8
+DataType=0x0007
+AccessType=rw
+CompactSubObj=2
+
+[607A]
+ParameterName=Target position
+ObjectType=0x7
+DataType=0x0004
+AccessType=rww
+PDOMapping=1
+LowLimit=-1000
+HighLimit=1000
+DefaultValue=$NODEID+0x10
+ParameterValue=-5
+",
+    )
+    .unwrap();
+    assert_eq!(eds.id(), SlaveId::new(0x9A, 0x30924));
+    assert_eq!(eds.product_name, "Gold Drive");
+    assert_eq!(eds.objects().count(), 3);
+    let vendor = eds.entry(SdoIdx::new(0x1018, 1)).unwrap();
+    assert_eq!(vendor.data_type, DataType::U32);
+    assert_eq!(vendor.default, Some(Value::U32(0x9A)));
+    assert_eq!(eds.object(0x1600).unwrap().entries.len(), 3);
+
+    let target = eds.entry(SdoIdx::new(0x607A, 0)).unwrap();
+    assert!(target.pdo_mapping);
+    assert_eq!(target.access, Access::ReadWrite);
+    assert_eq!(target.default, None);
+    assert_eq!(target.value, Some(Value::I32(-5)));
+    assert_eq!(
+        target.decode(&[0xFB, 0xFF, 0xFF, 0xFF]).unwrap(),
+        Value::I32(-5)
+    );
+    assert_eq!(
+        target.encode(&Value::I32(-5)).unwrap(),
+        [0xFB, 0xFF, 0xFF, 0xFF]
+    );
+    assert!(target.check(&Value::I32(1001)).is_err());
+    assert!(target.check(&Value::U32(5)).is_err());
+    assert_eq!(target.parse("-0x10").unwrap(), Value::I32(-16));
+    assert!(target.parse("2000").is_err());
+
+    let mut sim = SimMaster::new(vec![
+        SimSlave::new("drive", eds.id()).object(SdoIdx::new(0x607A, 0), &[0; 4])
+    ]);
+    let pos = SlavePos::from(0);
+    let idx = SdoIdx::new(0x607A, 0);
+    eds.download(&mut sim, pos, idx, &Value::I32(-300)).unwrap();
+    assert_eq!(eds.upload(&mut sim, pos, idx).unwrap(), Value::I32(-300));
+    assert!(eds
+        .download(&mut sim, pos, SdoIdx::new(0x1018, 1), &Value::U32(1))
+        .is_err());
+}
@@ -0,0 +1,490 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Database of EtherCAT Slave Information (ESI) files.
+//!
+//! The vendor XML files describing the devices are indexed by vendor id,
+//! product code and revision, to look up the official names, default PDOs
+//! and object descriptions of the slaves found on the bus.
+
+use crate::{eds::Eds, types::*};
+use roxmltree::{Document, Node};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::Path,
+};
+
+/// An object of the dictionary of a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EsiObject {
+    pub index: u16,
+    pub name: String,
+    pub data_type: String,
+    pub bit_size: u32,
+    /// Subindex and name of the entries, for records and arrays.
+    pub sub_items: Vec<(u8, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EsiPdoEntry {
+    /// Index 0 marks a gap.
+    pub index: u16,
+    pub sub_index: u8,
+    pub bit_len: u8,
+    pub name: String,
+    pub data_type: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EsiPdo {
+    pub index: u16,
+    pub name: String,
+    /// The sync manager the PDO is assigned to by default, if any.
+    pub sm: Option<u8>,
+    /// The mapping cannot be changed.
+    pub fixed: bool,
+    pub entries: Vec<EsiPdoEntry>,
+}
+
+impl EsiPdo {
+    /// The PDO as a configuration for
+    /// [`SlaveConfig::config_pdos`](crate::SlaveConfig::config_pdos).
+    pub fn to_pdo_cfg(&self) -> PdoCfg {
+        let mut cfg = PdoCfg::new(PdoIdx::from(self.index));
+        cfg.entries = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| PdoEntryInfo {
+                entry_idx: PdoEntryIdx::new(e.index, e.sub_index),
+                bit_len: e.bit_len,
+                name: e.name.clone(),
+                pos: PdoEntryPos::from(i as u8),
+            })
+            .collect();
+        cfg
+    }
+}
+
+/// The description of a device revision.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EsiDevice {
+    pub vendor_id: u32,
+    pub vendor_name: String,
+    pub product_code: u32,
+    pub revision: u32,
+    /// Short type name, e.g. `EK1100`.
+    pub type_name: String,
+    /// Descriptive name, in English if available.
+    pub name: String,
+    pub group: String,
+    pub rx_pdos: Vec<EsiPdo>,
+    pub tx_pdos: Vec<EsiPdo>,
+    pub objects: Vec<EsiObject>,
+}
+
+impl EsiDevice {
+    pub fn id(&self) -> SlaveId {
+        SlaveId::new(self.vendor_id, self.product_code)
+    }
+
+    pub fn object(&self, index: u16) -> Option<&EsiObject> {
+        self.objects.iter().find(|o| o.index == index)
+    }
+
+    /// Name of an object or of an entry of it.
+    pub fn object_name(&self, idx: SdoIdx) -> Option<&str> {
+        let object = self.object(idx.idx.into())?;
+        let sub = u8::from(idx.sub_idx);
+        match object.sub_items.iter().find(|(s, _)| *s == sub) {
+            Some((_, name)) => Some(name),
+            None if sub == 0 || object.sub_items.is_empty() => Some(&object.name),
+            None => None,
+        }
+    }
+
+    /// Add the objects of an EDS that are missing in the dictionary, e.g.
+    /// for devices whose ESI file has none. Returns their number.
+    pub fn import_eds(&mut self, eds: &Eds) -> usize {
+        let count = self.objects.len();
+        for object in eds.objects() {
+            if self.object(object.index).is_some() {
+                continue;
+            }
+            let bit_size = object
+                .entries
+                .iter()
+                .map(|e| match e.bit_len() {
+                    // the subindex 0 is padded to 16 bits
+                    Some(8) if e.sub_index == 0 && object.entries.len() > 1 => 16,
+                    Some(bits) => u32::from(bits),
+                    None => 0,
+                })
+                .sum();
+            self.objects.push(EsiObject {
+                index: object.index,
+                name: object.name.clone(),
+                data_type: match &object.entries[..] {
+                    [entry] => type_name(entry.data_type).into(),
+                    _ => format!("DT{:04X}", object.index),
+                },
+                bit_size,
+                sub_items: if object.entries.len() > 1 {
+                    object
+                        .entries
+                        .iter()
+                        .map(|e| (e.sub_index, e.name.clone()))
+                        .collect()
+                } else {
+                    vec![]
+                },
+            });
+        }
+        self.objects.sort_by_key(|o| o.index);
+        self.objects.len() - count
+    }
+
+    /// The PDOs assigned to sync managers by default.
+    pub fn default_pdos(&self) -> impl Iterator<Item = &EsiPdo> + '_ {
+        self.rx_pdos
+            .iter()
+            .chain(&self.tx_pdos)
+            .filter(|pdo| pdo.sm.is_some())
+    }
+}
+
+/// ESI device descriptions, indexed by identity and revision.
+#[derive(Debug, Clone, Default)]
+pub struct EsiDatabase {
+    devices: HashMap<(u32, u32), BTreeMap<u32, EsiDevice>>,
+}
+
+impl EsiDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load all `.xml` files in a directory.
+    ///
+    /// Files that cannot be parsed are skipped with a warning, since vendor
+    /// collections often contain a few broken ones.
+    pub fn load_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let mut db = Self::new();
+        let mut paths = fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        paths.sort();
+        for path in paths {
+            let is_xml = path
+                .extension()
+                .map_or(false, |ext| ext.eq_ignore_ascii_case("xml"));
+            if !is_xml {
+                continue;
+            }
+            if let Err(e) = db.load_file(&path) {
+                log::warn!("skipping ESI file {}: {}", path.display(), e);
+            }
+        }
+        Ok(db)
+    }
+
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<usize> {
+        let bytes = fs::read(path)?;
+        // ESI files are often encoded in ISO-8859-1
+        let xml = match String::from_utf8(bytes) {
+            Ok(xml) => xml,
+            Err(e) => e.into_bytes().iter().map(|&b| char::from(b)).collect(),
+        };
+        self.load_str(&xml)
+    }
+
+    /// Add the devices of an ESI document, returns their number.
+    pub fn load_str(&mut self, xml: &str) -> Result<usize> {
+        let doc = Document::parse(xml)
+            .map_err(|e| Error::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        let root = doc.root_element();
+        let vendor = child(root, "Vendor");
+        let vendor_id = vendor
+            .and_then(|v| child_text(v, "Id"))
+            .and_then(parse_number);
+        let vendor_id = vendor_id
+            .ok_or_else(|| Error::Io(io::Error::new(io::ErrorKind::InvalidData, "no vendor id")))?;
+        let vendor_name = vendor
+            .and_then(|v| child_text(v, "Name"))
+            .unwrap_or_default()
+            .to_string();
+
+        let mut count = 0;
+        let devices = root
+            .descendants()
+            .filter(|n| n.has_tag_name("Device") && child(*n, "Type").is_some());
+        for node in devices {
+            if let Some(device) = parse_device(node, vendor_id, &vendor_name) {
+                self.devices
+                    .entry((device.vendor_id, device.product_code))
+                    .or_default()
+                    .insert(device.revision, device);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.values().map(|revs| revs.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    pub fn devices(&self) -> impl Iterator<Item = &EsiDevice> + '_ {
+        self.devices.values().flat_map(|revs| revs.values())
+    }
+
+    /// The description of a device revision, or of the latest revision if
+    /// that one is not known.
+    pub fn get(&self, id: SlaveId, revision: u32) -> Option<&EsiDevice> {
+        let revs = self.devices.get(&(id.vendor_id, id.product_code))?;
+        revs.get(&revision).or_else(|| revs.values().next_back())
+    }
+
+    /// The description of a slave found on the bus.
+    pub fn lookup(&self, info: &SlaveInfo) -> Option<&EsiDevice> {
+        self.get(info.id, info.rev.revision_number)
+    }
+
+    /// Fill in the name of a slave from its description, if the slave does
+    /// not provide one.
+    pub fn enrich(&self, info: &mut SlaveInfo) {
+        if info.name.is_empty() {
+            if let Some(device) = self.lookup(info) {
+                info.name = device.name.clone();
+            }
+        }
+    }
+}
+
+/// Name of a data type in ESI files.
+fn type_name(data_type: DataType) -> &'static str {
+    match data_type {
+        DataType::Bool => "BOOL",
+        DataType::Byte => "BYTE",
+        DataType::I8 => "SINT",
+        DataType::I16 => "INT",
+        DataType::I24 => "INT24",
+        DataType::I32 => "DINT",
+        DataType::I40 => "INT40",
+        DataType::I48 => "INT48",
+        DataType::I56 => "INT56",
+        DataType::I64 => "LINT",
+        DataType::U8 => "USINT",
+        DataType::U16 => "UINT",
+        DataType::U24 => "UINT24",
+        DataType::U32 => "UDINT",
+        DataType::U40 => "UINT40",
+        DataType::U48 => "UINT48",
+        DataType::U56 => "UINT56",
+        DataType::U64 => "ULINT",
+        DataType::F32 => "REAL",
+        DataType::F64 => "LREAL",
+        DataType::String => "STRING",
+        DataType::U8Array => "OCTET_STRING",
+        DataType::U16Array => "UNICODE_STRING",
+        DataType::Bit1 => "BIT1",
+        DataType::Bit2 => "BIT2",
+        DataType::Bit3 => "BIT3",
+        DataType::Bit4 => "BIT4",
+        DataType::Bit5 => "BIT5",
+        DataType::Bit6 => "BIT6",
+        DataType::Bit7 => "BIT7",
+        DataType::Bit8 => "BIT8",
+        DataType::TimeOfDay => "TIME_OF_DAY",
+        DataType::TimeDifference => "TIME_DIFFERENCE",
+        DataType::Domain | DataType::Raw => "DOMAIN",
+    }
+}
+
+fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|n| n.text()).map(str::trim)
+}
+
+/// Parse a decimal or `#x` prefixed hexadecimal number.
+fn parse_number(s: &str) -> Option<u32> {
+    let s = s.trim();
+    match s.strip_prefix("#x").or_else(|| s.strip_prefix("#X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// The English name of an element with names in several languages.
+fn localized_name(node: Node, name: &str) -> String {
+    let names = node.children().filter(|n| n.has_tag_name(name));
+    let mut first = None;
+    for n in names {
+        if n.attribute("LcId") == Some("1033") {
+            return n.text().unwrap_or_default().trim().into();
+        }
+        first = first.or_else(|| n.text());
+    }
+    first.unwrap_or_default().trim().into()
+}
+
+fn parse_device(node: Node, vendor_id: u32, vendor_name: &str) -> Option<EsiDevice> {
+    let ty = child(node, "Type")?;
+    let product_code = parse_number(ty.attribute("ProductCode")?)?;
+    let revision = ty
+        .attribute("RevisionNo")
+        .and_then(parse_number)
+        .unwrap_or(0);
+    let pdos = |tag| {
+        node.children()
+            .filter(|n| n.has_tag_name(tag))
+            .filter_map(parse_pdo)
+            .collect()
+    };
+    let objects = node
+        .descendants()
+        .find(|n| n.has_tag_name("Dictionary"))
+        .map(parse_dictionary)
+        .unwrap_or_default();
+    Some(EsiDevice {
+        vendor_id,
+        vendor_name: vendor_name.into(),
+        product_code,
+        revision,
+        type_name: ty.text().unwrap_or_default().trim().into(),
+        name: localized_name(node, "Name"),
+        group: child_text(node, "GroupType").unwrap_or_default().into(),
+        rx_pdos: pdos("RxPdo"),
+        tx_pdos: pdos("TxPdo"),
+        objects,
+    })
+}
+
+fn parse_pdo(node: Node) -> Option<EsiPdo> {
+    let entries = node
+        .children()
+        .filter(|n| n.has_tag_name("Entry"))
+        .map(|e| {
+            let number = |tag| child_text(e, tag).and_then(parse_number).unwrap_or(0);
+            EsiPdoEntry {
+                index: number("Index") as u16,
+                sub_index: number("SubIndex") as u8,
+                bit_len: number("BitLen") as u8,
+                name: localized_name(e, "Name"),
+                data_type: child_text(e, "DataType").unwrap_or_default().into(),
+            }
+        })
+        .collect();
+    Some(EsiPdo {
+        index: child_text(node, "Index").and_then(parse_number)? as u16,
+        name: localized_name(node, "Name"),
+        sm: node
+            .attribute("Sm")
+            .and_then(parse_number)
+            .map(|sm| sm as u8),
+        fixed: matches!(node.attribute("Fixed"), Some("1") | Some("true")),
+        entries,
+    })
+}
+
+/// Objects of a dictionary, with the entry names from its data types.
+fn parse_dictionary(node: Node) -> Vec<EsiObject> {
+    let mut sub_items = HashMap::new();
+    if let Some(types) = child(node, "DataTypes") {
+        for ty in types.children().filter(|n| n.has_tag_name("DataType")) {
+            let items = ty
+                .children()
+                .filter(|n| n.has_tag_name("SubItem"))
+                .filter_map(|item| {
+                    let sub = child_text(item, "SubIdx").and_then(parse_number)?;
+                    Some((sub as u8, localized_name(item, "Name")))
+                })
+                .collect::<Vec<_>>();
+            if let Some(name) = child_text(ty, "Name") {
+                sub_items.insert(name, items);
+            }
+        }
+    }
+    let objects = match child(node, "Objects") {
+        Some(objects) => objects,
+        None => return vec![],
+    };
+    objects
+        .children()
+        .filter(|n| n.has_tag_name("Object"))
+        .filter_map(|o| {
+            let data_type = child_text(o, "Type").unwrap_or_default();
+            Some(EsiObject {
+                index: child_text(o, "Index").and_then(parse_number)? as u16,
+                name: localized_name(o, "Name"),
+                data_type: data_type.into(),
+                bit_size: child_text(o, "BitSize").and_then(parse_number).unwrap_or(0),
+                sub_items: sub_items.get(data_type).cloned().unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_esi_database() {
+    let xml = r##"<?xml version="1.0" encoding="ISO-8859-1"?>
+<EtherCATInfo>
+  <Vendor><Id>#x00000002</Id><Name>Beckhoff Automation GmbH</Name></Vendor>
+  <Descriptions><Devices>
+    <Device>
+      <Type ProductCode="#x0c1e3052" RevisionNo="#x00100000">EL3102</Type>
+      <Name LcId="1031">EL3102 2K. Ana. Eingang</Name>
+      <Name LcId="1033">EL3102 2Ch. Ana. Input +/-10V, Diff.</Name>
+      <GroupType>AnaIn</GroupType>
+      <Profile><Dictionary>
+        <DataTypes><DataType><Name>DT1018</Name>
+          <SubItem><SubIdx>1</SubIdx><Name>Vendor ID</Name></SubItem>
+          <SubItem><SubIdx>2</SubIdx><Name>Product code</Name></SubItem>
+        </DataType></DataTypes>
+        <Objects><Object><Index>#x1018</Index><Name>Identity</Name><Type>DT1018</Type><BitSize>144</BitSize></Object></Objects>
+      </Dictionary></Profile>
+      <TxPdo Fixed="1" Sm="3"><Index>#x1a00</Index><Name>AI Inputs</Name>
+        <Entry><Index>#x3101</Index><SubIndex>1</SubIndex><BitLen>8</BitLen><Name>Status</Name><DataType>USINT</DataType></Entry>
+        <Entry><Index>#x3101</Index><SubIndex>2</SubIndex><BitLen>16</BitLen><Name>Value</Name><DataType>INT</DataType></Entry>
+      </TxPdo>
+      <TxPdo><Index>#x1a01</Index><Name>AI Inputs Ch.2</Name></TxPdo>
+    </Device>
+  </Devices></Descriptions>
+</EtherCATInfo>"##;
+    let mut db = EsiDatabase::new();
+    assert_eq!(db.load_str(xml).unwrap(), 1);
+    let id = SlaveId::new(2, 0x0c1e_3052);
+    // unknown revisions fall back to the latest one
+    let device = db.get(id, 0x0011_0000).unwrap();
+    assert_eq!(device.type_name, "EL3102");
+    assert_eq!(device.name, "EL3102 2Ch. Ana. Input +/-10V, Diff.");
+    assert_eq!(
+        device.object_name(SdoIdx::new(0x1018, 2)),
+        Some("Product code")
+    );
+    assert_eq!(device.object_name(SdoIdx::new(0x1018, 0)), Some("Identity"));
+    let pdos = device.default_pdos().collect::<Vec<_>>();
+    assert_eq!(pdos.len(), 1);
+    let cfg = pdos[0].to_pdo_cfg();
+    assert_eq!(cfg.entries[1].entry_idx, PdoEntryIdx::new(0x3101, 2));
+    assert_eq!(cfg.entries[1].bit_len, 16);
+    assert!(db.get(SlaveId::new(2, 1), 0).is_none());
+
+    let eds = Eds::parse(
+        "[1018]\nParameterName=Identity\nDataType=0x0007\nAccessType=ro\n\
+         [6000]\nParameterName=Inputs\nDataType=0x0003\nAccessType=ro\n",
+    )
+    .unwrap();
+    let mut device = device.clone();
+    assert_eq!(device.import_eds(&eds), 1);
+    let object = device.object(0x6000).unwrap();
+    assert_eq!((object.data_type.as_str(), object.bit_size), ("INT", 16));
+}
@@ -0,0 +1,649 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use crate::{
+    devices::ChannelValue,
+    types::{DomainIdx, Offset},
+};
+use std::{convert::TryFrom, fmt, marker::PhantomData};
+
+/// Data types that can be stored in a PDO entry.
+///
+/// Values are stored little-endian and may start at any bit.
+pub trait PdoData: Copy {
+    const BITS: u32;
+    fn from_raw(raw: u64) -> Self;
+    fn to_raw(self) -> u64;
+}
+
+macro_rules! pdo_data_int {
+    ($($t:ty),*) => {
+        $(impl PdoData for $t {
+            const BITS: u32 = <$t>::BITS;
+            fn from_raw(raw: u64) -> Self {
+                raw as $t
+            }
+            fn to_raw(self) -> u64 {
+                self as u64
+            }
+        })*
+    };
+}
+
+pdo_data_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl PdoData for bool {
+    const BITS: u32 = 1;
+    fn from_raw(raw: u64) -> Self {
+        raw & 1 != 0
+    }
+    fn to_raw(self) -> u64 {
+        self as u64
+    }
+}
+
+impl PdoData for f32 {
+    const BITS: u32 = 32;
+    fn from_raw(raw: u64) -> Self {
+        f32::from_bits(raw as u32)
+    }
+    fn to_raw(self) -> u64 {
+        u64::from(self.to_bits())
+    }
+}
+
+impl PdoData for f64 {
+    const BITS: u32 = 64;
+    fn from_raw(raw: u64) -> Self {
+        f64::from_bits(raw)
+    }
+    fn to_raw(self) -> u64 {
+        self.to_bits()
+    }
+}
+
+/// Numeric PDO data, convertible to and from `f64`.
+pub trait PdoNumber: PdoData {
+    fn to_f64(self) -> f64;
+    /// Round and saturate `value` to the type.
+    fn from_f64(value: f64) -> Self;
+}
+
+macro_rules! pdo_number {
+    ($($t:ty),*) => {
+        $(impl PdoNumber for $t {
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+            fn from_f64(value: f64) -> Self {
+                value.round() as $t
+            }
+        })*
+    };
+}
+
+pdo_number!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl PdoNumber for f32 {
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+}
+
+impl PdoNumber for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+/// Typed location of a PDO entry in the process image of a domain.
+pub struct Field<T> {
+    pub domain: DomainIdx,
+    pub offset: Offset,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: PdoData> Field<T> {
+    pub const fn new(domain: DomainIdx, offset: Offset) -> Self {
+        Self {
+            domain,
+            offset,
+            _type: PhantomData,
+        }
+    }
+
+    /// Size of the process image needed to hold this entry.
+    pub fn end(&self) -> usize {
+        let (byte, bit) = split(self.offset);
+        byte.saturating_add((bit + T::BITS + 7) as usize / 8)
+    }
+
+    /// Read the value from the process image of its domain.
+    ///
+    /// Bits beyond the end of `data` read as zero.
+    pub fn get(&self, data: &[u8]) -> T {
+        T::from_raw(read_bits(data, self.offset, T::BITS))
+    }
+
+    /// Write the value into the process image of its domain.
+    ///
+    /// Bits beyond the end of `data` are dropped.
+    pub fn set(&self, data: &mut [u8], value: T) {
+        write_bits(data, self.offset, T::BITS, value.to_raw());
+    }
+}
+
+/// A numeric PDO entry in physical units: `value = raw * scale + offset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScaledField<T> {
+    pub field: Field<T>,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl<T: PdoNumber> ScaledField<T> {
+    pub const fn new(field: Field<T>, scale: f64, offset: f64) -> Self {
+        Self {
+            field,
+            scale,
+            offset,
+        }
+    }
+
+    /// Read the value in physical units.
+    pub fn get(&self, data: &[u8]) -> f64 {
+        self.field.get(data).to_f64() * self.scale + self.offset
+    }
+
+    /// Write a value in physical units, rounded and saturated to the raw
+    /// type.
+    pub fn set(&self, data: &mut [u8], value: f64) {
+        self.field
+            .set(data, T::from_f64((value - self.offset) / self.scale));
+    }
+}
+
+/// A field of any type, to access fields of different types together, e.g.
+/// all signals shown by a monitor, see [`FieldSet`].
+pub trait AnyField {
+    fn domain(&self) -> DomainIdx;
+
+    fn offset(&self) -> Offset;
+
+    /// Size of the process image needed to hold this entry.
+    fn end(&self) -> usize;
+
+    /// Read the value from the process image of its domain.
+    fn read(&self, data: &[u8]) -> ChannelValue;
+
+    /// Write the value into the process image of its domain, converted and
+    /// saturated to the type of the field.
+    fn write(&self, data: &mut [u8], value: ChannelValue);
+}
+
+macro_rules! any_field_int {
+    ($($t:ty),*) => {
+        $(impl AnyField for Field<$t> {
+            fn domain(&self) -> DomainIdx {
+                self.domain
+            }
+            fn offset(&self) -> Offset {
+                self.offset
+            }
+            fn end(&self) -> usize {
+                Field::end(self)
+            }
+            fn read(&self, data: &[u8]) -> ChannelValue {
+                ChannelValue::Integer(i64::try_from(self.get(data)).unwrap_or(i64::MAX))
+            }
+            fn write(&self, data: &mut [u8], value: ChannelValue) {
+                let value = match value {
+                    ChannelValue::Bool(b) => b as $t,
+                    ChannelValue::Integer(i) => <$t>::try_from(i)
+                        .unwrap_or(if i < 0 { <$t>::MIN } else { <$t>::MAX }),
+                    ChannelValue::Real(r) => <$t>::from_f64(r),
+                };
+                self.set(data, value);
+            }
+        })*
+    };
+}
+
+any_field_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+macro_rules! any_field_real {
+    ($($t:ty),*) => {
+        $(impl AnyField for Field<$t> {
+            fn domain(&self) -> DomainIdx {
+                self.domain
+            }
+            fn offset(&self) -> Offset {
+                self.offset
+            }
+            fn end(&self) -> usize {
+                Field::end(self)
+            }
+            fn read(&self, data: &[u8]) -> ChannelValue {
+                ChannelValue::Real(self.get(data).to_f64())
+            }
+            fn write(&self, data: &mut [u8], value: ChannelValue) {
+                self.set(data, <$t>::from_f64(real(value)));
+            }
+        })*
+    };
+}
+
+any_field_real!(f32, f64);
+
+impl AnyField for Field<bool> {
+    fn domain(&self) -> DomainIdx {
+        self.domain
+    }
+
+    fn offset(&self) -> Offset {
+        self.offset
+    }
+
+    fn end(&self) -> usize {
+        Field::end(self)
+    }
+
+    fn read(&self, data: &[u8]) -> ChannelValue {
+        ChannelValue::Bool(self.get(data))
+    }
+
+    fn write(&self, data: &mut [u8], value: ChannelValue) {
+        let value = match value {
+            ChannelValue::Bool(b) => b,
+            ChannelValue::Integer(i) => i != 0,
+            ChannelValue::Real(r) => r != 0.0,
+        };
+        self.set(data, value);
+    }
+}
+
+/// Scaled fields are read and written in physical units.
+impl<T: PdoNumber> AnyField for ScaledField<T> {
+    fn domain(&self) -> DomainIdx {
+        self.field.domain
+    }
+
+    fn offset(&self) -> Offset {
+        self.field.offset
+    }
+
+    fn end(&self) -> usize {
+        self.field.end()
+    }
+
+    fn read(&self, data: &[u8]) -> ChannelValue {
+        ChannelValue::Real(self.get(data))
+    }
+
+    fn write(&self, data: &mut [u8], value: ChannelValue) {
+        self.set(data, real(value));
+    }
+}
+
+fn real(value: ChannelValue) -> f64 {
+    match value {
+        ChannelValue::Bool(b) => f64::from(u8::from(b)),
+        ChannelValue::Integer(i) => i as f64,
+        ChannelValue::Real(r) => r,
+    }
+}
+
+/// Fields of one domain read or written together, e.g. hundreds of signals
+/// shown by a monitor.
+///
+/// The fields are sorted by offset once, so that
+/// [`DomainView::read_many`] and [`DomainView::write_many`] walk the image
+/// in one pass. The values stay in the order the fields were given in:
+///
+/// ```ignore
+/// let signals = FieldSet::new(&[&position, &status, &voltage]);
+/// let mut values = vec![ChannelValue::Bool(false); signals.len()];
+/// loop {
+///     // ...
+///     master.domain_view(domain)?.read_many(&signals, &mut values);
+///     // values[1] is the status
+/// }
+/// ```
+#[derive(Clone)]
+pub struct FieldSet<'f> {
+    /// The fields in offset order, with their position in the given order.
+    fields: Vec<(usize, &'f dyn AnyField)>,
+}
+
+impl<'f> FieldSet<'f> {
+    pub fn new(fields: &[&'f dyn AnyField]) -> Self {
+        let mut fields: Vec<_> = fields.iter().copied().enumerate().collect();
+        fields.sort_by_key(|(_, field)| {
+            let offset = field.offset();
+            (offset.byte, offset.bit)
+        });
+        Self { fields }
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+/// Typed access to the process image of one domain, borrowed for a cycle.
+///
+/// The fields are located once, when configuring the slaves, so reading or
+/// writing one in the cycle is a shift at a fixed offset of the image,
+/// without looking up the entry or copying the image:
+///
+/// ```ignore
+/// let position = config.register_field::<i32>(PdoEntryIdx::new(0x6064, 0), domain)?;
+/// let target = config.register_field::<i32>(PdoEntryIdx::new(0x607A, 0), domain)?;
+/// let mut master = master.activate()?;
+/// loop {
+///     master.receive()?;
+///     master.domain(domain).process()?;
+///     let mut view = master.domain_view(domain)?;
+///     let pos = view.get(&position);
+///     view.set(&target, pos + 10);
+///     master.domain(domain).queue()?;
+///     master.send()?;
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DomainView<'a> {
+    domain: DomainIdx,
+    data: &'a mut [u8],
+}
+
+impl<'a> DomainView<'a> {
+    pub fn new(domain: DomainIdx, data: &'a mut [u8]) -> Self {
+        Self { domain, data }
+    }
+
+    pub const fn domain(&self) -> DomainIdx {
+        self.domain
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        self.data
+    }
+
+    /// Returns `true` if `field` is located in this image.
+    ///
+    /// Check the fields once after activation: [`get`](Self::get) and
+    /// [`set`](Self::set) do not fail for a field outside of the image, but
+    /// read zeros and drop the value.
+    pub fn contains<T: PdoData>(&self, field: &Field<T>) -> bool {
+        field.domain == self.domain && field.end() <= self.data.len()
+    }
+
+    /// Read a field of this domain.
+    pub fn get<T: PdoData>(&self, field: &Field<T>) -> T {
+        debug_assert_eq!(field.domain, self.domain, "field of another domain");
+        field.get(self.data)
+    }
+
+    /// Write a field of this domain.
+    pub fn set<T: PdoData>(&mut self, field: &Field<T>, value: T) {
+        debug_assert_eq!(field.domain, self.domain, "field of another domain");
+        field.set(self.data, value);
+    }
+
+    /// Read a field of this domain in physical units.
+    pub fn get_scaled<T: PdoNumber>(&self, field: &ScaledField<T>) -> f64 {
+        debug_assert_eq!(field.field.domain, self.domain, "field of another domain");
+        field.get(self.data)
+    }
+
+    /// Write a field of this domain in physical units.
+    pub fn set_scaled<T: PdoNumber>(&mut self, field: &ScaledField<T>, value: f64) {
+        debug_assert_eq!(field.field.domain, self.domain, "field of another domain");
+        field.set(self.data, value);
+    }
+
+    /// Read many fields of this domain in offset order, each into the value
+    /// at its position in the set.
+    ///
+    /// If `values` is shorter than `fields`, the fields without a value are
+    /// not read.
+    pub fn read_many(&self, fields: &FieldSet, values: &mut [ChannelValue]) {
+        for (pos, field) in &fields.fields {
+            debug_assert_eq!(field.domain(), self.domain, "field of another domain");
+            if let Some(value) = values.get_mut(*pos) {
+                *value = field.read(self.data);
+            }
+        }
+    }
+
+    /// Write many fields of this domain in offset order, each from the value
+    /// at its position in the set.
+    ///
+    /// If `values` is shorter than `fields`, the fields without a value are
+    /// not written.
+    pub fn write_many(&mut self, fields: &FieldSet, values: &[ChannelValue]) {
+        for (pos, field) in &fields.fields {
+            debug_assert_eq!(field.domain(), self.domain, "field of another domain");
+            if let Some(value) = values.get(*pos) {
+                field.write(self.data, *value);
+            }
+        }
+    }
+
+    /// Read the `bits` bits at `offset`, for entries whose type is only
+    /// known at runtime, e.g. from an ESI file.
+    ///
+    /// At most 64 bits are read, bits beyond the end of the image read as
+    /// zero.
+    pub fn read(&self, offset: Offset, bits: u32) -> u64 {
+        read_bits(self.data, offset, bits)
+    }
+
+    /// Write the `bits` bits at `offset`, see [`read`](Self::read).
+    ///
+    /// At most 64 bits are written, bits beyond the end of the image are
+    /// dropped.
+    pub fn write(&mut self, offset: Offset, bits: u32, raw: u64) {
+        write_bits(self.data, offset, bits, raw);
+    }
+}
+
+/// The byte and the bit in it, `0..8`, at which `offset` starts.
+fn split(offset: Offset) -> (usize, u32) {
+    let byte = offset.byte.saturating_add((offset.bit / 8) as usize);
+    (byte, offset.bit % 8)
+}
+
+fn read_bits(data: &[u8], offset: Offset, bits: u32) -> u64 {
+    let (byte, bit) = split(offset);
+    let bits = bits.min(64);
+    let n = (bit + bits + 7) as usize / 8;
+    let bytes = data.get(byte..).unwrap_or_default();
+    if bit == 0 && bits % 8 == 0 && bytes.len() >= n {
+        let mut raw = [0; 8];
+        raw[..n].copy_from_slice(&bytes[..n]);
+        return u64::from_le_bytes(raw);
+    }
+    let mut raw = 0_u128;
+    for (i, b) in bytes.iter().take(n).enumerate() {
+        raw |= u128::from(*b) << (8 * i);
+    }
+    ((raw >> bit) & mask(bits)) as u64
+}
+
+fn write_bits(data: &mut [u8], offset: Offset, bits: u32, value: u64) {
+    let (byte, bit) = split(offset);
+    let bits = bits.min(64);
+    let n = (bit + bits + 7) as usize / 8;
+    let bytes = match data.get_mut(byte..) {
+        Some(bytes) if bytes.len() >= n => &mut bytes[..n],
+        Some(bytes) => bytes,
+        None => return,
+    };
+    if bit == 0 && bits % 8 == 0 && bytes.len() == n {
+        bytes.copy_from_slice(&value.to_le_bytes()[..n]);
+        return;
+    }
+    let mut raw = 0_u128;
+    for (i, b) in bytes.iter().enumerate() {
+        raw |= u128::from(*b) << (8 * i);
+    }
+    raw &= !(mask(bits) << bit);
+    raw |= (u128::from(value) & mask(bits)) << bit;
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = (raw >> (8 * i)) as u8;
+    }
+}
+
+const fn mask(bits: u32) -> u128 {
+    (1 << bits) - 1
+}
+
+impl<T> Clone for Field<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Field<T> {}
+
+impl<T> PartialEq for Field<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.domain == other.domain && self.offset == other.offset
+    }
+}
+
+impl<T> Eq for Field<T> {}
+
+impl<T> fmt::Debug for Field<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Field")
+            .field("type", &std::any::type_name::<T>())
+            .field("domain", &self.domain)
+            .field("offset", &self.offset)
+            .finish()
+    }
+}
+
+#[test]
+fn test_field_access() {
+    let field = |byte, bit| Offset { byte, bit };
+    let d = DomainIdx::new(0);
+    let mut data = [0_u8; 8];
+
+    let word = Field::<u16>::new(d, field(1, 0));
+    word.set(&mut data, 0x1234);
+    assert_eq!(data[..4], [0, 0x34, 0x12, 0]);
+    assert_eq!(word.get(&data), 0x1234);
+
+    let flag = Field::<bool>::new(d, field(0, 3));
+    flag.set(&mut data, true);
+    assert_eq!(data[0], 0b1000);
+    assert!(flag.get(&data));
+    flag.set(&mut data, false);
+    assert_eq!(data[0], 0);
+
+    let shifted = Field::<i16>::new(d, field(4, 4));
+    shifted.set(&mut data, -2);
+    assert_eq!(data[4..7], [0xE0, 0xFF, 0x0F]);
+    assert_eq!(shifted.get(&data), -2);
+    assert_eq!(word.get(&data), 0x1234);
+
+    let real = Field::<f32>::new(d, field(0, 0));
+    real.set(&mut data, 1.5);
+    assert_eq!(real.get(&data), 1.5);
+
+    let volts = ScaledField::new(Field::<i16>::new(d, field(0, 0)), 10.0 / 32767.0, 0.0);
+    volts.set(&mut data, -5.0);
+    assert_eq!(volts.field.get(&data), -16384);
+    volts.set(&mut data, 20.0);
+    assert_eq!(volts.field.get(&data), i16::MAX);
+    assert!((volts.get(&data) - 10.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_domain_view() {
+    let d = DomainIdx::new(1);
+    let mut data = [0_u8; 6];
+    let status = Field::<u16>::new(d, Offset { byte: 0, bit: 0 });
+    let enable = Field::<bool>::new(d, Offset { byte: 2, bit: 5 });
+    let current = ScaledField::new(Field::<i16>::new(d, Offset { byte: 3, bit: 0 }), 0.5, 0.0);
+    let outside = Field::<u32>::new(d, Offset { byte: 4, bit: 0 });
+
+    let mut view = DomainView::new(d, &mut data);
+    assert!(view.contains(&current.field) && !view.contains(&outside));
+    assert!(!view.contains(&Field::<u8>::new(DomainIdx::new(0), Offset::default())));
+    view.set(&status, 0x0237);
+    view.set(&enable, true);
+    view.set_scaled(&current, -2.0);
+    assert_eq!(view.get(&status), 0x0237);
+    assert_eq!(view.get_scaled(&current), -2.0);
+    assert_eq!(view.read(Offset { byte: 2, bit: 4 }, 3), 0b010);
+    view.write(Offset { byte: 5, bit: 1 }, 2, 0b11);
+    assert_eq!(view.data(), [0x37, 0x02, 0b10_0000, 0xFC, 0xFF, 0b110]);
+
+    // outside of the image
+    view.set(&outside, 0x1234_5678);
+    assert_eq!(view.data(), [0x37, 0x02, 0b10_0000, 0xFC, 0x78, 0x56]);
+    assert_eq!(view.get(&outside), 0x5678);
+    view.set(&Field::<u64>::new(d, Offset { byte: 9, bit: 0 }), 1);
+    assert_eq!(view.read(Offset { byte: 0, bit: 36 }, 100), 0x567);
+}
+
+#[test]
+fn test_many_fields() {
+    let d = DomainIdx::new(0);
+    let at = |byte, bit| Offset { byte, bit };
+    let flag = Field::<bool>::new(d, at(0, 2));
+    let count = Field::<u8>::new(d, at(1, 0));
+    let speed = Field::<i32>::new(d, at(2, 4));
+    let volts = ScaledField::new(Field::<i16>::new(d, at(7, 0)), 0.5, 0.0);
+    // out of offset order
+    let fields = FieldSet::new(&[&speed, &volts, &flag, &count]);
+    assert_eq!(fields.len(), 4);
+
+    let mut data = [0_u8; 9];
+    let mut view = DomainView::new(d, &mut data);
+    let values = [
+        ChannelValue::Real(-1.6),
+        ChannelValue::Real(3.0),
+        ChannelValue::Integer(1),
+        ChannelValue::Integer(300),
+    ];
+    view.write_many(&fields, &values);
+    assert!(view.get(&flag));
+    assert_eq!(view.get(&count), 255);
+    assert_eq!(view.get(&speed), -2);
+    assert_eq!(view.get(&volts.field), 6);
+
+    let mut back = [ChannelValue::Bool(false); 4];
+    view.read_many(&fields, &mut back);
+    assert_eq!(
+        back,
+        [
+            ChannelValue::Integer(-2),
+            ChannelValue::Real(3.0),
+            ChannelValue::Bool(true),
+            ChannelValue::Integer(255),
+        ]
+    );
+
+    // a shorter list of values leaves the other fields alone
+    let mut back = [ChannelValue::Bool(false); 2];
+    view.read_many(&fields, &mut back);
+    assert_eq!(back, [ChannelValue::Integer(-2), ChannelValue::Real(3.0)]);
+}
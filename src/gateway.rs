@@ -0,0 +1,400 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! EtherCAT Mailbox Gateway (ETG.8200).
+//!
+//! Engineering tools reach the mailbox of a slave by sending the mailbox
+//! message, wrapped in an EtherCAT header, in a UDP datagram to port 0x88A4
+//! of the machine running the master. The address in the mailbox header is
+//! the station address of the slave.
+//!
+//! The gateway translates CoE SDO uploads and downloads and SoE reads and
+//! writes into calls of a [`Backend`]. Segmented SDO transfers and the other
+//! mailbox protocols are refused with an SDO abort or a mailbox error.
+//!
+//! Mailbox transfers block until the slave answers, so the gateway should run
+//! in its own thread, with its own master handle.
+
+use crate::{backend::Backend, types::*};
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+/// UDP port of the mailbox gateway.
+pub const PORT: u16 = 0x88A4;
+
+/// Frame type of mailbox gateway messages in the EtherCAT header.
+const TYPE_MAILBOX: u16 = 5;
+const HEADER: usize = 2;
+const MBX_HEADER: usize = 6;
+const COE_HEADER: usize = 2;
+const SDO_HEADER: usize = 8;
+const SOE_HEADER: usize = 4;
+/// The length in the EtherCAT header has 11 bits.
+const MAX_MAILBOX: usize = 0x07FF;
+
+const MBX_ERR: u8 = 0;
+const MBX_COE: u8 = 3;
+const MBX_SOE: u8 = 5;
+
+const MBXERR_SERVICE_NOT_SUPPORTED: u16 = 0x0004;
+const MBXERR_INVALID_HEADER: u16 = 0x0005;
+const MBXERR_SIZE_TOO_SHORT: u16 = 0x0006;
+
+const COE_SDO_REQUEST: u16 = 2;
+const COE_SDO_RESPONSE: u16 = 3;
+
+const SDO_COMPLETE_ACCESS: u8 = 0x10;
+const SDO_ABORT_COMMAND: u32 = 0x0504_0001;
+const SDO_ABORT_GENERAL: u32 = 0x0800_0000;
+
+const SOE_READ_REQUEST: u8 = 1;
+const SOE_READ_RESPONSE: u8 = 2;
+const SOE_WRITE_REQUEST: u8 = 3;
+const SOE_WRITE_RESPONSE: u8 = 4;
+const SOE_ERROR: u8 = 0x10;
+/// The value is the only element of an IDN the master can access.
+const SOE_ELEMENT_VALUE: u8 = 0x40;
+
+/// A mailbox gateway server.
+#[derive(Debug)]
+pub struct MailboxGateway {
+    socket: UdpSocket,
+    stations: Vec<u16>,
+}
+
+impl MailboxGateway {
+    /// Listen on the given address, usually `("0.0.0.0", gateway::PORT)`.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(addr)?,
+            stations: vec![],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// How long [`serve_one`](Self::serve_one) waits for a request, `None`
+    /// to wait forever (the default).
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+
+    /// Wait for one request and answer it.
+    ///
+    /// Returns `false` if no request arrived within the timeout. Requests
+    /// that are not mailbox messages or address an unknown slave are
+    /// dropped, like a slave that does not answer.
+    pub fn serve_one<B: Backend + ?Sized>(&mut self, backend: &mut B) -> Result<bool> {
+        let mut buf = [0; HEADER + MAX_MAILBOX];
+        let (len, peer) = match self.socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                return Ok(false)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let request = &buf[..len];
+        let address = match mailbox_address(request) {
+            Some(address) => address,
+            None => {
+                log::debug!("Mailbox gateway: dropping invalid request from {}", peer);
+                return Ok(true);
+            }
+        };
+        let slave = match self.resolve(backend, address)? {
+            Some(slave) => slave,
+            None => {
+                log::debug!("Mailbox gateway: no slave with station address {}", address);
+                return Ok(true);
+            }
+        };
+        if let Some(response) = handle_request(backend, slave, request) {
+            self.socket.send_to(&response, peer)?;
+        }
+        Ok(true)
+    }
+
+    /// Answer requests until an I/O error occurs.
+    pub fn serve<B: Backend + ?Sized>(&mut self, backend: &mut B) -> Result<()> {
+        loop {
+            self.serve_one(backend)?;
+        }
+    }
+
+    /// Find the position of a slave by its station address, rescanning the
+    /// bus if it is not known (yet).
+    fn resolve<B: Backend + ?Sized>(
+        &mut self,
+        backend: &mut B,
+        address: u16,
+    ) -> Result<Option<SlavePos>> {
+        if !self.stations.contains(&address) {
+            self.stations = station_addresses(backend)?;
+        }
+        Ok(self
+            .stations
+            .iter()
+            .position(|&a| a == address)
+            .map(|pos| SlavePos::from(pos as u16)))
+    }
+}
+
+/// The configured station addresses of all slaves, by position.
+///
+/// Slaves without one are assumed to have position + 1, which is what the
+/// IgH master assigns.
+fn station_addresses<B: Backend + ?Sized>(backend: &mut B) -> Result<Vec<u16>> {
+    (0..backend.slave_count()? as u16)
+        .map(|pos| {
+            let mut reg = [0; 2];
+            backend.read_register(pos.into(), crate::backend::reg::STATION_ADDRESS, &mut reg)?;
+            Ok(match u16::from_le_bytes(reg) {
+                0 => pos + 1,
+                address => address,
+            })
+        })
+        .collect()
+}
+
+/// The station address of a mailbox gateway request.
+fn mailbox_address(request: &[u8]) -> Option<u16> {
+    if request.len() < HEADER + MBX_HEADER {
+        return None;
+    }
+    let header = u16::from_le_bytes([request[0], request[1]]);
+    if header >> 12 != TYPE_MAILBOX {
+        return None;
+    }
+    Some(u16::from_le_bytes([request[4], request[5]]))
+}
+
+/// Execute a mailbox gateway request for the slave at `slave`, and build the
+/// response, `None` if the request is not a valid mailbox message.
+pub fn handle_request<B: Backend + ?Sized>(
+    backend: &mut B,
+    slave: SlavePos,
+    request: &[u8],
+) -> Option<Vec<u8>> {
+    mailbox_address(request)?;
+    let mailbox = &request[HEADER..];
+    let len = u16::from_le_bytes([mailbox[0], mailbox[1]]) as usize;
+    let data = mailbox.get(MBX_HEADER..MBX_HEADER + len)?;
+    let header = &mailbox[..MBX_HEADER];
+    let response = match header[5] & 0x0F {
+        MBX_COE => handle_coe(backend, slave, data),
+        MBX_SOE => handle_soe(backend, slave, data),
+        _ => Response::Error(MBXERR_SERVICE_NOT_SUPPORTED),
+    };
+    let (kind, data) = match response {
+        Response::Data(kind, data) => (kind, data),
+        Response::Error(detail) => {
+            let mut data = 1u16.to_le_bytes().to_vec();
+            data.extend_from_slice(&detail.to_le_bytes());
+            (MBX_ERR, data)
+        }
+    };
+    let mut response = Vec::with_capacity(HEADER + MBX_HEADER + data.len());
+    let len = (MBX_HEADER + data.len()) as u16;
+    response.extend_from_slice(&(len | TYPE_MAILBOX << 12).to_le_bytes());
+    response.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    // address, channel and priority are those of the request
+    response.extend_from_slice(&header[2..5]);
+    response.push(kind | (header[5] & 0x70));
+    response.extend_from_slice(&data);
+    Some(response)
+}
+
+enum Response {
+    /// Mailbox type and data.
+    Data(u8, Vec<u8>),
+    /// A mailbox error with its detail code.
+    Error(u16),
+}
+
+fn handle_coe<B: Backend + ?Sized>(backend: &mut B, slave: SlavePos, data: &[u8]) -> Response {
+    if data.len() < COE_HEADER + SDO_HEADER {
+        return Response::Error(MBXERR_SIZE_TOO_SHORT);
+    }
+    let coe = u16::from_le_bytes([data[0], data[1]]);
+    if coe >> 12 != COE_SDO_REQUEST {
+        return Response::Error(MBXERR_SERVICE_NOT_SUPPORTED);
+    }
+    let sdo = &data[COE_HEADER..];
+    let command = sdo[0];
+    let complete_access = command & SDO_COMPLETE_ACCESS != 0;
+    let index = SdoIdx::new(u16::from_le_bytes([sdo[1], sdo[2]]), sdo[3]);
+    let coe_response = |service: u16, mut body: Vec<u8>| {
+        let mut data = (service << 12).to_le_bytes().to_vec();
+        data.append(&mut body);
+        Response::Data(MBX_COE, data)
+    };
+    let abort = |code: u32| {
+        let mut body = vec![0x80, sdo[1], sdo[2], sdo[3]];
+        body.extend_from_slice(&code.to_le_bytes());
+        coe_response(COE_SDO_REQUEST, body)
+    };
+
+    match command >> 5 {
+        // initiate upload
+        2 => {
+            let mut target = vec![0; MAX_MAILBOX - MBX_HEADER - COE_HEADER - SDO_HEADER];
+            let size = match backend.sdo_upload(slave, index, complete_access, &mut target) {
+                Ok(size) => size,
+                Err(e) => {
+                    log::debug!("Mailbox gateway: SDO upload failed: {}", e);
+                    return abort(SDO_ABORT_GENERAL);
+                }
+            };
+            let mut body = vec![0, sdo[1], sdo[2], sdo[3]];
+            if (1..=4).contains(&size) {
+                body[0] = 0x43 | (4 - size as u8) << 2;
+                target.resize(4, 0);
+            } else {
+                body[0] = 0x41;
+                body.extend_from_slice(&(size as u32).to_le_bytes());
+                target.truncate(size);
+            }
+            body[0] |= command & SDO_COMPLETE_ACCESS;
+            body.append(&mut target);
+            coe_response(COE_SDO_RESPONSE, body)
+        }
+        // initiate download
+        1 => {
+            let payload = if command & 0x02 != 0 {
+                // expedited, with the size if indicated
+                let size = if command & 0x01 != 0 {
+                    4 - (command >> 2 & 0x03) as usize
+                } else {
+                    4
+                };
+                &sdo[4..4 + size]
+            } else {
+                let size = u32::from_le_bytes([sdo[4], sdo[5], sdo[6], sdo[7]]) as usize;
+                match sdo.get(SDO_HEADER..SDO_HEADER + size) {
+                    Some(payload) => payload,
+                    // the rest would follow in segments
+                    None => return abort(SDO_ABORT_COMMAND),
+                }
+            };
+            if let Err(e) = backend.sdo_download(slave, index, complete_access, payload) {
+                log::debug!("Mailbox gateway: SDO download failed: {}", e);
+                return abort(SDO_ABORT_GENERAL);
+            }
+            let body = vec![
+                0x60 | command & SDO_COMPLETE_ACCESS,
+                sdo[1],
+                sdo[2],
+                sdo[3],
+                0,
+                0,
+                0,
+                0,
+            ];
+            coe_response(COE_SDO_RESPONSE, body)
+        }
+        _ => abort(SDO_ABORT_COMMAND),
+    }
+}
+
+fn handle_soe<B: Backend + ?Sized>(backend: &mut B, slave: SlavePos, data: &[u8]) -> Response {
+    if data.len() < SOE_HEADER {
+        return Response::Error(MBXERR_SIZE_TOO_SHORT);
+    }
+    let opcode = data[0] & 0x07;
+    let drive_no = data[0] >> 5;
+    let idn = u16::from_le_bytes([data[2], data[3]]);
+    if data[1] != SOE_ELEMENT_VALUE || data[0] & 0x08 != 0 {
+        // other elements and fragmented writes
+        return Response::Error(MBXERR_SERVICE_NOT_SUPPORTED);
+    }
+    let header = |opcode: u8| vec![opcode | data[0] & 0xE0, data[1], data[2], data[3]];
+    let result = match opcode {
+        SOE_READ_REQUEST => {
+            let mut target = vec![0; MAX_MAILBOX - MBX_HEADER - SOE_HEADER];
+            backend
+                .soe_read(slave, drive_no, idn, &mut target)
+                .map(|size| {
+                    let mut body = header(SOE_READ_RESPONSE);
+                    body.extend_from_slice(&target[..size]);
+                    body
+                })
+        }
+        SOE_WRITE_REQUEST => backend
+            .soe_write(slave, drive_no, idn, &data[SOE_HEADER..])
+            .map(|_| header(SOE_WRITE_RESPONSE)),
+        _ => return Response::Error(MBXERR_INVALID_HEADER),
+    };
+    match result {
+        Ok(body) => Response::Data(MBX_SOE, body),
+        Err(Error::SoeError(code)) => {
+            let mut body = header((opcode + 1) | SOE_ERROR);
+            body.extend_from_slice(&code.to_le_bytes());
+            Response::Data(MBX_SOE, body)
+        }
+        Err(e) => {
+            log::debug!("Mailbox gateway: SoE request failed: {}", e);
+            Response::Error(MBXERR_SERVICE_NOT_SUPPORTED)
+        }
+    }
+}
+
+#[test]
+fn test_mailbox_gateway() {
+    use crate::backend::{SimMaster, SimSlave};
+
+    let mut sim = SimMaster::new(vec![SimSlave::new("EL3102", SlaveId::new(2, 0x0C1E_3052))
+        .object(SdoIdx::new(0x1018, 1), &[2, 0, 0, 0])
+        .object(SdoIdx::new(0x1008, 0), b"EL3102-0000")
+        .object(SdoIdx::new(0x100A, 0), b"")]);
+    let request = |address: u16, mbx_type: u8, data: &[u8]| {
+        let mut request = ((MBX_HEADER + data.len()) as u16 | TYPE_MAILBOX << 12)
+            .to_le_bytes()
+            .to_vec();
+        request.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        request.extend_from_slice(&address.to_le_bytes());
+        request.extend_from_slice(&[0, mbx_type | 0x10]);
+        request.extend_from_slice(data);
+        request
+    };
+
+    // expedited upload
+    let req = request(1, MBX_COE, &[0, 0x20, 0x40, 0x18, 0x10, 1, 0, 0, 0, 0]);
+    assert_eq!(mailbox_address(&req), Some(1));
+    let resp = handle_request(&mut sim, 0.into(), &req).unwrap();
+    assert_eq!(resp[..8], [16, 0x50, 10, 0, 1, 0, 0, 0x13]);
+    assert_eq!(resp[8..], [0, 0x30, 0x43, 0x18, 0x10, 1, 2, 0, 0, 0]);
+
+    // normal upload
+    let req = request(1, MBX_COE, &[0, 0x20, 0x40, 0x08, 0x10, 0, 0, 0, 0, 0]);
+    let resp = handle_request(&mut sim, 0.into(), &req).unwrap();
+    assert_eq!(resp[10..18], [0x41, 0x08, 0x10, 0, 11, 0, 0, 0]);
+    assert_eq!(&resp[18..], b"EL3102-0000");
+
+    // an empty object has no expedited form
+    let req = request(1, MBX_COE, &[0, 0x20, 0x40, 0x0A, 0x10, 0, 0, 0, 0, 0]);
+    let resp = handle_request(&mut sim, 0.into(), &req).unwrap();
+    assert_eq!(resp[10..], [0x41, 0x0A, 0x10, 0, 0, 0, 0, 0]);
+
+    // expedited download, then an unknown object
+    let req = request(1, MBX_COE, &[0, 0x20, 0x23, 0x18, 0x10, 1, 7, 0, 0, 0]);
+    let resp = handle_request(&mut sim, 0.into(), &req).unwrap();
+    assert_eq!(resp[10..], [0x60, 0x18, 0x10, 1, 0, 0, 0, 0]);
+    let req = request(1, MBX_COE, &[0, 0x20, 0x40, 0x00, 0x60, 0, 0, 0, 0, 0]);
+    let resp = handle_request(&mut sim, 0.into(), &req).unwrap();
+    assert_eq!(resp[8..], [0, 0x20, 0x80, 0x00, 0x60, 0, 0, 0, 0, 0x08]);
+
+    // the simulation does not speak SoE
+    let req = request(1, MBX_SOE, &[0x01, 0x40, 0x20, 0]);
+    let resp = handle_request(&mut sim, 0.into(), &req).unwrap();
+    assert_eq!(resp[7], MBX_ERR | 0x10);
+    assert_eq!(resp[8..], [1, 0, 4, 0]);
+
+    assert_eq!(station_addresses(&mut sim).unwrap(), vec![1]);
+}
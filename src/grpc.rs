@@ -0,0 +1,522 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! gRPC service for remote access to process data, SDOs and application
+//! commands.
+//!
+//! The service is described in `proto/ethercat.proto`, for clients in other
+//! languages. It never touches the cyclic thread directly:
+//!
+//! - PDO entries are read from [`SnapshotReader`]s,
+//! - SDOs go through a separate [`Backend`], usually a second master
+//!   handle, and
+//! - commands are passed into the cycle with a [`command_channel`].
+//!
+//! What a command does, e.g. a motion command, is up to the application.
+//! Every call must be authenticated with the token the service was built
+//! with, sent as `authorization: Bearer <token>` metadata.
+//!
+//! [`command_channel`]: crate::runtime::command_channel
+
+// tonic dictates `Status` as the error type
+#![allow(clippy::result_large_err)]
+
+mod proto {
+    include!(concat!(env!("OUT_DIR"), "/ethercat.Master.rs"));
+}
+
+pub use self::proto::{master_client::MasterClient, master_server::MasterServer};
+
+use crate::{
+    backend::Backend,
+    field::{Field, PdoData},
+    runtime::{snapshot_index, CommandSender, SnapshotReader},
+    types::*,
+};
+use std::{
+    convert::TryFrom,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use tonic::{
+    metadata::MetadataValue, service::interceptor::InterceptedService, Request, Response, Status,
+};
+
+/// Largest SDO that can be read.
+const SDO_BUFFER: usize = 4096;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ReadFieldsRequest {
+    /// Names of the entries, empty for all.
+    #[prost(string, repeated, tag = "1")]
+    pub names: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ReadFieldsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub values: Vec<FieldValue>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FieldValue {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    /// Cycle of the snapshot the value was read from.
+    #[prost(uint64, tag = "2")]
+    pub seq: u64,
+    /// DC time in ns of the snapshot the value was read from.
+    #[prost(uint64, tag = "3")]
+    pub dc_time: u64,
+    #[prost(oneof = "Value", tags = "4, 5, 6, 7")]
+    pub value: Option<Value>,
+}
+
+#[derive(Clone, Copy, PartialEq, prost::Oneof)]
+pub enum Value {
+    #[prost(int64, tag = "4")]
+    Integer(i64),
+    #[prost(uint64, tag = "5")]
+    Unsigned(u64),
+    #[prost(double, tag = "6")]
+    Number(f64),
+    #[prost(bool, tag = "7")]
+    Boolean(bool),
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SdoReadRequest {
+    #[prost(uint32, tag = "1")]
+    pub slave: u32,
+    #[prost(uint32, tag = "2")]
+    pub index: u32,
+    #[prost(uint32, tag = "3")]
+    pub subindex: u32,
+    #[prost(bool, tag = "4")]
+    pub complete_access: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SdoReadResponse {
+    #[prost(bytes = "vec", tag = "1")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SdoWriteRequest {
+    #[prost(uint32, tag = "1")]
+    pub slave: u32,
+    #[prost(uint32, tag = "2")]
+    pub index: u32,
+    #[prost(uint32, tag = "3")]
+    pub subindex: u32,
+    #[prost(bool, tag = "4")]
+    pub complete_access: bool,
+    #[prost(bytes = "vec", tag = "5")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SdoWriteResponse {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CommandRequest {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(double, repeated, tag = "2")]
+    pub args: Vec<f64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CommandResponse {}
+
+/// PDO entry types that can be read remotely.
+pub trait RemoteValue: PdoData + Send + Sync + 'static {
+    fn to_value(self) -> Value;
+}
+
+macro_rules! remote_value {
+    ($variant:ident as $conv:ty: $($t:ty),*) => {
+        $(impl RemoteValue for $t {
+            fn to_value(self) -> Value {
+                Value::$variant(self as $conv)
+            }
+        })*
+    };
+}
+
+remote_value!(Integer as i64: i8, i16, i32, i64);
+remote_value!(Unsigned as u64: u8, u16, u32, u64);
+remote_value!(Number as f64: f32, f64);
+
+impl RemoteValue for bool {
+    fn to_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+/// A command received by the service, to be executed by the application.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteCommand {
+    pub name: String,
+    pub args: Vec<f64>,
+}
+
+type ReadValue = Box<dyn Fn(&[u8]) -> Value + Send + Sync>;
+
+struct RemoteField {
+    name: String,
+    /// Index into the snapshot readers.
+    domain: usize,
+    read: ReadValue,
+}
+
+type Mailbox = Arc<Mutex<Box<dyn Backend + Send>>>;
+
+/// Builder for a [`GrpcService`].
+pub struct GrpcServiceBuilder {
+    token: String,
+    snapshots: Vec<(DomainIdx, SnapshotReader)>,
+    fields: Vec<RemoteField>,
+    mailbox: Option<Mailbox>,
+    commands: Option<CommandSender<RemoteCommand>>,
+}
+
+/// Implementation of the gRPC service.
+pub struct GrpcService {
+    snapshots: Vec<SnapshotReader>,
+    fields: Vec<RemoteField>,
+    mailbox: Option<Mailbox>,
+    commands: Option<CommandSender<RemoteCommand>>,
+}
+
+/// The service with the authentication in front, to add to a
+/// `tonic::transport::Server`.
+pub type GrpcServer = InterceptedService<MasterServer<GrpcService>, TokenCheck>;
+
+impl GrpcServiceBuilder {
+    /// Publish the snapshots of a domain, for the fields of this domain.
+    pub fn snapshot(mut self, domain: DomainIdx, reader: SnapshotReader) -> Self {
+        self.snapshots.push((domain, reader));
+        self
+    }
+
+    /// Publish a PDO entry under a name.
+    ///
+    /// Fails if no snapshot of the field's domain was added, see
+    /// [`snapshot_index`].
+    pub fn field<T: RemoteValue>(
+        mut self,
+        name: impl Into<String>,
+        field: Field<T>,
+    ) -> Result<Self> {
+        let domain = snapshot_index(&self.snapshots, &field)?;
+        self.fields.push(RemoteField {
+            name: name.into(),
+            domain,
+            read: Box::new(move |data| field.get(data).to_value()),
+        });
+        Ok(self)
+    }
+
+    /// Serve SDO reads and writes with this backend.
+    ///
+    /// Transfers block until the slave answers; they run on the blocking
+    /// thread pool of the runtime, one at a time.
+    pub fn mailbox<B: Backend + Send + 'static>(mut self, backend: B) -> Self {
+        self.mailbox = Some(Arc::new(Mutex::new(Box::new(backend))));
+        self
+    }
+
+    /// Pass received commands into this channel.
+    pub fn commands(mut self, sender: CommandSender<RemoteCommand>) -> Self {
+        self.commands = Some(sender);
+        self
+    }
+
+    pub fn build(self) -> GrpcServer {
+        let check = TokenCheck {
+            token: self.token.clone(),
+        };
+        MasterServer::with_interceptor(self.build_service(), check)
+    }
+
+    fn build_service(self) -> GrpcService {
+        GrpcService {
+            snapshots: self.snapshots.into_iter().map(|(_, r)| r).collect(),
+            fields: self.fields,
+            mailbox: self.mailbox,
+            commands: self.commands,
+        }
+    }
+
+    /// Build the service and serve it on `addr` until an error occurs.
+    pub async fn serve(self, addr: SocketAddr) -> std::result::Result<(), tonic::transport::Error> {
+        tonic::transport::Server::builder()
+            .add_service(self.build())
+            .serve(addr)
+            .await
+    }
+}
+
+impl GrpcService {
+    /// Build a service that accepts calls carrying `token`.
+    pub fn builder(token: impl Into<String>) -> GrpcServiceBuilder {
+        GrpcServiceBuilder {
+            token: token.into(),
+            snapshots: vec![],
+            fields: vec![],
+            mailbox: None,
+            commands: None,
+        }
+    }
+
+    async fn with_mailbox<T, F>(&self, f: F) -> std::result::Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut dyn Backend) -> Result<T> + Send + 'static,
+    {
+        let mailbox = self
+            .mailbox
+            .clone()
+            .ok_or_else(|| Status::unimplemented("no SDO access configured"))?;
+        tokio::task::spawn_blocking(move || {
+            let mut backend = mailbox.lock().unwrap_or_else(|e| e.into_inner());
+            f(backend.as_mut())
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(|e| Status::aborted(e.to_string()))
+    }
+}
+
+fn sdo_address(
+    slave: u32,
+    index: u32,
+    subindex: u32,
+) -> std::result::Result<(SlavePos, SdoIdx), Status> {
+    let invalid = |_| Status::invalid_argument("address out of range");
+    let slave = u16::try_from(slave).map_err(invalid)?;
+    let index = u16::try_from(index).map_err(invalid)?;
+    let subindex = u8::try_from(subindex).map_err(invalid)?;
+    Ok((SlavePos::from(slave), SdoIdx::new(index, subindex)))
+}
+
+#[tonic::async_trait]
+impl proto::master_server::Master for GrpcService {
+    async fn read_fields(
+        &self,
+        request: Request<ReadFieldsRequest>,
+    ) -> std::result::Result<Response<ReadFieldsResponse>, Status> {
+        let names = request.into_inner().names;
+        let fields = if names.is_empty() {
+            self.fields.iter().collect()
+        } else {
+            names
+                .iter()
+                .map(|name| {
+                    self.fields
+                        .iter()
+                        .find(|f| f.name == *name)
+                        .ok_or_else(|| Status::not_found(format!("no field {:?}", name)))
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?
+        };
+        // read every snapshot once, so that values of a domain are consistent
+        let mut snapshots = vec![None; self.snapshots.len()];
+        let values = fields
+            .into_iter()
+            .map(|field| {
+                let snapshot = snapshots[field.domain]
+                    .get_or_insert_with(|| self.snapshots[field.domain].read());
+                FieldValue {
+                    name: field.name.clone(),
+                    seq: snapshot.seq,
+                    dc_time: snapshot.dc_time,
+                    value: Some((field.read)(&snapshot.data)),
+                }
+            })
+            .collect();
+        Ok(Response::new(ReadFieldsResponse { values }))
+    }
+
+    async fn sdo_read(
+        &self,
+        request: Request<SdoReadRequest>,
+    ) -> std::result::Result<Response<SdoReadResponse>, Status> {
+        let req = request.into_inner();
+        let (slave, index) = sdo_address(req.slave, req.index, req.subindex)?;
+        let data = self
+            .with_mailbox(move |backend| {
+                let mut data = vec![0; SDO_BUFFER];
+                let size = backend.sdo_upload(slave, index, req.complete_access, &mut data)?;
+                data.truncate(size);
+                Ok(data)
+            })
+            .await?;
+        Ok(Response::new(SdoReadResponse { data }))
+    }
+
+    async fn sdo_write(
+        &self,
+        request: Request<SdoWriteRequest>,
+    ) -> std::result::Result<Response<SdoWriteResponse>, Status> {
+        let req = request.into_inner();
+        let (slave, index) = sdo_address(req.slave, req.index, req.subindex)?;
+        self.with_mailbox(move |backend| {
+            backend.sdo_download(slave, index, req.complete_access, &req.data)
+        })
+        .await?;
+        Ok(Response::new(SdoWriteResponse {}))
+    }
+
+    async fn command(
+        &self,
+        request: Request<CommandRequest>,
+    ) -> std::result::Result<Response<CommandResponse>, Status> {
+        let commands = self
+            .commands
+            .as_ref()
+            .ok_or_else(|| Status::unimplemented("no commands accepted"))?;
+        let req = request.into_inner();
+        commands
+            .try_send(RemoteCommand {
+                name: req.name,
+                args: req.args,
+            })
+            .map_err(|_| Status::resource_exhausted("command queue is full"))?;
+        Ok(Response::new(CommandResponse {}))
+    }
+}
+
+/// Checks the bearer token of every call.
+#[derive(Clone)]
+pub struct TokenCheck {
+    token: String,
+}
+
+impl tonic::service::Interceptor for TokenCheck {
+    fn call(&mut self, request: Request<()>) -> std::result::Result<Request<()>, Status> {
+        let expected = format!("Bearer {}", self.token);
+        let given = request
+            .metadata()
+            .get("authorization")
+            .map(MetadataValue::as_bytes)
+            .unwrap_or_default();
+        // compare in constant time, not to leak the length of a matching prefix
+        let equal = given.len() == expected.len()
+            && given
+                .iter()
+                .zip(expected.as_bytes())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0;
+        if equal {
+            Ok(request)
+        } else {
+            Err(Status::unauthenticated("invalid token"))
+        }
+    }
+}
+
+#[test]
+fn test_grpc_service() {
+    use crate::{
+        backend::{SimMaster, SimSlave},
+        runtime::{command_channel, snapshot_buffer},
+    };
+    use proto::master_server::Master as _;
+    use tonic::service::Interceptor;
+
+    let (mut writer, reader) = snapshot_buffer(4);
+    writer.publish(7, 1000, &[0x34, 0x12, 0xFF, 1]);
+    let (sender, mut receiver) = command_channel(1);
+    let sim = SimMaster::new(vec![
+        SimSlave::new("drive", SlaveId::new(2, 0x1234)).object(SdoIdx::new(0x6060, 0), &[1])
+    ]);
+    let service = GrpcService::builder("secret")
+        .snapshot(DomainIdx::from(0), reader)
+        .field(
+            "word",
+            Field::<u16>::new(0.into(), Offset { byte: 0, bit: 0 }),
+        )
+        .unwrap()
+        .field(
+            "byte",
+            Field::<i8>::new(0.into(), Offset { byte: 2, bit: 0 }),
+        )
+        .unwrap()
+        .field(
+            "bit",
+            Field::<bool>::new(0.into(), Offset { byte: 3, bit: 0 }),
+        )
+        .unwrap()
+        .mailbox(sim)
+        .commands(sender)
+        .build_service();
+
+    let mut check = TokenCheck {
+        token: "secret".into(),
+    };
+    let mut request = Request::new(());
+    assert!(check.call(Request::new(())).is_err());
+    request
+        .metadata_mut()
+        .insert("authorization", "Bearer secre".parse().unwrap());
+    assert!(check.call(request).is_err());
+    let mut request = Request::new(());
+    request
+        .metadata_mut()
+        .insert("authorization", "Bearer secret".parse().unwrap());
+    assert!(check.call(request).is_ok());
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let values = service
+            .read_fields(Request::new(ReadFieldsRequest { names: vec![] }))
+            .await
+            .unwrap()
+            .into_inner()
+            .values;
+        assert_eq!(values.len(), 3);
+        assert_eq!((values[0].seq, values[0].dc_time), (7, 1000));
+        assert_eq!(values[0].value, Some(Value::Unsigned(0x1234)));
+        assert_eq!(values[1].value, Some(Value::Integer(-1)));
+        assert_eq!(values[2].value, Some(Value::Boolean(true)));
+        let missing = ReadFieldsRequest {
+            names: vec!["nope".into()],
+        };
+        assert!(service.read_fields(Request::new(missing)).await.is_err());
+
+        let write = SdoWriteRequest {
+            slave: 0,
+            index: 0x6060,
+            subindex: 0,
+            complete_access: false,
+            data: vec![8],
+        };
+        service.sdo_write(Request::new(write)).await.unwrap();
+        let read = SdoReadRequest {
+            slave: 0,
+            index: 0x6060,
+            subindex: 0,
+            complete_access: false,
+        };
+        let data = service.sdo_read(Request::new(read)).await.unwrap();
+        assert_eq!(data.into_inner().data, vec![8]);
+
+        let command = CommandRequest {
+            name: "move_to".into(),
+            args: vec![1.5],
+        };
+        service.command(Request::new(command)).await.unwrap();
+    });
+    assert_eq!(
+        receiver.try_recv(),
+        Some(RemoteCommand {
+            name: "move_to".into(),
+            args: vec![1.5]
+        })
+    );
+}
@@ -0,0 +1,23 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Instrumentation with `tracing`, compiled out without the `tracing` feature.
+//!
+//! The hot path of the cyclic exchange only emits events with plain numeric
+//! fields, which do not allocate unless the subscriber does.
+
+/// Enter a span until the end of the enclosing block.
+macro_rules! trace_span {
+    ($level:ident, $name:literal $(, $($fields:tt)*)?) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::span!(tracing::Level::$level, $name $(, $($fields)*)?).entered();
+    };
+}
+
+/// Emit an event.
+macro_rules! trace_event {
+    ($level:ident, $($args:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::event!(tracing::Level::$level, $($args)+);
+    };
+}
@@ -0,0 +1,86 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Minimal helpers to emit JSON without pulling in a serializer.
+
+use crate::types::BusHealth;
+use std::io::{self, Write};
+
+/// Write `s` as a quoted JSON string.
+pub(crate) fn write_str(out: &mut dyn Write, s: &str) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_all(b"\\\"")?,
+            '\\' => out.write_all(b"\\\\")?,
+            '\n' => out.write_all(b"\\n")?,
+            '\r' => out.write_all(b"\\r")?,
+            '\t' => out.write_all(b"\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    out.write_all(b"\"")
+}
+
+/// Write the bus health summary as a JSON object.
+pub(crate) fn write_health(out: &mut dyn Write, health: &BusHealth) -> io::Result<()> {
+    write!(
+        out,
+        "{{\"ok\":{},\"link_up\":{},\"slaves_responding\":{},\
+         \"slaves_configured\":{},\"worst_al_state\":",
+        health.is_ok(),
+        health.link_up,
+        health.slaves_responding,
+        health.slaves_configured
+    )?;
+    match health.worst_al_state {
+        Some(state) => write!(out, "\"{:?}\"", state)?,
+        None => write!(out, "null")?,
+    }
+    write!(out, ",\"dc_deviation\":")?;
+    match health.dc_deviation {
+        Some(dev) => write!(out, "{}", dev)?,
+        None => write!(out, "null")?,
+    }
+    write!(
+        out,
+        ",\"emergency_overruns\":{},\"domains\":[",
+        health.emergency_overruns
+    )?;
+    for (i, (idx, state)) in health.domains.iter().enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        write!(
+            out,
+            "{{\"index\":{},\"working_counter\":{},\"wc_state\":\"{:?}\",\
+             \"redundancy_active\":{}}}",
+            usize::from(*idx),
+            state.working_counter,
+            state.wc_state,
+            state.redundancy_active
+        )?;
+    }
+    write!(out, "],\"power_faults\":[")?;
+    for (i, fault) in health.power_faults.iter().enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        write!(
+            out,
+            "{{\"slave\":{},\"channel\":{},\"kind\":\"{:?}\"}}",
+            u16::from(fault.slave),
+            fault.channel,
+            fault.kind
+        )?;
+    }
+    write!(out, "]}}")
+}
+
+#[test]
+fn test_write_str() {
+    let mut out = vec![];
+    write_str(&mut out, "a \"b\"\\\n\u{1}é").unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), r#""a \"b\"\\\n\u0001é""#);
+}
@@ -1,13 +1,57 @@
 // Part of ethercat-rs. Copyright 2018-2022 by the authors.
 // This work is dual-licensed under Apache 2.0 and MIT terms.
 
+#[cfg(target_os = "linux")]
 use ethercat_sys as ec;
 
+#[macro_use]
+#[cfg_attr(not(target_os = "linux"), allow(unused_macros))]
+mod instrument;
+
+pub mod alarms;
+pub mod backend;
+#[cfg(target_os = "linux")]
 mod convert;
+pub mod devices;
+pub mod diagnostics;
+pub mod eds;
+#[cfg(feature = "esi")]
+pub mod esi;
+mod field;
+pub mod gateway;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod json;
+pub mod logging;
+#[cfg(target_os = "linux")]
 mod master;
+#[cfg(feature = "modbus")]
+pub mod modbus;
+#[cfg(feature = "opcua")]
+pub mod opcua;
+#[cfg(all(feature = "python", target_os = "linux"))]
+pub mod python;
+#[cfg(feature = "ros2")]
+pub mod ros2;
+#[cfg(feature = "serde")]
+mod serialize;
+mod topology;
 mod types;
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+mod validation;
+
+pub mod runtime;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
+#[cfg(target_os = "linux")]
+pub use self::master::{
+    ActivateError, Active, Config, DeactivateError, Domain, Master, MasterAccess, MasterMonitor,
+    PhaseError, SlaveConfig,
+};
 pub use self::{
-    master::{Domain, Master, MasterAccess, SlaveConfig},
+    field::{AnyField, DomainView, Field, FieldSet, PdoData, PdoNumber, ScaledField},
+    topology::{port_name, Link, Topology, TopologyNode},
     types::*,
+    validation::ConfigProblem,
 };
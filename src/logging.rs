@@ -0,0 +1,40 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Recording of process data to disk.
+//!
+//! Loggers are fed from the cyclic thread without allocating or blocking;
+//! the encoding and writing happens on a separate thread. The logs can be
+//! read back with [`read_log`], e.g. to compare recordings.
+
+mod csv;
+mod mcap;
+mod pool;
+mod replay;
+
+pub use self::{
+    csv::{CsvLogger, CsvLoggerBuilder},
+    mcap::{McapChannel, McapLogger, McapLoggerBuilder},
+    replay::{align, read_csv, read_log, read_mcap, Series},
+};
+
+use crate::field::PdoData;
+use std::fmt::Display;
+
+/// PDO entry types that can be logged.
+pub trait LogValue: PdoData + Display + 'static {
+    /// Type of the value in a JSON schema.
+    const JSON_TYPE: &'static str;
+}
+
+macro_rules! log_value {
+    ($json_type:literal: $($t:ty),*) => {
+        $(impl LogValue for $t {
+            const JSON_TYPE: &'static str = $json_type;
+        })*
+    };
+}
+
+log_value!("integer": u8, u16, u32, u64, i8, i16, i32, i64);
+log_value!("number": f32, f64);
+log_value!("boolean": bool);
@@ -0,0 +1,234 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{
+    pool::{RowPool, RowWriter},
+    LogValue,
+};
+use crate::{field::Field, types::DomainIdx};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+type Read = Box<dyn Fn(&[u8]) -> u64 + Send>;
+type Format = fn(u64, &mut dyn Write) -> io::Result<()>;
+
+struct Column {
+    name: String,
+    read: Read,
+    format: Format,
+}
+
+struct Row {
+    seq: u64,
+    dc_time: u64,
+    values: Vec<u64>,
+    image: Vec<u8>,
+}
+
+struct CsvWriter<W> {
+    out: W,
+    formats: Vec<Format>,
+}
+
+/// Builder for a [`CsvLogger`].
+pub struct CsvLoggerBuilder {
+    domain: DomainIdx,
+    columns: Vec<Column>,
+    image_size: usize,
+    end: usize,
+    capacity: usize,
+}
+
+/// Writes selected PDO entries of a domain as CSV rows, one per recorded
+/// cycle.
+///
+/// Each row starts with the cycle number and the DC time in ns. Rows are
+/// passed through a bounded pool of preallocated buffers; when the writer
+/// falls behind, rows are dropped and counted instead of blocking the cycle.
+pub struct CsvLogger {
+    domain: DomainIdx,
+    readers: Vec<Read>,
+    end: usize,
+    pool: RowPool<Row>,
+}
+
+impl CsvLoggerBuilder {
+    /// Add a column with the value of a PDO entry.
+    ///
+    /// Panics if the field belongs to another domain.
+    pub fn field<T: LogValue>(mut self, name: impl Into<String>, field: Field<T>) -> Self {
+        assert_eq!(field.domain, self.domain, "field of another domain");
+        self.end = self.end.max(field.end());
+        self.columns.push(Column {
+            name: name.into(),
+            read: Box::new(move |data| field.get(data).to_raw()),
+            format: format_value::<T>,
+        });
+        self
+    }
+
+    /// Add a column with the whole process image of `size` bytes as hex.
+    pub fn image(mut self, size: usize) -> Self {
+        self.image_size = size;
+        self.end = self.end.max(size);
+        self
+    }
+
+    /// Number of rows that can be pending (default 1024).
+    pub fn capacity(mut self, rows: usize) -> Self {
+        self.capacity = rows;
+        self
+    }
+
+    pub fn create(self, path: impl AsRef<Path>) -> io::Result<CsvLogger> {
+        self.build(BufWriter::new(File::create(path)?))
+    }
+
+    /// Write the header and start the writer thread.
+    pub fn build<W: Write + Send + 'static>(self, mut out: W) -> io::Result<CsvLogger> {
+        write!(out, "cycle,dc_time")?;
+        for c in &self.columns {
+            write!(out, ",{}", escape(&c.name))?;
+        }
+        if self.image_size > 0 {
+            write!(out, ",image")?;
+        }
+        writeln!(out)?;
+
+        let (readers, formats): (Vec<_>, Vec<_>) =
+            self.columns.into_iter().map(|c| (c.read, c.format)).unzip();
+        let (count, image_size) = (readers.len(), self.image_size);
+        let pool = RowPool::spawn(
+            "ethercat-csv",
+            self.capacity,
+            || Row {
+                seq: 0,
+                dc_time: 0,
+                values: vec![0; count],
+                image: vec![0; image_size],
+            },
+            CsvWriter { out, formats },
+        )?;
+        Ok(CsvLogger {
+            domain: self.domain,
+            readers,
+            end: self.end,
+            pool,
+        })
+    }
+}
+
+impl CsvLogger {
+    pub fn builder(domain: DomainIdx) -> CsvLoggerBuilder {
+        CsvLoggerBuilder {
+            domain,
+            columns: vec![],
+            image_size: 0,
+            end: 0,
+            capacity: 1024,
+        }
+    }
+
+    pub fn domain(&self) -> DomainIdx {
+        self.domain
+    }
+
+    /// Record the process image `data` of cycle `seq`, sent at `dc_time`.
+    ///
+    /// Never blocks or allocates; the row is dropped if no buffer is free or
+    /// `data` is too short for the logged entries.
+    pub fn record(&mut self, seq: u64, dc_time: u64, data: &[u8]) {
+        if data.len() < self.end {
+            self.pool.drop_row();
+            return;
+        }
+        let mut row = match self.pool.acquire() {
+            Some(row) => row,
+            None => return,
+        };
+        row.seq = seq;
+        row.dc_time = dc_time;
+        for (value, read) in row.values.iter_mut().zip(&self.readers) {
+            *value = read(data);
+        }
+        let n = row.image.len();
+        row.image.copy_from_slice(&data[..n]);
+        self.pool.submit(row);
+    }
+
+    /// Number of rows lost so far.
+    pub fn dropped(&self) -> u64 {
+        self.pool.dropped()
+    }
+
+    /// Write the pending rows, stop the writer thread and return its result.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.pool.finish()
+    }
+}
+
+impl<W: Write + Send + 'static> RowWriter for CsvWriter<W> {
+    type Row = Row;
+
+    fn write(&mut self, row: &Row) -> io::Result<()> {
+        let out = &mut self.out;
+        write!(out, "{},{}", row.seq, row.dc_time)?;
+        for (value, format) in row.values.iter().zip(&self.formats) {
+            out.write_all(b",")?;
+            format(*value, out)?;
+        }
+        if !row.image.is_empty() {
+            out.write_all(b",")?;
+            for b in &row.image {
+                write!(out, "{:02x}", b)?;
+            }
+        }
+        writeln!(out)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn format_value<T: LogValue>(raw: u64, out: &mut dyn Write) -> io::Result<()> {
+    write!(out, "{}", T::from_raw(raw))
+}
+
+fn escape(name: &str) -> String {
+    if name.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    } else {
+        name.to_string()
+    }
+}
+
+#[test]
+fn test_csv_logger() {
+    use crate::types::Offset;
+
+    let domain = DomainIdx::from(0);
+    let speed = Field::<i16>::new(domain, Offset { byte: 0, bit: 0 });
+    let ready = Field::<bool>::new(domain, Offset { byte: 2, bit: 3 });
+    let path = std::env::temp_dir().join(format!("ethercat-csv-{}.csv", std::process::id()));
+    let mut logger = CsvLogger::builder(domain)
+        .field("speed", speed)
+        .field("ready, drive 1", ready)
+        .image(3)
+        .capacity(2)
+        .create(&path)
+        .unwrap();
+    logger.record(1, 1000, &[0xFE, 0xFF, 0x08]);
+    logger.record(2, 2000, &[0x02, 0x00]);
+    assert_eq!(logger.dropped(), 1);
+    logger.finish().unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        text,
+        "cycle,dc_time,speed,\"ready, drive 1\",image\n1,1000,-2,true,feff08\n"
+    );
+}
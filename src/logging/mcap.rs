@@ -0,0 +1,480 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{
+    pool::{RowPool, RowWriter},
+    LogValue,
+};
+use crate::{field::Field, json, types::*};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::Range,
+    path::Path,
+};
+
+pub(super) const MAGIC: &[u8] = b"\x89MCAP0\r\n";
+
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+pub(super) const OP_CHANNEL: u8 = 0x04;
+pub(super) const OP_MESSAGE: u8 = 0x05;
+pub(super) const OP_DATA_END: u8 = 0x0F;
+
+/// Offset between the DC epoch (2000-01-01) and the Unix epoch in ns.
+pub(super) const DC_EPOCH: u64 = 946_684_800_000_000_000;
+
+type Read = Box<dyn Fn(&[u8]) -> u64 + Send>;
+type Format = fn(u64, &mut dyn Write) -> io::Result<()>;
+
+struct Entry {
+    domain: DomainIdx,
+    end: usize,
+    name: String,
+    json_type: &'static str,
+    read: Read,
+    format: Format,
+}
+
+/// A group of PDO entries recorded as one MCAP channel with its own schema.
+pub struct McapChannel {
+    topic: String,
+    entries: Vec<Entry>,
+}
+
+impl McapChannel {
+    pub fn new(topic: impl Into<String>) -> Self {
+        Self {
+            topic: topic.into(),
+            entries: vec![],
+        }
+    }
+
+    /// A channel for a PDO of a slave, named `/slave/<pos>/pdo/<index>`.
+    pub fn pdo(slave: SlavePos, pdo: PdoIdx) -> Self {
+        Self::new(format!(
+            "/slave/{}/pdo/{:#06x}",
+            u16::from(slave),
+            u16::from(pdo)
+        ))
+    }
+
+    pub fn field<T: LogValue>(mut self, name: impl Into<String>, field: Field<T>) -> Self {
+        self.entries.push(Entry {
+            domain: field.domain,
+            end: field.end(),
+            name: name.into(),
+            json_type: T::JSON_TYPE,
+            read: Box::new(move |data| field.get(data).to_raw()),
+            format: format_value::<T>,
+        });
+        self
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Payload {
+    ProcessData,
+    Master(u32, u8, bool),
+    Domain(u32, WcState, bool),
+}
+
+struct Row {
+    seq: u64,
+    dc_time: u64,
+    payload: Payload,
+    values: Vec<u64>,
+}
+
+/// Builder for a [`McapLogger`].
+pub struct McapLoggerBuilder {
+    domain: DomainIdx,
+    channels: Vec<McapChannel>,
+    capacity: usize,
+}
+
+/// Records process data and master/domain state as an MCAP file, readable by
+/// Foxglove Studio or PlotJuggler.
+///
+/// Messages are JSON encoded with a JSON schema per channel. Besides the
+/// configured PDO channels, the file contains the channels `/master/state`
+/// and `/domain/<idx>/state`. Log times are the DC times converted to the
+/// Unix epoch. Like the [`CsvLogger`](super::CsvLogger), it never blocks
+/// the cyclic thread and drops rows when the writer falls behind.
+pub struct McapLogger {
+    domain: DomainIdx,
+    readers: Vec<Read>,
+    end: usize,
+    pool: RowPool<Row>,
+}
+
+/// Value range and the JSON keys and formats of the entries of a channel.
+type ChannelFormat = (Range<usize>, Vec<(Vec<u8>, Format)>);
+
+struct McapWriter<W> {
+    out: W,
+    channels: Vec<ChannelFormat>,
+    sequences: Vec<u32>,
+    buf: Vec<u8>,
+}
+
+impl McapLoggerBuilder {
+    /// Add a PDO channel.
+    pub fn channel(mut self, channel: McapChannel) -> Self {
+        self.channels.push(channel);
+        self
+    }
+
+    /// Number of rows that can be pending (default 1024).
+    pub fn capacity(mut self, rows: usize) -> Self {
+        self.capacity = rows;
+        self
+    }
+
+    pub fn create(self, path: impl AsRef<Path>) -> io::Result<McapLogger> {
+        self.build(BufWriter::new(File::create(path)?))
+    }
+
+    /// Write the header, schemas and channels and start the writer thread.
+    ///
+    /// Panics if an entry belongs to another domain.
+    pub fn build<W: Write + Send + 'static>(self, out: W) -> io::Result<McapLogger> {
+        let mut writer = McapWriter {
+            out,
+            channels: vec![],
+            sequences: vec![0; self.channels.len() + 2],
+            buf: vec![],
+        };
+        writer.out.write_all(MAGIC)?;
+        writer.write_record(OP_HEADER, |b| {
+            put_str(b, "");
+            put_str(b, concat!("ethercat-rs ", env!("CARGO_PKG_VERSION")));
+        })?;
+
+        let mut readers = vec![];
+        let mut end = 0;
+        for (id, channel) in self.channels.into_iter().enumerate() {
+            let mut schema = vec![];
+            schema_json(
+                &mut schema,
+                channel.entries.iter().map(|e| (&e.name[..], e.json_type)),
+            )?;
+            writer.write_channel(id as u16 + 1, &channel.topic, &schema)?;
+            let start = readers.len();
+            let mut formats = vec![];
+            for e in channel.entries {
+                assert_eq!(e.domain, self.domain, "field of another domain");
+                end = end.max(e.end);
+                let mut key = vec![];
+                json::write_str(&mut key, &e.name)?;
+                key.push(b':');
+                formats.push((key, e.format));
+                readers.push(e.read);
+            }
+            writer.channels.push((start..readers.len(), formats));
+        }
+        let mut schema = vec![];
+        schema_json(
+            &mut schema,
+            [
+                ("slaves_responding", "integer"),
+                ("al_states", "integer"),
+                ("link_up", "boolean"),
+            ],
+        )?;
+        writer.write_channel(writer.master_channel(), "/master/state", &schema)?;
+        schema.clear();
+        schema_json(
+            &mut schema,
+            [
+                ("working_counter", "integer"),
+                ("wc_state", "string"),
+                ("redundancy_active", "boolean"),
+            ],
+        )?;
+        let topic = format!("/domain/{}/state", usize::from(self.domain));
+        writer.write_channel(writer.master_channel() + 1, &topic, &schema)?;
+        writer.out.flush()?;
+
+        let count = readers.len();
+        let pool = RowPool::spawn(
+            "ethercat-mcap",
+            self.capacity,
+            || Row {
+                seq: 0,
+                dc_time: 0,
+                payload: Payload::ProcessData,
+                values: vec![0; count],
+            },
+            writer,
+        )?;
+        Ok(McapLogger {
+            domain: self.domain,
+            readers,
+            end,
+            pool,
+        })
+    }
+}
+
+impl McapLogger {
+    pub fn builder(domain: DomainIdx) -> McapLoggerBuilder {
+        McapLoggerBuilder {
+            domain,
+            channels: vec![],
+            capacity: 1024,
+        }
+    }
+
+    pub fn domain(&self) -> DomainIdx {
+        self.domain
+    }
+
+    /// Record the process image `data` of cycle `seq`, sent at `dc_time`.
+    ///
+    /// Never blocks or allocates; the row is dropped if no buffer is free or
+    /// `data` is too short for the logged entries.
+    pub fn record(&mut self, seq: u64, dc_time: u64, data: &[u8]) {
+        if data.len() < self.end {
+            self.pool.drop_row();
+            return;
+        }
+        if let Some(mut row) = self.pool.acquire() {
+            for (value, read) in row.values.iter_mut().zip(&self.readers) {
+                *value = read(data);
+            }
+            self.submit(row, seq, dc_time, Payload::ProcessData);
+        }
+    }
+
+    pub fn record_master_state(&mut self, seq: u64, dc_time: u64, state: &MasterState) {
+        if let Some(row) = self.pool.acquire() {
+            self.submit(
+                row,
+                seq,
+                dc_time,
+                Payload::Master(state.slaves_responding, state.al_states, state.link_up),
+            );
+        }
+    }
+
+    pub fn record_domain_state(&mut self, seq: u64, dc_time: u64, state: &DomainState) {
+        if let Some(row) = self.pool.acquire() {
+            let payload = Payload::Domain(
+                state.working_counter,
+                state.wc_state,
+                state.redundancy_active,
+            );
+            self.submit(row, seq, dc_time, payload);
+        }
+    }
+
+    fn submit(&mut self, mut row: Row, seq: u64, dc_time: u64, payload: Payload) {
+        row.seq = seq;
+        row.dc_time = dc_time;
+        row.payload = payload;
+        self.pool.submit(row);
+    }
+
+    /// Number of rows lost so far.
+    pub fn dropped(&self) -> u64 {
+        self.pool.dropped()
+    }
+
+    /// Write the pending rows, finish the file and return the writer result.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.pool.finish()
+    }
+}
+
+impl<W: Write> McapWriter<W> {
+    fn master_channel(&self) -> u16 {
+        self.channels.len() as u16 + 1
+    }
+
+    fn write_record(&mut self, op: u8, content: impl FnOnce(&mut Vec<u8>)) -> io::Result<()> {
+        self.buf.clear();
+        content(&mut self.buf);
+        self.out.write_all(&[op])?;
+        self.out.write_all(&(self.buf.len() as u64).to_le_bytes())?;
+        self.out.write_all(&self.buf)
+    }
+
+    /// Write a schema and a channel using it, with the same id.
+    fn write_channel(&mut self, id: u16, topic: &str, schema: &[u8]) -> io::Result<()> {
+        self.write_record(OP_SCHEMA, |b| {
+            b.extend_from_slice(&id.to_le_bytes());
+            put_str(b, topic);
+            put_str(b, "jsonschema");
+            put_bytes(b, schema);
+        })?;
+        self.write_record(OP_CHANNEL, |b| {
+            b.extend_from_slice(&id.to_le_bytes());
+            b.extend_from_slice(&id.to_le_bytes());
+            put_str(b, topic);
+            put_str(b, "json");
+            // empty metadata map
+            b.extend_from_slice(&0_u32.to_le_bytes());
+        })
+    }
+
+    fn write_message(&mut self, id: u16, dc_time: u64, json: &[u8]) -> io::Result<()> {
+        let seq = &mut self.sequences[id as usize - 1];
+        *seq = seq.wrapping_add(1);
+        let seq = *seq;
+        let time = dc_time + DC_EPOCH;
+        self.write_record(OP_MESSAGE, |b| {
+            b.extend_from_slice(&id.to_le_bytes());
+            b.extend_from_slice(&seq.to_le_bytes());
+            b.extend_from_slice(&time.to_le_bytes());
+            b.extend_from_slice(&time.to_le_bytes());
+            b.extend_from_slice(json);
+        })
+    }
+}
+
+impl<W: Write + Send + 'static> RowWriter for McapWriter<W> {
+    type Row = Row;
+
+    fn write(&mut self, row: &Row) -> io::Result<()> {
+        let mut json = vec![];
+        match row.payload {
+            Payload::ProcessData => {
+                for i in 0..self.channels.len() {
+                    json.clear();
+                    json.push(b'{');
+                    let (range, formats) = &self.channels[i];
+                    let values = row.values[range.clone()].iter().zip(formats);
+                    for (j, (value, (key, format))) in values.enumerate() {
+                        if j > 0 {
+                            json.push(b',');
+                        }
+                        json.extend_from_slice(key);
+                        format(*value, &mut json)?;
+                    }
+                    json.push(b'}');
+                    self.write_message(i as u16 + 1, row.dc_time, &json)?;
+                }
+                Ok(())
+            }
+            Payload::Master(slaves_responding, al_states, link_up) => {
+                write!(
+                    json,
+                    r#"{{"slaves_responding":{},"al_states":{},"link_up":{}}}"#,
+                    slaves_responding, al_states, link_up
+                )?;
+                self.write_message(self.master_channel(), row.dc_time, &json)
+            }
+            Payload::Domain(working_counter, wc_state, redundancy_active) => {
+                let wc_state = match wc_state {
+                    WcState::Zero => "zero",
+                    WcState::Incomplete => "incomplete",
+                    WcState::Complete => "complete",
+                };
+                write!(
+                    json,
+                    r#"{{"working_counter":{},"wc_state":"{}","redundancy_active":{}}}"#,
+                    working_counter, wc_state, redundancy_active
+                )?;
+                self.write_message(self.master_channel() + 1, row.dc_time, &json)
+            }
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        // no summary section, so the data section CRC and offsets stay 0
+        self.write_record(OP_DATA_END, |b| b.extend_from_slice(&0_u32.to_le_bytes()))?;
+        self.write_record(OP_FOOTER, |b| b.extend_from_slice(&[0; 20]))?;
+        self.out.write_all(MAGIC)?;
+        self.out.flush()
+    }
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+    put_bytes(buf, s.as_bytes());
+}
+
+fn put_bytes(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(data);
+}
+
+fn schema_json<'a>(
+    out: &mut Vec<u8>,
+    properties: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> io::Result<()> {
+    out.extend_from_slice(br#"{"type":"object","properties":{"#);
+    for (i, (name, json_type)) in properties.into_iter().enumerate() {
+        if i > 0 {
+            out.push(b',');
+        }
+        json::write_str(out, name)?;
+        write!(out, r#":{{"type":"{}"}}"#, json_type)?;
+    }
+    out.extend_from_slice(b"}}");
+    Ok(())
+}
+
+fn format_value<T: LogValue>(raw: u64, out: &mut dyn Write) -> io::Result<()> {
+    let value = T::from_raw(raw).to_string();
+    match &value[..] {
+        "NaN" | "inf" | "-inf" => out.write_all(b"null"),
+        _ => out.write_all(value.as_bytes()),
+    }
+}
+
+#[test]
+fn test_mcap_logger() {
+    let domain = DomainIdx::from(0);
+    let path = std::env::temp_dir().join(format!("ethercat-mcap-{}.mcap", std::process::id()));
+    let channel = McapChannel::pdo(SlavePos::from(2), PdoIdx::from(0x1A00))
+        .field(
+            "position",
+            Field::<i32>::new(domain, Offset { byte: 0, bit: 0 }),
+        )
+        .field(
+            "ready",
+            Field::<bool>::new(domain, Offset { byte: 4, bit: 0 }),
+        );
+    let mut logger = McapLogger::builder(domain)
+        .channel(channel)
+        .create(&path)
+        .unwrap();
+    logger.record(1, 1000, &[0xFF, 0xFF, 0xFF, 0xFF, 1]);
+    logger.record_master_state(
+        1,
+        1000,
+        &MasterState {
+            slaves_responding: 3,
+            al_states: 8,
+            link_up: true,
+        },
+    );
+    logger.finish().unwrap();
+    let data = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(data.starts_with(MAGIC) && data.ends_with(MAGIC));
+    let mut ops = vec![];
+    let mut messages = vec![];
+    let mut rest = &data[MAGIC.len()..data.len() - MAGIC.len()];
+    while !rest.is_empty() {
+        let mut len = [0; 8];
+        len.copy_from_slice(&rest[1..9]);
+        let len = u64::from_le_bytes(len) as usize;
+        ops.push(rest[0]);
+        if rest[0] == OP_MESSAGE {
+            messages.push(String::from_utf8(rest[9 + 22..9 + len].to_vec()).unwrap());
+        }
+        rest = &rest[9 + len..];
+    }
+    assert_eq!(ops, [1, 3, 4, 3, 4, 3, 4, 5, 5, 15, 2]);
+    assert_eq!(
+        messages,
+        [
+            r#"{"position":-1,"ready":true}"#,
+            r#"{"slaves_responding":3,"al_states":8,"link_up":true}"#
+        ]
+    );
+}
@@ -0,0 +1,120 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use crate::runtime::{command_channel, CommandReceiver, CommandSender};
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Encodes the rows of a [`RowPool`] on the writer thread.
+pub(super) trait RowWriter: Send + 'static {
+    type Row: Send + 'static;
+
+    fn write(&mut self, row: &Self::Row) -> io::Result<()>;
+
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// A fixed set of preallocated rows, filled by the cyclic thread and handed
+/// to a writer thread, which returns them after writing.
+pub(super) struct RowPool<R> {
+    free: CommandReceiver<R>,
+    full: CommandSender<R>,
+    dropped: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl<R: Send + 'static> RowPool<R> {
+    pub fn spawn<W>(
+        name: &str,
+        capacity: usize,
+        mut make_row: impl FnMut() -> R,
+        mut writer: W,
+    ) -> io::Result<Self>
+    where
+        W: RowWriter<Row = R>,
+    {
+        let (free_tx, free_rx) = command_channel(capacity);
+        let (full_tx, mut full_rx) = command_channel(capacity);
+        for _ in 0..free_tx.capacity() {
+            let _ = free_tx.try_send(make_row());
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name(name.into())
+                .spawn(move || loop {
+                    let stopping = stop.load(Ordering::Acquire);
+                    while let Some(row) = full_rx.try_recv() {
+                        writer.write(&row)?;
+                        let _ = free_tx.try_send(row);
+                    }
+                    if stopping {
+                        return writer.finish();
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                })?
+        };
+        Ok(Self {
+            free: free_rx,
+            full: full_tx,
+            dropped: Arc::new(AtomicU64::new(0)),
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Take a free row, or count a dropped one if there is none.
+    pub fn acquire(&mut self) -> Option<R> {
+        let row = self.free.try_recv();
+        if row.is_none() {
+            self.drop_row();
+        }
+        row
+    }
+
+    pub fn submit(&mut self, row: R) {
+        // cannot fail, both queues hold all rows
+        let _ = self.full.try_send(row);
+    }
+
+    pub fn drop_row(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Write the pending rows, stop the writer thread and return its result.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Release);
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::Other, "writer panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<R> Drop for RowPool<R> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            match thread.join() {
+                Ok(Err(err)) => log::error!("logger: {}", err),
+                Err(_) => log::error!("logger: writer panicked"),
+                Ok(Ok(())) => (),
+            }
+        }
+    }
+}
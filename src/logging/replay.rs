@@ -0,0 +1,360 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::mcap::{DC_EPOCH, MAGIC, OP_CHANNEL, OP_DATA_END, OP_MESSAGE};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead},
+    path::Path,
+};
+
+/// The values of one logged entry over time, read back from a log file.
+///
+/// Values are converted to `f64`, booleans to 0 and 1; 64 bit integers
+/// beyond 2^53 lose precision.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    /// The CSV column, or the MCAP topic and key, e.g.
+    /// `/slave/2/pdo/0x1a00/position`.
+    pub name: String,
+    /// DC time in ns and value.
+    pub points: Vec<(u64, f64)>,
+}
+
+/// Read a log written by a [`CsvLogger`](super::CsvLogger) or a
+/// [`McapLogger`](super::McapLogger), depending on its content.
+pub fn read_log(path: impl AsRef<Path>) -> io::Result<Vec<Series>> {
+    let data = fs::read(path)?;
+    if data.starts_with(MAGIC) {
+        read_mcap(&data)
+    } else {
+        read_csv(&data[..])
+    }
+}
+
+/// Read the columns of a CSV log, except the cycle number and the image.
+pub fn read_csv<R: BufRead>(input: R) -> io::Result<Vec<Series>> {
+    let mut lines = input.lines();
+    let header = match lines.next() {
+        Some(line) => split_csv(&line?),
+        None => return Ok(vec![]),
+    };
+    if header.len() < 2 || header[0] != "cycle" || header[1] != "dc_time" {
+        return Err(invalid("not a CSV log"));
+    }
+    let image = header.last().map(String::as_str) == Some("image");
+    let columns = header.len() - 2 - image as usize;
+    let mut series: Vec<_> = header[2..2 + columns]
+        .iter()
+        .map(|name| Series {
+            name: name.clone(),
+            points: vec![],
+        })
+        .collect();
+    for line in lines {
+        let row = split_csv(&line?);
+        if row.len() < 2 + columns {
+            // the last row of a log that was not finished
+            break;
+        }
+        let time = row[1]
+            .parse()
+            .map_err(|_| invalid(&format!("invalid DC time {:?}", row[1])))?;
+        for (s, value) in series.iter_mut().zip(&row[2..]) {
+            let value = parse_value(value)
+                .ok_or_else(|| invalid(&format!("invalid value {:?} of {}", value, s.name)))?;
+            s.points.push((time, value));
+        }
+    }
+    Ok(series)
+}
+
+/// Read the JSON channels of an MCAP log.
+///
+/// Reading stops at the end of the data or at a truncated record, so that
+/// logs of crashed applications can be read as well. String values, like
+/// the working counter state, are skipped.
+pub fn read_mcap(data: &[u8]) -> io::Result<Vec<Series>> {
+    if !data.starts_with(MAGIC) {
+        return Err(invalid("not an MCAP file"));
+    }
+    let mut topics = HashMap::new();
+    let mut series: Vec<Series> = vec![];
+    let mut index = HashMap::new();
+    let mut rest = &data[MAGIC.len()..];
+    while rest.len() >= 9 {
+        let op = rest[0];
+        let len = u64::from_le_bytes(array(&rest[1..9])) as usize;
+        let record = match len.checked_add(9).and_then(|end| rest.get(9..end)) {
+            Some(record) => record,
+            None => break,
+        };
+        rest = &rest[9 + len..];
+        match op {
+            OP_CHANNEL => {
+                let mut r = Reader(record);
+                let id = r.u16()?;
+                let _schema = r.u16()?;
+                let topic = r.string()?;
+                if r.string()? == "json" {
+                    topics.insert(id, topic);
+                }
+            }
+            OP_MESSAGE => {
+                let mut r = Reader(record);
+                let id = r.u16()?;
+                let _seq = r.take(4)?;
+                let time = u64::from_le_bytes(array(r.take(8)?)).saturating_sub(DC_EPOCH);
+                let _publish_time = r.take(8)?;
+                let topic = match topics.get(&id) {
+                    Some(topic) => topic,
+                    None => continue,
+                };
+                let json = std::str::from_utf8(r.0).map_err(|_| invalid("invalid message"))?;
+                for (key, value) in parse_json_object(json)? {
+                    let value = match value {
+                        Some(value) => value,
+                        None => continue,
+                    };
+                    let name = format!("{}/{}", topic, key);
+                    let i = *index.entry(name.clone()).or_insert_with(|| {
+                        series.push(Series {
+                            name,
+                            points: vec![],
+                        });
+                        series.len() - 1
+                    });
+                    series[i].points.push((time, value));
+                }
+            }
+            OP_DATA_END => break,
+            _ => (),
+        }
+    }
+    Ok(series)
+}
+
+/// Align series on the union of their time stamps.
+///
+/// Each row holds the time and the last value of each series at that time,
+/// `None` before its first value.
+pub fn align(series: &[&Series]) -> Vec<(u64, Vec<Option<f64>>)> {
+    let mut times: Vec<u64> = series
+        .iter()
+        .flat_map(|s| s.points.iter().map(|p| p.0))
+        .collect();
+    times.sort_unstable();
+    times.dedup();
+    let mut next = vec![0; series.len()];
+    let mut values = vec![None; series.len()];
+    times
+        .into_iter()
+        .map(|time| {
+            for ((s, i), value) in series.iter().zip(&mut next).zip(&mut values) {
+                while let Some(&(t, v)) = s.points.get(*i) {
+                    if t > time {
+                        break;
+                    }
+                    *value = Some(v);
+                    *i += 1;
+                }
+            }
+            (time, values.clone())
+        })
+        .collect()
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn array<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let mut array = [0; N];
+    array.copy_from_slice(bytes);
+    array
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(invalid("truncated record"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(array(self.take(2)?)))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = u32::from_le_bytes(array(self.take(4)?)) as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| invalid("invalid string"))
+    }
+}
+
+/// Split a CSV line as written by the `CsvLogger`, with quoted fields.
+fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn parse_value(text: &str) -> Option<f64> {
+    match text {
+        "true" => Some(1.0),
+        "false" => Some(0.0),
+        "null" => Some(f64::NAN),
+        _ => text.parse().ok(),
+    }
+}
+
+/// Parse a flat JSON object as written by the `McapLogger`; values that are
+/// no numbers or booleans are `None`.
+fn parse_json_object(json: &str) -> io::Result<Vec<(String, Option<f64>)>> {
+    let err = || invalid("invalid JSON message");
+    let mut chars = json.trim().chars().peekable();
+    let mut entries = vec![];
+    if chars.next() != Some('{') {
+        return Err(err());
+    }
+    if chars.peek() == Some(&'}') {
+        return Ok(entries);
+    }
+    loop {
+        if chars.next() != Some('"') {
+            return Err(err());
+        }
+        let key = parse_json_string(&mut chars).ok_or_else(err)?;
+        if chars.next() != Some(':') {
+            return Err(err());
+        }
+        let value = if chars.peek() == Some(&'"') {
+            chars.next();
+            parse_json_string(&mut chars).ok_or_else(err)?;
+            None
+        } else {
+            let mut text = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == ',' || c == '}' {
+                    break;
+                }
+                text.push(c);
+                chars.next();
+            }
+            Some(parse_value(text.trim()).ok_or_else(err)?)
+        };
+        entries.push((key, value));
+        match chars.next() {
+            Some(',') => continue,
+            Some('}') => return Ok(entries),
+            _ => return Err(err()),
+        }
+    }
+}
+
+/// Parse the rest of a JSON string after the opening quote.
+fn parse_json_string(chars: &mut impl Iterator<Item = char>) -> Option<String> {
+    let mut s = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(s),
+            '\\' => s.push(match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'u' => {
+                    let hex: String = chars.take(4).collect();
+                    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                }
+                c => c,
+            }),
+            c => s.push(c),
+        }
+    }
+}
+
+#[test]
+fn test_replay() {
+    use super::{CsvLogger, McapChannel, McapLogger};
+    use crate::{field::Field, types::*};
+
+    let domain = DomainIdx::from(0);
+    let speed = Field::<i16>::new(domain, Offset { byte: 0, bit: 0 });
+    let ready = Field::<bool>::new(domain, Offset { byte: 2, bit: 3 });
+    let dir = std::env::temp_dir();
+    let csv = dir.join(format!("ethercat-replay-{}.csv", std::process::id()));
+    let mcap = dir.join(format!("ethercat-replay-{}.mcap", std::process::id()));
+
+    let mut logger = CsvLogger::builder(domain)
+        .field("speed", speed)
+        .field("ready, drive 1", ready)
+        .image(3)
+        .create(&csv)
+        .unwrap();
+    logger.record(1, 1000, &[0xFE, 0xFF, 0x08]);
+    logger.record(2, 3000, &[0x02, 0x00, 0x00]);
+    logger.finish().unwrap();
+    let mut logger = McapLogger::builder(domain)
+        .channel(McapChannel::new("/drive").field("speed", speed))
+        .create(&mcap)
+        .unwrap();
+    logger.record(1, 2000, &[0x05, 0x00, 0x00]);
+    logger.record_domain_state(
+        1,
+        2000,
+        &DomainState {
+            working_counter: 3,
+            wc_state: WcState::Complete,
+            redundancy_active: false,
+        },
+    );
+    logger.finish().unwrap();
+    let from_csv = read_log(&csv).unwrap();
+    let from_mcap = read_log(&mcap).unwrap();
+    std::fs::remove_file(&csv).unwrap();
+    std::fs::remove_file(&mcap).unwrap();
+
+    assert_eq!(from_csv.len(), 2);
+    assert_eq!(from_csv[0].points, [(1000, -2.0), (3000, 2.0)]);
+    assert_eq!(from_csv[1].name, "ready, drive 1");
+    assert_eq!(from_csv[1].points, [(1000, 1.0), (3000, 0.0)]);
+    let names: Vec<_> = from_mcap.iter().map(|s| &s.name[..]).collect();
+    assert_eq!(
+        names,
+        [
+            "/drive/speed",
+            "/domain/0/state/working_counter",
+            "/domain/0/state/redundancy_active"
+        ]
+    );
+    assert_eq!(from_mcap[0].points, [(2000, 5.0)]);
+
+    let rows = align(&[&from_csv[0], &from_mcap[0]]);
+    assert_eq!(
+        rows,
+        [
+            (1000, vec![Some(-2.0), None]),
+            (2000, vec![Some(-2.0), Some(5.0)]),
+            (3000, vec![Some(2.0), Some(5.0)]),
+        ]
+    );
+}
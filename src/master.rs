@@ -3,56 +3,265 @@
 
 #![allow(clippy::field_reassign_with_default)]
 
-use crate::{convert, ec, types::*};
+use crate::{
+    convert,
+    diagnostics::{dc, selftest, DiagnosticSnapshot, SelfTestReport},
+    ec,
+    field::*,
+    runtime::Cycles,
+    topology::Topology,
+    types::*,
+    validation::{self, RegisteredEntry},
+};
 use num_traits::cast::FromPrimitive;
 use std::{
     collections::HashMap,
     convert::TryFrom,
     ffi::CStr,
+    fmt,
     fs::{File, OpenOptions},
     io,
+    marker::PhantomData,
     os::{raw::c_ulong, unix::io::AsRawFd},
+    sync::Mutex,
 };
 
+const _: () = assert!(ec::EC_MAX_PORTS as usize == MAX_PORTS);
+
+/// Call an ioctl of the master, optionally with the slave, domain or
+/// configuration it is about as context of an error.
 macro_rules! ioctl {
-    ($m:expr, $f:expr) => { ioctl!($m, $f,) };
-    ($m:expr, $f:expr, $($arg:tt)*) => {{
-        let res = unsafe { $f($m.file.as_raw_fd(), $($arg)*) };
-        if res < 0 { Err(Error::Io(io::Error::last_os_error())) } else { Ok(res) }
-    }}
+    ($ctx:expr; $m:expr, ec::ioctl::$f:ident $(, $arg:expr)* $(,)?) => {{
+        let res = unsafe { ec::ioctl::$f($m.file.as_raw_fd() $(, $arg)*) };
+        if res < 0 {
+            Err(Error::Ioctl {
+                op: stringify!($f),
+                context: $ctx,
+                source: io::Error::last_os_error(),
+            })
+        } else {
+            Ok(res)
+        }
+    }};
+    ($m:expr, ec::ioctl::$f:ident $(, $arg:expr)* $(,)?) => {
+        ioctl!(ErrorContext::Master; $m, ec::ioctl::$f $(, $arg)*)
+    };
 }
 
+/// The phase of a [`Master`] before activation, where slaves and domains
+/// are configured.
+#[derive(Debug)]
+pub enum Config {}
+
+/// The phase of an activated [`Master`], where the process data is
+/// exchanged.
+#[derive(Debug)]
+pub enum Active {}
+
 /// An EtherCAT master.
-pub struct Master {
+///
+/// Its phase is part of the type: [`Master::open`] returns a master in the
+/// [`Config`] phase, where slaves are configured and PDO entries registered
+/// in domains. [`activate`](Master::activate) turns it into a master in the
+/// [`Active`] phase, which gives access to the process data:
+///
+/// ```ignore
+/// let mut master = Master::open(0, MasterAccess::ReadWrite)?;
+/// master.reserve()?;
+/// let domain = master.create_domain()?;
+/// let el2004 = SlaveId::new(2, 0x07d4_3052);
+/// let mut config = master.configure_slave(SlaveAddr::ByPos(0), el2004)?;
+/// let outputs = config.register_pdo_entry(PdoEntryIdx::new(0x7000, 1), domain)?;
+/// let mut master = master.activate()?;
+/// master.domain_data(domain)?[outputs.byte] = 0x0F;
+/// ```
+///
+/// Queries, mailbox transfers and state requests work in both phases. The
+/// process data is not available before activation:
+///
+/// ```compile_fail
+/// # fn cycle(mut master: ethercat::Master) -> ethercat::Result<()> {
+/// master.domain_data(ethercat::DomainIdx::from(0))?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Master<S = Config> {
+    idx: MasterIdx,
     file: File,
-    map: Option<memmap::MmapMut>,
-    domains: HashMap<DomainIdx, DomainDataPlacement>,
+    image: Option<ProcessImage>,
+    /// Placement of the domains in the mapped process data, by index.
+    domains: Vec<DomainDataPlacement>,
+    sdo_stats: Mutex<HashMap<u16, SdoCounter>>,
+    /// Entries registered in the domains, to check them on activation.
+    registered: Mutex<Vec<RegisteredEntry>>,
+    /// Sync managers assigned through `config_sm_pdos`, to check them on
+    /// activation.
+    assigned: Mutex<Vec<(SlaveConfigIdx, SmIdx)>>,
+    phase: PhantomData<S>,
 }
 
-pub struct Domain<'m> {
-    master: &'m Master,
+/// The process data of all domains, mapped on activation.
+enum ProcessImage {
+    Mapped(memmap::MmapMut),
+    /// No process data, e.g. without any slave configured. A mapping cannot
+    /// be empty.
+    Empty,
+}
+
+impl ProcessImage {
+    fn map(file: &File, size: usize) -> Result<Self> {
+        if size == 0 {
+            return Ok(ProcessImage::Empty);
+        }
+        let mut map = unsafe { memmap::MmapOptions::new().len(size).map_mut(file)? };
+        map[0] = 0;
+        Ok(ProcessImage::Mapped(map))
+    }
+
+    fn data_mut(&mut self) -> &mut [u8] {
+        match self {
+            ProcessImage::Mapped(map) => map,
+            ProcessImage::Empty => &mut [],
+        }
+    }
+
+    /// The data of a domain, as placed by the master.
+    fn domain_mut(&mut self, placement: &DomainDataPlacement) -> Result<&mut [u8]> {
+        let end = placement
+            .offset
+            .checked_add(placement.size)
+            .ok_or(Error::NoDomain)?;
+        self.data_mut()
+            .get_mut(placement.offset..end)
+            .ok_or(Error::NoDomain)
+    }
+}
+
+/// A failed change of the phase, with the master still in phase `S`.
+pub struct PhaseError<S> {
+    pub error: Error,
+    pub master: Box<Master<S>>,
+}
+
+/// A failed [`activate`](Master::activate).
+///
+/// If the kernel had activated the master before the failure, it is
+/// deactivated again, which drops the configuration.
+pub type ActivateError = PhaseError<Config>;
+
+/// A failed [`deactivate`](Master::deactivate), with the master still
+/// active.
+pub type DeactivateError = PhaseError<Active>;
+
+impl<S> fmt::Debug for PhaseError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PhaseError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> fmt::Display for PhaseError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<S> std::error::Error for PhaseError<S> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        std::error::Error::source(&self.error)
+    }
+}
+
+impl<S> From<PhaseError<S>> for Error {
+    fn from(e: PhaseError<S>) -> Self {
+        e.error
+    }
+}
+
+impl<S> From<PhaseError<S>> for io::Error {
+    fn from(e: PhaseError<S>) -> Self {
+        e.error.into()
+    }
+}
+
+#[derive(Default)]
+struct SdoCounter {
+    stats: SdoStats,
+    // object whose last transfer failed
+    failed: Option<(u16, u8)>,
+}
+
+impl SdoCounter {
+    fn count(&mut self, object: (u16, u8), res: &Result<()>, abort_code: u32) {
+        let stats = &mut self.stats;
+        stats.attempts += 1;
+        if self.failed == Some(object) {
+            stats.retries += 1;
+        }
+        self.failed = match res {
+            Ok(()) => None,
+            Err(_) if abort_code != 0 => {
+                stats.aborts += 1;
+                *stats.abort_codes.entry(abort_code).or_default() += 1;
+                Some(object)
+            }
+            Err(e) if e.io_error().map(io::Error::kind) == Some(io::ErrorKind::TimedOut) => {
+                stats.timeouts += 1;
+                Some(object)
+            }
+            Err(_) => {
+                stats.errors += 1;
+                Some(object)
+            }
+        };
+    }
+}
+
+pub struct Domain<'m, S = Config> {
+    master: &'m Master<S>,
     idx: DomainIdx,
 }
 
+/// A read-only handle to a master for lower-priority threads.
+///
+/// It uses its own file descriptor and exposes only queries, so monitoring
+/// threads never have to share (and lock) the [`Master`] owned by the cyclic
+/// thread. It can be shared freely, e.g. in an `Arc`.
+pub struct MasterMonitor {
+    master: Master,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MasterAccess {
     ReadOnly,
     ReadWrite,
 }
 
-impl Master {
+impl Master<Config> {
     pub fn open(idx: MasterIdx, access: MasterAccess) -> Result<Self> {
         let devpath = format!("/dev/EtherCAT{}", idx);
         log::debug!("Open EtherCAT Master {}", devpath);
+        trace_span!(
+            INFO,
+            "open",
+            master = idx,
+            read_only = access == MasterAccess::ReadOnly
+        );
         let file = OpenOptions::new()
             .read(true)
             .write(access == MasterAccess::ReadWrite)
             .open(&devpath)?;
         let mut module_info = ec::ec_ioctl_module_t::default();
         let master = Master {
+            idx,
             file,
-            map: None,
-            domains: HashMap::new(),
+            image: None,
+            domains: Vec::new(),
+            sdo_stats: Mutex::new(HashMap::new()),
+            registered: Mutex::new(Vec::new()),
+            assigned: Mutex::new(Vec::new()),
+            phase: PhantomData,
         };
         ioctl!(master, ec::ioctl::MODULE, &mut module_info)?;
         if module_info.ioctl_version_magic != ec::EC_IOCTL_VERSION_MAGIC {
@@ -81,70 +290,299 @@ impl Master {
         Ok((ioctl!(self, ec::ioctl::CREATE_DOMAIN)? as usize).into())
     }
 
-    pub const fn domain(&self, idx: DomainIdx) -> Domain {
-        Domain::new(idx, self)
+    pub fn configure_slave(
+        &mut self,
+        addr: SlaveAddr,
+        expected: SlaveId,
+    ) -> Result<SlaveConfig<'_>> {
+        log::debug!("Configure slave {:?}", addr);
+        trace_event!(
+            DEBUG,
+            alias = addr.as_pair().0,
+            position = addr.as_pair().1,
+            vendor_id = expected.vendor_id,
+            product_code = expected.product_code,
+            "configuring slave"
+        );
+        let mut data = ec::ec_ioctl_config_t::default();
+        let (alias, pos) = addr.as_pair();
+        data.alias = alias;
+        data.position = pos;
+        data.vendor_id = expected.vendor_id;
+        data.product_code = expected.product_code;
+        ioctl!(self, ec::ioctl::CREATE_SLAVE_CONFIG, &mut data)?;
+        Ok(SlaveConfig {
+            master: self,
+            idx: data.config_index,
+        })
     }
 
-    pub fn domain_data(&mut self, idx: DomainIdx) -> Result<&mut [u8]> {
-        let p = self
-            .domain_data_placement(idx)
-            .map_err(|_| Error::NoDomain)?;
-        let data = self.map.as_mut().ok_or_else(|| Error::NotActivated)?;
-        Ok(&mut data[p.offset..p.offset + p.size])
-    }
-
-    fn domain_data_placement(&mut self, idx: DomainIdx) -> Result<DomainDataPlacement> {
-        Ok(match self.domains.get(&idx) {
-            None => {
-                let d_idx =
-                    c_ulong::try_from(idx).map_err(|_| Error::DomainIdx(usize::from(idx)))?;
-                let offset = ioctl!(self, ec::ioctl::DOMAIN_OFFSET, d_idx)? as usize;
-                let size = ioctl!(self, ec::ioctl::DOMAIN_SIZE, d_idx)? as usize;
-                let meta_data = DomainDataPlacement { offset, size };
-                self.domains.insert(idx, meta_data);
-                meta_data
+    /// Check the configuration before activation: every configuration must
+    /// match a slave on the bus, every sync manager assigned through
+    /// [`SlaveConfig::config_sm_pdos`] must have a PDO, and every registered
+    /// entry must fit into its domain.
+    ///
+    /// Sync managers of the default configuration are not checked, as the
+    /// mailbox ones and the unused ones of a terminal have no PDO.
+    ///
+    /// Returns [`Error::InvalidConfig`] with all problems found.
+    pub fn validate(&self) -> Result<()> {
+        let info = self.get_info()?;
+        let configs = (0..info.config_count)
+            .map(|idx| Ok((idx, self.get_config_info(idx)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let mut mapped = vec![];
+        for (idx, config) in &configs {
+            for sync in &config.syncs {
+                self.config_pdo_entries(*idx, sync, &mut mapped)?;
             }
-            Some(d) => *d,
-        })
+        }
+        let domain_sizes = (0..info.domain_count as usize)
+            .map(|idx| self.domain(DomainIdx::from(idx)).size())
+            .collect::<Result<Vec<_>>>()?;
+        let assigned = self.assigned.lock().unwrap_or_else(|e| e.into_inner());
+        let registered = self.registered.lock().unwrap_or_else(|e| e.into_inner());
+        let problems = validation::check(&configs, &assigned, &mapped, &registered, &domain_sizes);
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            for problem in &problems {
+                log::error!("Invalid configuration: {}", problem);
+            }
+            Err(Error::InvalidConfig(problems))
+        }
+    }
+
+    /// Collect the entries mapped to the PDOs of a configured sync manager.
+    fn config_pdo_entries(
+        &self,
+        idx: SlaveConfigIdx,
+        sync: &ConfigSyncInfo,
+        entries: &mut Vec<(SlaveConfigIdx, PdoEntryIdx, u8)>,
+    ) -> Result<()> {
+        for pdo_pos in 0..sync.pdo_count as u16 {
+            let mut pdo = ec::ec_ioctl_config_pdo_t::default();
+            pdo.config_index = idx;
+            pdo.sync_index = u8::from(sync.idx);
+            pdo.pdo_pos = pdo_pos;
+            ioctl!(ErrorContext::Config(idx); self, ec::ioctl::CONFIG_PDO, &mut pdo)?;
+            for entry_pos in 0..pdo.entry_count {
+                let entry = self.config_pdo_entry(idx, sync.idx, pdo_pos, entry_pos)?;
+                entries.push((
+                    idx,
+                    PdoEntryIdx::new(entry.index, entry.subindex),
+                    entry.bit_length,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn config_pdo_entry(
+        &self,
+        idx: SlaveConfigIdx,
+        sm: SmIdx,
+        pdo_pos: u16,
+        entry_pos: u8,
+    ) -> Result<ec::ec_ioctl_config_pdo_entry_t> {
+        let mut entry = ec::ec_ioctl_config_pdo_entry_t::default();
+        entry.config_index = idx;
+        entry.sync_index = u8::from(sm);
+        entry.pdo_pos = pdo_pos;
+        entry.entry_pos = entry_pos;
+        ioctl!(ErrorContext::Config(idx); self, ec::ioctl::CONFIG_PDO_ENTRY, &mut entry)?;
+        Ok(entry)
+    }
+
+    /// Check the configuration with [`validate`](Self::validate) and start
+    /// the cyclic operation.
+    ///
+    /// On failure, the master is handed back in the [`ActivateError`].
+    pub fn activate(self) -> std::result::Result<Master<Active>, ActivateError> {
+        match self.validate() {
+            Ok(()) => self.activate_unchecked(),
+            Err(error) => Err(PhaseError {
+                error,
+                master: Box::new(self),
+            }),
+        }
+    }
+
+    /// Start the cyclic operation without checking the configuration, e.g.
+    /// for a bus where some configured slaves are connected later.
+    pub fn activate_unchecked(mut self) -> std::result::Result<Master<Active>, ActivateError> {
+        match self.start() {
+            Ok(()) => Ok(self.into_phase()),
+            Err(error) => Err(PhaseError {
+                error,
+                master: Box::new(self),
+            }),
+        }
     }
 
-    pub fn activate(&mut self) -> Result<()> {
+    fn start(&mut self) -> Result<()> {
         log::debug!("Activate EtherCAT Master");
+        trace_span!(INFO, "activate", master = self.idx);
         let mut data = ec::ec_ioctl_master_activate_t::default();
         ioctl!(self, ec::ioctl::ACTIVATE, &mut data)?;
 
-        self.map = unsafe {
-            memmap::MmapOptions::new()
-                .len(data.process_data_size)
-                .map_mut(&self.file)
-                .map(Some)?
-        };
-        self.map.as_mut().ok_or_else(|| Error::NotActivated)?[0] = 0;
-        Ok(())
+        let mapped = self.map_process_data(data.process_data_size);
+        if mapped.is_err() {
+            // do not leave the kernel master active behind a Master<Config>
+            if let Err(e) = self.stop() {
+                log::error!("Deactivate after a failed activation: {}", e);
+            }
+        }
+        mapped
     }
 
-    pub fn deactivate(&mut self) -> Result<()> {
-        log::debug!("Deactivate EtherCAT Master");
-        ioctl!(self, ec::ioctl::DEACTIVATE)?;
-        self.domains.clear();
-        self.map = None;
+    fn map_process_data(&mut self, size: usize) -> Result<()> {
+        self.image = Some(ProcessImage::map(&self.file, size)?);
+        // locate the domains now, the cycle must not allocate
+        let domain_count = self.get_info()?.domain_count as usize;
+        self.domains = (0..domain_count)
+            .map(|idx| self.domain_data_placement(DomainIdx::from(idx)))
+            .collect::<Result<_>>()?;
         Ok(())
     }
+}
 
-    pub fn set_send_interval(&mut self, interval_us: usize) -> Result<()> {
-        ioctl!(self, ec::ioctl::SET_SEND_INTERVAL, &interval_us).map(|_| ())
+impl Master<Active> {
+    /// The process data of a domain, in the image mapped on activation.
+    ///
+    /// The domains are located once by [`activate`](Master::activate), so
+    /// this is only a slice of the mapping, without any ioctl.
+    pub fn domain_data(&mut self, idx: DomainIdx) -> Result<&mut [u8]> {
+        self.process_data(idx)
+    }
+
+    /// Typed access to the process data of a domain, see [`DomainView`].
+    pub fn domain_view(&mut self, idx: DomainIdx) -> Result<DomainView<'_>> {
+        Ok(DomainView::new(idx, self.domain_data(idx)?))
+    }
+
+    /// Iterate over the cycles of the process data exchange with `period`.
+    ///
+    /// Each [`Cycle`](crate::runtime::Cycle) has received and processed all
+    /// domains, and queues and sends them when dropped:
+    ///
+    /// ```ignore
+    /// for cycle in master.cycles(Duration::from_millis(1))? {
+    ///     let mut cycle = cycle?;
+    ///     let pos = cycle.get(&position)?;
+    ///     cycle.set(&target, pos + 10)?;
+    /// }
+    /// ```
+    pub fn cycles(&mut self, period: std::time::Duration) -> Result<Cycles<'_>> {
+        Cycles::new(self, period)
     }
 
     pub fn send(&mut self) -> Result<usize> {
+        self.send_frames()
+    }
+
+    pub fn receive(&mut self) -> Result<()> {
+        self.receive_frames()
+    }
+
+    /// Stop the cyclic operation. The master drops the configurations and
+    /// domains, so the bus can be configured anew.
+    ///
+    /// On failure, the master is handed back in the [`DeactivateError`].
+    pub fn deactivate(mut self) -> std::result::Result<Master<Config>, DeactivateError> {
+        match self.stop() {
+            Ok(()) => Ok(self.into_phase()),
+            Err(error) => Err(PhaseError {
+                error,
+                master: Box::new(self),
+            }),
+        }
+    }
+}
+
+impl<S> Master<S> {
+    /// The same master in another phase.
+    fn into_phase<T>(self) -> Master<T> {
+        Master {
+            idx: self.idx,
+            file: self.file,
+            image: self.image,
+            domains: self.domains,
+            sdo_stats: self.sdo_stats,
+            registered: self.registered,
+            assigned: self.assigned,
+            phase: PhantomData,
+        }
+    }
+
+    /// The process data is mapped, which it is in the [`Active`] phase unless
+    /// deactivated through [`Backend`](crate::backend::Backend).
+    pub(crate) const fn is_active(&self) -> bool {
+        self.image.is_some()
+    }
+
+    /// The process data of a domain, if the master is activated.
+    pub(crate) fn process_data(&mut self, idx: DomainIdx) -> Result<&mut [u8]> {
+        let image = self.image.as_mut().ok_or(Error::NotActivated)?;
+        let placement = self.domains.get(usize::from(idx)).ok_or(Error::NoDomain)?;
+        image.domain_mut(placement)
+    }
+
+    pub(crate) fn send_frames(&mut self) -> Result<usize> {
         let mut sent = 0;
         ioctl!(self, ec::ioctl::SEND, &mut sent as *mut _ as c_ulong)?;
         Ok(sent)
     }
 
-    pub fn receive(&mut self) -> Result<()> {
+    pub(crate) fn receive_frames(&mut self) -> Result<()> {
         ioctl!(self, ec::ioctl::RECEIVE).map(|_| ())
     }
 
+    pub(crate) fn stop(&mut self) -> Result<()> {
+        log::debug!("Deactivate EtherCAT Master");
+        trace_span!(INFO, "deactivate", master = self.idx);
+        ioctl!(self, ec::ioctl::DEACTIVATE)?;
+        // the master drops the configurations and domains
+        self.registered
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        self.assigned
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        self.domains.clear();
+        self.image = None;
+        Ok(())
+    }
+
+    pub const fn index(&self) -> MasterIdx {
+        self.idx
+    }
+
+    /// Open a read-only [`MasterMonitor`] for the same master.
+    pub fn monitor(&self) -> Result<MasterMonitor> {
+        MasterMonitor::open(self.idx)
+    }
+
+    pub const fn domain(&self, idx: DomainIdx) -> Domain<'_, S> {
+        Domain::new(idx, self)
+    }
+
+    fn domain_data_placement(&self, idx: DomainIdx) -> Result<DomainDataPlacement> {
+        let d_idx = c_ulong::try_from(idx).map_err(|_| Error::DomainIdx(usize::from(idx)))?;
+        let offset = ioctl!(
+            ErrorContext::Domain(idx);
+            self, ec::ioctl::DOMAIN_OFFSET, d_idx
+        )? as usize;
+        let size = ioctl!(ErrorContext::Domain(idx); self, ec::ioctl::DOMAIN_SIZE, d_idx)? as usize;
+        Ok(DomainDataPlacement { offset, size })
+    }
+
+    pub fn set_send_interval(&mut self, interval_us: usize) -> Result<()> {
+        ioctl!(self, ec::ioctl::SET_SEND_INTERVAL, &interval_us).map(|_| ())
+    }
+
     pub fn reset(&mut self) -> Result<()> {
         ioctl!(self, ec::ioctl::RESET).map(|_| ())
     }
@@ -159,6 +597,79 @@ impl Master {
         })
     }
 
+    /// Summarize link, slave, domain, DC and emergency state.
+    ///
+    /// This reads a register of every DC slave, so it is meant for a
+    /// supervisory loop rather than the cycle. IgH offers no way to count
+    /// queued emergencies without popping them, so only overruns are
+    /// reported. Power faults are only known to the drivers of the power
+    /// terminals, which add them to the summary, e.g. with
+    /// [`PowerSupply::report`](crate::devices::PowerSupply::report).
+    pub fn health(&self) -> Result<BusHealth> {
+        let mut health = self.bus_health(|domain| domain.state())?;
+        for i in 0..health.slaves_configured {
+            health.emergency_overruns += self.slave_config(i).emerg_overruns()? as u32;
+        }
+        Ok(health)
+    }
+
+    /// The health without the emergency overruns, with the state of each
+    /// domain from `domain_state`.
+    fn bus_health<F>(&self, domain_state: F) -> Result<BusHealth>
+    where
+        F: Fn(&Domain<S>) -> Result<DomainState>,
+    {
+        let state = self.state()?;
+        let info = self.get_info()?;
+        let worst_al_state = [AlState::Init, AlState::PreOp, AlState::SafeOp, AlState::Op]
+            .iter()
+            .copied()
+            .find(|s| state.al_states & *s as u8 != 0);
+        let domains = (0..info.domain_count as usize)
+            .map(|i| {
+                let idx = DomainIdx::from(i);
+                Ok((idx, domain_state(&self.domain(idx))?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut dc_deviation = None;
+        for i in 0..info.slave_count as u16 {
+            let pos = SlavePos::from(i);
+            if !self.get_slave_info(pos)?.has_dc_system_time {
+                continue;
+            }
+            let mut reg = [0; 4];
+            self.read_register(pos, dc::SYSTEM_TIME_DIFFERENCE, &mut reg)?;
+            let dev = dc::decode_difference(u32::from_le_bytes(reg)).unsigned_abs();
+            dc_deviation = Some(dc_deviation.map_or(dev, |max: u32| max.max(dev)));
+        }
+        Ok(BusHealth {
+            link_up: state.link_up,
+            slaves_responding: state.slaves_responding,
+            slaves_configured: info.config_count,
+            worst_al_state,
+            domains,
+            dc_deviation,
+            emergency_overruns: 0,
+            power_faults: vec![],
+        })
+    }
+
+    /// Check the bus before going to Op: the identity of the configured
+    /// slaves, their DC capability, their CoE mailbox and a transition from
+    /// PreOp to SafeOp and back.
+    ///
+    /// All slaves must be in PreOp, so this is meant to be called before
+    /// [`activate`](Master::activate). `timeout` applies to each state
+    /// transition.
+    pub fn selftest(&mut self, timeout: std::time::Duration) -> Result<SelfTestReport> {
+        selftest::run(self, timeout)
+    }
+
+    /// Collect the state of the master and the bus for a support ticket.
+    pub fn diagnostic_snapshot(&self) -> Result<DiagnosticSnapshot> {
+        DiagnosticSnapshot::read(self, self.health()?)
+    }
+
     pub fn link_state(&self, dev_idx: u32) -> Result<MasterState> {
         let mut state = ec::ec_master_link_state_t::default();
         let mut data = ec::ec_ioctl_link_state_t {
@@ -178,16 +689,20 @@ impl Master {
         ioctl!(self, ec::ioctl::MASTER, &mut data)?;
         let ec::ec_ioctl_master_t {
             slave_count,
+            config_count,
+            domain_count,
             devices,
             scan_busy,
             app_time,
             ..
         } = data;
-        let first_device = devices.get(0).ok_or_else(|| Error::NoDevices)?;
+        let first_device = devices.first().ok_or(Error::NoDevices)?;
         let link_up = first_device.link_state != 0;
         let scan_busy = scan_busy != 0;
         Ok(MasterInfo {
             slave_count,
+            config_count,
+            domain_count,
             link_up,
             scan_busy,
             app_time,
@@ -197,15 +712,15 @@ impl Master {
     pub fn get_slave_info(&self, position: SlavePos) -> Result<SlaveInfo> {
         let mut data = ec::ec_ioctl_slave_t::default();
         data.position = u16::from(position);
-        ioctl!(self, ec::ioctl::SLAVE, &mut data)?;
-        let mut ports = [SlavePortInfo::default(); ec::EC_MAX_PORTS as usize];
-        for (i, port) in ports.iter_mut().enumerate().take(ec::EC_MAX_PORTS as usize) {
+        ioctl!(ErrorContext::Slave(position); self, ec::ioctl::SLAVE, &mut data)?;
+        let mut ports = [SlavePortInfo::default(); MAX_PORTS];
+        for (i, port) in ports.iter_mut().enumerate().take(MAX_PORTS) {
             port.desc = match data.ports[i].desc {
                 ec::EC_PORT_NOT_IMPLEMENTED => SlavePortType::NotImplemented,
                 ec::EC_PORT_NOT_CONFIGURED => SlavePortType::NotConfigured,
                 ec::EC_PORT_EBUS => SlavePortType::EBus,
                 ec::EC_PORT_MII => SlavePortType::MII,
-                x => panic!("invalid port type {}", x),
+                x => return Err(Error::InvalidPortType(x)),
             };
             port.link = SlavePortLink {
                 link_up: data.ports[i].link.link_up != 0,
@@ -222,7 +737,7 @@ impl Master {
                     .to_string_lossy()
                     .into_owned()
             },
-            ring_pos: data.position,
+            ring_pos: SlavePos::from(data.position),
             id: SlaveId {
                 vendor_id: data.vendor_id,
                 product_code: data.product_code,
@@ -239,13 +754,16 @@ impl Master {
             sync_count: data.sync_count,
             sdo_count: data.sdo_count,
             ports,
+            has_dc_system_time: data.dc_supported != 0 && data.has_dc_system_time != 0,
+            sii_nwords: data.sii_nwords,
+            mailbox_protocols: data.mailbox_protocols,
         })
     }
 
     pub fn get_config_info(&self, idx: SlaveConfigIdx) -> Result<ConfigInfo> {
         let mut data = ec::ec_ioctl_config_t::default();
         data.config_index = idx;
-        ioctl!(self, ec::ioctl::CONFIG, &mut data)?;
+        ioctl!(ErrorContext::Config(idx); self, ec::ioctl::CONFIG, &mut data)?;
         let id = SlaveId {
             vendor_id: data.vendor_id,
             product_code: data.product_code,
@@ -255,6 +773,21 @@ impl Master {
         } else {
             Some(SlavePos::from(data.slave_position as u16))
         };
+        let syncs = data
+            .syncs
+            .iter()
+            .enumerate()
+            .filter(|(_, sync)| sync.dir != ec::EC_DIR_INVALID)
+            .map(|(i, sync)| ConfigSyncInfo {
+                idx: SmIdx::from(i as u8),
+                direction: if sync.dir == ec::EC_DIR_OUTPUT {
+                    SyncDirection::Output
+                } else {
+                    SyncDirection::Input
+                },
+                pdo_count: sync.pdo_count,
+            })
+            .collect();
         Ok(ConfigInfo {
             alias: data.alias,
             position: data.position,
@@ -262,29 +795,21 @@ impl Master {
             slave_position,
             sdo_count: data.sdo_count,
             idn_count: data.idn_count,
+            dc_assign_activate: data.dc_assign_activate,
+            syncs,
         })
     }
 
-    pub fn configure_slave(&mut self, addr: SlaveAddr, expected: SlaveId) -> Result<SlaveConfig> {
-        log::debug!("Configure slave {:?}", addr);
-        let mut data = ec::ec_ioctl_config_t::default();
-        let (alias, pos) = addr.as_pair();
-        data.alias = alias;
-        data.position = pos;
-        data.vendor_id = expected.vendor_id;
-        data.product_code = expected.product_code;
-        ioctl!(self, ec::ioctl::CREATE_SLAVE_CONFIG, &mut data)?;
-        Ok(SlaveConfig {
-            master: self,
-            idx: data.config_index,
-        })
+    /// Access a slave configuration created before.
+    pub fn slave_config(&self, idx: SlaveConfigIdx) -> SlaveConfig<'_, S> {
+        SlaveConfig { master: self, idx }
     }
 
     pub fn get_sdo(&mut self, slave_pos: SlavePos, sdo_pos: SdoPos) -> Result<SdoInfo> {
         let mut sdo = ec::ec_ioctl_slave_sdo_t::default();
         sdo.slave_position = u16::from(slave_pos);
         sdo.sdo_position = u16::from(sdo_pos);
-        ioctl!(self, ec::ioctl::SLAVE_SDO, &mut sdo)?;
+        ioctl!(ErrorContext::Slave(slave_pos); self, ec::ioctl::SLAVE_SDO, &mut sdo)?;
         #[cfg(feature = "sncn")]
         {
             Ok(SdoInfo {
@@ -320,7 +845,7 @@ impl Master {
         };
         entry.sdo_spec = spec;
         entry.sdo_entry_subindex = u8::from(sub);
-        ioctl!(self, ec::ioctl::SLAVE_SDO_ENTRY, &mut entry)?;
+        ioctl!(ErrorContext::Slave(slave_pos); self, ec::ioctl::SLAVE_SDO_ENTRY, &mut entry)?;
         Ok(SdoEntryInfo {
             data_type: DataType::from_u16(entry.data_type).unwrap_or_else(|| {
                 let fallback = DataType::Raw;
@@ -353,6 +878,14 @@ impl Master {
     where
         T: SdoData + ?Sized,
     {
+        trace_span!(
+            DEBUG,
+            "sdo_download",
+            slave = u16::from(position),
+            index = u16::from(sdo_idx.idx),
+            subindex = u8::from(sdo_idx.sub_idx),
+            size = data.data_size(),
+        );
         #[cfg(feature = "sncn")]
         let data_ptr = data.data_ptr();
 
@@ -368,7 +901,17 @@ impl Master {
             data: data_ptr,
             abort_code: 0,
         };
-        ioctl!(self, ec::ioctl::SLAVE_SDO_DOWNLOAD, &mut data).map(|_| ())
+        let res = ioctl!(
+            ErrorContext::Slave(position);
+            self, ec::ioctl::SLAVE_SDO_DOWNLOAD, &mut data
+        )
+        .map(|_| ())
+        .map_err(|e| sdo_error(e, position, sdo_idx, data.abort_code));
+        if res.is_err() {
+            trace_event!(WARN, abort_code = data.abort_code, "SDO download failed");
+        }
+        self.count_sdo(position, sdo_idx, &res, data.abort_code);
+        res
     }
 
     pub fn sdo_upload<'t>(
@@ -382,6 +925,13 @@ impl Master {
         let sdo_index = u16::from(sdo_idx.idx);
         let sdo_entry_subindex = u8::from(sdo_idx.sub_idx);
         let target_size = target.len();
+        trace_span!(
+            DEBUG,
+            "sdo_upload",
+            slave = slave_position,
+            index = sdo_index,
+            subindex = sdo_entry_subindex,
+        );
         let data_size = 0;
         let abort_code = 0;
 
@@ -408,10 +958,59 @@ impl Master {
             complete_access: if complete_access { 1 } else { 0 },
         };
 
-        ioctl!(self, ec::ioctl::SLAVE_SDO_UPLOAD, &mut data)?;
+        let res = ioctl!(
+            ErrorContext::Slave(position);
+            self, ec::ioctl::SLAVE_SDO_UPLOAD, &mut data
+        )
+        .map(|_| ())
+        .map_err(|e| sdo_error(e, position, sdo_idx, data.abort_code));
+        if res.is_err() {
+            trace_event!(WARN, abort_code = data.abort_code, "SDO upload failed");
+        }
+        self.count_sdo(position, sdo_idx, &res, data.abort_code);
+        res?;
+        trace_event!(TRACE, size = data.data_size, "SDO upload done");
         Ok(&mut target[..data.data_size])
     }
 
+    fn count_sdo(&self, position: SlavePos, sdo_idx: SdoIdx, res: &Result<()>, abort_code: u32) {
+        let object = (u16::from(sdo_idx.idx), u8::from(sdo_idx.sub_idx));
+        let mut counters = self.sdo_stats.lock().unwrap_or_else(|e| e.into_inner());
+        counters
+            .entry(position.into())
+            .or_default()
+            .count(object, res, abort_code);
+    }
+
+    /// SDO transfer statistics of a slave.
+    ///
+    /// Only the transfers made through this handle are counted.
+    pub fn sdo_stats(&self, position: SlavePos) -> SdoStats {
+        let counters = self.sdo_stats.lock().unwrap_or_else(|e| e.into_inner());
+        counters
+            .get(&position.into())
+            .map(|c| c.stats.clone())
+            .unwrap_or_default()
+    }
+
+    /// SDO transfer statistics of all slaves with at least one transfer.
+    pub fn all_sdo_stats(&self) -> Vec<(SlavePos, SdoStats)> {
+        let counters = self.sdo_stats.lock().unwrap_or_else(|e| e.into_inner());
+        let mut all = counters
+            .iter()
+            .map(|(pos, c)| (SlavePos::from(*pos), c.stats.clone()))
+            .collect::<Vec<_>>();
+        all.sort_by_key(|(pos, _)| u16::from(*pos));
+        all
+    }
+
+    pub fn reset_sdo_stats(&self) {
+        self.sdo_stats
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
     pub fn get_pdo(
         &mut self,
         slave_pos: SlavePos,
@@ -422,7 +1021,7 @@ impl Master {
         pdo.slave_position = u16::from(slave_pos);
         pdo.sync_index = u8::from(sync_index) as u32;
         pdo.pdo_pos = u8::from(pdo_position) as u32;
-        ioctl!(self, ec::ioctl::SLAVE_SYNC_PDO, &mut pdo)?;
+        ioctl!(ErrorContext::Slave(slave_pos); self, ec::ioctl::SLAVE_SYNC_PDO, &mut pdo)?;
         Ok(PdoInfo {
             sm: SmIdx::from(pdo.sync_index as u8),
             pos: PdoPos::from(pdo.pdo_pos as u8),
@@ -444,7 +1043,7 @@ impl Master {
         entry.sync_index = u8::from(sync_index) as u32;
         entry.pdo_pos = u8::from(pdo_pos) as u32;
         entry.entry_pos = u8::from(entry_pos) as u32;
-        ioctl!(self, ec::ioctl::SLAVE_SYNC_PDO_ENTRY, &mut entry)?;
+        ioctl!(ErrorContext::Slave(slave_pos); self, ec::ioctl::SLAVE_SYNC_PDO_ENTRY, &mut entry)?;
         Ok(PdoEntryInfo {
             pos: PdoEntryPos::from(entry.pdo_pos as u8),
             entry_idx: PdoEntryIdx {
@@ -460,7 +1059,7 @@ impl Master {
         let mut sync = ec::ec_ioctl_slave_sync_t::default();
         sync.slave_position = u16::from(slave_pos);
         sync.sync_index = u8::from(sm) as u32;
-        ioctl!(self, ec::ioctl::SLAVE_SYNC, &mut sync)?;
+        ioctl!(ErrorContext::Slave(slave_pos); self, ec::ioctl::SLAVE_SYNC, &mut sync)?;
         Ok(SmInfo {
             idx: SmIdx::from(sync.sync_index as u8),
             start_addr: sync.physical_start_address,
@@ -472,10 +1071,16 @@ impl Master {
     }
 
     pub fn request_state(&mut self, slave_pos: SlavePos, state: AlState) -> Result<()> {
+        trace_event!(
+            INFO,
+            slave = u16::from(slave_pos),
+            state = ?state,
+            "requesting AL state"
+        );
         let mut data = ec::ec_ioctl_slave_state_t::default();
         data.slave_position = u16::from(slave_pos);
         data.al_state = state as u8;
-        ioctl!(self, ec::ioctl::SLAVE_STATE, &data)?;
+        ioctl!(ErrorContext::Slave(slave_pos); self, ec::ioctl::SLAVE_STATE, &data)?;
         Ok(())
     }
 
@@ -483,7 +1088,7 @@ impl Master {
     pub fn dict_upload(&mut self, slave_pos: SlavePos) -> Result<()> {
         let mut data = ec::ec_ioctl_slave_dict_upload_t::default();
         data.slave_position = u16::from(slave_pos);
-        ioctl!(self, ec::ioctl::SLAVE_DICT_UPLOAD, &mut data)?;
+        ioctl!(ErrorContext::Slave(slave_pos); self, ec::ioctl::SLAVE_DICT_UPLOAD, &mut data)?;
         Ok(())
     }
 
@@ -520,6 +1125,7 @@ impl Master {
     }
 
     pub fn foe_read(&mut self, idx: SlavePos, name: &str) -> Result<Vec<u8>> {
+        trace_span!(DEBUG, "foe_read", slave = u16::from(idx), name);
         let file_name = convert::string_to_foe_name(name)?;
         // FIXME: this is the same as in the c-implementation. Should read in chunks instead of a
         // fixed size buffer. The ioctl-call in the master pre-allocates a 10000 byte buffer, so we
@@ -534,18 +1140,49 @@ impl Master {
             file_name,
             ..Default::default()
         };
-        ioctl!(self, ec::ioctl::SLAVE_FOE_READ, &mut data)?;
+        ioctl!(ErrorContext::Slave(idx); self, ec::ioctl::SLAVE_FOE_READ, &mut data)?;
 
-        assert!(data.data_size <= FOE_SIZE);
+        // a size beyond the buffer leaves it as is
         buf.truncate(data.data_size);
         Ok(buf)
     }
 
     pub fn foe_write(&mut self, idx: SlavePos, name: &str, data: &[u8]) -> Result<()> {
+        self.foe_write_file(idx, name, 0, data)
+    }
+
+    /// Write a file that the slave protects with a password.
+    #[cfg(feature = "sncn")]
+    pub fn foe_write_with_password(
+        &mut self,
+        idx: SlavePos,
+        name: &str,
+        password: u32,
+        data: &[u8],
+    ) -> Result<()> {
+        self.foe_write_file(idx, name, password, data)
+    }
+
+    fn foe_write_file(
+        &mut self,
+        idx: SlavePos,
+        name: &str,
+        #[allow(unused_variables)] password: u32,
+        data: &[u8],
+    ) -> Result<()> {
+        trace_span!(
+            DEBUG,
+            "foe_write",
+            slave = u16::from(idx),
+            name,
+            size = data.len()
+        );
         let file_name = convert::string_to_foe_name(name)?;
 
         let buffer = data.as_ptr() as *mut _;
         let data = ec::ec_ioctl_slave_foe_t {
+            #[cfg(feature = "sncn")]
+            password,
             slave_position: idx.into(),
             offset: 0,
             buffer_size: data.len(),
@@ -553,20 +1190,247 @@ impl Master {
             file_name,
             ..Default::default()
         };
-        ioctl!(self, ec::ioctl::SLAVE_FOE_WRITE, &data)?;
+        ioctl!(ErrorContext::Slave(idx); self, ec::ioctl::SLAVE_FOE_WRITE, &data)?;
+
+        Ok(())
+    }
+
+    /// Read an IDN of a SoE (Servo drive profile over EtherCAT) slave into
+    /// `target`.
+    pub fn soe_read<'t>(
+        &self,
+        position: SlavePos,
+        drive_no: u8,
+        idn: u16,
+        target: &'t mut [u8],
+    ) -> Result<&'t mut [u8]> {
+        trace_span!(
+            DEBUG,
+            "soe_read",
+            slave = u16::from(position),
+            drive_no,
+            idn
+        );
+        let mut data = ec::ec_ioctl_slave_soe_read_t {
+            slave_position: position.into(),
+            drive_no,
+            idn,
+            mem_size: target.len(),
+            data: target.as_mut_ptr(),
+            ..Default::default()
+        };
+        if let Err(e) = ioctl!(
+            ErrorContext::Slave(position);
+            self, ec::ioctl::SLAVE_SOE_READ, &mut data
+        ) {
+            return Err(match data.error_code {
+                0 => e,
+                code => Error::SoeError(code),
+            });
+        }
+        Ok(&mut target[..data.data_size])
+    }
 
+    /// Write an IDN of a SoE slave.
+    pub fn soe_write(
+        &mut self,
+        position: SlavePos,
+        drive_no: u8,
+        idn: u16,
+        data: &[u8],
+    ) -> Result<()> {
+        trace_span!(
+            DEBUG,
+            "soe_write",
+            slave = u16::from(position),
+            drive_no,
+            idn,
+            size = data.len()
+        );
+        let mut data = ec::ec_ioctl_slave_soe_write_t {
+            slave_position: position.into(),
+            drive_no,
+            idn,
+            data_size: data.len(),
+            data: data.as_ptr() as *mut _,
+            ..Default::default()
+        };
+        if let Err(e) = ioctl!(
+            ErrorContext::Slave(position);
+            self, ec::ioctl::SLAVE_SOE_WRITE, &mut data
+        ) {
+            return Err(match data.error_code {
+                0 => e,
+                code => Error::SoeError(code),
+            });
+        }
         Ok(())
     }
 
+    /// Read `target.len()` bytes of the ESC registers of a slave, starting
+    /// at `address`.
+    pub fn read_register(&self, position: SlavePos, address: u16, target: &mut [u8]) -> Result<()> {
+        let mut data = ec::ec_ioctl_slave_reg_t {
+            slave_position: position.into(),
+            address,
+            size: target.len(),
+            data: target.as_mut_ptr(),
+            ..Default::default()
+        };
+        ioctl!(
+            ErrorContext::Slave(position);
+            self, ec::ioctl::SLAVE_REG_READ, &mut data
+        )
+        .map(|_| ())
+    }
+
+    /// Write `data` to the ESC registers of a slave, starting at `address`.
+    pub fn write_register(&mut self, position: SlavePos, address: u16, data: &[u8]) -> Result<()> {
+        let data = ec::ec_ioctl_slave_reg_t {
+            slave_position: position.into(),
+            address,
+            size: data.len(),
+            data: data.as_ptr() as *mut _,
+            ..Default::default()
+        };
+        ioctl!(ErrorContext::Slave(position); self, ec::ioctl::SLAVE_REG_WRITE, &data).map(|_| ())
+    }
+
+    /// Read `target.len()` words of the SII (EEPROM) image of a slave,
+    /// starting at word `offset`.
+    pub fn read_sii(&self, position: SlavePos, offset: u16, target: &mut [u16]) -> Result<()> {
+        let mut data = ec::ec_ioctl_slave_sii_t {
+            slave_position: position.into(),
+            offset,
+            nwords: target.len() as u32,
+            words: target.as_mut_ptr(),
+        };
+        ioctl!(
+            ErrorContext::Slave(position);
+            self, ec::ioctl::SLAVE_SII_READ, &mut data
+        )
+        .map(|_| ())
+    }
+
+    /// Write `words` to the SII (EEPROM) image of a slave, starting at word
+    /// `offset`.
+    ///
+    /// The master writes the words one by one and rescans the bus when done.
+    pub fn write_sii(&mut self, position: SlavePos, offset: u16, words: &[u16]) -> Result<()> {
+        log::debug!(
+            "Write {} SII words at {} to slave {:?}",
+            words.len(),
+            offset,
+            position
+        );
+        let data = ec::ec_ioctl_slave_sii_t {
+            slave_position: position.into(),
+            offset,
+            nwords: words.len() as u32,
+            words: words.as_ptr() as *mut u16,
+        };
+        ioctl!(ErrorContext::Slave(position); self, ec::ioctl::SLAVE_SII_WRITE, &data).map(|_| ())
+    }
+
     // XXX missing: write_idn, read_idn
 }
 
-pub struct SlaveConfig<'m> {
-    master: &'m Master,
+impl MasterMonitor {
+    pub fn open(idx: MasterIdx) -> Result<Self> {
+        Ok(Self {
+            master: Master::open(idx, MasterAccess::ReadOnly)?,
+        })
+    }
+
+    pub fn state(&self) -> Result<MasterState> {
+        self.master.state()
+    }
+
+    pub fn link_state(&self, dev_idx: u32) -> Result<MasterState> {
+        self.master.link_state(dev_idx)
+    }
+
+    /// Summarize the bus like [`Master::health`].
+    ///
+    /// The state and emergency rings of domains and slave configs are only
+    /// open to the handle that reserved the master. Here the domain states
+    /// are derived from the working counters of the domain info, without
+    /// redundancy, and no emergency overruns are counted.
+    pub fn health(&self) -> Result<BusHealth> {
+        self.master
+            .bus_health(|domain| domain.info().map(|info| unreserved_state(&info)))
+    }
+
+    pub fn get_info(&self) -> Result<MasterInfo> {
+        self.master.get_info()
+    }
+
+    pub fn get_slave_info(&self, position: SlavePos) -> Result<SlaveInfo> {
+        self.master.get_slave_info(position)
+    }
+
+    pub fn get_config_info(&self, idx: SlaveConfigIdx) -> Result<ConfigInfo> {
+        self.master.get_config_info(idx)
+    }
+
+    /// Size and working counter of a domain of the application.
+    pub fn domain_info(&self, idx: DomainIdx) -> Result<DomainInfo> {
+        self.master.domain(idx).info()
+    }
+
+    pub fn sdo_upload<'t>(
+        &self,
+        position: SlavePos,
+        sdo_idx: SdoIdx,
+        complete_access: bool,
+        target: &'t mut [u8],
+    ) -> Result<&'t mut [u8]> {
+        self.master
+            .sdo_upload(position, sdo_idx, complete_access, target)
+    }
+
+    pub fn sdo_stats(&self, position: SlavePos) -> SdoStats {
+        self.master.sdo_stats(position)
+    }
+
+    pub fn read_register(&self, position: SlavePos, address: u16, target: &mut [u8]) -> Result<()> {
+        self.master.read_register(position, address, target)
+    }
+
+    pub fn read_sii(&self, position: SlavePos, offset: u16, target: &mut [u16]) -> Result<()> {
+        self.master.read_sii(position, offset, target)
+    }
+
+    pub fn topology(&self) -> Result<Topology> {
+        Topology::read(&self.master)
+    }
+
+    pub fn diagnostic_snapshot(&self) -> Result<DiagnosticSnapshot> {
+        DiagnosticSnapshot::read(&self.master, self.health()?)
+    }
+}
+
+/// The state of a domain as seen from its info.
+fn unreserved_state(info: &DomainInfo) -> DomainState {
+    DomainState {
+        working_counter: u32::from(info.working_counter),
+        wc_state: if info.working_counter == 0 {
+            WcState::Zero
+        } else if info.working_counter == info.expected_working_counter {
+            WcState::Complete
+        } else {
+            WcState::Incomplete
+        },
+        redundancy_active: false,
+    }
+}
+
+pub struct SlaveConfig<'m, S = Config> {
+    master: &'m Master<S>,
     idx: SlaveConfigIdx,
 }
 
-impl<'m> SlaveConfig<'m> {
+impl<'m, S> SlaveConfig<'m, S> {
     pub const fn index(&self) -> SlaveConfigIdx {
         self.idx
     }
@@ -577,7 +1441,7 @@ impl<'m> SlaveConfig<'m> {
             config_index: self.idx,
             state: &mut state,
         };
-        ioctl!(self.master, ec::ioctl::SC_STATE, &mut data)?;
+        ioctl!(ErrorContext::Config(self.idx); self.master, ec::ioctl::SC_STATE, &mut data)?;
         let al_state_u8 = state.al_state() as u8;
         Ok(SlaveConfigState {
             online: state.online() != 0,
@@ -587,9 +1451,49 @@ impl<'m> SlaveConfig<'m> {
         })
     }
 
+    pub fn pop_emerg(&mut self, target: &mut [u8]) -> Result<()> {
+        let mut data = ec::ec_ioctl_sc_emerg_t::default();
+        data.config_index = self.idx;
+        data.target = target.as_mut_ptr();
+        ioctl!(
+            ErrorContext::Config(self.idx);
+            self.master, ec::ioctl::SC_EMERG_POP, &mut data
+        )
+        .map(|_| ())
+    }
+
+    pub fn clear_emerg(&mut self) -> Result<()> {
+        let mut data = ec::ec_ioctl_sc_emerg_t::default();
+        data.config_index = self.idx;
+        ioctl!(
+            ErrorContext::Config(self.idx);
+            self.master, ec::ioctl::SC_EMERG_CLEAR, &data
+        )
+        .map(|_| ())
+    }
+
+    pub fn emerg_overruns(&mut self) -> Result<i32> {
+        let mut data = ec::ec_ioctl_sc_emerg_t::default();
+        data.config_index = self.idx;
+        ioctl!(
+            ErrorContext::Config(self.idx);
+            self.master, ec::ioctl::SC_EMERG_OVERRUNS, &mut data
+        )?;
+        Ok(data.overruns)
+    }
+
+    // XXX missing: create_sdo_request, create_reg_request, create_voe_handler
+}
+
+impl<'m> SlaveConfig<'m, Config> {
     /// Configure PDOs of a specifc Sync Manager
     pub fn config_sm_pdos(&mut self, sm_cfg: SmCfg, pdo_cfgs: &[PdoCfg]) -> Result<()> {
         self.config_sync_manager(&sm_cfg)?;
+        self.master
+            .assigned
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((self.idx, sm_cfg.idx));
         self.clear_pdo_assignments(sm_cfg.idx)?;
         for pdo_cfg in pdo_cfgs {
             self.add_pdo_assignment(sm_cfg.idx, pdo_cfg.idx)?;
@@ -608,7 +1512,11 @@ impl<'m> SlaveConfig<'m> {
         data.config_index = self.idx;
         data.watchdog_divider = divider;
         data.watchdog_intervals = intervals;
-        ioctl!(self.master, ec::ioctl::SC_WATCHDOG, &data).map(|_| ())
+        ioctl!(
+            ErrorContext::Config(self.idx);
+            self.master, ec::ioctl::SC_WATCHDOG, &data
+        )
+        .map(|_| ())
     }
 
     #[cfg(feature = "sncn")]
@@ -616,7 +1524,11 @@ impl<'m> SlaveConfig<'m> {
         let mut data = ec::ec_ioctl_config_t::default();
         data.config_index = self.idx;
         data.allow_overlapping_pdos = allow as u8;
-        ioctl!(self.master, ec::ioctl::SC_OVERLAPPING_IO, &data).map(|_| ())
+        ioctl!(
+            ErrorContext::Config(self.idx);
+            self.master, ec::ioctl::SC_OVERLAPPING_IO, &data
+        )
+        .map(|_| ())
     }
 
     pub fn config_sync_manager(&mut self, cfg: &SmCfg) -> Result<()> {
@@ -630,14 +1542,18 @@ impl<'m> SlaveConfig<'m> {
         data.syncs[ix].dir = cfg.direction as u32;
         data.syncs[ix].watchdog_mode = cfg.watchdog_mode as u32;
         data.syncs[ix].config_this = 1;
-        ioctl!(self.master, ec::ioctl::SC_SYNC, &data).map(|_| ())
+        ioctl!(ErrorContext::Config(self.idx); self.master, ec::ioctl::SC_SYNC, &data).map(|_| ())
     }
 
     pub fn clear_pdo_assignments(&mut self, sync_idx: SmIdx) -> Result<()> {
         let mut data = ec::ec_ioctl_config_pdo_t::default();
         data.config_index = self.idx;
         data.sync_index = u8::from(sync_idx);
-        ioctl!(self.master, ec::ioctl::SC_CLEAR_PDOS, &data).map(|_| ())
+        ioctl!(
+            ErrorContext::Config(self.idx);
+            self.master, ec::ioctl::SC_CLEAR_PDOS, &data
+        )
+        .map(|_| ())
     }
 
     pub fn add_pdo_assignment(&mut self, sync_idx: SmIdx, pdo_idx: PdoIdx) -> Result<()> {
@@ -645,14 +1561,22 @@ impl<'m> SlaveConfig<'m> {
         data.config_index = self.idx;
         data.sync_index = u8::from(sync_idx);
         data.index = u16::from(pdo_idx);
-        ioctl!(self.master, ec::ioctl::SC_ADD_PDO, &data).map(|_| ())
+        ioctl!(
+            ErrorContext::Config(self.idx);
+            self.master, ec::ioctl::SC_ADD_PDO, &data
+        )
+        .map(|_| ())
     }
 
     pub fn clear_pdo_mapping(&mut self, pdo_idx: PdoIdx) -> Result<()> {
         let mut data = ec::ec_ioctl_config_pdo_t::default();
         data.config_index = self.idx;
         data.index = u16::from(pdo_idx);
-        ioctl!(self.master, ec::ioctl::SC_CLEAR_ENTRIES, &data).map(|_| ())
+        ioctl!(
+            ErrorContext::Config(self.idx);
+            self.master, ec::ioctl::SC_CLEAR_ENTRIES, &data
+        )
+        .map(|_| ())
     }
 
     pub fn add_pdo_mapping(&mut self, pdo_index: PdoIdx, entry: &PdoEntryInfo) -> Result<()> {
@@ -663,7 +1587,11 @@ impl<'m> SlaveConfig<'m> {
             entry_subindex: u8::from(entry.entry_idx.sub_idx),
             entry_bit_length: entry.bit_len,
         };
-        ioctl!(self.master, ec::ioctl::SC_ADD_ENTRY, &data).map(|_| ())
+        ioctl!(
+            ErrorContext::Config(self.idx);
+            self.master, ec::ioctl::SC_ADD_ENTRY, &data
+        )
+        .map(|_| ())
     }
 
     pub fn register_pdo_entry(&mut self, index: PdoEntryIdx, domain: DomainIdx) -> Result<Offset> {
@@ -675,11 +1603,25 @@ impl<'m> SlaveConfig<'m> {
                 .map_err(|_| Error::DomainIdx(usize::from(domain)))?,
             bit_position: 0,
         };
-        let byte = ioctl!(self.master, ec::ioctl::SC_REG_PDO_ENTRY, &mut data)?;
-        Ok(Offset {
+        let byte = ioctl!(
+            ErrorContext::Config(self.idx);
+            self.master, ec::ioctl::SC_REG_PDO_ENTRY, &mut data
+        )?;
+        let offset = Offset {
             byte: byte as usize,
             bit: data.bit_position,
-        })
+        };
+        self.registered(index, domain, offset);
+        Ok(offset)
+    }
+
+    /// Register a PDO entry and return a typed handle to it.
+    pub fn register_field<T: PdoData>(
+        &mut self,
+        index: PdoEntryIdx,
+        domain: DomainIdx,
+    ) -> Result<Field<T>> {
+        Ok(Field::new(domain, self.register_pdo_entry(index, domain)?))
     }
 
     pub fn register_pdo_entry_by_position(
@@ -698,11 +1640,37 @@ impl<'m> SlaveConfig<'m> {
                 .map_err(|_| Error::DomainIdx(usize::from(domain)))?,
             bit_position: 0,
         };
-        let byte = ioctl!(self.master, ec::ioctl::SC_REG_PDO_POS, &mut data)?;
-        Ok(Offset {
+        let byte = ioctl!(
+            ErrorContext::Config(self.idx);
+            self.master, ec::ioctl::SC_REG_PDO_POS, &mut data
+        )?;
+        let offset = Offset {
             byte: byte as usize,
             bit: data.bit_position,
-        })
+        };
+        let entry =
+            self.master
+                .config_pdo_entry(self.idx, sync_index, pdo_pos as u16, entry_pos as u8)?;
+        self.registered(
+            PdoEntryIdx::new(entry.index, entry.subindex),
+            domain,
+            offset,
+        );
+        Ok(offset)
+    }
+
+    fn registered(&self, entry: PdoEntryIdx, domain: DomainIdx, offset: Offset) {
+        let mut registered = self
+            .master
+            .registered
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        registered.push(RegisteredEntry {
+            config: self.idx,
+            entry,
+            domain,
+            offset,
+        });
     }
 
     pub fn config_dc(
@@ -720,7 +1688,7 @@ impl<'m> SlaveConfig<'m> {
         data.dc_sync[0].shift_time = sync0_shift_time;
         data.dc_sync[1].cycle_time = sync1_cycle_time;
         data.dc_sync[1].shift_time = sync1_shift_time;
-        ioctl!(self.master, ec::ioctl::SC_DC, &data).map(|_| ())
+        ioctl!(ErrorContext::Config(self.idx); self.master, ec::ioctl::SC_DC, &data).map(|_| ())
     }
 
     pub fn add_sdo<T>(&mut self, index: SdoIdx, data: &T) -> Result<()>
@@ -735,7 +1703,7 @@ impl<'m> SlaveConfig<'m> {
             size: data.data_size(),
             complete_access: 0,
         };
-        ioctl!(self.master, ec::ioctl::SC_SDO, &data).map(|_| ())
+        ioctl!(ErrorContext::Config(self.idx); self.master, ec::ioctl::SC_SDO, &data).map(|_| ())
     }
 
     pub fn add_complete_sdo(&mut self, index: SdoIdx, data: &[u8]) -> Result<()> {
@@ -747,7 +1715,7 @@ impl<'m> SlaveConfig<'m> {
             size: data.len(),
             complete_access: 1,
         };
-        ioctl!(self.master, ec::ioctl::SC_SDO, &data).map(|_| ())
+        ioctl!(ErrorContext::Config(self.idx); self.master, ec::ioctl::SC_SDO, &data).map(|_| ())
     }
 
     pub fn config_idn(
@@ -765,46 +1733,29 @@ impl<'m> SlaveConfig<'m> {
             data: data.as_ptr(),
             size: data.len(),
         };
-        ioctl!(self.master, ec::ioctl::SC_IDN, &data).map(|_| ())
+        ioctl!(ErrorContext::Config(self.idx); self.master, ec::ioctl::SC_IDN, &data).map(|_| ())
     }
 
     pub fn set_emerg_size(&mut self, elements: u64) -> Result<()> {
         let mut data = ec::ec_ioctl_sc_emerg_t::default();
         data.config_index = self.idx;
         data.size = elements as usize;
-        ioctl!(self.master, ec::ioctl::SC_EMERG_SIZE, &data).map(|_| ())
-    }
-
-    pub fn pop_emerg(&mut self, target: &mut [u8]) -> Result<()> {
-        let mut data = ec::ec_ioctl_sc_emerg_t::default();
-        data.config_index = self.idx;
-        data.target = target.as_mut_ptr();
-        ioctl!(self.master, ec::ioctl::SC_EMERG_POP, &mut data).map(|_| ())
-    }
-
-    pub fn clear_emerg(&mut self) -> Result<()> {
-        let mut data = ec::ec_ioctl_sc_emerg_t::default();
-        data.config_index = self.idx;
-        ioctl!(self.master, ec::ioctl::SC_EMERG_CLEAR, &data).map(|_| ())
-    }
-
-    pub fn emerg_overruns(&mut self) -> Result<i32> {
-        let mut data = ec::ec_ioctl_sc_emerg_t::default();
-        data.config_index = self.idx;
-        ioctl!(self.master, ec::ioctl::SC_EMERG_OVERRUNS, &mut data)?;
-        Ok(data.overruns)
+        ioctl!(
+            ErrorContext::Config(self.idx);
+            self.master, ec::ioctl::SC_EMERG_SIZE, &data
+        )
+        .map(|_| ())
     }
-
-    // XXX missing: create_sdo_request, create_reg_request, create_voe_handler
 }
 
-impl<'m> Domain<'m> {
-    pub const fn new(idx: DomainIdx, master: &'m Master) -> Self {
+impl<'m, S> Domain<'m, S> {
+    pub const fn new(idx: DomainIdx, master: &'m Master<S>) -> Self {
         Self { idx, master }
     }
 
     pub fn size(&self) -> Result<usize> {
         ioctl!(
+            ErrorContext::Domain(self.idx);
             self.master,
             ec::ioctl::DOMAIN_SIZE,
             c_ulong::try_from(self.idx).map_err(|_| Error::DomainIdx(usize::from(self.idx)))?
@@ -812,6 +1763,40 @@ impl<'m> Domain<'m> {
         .map(|v| v as usize)
     }
 
+    pub fn info(&self) -> Result<DomainInfo> {
+        let mut data = ec::ec_ioctl_domain_t::default();
+        data.index =
+            u32::try_from(self.idx).map_err(|_| Error::DomainIdx(usize::from(self.idx)))?;
+        ioctl!(ErrorContext::Domain(self.idx); self.master, ec::ioctl::DOMAIN, &mut data)?;
+        Ok(DomainInfo {
+            data_size: data.data_size as usize,
+            logical_base_address: data.logical_base_address,
+            working_counter: data.working_counter[0],
+            expected_working_counter: data.expected_working_counter,
+            fmmu_count: data.fmmu_count,
+        })
+    }
+
+    pub fn fmmu(&self, fmmu_index: u32) -> Result<DomainFmmuInfo> {
+        let mut data = ec::ec_ioctl_domain_fmmu_t::default();
+        data.domain_index =
+            u32::try_from(self.idx).map_err(|_| Error::DomainIdx(usize::from(self.idx)))?;
+        data.fmmu_index = fmmu_index;
+        ioctl!(ErrorContext::Domain(self.idx); self.master, ec::ioctl::DOMAIN_FMMU, &mut data)?;
+        Ok(DomainFmmuInfo {
+            slave_config_alias: data.slave_config_alias,
+            slave_config_position: data.slave_config_position,
+            sync_index: SmIdx::from(data.sync_index),
+            direction: match data.dir {
+                ec::EC_DIR_OUTPUT => SyncDirection::Output,
+                ec::EC_DIR_INPUT => SyncDirection::Input,
+                _ => SyncDirection::Invalid,
+            },
+            logical_address: data.logical_address,
+            data_size: data.data_size as usize,
+        })
+    }
+
     pub fn state(&self) -> Result<DomainState> {
         let mut state = ec::ec_domain_state_t::default();
         let mut data = ec::ec_ioctl_domain_state_t {
@@ -819,16 +1804,17 @@ impl<'m> Domain<'m> {
                 .map_err(|_| Error::DomainIdx(usize::from(self.idx)))?,
             state: &mut state,
         };
-        ioctl!(self.master, ec::ioctl::DOMAIN_STATE, &mut data)?;
+        ioctl!(ErrorContext::Domain(self.idx); self.master, ec::ioctl::DOMAIN_STATE, &mut data)?;
         Ok(DomainState {
             working_counter: state.working_counter,
             redundancy_active: state.redundancy_active != 0,
-            wc_state: WcState::from(state.wc_state),
+            wc_state: WcState::try_from(state.wc_state)?,
         })
     }
 
     pub fn process(&mut self) -> Result<()> {
         ioctl!(
+            ErrorContext::Domain(self.idx);
             self.master,
             ec::ioctl::DOMAIN_PROCESS,
             usize::from(self.idx) as c_ulong
@@ -838,6 +1824,7 @@ impl<'m> Domain<'m> {
 
     pub fn queue(&mut self) -> Result<()> {
         ioctl!(
+            ErrorContext::Domain(self.idx);
             self.master,
             ec::ioctl::DOMAIN_QUEUE,
             c_ulong::try_from(self.idx).map_err(|_| Error::DomainIdx(usize::from(self.idx)))?
@@ -845,3 +1832,92 @@ impl<'m> Domain<'m> {
         .map(|_| ())
     }
 }
+
+/// The abort of an SDO transfer by the slave, or else the failed ioctl.
+fn sdo_error(e: Error, slave: SlavePos, sdo: SdoIdx, abort_code: u32) -> Error {
+    if abort_code == 0 {
+        e
+    } else {
+        Error::SdoAbort(SdoAbort {
+            slave,
+            sdo,
+            code: abort_code,
+        })
+    }
+}
+
+#[test]
+fn test_sdo_counter() {
+    let mut counter = SdoCounter::default();
+    let timeout = || {
+        Err(Error::Ioctl {
+            op: "SLAVE_SDO_UPLOAD",
+            context: ErrorContext::Slave(SlavePos::from(1)),
+            source: io::ErrorKind::TimedOut.into(),
+        })
+    };
+    counter.count((0x6060, 0), &Ok(()), 0);
+    counter.count((0x8000, 1), &timeout(), 0);
+    counter.count((0x8000, 1), &timeout(), 0);
+    counter.count(
+        (0x8000, 1),
+        &Err(Error::Io(io::Error::from_raw_os_error(5))),
+        0x0609_0011,
+    );
+    counter.count((0x8000, 1), &Ok(()), 0);
+    counter.count((0x8000, 1), &Ok(()), 0);
+    let stats = &counter.stats;
+    assert_eq!(
+        (
+            stats.attempts,
+            stats.retries,
+            stats.timeouts,
+            stats.aborts,
+            stats.errors
+        ),
+        (6, 3, 2, 1, 0)
+    );
+    assert_eq!(stats.abort_codes.get(&0x0609_0011), Some(&1));
+}
+
+#[test]
+fn test_process_image() {
+    let path = std::env::temp_dir().join(format!("ethercat-image-{}", std::process::id()));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    file.set_len(8).unwrap();
+    // activated without process data
+    let mut empty = ProcessImage::map(&file, 0).unwrap();
+    assert!(empty.data_mut().is_empty());
+    let mut mapped = ProcessImage::map(&file, 8).unwrap();
+    assert_eq!(mapped.data_mut().len(), 8);
+    let placement = |offset, size| DomainDataPlacement { offset, size };
+    assert_eq!(mapped.domain_mut(&placement(2, 6)).unwrap().len(), 6);
+    assert!(empty.domain_mut(&placement(0, 0)).unwrap().is_empty());
+    // a placement outside the image
+    assert!(mapped.domain_mut(&placement(4, 6)).is_err());
+    assert!(mapped.domain_mut(&placement(usize::MAX, 2)).is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_unreserved_state() {
+    let state = |working_counter| {
+        unreserved_state(&DomainInfo {
+            data_size: 8,
+            logical_base_address: 0,
+            working_counter,
+            expected_working_counter: 3,
+            fmmu_count: 2,
+        })
+        .wc_state
+    };
+    assert_eq!(state(0), WcState::Zero);
+    assert_eq!(state(2), WcState::Incomplete);
+    assert_eq!(state(3), WcState::Complete);
+}
@@ -0,0 +1,385 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Read-only Modbus TCP server, e.g. for HMIs that only speak Modbus.
+//!
+//! PDO entries are mapped to input or holding registers, and boolean
+//! entries also to discrete inputs. Values wider than 16 bits take several
+//! consecutive registers, most significant word first; floats are stored as
+//! their IEEE 754 bits.
+//!
+//! The server answers the read functions 2 (discrete inputs), 3 (holding
+//! registers) and 4 (input registers). Writes are refused with exception 1
+//! (illegal function), reads of unmapped addresses with exception 2
+//! (illegal data address). Values come from [`SnapshotReader`]s, so the
+//! server never touches the cyclic thread.
+
+use crate::{
+    field::{Field, PdoData},
+    runtime::{snapshot_index, Snapshot, SnapshotReader},
+    types::*,
+};
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Usual port of Modbus TCP servers.
+pub const PORT: u16 = 502;
+
+const POLL: Duration = Duration::from_millis(50);
+/// Time to receive the rest of a frame after its first byte.
+const FRAME_TIMEOUT: Duration = Duration::from_secs(1);
+const MBAP_HEADER: usize = 7;
+/// Largest PDU of a Modbus TCP frame.
+const MAX_PDU: usize = 253;
+
+const READ_DISCRETE_INPUTS: u8 = 0x02;
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const READ_INPUT_REGISTERS: u8 = 0x04;
+
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+
+type ReadRaw = Box<dyn Fn(&[u8]) -> u64 + Send + Sync>;
+
+/// A mapped PDO entry.
+struct MappedField {
+    /// Index into the snapshot readers.
+    domain: usize,
+    /// Number of registers.
+    words: usize,
+    read: ReadRaw,
+}
+
+/// Location of a register or discrete input: field and word, most
+/// significant first.
+type Mapping = BTreeMap<u16, (usize, usize)>;
+
+/// Builder for a [`ModbusServer`].
+pub struct ModbusServerBuilder {
+    snapshots: Vec<(DomainIdx, SnapshotReader)>,
+    fields: Vec<MappedField>,
+    discrete_inputs: Mapping,
+    holding_registers: Mapping,
+    input_registers: Mapping,
+}
+
+/// A Modbus TCP server, see the [module docs](self).
+pub struct ModbusServer {
+    listener: TcpListener,
+    registers: Arc<Registers>,
+    stop: Arc<AtomicBool>,
+}
+
+struct Registers {
+    snapshots: Vec<SnapshotReader>,
+    fields: Vec<MappedField>,
+    discrete_inputs: Mapping,
+    holding_registers: Mapping,
+    input_registers: Mapping,
+}
+
+impl ModbusServerBuilder {
+    /// Publish the snapshots of a domain, for the fields of this domain.
+    pub fn snapshot(mut self, domain: DomainIdx, reader: SnapshotReader) -> Self {
+        self.snapshots.push((domain, reader));
+        self
+    }
+
+    /// Map a PDO entry to the input registers starting at `address`.
+    ///
+    /// Fails if no snapshot of the field's domain was added, see
+    /// [`snapshot_index`]. Panics if one of the registers is already mapped.
+    pub fn input_register<T: PdoData + Send + Sync + 'static>(
+        mut self,
+        address: u16,
+        field: Field<T>,
+    ) -> Result<Self> {
+        let index = self.add_field(field)?;
+        let words = self.fields[index].words;
+        map(&mut self.input_registers, address, index, words);
+        Ok(self)
+    }
+
+    /// Map a PDO entry to the holding registers starting at `address`.
+    ///
+    /// Fails and panics like [`input_register`](Self::input_register).
+    pub fn holding_register<T: PdoData + Send + Sync + 'static>(
+        mut self,
+        address: u16,
+        field: Field<T>,
+    ) -> Result<Self> {
+        let index = self.add_field(field)?;
+        let words = self.fields[index].words;
+        map(&mut self.holding_registers, address, index, words);
+        Ok(self)
+    }
+
+    /// Map a boolean PDO entry to the discrete input at `address`.
+    ///
+    /// Fails and panics like [`input_register`](Self::input_register).
+    pub fn discrete_input(mut self, address: u16, field: Field<bool>) -> Result<Self> {
+        let index = self.add_field(field)?;
+        map(&mut self.discrete_inputs, address, index, 1);
+        Ok(self)
+    }
+
+    fn add_field<T: PdoData + Send + Sync + 'static>(&mut self, field: Field<T>) -> Result<usize> {
+        let domain = snapshot_index(&self.snapshots, &field)?;
+        self.fields.push(MappedField {
+            domain,
+            words: (T::BITS as usize + 15) / 16,
+            read: Box::new(move |data| field.get(data).to_raw()),
+        });
+        Ok(self.fields.len() - 1)
+    }
+
+    /// Listen on `addr`, usually `("0.0.0.0", modbus::PORT)`.
+    pub fn bind<A: ToSocketAddrs>(self, addr: A) -> io::Result<ModbusServer> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(ModbusServer {
+            listener,
+            registers: Arc::new(Registers {
+                snapshots: self.snapshots.into_iter().map(|(_, r)| r).collect(),
+                fields: self.fields,
+                discrete_inputs: self.discrete_inputs,
+                holding_registers: self.holding_registers,
+                input_registers: self.input_registers,
+            }),
+            stop: Arc::new(AtomicBool::new(false)),
+        })
+    }
+}
+
+fn map(mapping: &mut Mapping, address: u16, field: usize, words: usize) {
+    for word in 0..words {
+        let address = address
+            .checked_add(word as u16)
+            .expect("register address out of range");
+        assert!(
+            mapping.insert(address, (field, word)).is_none(),
+            "register {} mapped twice",
+            address
+        );
+    }
+}
+
+impl ModbusServer {
+    pub fn builder() -> ModbusServerBuilder {
+        ModbusServerBuilder {
+            snapshots: vec![],
+            fields: vec![],
+            discrete_inputs: Mapping::new(),
+            holding_registers: Mapping::new(),
+            input_registers: Mapping::new(),
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Returns a flag that makes [`run`](Self::run) and the connections
+    /// return when set.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Accept clients, each served by its own thread, until the stop flag
+    /// is set.
+    pub fn run(&mut self) -> io::Result<()> {
+        while !self.stop.load(Ordering::Acquire) {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(POLL);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let registers = self.registers.clone();
+            let stop = self.stop.clone();
+            thread::Builder::new()
+                .name("modbus-connection".into())
+                .spawn(move || {
+                    if let Err(e) = serve(&registers, stream, &stop) {
+                        log::debug!("Modbus connection closed: {}", e);
+                    }
+                })?;
+        }
+        Ok(())
+    }
+}
+
+fn serve(registers: &Registers, mut stream: TcpStream, stop: &AtomicBool) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL))?;
+    let mut header = [0; MBAP_HEADER];
+    loop {
+        // wait for the first byte, checking the stop flag
+        match stream.read(&mut header[..1]) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                if stop.load(Ordering::Acquire) {
+                    return Ok(());
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        }
+        stream.set_read_timeout(Some(FRAME_TIMEOUT))?;
+        stream.read_exact(&mut header[1..])?;
+        let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if header[2..4] != [0, 0] || !(2..=MAX_PDU + 1).contains(&length) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid Modbus TCP header",
+            ));
+        }
+        let mut pdu = vec![0; length - 1];
+        stream.read_exact(&mut pdu)?;
+        stream.set_read_timeout(Some(POLL))?;
+        let response = registers.handle(&pdu);
+        let mut frame = header[..4].to_vec();
+        frame.extend_from_slice(&(response.len() as u16 + 1).to_be_bytes());
+        frame.push(header[6]);
+        frame.extend_from_slice(&response);
+        stream.write_all(&frame)?;
+    }
+}
+
+impl Registers {
+    /// Handle a request PDU and return the response PDU.
+    fn handle(&self, pdu: &[u8]) -> Vec<u8> {
+        let function = pdu[0];
+        match self.read(function, &pdu[1..]) {
+            Ok(data) => {
+                let mut response = vec![function, data.len() as u8];
+                response.extend_from_slice(&data);
+                response
+            }
+            Err(code) => vec![function | 0x80, code],
+        }
+    }
+
+    fn read(&self, function: u8, request: &[u8]) -> std::result::Result<Vec<u8>, u8> {
+        let (mapping, max_count) = match function {
+            READ_DISCRETE_INPUTS => (&self.discrete_inputs, 2000),
+            READ_HOLDING_REGISTERS => (&self.holding_registers, 125),
+            READ_INPUT_REGISTERS => (&self.input_registers, 125),
+            _ => return Err(ILLEGAL_FUNCTION),
+        };
+        if request.len() != 4 {
+            return Err(ILLEGAL_DATA_VALUE);
+        }
+        let start = u16::from_be_bytes([request[0], request[1]]);
+        let count = u16::from_be_bytes([request[2], request[3]]);
+        if count == 0 || count > max_count {
+            return Err(ILLEGAL_DATA_VALUE);
+        }
+        let locations = (0..count)
+            .map(|i| {
+                let address = start.checked_add(i).ok_or(ILLEGAL_DATA_ADDRESS)?;
+                mapping.get(&address).copied().ok_or(ILLEGAL_DATA_ADDRESS)
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        // read every snapshot once, so that all values are from one cycle
+        let mut snapshots: Vec<Option<Snapshot>> = vec![None; self.snapshots.len()];
+        let mut word = |(field, word): (usize, usize)| {
+            let field = &self.fields[field];
+            let data = &snapshots[field.domain]
+                .get_or_insert_with(|| self.snapshots[field.domain].read())
+                .data;
+            let shift = 16 * (field.words - 1 - word);
+            ((field.read)(data) >> shift) as u16
+        };
+        Ok(if function == READ_DISCRETE_INPUTS {
+            let mut bits = vec![0; (count as usize + 7) / 8];
+            for (i, location) in locations.into_iter().enumerate() {
+                if word(location) & 1 != 0 {
+                    bits[i / 8] |= 1 << (i % 8);
+                }
+            }
+            bits
+        } else {
+            locations
+                .into_iter()
+                .flat_map(|location| word(location).to_be_bytes())
+                .collect()
+        })
+    }
+}
+
+#[test]
+fn test_modbus_server() {
+    use crate::runtime::snapshot_buffer;
+
+    let (mut writer, reader) = snapshot_buffer(8);
+    writer.publish(1, 0, &[0x34, 0x12, 0x78, 0x56, 0x34, 0x12, 0b101, 0]);
+    let field = |byte, bit| Offset { byte, bit };
+    assert!(ModbusServer::builder()
+        .input_register(0, Field::<u16>::new(0.into(), field(0, 0)))
+        .is_err());
+    let mut server = ModbusServer::builder()
+        .snapshot(0.into(), reader)
+        .input_register(0, Field::<u16>::new(0.into(), field(0, 0)))
+        .unwrap()
+        .input_register(1, Field::<i32>::new(0.into(), field(2, 0)))
+        .unwrap()
+        .holding_register(100, Field::<u16>::new(0.into(), field(2, 0)))
+        .unwrap()
+        .discrete_input(0, Field::<bool>::new(0.into(), field(6, 0)))
+        .unwrap()
+        .discrete_input(1, Field::<bool>::new(0.into(), field(6, 1)))
+        .unwrap()
+        .discrete_input(2, Field::<bool>::new(0.into(), field(6, 2)))
+        .unwrap()
+        .bind("127.0.0.1:0")
+        .unwrap();
+    let addr = server.local_addr().unwrap();
+    let stop = server.stop_handle();
+    let thread = thread::spawn(move || server.run());
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut request = |pdu: &[u8]| {
+        let mut frame = vec![0, 42, 0, 0, 0, pdu.len() as u8 + 1, 1];
+        frame.extend_from_slice(pdu);
+        stream.write_all(&frame).unwrap();
+        let mut header = [0; MBAP_HEADER];
+        stream.read_exact(&mut header).unwrap();
+        assert_eq!(header[..2], [0, 42]);
+        let mut response = vec![0; header[5] as usize - 1];
+        stream.read_exact(&mut response).unwrap();
+        response
+    };
+
+    assert_eq!(
+        request(&[4, 0, 0, 0, 3]),
+        [4, 6, 0x12, 0x34, 0x12, 0x34, 0x56, 0x78]
+    );
+    assert_eq!(request(&[3, 0, 100, 0, 1]), [3, 2, 0x56, 0x78]);
+    assert_eq!(request(&[2, 0, 0, 0, 3]), [2, 1, 0b101]);
+    // unmapped register, write, and too many registers
+    assert_eq!(request(&[4, 0, 2, 0, 2]), [0x84, ILLEGAL_DATA_ADDRESS]);
+    assert_eq!(request(&[6, 0, 100, 0, 1]), [0x86, ILLEGAL_FUNCTION]);
+    assert_eq!(request(&[3, 0, 100, 0, 200]), [0x83, ILLEGAL_DATA_VALUE]);
+
+    stop.store(true, Ordering::Release);
+    thread.join().unwrap().unwrap();
+}
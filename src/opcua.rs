@@ -0,0 +1,1450 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! OPC UA server exposing selected PDO entries and SDOs, e.g. for SCADA
+//! systems.
+//!
+//! The server implements the subset of OPC UA needed for browsing, reading
+//! and writing variables over `opc.tcp`: the binary encoding without
+//! security (SecurityPolicy `None`), anonymous sessions and the Browse, Read
+//! and Write services. There are no subscriptions; clients poll with Read.
+//!
+//! The variables are organized in an `EtherCAT` folder under `Objects`, with
+//! the node ids `ns=1;s=<name>`:
+//!
+//! - PDO entries are read from [`SnapshotReader`]s and cannot be written,
+//!   since the cyclic thread owns the process image;
+//! - SDOs go through a separate [`Backend`], usually a second master handle.
+//!   Writes are only accepted for SDOs added as writable, and only if the
+//!   write guard allows them.
+//!
+//! The server runs its own threads and never touches the cyclic thread.
+
+mod binary;
+
+use self::binary::{status, Decoded, NodeId, Reader, Variant, Writer};
+use crate::{
+    backend::Backend,
+    field::{Field, PdoData},
+    runtime::{snapshot_index, SnapshotReader},
+    types::*,
+};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Usual port of OPC UA servers.
+pub const PORT: u16 = 4840;
+
+const POLICY_NONE: &str = "http://opcfoundation.org/UA/SecurityPolicy#None";
+const TRANSPORT_PROFILE: &str = "http://opcfoundation.org/UA-Profile/Transport/uatcp-uasc-uabinary";
+const PRODUCT_URI: &str = "urn:ethercat-rs";
+
+/// Size of our send and receive buffers, i.e. the largest message chunk.
+const BUFFER: usize = 0x1_0000;
+const POLL: Duration = Duration::from_millis(50);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Namespace of the EtherCAT nodes.
+const NS: u16 = 1;
+
+// binary encoding ids of the services
+const OPEN_SECURE_CHANNEL: u32 = 446;
+const GET_ENDPOINTS: u32 = 428;
+const FIND_SERVERS: u32 = 422;
+const CREATE_SESSION: u32 = 461;
+const ACTIVATE_SESSION: u32 = 467;
+const CLOSE_SESSION: u32 = 473;
+const BROWSE: u32 = 527;
+const READ: u32 = 631;
+const WRITE: u32 = 673;
+const SERVICE_FAULT: u32 = 397;
+/// A response has the encoding id of its request + 3.
+const RESPONSE: u32 = 3;
+const ANONYMOUS_IDENTITY_TOKEN: u32 = 321;
+const SERVER_STATUS_DATA_TYPE: u32 = 864;
+
+// reference types
+const REFERENCES: u32 = 31;
+const HIERARCHICAL_REFERENCES: u32 = 33;
+const ORGANIZES: u32 = 35;
+const HAS_PROPERTY: u32 = 46;
+const HAS_COMPONENT: u32 = 47;
+
+// type definitions
+const FOLDER_TYPE: u32 = 61;
+const BASE_DATA_VARIABLE_TYPE: u32 = 63;
+const PROPERTY_TYPE: u32 = 68;
+const SERVER_TYPE: u32 = 2004;
+const SERVER_STATUS_TYPE: u32 = 2138;
+
+// attributes
+const ATTR_NODE_ID: u32 = 1;
+const ATTR_NODE_CLASS: u32 = 2;
+const ATTR_BROWSE_NAME: u32 = 3;
+const ATTR_DISPLAY_NAME: u32 = 4;
+const ATTR_DESCRIPTION: u32 = 5;
+const ATTR_WRITE_MASK: u32 = 6;
+const ATTR_USER_WRITE_MASK: u32 = 7;
+const ATTR_EVENT_NOTIFIER: u32 = 12;
+const ATTR_VALUE: u32 = 13;
+const ATTR_DATA_TYPE: u32 = 14;
+const ATTR_VALUE_RANK: u32 = 15;
+const ATTR_ARRAY_DIMENSIONS: u32 = 16;
+const ATTR_ACCESS_LEVEL: u32 = 17;
+const ATTR_USER_ACCESS_LEVEL: u32 = 18;
+const ATTR_MINIMUM_SAMPLING_INTERVAL: u32 = 19;
+const ATTR_HISTORIZING: u32 = 20;
+
+const NODE_CLASS_OBJECT: u32 = 1;
+const NODE_CLASS_VARIABLE: u32 = 2;
+
+/// 100 ns ticks from 1601-01-01 to 2000-01-01, the epoch of DC times.
+const DC_EPOCH_TICKS: i64 = 125_911_584_000_000_000;
+
+/// PDO entry and SDO types that can be published as OPC UA variables.
+pub trait UaValue: PdoData + Send + Sync + 'static {
+    /// Numeric node id of the OPC UA data type, e.g. 6 for Int32.
+    const DATA_TYPE: u32;
+}
+
+macro_rules! ua_value {
+    ($($t:ty: $data_type:literal),*) => {
+        $(impl UaValue for $t {
+            const DATA_TYPE: u32 = $data_type;
+        })*
+    };
+}
+
+ua_value!(bool: 1, i8: 2, u8: 3, i16: 4, u16: 5, i32: 6, u32: 7, i64: 8, u64: 9, f32: 10, f64: 11);
+
+/// The variant of the raw value of a [`UaValue`] with the given data type.
+fn to_variant(data_type: u32, raw: u64) -> Variant {
+    match data_type {
+        1 => Variant::Boolean(raw & 1 != 0),
+        2 => Variant::SByte(raw as i8),
+        3 => Variant::Byte(raw as u8),
+        4 => Variant::Int16(raw as i16),
+        5 => Variant::UInt16(raw as u16),
+        6 => Variant::Int32(raw as i32),
+        7 => Variant::UInt32(raw as u32),
+        8 => Variant::Int64(raw as i64),
+        9 => Variant::UInt64(raw),
+        10 => Variant::Float(f32::from_bits(raw as u32)),
+        _ => Variant::Double(f64::from_bits(raw)),
+    }
+}
+
+/// The raw value of a written variant, and the value for the write guard.
+fn from_variant(data_type: u32, value: &Variant) -> Decoded<(u64, f64)> {
+    let range = match data_type {
+        1 => {
+            return match value {
+                Variant::Boolean(b) => Ok((*b as u64, *b as u8 as f64)),
+                _ => Err(status::BAD_TYPE_MISMATCH),
+            }
+        }
+        10 | 11 => {
+            let v = match value {
+                Variant::Float(_) | Variant::Double(_) => value.as_f64().expect("float"),
+                _ => return Err(status::BAD_TYPE_MISMATCH),
+            };
+            let raw = if data_type == 10 {
+                (v as f32).to_bits().into()
+            } else {
+                v.to_bits()
+            };
+            return Ok((raw, v));
+        }
+        2 => i8::MIN.into()..=i8::MAX.into(),
+        3 => 0..=u8::MAX.into(),
+        4 => i16::MIN.into()..=i16::MAX.into(),
+        5 => 0..=u16::MAX.into(),
+        6 => i32::MIN.into()..=i32::MAX.into(),
+        7 => 0..=u32::MAX.into(),
+        8 => i64::MIN.into()..=i64::MAX.into(),
+        _ => 0..=u64::MAX.into(),
+    };
+    let v: i128 = value.as_integer().ok_or(status::BAD_TYPE_MISMATCH)?;
+    if !range.contains(&v) {
+        return Err(status::BAD_OUT_OF_RANGE);
+    }
+    Ok((v as u64, v as f64))
+}
+
+type ReadRaw = Box<dyn Fn(&[u8]) -> u64 + Send + Sync>;
+type WriteGuard = Box<dyn Fn(&str, f64) -> bool + Send + Sync>;
+type Mailbox = Mutex<Box<dyn Backend + Send>>;
+
+struct UaField {
+    /// Index into the snapshot readers.
+    domain: usize,
+    read: ReadRaw,
+}
+
+struct UaSdo {
+    slave: SlavePos,
+    index: SdoIdx,
+    size: usize,
+}
+
+/// Where the value of a variable comes from.
+enum Source {
+    NamespaceArray,
+    ServerStatus,
+    StartTime,
+    CurrentTime,
+    ServerState,
+    Field(UaField),
+    Sdo(UaSdo),
+}
+
+enum NodeKind {
+    Object {
+        type_def: u32,
+    },
+    Variable {
+        type_def: u32,
+        data_type: u32,
+        /// -1 for scalars, 1 for arrays.
+        value_rank: i32,
+        writable: bool,
+        source: Source,
+    },
+}
+
+struct Node {
+    id: NodeId,
+    name: String,
+    kind: NodeKind,
+    /// Index of the parent node and the reference type from it.
+    parent: Option<(usize, u32)>,
+}
+
+/// Builder for an [`OpcUaServer`].
+pub struct OpcUaServerBuilder {
+    application_name: String,
+    application_uri: String,
+    snapshots: Vec<(DomainIdx, SnapshotReader)>,
+    nodes: Vec<Node>,
+    mailbox: Option<Mailbox>,
+    guard: Option<WriteGuard>,
+}
+
+/// An OPC UA server, see the [module docs](self).
+pub struct OpcUaServer {
+    listener: TcpListener,
+    space: Arc<AddressSpace>,
+    stop: Arc<AtomicBool>,
+}
+
+struct AddressSpace {
+    application_name: String,
+    application_uri: String,
+    start_time: i64,
+    snapshots: Vec<SnapshotReader>,
+    nodes: Vec<Node>,
+    mailbox: Option<Mailbox>,
+    guard: Option<WriteGuard>,
+    channels: AtomicU32,
+    stop: Arc<AtomicBool>,
+}
+
+/// Index of the `EtherCAT` folder.
+const ETHERCAT_FOLDER: usize = 10;
+
+fn standard_nodes() -> Vec<Node> {
+    let object = |id, name: &str, type_def, parent| Node {
+        id: NodeId::ns0(id),
+        name: name.into(),
+        kind: NodeKind::Object { type_def },
+        parent,
+    };
+    let variable = |id, name: &str, data_type, source, parent| Node {
+        id: NodeId::ns0(id),
+        name: name.into(),
+        kind: NodeKind::Variable {
+            type_def: if id == 2255 {
+                PROPERTY_TYPE
+            } else {
+                BASE_DATA_VARIABLE_TYPE
+            },
+            data_type,
+            value_rank: if id == 2255 { 1 } else { -1 },
+            writable: false,
+            source,
+        },
+        parent,
+    };
+    let mut nodes = vec![
+        object(84, "Root", FOLDER_TYPE, None),
+        object(85, "Objects", FOLDER_TYPE, Some((0, ORGANIZES))),
+        object(86, "Types", FOLDER_TYPE, Some((0, ORGANIZES))),
+        object(87, "Views", FOLDER_TYPE, Some((0, ORGANIZES))),
+        object(2253, "Server", SERVER_TYPE, Some((1, ORGANIZES))),
+        variable(
+            2255,
+            "NamespaceArray",
+            12,
+            Source::NamespaceArray,
+            Some((4, HAS_PROPERTY)),
+        ),
+        variable(
+            2256,
+            "ServerStatus",
+            862,
+            Source::ServerStatus,
+            Some((4, HAS_COMPONENT)),
+        ),
+        variable(
+            2257,
+            "StartTime",
+            13,
+            Source::StartTime,
+            Some((6, HAS_COMPONENT)),
+        ),
+        variable(
+            2258,
+            "CurrentTime",
+            13,
+            Source::CurrentTime,
+            Some((6, HAS_COMPONENT)),
+        ),
+        variable(
+            2259,
+            "State",
+            852,
+            Source::ServerState,
+            Some((6, HAS_COMPONENT)),
+        ),
+    ];
+    if let NodeKind::Variable { type_def, .. } = &mut nodes[6].kind {
+        *type_def = SERVER_STATUS_TYPE;
+    }
+    nodes.push(Node {
+        id: NodeId::String(NS, "EtherCAT".into()),
+        name: "EtherCAT".into(),
+        kind: NodeKind::Object {
+            type_def: FOLDER_TYPE,
+        },
+        parent: Some((1, ORGANIZES)),
+    });
+    nodes
+}
+
+impl OpcUaServerBuilder {
+    /// Name of the server shown by clients, "EtherCAT" by default.
+    pub fn application_name(mut self, name: impl Into<String>) -> Self {
+        self.application_name = name.into();
+        self
+    }
+
+    /// URI of the server application, also the URI of namespace 1.
+    pub fn application_uri(mut self, uri: impl Into<String>) -> Self {
+        self.application_uri = uri.into();
+        self
+    }
+
+    /// Publish the snapshots of a domain, for the fields of this domain.
+    pub fn snapshot(mut self, domain: DomainIdx, reader: SnapshotReader) -> Self {
+        self.snapshots.push((domain, reader));
+        self
+    }
+
+    /// Serve SDO reads and writes with this backend.
+    ///
+    /// Transfers block until the slave answers; they are done one at a time.
+    pub fn mailbox<B: Backend + Send + 'static>(mut self, backend: B) -> Self {
+        self.mailbox = Some(Mutex::new(Box::new(backend)));
+        self
+    }
+
+    /// Decide whether a write of `value` into the named variable is allowed,
+    /// e.g. only while the machine is stopped. Booleans are passed as 0 or 1.
+    pub fn write_guard<F>(mut self, guard: F) -> Self
+    where
+        F: Fn(&str, f64) -> bool + Send + Sync + 'static,
+    {
+        self.guard = Some(Box::new(guard));
+        self
+    }
+
+    /// Publish a PDO entry as a read-only variable.
+    ///
+    /// Fails if no snapshot of the field's domain was added, see
+    /// [`snapshot_index`].
+    pub fn field<T: UaValue>(self, name: impl Into<String>, field: Field<T>) -> Result<Self> {
+        let domain = snapshot_index(&self.snapshots, &field)?;
+        let source = Source::Field(UaField {
+            domain,
+            read: Box::new(move |data| field.get(data).to_raw()),
+        });
+        Ok(self.variable::<T>(name.into(), false, source))
+    }
+
+    /// Publish an SDO as a read-only variable.
+    pub fn sdo<T: UaValue>(self, name: impl Into<String>, slave: SlavePos, index: SdoIdx) -> Self {
+        self.add_sdo::<T>(name.into(), slave, index, false)
+    }
+
+    /// Publish an SDO as a variable that clients can write, subject to the
+    /// write guard.
+    pub fn writable_sdo<T: UaValue>(
+        self,
+        name: impl Into<String>,
+        slave: SlavePos,
+        index: SdoIdx,
+    ) -> Self {
+        self.add_sdo::<T>(name.into(), slave, index, true)
+    }
+
+    fn add_sdo<T: UaValue>(
+        self,
+        name: String,
+        slave: SlavePos,
+        index: SdoIdx,
+        writable: bool,
+    ) -> Self {
+        let source = Source::Sdo(UaSdo {
+            slave,
+            index,
+            size: (T::BITS as usize + 7) / 8,
+        });
+        self.variable::<T>(name, writable, source)
+    }
+
+    fn variable<T: UaValue>(mut self, name: String, writable: bool, source: Source) -> Self {
+        self.nodes.push(Node {
+            id: NodeId::String(NS, name.clone()),
+            name,
+            kind: NodeKind::Variable {
+                type_def: BASE_DATA_VARIABLE_TYPE,
+                data_type: T::DATA_TYPE,
+                value_rank: -1,
+                writable,
+                source,
+            },
+            parent: Some((ETHERCAT_FOLDER, ORGANIZES)),
+        });
+        self
+    }
+
+    /// Listen on `addr`, usually `("0.0.0.0", opcua::PORT)`.
+    ///
+    /// Panics if SDOs were added without a mailbox.
+    pub fn bind<A: ToSocketAddrs>(self, addr: A) -> io::Result<OpcUaServer> {
+        let has_sdos = self.nodes.iter().any(|n| {
+            matches!(
+                n.kind,
+                NodeKind::Variable {
+                    source: Source::Sdo(_),
+                    ..
+                }
+            )
+        });
+        assert!(
+            !has_sdos || self.mailbox.is_some(),
+            "SDO variables need a mailbox"
+        );
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let space = AddressSpace {
+            application_name: self.application_name,
+            application_uri: self.application_uri,
+            start_time: binary::now(),
+            snapshots: self.snapshots.into_iter().map(|(_, r)| r).collect(),
+            nodes: self.nodes,
+            mailbox: self.mailbox,
+            guard: self.guard,
+            channels: AtomicU32::new(0),
+            stop: stop.clone(),
+        };
+        Ok(OpcUaServer {
+            listener,
+            space: Arc::new(space),
+            stop,
+        })
+    }
+}
+
+impl OpcUaServer {
+    pub fn builder() -> OpcUaServerBuilder {
+        OpcUaServerBuilder {
+            application_name: "EtherCAT".into(),
+            application_uri: "urn:ethercat-rs:server".into(),
+            snapshots: vec![],
+            nodes: standard_nodes(),
+            mailbox: None,
+            guard: None,
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Returns a flag that makes [`run`](Self::run) and the connections
+    /// return when set.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Accept clients, each served by its own thread, until the stop flag
+    /// is set.
+    pub fn run(&mut self) -> io::Result<()> {
+        while !self.stop.load(Ordering::Acquire) {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(POLL);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let space = self.space.clone();
+            thread::Builder::new()
+                .name("opcua-connection".into())
+                .spawn(move || {
+                    if let Err(e) = Connection::new(&space, stream).and_then(|mut c| c.run()) {
+                        log::debug!("OPC UA connection closed: {}", e);
+                    }
+                })?;
+        }
+        Ok(())
+    }
+}
+
+fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+fn random_bytes(n: usize) -> Vec<u8> {
+    (0..(n + 7) / 8)
+        .flat_map(|_| random_u64().to_le_bytes())
+        .take(n)
+        .collect()
+}
+
+fn protocol_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+struct RequestHeader {
+    token: NodeId,
+    handle: u32,
+}
+
+impl RequestHeader {
+    fn decode(r: &mut Reader) -> Decoded<Self> {
+        let token = r.node_id()?;
+        r.i64()?; // timestamp
+        let handle = r.u32()?;
+        r.u32()?; // return diagnostics
+        r.string()?; // audit entry id
+        r.u32()?; // timeout hint
+        r.extension_object()?;
+        Ok(Self { token, handle })
+    }
+}
+
+struct Session {
+    id: u32,
+    token: NodeId,
+    activated: bool,
+}
+
+/// A client connection, i.e. one secure channel.
+struct Connection<'a> {
+    space: &'a AddressSpace,
+    stream: TcpStream,
+    endpoint_url: String,
+    /// Largest chunk the client accepts.
+    send_limit: usize,
+    channel_id: u32,
+    token_id: u32,
+    sequence: u32,
+    session: Option<Session>,
+}
+
+impl<'a> Connection<'a> {
+    fn new(space: &'a AddressSpace, stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(POLL))?;
+        let endpoint_url = format!("opc.tcp://{}", stream.local_addr()?);
+        Ok(Self {
+            space,
+            stream,
+            endpoint_url,
+            send_limit: BUFFER,
+            channel_id: space.channels.fetch_add(1, Ordering::Relaxed) + 1,
+            token_id: 1,
+            sequence: 0,
+            session: None,
+        })
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        let mut idle = Duration::from_secs(0);
+        let mut header = [0; 8];
+        loop {
+            // wait for the first byte, checking the stop flag
+            match self.stream.read(&mut header[..1]) {
+                Ok(0) => return Ok(()),
+                Ok(_) => idle = Duration::from_secs(0),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    idle += POLL;
+                    if self.space.stop.load(Ordering::Acquire) || idle > IDLE_TIMEOUT {
+                        return Ok(());
+                    }
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+            self.stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+            self.stream.read_exact(&mut header[1..])?;
+            let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+            if !(8..=BUFFER).contains(&size) {
+                self.error(status::BAD_ENCODING_LIMITS_EXCEEDED, "message too large")?;
+                return Err(protocol_error("message too large"));
+            }
+            let mut body = vec![0; size - 8];
+            self.stream.read_exact(&mut body)?;
+            self.stream.set_read_timeout(Some(POLL))?;
+            if header[3] != b'F' {
+                self.error(status::BAD_ENCODING_LIMITS_EXCEEDED, "chunking unsupported")?;
+                return Err(protocol_error("chunked message"));
+            }
+            let keep_open = match &header[..3] {
+                b"HEL" => self.hello(&body),
+                b"OPN" => self.open(&body),
+                b"MSG" => self.message(&body),
+                b"CLO" => Ok(false),
+                _ => {
+                    self.error(status::BAD_TCP_MESSAGE_TYPE_INVALID, "")?;
+                    Ok(false)
+                }
+            }?;
+            if !keep_open {
+                let _ = self.stream.shutdown(Shutdown::Both);
+                return Ok(());
+            }
+        }
+    }
+
+    fn send(&mut self, message_type: &[u8; 3], body: &[u8]) -> io::Result<()> {
+        let mut message = Vec::with_capacity(body.len() + 8);
+        message.extend_from_slice(message_type);
+        message.push(b'F');
+        message.extend_from_slice(&(body.len() as u32 + 8).to_le_bytes());
+        message.extend_from_slice(body);
+        self.stream.write_all(&message)
+    }
+
+    fn error(&mut self, code: u32, reason: &str) -> io::Result<()> {
+        let mut w = Writer::default();
+        w.u32(code);
+        w.string(reason);
+        self.send(b"ERR", &w.buf)
+    }
+
+    fn hello(&mut self, body: &[u8]) -> io::Result<bool> {
+        let mut r = Reader::new(body);
+        let hello: Decoded<(u32, String)> = (|| {
+            r.u32()?; // protocol version
+            let receive_buffer = r.u32()?;
+            r.u32()?; // send buffer
+            r.u32()?; // max message size
+            r.u32()?; // max chunk count
+            Ok((receive_buffer, r.string()?))
+        })();
+        let (receive_buffer, url) = match hello {
+            Ok(hello) => hello,
+            Err(code) => {
+                self.error(code, "invalid hello")?;
+                return Ok(false);
+            }
+        };
+        if !url.is_empty() {
+            self.endpoint_url = url;
+        }
+        self.send_limit = BUFFER.min(receive_buffer as usize);
+        let mut w = Writer::default();
+        w.u32(0);
+        w.u32(BUFFER as u32);
+        w.u32(self.send_limit as u32);
+        w.u32(0);
+        w.u32(0);
+        self.send(b"ACK", &w.buf)?;
+        Ok(true)
+    }
+
+    fn open(&mut self, body: &[u8]) -> io::Result<bool> {
+        let mut r = Reader::new(body);
+        let request: Decoded<_> = (|| {
+            r.u32()?; // channel id
+            let policy = r.string()?;
+            r.byte_string()?; // certificate
+            r.byte_string()?; // thumbprint
+            r.u32()?; // sequence number
+            let request_id = r.u32()?;
+            if r.node_id()? != NodeId::ns0(OPEN_SECURE_CHANNEL) {
+                return Err(status::BAD_SERVICE_UNSUPPORTED);
+            }
+            let header = RequestHeader::decode(&mut r)?;
+            r.u32()?; // protocol version
+            r.u32()?; // request type
+            r.u32()?; // security mode
+            r.byte_string()?; // nonce
+            let lifetime = r.u32()?;
+            Ok((policy, request_id, header, lifetime))
+        })();
+        let (policy, request_id, header, lifetime) = match request {
+            Ok(request) => request,
+            Err(code) => {
+                self.error(code, "invalid open request")?;
+                return Ok(false);
+            }
+        };
+        if policy != POLICY_NONE {
+            self.error(
+                status::BAD_SECURITY_POLICY_REJECTED,
+                "only None is supported",
+            )?;
+            return Ok(false);
+        }
+        let mut w = Writer::default();
+        w.u32(self.channel_id);
+        w.string(POLICY_NONE);
+        w.null();
+        w.null();
+        self.sequence_header(&mut w, request_id);
+        w.node_id(&NodeId::ns0(OPEN_SECURE_CHANNEL + RESPONSE));
+        response_header(&mut w, header.handle, status::GOOD);
+        w.u32(0);
+        w.u32(self.channel_id);
+        w.u32(self.token_id);
+        w.i64(binary::now());
+        w.u32(lifetime.clamp(10_000, 3_600_000));
+        w.byte_string(&[]);
+        self.send(b"OPN", &w.buf)?;
+        Ok(true)
+    }
+
+    fn sequence_header(&mut self, w: &mut Writer, request_id: u32) {
+        self.sequence = self.sequence.wrapping_add(1);
+        w.u32(self.sequence);
+        w.u32(request_id);
+    }
+
+    fn message(&mut self, body: &[u8]) -> io::Result<bool> {
+        let mut r = Reader::new(body);
+        let request: Decoded<_> = (|| {
+            if r.u32()? != self.channel_id {
+                return Err(status::BAD_TCP_MESSAGE_TYPE_INVALID);
+            }
+            r.u32()?; // token id
+            r.u32()?; // sequence number
+            let request_id = r.u32()?;
+            let type_id = r.node_id()?;
+            let header = RequestHeader::decode(&mut r)?;
+            Ok((request_id, type_id, header))
+        })();
+        let (request_id, type_id, header) = match request {
+            Ok(request) => request,
+            Err(code) => {
+                self.error(code, "invalid message")?;
+                return Ok(false);
+            }
+        };
+        let mut body = Writer::default();
+        let result = match type_id {
+            NodeId::Numeric(0, id) => self.service(id, &header, &mut r, &mut body),
+            _ => Err(status::BAD_SERVICE_UNSUPPORTED),
+        };
+        let mut w = Writer::default();
+        w.u32(self.channel_id);
+        w.u32(self.token_id);
+        self.sequence_header(&mut w, request_id);
+        match result {
+            Ok(id) => {
+                w.node_id(&NodeId::ns0(id + RESPONSE));
+                response_header(&mut w, header.handle, status::GOOD);
+                w.bytes(&body.buf);
+            }
+            Err(code) => {
+                w.node_id(&NodeId::ns0(SERVICE_FAULT));
+                response_header(&mut w, header.handle, code);
+            }
+        }
+        if w.buf.len() + 8 > self.send_limit {
+            let mut fault = Writer::default();
+            fault.bytes(&w.buf[..16]);
+            fault.node_id(&NodeId::ns0(SERVICE_FAULT));
+            response_header(
+                &mut fault,
+                header.handle,
+                status::BAD_ENCODING_LIMITS_EXCEEDED,
+            );
+            w = fault;
+        }
+        self.send(b"MSG", &w.buf)?;
+        Ok(true)
+    }
+
+    /// Handle a request and write the response body; returns the request
+    /// type.
+    fn service(
+        &mut self,
+        id: u32,
+        header: &RequestHeader,
+        r: &mut Reader,
+        w: &mut Writer,
+    ) -> Decoded<u32> {
+        match id {
+            GET_ENDPOINTS => {
+                w.array(&[()], |w, _| self.endpoint(w));
+            }
+            FIND_SERVERS => {
+                w.array(&[()], |w, _| self.application(w));
+            }
+            CREATE_SESSION => self.create_session(w),
+            ACTIVATE_SESSION => self.activate_session(header, r, w)?,
+            CLOSE_SESSION => {
+                self.check_session(header, false)?;
+                self.session = None;
+            }
+            BROWSE => {
+                self.check_session(header, true)?;
+                self.space.browse(r, w)?;
+            }
+            READ => {
+                self.check_session(header, true)?;
+                self.space.read(r, w)?;
+            }
+            WRITE => {
+                self.check_session(header, true)?;
+                self.space.write(r, w)?;
+            }
+            _ => return Err(status::BAD_SERVICE_UNSUPPORTED),
+        }
+        Ok(id)
+    }
+
+    fn check_session(&self, header: &RequestHeader, activated: bool) -> Decoded<()> {
+        match &self.session {
+            Some(session) if session.token == header.token => {
+                if activated && !session.activated {
+                    Err(status::BAD_SESSION_NOT_ACTIVATED)
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Err(status::BAD_SESSION_ID_INVALID),
+        }
+    }
+
+    fn application(&self, w: &mut Writer) {
+        w.string(&self.space.application_uri);
+        w.string(PRODUCT_URI);
+        w.localized_text(&self.space.application_name);
+        w.u32(0); // server
+        w.null();
+        w.null();
+        w.array(&[&self.endpoint_url], |w, url| w.string(url));
+    }
+
+    fn endpoint(&self, w: &mut Writer) {
+        w.string(&self.endpoint_url);
+        self.application(w);
+        w.null(); // certificate
+        w.u32(1); // security mode None
+        w.string(POLICY_NONE);
+        w.array(&[()], |w, _| {
+            w.string("anonymous");
+            w.u32(0); // anonymous
+            w.null();
+            w.null();
+            w.null();
+        });
+        w.string(TRANSPORT_PROFILE);
+        w.u8(0);
+    }
+
+    fn create_session(&mut self, w: &mut Writer) {
+        let session = Session {
+            id: self.channel_id,
+            token: NodeId::Opaque(5, NS, random_bytes(16)),
+            activated: false,
+        };
+        w.node_id(&NodeId::Numeric(NS, session.id));
+        w.node_id(&session.token);
+        w.f64(IDLE_TIMEOUT.as_millis() as f64);
+        w.byte_string(&random_bytes(32));
+        w.null(); // certificate
+        w.array(&[()], |w, _| self.endpoint(w));
+        w.i32(0); // software certificates
+        w.null(); // signature algorithm
+        w.null(); // signature
+        w.u32(0);
+        self.session = Some(session);
+    }
+
+    fn activate_session(
+        &mut self,
+        header: &RequestHeader,
+        r: &mut Reader,
+        w: &mut Writer,
+    ) -> Decoded<()> {
+        self.check_session(header, false)?;
+        r.string()?; // signature algorithm
+        r.byte_string()?; // signature
+        r.array(|r| {
+            r.byte_string()?;
+            r.byte_string()
+        })?;
+        r.array(|r| r.string())?; // locale ids
+        let (token_type, _) = r.extension_object()?;
+        if token_type != NodeId::ns0(ANONYMOUS_IDENTITY_TOKEN) && token_type != NodeId::NULL {
+            return Err(status::BAD_IDENTITY_TOKEN_REJECTED);
+        }
+        if let Some(session) = &mut self.session {
+            session.activated = true;
+        }
+        w.byte_string(&random_bytes(32));
+        w.i32(0); // results
+        w.i32(0); // diagnostics
+        Ok(())
+    }
+}
+
+fn response_header(w: &mut Writer, handle: u32, result: u32) {
+    w.i64(binary::now());
+    w.u32(handle);
+    w.u32(result);
+    w.u8(0); // diagnostics
+    w.null(); // string table
+    w.no_extension_object();
+}
+
+impl AddressSpace {
+    fn node(&self, id: &NodeId) -> Option<(usize, &Node)> {
+        self.nodes.iter().enumerate().find(|(_, n)| n.id == *id)
+    }
+
+    fn browse(&self, r: &mut Reader, w: &mut Writer) -> Decoded<()> {
+        r.node_id()?; // view
+        r.i64()?;
+        r.u32()?;
+        r.u32()?; // max references per node
+        let requests = r.array(|r| {
+            let id = r.node_id()?;
+            let direction = r.u32()?;
+            let ref_type = r.node_id()?;
+            let subtypes = r.bool()?;
+            let class_mask = r.u32()?;
+            let result_mask = r.u32()?;
+            Ok((id, direction, ref_type, subtypes, class_mask, result_mask))
+        })?;
+        if requests.is_empty() {
+            return Err(status::BAD_NOTHING_TO_DO);
+        }
+        w.array(
+            &requests,
+            |w, (id, direction, ref_type, subtypes, class_mask, result_mask)| {
+                let index = match self.node(id) {
+                    Some((index, _)) => index,
+                    None => {
+                        w.u32(status::BAD_NODE_ID_UNKNOWN);
+                        w.null();
+                        w.i32(0);
+                        return;
+                    }
+                };
+                let matches_type = |reference| match ref_type {
+                    NodeId::Numeric(0, 0) => true,
+                    NodeId::Numeric(0, REFERENCES)
+                    | NodeId::Numeric(0, HIERARCHICAL_REFERENCES) => *subtypes,
+                    NodeId::Numeric(0, t) => *t == reference,
+                    _ => false,
+                };
+                let mut references = vec![];
+                if *direction != 1 {
+                    for (i, node) in self.nodes.iter().enumerate() {
+                        if let Some((parent, reference)) = node.parent {
+                            if parent == index && matches_type(reference) {
+                                references.push((i, reference, true));
+                            }
+                        }
+                    }
+                }
+                if *direction != 0 {
+                    if let Some((parent, reference)) = self.nodes[index].parent {
+                        if matches_type(reference) {
+                            references.push((parent, reference, false));
+                        }
+                    }
+                }
+                references.retain(|(i, _, _)| {
+                    *class_mask == 0 || class_mask & self.nodes[*i].node_class() != 0
+                });
+                w.u32(status::GOOD);
+                w.null();
+                w.array(&references, |w, (i, reference, forward)| {
+                    let node = &self.nodes[*i];
+                    // fields not in the result mask are left at their defaults
+                    let field = |bit: u32| result_mask & bit != 0;
+                    let ns0_if = |bit, id| NodeId::ns0(if field(bit) { id } else { 0 });
+                    let (ns, name) = if field(0x08) {
+                        (node.namespace(), node.name.as_str())
+                    } else {
+                        (0, "")
+                    };
+                    w.node_id(&ns0_if(0x01, *reference));
+                    w.bool(field(0x02) && *forward);
+                    w.node_id(&node.id);
+                    w.qualified_name(ns, name);
+                    w.localized_text(if field(0x10) { &node.name } else { "" });
+                    w.u32(if field(0x04) { node.node_class() } else { 0 });
+                    w.node_id(&ns0_if(0x20, node.type_def()));
+                });
+            },
+        );
+        w.i32(0); // diagnostics
+        Ok(())
+    }
+
+    fn read(&self, r: &mut Reader, w: &mut Writer) -> Decoded<()> {
+        r.f64()?; // max age
+        let timestamps = r.u32()?;
+        let requests = r.array(|r| {
+            let id = r.node_id()?;
+            let attribute = r.u32()?;
+            let range = r.string()?;
+            r.qualified_name()?; // data encoding
+            Ok((id, attribute, range))
+        })?;
+        if requests.is_empty() {
+            return Err(status::BAD_NOTHING_TO_DO);
+        }
+        let now = binary::now();
+        w.array(&requests, |w, (id, attribute, range)| {
+            let (value, source_time) = match self.node(id) {
+                None => (Err(status::BAD_NODE_ID_UNKNOWN), None),
+                Some(_) if !range.is_empty() => (Err(status::BAD_INDEX_RANGE_INVALID), None),
+                Some((_, node)) => self.read_attribute(node, *attribute),
+            };
+            let server_time = if matches!(timestamps, 1 | 2) {
+                Some(now)
+            } else {
+                None
+            };
+            let source_time = if matches!(timestamps, 0 | 2) && *attribute == ATTR_VALUE {
+                source_time.or(Some(now))
+            } else {
+                None
+            };
+            match value {
+                Ok(value) => w.data_value(Some(&value), status::GOOD, source_time, server_time),
+                Err(code) => w.data_value(None, code, None, server_time),
+            }
+        });
+        w.i32(0); // diagnostics
+        Ok(())
+    }
+
+    /// The value of an attribute, and the source time for values.
+    fn read_attribute(&self, node: &Node, attribute: u32) -> (Decoded<Variant>, Option<i64>) {
+        let value = match (attribute, &node.kind) {
+            (ATTR_NODE_ID, _) => Variant::NodeId(node.id.clone()),
+            (ATTR_NODE_CLASS, _) => Variant::Int32(node.node_class() as i32),
+            (ATTR_BROWSE_NAME, _) => Variant::QualifiedName(node.namespace(), node.name.clone()),
+            (ATTR_DISPLAY_NAME, _) => Variant::LocalizedText(node.name.clone()),
+            (ATTR_DESCRIPTION, _) => Variant::LocalizedText(String::new()),
+            (ATTR_WRITE_MASK, _) | (ATTR_USER_WRITE_MASK, _) => Variant::UInt32(0),
+            (ATTR_EVENT_NOTIFIER, NodeKind::Object { .. }) => Variant::Byte(0),
+            (
+                ATTR_VALUE,
+                NodeKind::Variable {
+                    source, data_type, ..
+                },
+            ) => return self.read_value(source, *data_type),
+            (ATTR_DATA_TYPE, NodeKind::Variable { data_type, .. }) => {
+                Variant::NodeId(NodeId::ns0(*data_type))
+            }
+            (ATTR_VALUE_RANK, NodeKind::Variable { value_rank, .. }) => Variant::Int32(*value_rank),
+            (ATTR_ARRAY_DIMENSIONS, NodeKind::Variable { value_rank, .. }) => {
+                if *value_rank == 1 {
+                    Variant::Array(7, vec![Variant::UInt32(0)])
+                } else {
+                    Variant::Empty
+                }
+            }
+            (ATTR_ACCESS_LEVEL, NodeKind::Variable { writable, .. })
+            | (ATTR_USER_ACCESS_LEVEL, NodeKind::Variable { writable, .. }) => {
+                Variant::Byte(if *writable { 0x03 } else { 0x01 })
+            }
+            (ATTR_MINIMUM_SAMPLING_INTERVAL, NodeKind::Variable { .. }) => Variant::Double(0.0),
+            (ATTR_HISTORIZING, NodeKind::Variable { .. }) => Variant::Boolean(false),
+            _ => return (Err(status::BAD_ATTRIBUTE_ID_INVALID), None),
+        };
+        (Ok(value), None)
+    }
+
+    fn read_value(&self, source: &Source, data_type: u32) -> (Decoded<Variant>, Option<i64>) {
+        let value = match source {
+            Source::NamespaceArray => Variant::Array(
+                12,
+                vec![
+                    Variant::String("http://opcfoundation.org/UA/".into()),
+                    Variant::String(self.application_uri.clone()),
+                ],
+            ),
+            Source::ServerStatus => {
+                let mut w = Writer::default();
+                w.i64(self.start_time);
+                w.i64(binary::now());
+                w.u32(0); // running
+                w.string(PRODUCT_URI);
+                w.string("ethercat-rs");
+                w.string("ethercat");
+                w.string(env!("CARGO_PKG_VERSION"));
+                w.string(env!("CARGO_PKG_VERSION"));
+                w.i64(self.start_time);
+                w.u32(0); // seconds till shutdown
+                w.localized_text("");
+                Variant::ExtensionObject(NodeId::ns0(SERVER_STATUS_DATA_TYPE), w.buf)
+            }
+            Source::StartTime => Variant::DateTime(self.start_time),
+            Source::CurrentTime => Variant::DateTime(binary::now()),
+            Source::ServerState => Variant::Int32(0),
+            Source::Field(field) => {
+                let snapshot = self.snapshots[field.domain].read();
+                let source_time = DC_EPOCH_TICKS + (snapshot.dc_time / 100) as i64;
+                let raw = (field.read)(&snapshot.data);
+                return (Ok(to_variant(data_type, raw)), Some(source_time));
+            }
+            Source::Sdo(sdo) => {
+                let mut data = [0; 8];
+                let mailbox = self.mailbox.as_ref().expect("checked by bind");
+                let mut backend = mailbox.lock().unwrap_or_else(|e| e.into_inner());
+                let raw =
+                    match backend.sdo_upload(sdo.slave, sdo.index, false, &mut data[..sdo.size]) {
+                        Ok(_) => u64::from_le_bytes(data),
+                        Err(e) => {
+                            log::debug!("OPC UA read of SDO {:?} failed: {}", sdo.index, e);
+                            return (Err(status::BAD_COMMUNICATION_ERROR), None);
+                        }
+                    };
+                return (Ok(to_variant(data_type, raw)), None);
+            }
+        };
+        (Ok(value), None)
+    }
+
+    fn write(&self, r: &mut Reader, w: &mut Writer) -> Decoded<()> {
+        let requests = r.array(|r| {
+            let id = r.node_id()?;
+            let attribute = r.u32()?;
+            let range = r.string()?;
+            let value = r.data_value()?;
+            Ok((id, attribute, range, value))
+        })?;
+        if requests.is_empty() {
+            return Err(status::BAD_NOTHING_TO_DO);
+        }
+        w.array(&requests, |w, (id, attribute, range, value)| {
+            let result = match self.node(id) {
+                None => status::BAD_NODE_ID_UNKNOWN,
+                Some((_, node)) => {
+                    match self.write_value(node, *attribute, range, value.as_ref()) {
+                        Ok(()) => status::GOOD,
+                        Err(code) => code,
+                    }
+                }
+            };
+            w.u32(result);
+        });
+        w.i32(0); // diagnostics
+        Ok(())
+    }
+
+    fn write_value(
+        &self,
+        node: &Node,
+        attribute: u32,
+        range: &str,
+        value: Option<&Variant>,
+    ) -> Decoded<()> {
+        let (data_type, sdo) = match &node.kind {
+            NodeKind::Variable {
+                writable: true,
+                data_type,
+                source: Source::Sdo(sdo),
+                ..
+            } if attribute == ATTR_VALUE => (*data_type, sdo),
+            _ => return Err(status::BAD_NOT_WRITABLE),
+        };
+        if !range.is_empty() {
+            return Err(status::BAD_INDEX_RANGE_INVALID);
+        }
+        let (raw, value) = from_variant(data_type, value.ok_or(status::BAD_TYPE_MISMATCH)?)?;
+        if let Some(guard) = &self.guard {
+            if !guard(&node.name, value) {
+                return Err(status::BAD_USER_ACCESS_DENIED);
+            }
+        }
+        let mailbox = self.mailbox.as_ref().ok_or(status::BAD_INTERNAL_ERROR)?;
+        let mut backend = mailbox.lock().unwrap_or_else(|e| e.into_inner());
+        backend
+            .sdo_download(sdo.slave, sdo.index, false, &raw.to_le_bytes()[..sdo.size])
+            .map_err(|e| {
+                log::debug!("OPC UA write of SDO {:?} failed: {}", sdo.index, e);
+                status::BAD_COMMUNICATION_ERROR
+            })
+    }
+}
+
+impl Node {
+    fn node_class(&self) -> u32 {
+        match self.kind {
+            NodeKind::Object { .. } => NODE_CLASS_OBJECT,
+            NodeKind::Variable { .. } => NODE_CLASS_VARIABLE,
+        }
+    }
+
+    fn namespace(&self) -> u16 {
+        match self.id {
+            NodeId::Numeric(ns, _) | NodeId::String(ns, _) | NodeId::Opaque(_, ns, _) => ns,
+        }
+    }
+
+    fn type_def(&self) -> u32 {
+        match self.kind {
+            NodeKind::Object { type_def } | NodeKind::Variable { type_def, .. } => type_def,
+        }
+    }
+}
+
+#[test]
+fn test_opcua_server() {
+    use crate::{
+        backend::{SimMaster, SimSlave},
+        runtime::snapshot_buffer,
+    };
+
+    let (mut writer, reader) = snapshot_buffer(2);
+    writer.publish(1, 1_000_000_000, &[0x2C, 0x01]);
+    let sim = SimMaster::new(vec![SimSlave::new("EL7201", SlaveId::new(2, 0x1C21_3052))
+        .object(SdoIdx::new(0x8010, 1), &[0xE8, 0x03])]);
+    let mut server = OpcUaServer::builder()
+        .snapshot(0.into(), reader)
+        .field(
+            "speed",
+            Field::<i16>::new(0.into(), Offset { byte: 0, bit: 0 }),
+        )
+        .unwrap()
+        .mailbox(sim)
+        .writable_sdo::<u16>("current", 0.into(), SdoIdx::new(0x8010, 1))
+        .write_guard(|_, value| value <= 5000.0)
+        .bind("127.0.0.1:0")
+        .unwrap();
+    let addr = server.local_addr().unwrap();
+    let stop = server.stop_handle();
+    let thread = thread::spawn(move || server.run());
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut roundtrip = |message_type: &[u8; 3], body: &[u8]| {
+        let mut message = message_type.to_vec();
+        message.push(b'F');
+        message.extend_from_slice(&(body.len() as u32 + 8).to_le_bytes());
+        message.extend_from_slice(body);
+        stream.write_all(&message).unwrap();
+        let mut header = [0; 8];
+        stream.read_exact(&mut header).unwrap();
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let mut response = vec![0; size - 8];
+        stream.read_exact(&mut response).unwrap();
+        (header[..3].to_vec(), response)
+    };
+    let request_header = |w: &mut Writer, token: &NodeId| {
+        w.node_id(token);
+        w.i64(0);
+        w.u32(7);
+        w.u32(0);
+        w.null();
+        w.u32(0);
+        w.no_extension_object();
+    };
+
+    let mut w = Writer::default();
+    w.array(&[0u32, 8192, 8192, 0, 0], |w, v| w.u32(*v));
+    w.string("opc.tcp://plc:4840");
+    let (message_type, _) = roundtrip(b"HEL", &w.buf[4..]);
+    assert_eq!(message_type, b"ACK");
+
+    let mut w = Writer::default();
+    w.u32(0);
+    w.string(POLICY_NONE);
+    w.null();
+    w.null();
+    w.u32(1);
+    w.u32(1);
+    w.node_id(&NodeId::ns0(OPEN_SECURE_CHANNEL));
+    request_header(&mut w, &NodeId::NULL);
+    w.array(&[0u32, 0, 1], |w, v| w.u32(*v));
+    w.null();
+    w.u32(60_000);
+    let (message_type, response) = roundtrip(b"OPN", &w.buf);
+    assert_eq!(message_type, b"OPN");
+    let channel = u32::from_le_bytes([response[0], response[1], response[2], response[3]]);
+
+    // a service call; returns the response type and the body after the
+    // response header
+    let mut call = |type_id: u32, token: &NodeId, body: &[u8]| {
+        let mut w = Writer::default();
+        w.u32(channel);
+        w.u32(1);
+        w.u32(2);
+        w.u32(3);
+        w.node_id(&NodeId::ns0(type_id));
+        request_header(&mut w, token);
+        w.bytes(body);
+        let (message_type, response) = roundtrip(b"MSG", &w.buf);
+        assert_eq!(message_type, b"MSG");
+        let mut r = Reader::new(&response[16..]);
+        let response_type = r.node_id().unwrap();
+        r.i64().unwrap();
+        assert_eq!(r.u32().unwrap(), 7);
+        let result = r.u32().unwrap();
+        r.u8().unwrap();
+        r.array(|r| r.string()).unwrap();
+        r.extension_object().unwrap();
+        (response_type, result, r.rest().to_vec())
+    };
+
+    // services need an activated session
+    let (response_type, result, _) = call(READ, &NodeId::NULL, &[]);
+    assert_eq!(response_type, NodeId::ns0(SERVICE_FAULT));
+    assert_eq!(result, status::BAD_SESSION_ID_INVALID);
+
+    let (_, result, body) = call(CREATE_SESSION, &NodeId::NULL, &[]);
+    assert_eq!(result, status::GOOD);
+    let mut r = Reader::new(&body);
+    r.node_id().unwrap();
+    let token = r.node_id().unwrap();
+    let mut w = Writer::default();
+    w.null();
+    w.null();
+    w.i32(0);
+    w.i32(0);
+    w.extension_object(&NodeId::ns0(ANONYMOUS_IDENTITY_TOKEN), &[]);
+    let (response_type, result, _) = call(ACTIVATE_SESSION, &token, &w.buf);
+    assert_eq!(response_type, NodeId::ns0(ACTIVATE_SESSION + RESPONSE));
+    assert_eq!(result, status::GOOD);
+
+    // browse the EtherCAT folder
+    let mut w = Writer::default();
+    w.node_id(&NodeId::NULL);
+    w.i64(0);
+    w.u32(0);
+    w.u32(0);
+    w.array(&[()], |w, _| {
+        w.node_id(&NodeId::String(NS, "EtherCAT".into()));
+        w.u32(0);
+        w.node_id(&NodeId::ns0(HIERARCHICAL_REFERENCES));
+        w.bool(true);
+        w.u32(0);
+        w.u32(0x3F);
+    });
+    let (_, _, body) = call(BROWSE, &token, &w.buf);
+    let mut r = Reader::new(&body);
+    let names = r
+        .array(|r| {
+            assert_eq!(r.u32()?, status::GOOD);
+            r.byte_string()?;
+            r.array(|r| {
+                r.node_id()?;
+                r.bool()?;
+                let id = r.expanded_node_id()?;
+                r.qualified_name()?;
+                r.localized_text()?;
+                assert_eq!(r.u32()?, NODE_CLASS_VARIABLE);
+                r.expanded_node_id()?;
+                Ok(id)
+            })
+        })
+        .unwrap();
+    assert_eq!(
+        names,
+        [vec![
+            NodeId::String(NS, "speed".into()),
+            NodeId::String(NS, "current".into())
+        ]]
+    );
+
+    // read the field, the SDO and an unknown node
+    let mut read = Writer::default();
+    read.f64(0.0);
+    read.u32(3);
+    read.array(&["speed", "current", "torque"], |w, name| {
+        w.node_id(&NodeId::String(NS, (*name).into()));
+        w.u32(ATTR_VALUE);
+        w.null();
+        w.qualified_name(0, "");
+    });
+    let values = |body: Vec<u8>| Reader::new(&body).array(|r| r.data_value()).unwrap();
+    assert_eq!(
+        values(call(READ, &token, &read.buf).2),
+        [Some(Variant::Int16(300)), Some(Variant::UInt16(1000)), None]
+    );
+
+    // writes: allowed, denied by the guard, and not writable
+    let mut w = Writer::default();
+    w.array(
+        &[
+            ("current", Variant::UInt16(2000)),
+            ("current", Variant::UInt16(6000)),
+            ("current", Variant::Double(1.0)),
+            ("speed", Variant::Int16(0)),
+        ],
+        |w, (name, value)| {
+            w.node_id(&NodeId::String(NS, (*name).into()));
+            w.u32(ATTR_VALUE);
+            w.null();
+            w.data_value(Some(value), status::GOOD, None, None);
+        },
+    );
+    let (_, _, body) = call(WRITE, &token, &w.buf);
+    let results = Reader::new(&body).array(|r| r.u32()).unwrap();
+    assert_eq!(
+        results,
+        [
+            status::GOOD,
+            status::BAD_USER_ACCESS_DENIED,
+            status::BAD_TYPE_MISMATCH,
+            status::BAD_NOT_WRITABLE
+        ]
+    );
+    assert_eq!(
+        values(call(READ, &token, &read.buf).2)[1],
+        Some(Variant::UInt16(2000))
+    );
+
+    stop.store(true, Ordering::Release);
+    thread.join().unwrap().unwrap();
+}
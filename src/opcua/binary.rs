@@ -0,0 +1,538 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! OPC UA binary encoding of the built-in types used by the server.
+
+use std::{
+    convert::TryInto,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Status codes, see OPC UA part 6, annex A.
+pub mod status {
+    pub const GOOD: u32 = 0;
+    pub const BAD_INTERNAL_ERROR: u32 = 0x8002_0000;
+    pub const BAD_COMMUNICATION_ERROR: u32 = 0x8005_0000;
+    pub const BAD_DECODING_ERROR: u32 = 0x8007_0000;
+    pub const BAD_ENCODING_LIMITS_EXCEEDED: u32 = 0x8008_0000;
+    pub const BAD_SERVICE_UNSUPPORTED: u32 = 0x800B_0000;
+    pub const BAD_NOTHING_TO_DO: u32 = 0x800F_0000;
+    pub const BAD_USER_ACCESS_DENIED: u32 = 0x801F_0000;
+    pub const BAD_IDENTITY_TOKEN_REJECTED: u32 = 0x8021_0000;
+    pub const BAD_SESSION_ID_INVALID: u32 = 0x8025_0000;
+    pub const BAD_SESSION_NOT_ACTIVATED: u32 = 0x8027_0000;
+    pub const BAD_NODE_ID_UNKNOWN: u32 = 0x8034_0000;
+    pub const BAD_ATTRIBUTE_ID_INVALID: u32 = 0x8035_0000;
+    pub const BAD_INDEX_RANGE_INVALID: u32 = 0x8036_0000;
+    pub const BAD_NOT_WRITABLE: u32 = 0x803B_0000;
+    pub const BAD_OUT_OF_RANGE: u32 = 0x803C_0000;
+    pub const BAD_SECURITY_POLICY_REJECTED: u32 = 0x8055_0000;
+    pub const BAD_TYPE_MISMATCH: u32 = 0x8074_0000;
+    pub const BAD_TCP_MESSAGE_TYPE_INVALID: u32 = 0x807E_0000;
+}
+
+/// Result of decoding, failing with a status code.
+pub type Decoded<T> = std::result::Result<T, u32>;
+
+/// 100 ns ticks from 1601-01-01 to 1970-01-01.
+const UNIX_EPOCH_TICKS: i64 = 116_444_736_000_000_000;
+
+/// The current time as an OPC UA `DateTime`.
+pub fn now() -> i64 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    UNIX_EPOCH_TICKS + (since_epoch.as_nanos() / 100) as i64
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NodeId {
+    Numeric(u16, u32),
+    String(u16, String),
+    /// A GUID (kind 4) or opaque (kind 5) identifier.
+    Opaque(u8, u16, Vec<u8>),
+}
+
+impl NodeId {
+    pub const NULL: NodeId = NodeId::Numeric(0, 0);
+
+    pub const fn ns0(id: u32) -> Self {
+        NodeId::Numeric(0, id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Variant {
+    Empty,
+    Boolean(bool),
+    SByte(i8),
+    Byte(u8),
+    Int16(i16),
+    UInt16(u16),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    DateTime(i64),
+    ByteString(Vec<u8>),
+    NodeId(NodeId),
+    StatusCode(u32),
+    QualifiedName(u16, String),
+    LocalizedText(String),
+    ExtensionObject(NodeId, Vec<u8>),
+    /// One-dimensional array with the type id of the elements.
+    Array(u8, Vec<Variant>),
+}
+
+impl Variant {
+    pub fn type_id(&self) -> u8 {
+        match self {
+            Variant::Empty => 0,
+            Variant::Boolean(_) => 1,
+            Variant::SByte(_) => 2,
+            Variant::Byte(_) => 3,
+            Variant::Int16(_) => 4,
+            Variant::UInt16(_) => 5,
+            Variant::Int32(_) => 6,
+            Variant::UInt32(_) => 7,
+            Variant::Int64(_) => 8,
+            Variant::UInt64(_) => 9,
+            Variant::Float(_) => 10,
+            Variant::Double(_) => 11,
+            Variant::String(_) => 12,
+            Variant::DateTime(_) => 13,
+            Variant::ByteString(_) => 15,
+            Variant::NodeId(_) => 17,
+            Variant::StatusCode(_) => 19,
+            Variant::QualifiedName(..) => 20,
+            Variant::LocalizedText(_) => 21,
+            Variant::ExtensionObject(..) => 22,
+            Variant::Array(ty, _) => *ty,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i128> {
+        Some(match *self {
+            Variant::SByte(v) => v.into(),
+            Variant::Byte(v) => v.into(),
+            Variant::Int16(v) => v.into(),
+            Variant::UInt16(v) => v.into(),
+            Variant::Int32(v) => v.into(),
+            Variant::UInt32(v) => v.into(),
+            Variant::Int64(v) => v.into(),
+            Variant::UInt64(v) => v.into(),
+            _ => return None,
+        })
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Variant::Float(v) => Some(v.into()),
+            Variant::Double(v) => Some(v),
+            _ => self.as_integer().map(|v| v as f64),
+        }
+    }
+}
+
+pub struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Decoded<&'a [u8]> {
+        if self.buf.len() < n {
+            return Err(status::BAD_DECODING_ERROR);
+        }
+        let (bytes, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    #[cfg(test)]
+    pub fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.buf)
+    }
+
+    fn fixed<const N: usize>(&mut self) -> Decoded<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().expect("N bytes"))
+    }
+
+    pub fn u8(&mut self) -> Decoded<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Decoded<bool> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Decoded<u16> {
+        self.fixed().map(u16::from_le_bytes)
+    }
+
+    pub fn u32(&mut self) -> Decoded<u32> {
+        self.fixed().map(u32::from_le_bytes)
+    }
+
+    pub fn i32(&mut self) -> Decoded<i32> {
+        self.fixed().map(i32::from_le_bytes)
+    }
+
+    pub fn u64(&mut self) -> Decoded<u64> {
+        self.fixed().map(u64::from_le_bytes)
+    }
+
+    pub fn i64(&mut self) -> Decoded<i64> {
+        self.fixed().map(i64::from_le_bytes)
+    }
+
+    pub fn f64(&mut self) -> Decoded<f64> {
+        self.fixed().map(f64::from_le_bytes)
+    }
+
+    /// A byte string, empty if null.
+    pub fn byte_string(&mut self) -> Decoded<Vec<u8>> {
+        let len = self.i32()?;
+        if len < 0 {
+            return Ok(vec![]);
+        }
+        Ok(self.bytes(len as usize)?.to_vec())
+    }
+
+    /// A string, empty if null.
+    pub fn string(&mut self) -> Decoded<String> {
+        String::from_utf8(self.byte_string()?).map_err(|_| status::BAD_DECODING_ERROR)
+    }
+
+    /// An array, empty if null.
+    pub fn array<T>(&mut self, mut f: impl FnMut(&mut Self) -> Decoded<T>) -> Decoded<Vec<T>> {
+        let len = self.i32()?;
+        // every element takes at least one byte
+        if len > self.buf.len() as i32 {
+            return Err(status::BAD_DECODING_ERROR);
+        }
+        (0..len).map(|_| f(self)).collect()
+    }
+
+    fn node_id_body(&mut self, kind: u8) -> Decoded<NodeId> {
+        Ok(match kind & 0x0F {
+            0 => NodeId::Numeric(0, self.u8()?.into()),
+            1 => {
+                let ns = self.u8()?.into();
+                NodeId::Numeric(ns, self.u16()?.into())
+            }
+            2 => NodeId::Numeric(self.u16()?, self.u32()?),
+            3 => NodeId::String(self.u16()?, self.string()?),
+            4 => NodeId::Opaque(4, self.u16()?, self.bytes(16)?.to_vec()),
+            5 => NodeId::Opaque(5, self.u16()?, self.byte_string()?),
+            _ => return Err(status::BAD_DECODING_ERROR),
+        })
+    }
+
+    pub fn node_id(&mut self) -> Decoded<NodeId> {
+        let kind = self.u8()?;
+        self.node_id_body(kind)
+    }
+
+    /// An expanded node id; the namespace URI and server index are skipped.
+    pub fn expanded_node_id(&mut self) -> Decoded<NodeId> {
+        let kind = self.u8()?;
+        let id = self.node_id_body(kind)?;
+        if kind & 0x80 != 0 {
+            self.string()?;
+        }
+        if kind & 0x40 != 0 {
+            self.u32()?;
+        }
+        Ok(id)
+    }
+
+    pub fn qualified_name(&mut self) -> Decoded<(u16, String)> {
+        Ok((self.u16()?, self.string()?))
+    }
+
+    /// A localized text; the locale is skipped.
+    pub fn localized_text(&mut self) -> Decoded<String> {
+        let mask = self.u8()?;
+        if mask & 0x01 != 0 {
+            self.string()?;
+        }
+        if mask & 0x02 != 0 {
+            return self.string();
+        }
+        Ok(String::new())
+    }
+
+    /// An extension object with its type id and binary body.
+    pub fn extension_object(&mut self) -> Decoded<(NodeId, Vec<u8>)> {
+        let type_id = self.node_id()?;
+        let body = match self.u8()? {
+            0 => vec![],
+            1 => self.byte_string()?,
+            2 => self.string()?.into_bytes(),
+            _ => return Err(status::BAD_DECODING_ERROR),
+        };
+        Ok((type_id, body))
+    }
+
+    pub fn variant(&mut self) -> Decoded<Variant> {
+        let mask = self.u8()?;
+        let ty = mask & 0x3F;
+        if mask & 0x80 == 0 {
+            return self.scalar(ty);
+        }
+        let items = self.array(|r| r.scalar(ty))?;
+        if mask & 0x40 != 0 {
+            self.array(|r| r.i32())?;
+        }
+        Ok(Variant::Array(ty, items))
+    }
+
+    fn scalar(&mut self, ty: u8) -> Decoded<Variant> {
+        Ok(match ty {
+            0 => Variant::Empty,
+            1 => Variant::Boolean(self.bool()?),
+            2 => Variant::SByte(self.u8()? as i8),
+            3 => Variant::Byte(self.u8()?),
+            4 => Variant::Int16(self.u16()? as i16),
+            5 => Variant::UInt16(self.u16()?),
+            6 => Variant::Int32(self.i32()?),
+            7 => Variant::UInt32(self.u32()?),
+            8 => Variant::Int64(self.i64()?),
+            9 => Variant::UInt64(self.u64()?),
+            10 => Variant::Float(f32::from_le_bytes(self.fixed()?)),
+            11 => Variant::Double(self.f64()?),
+            12 => Variant::String(self.string()?),
+            13 => Variant::DateTime(self.i64()?),
+            15 => Variant::ByteString(self.byte_string()?),
+            17 => Variant::NodeId(self.node_id()?),
+            18 => Variant::NodeId(self.expanded_node_id()?),
+            19 => Variant::StatusCode(self.u32()?),
+            20 => {
+                let (ns, name) = self.qualified_name()?;
+                Variant::QualifiedName(ns, name)
+            }
+            21 => Variant::LocalizedText(self.localized_text()?),
+            22 => {
+                let (type_id, body) = self.extension_object()?;
+                Variant::ExtensionObject(type_id, body)
+            }
+            _ => return Err(status::BAD_DECODING_ERROR),
+        })
+    }
+
+    /// A data value; only the value is kept.
+    pub fn data_value(&mut self) -> Decoded<Option<Variant>> {
+        let mask = self.u8()?;
+        let value = if mask & 0x01 != 0 {
+            Some(self.variant()?)
+        } else {
+            None
+        };
+        if mask & 0x02 != 0 {
+            self.u32()?;
+        }
+        if mask & 0x04 != 0 {
+            self.i64()?;
+        }
+        if mask & 0x10 != 0 {
+            self.u16()?;
+        }
+        if mask & 0x08 != 0 {
+            self.i64()?;
+        }
+        if mask & 0x20 != 0 {
+            self.u16()?;
+        }
+        Ok(value)
+    }
+}
+
+#[derive(Default)]
+pub struct Writer {
+    pub buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn bool(&mut self, v: bool) {
+        self.buf.push(v as u8);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn i32(&mut self, v: i32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn i64(&mut self, v: i64) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn f64(&mut self, v: f64) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn byte_string(&mut self, v: &[u8]) {
+        self.i32(v.len() as i32);
+        self.bytes(v);
+    }
+
+    pub fn string(&mut self, v: &str) {
+        self.byte_string(v.as_bytes());
+    }
+
+    /// A null string or byte string.
+    pub fn null(&mut self) {
+        self.i32(-1);
+    }
+
+    pub fn array<T>(&mut self, items: &[T], mut f: impl FnMut(&mut Self, &T)) {
+        self.i32(items.len() as i32);
+        for item in items {
+            f(self, item);
+        }
+    }
+
+    pub fn node_id(&mut self, id: &NodeId) {
+        match id {
+            NodeId::Numeric(0, v) if *v < 0x100 => {
+                self.u8(0);
+                self.u8(*v as u8);
+            }
+            NodeId::Numeric(ns, v) if *ns < 0x100 && *v < 0x1_0000 => {
+                self.u8(1);
+                self.u8(*ns as u8);
+                self.u16(*v as u16);
+            }
+            NodeId::Numeric(ns, v) => {
+                self.u8(2);
+                self.u16(*ns);
+                self.u32(*v);
+            }
+            NodeId::String(ns, v) => {
+                self.u8(3);
+                self.u16(*ns);
+                self.string(v);
+            }
+            NodeId::Opaque(4, ns, v) => {
+                self.u8(4);
+                self.u16(*ns);
+                self.bytes(v);
+            }
+            NodeId::Opaque(kind, ns, v) => {
+                self.u8(*kind);
+                self.u16(*ns);
+                self.byte_string(v);
+            }
+        }
+    }
+
+    pub fn qualified_name(&mut self, ns: u16, name: &str) {
+        self.u16(ns);
+        self.string(name);
+    }
+
+    pub fn localized_text(&mut self, text: &str) {
+        if text.is_empty() {
+            self.u8(0);
+        } else {
+            self.u8(0x02);
+            self.string(text);
+        }
+    }
+
+    pub fn extension_object(&mut self, type_id: &NodeId, body: &[u8]) {
+        self.node_id(type_id);
+        self.u8(1);
+        self.byte_string(body);
+    }
+
+    /// An empty extension object.
+    pub fn no_extension_object(&mut self) {
+        self.node_id(&NodeId::NULL);
+        self.u8(0);
+    }
+
+    pub fn variant(&mut self, v: &Variant) {
+        match v {
+            Variant::Array(ty, items) => {
+                self.u8(ty | 0x80);
+                self.array(items, |w, item| w.scalar(item));
+            }
+            _ => {
+                self.u8(v.type_id());
+                self.scalar(v);
+            }
+        }
+    }
+
+    fn scalar(&mut self, v: &Variant) {
+        match v {
+            Variant::Empty | Variant::Array(..) => {}
+            Variant::Boolean(v) => self.bool(*v),
+            Variant::SByte(v) => self.u8(*v as u8),
+            Variant::Byte(v) => self.u8(*v),
+            Variant::Int16(v) => self.bytes(&v.to_le_bytes()),
+            Variant::UInt16(v) => self.u16(*v),
+            Variant::Int32(v) => self.i32(*v),
+            Variant::UInt32(v) | Variant::StatusCode(v) => self.u32(*v),
+            Variant::Int64(v) | Variant::DateTime(v) => self.i64(*v),
+            Variant::UInt64(v) => self.bytes(&v.to_le_bytes()),
+            Variant::Float(v) => self.bytes(&v.to_le_bytes()),
+            Variant::Double(v) => self.f64(*v),
+            Variant::String(v) => self.string(v),
+            Variant::ByteString(v) => self.byte_string(v),
+            Variant::NodeId(v) => self.node_id(v),
+            Variant::QualifiedName(ns, name) => self.qualified_name(*ns, name),
+            Variant::LocalizedText(v) => self.localized_text(v),
+            Variant::ExtensionObject(type_id, body) => self.extension_object(type_id, body),
+        }
+    }
+
+    /// A data value with the given parts.
+    pub fn data_value(
+        &mut self,
+        value: Option<&Variant>,
+        status: u32,
+        source_time: Option<i64>,
+        server_time: Option<i64>,
+    ) {
+        let mask = value.map_or(0, |_| 0x01)
+            | if status != status::GOOD { 0x02 } else { 0 }
+            | source_time.map_or(0, |_| 0x04)
+            | server_time.map_or(0, |_| 0x08);
+        self.u8(mask);
+        if let Some(value) = value {
+            self.variant(value);
+        }
+        if status != status::GOOD {
+            self.u32(status);
+        }
+        if let Some(time) = source_time {
+            self.i64(time);
+        }
+        if let Some(time) = server_time {
+            self.i64(time);
+        }
+    }
+}
@@ -0,0 +1,562 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Python bindings, enabled with the `python` feature.
+//!
+//! [`module`] fills a Python module with the `Master` and `Field` classes.
+//! An extension crate built as `cdylib` (with the `extension-module` feature
+//! of PyO3) only has to wrap it:
+//!
+//! ```ignore
+//! #[pymodule]
+//! fn ethercat(py: Python, m: &PyModule) -> PyResult<()> {
+//!     ethercat::python::module(py, m)
+//! }
+//! ```
+//!
+//! Process images are exchanged as `bytes`, which NumPy can view without a
+//! copy with `numpy.frombuffer`. The cyclic loop is meant to stay in Rust;
+//! the bindings are for configuring, inspecting and scripting the bus.
+
+use crate::{
+    field::Field,
+    master::{Active, Config, Master, MasterAccess},
+    types::*,
+};
+use pyo3::{
+    exceptions::{PyOSError, PyRuntimeError, PyValueError},
+    prelude::*,
+    types::{PyByteArray, PyBytes, PyDict},
+};
+
+impl From<Error> for PyErr {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(_) | Error::Ioctl { .. } => PyOSError::new_err(e.to_string()),
+            e => PyRuntimeError::new_err(e.to_string()),
+        }
+    }
+}
+
+/// Register the classes in a Python module.
+pub fn module(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyMaster>()?;
+    m.add_class::<PyField>()?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dtype {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl Dtype {
+    const NAMES: [(&'static str, Dtype); 11] = [
+        ("bool", Dtype::Bool),
+        ("uint8", Dtype::U8),
+        ("uint16", Dtype::U16),
+        ("uint32", Dtype::U32),
+        ("uint64", Dtype::U64),
+        ("int8", Dtype::I8),
+        ("int16", Dtype::I16),
+        ("int32", Dtype::I32),
+        ("int64", Dtype::I64),
+        ("float32", Dtype::F32),
+        ("float64", Dtype::F64),
+    ];
+
+    /// Parse a NumPy style type name.
+    fn parse(name: &str) -> PyResult<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, t)| *t)
+            .ok_or_else(|| PyValueError::new_err(format!("unsupported dtype {:?}", name)))
+    }
+
+    fn name(self) -> &'static str {
+        Self::NAMES.iter().find(|(_, t)| *t == self).unwrap().0
+    }
+}
+
+/// Apply `$f` to a `Field<T>` of the type given by `$dtype`.
+macro_rules! with_field {
+    ($dtype:expr, $domain:expr, $offset:expr, |$field:ident| $f:expr) => {
+        match $dtype {
+            Dtype::Bool => {
+                let $field = Field::<bool>::new($domain, $offset);
+                $f
+            }
+            Dtype::U8 => {
+                let $field = Field::<u8>::new($domain, $offset);
+                $f
+            }
+            Dtype::U16 => {
+                let $field = Field::<u16>::new($domain, $offset);
+                $f
+            }
+            Dtype::U32 => {
+                let $field = Field::<u32>::new($domain, $offset);
+                $f
+            }
+            Dtype::U64 => {
+                let $field = Field::<u64>::new($domain, $offset);
+                $f
+            }
+            Dtype::I8 => {
+                let $field = Field::<i8>::new($domain, $offset);
+                $f
+            }
+            Dtype::I16 => {
+                let $field = Field::<i16>::new($domain, $offset);
+                $f
+            }
+            Dtype::I32 => {
+                let $field = Field::<i32>::new($domain, $offset);
+                $f
+            }
+            Dtype::I64 => {
+                let $field = Field::<i64>::new($domain, $offset);
+                $f
+            }
+            Dtype::F32 => {
+                let $field = Field::<f32>::new($domain, $offset);
+                $f
+            }
+            Dtype::F64 => {
+                let $field = Field::<f64>::new($domain, $offset);
+                $f
+            }
+        }
+    };
+}
+
+/// A typed PDO entry in the process image of a domain.
+#[pyclass(name = "Field")]
+#[derive(Debug, Clone, Copy)]
+pub struct PyField {
+    domain: DomainIdx,
+    offset: Offset,
+    dtype: Dtype,
+}
+
+#[pymethods]
+impl PyField {
+    #[new]
+    fn new(domain: usize, byte: usize, bit: u32, dtype: &str) -> PyResult<Self> {
+        Ok(Self {
+            domain: DomainIdx::from(domain),
+            offset: Offset { byte, bit },
+            dtype: Dtype::parse(dtype)?,
+        })
+    }
+
+    #[getter]
+    fn domain(&self) -> usize {
+        self.domain.into()
+    }
+
+    #[getter]
+    fn byte(&self) -> usize {
+        self.offset.byte
+    }
+
+    #[getter]
+    fn bit(&self) -> u32 {
+        self.offset.bit
+    }
+
+    #[getter]
+    fn dtype(&self) -> &'static str {
+        self.dtype.name()
+    }
+
+    /// Read the value from a process image.
+    fn get(&self, py: Python, data: &[u8]) -> PyResult<PyObject> {
+        with_field!(self.dtype, self.domain, self.offset, |field| {
+            if data.len() < field.end() {
+                return Err(PyValueError::new_err("process image too short"));
+            }
+            Ok(field.get(data).into_py(py))
+        })
+    }
+
+    /// Write the value into a process image.
+    fn set(&self, data: &PyByteArray, value: &PyAny) -> PyResult<()> {
+        // SAFETY: the buffer is not resized while we hold the GIL
+        let data = unsafe { data.as_bytes_mut() };
+        with_field!(self.dtype, self.domain, self.offset, |field| {
+            if data.len() < field.end() {
+                return Err(PyValueError::new_err("process image too short"));
+            }
+            field.set(data, value.extract()?);
+            Ok(())
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Field(domain={}, byte={}, bit={}, dtype={:?})",
+            usize::from(self.domain),
+            self.offset.byte,
+            self.offset.bit,
+            self.dtype.name()
+        )
+    }
+}
+
+/// An EtherCAT master.
+#[pyclass(name = "Master")]
+pub struct PyMaster {
+    /// `None` once a failed deactivation dropped the master.
+    master: Option<Phase>,
+}
+
+/// The master in the phase it is in, which Python only knows at runtime.
+enum Phase {
+    Config(Master<Config>),
+    Active(Master<Active>),
+}
+
+/// Evaluate `$e` with the master of either phase as `$m`.
+macro_rules! either {
+    ($phase:expr, $m:ident => $e:expr) => {
+        match $phase {
+            Phase::Config($m) => $e,
+            Phase::Active($m) => $e,
+        }
+    };
+}
+
+impl PyMaster {
+    fn phase(&self) -> PyResult<&Phase> {
+        self.master.as_ref().ok_or_else(closed)
+    }
+
+    fn phase_mut(&mut self) -> PyResult<&mut Phase> {
+        self.master.as_mut().ok_or_else(closed)
+    }
+
+    fn config(&mut self) -> PyResult<&mut Master<Config>> {
+        match self.phase_mut()? {
+            Phase::Config(master) => Ok(master),
+            Phase::Active(_) => Err(PyRuntimeError::new_err("Master is activated")),
+        }
+    }
+
+    fn active(&mut self) -> PyResult<&mut Master<Active>> {
+        match self.phase_mut()? {
+            Phase::Active(master) => Ok(master),
+            Phase::Config(_) => Err(Error::NotActivated.into()),
+        }
+    }
+}
+
+fn closed() -> PyErr {
+    PyRuntimeError::new_err("Master is closed")
+}
+
+fn al_state(name: &str) -> PyResult<AlState> {
+    match name {
+        "Init" => Ok(AlState::Init),
+        "PreOp" => Ok(AlState::PreOp),
+        "Boot" => Ok(AlState::Boot),
+        "SafeOp" => Ok(AlState::SafeOp),
+        "Op" => Ok(AlState::Op),
+        _ => Err(PyValueError::new_err(format!(
+            "unknown AL state {:?}",
+            name
+        ))),
+    }
+}
+
+#[pymethods]
+impl PyMaster {
+    #[new]
+    #[pyo3(signature = (index = 0, read_only = false))]
+    fn new(index: MasterIdx, read_only: bool) -> PyResult<Self> {
+        let access = if read_only {
+            MasterAccess::ReadOnly
+        } else {
+            MasterAccess::ReadWrite
+        };
+        Ok(Self {
+            master: Some(Phase::Config(Master::open(index, access)?)),
+        })
+    }
+
+    fn reserve(&mut self) -> PyResult<()> {
+        Ok(self.config()?.reserve()?)
+    }
+
+    fn create_domain(&mut self) -> PyResult<usize> {
+        Ok(self.config()?.create_domain()?.into())
+    }
+
+    /// Create a slave configuration and return its index.
+    fn configure_slave(
+        &mut self,
+        alias: u16,
+        position: u16,
+        vendor_id: u32,
+        product_code: u32,
+    ) -> PyResult<SlaveConfigIdx> {
+        let addr = if alias == 0 {
+            SlaveAddr::ByPos(position)
+        } else {
+            SlaveAddr::ByAlias(alias, position)
+        };
+        let config = self
+            .config()?
+            .configure_slave(addr, SlaveId::new(vendor_id, product_code))?;
+        Ok(config.index())
+    }
+
+    /// Register a PDO entry of a slave configuration in a domain.
+    fn register_field(
+        &mut self,
+        config: SlaveConfigIdx,
+        index: u16,
+        subindex: u8,
+        domain: usize,
+        dtype: &str,
+    ) -> PyResult<PyField> {
+        let dtype = Dtype::parse(dtype)?;
+        let domain = DomainIdx::from(domain);
+        let offset = self
+            .config()?
+            .slave_config(config)
+            .register_pdo_entry(PdoEntryIdx::new(index, subindex), domain)?;
+        Ok(PyField {
+            domain,
+            offset,
+            dtype,
+        })
+    }
+
+    /// Add an SDO to be downloaded when the slave is configured.
+    fn config_sdo(
+        &mut self,
+        config: SlaveConfigIdx,
+        index: u16,
+        subindex: u8,
+        data: &[u8],
+    ) -> PyResult<()> {
+        Ok(self
+            .config()?
+            .slave_config(config)
+            .add_sdo(SdoIdx::new(index, subindex), &data)?)
+    }
+
+    #[pyo3(signature = (config, assign_activate, sync0_cycle_time, sync0_shift_time = 0, sync1_cycle_time = 0, sync1_shift_time = 0))]
+    fn config_dc(
+        &mut self,
+        config: SlaveConfigIdx,
+        assign_activate: u16,
+        sync0_cycle_time: u32,
+        sync0_shift_time: i32,
+        sync1_cycle_time: u32,
+        sync1_shift_time: i32,
+    ) -> PyResult<()> {
+        Ok(self.config()?.slave_config(config).config_dc(
+            assign_activate,
+            sync0_cycle_time,
+            sync0_shift_time,
+            sync1_cycle_time,
+            sync1_shift_time,
+        )?)
+    }
+
+    fn activate(&mut self) -> PyResult<()> {
+        match self.master.take() {
+            Some(Phase::Config(master)) => match master.activate() {
+                Ok(master) => self.master = Some(Phase::Active(master)),
+                Err(e) => {
+                    self.master = Some(Phase::Config(*e.master));
+                    return Err(e.error.into());
+                }
+            },
+            phase => self.master = phase,
+        }
+        Ok(())
+    }
+
+    fn deactivate(&mut self) -> PyResult<()> {
+        match self.master.take() {
+            Some(Phase::Active(master)) => match master.deactivate() {
+                Ok(master) => self.master = Some(Phase::Config(master)),
+                Err(e) => {
+                    self.master = Some(Phase::Active(*e.master));
+                    return Err(e.error.into());
+                }
+            },
+            phase => self.master = phase,
+        }
+        Ok(())
+    }
+
+    fn set_application_time(&mut self, app_time: u64) -> PyResult<()> {
+        Ok(either!(self.phase_mut()?, m => m.set_application_time(app_time))?)
+    }
+
+    fn sync_reference_clock(&mut self) -> PyResult<()> {
+        Ok(either!(self.phase_mut()?, m => m.sync_reference_clock())?)
+    }
+
+    fn sync_slave_clocks(&mut self) -> PyResult<()> {
+        Ok(either!(self.phase_mut()?, m => m.sync_slave_clocks())?)
+    }
+
+    fn receive(&mut self) -> PyResult<()> {
+        Ok(self.active()?.receive()?)
+    }
+
+    fn send(&mut self) -> PyResult<usize> {
+        Ok(self.active()?.send()?)
+    }
+
+    fn process(&mut self, domain: usize) -> PyResult<()> {
+        Ok(self.active()?.domain(DomainIdx::from(domain)).process()?)
+    }
+
+    fn queue(&mut self, domain: usize) -> PyResult<()> {
+        Ok(self.active()?.domain(DomainIdx::from(domain)).queue()?)
+    }
+
+    /// Copy of the process image of a domain.
+    fn domain_data<'py>(&mut self, py: Python<'py>, domain: usize) -> PyResult<&'py PyBytes> {
+        let data = self.active()?.domain_data(DomainIdx::from(domain))?;
+        Ok(PyBytes::new(py, data))
+    }
+
+    /// Write `data` into the process image of a domain at `offset`.
+    #[pyo3(signature = (domain, data, offset = 0))]
+    fn write_domain_data(&mut self, domain: usize, data: &[u8], offset: usize) -> PyResult<()> {
+        let image = self.active()?.domain_data(DomainIdx::from(domain))?;
+        image
+            .get_mut(offset..offset + data.len())
+            .ok_or_else(|| PyValueError::new_err("data exceeds the process image"))?
+            .copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Read a field from the process image of its domain.
+    fn read(&mut self, py: Python, field: &PyField) -> PyResult<PyObject> {
+        let data = self.active()?.domain_data(field.domain)?;
+        field.get(py, data)
+    }
+
+    /// Write a field into the process image of its domain.
+    fn write(&mut self, field: &PyField, value: &PyAny) -> PyResult<()> {
+        let data = self.active()?.domain_data(field.domain)?;
+        with_field!(field.dtype, field.domain, field.offset, |f| {
+            if data.len() < f.end() {
+                return Err(PyValueError::new_err("process image too short"));
+            }
+            f.set(data, value.extract()?);
+            Ok(())
+        })
+    }
+
+    /// Working counter and its state (`"Zero"`, `"Incomplete"` or
+    /// `"Complete"`) of a domain.
+    fn domain_state(&self, domain: usize) -> PyResult<(u32, String)> {
+        let state = either!(self.phase()?, m => m.domain(DomainIdx::from(domain)).state())?;
+        Ok((state.working_counter, format!("{:?}", state.wc_state)))
+    }
+
+    #[pyo3(signature = (slave, index, subindex, size = 256, complete_access = false))]
+    fn sdo_upload<'py>(
+        &self,
+        py: Python<'py>,
+        slave: u16,
+        index: u16,
+        subindex: u8,
+        size: usize,
+        complete_access: bool,
+    ) -> PyResult<&'py PyBytes> {
+        let mut buf = vec![0; size];
+        let data = either!(self.phase()?, m => m.sdo_upload(
+            SlavePos::from(slave),
+            SdoIdx::new(index, subindex),
+            complete_access,
+            &mut buf,
+        ))?;
+        Ok(PyBytes::new(py, data))
+    }
+
+    #[pyo3(signature = (slave, index, subindex, data, complete_access = false))]
+    fn sdo_download(
+        &mut self,
+        slave: u16,
+        index: u16,
+        subindex: u8,
+        data: &[u8],
+        complete_access: bool,
+    ) -> PyResult<()> {
+        Ok(either!(self.phase_mut()?, m => m.sdo_download(
+            SlavePos::from(slave),
+            SdoIdx::new(index, subindex),
+            complete_access,
+            &data,
+        ))?)
+    }
+
+    /// Request an AL state (`"Init"`, `"PreOp"`, `"SafeOp"` or `"Op"`).
+    fn request_state(&mut self, slave: u16, state: &str) -> PyResult<()> {
+        let state = al_state(state)?;
+        Ok(either!(self.phase_mut()?, m => m.request_state(SlavePos::from(slave), state))?)
+    }
+
+    fn state<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let state = either!(self.phase()?, m => m.state())?;
+        let dict = PyDict::new(py);
+        dict.set_item("slaves_responding", state.slaves_responding)?;
+        dict.set_item("al_states", state.al_states)?;
+        dict.set_item("link_up", state.link_up)?;
+        Ok(dict)
+    }
+
+    fn slave_count(&self) -> PyResult<u32> {
+        Ok(either!(self.phase()?, m => m.get_info())?.slave_count)
+    }
+
+    fn slave_info<'py>(&self, py: Python<'py>, slave: u16) -> PyResult<&'py PyDict> {
+        let info = either!(self.phase()?, m => m.get_slave_info(SlavePos::from(slave)))?;
+        let dict = PyDict::new(py);
+        dict.set_item("name", info.name)?;
+        dict.set_item("position", u16::from(info.ring_pos))?;
+        dict.set_item("alias", info.alias)?;
+        dict.set_item("vendor_id", info.id.vendor_id)?;
+        dict.set_item("product_code", info.id.product_code)?;
+        dict.set_item("revision_number", info.rev.revision_number)?;
+        dict.set_item("serial_number", info.rev.serial_number)?;
+        dict.set_item("al_state", format!("{:?}", info.al_state))?;
+        dict.set_item("error_flag", info.error_flag)?;
+        dict.set_item("dc_system_time", info.has_dc_system_time)?;
+        Ok(dict)
+    }
+
+    /// The diagnostic snapshot of the bus as JSON, for `json.loads`.
+    fn diagnostic_snapshot(&self) -> PyResult<String> {
+        Ok(either!(self.phase()?, m => m.diagnostic_snapshot())?.to_json())
+    }
+}
+
+#[test]
+fn test_dtype_names() {
+    for (name, dtype) in Dtype::NAMES.iter() {
+        assert_eq!(Dtype::parse(name).unwrap(), *dtype);
+        assert_eq!(dtype.name(), *name);
+    }
+}
@@ -0,0 +1,331 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Helpers for backing a ros2_control-style hardware interface.
+//!
+//! A [`JointInterface`] maps the PDO entries of the drives to joints in SI
+//! units: [`read`](JointInterface::read) returns a [`JointState`] shaped like
+//! `sensor_msgs/JointState`, and [`write`](JointInterface::write) stores
+//! position commands in the outputs. A [`TrajectoryFollower`] accepts
+//! [`JointTrajectory`] goals shaped like `trajectory_msgs/JointTrajectory`
+//! and samples them every cycle.
+//!
+//! The structs carry no ROS dependency; copying them into the messages of
+//! the ROS client library is left to the node.
+
+use crate::{
+    field::{Field, PdoNumber},
+    types::*,
+};
+use std::{io, time::Duration};
+
+/// PDO entry types that can hold a joint value.
+pub use crate::field::PdoNumber as JointValue;
+
+type ReadValue = Box<dyn Fn(&[u8]) -> f64 + Send>;
+type WriteValue = Box<dyn Fn(&mut [u8], f64) + Send>;
+
+fn reader<T: PdoNumber + Send + 'static>(field: Field<T>, scale: f64) -> ReadValue {
+    Box::new(move |data| field.get(data).to_f64() * scale)
+}
+
+/// A joint, i.e. one axis of a drive.
+///
+/// Values in the PDO entries are multiplied by the scale to get SI units,
+/// e.g. `2π / 4096` for a position in encoder counts.
+pub struct Joint {
+    name: String,
+    position: Option<ReadValue>,
+    velocity: Option<ReadValue>,
+    effort: Option<ReadValue>,
+    command: Option<WriteValue>,
+}
+
+impl Joint {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            position: None,
+            velocity: None,
+            effort: None,
+            command: None,
+        }
+    }
+
+    /// The actual position, e.g. object 0x6064.
+    pub fn position<T: PdoNumber + Send + 'static>(mut self, field: Field<T>, scale: f64) -> Self {
+        self.position = Some(reader(field, scale));
+        self
+    }
+
+    /// The actual velocity, e.g. object 0x606C.
+    pub fn velocity<T: PdoNumber + Send + 'static>(mut self, field: Field<T>, scale: f64) -> Self {
+        self.velocity = Some(reader(field, scale));
+        self
+    }
+
+    /// The actual torque or force, e.g. object 0x6077.
+    pub fn effort<T: PdoNumber + Send + 'static>(mut self, field: Field<T>, scale: f64) -> Self {
+        self.effort = Some(reader(field, scale));
+        self
+    }
+
+    /// The target position, e.g. object 0x607A.
+    pub fn position_command<T: PdoNumber + Send + 'static>(
+        mut self,
+        field: Field<T>,
+        scale: f64,
+    ) -> Self {
+        self.command = Some(Box::new(move |data, value| {
+            field.set(data, T::from_f64(value / scale))
+        }));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// State of the joints, like `sensor_msgs/JointState`.
+///
+/// Values a joint has no entry for are NaN.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JointState {
+    /// Time stamp in ns, usually the DC time of the cycle.
+    pub stamp: u64,
+    pub name: Vec<String>,
+    /// Positions in rad or m.
+    pub position: Vec<f64>,
+    /// Velocities in rad/s or m/s.
+    pub velocity: Vec<f64>,
+    /// Efforts in Nm or N.
+    pub effort: Vec<f64>,
+}
+
+/// Maps the process image of a domain to joints.
+pub struct JointInterface {
+    joints: Vec<Joint>,
+}
+
+impl JointInterface {
+    pub fn new(joints: Vec<Joint>) -> Self {
+        Self { joints }
+    }
+
+    pub fn joint_names(&self) -> Vec<String> {
+        self.joints.iter().map(|j| j.name.clone()).collect()
+    }
+
+    /// The joint state in the domain data `data`.
+    pub fn read(&self, data: &[u8], stamp: u64) -> JointState {
+        let get = |value: &Option<ReadValue>| value.as_ref().map_or(f64::NAN, |f| f(data));
+        JointState {
+            stamp,
+            name: self.joint_names(),
+            position: self.joints.iter().map(|j| get(&j.position)).collect(),
+            velocity: self.joints.iter().map(|j| get(&j.velocity)).collect(),
+            effort: self.joints.iter().map(|j| get(&j.effort)).collect(),
+        }
+    }
+
+    /// Store one position per joint, in the order of the joints, in the
+    /// domain data `data`. Joints without a command entry are skipped.
+    pub fn write(&self, data: &mut [u8], positions: &[f64]) {
+        for (joint, &position) in self.joints.iter().zip(positions) {
+            if let Some(command) = &joint.command {
+                command(data, position);
+            }
+        }
+    }
+}
+
+/// A point of a [`JointTrajectory`], like
+/// `trajectory_msgs/JointTrajectoryPoint`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JointTrajectoryPoint {
+    pub positions: Vec<f64>,
+    /// Empty, or one velocity per joint for cubic interpolation.
+    pub velocities: Vec<f64>,
+    pub time_from_start: Duration,
+}
+
+/// A trajectory goal, like `trajectory_msgs/JointTrajectory`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JointTrajectory {
+    pub joint_names: Vec<String>,
+    pub points: Vec<JointTrajectoryPoint>,
+}
+
+fn invalid_goal(msg: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidInput, msg))
+}
+
+/// Interpolates trajectory goals for the joints of an interface.
+///
+/// The goal starts at the position the joints have when it is accepted;
+/// between points, positions are interpolated linearly, or with cubic
+/// Hermite splines if both points have velocities.
+pub struct TrajectoryFollower {
+    joint_names: Vec<String>,
+    /// Points in the order of the joints, starting with the start position.
+    points: Vec<JointTrajectoryPoint>,
+}
+
+impl TrajectoryFollower {
+    pub fn new(interface: &JointInterface) -> Self {
+        Self {
+            joint_names: interface.joint_names(),
+            points: vec![],
+        }
+    }
+
+    /// Replace the current goal with `goal`, starting from `current`.
+    ///
+    /// The goal must name every joint once, and its points must have
+    /// increasing times.
+    pub fn accept(&mut self, goal: &JointTrajectory, current: &JointState) -> Result<()> {
+        let n = self.joint_names.len();
+        if goal.joint_names.len() != n {
+            return Err(invalid_goal(format!(
+                "goal has {} joints, expected {}",
+                goal.joint_names.len(),
+                n
+            )));
+        }
+        let order = self
+            .joint_names
+            .iter()
+            .map(|name| {
+                goal.joint_names
+                    .iter()
+                    .position(|g| g == name)
+                    .ok_or_else(|| invalid_goal(format!("goal has no joint {:?}", name)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if current.position.len() != n || current.position.iter().any(|p| p.is_nan()) {
+            return Err(invalid_goal(
+                "current position of the joints unknown".into(),
+            ));
+        }
+        let mut points = vec![JointTrajectoryPoint {
+            positions: current.position.clone(),
+            velocities: vec![0.0; n],
+            time_from_start: Duration::from_secs(0),
+        }];
+        for point in &goal.points {
+            if point.positions.len() != n
+                || !(point.velocities.is_empty() || point.velocities.len() == n)
+            {
+                return Err(invalid_goal("point with wrong number of values".into()));
+            }
+            if point.time_from_start <= points[points.len() - 1].time_from_start {
+                return Err(invalid_goal("point times are not increasing".into()));
+            }
+            let reorder = |values: &[f64]| order.iter().map(|&i| values[i]).collect::<Vec<_>>();
+            points.push(JointTrajectoryPoint {
+                positions: reorder(&point.positions),
+                velocities: if point.velocities.is_empty() {
+                    vec![]
+                } else {
+                    reorder(&point.velocities)
+                },
+                time_from_start: point.time_from_start,
+            });
+        }
+        self.points = points;
+        Ok(())
+    }
+
+    /// Drop the current goal.
+    pub fn cancel(&mut self) {
+        self.points.clear();
+    }
+
+    /// Whether a goal was accepted and not cancelled.
+    pub fn is_active(&self) -> bool {
+        !self.points.is_empty()
+    }
+
+    /// Duration of the current goal.
+    pub fn duration(&self) -> Duration {
+        self.points
+            .last()
+            .map_or(Duration::from_secs(0), |p| p.time_from_start)
+    }
+
+    /// The positions at `elapsed` since the goal was accepted, in the order
+    /// of the joints; the last point is held after the end of the goal.
+    pub fn sample(&self, elapsed: Duration) -> Option<Vec<f64>> {
+        let last = self.points.last()?;
+        let next = match self.points.iter().position(|p| p.time_from_start > elapsed) {
+            Some(next) => next,
+            None => return Some(last.positions.clone()),
+        };
+        let (a, b) = (&self.points[next - 1], &self.points[next]);
+        let span = (b.time_from_start - a.time_from_start).as_secs_f64();
+        let s = (elapsed - a.time_from_start).as_secs_f64() / span;
+        let cubic = !a.velocities.is_empty() && !b.velocities.is_empty();
+        let positions = (0..a.positions.len())
+            .map(|i| {
+                let (p0, p1) = (a.positions[i], b.positions[i]);
+                if cubic {
+                    let (m0, m1) = (a.velocities[i] * span, b.velocities[i] * span);
+                    let (s2, s3) = (s * s, s * s * s);
+                    (2.0 * s3 - 3.0 * s2 + 1.0) * p0
+                        + (s3 - 2.0 * s2 + s) * m0
+                        + (-2.0 * s3 + 3.0 * s2) * p1
+                        + (s3 - s2) * m1
+                } else {
+                    p0 + (p1 - p0) * s
+                }
+            })
+            .collect();
+        Some(positions)
+    }
+}
+
+#[test]
+fn test_joint_trajectory() {
+    let offset = |byte| Offset { byte, bit: 0 };
+    let interface = JointInterface::new(vec![
+        Joint::new("shoulder")
+            .position(Field::<i32>::new(0.into(), offset(0)), 0.001)
+            .position_command(Field::<i32>::new(0.into(), offset(8)), 0.001),
+        Joint::new("elbow")
+            .position(Field::<i32>::new(0.into(), offset(4)), 0.001)
+            .effort(Field::<i16>::new(0.into(), offset(12)), 0.5),
+    ]);
+    let mut data = [0; 16];
+    data[..4].copy_from_slice(&1000i32.to_le_bytes());
+    data[12..14].copy_from_slice(&(-4i16).to_le_bytes());
+    let state = interface.read(&data, 42);
+    assert_eq!(state.name, ["shoulder", "elbow"]);
+    assert_eq!(state.position, [1.0, 0.0]);
+    assert!(state.velocity.iter().all(|v| v.is_nan()));
+    assert_eq!(state.effort[1], -2.0);
+
+    let mut follower = TrajectoryFollower::new(&interface);
+    let mut goal = JointTrajectory {
+        joint_names: vec!["elbow".into(), "shoulder".into()],
+        points: vec![JointTrajectoryPoint {
+            positions: vec![0.5, 3.0],
+            velocities: vec![],
+            time_from_start: Duration::from_secs(2),
+        }],
+    };
+    follower.accept(&goal, &state).unwrap();
+    assert_eq!(
+        follower.sample(Duration::from_secs(1)).unwrap(),
+        [2.0, 0.25]
+    );
+    let end = follower.sample(Duration::from_secs(5)).unwrap();
+    assert_eq!(end, [3.0, 0.5]);
+    interface.write(&mut data, &end);
+    assert_eq!(data[8..12], 3000i32.to_le_bytes());
+
+    goal.joint_names[0] = "wrist".into();
+    assert!(follower.accept(&goal, &state).is_err());
+    follower.cancel();
+    assert!(follower.sample(Duration::from_secs(1)).is_none());
+}
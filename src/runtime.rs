@@ -0,0 +1,69 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Helpers for running the cyclic process data exchange.
+//!
+//! The cyclic thread is the single owner of the [`Master`](crate::Master),
+//! usually inside an [`Executor`]. Other threads never lock it; instead they
+//! use
+//!
+//! - [`SnapshotReader`]s to read consistent copies of the process image,
+//!   or [`SharedImageReader`]s from other processes, which can wait for the
+//!   end of each cycle on a [`CycleBarrierWaiter`],
+//! - a [`command_channel`] to pass commands into the cycle, and
+//! - a [`MasterMonitor`](crate::MasterMonitor) for state and slave queries.
+//!
+//! None of these block the cyclic thread, so lower-priority threads cannot
+//! cause priority inversion. The same holds for the opt-in [`Maintenance`]
+//! thread, which reconfigures slaves coming back after a power loss on its
+//! own handle of the bus.
+//!
+//! The executor, shared images, cycle barriers and memory locking are Linux
+//! only; channels, snapshots and statistics also build on other systems.
+
+#[cfg(target_os = "linux")]
+mod barrier;
+mod channel;
+mod clock;
+#[cfg(target_os = "linux")]
+mod cycle;
+#[cfg(target_os = "linux")]
+mod dc;
+#[cfg(target_os = "linux")]
+mod executor;
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+mod health;
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+mod hooks;
+mod maintenance;
+#[cfg(target_os = "linux")]
+mod memory;
+mod rtlog;
+#[cfg(target_os = "linux")]
+mod shm;
+mod snapshot;
+mod stats;
+mod time;
+#[cfg(target_os = "linux")]
+mod watchdog;
+
+#[cfg(target_os = "linux")]
+pub use self::{
+    barrier::{CycleBarrier, CycleBarrierWaiter},
+    cycle::{Cycle, Cycles},
+    dc::{dc_startup, DcStartupCfg},
+    executor::{CycleContext, Executor, ExecutorBuilder},
+    memory::{lock_memory, lock_memory_with, MemoryLockCfg},
+    shm::{LayoutEntry, SharedImage, SharedImageBuilder, SharedImageReader},
+    watchdog::{Watchdog, WatchdogCfg, WatchdogFeeder},
+};
+pub use self::{
+    channel::{command_channel, CommandReceiver, CommandSender},
+    clock::PiController,
+    health::{DomainHealth, WcAnomaly, WcLayout},
+    hooks::{EventThresholds, ExecutorEvent},
+    maintenance::{Maintenance, MaintenanceCfg, Reconfigurator},
+    rtlog::{rt_logger, RtArg, RtLogDrain, RtLogger},
+    snapshot::{snapshot_buffer, snapshot_index, Snapshot, SnapshotReader, SnapshotWriter},
+    stats::{CycleStats, Phase, PhaseSummary},
+};
@@ -0,0 +1,185 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    mem::size_of,
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    ptr,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::Duration,
+};
+
+const MAGIC: [u8; 8] = *b"ECATBAR1";
+
+/// Content of a barrier segment.
+#[repr(C)]
+struct Shared {
+    magic: [u8; 8],
+    /// Cycle number of the last signal.
+    cycle: AtomicU64,
+    /// Futex word, incremented on every signal.
+    generation: AtomicU32,
+    /// Number of processes blocked in the futex.
+    waiters: AtomicU32,
+    _reserved: [u64; 5],
+}
+
+/// Signals the end of each exchange to other processes, through a futex in a
+/// named shared-memory segment (`/dev/shm/<name>`).
+///
+/// Consumers of a [`SharedImage`](super::SharedImage) wait on a
+/// [`CycleBarrierWaiter`] to run phase-locked to the bus cycle. Signaling
+/// never blocks and only makes a system call if a process is waiting. The
+/// segment is removed when the barrier is dropped.
+pub struct CycleBarrier {
+    path: PathBuf,
+    map: memmap::MmapMut,
+}
+
+/// Waits for the signals of a [`CycleBarrier`] in another process.
+pub struct CycleBarrierWaiter {
+    // writable for the waiter count
+    map: memmap::MmapMut,
+    /// Generation seen by the last wait.
+    seen: u32,
+}
+
+fn segment_path(name: &str) -> PathBuf {
+    PathBuf::from("/dev/shm").join(name.trim_start_matches('/'))
+}
+
+fn shared<'a>(ptr: *const u8) -> &'a Shared {
+    // the mapping is page aligned, and only accessed through atomics
+    unsafe { &*(ptr as *const Shared) }
+}
+
+impl CycleBarrier {
+    /// Create the segment `name`, replacing an existing one.
+    ///
+    /// Only processes of the same user or group can wait on it.
+    pub fn create(name: &str) -> io::Result<Self> {
+        let path = segment_path(name);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            // waiters need write access for the waiter count, so they run
+            // in the group of the creator
+            .mode(0o660)
+            .open(&path)?;
+        file.set_len(size_of::<Shared>() as u64)?;
+        let mut map = unsafe { memmap::MmapMut::map_mut(&file)? };
+        map[..8].copy_from_slice(&MAGIC);
+        Ok(Self { path, map })
+    }
+
+    /// Wake all waiting processes for the end of cycle `cycle`.
+    pub fn signal(&self, cycle: u64) {
+        let shared = shared(self.map.as_ptr());
+        shared.cycle.store(cycle, Ordering::Relaxed);
+        shared.generation.fetch_add(1, Ordering::SeqCst);
+        if shared.waiters.load(Ordering::SeqCst) > 0 {
+            unsafe {
+                libc::syscall(
+                    libc::SYS_futex,
+                    &shared.generation as *const AtomicU32,
+                    libc::FUTEX_WAKE,
+                    i32::MAX,
+                );
+            }
+        }
+    }
+}
+
+impl Drop for CycleBarrier {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl CycleBarrierWaiter {
+    /// Open the segment created under `name`.
+    pub fn open(name: &str) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(segment_path(name))?;
+        let map = unsafe { memmap::MmapMut::map_mut(&file)? };
+        if map.len() < size_of::<Shared>() || map[..8] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a cycle barrier",
+            ));
+        }
+        let seen = shared(map.as_ptr()).generation.load(Ordering::Acquire);
+        Ok(Self { map, seen })
+    }
+
+    /// Block until the next signal and return its cycle number.
+    ///
+    /// Returns immediately if a signal came since the last wait; signals
+    /// missed in between can be detected from the cycle numbers. Fails with
+    /// `TimedOut` if there was no signal within `timeout`.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> io::Result<u64> {
+        let shared = shared(self.map.as_ptr());
+        let timeout = timeout.map(|t| libc::timespec {
+            tv_sec: t.as_secs() as _,
+            tv_nsec: t.subsec_nanos() as _,
+        });
+        loop {
+            let generation = shared.generation.load(Ordering::SeqCst);
+            if generation != self.seen {
+                self.seen = generation;
+                return Ok(shared.cycle.load(Ordering::Acquire));
+            }
+            shared.waiters.fetch_add(1, Ordering::SeqCst);
+            // returns at once if the generation changed in the meantime
+            let res = unsafe {
+                libc::syscall(
+                    libc::SYS_futex,
+                    &shared.generation as *const AtomicU32,
+                    libc::FUTEX_WAIT,
+                    generation,
+                    timeout.as_ref().map_or(ptr::null(), |t| t as *const _),
+                )
+            };
+            shared.waiters.fetch_sub(1, Ordering::SeqCst);
+            if res != 0 {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::EAGAIN) | Some(libc::EINTR) => {}
+                    Some(libc::ETIMEDOUT) => return Err(io::ErrorKind::TimedOut.into()),
+                    _ => return Err(err),
+                }
+            }
+        }
+    }
+}
+
+const _: () = assert!(size_of::<Shared>() == 64);
+
+#[test]
+fn test_cycle_barrier() {
+    let name = format!("ethercat-barrier-test-{}", std::process::id());
+    let barrier = CycleBarrier::create(&name).unwrap();
+    let mut waiter = CycleBarrierWaiter::open(&name).unwrap();
+    let err = waiter.wait(Some(Duration::from_millis(1))).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+    let thread = std::thread::spawn(move || {
+        let first = waiter.wait(None).unwrap();
+        let second = waiter.wait(Some(Duration::from_secs(5))).unwrap();
+        (first, second)
+    });
+    for cycle in 1..=2 {
+        std::thread::sleep(Duration::from_millis(20));
+        barrier.signal(cycle);
+    }
+    assert_eq!(thread.join().unwrap(), (1, 2));
+    drop(barrier);
+    assert!(CycleBarrierWaiter::open(&name).is_err());
+}
@@ -0,0 +1,196 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Create a bounded, lock-free command queue.
+///
+/// The storage is allocated once here, so sending and receiving never
+/// allocate or block. This makes it suitable to pass setpoints and mode
+/// changes from user threads into the cyclic closure. The capacity is rounded
+/// up to the next power of two.
+pub fn command_channel<T: Send>(capacity: usize) -> (CommandSender<T>, CommandReceiver<T>) {
+    let capacity = capacity.max(2).next_power_of_two();
+    let queue = Arc::new(Queue {
+        slots: (0..capacity)
+            .map(|i| Slot {
+                seq: AtomicUsize::new(i),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect(),
+        mask: capacity - 1,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (
+        CommandSender {
+            queue: queue.clone(),
+        },
+        CommandReceiver { queue },
+    )
+}
+
+/// Sending side of a command queue; may be cloned for multiple producers.
+pub struct CommandSender<T> {
+    queue: Arc<Queue<T>>,
+}
+
+/// Receiving side of a command queue, owned by the cyclic thread.
+pub struct CommandReceiver<T> {
+    queue: Arc<Queue<T>>,
+}
+
+struct Slot<T> {
+    seq: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// Bounded MPMC queue after D. Vyukov.
+struct Queue<T> {
+    slots: Box<[Slot<T>]>,
+    mask: usize,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for Queue<T> {}
+unsafe impl<T: Send> Sync for Queue<T> {}
+
+impl<T> Queue<T> {
+    fn push(&self, value: T) -> std::result::Result<(), T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let seq = slot.seq.load(Ordering::Acquire);
+            let diff = seq as isize - pos as isize;
+            if diff == 0 {
+                match self.tail.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).as_mut_ptr().write(value) };
+                        slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                return Err(value);
+            } else {
+                pos = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let mut pos = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let seq = slot.seq.load(Ordering::Acquire);
+            let diff = seq as isize - pos.wrapping_add(1) as isize;
+            if diff == 0 {
+                match self.head.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).as_ptr().read() };
+                        slot.seq
+                            .store(pos.wrapping_add(self.mask + 1), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                return None;
+            } else {
+                pos = self.head.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T: Send> CommandSender<T> {
+    /// Enqueue a command; gives the command back if the queue is full.
+    pub fn try_send(&self, cmd: T) -> std::result::Result<(), T> {
+        self.queue.push(cmd)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.slots.len()
+    }
+}
+
+impl<T> Clone for CommandSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T: Send> CommandReceiver<T> {
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.queue.pop()
+    }
+
+    /// Iterate over all commands that are currently queued.
+    pub fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(move || self.queue.pop())
+    }
+}
+
+#[test]
+fn test_command_channel() {
+    let (tx, mut rx) = command_channel(3);
+    assert_eq!(tx.capacity(), 4);
+    assert_eq!(rx.try_recv(), None);
+    for i in 0..4 {
+        tx.try_send(i).unwrap();
+    }
+    assert_eq!(tx.try_send(4), Err(4));
+    assert_eq!(rx.try_recv(), Some(0));
+    tx.try_send(4).unwrap();
+    assert_eq!(rx.drain().collect::<Vec<_>>(), [1, 2, 3, 4]);
+
+    let producers: Vec<_> = (0..4)
+        .map(|p| {
+            let tx = tx.clone();
+            std::thread::spawn(move || {
+                for i in 0..1000 {
+                    let mut cmd = p * 1000 + i;
+                    while let Err(c) = tx.try_send(cmd) {
+                        cmd = c;
+                        std::thread::yield_now();
+                    }
+                }
+            })
+        })
+        .collect();
+    let mut received = vec![];
+    while received.len() < 4000 {
+        received.extend(rx.drain());
+        std::thread::yield_now();
+    }
+    producers.into_iter().for_each(|p| p.join().unwrap());
+    received.sort_unstable();
+    assert_eq!(received, (0..4000).collect::<Vec<_>>());
+}
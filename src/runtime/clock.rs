@@ -0,0 +1,56 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+/// A PI controller used to discipline the application clock.
+#[derive(Debug, Clone)]
+pub struct PiController {
+    pub kp: f64,
+    pub ki: f64,
+    /// Limit of the controller output (and of the integral part).
+    pub limit: f64,
+    integral: f64,
+}
+
+impl PiController {
+    pub const fn new(kp: f64, ki: f64, limit: f64) -> Self {
+        Self {
+            kp,
+            ki,
+            limit,
+            integral: 0.0,
+        }
+    }
+
+    /// Feed a new error sample and return the correction.
+    pub fn update(&mut self, error: f64) -> f64 {
+        self.integral = (self.integral + self.ki * error)
+            .max(-self.limit)
+            .min(self.limit);
+        (self.kp * error + self.integral)
+            .max(-self.limit)
+            .min(self.limit)
+    }
+
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+    }
+}
+
+impl Default for PiController {
+    /// Gains suitable to correct the cycle phase in ns.
+    fn default() -> Self {
+        Self::new(0.1, 0.01, 100_000.0)
+    }
+}
+
+#[test]
+fn test_pi_controller() {
+    let mut pi = PiController::new(0.5, 0.1, 10.0);
+    assert_eq!(pi.update(0.0), 0.0);
+    assert!((pi.update(4.0) - 2.4).abs() < 1e-9);
+    assert!((pi.update(4.0) - 2.8).abs() < 1e-9);
+    assert_eq!(pi.update(1000.0), 10.0);
+    assert_eq!(pi.update(-1000.0), -10.0);
+    pi.reset();
+    assert_eq!(pi.update(0.0), 0.0);
+}
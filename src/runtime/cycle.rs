@@ -0,0 +1,165 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::time;
+use crate::{
+    field::{DomainView, Field, PdoData},
+    master::{Active, Master},
+    types::*,
+};
+use std::{
+    cell::{Cell, RefCell},
+    marker::PhantomData,
+    ptr::NonNull,
+    rc::Rc,
+    time::Duration,
+};
+
+/// Iterator over the cycles of an activated master, see [`Master::cycles`].
+pub struct Cycles<'m> {
+    master: NonNull<Master<Active>>,
+    shared: Rc<Shared>,
+    period: Duration,
+    next: Option<Duration>,
+    _master: PhantomData<&'m mut Master<Active>>,
+}
+
+/// Guard for one cycle of the process data exchange.
+///
+/// When it is created, the frames have been received and all domains
+/// processed. When it is dropped, the domains are queued and the frames are
+/// sent. Use [`Cycle::finish`] to handle errors of the latter.
+pub struct Cycle<'m> {
+    master: &'m mut Master<Active>,
+    shared: Rc<Shared>,
+    cycle: u64,
+    finished: bool,
+}
+
+struct Shared {
+    domains: Box<[DomainIdx]>,
+    active: Cell<bool>,
+    cycle: Cell<u64>,
+    error: RefCell<Option<Error>>,
+}
+
+impl<'m> Cycles<'m> {
+    pub(crate) fn new(master: &'m mut Master<Active>, period: Duration) -> Result<Self> {
+        let domain_count = master.get_info()?.domain_count;
+        let domains = (0..domain_count as usize).map(DomainIdx::from).collect();
+        Ok(Self {
+            master: NonNull::from(master),
+            shared: Rc::new(Shared {
+                domains,
+                active: Cell::new(false),
+                cycle: Cell::new(0),
+                error: RefCell::new(None),
+            }),
+            period,
+            next: None,
+            _master: PhantomData,
+        })
+    }
+
+    fn start(&mut self) -> Result<Cycle<'m>> {
+        if let Some(err) = self.shared.error.borrow_mut().take() {
+            return Err(err);
+        }
+        let now = time::monotonic_now();
+        let start = match self.next {
+            Some(next) => {
+                time::sleep_until(next)?;
+                next
+            }
+            None => now,
+        };
+        self.next = Some(start + self.period);
+        // Safety: the master is borrowed mutably for 'm by `Cycles` and only
+        // handed out to a single `Cycle` at a time, see `active`.
+        let master = unsafe { &mut *self.master.as_ptr() };
+        master.receive()?;
+        for idx in self.shared.domains.iter() {
+            master.domain(*idx).process()?;
+        }
+        let cycle = self.shared.cycle.get();
+        self.shared.cycle.set(cycle + 1);
+        self.shared.active.set(true);
+        Ok(Cycle {
+            master,
+            shared: self.shared.clone(),
+            cycle,
+            finished: false,
+        })
+    }
+}
+
+impl<'m> Iterator for Cycles<'m> {
+    type Item = Result<Cycle<'m>>;
+
+    /// Wait for the next cycle and receive it.
+    ///
+    /// Panics if the previous [`Cycle`] is still alive.
+    fn next(&mut self) -> Option<Self::Item> {
+        assert!(
+            !self.shared.active.get(),
+            "previous cycle must be dropped before starting the next one"
+        );
+        Some(self.start())
+    }
+}
+
+impl<'m> Cycle<'m> {
+    /// Number of the cycle, starting at zero.
+    pub const fn number(&self) -> u64 {
+        self.cycle
+    }
+
+    pub fn data(&mut self, idx: DomainIdx) -> Result<&mut [u8]> {
+        self.master.domain_data(idx)
+    }
+
+    /// Typed access to the process data of a domain, for many fields
+    /// without looking up the domain for each of them.
+    pub fn view(&mut self, idx: DomainIdx) -> Result<DomainView<'_>> {
+        self.master.domain_view(idx)
+    }
+
+    pub fn get<T: PdoData>(&mut self, field: &Field<T>) -> Result<T> {
+        Ok(field.get(self.master.domain_data(field.domain)?))
+    }
+
+    pub fn set<T: PdoData>(&mut self, field: &Field<T>, value: T) -> Result<()> {
+        field.set(self.master.domain_data(field.domain)?, value);
+        Ok(())
+    }
+
+    pub fn master(&mut self) -> &mut Master<Active> {
+        self.master
+    }
+
+    /// Queue all domains and send the frames.
+    pub fn finish(mut self) -> Result<()> {
+        self.send()
+    }
+
+    fn send(&mut self) -> Result<()> {
+        self.finished = true;
+        self.shared.active.set(false);
+        for idx in self.shared.domains.iter() {
+            self.master.domain(*idx).queue()?;
+        }
+        self.master.send()?;
+        Ok(())
+    }
+}
+
+impl<'m> Drop for Cycle<'m> {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Err(err) = self.send() {
+                // reported by the next call of `Cycles::next`
+                *self.shared.error.borrow_mut() = Some(err);
+            }
+        }
+    }
+}
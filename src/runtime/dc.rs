@@ -0,0 +1,68 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{time, Executor};
+use crate::types::*;
+use std::time::Duration;
+
+/// Parameters of [`dc_startup`].
+#[derive(Debug, Clone)]
+pub struct DcStartupCfg {
+    /// Maximum tolerated system time difference in ns.
+    pub max_deviation: u32,
+    /// Number of consecutive cycles the deviation must stay below the limit.
+    pub settle_cycles: u32,
+    /// Give up if the clocks did not settle within this time.
+    pub timeout: Duration,
+    /// Slaves to bring into Op; `None` means all slaves on the bus.
+    pub slaves: Option<Vec<SlavePos>>,
+}
+
+impl Default for DcStartupCfg {
+    fn default() -> Self {
+        Self {
+            max_deviation: 1_000,
+            settle_cycles: 100,
+            timeout: Duration::from_secs(10),
+            slaves: None,
+        }
+    }
+}
+
+/// Bring the slaves into Op with aligned distributed clocks.
+///
+/// The slaves are held in SafeOp while cycles are running and the clocks are
+/// synchronized. Op is requested once the system time difference stayed
+/// below `max_deviation` for `settle_cycles` cycles. The executor must have
+/// been built with distributed clocks enabled.
+pub fn dc_startup(executor: &mut Executor, cfg: &DcStartupCfg) -> Result<()> {
+    trace_span!(INFO, "dc_startup", max_deviation = cfg.max_deviation);
+    let slaves = match &cfg.slaves {
+        Some(slaves) => slaves.clone(),
+        None => (0..executor.master().get_info()?.slave_count as u16)
+            .map(SlavePos::from)
+            .collect(),
+    };
+    log::debug!("DC startup: hold {} slaves in SafeOp", slaves.len());
+    for slave in &slaves {
+        executor.master().request_state(*slave, AlState::SafeOp)?;
+    }
+    let deadline = time::monotonic_now() + cfg.timeout;
+    let mut settled = 0;
+    while settled < cfg.settle_cycles {
+        if time::monotonic_now() > deadline {
+            trace_event!(ERROR, "distributed clocks did not settle");
+            return Err(Error::DcTimeout);
+        }
+        executor.run_cycle(|_| Ok(()))?;
+        settled = match executor.dc_deviation() {
+            Some(dev) if dev <= cfg.max_deviation => settled + 1,
+            _ => 0,
+        };
+    }
+    log::debug!("DC startup: clocks settled, request Op");
+    for slave in &slaves {
+        executor.master().request_state(*slave, AlState::Op)?;
+    }
+    Ok(())
+}
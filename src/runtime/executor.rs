@@ -0,0 +1,655 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{
+    barrier::CycleBarrier,
+    clock::PiController,
+    health::{DomainHealth, HealthTracker, WcAnomaly, WcLayout},
+    hooks::{EventThresholds, ExecutorEvent, Hook, HookRunner},
+    memory::{lock_memory_with, MemoryLockCfg},
+    shm::{SharedImage, SharedImageBuilder},
+    snapshot::{snapshot_buffer, SnapshotReader, SnapshotWriter},
+    stats::{CycleStats, Phase},
+    time, WatchdogFeeder,
+};
+use crate::{
+    field::DomainView,
+    master::{Active, Master},
+    types::*,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Builder for an [`Executor`].
+pub struct ExecutorBuilder {
+    master: Master,
+    period: Duration,
+    domains: Vec<ScheduledDomain>,
+    snapshots: Vec<DomainIdx>,
+    shared_images: Vec<SharedImageBuilder>,
+    cycle_barrier: Option<CycleBarrier>,
+    watchdog: Option<WatchdogFeeder>,
+    distributed_clocks: bool,
+    follow_reference: Option<PiController>,
+    lock_memory: Option<MemoryLockCfg>,
+    send_offset: Option<Duration>,
+    stats_window: usize,
+    thresholds: EventThresholds,
+    hooks: Vec<Hook>,
+}
+
+/// Runs the cyclic process data exchange of an activated master.
+///
+/// Each cycle receives the frames, processes all domains, calls the user
+/// closure, queues the domains and sends the frames again.
+pub struct Executor {
+    master: Master<Active>,
+    period: Duration,
+    domains: Vec<ScheduledDomain>,
+    snapshots: Vec<(DomainIdx, SnapshotWriter, SnapshotReader)>,
+    shared_images: Vec<SharedImage>,
+    cycle_barrier: Option<CycleBarrier>,
+    watchdog: Option<WatchdogFeeder>,
+    distributed_clocks: bool,
+    follow_reference: Option<PiController>,
+    send_offset: Option<Duration>,
+    stats: CycleStats,
+    thresholds: EventThresholds,
+    hooks: Option<HookRunner>,
+    dc_deviation: Option<u32>,
+    app_time_base: u64,
+    app_time: u64,
+    send_time: Option<u64>,
+    phase_correction: i64,
+    stop: Arc<AtomicBool>,
+    stopping: bool,
+    cycle: u64,
+    next: Option<Duration>,
+}
+
+struct ScheduledDomain {
+    idx: DomainIdx,
+    divider: u64,
+    state: Option<DomainState>,
+    health: Option<HealthTracker>,
+    wc_errors: u32,
+}
+
+impl ScheduledDomain {
+    /// The domain is received and processed in this cycle.
+    const fn is_received(&self, cycle: u64) -> bool {
+        cycle % self.divider == 0
+    }
+
+    /// The domain is queued in this cycle, so that it is received in the
+    /// next one.
+    const fn is_queued(&self, cycle: u64) -> bool {
+        (cycle + 1) % self.divider == 0
+    }
+}
+
+/// Access to the master from within a cycle.
+pub struct CycleContext<'a> {
+    master: &'a mut Master<Active>,
+    domains: &'a [ScheduledDomain],
+    stats: &'a CycleStats,
+    cycle: u64,
+    wakeup_latency: Duration,
+    stopping: bool,
+    dc_deviation: Option<u32>,
+    dc_time: Option<u64>,
+}
+
+impl ExecutorBuilder {
+    /// Add a domain to be exchanged every cycle.
+    pub fn domain(self, idx: DomainIdx) -> Self {
+        self.domain_every(idx, 1)
+    }
+
+    /// Add a domain to be exchanged every `divider`th cycle only.
+    ///
+    /// This lets slow, diagnostic-heavy slaves live in their own domain
+    /// without constraining the rate of the fast domains. The working
+    /// counter of each domain is monitored independently.
+    pub fn domain_every(mut self, idx: DomainIdx, divider: u32) -> Self {
+        self.domains.push(ScheduledDomain {
+            idx,
+            divider: u64::from(divider.max(1)),
+            state: None,
+            health: None,
+            wc_errors: 0,
+        });
+        self
+    }
+
+    /// Copy the data of `idx` into a double buffer at the end of every cycle.
+    ///
+    /// Use [`Executor::snapshot_reader`] to obtain readers.
+    pub fn snapshot(mut self, idx: DomainIdx) -> Self {
+        self.snapshots.push(idx);
+        self
+    }
+
+    /// Publish the data of a domain into a shared-memory segment at the end
+    /// of every cycle, for other processes.
+    pub fn shared_image(mut self, image: SharedImageBuilder) -> Self {
+        self.shared_images.push(image);
+        self
+    }
+
+    /// Signal the barrier at the end of every cycle, after the snapshots and
+    /// shared images are published.
+    pub fn cycle_barrier(mut self, barrier: CycleBarrier) -> Self {
+        self.cycle_barrier = Some(barrier);
+        self
+    }
+
+    /// Feed the given watchdog after every exchange.
+    pub fn watchdog(mut self, feeder: WatchdogFeeder) -> Self {
+        self.watchdog = Some(feeder);
+        self
+    }
+
+    /// Synchronize the distributed clocks every cycle.
+    ///
+    /// The application time is set from the system clock, the reference
+    /// clock and the slave clocks are synchronized, and the system time
+    /// difference is monitored.
+    pub fn distributed_clocks(mut self, enable: bool) -> Self {
+        self.distributed_clocks = enable;
+        self
+    }
+
+    /// Make the application clock follow the reference slave clock.
+    ///
+    /// Instead of adjusting the reference clock to the application time, the
+    /// cycle phase is corrected by the given controller so that the Linux
+    /// timer does not drift away from the DC domain. Implies
+    /// [`distributed_clocks`](Self::distributed_clocks).
+    pub fn follow_reference_clock(mut self, controller: PiController) -> Self {
+        self.distributed_clocks = true;
+        self.follow_reference = Some(controller);
+        self
+    }
+
+    /// Configure memory locking, see [`lock_memory_with`].
+    ///
+    /// By default, memory is locked with [`MemoryLockCfg::default`] when the
+    /// executor is built; pass `None` to skip it.
+    pub fn lock_memory(mut self, cfg: Option<MemoryLockCfg>) -> Self {
+        self.lock_memory = cfg;
+        self
+    }
+
+    /// Send the frames at a fixed offset from the cycle start.
+    ///
+    /// By default the frames are sent right after the user closure returned.
+    /// With an offset, the executor receives at cycle start, runs the closure
+    /// and then waits until `offset` into the cycle before queuing and
+    /// sending, e.g. at 30 % of the period. This keeps the send time stable
+    /// relative to the DC sync events regardless of the computation time.
+    pub fn send_offset(mut self, offset: Duration) -> Self {
+        self.send_offset = Some(offset);
+        self
+    }
+
+    /// Number of cycles covered by the busy time statistics (default 1000).
+    pub fn stats_window(mut self, cycles: usize) -> Self {
+        self.stats_window = cycles;
+        self
+    }
+
+    /// Limits for raising [`ExecutorEvent`]s.
+    pub fn event_thresholds(mut self, thresholds: EventThresholds) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    /// Register a hook that is called for every [`ExecutorEvent`].
+    ///
+    /// Hooks run on a separate, non-realtime thread, so they may block, e.g.
+    /// to notify an operator or to initiate a controlled shutdown.
+    pub fn hook<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(&ExecutorEvent) + Send + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Activate the master and create the executor.
+    ///
+    /// This should be called from the thread that will run the cycles, so
+    /// that its stack gets prefaulted.
+    pub fn build(self) -> Result<Executor> {
+        if let Some(cfg) = &self.lock_memory {
+            lock_memory_with(cfg)?;
+        }
+        let mut master = self.master;
+        let app_time = time::dc_now();
+        if self.distributed_clocks {
+            master.set_application_time(app_time)?;
+        }
+        let mut master = master.activate()?;
+        let mut domains = self.domains;
+        for domain in &mut domains {
+            domain.health = Some(HealthTracker::new(WcLayout::read(&master, domain.idx)?));
+        }
+        let mut snapshots = vec![];
+        for idx in self.snapshots {
+            let (writer, reader) = snapshot_buffer(master.domain_data(idx)?.len());
+            snapshots.push((idx, writer, reader));
+        }
+        let mut shared_images = vec![];
+        for image in self.shared_images {
+            let size = master.domain_data(image.domain())?.len();
+            shared_images.push(image.create(size)?);
+        }
+        let hooks = if self.hooks.is_empty() {
+            None
+        } else {
+            Some(HookRunner::spawn(self.hooks)?)
+        };
+        Ok(Executor {
+            master,
+            period: self.period,
+            domains,
+            snapshots,
+            shared_images,
+            cycle_barrier: self.cycle_barrier,
+            watchdog: self.watchdog,
+            distributed_clocks: self.distributed_clocks,
+            follow_reference: self.follow_reference,
+            send_offset: self.send_offset,
+            stats: CycleStats::new(self.stats_window),
+            thresholds: self.thresholds,
+            hooks,
+            dc_deviation: None,
+            app_time_base: app_time,
+            app_time,
+            send_time: None,
+            phase_correction: 0,
+            stop: Arc::new(AtomicBool::new(false)),
+            stopping: false,
+            cycle: 0,
+            next: None,
+        })
+    }
+}
+
+impl Executor {
+    /// Start building an executor for a fully configured, not yet activated
+    /// master.
+    pub fn builder(master: Master, period: Duration) -> ExecutorBuilder {
+        ExecutorBuilder {
+            master,
+            period,
+            domains: vec![],
+            snapshots: vec![],
+            shared_images: vec![],
+            cycle_barrier: None,
+            watchdog: None,
+            distributed_clocks: false,
+            follow_reference: None,
+            lock_memory: Some(MemoryLockCfg::default()),
+            send_offset: None,
+            stats_window: 1000,
+            thresholds: EventThresholds::default(),
+            hooks: vec![],
+        }
+    }
+
+    pub fn master(&mut self) -> &mut Master<Active> {
+        &mut self.master
+    }
+
+    pub fn into_master(self) -> Master<Active> {
+        self.master
+    }
+
+    pub const fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Returns a flag that makes [`Executor::run`] return when set.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    pub fn snapshot_reader(&self, idx: DomainIdx) -> Option<SnapshotReader> {
+        self.snapshots
+            .iter()
+            .find(|(i, _, _)| *i == idx)
+            .map(|(_, _, reader)| reader.clone())
+    }
+
+    /// Run cycles until the stop flag is set or an error occurs.
+    ///
+    /// After the stop flag is set, a last cycle is run in which
+    /// [`CycleContext::is_stopping`] is `true`, so that the closure can bring
+    /// the outputs into a safe state before `run` returns.
+    pub fn run<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&mut CycleContext) -> Result<()>,
+    {
+        while !self.stop.load(Ordering::Acquire) {
+            self.run_cycle(&mut f)?;
+        }
+        self.stopping = true;
+        let result = self.run_cycle(&mut f);
+        self.stopping = false;
+        result
+    }
+
+    /// Run a single cycle and wait for the start of the next one.
+    pub fn run_cycle<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&mut CycleContext) -> Result<()>,
+    {
+        let start = match self.next {
+            Some(next) => next,
+            None => time::monotonic_now(),
+        };
+        self.exchange(start, &mut f)?;
+        let now = time::monotonic_now();
+        let correction = Duration::from_nanos(self.phase_correction.unsigned_abs());
+        let next = if self.phase_correction < 0 {
+            (start + self.period).saturating_sub(correction)
+        } else {
+            start + self.period + correction
+        };
+        self.next = Some(next);
+        if now > next {
+            trace_event!(
+                WARN,
+                cycle = self.cycle - 1,
+                overrun_ns = (now - next).as_nanos() as u64,
+                "deadline missed"
+            );
+            self.notify(ExecutorEvent::DeadlineMiss {
+                cycle: self.cycle - 1,
+                overrun: now - next,
+            });
+        }
+        time::sleep_until(next)?;
+        Ok(())
+    }
+
+    /// Upper estimation of the maximum system time difference of the
+    /// distributed clocks in ns, as seen in the last cycle.
+    pub const fn dc_deviation(&self) -> Option<u32> {
+        self.dc_deviation
+    }
+
+    /// Busy time statistics per phase of the exchange.
+    pub const fn stats(&self) -> &CycleStats {
+        &self.stats
+    }
+
+    /// Working counter health of the domain.
+    pub fn domain_health(&self, idx: DomainIdx) -> Option<&DomainHealth> {
+        domain_health(&self.domains, idx)
+    }
+
+    fn exchange<F>(&mut self, start: Duration, f: &mut F) -> Result<()>
+    where
+        F: FnMut(&mut CycleContext) -> Result<()>,
+    {
+        let mut times = [Duration::default(); 5];
+        let mut mark = time::monotonic_now();
+        let latency = mark.saturating_sub(start);
+        if latency > self.thresholds.jitter {
+            trace_event!(
+                DEBUG,
+                cycle = self.cycle,
+                latency_ns = latency.as_nanos() as u64,
+                "wakeup jitter"
+            );
+            self.notify(ExecutorEvent::Jitter {
+                cycle: self.cycle,
+                latency,
+            });
+        }
+        let mut lap = |phase: Phase| {
+            let now = time::monotonic_now();
+            times[phase as usize] = now.saturating_sub(mark);
+            mark = now;
+        };
+        self.master.receive()?;
+        if self.distributed_clocks && self.cycle > 0 {
+            self.dc_deviation = Some(self.master.sync_monitor_process()?);
+        }
+        lap(Phase::Receive);
+        for domain in &mut self.domains {
+            if domain.is_received(self.cycle) {
+                let mut d = self.master.domain(domain.idx);
+                d.process()?;
+                let state = d.state()?;
+                if let Some(prev) = &domain.state {
+                    if prev.wc_state != state.wc_state {
+                        log::warn!(
+                            "Domain {:?}: working counter {} ({:?})",
+                            domain.idx,
+                            state.working_counter,
+                            state.wc_state
+                        );
+                        trace_event!(
+                            WARN,
+                            domain = usize::from(domain.idx),
+                            working_counter = state.working_counter,
+                            wc_state = ?state.wc_state,
+                            "working counter changed"
+                        );
+                    }
+                }
+                let anomaly = match &mut domain.health {
+                    Some(health) => health.update(state.working_counter).anomaly,
+                    None => WcAnomaly::None,
+                };
+                if state.wc_state == WcState::Complete {
+                    domain.wc_errors = 0;
+                } else {
+                    domain.wc_errors += 1;
+                    if domain.wc_errors == self.thresholds.wc_error_cycles {
+                        if let Some(hooks) = &self.hooks {
+                            hooks.notify(ExecutorEvent::WorkingCounter {
+                                cycle: self.cycle,
+                                domain: domain.idx,
+                                working_counter: state.working_counter,
+                                wc_state: state.wc_state,
+                                anomaly,
+                            });
+                        }
+                    }
+                }
+                domain.state = Some(state);
+            }
+        }
+        lap(Phase::Process);
+        f(&mut CycleContext {
+            master: &mut self.master,
+            domains: &self.domains,
+            stats: &self.stats,
+            cycle: self.cycle,
+            wakeup_latency: latency,
+            stopping: self.stopping,
+            dc_deviation: self.dc_deviation,
+            dc_time: self.send_time,
+        })?;
+        lap(Phase::User);
+        if let Some(offset) = self.send_offset {
+            time::sleep_until(start + offset)?;
+            // waiting is not busy time
+            lap(Phase::Queue);
+        }
+        if self.distributed_clocks {
+            self.sync_clocks()?;
+        }
+        for domain in &self.domains {
+            if domain.is_queued(self.cycle) {
+                self.master.domain(domain.idx).queue()?;
+            }
+        }
+        lap(Phase::Queue);
+        let send_time = if self.distributed_clocks {
+            self.app_time
+        } else {
+            time::dc_now()
+        };
+        self.master.send()?;
+        lap(Phase::Send);
+        self.stats.record(times);
+        trace_event!(
+            TRACE,
+            cycle = self.cycle,
+            receive_ns = times[Phase::Receive as usize].as_nanos() as u64,
+            process_ns = times[Phase::Process as usize].as_nanos() as u64,
+            user_ns = times[Phase::User as usize].as_nanos() as u64,
+            queue_ns = times[Phase::Queue as usize].as_nanos() as u64,
+            send_ns = times[Phase::Send as usize].as_nanos() as u64,
+            "cycle"
+        );
+        self.send_time = Some(send_time);
+        for (idx, writer, _) in &mut self.snapshots {
+            writer.publish(self.cycle, send_time, self.master.domain_data(*idx)?);
+        }
+        for image in &mut self.shared_images {
+            let data = self.master.domain_data(image.domain())?;
+            image.publish(self.cycle, send_time, data);
+        }
+        if let Some(barrier) = &self.cycle_barrier {
+            barrier.signal(self.cycle);
+        }
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.feed();
+        }
+        self.cycle += 1;
+        Ok(())
+    }
+
+    fn notify(&self, event: ExecutorEvent) {
+        if let Some(hooks) = &self.hooks {
+            hooks.notify(event);
+        }
+    }
+
+    fn sync_clocks(&mut self) -> Result<()> {
+        let prev_app_time = self.app_time;
+        match &mut self.follow_reference {
+            Some(pi) => {
+                // The application time advances by exactly one period per
+                // cycle; the wakeup of the next cycle is shifted instead,
+                // until the reference clock advances by one period as well.
+                self.app_time = self.app_time_base + self.cycle * self.period.as_nanos() as u64;
+                self.master.set_application_time(self.app_time)?;
+                if self.cycle > 0 {
+                    // latched by the sync datagram of the previous cycle
+                    let ref_time = self.master.get_reference_clock_time()?;
+                    let diff = (prev_app_time as u32).wrapping_sub(ref_time) as i32;
+                    self.phase_correction = pi.update(diff as f64) as i64;
+                }
+            }
+            None => {
+                self.app_time = time::dc_now();
+                self.master.set_application_time(self.app_time)?;
+                self.master.sync_reference_clock()?;
+            }
+        }
+        self.master.sync_slave_clocks()?;
+        self.master.sync_monitor_queue()?;
+        Ok(())
+    }
+}
+
+impl<'a> CycleContext<'a> {
+    /// Number of the current cycle, starting at zero.
+    pub const fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    /// Time between the scheduled start of the cycle and the wakeup of the
+    /// cyclic thread.
+    pub const fn wakeup_latency(&self) -> Duration {
+        self.wakeup_latency
+    }
+
+    /// Returns `true` in the last cycle of [`Executor::run`], after the stop
+    /// flag was set.
+    ///
+    /// Outputs written in this cycle, e.g. the safe states of the
+    /// [`devices`](crate::devices), are sent before `run` returns, at least
+    /// for the domains exchanged every cycle.
+    pub const fn is_stopping(&self) -> bool {
+        self.stopping
+    }
+
+    pub fn domain_data(&mut self, idx: DomainIdx) -> Result<&mut [u8]> {
+        self.master.domain_data(idx)
+    }
+
+    /// Typed access to the process data of a domain, see [`DomainView`].
+    pub fn domain_view(&mut self, idx: DomainIdx) -> Result<DomainView<'_>> {
+        self.master.domain_view(idx)
+    }
+
+    /// DC time in ns at which the frame carrying the current inputs was
+    /// sent, i.e. the send time of the previous cycle.
+    ///
+    /// With distributed clocks enabled this is the application time
+    /// distributed to the slaves, otherwise the system time is used.
+    pub const fn dc_time(&self) -> Option<u64> {
+        self.dc_time
+    }
+
+    /// Returns `true` if fresh inputs of the domain were received in this
+    /// cycle.
+    ///
+    /// Outputs written now are sent with the next exchange of the domain,
+    /// which is at most `divider - 1` cycles later. Always `true` for domains
+    /// exchanged every cycle.
+    pub fn is_due(&self, idx: DomainIdx) -> bool {
+        self.domains
+            .iter()
+            .any(|d| d.idx == idx && d.is_received(self.cycle))
+    }
+
+    /// State of the domain when it was last received.
+    pub fn domain_state(&self, idx: DomainIdx) -> Option<&DomainState> {
+        self.domains
+            .iter()
+            .find(|d| d.idx == idx)
+            .and_then(|d| d.state.as_ref())
+    }
+
+    /// Working counter health of the domain.
+    pub fn domain_health(&self, idx: DomainIdx) -> Option<&DomainHealth> {
+        domain_health(self.domains, idx)
+    }
+
+    /// Busy time statistics of the previous cycles.
+    pub const fn stats(&self) -> &CycleStats {
+        self.stats
+    }
+
+    /// See [`Executor::dc_deviation`].
+    pub const fn dc_deviation(&self) -> Option<u32> {
+        self.dc_deviation
+    }
+
+    pub fn master(&mut self) -> &mut Master<Active> {
+        self.master
+    }
+}
+
+fn domain_health(domains: &[ScheduledDomain], idx: DomainIdx) -> Option<&DomainHealth> {
+    domains
+        .iter()
+        .find(|d| d.idx == idx)
+        .and_then(|d| d.health.as_ref())
+        .map(|h| h.health())
+}
@@ -0,0 +1,213 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+#[cfg(target_os = "linux")]
+use crate::master::Master;
+use crate::types::*;
+
+/// Received cycles over which dropouts are counted to detect intermittent
+/// errors.
+const INTERMITTENT_WINDOW: u64 = 1000;
+/// Dropouts within the window above which errors count as intermittent.
+const INTERMITTENT_DROPOUTS: u32 = 3;
+
+/// Interpretation of a working counter below the expected value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WcAnomaly {
+    /// All slaves exchanged their data.
+    None,
+    /// The missing part matches the contribution of a single slave.
+    SlaveMissing { slave: SlavePos },
+    /// The missing part matches all slaves from `first` on, as after a
+    /// cable break or a power loss of a segment.
+    SegmentDown { first: SlavePos },
+    /// Several slaves are missing, which cannot be told apart.
+    SlavesMissing { deficit: u32 },
+    /// The working counter dropped repeatedly within a short time.
+    Intermittent { dropouts: u32 },
+}
+
+/// Working counter health of a domain over time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DomainHealth {
+    pub expected_working_counter: u32,
+    pub working_counter: u32,
+    pub anomaly: WcAnomaly,
+    /// Received cycles with an incomplete working counter.
+    pub error_cycles: u64,
+    /// Number of times the working counter dropped below the expected value.
+    pub dropouts: u64,
+}
+
+/// Expected working counter contributions of the slaves of a domain.
+///
+/// With logical read/write datagrams, slaves add 2 for outputs and 1 for
+/// inputs.
+#[derive(Debug, Clone, Default)]
+pub struct WcLayout {
+    // sorted by position
+    contributions: Vec<(SlavePos, u32)>,
+}
+
+impl WcLayout {
+    pub fn new(mut contributions: Vec<(SlavePos, u32)>) -> Self {
+        contributions.sort_by_key(|(pos, _)| u16::from(*pos));
+        Self { contributions }
+    }
+
+    /// Determine the contributions from the FMMUs of an activated domain.
+    #[cfg(target_os = "linux")]
+    pub fn read<S>(master: &Master<S>, idx: DomainIdx) -> Result<Self> {
+        let domain = master.domain(idx);
+        let configs = (0..master.get_info()?.config_count)
+            .map(|i| master.get_config_info(i))
+            .collect::<Result<Vec<_>>>()?;
+        let mut contributions: Vec<(SlavePos, u32)> = vec![];
+        let mut seen = vec![];
+        for i in 0..domain.info()?.fmmu_count {
+            let fmmu = domain.fmmu(i)?;
+            let weight = match fmmu.direction {
+                SyncDirection::Output => 2,
+                SyncDirection::Input => 1,
+                SyncDirection::Invalid => continue,
+            };
+            let pos = configs
+                .iter()
+                .find(|c| {
+                    c.alias == fmmu.slave_config_alias && c.position == fmmu.slave_config_position
+                })
+                .and_then(|c| c.slave_position);
+            let pos = match pos {
+                Some(pos) => pos,
+                None => continue,
+            };
+            // each direction counts once per slave
+            if seen.contains(&(pos, weight)) {
+                continue;
+            }
+            seen.push((pos, weight));
+            match contributions.iter_mut().find(|(p, _)| *p == pos) {
+                Some((_, wc)) => *wc += weight,
+                None => contributions.push((pos, weight)),
+            }
+        }
+        Ok(Self::new(contributions))
+    }
+
+    pub fn expected(&self) -> u32 {
+        self.contributions.iter().map(|(_, wc)| wc).sum()
+    }
+
+    /// Explain a working counter below the expected value, assuming slaves
+    /// are wired in the order of their positions.
+    pub fn classify(&self, working_counter: u32) -> WcAnomaly {
+        let expected = self.expected();
+        if working_counter >= expected {
+            return WcAnomaly::None;
+        }
+        let deficit = expected - working_counter;
+        if let Some((slave, _)) = self.contributions.iter().find(|(_, wc)| *wc == deficit) {
+            return WcAnomaly::SlaveMissing { slave: *slave };
+        }
+        let mut missing = 0;
+        for (slave, wc) in self.contributions.iter().rev() {
+            missing += wc;
+            if missing == deficit {
+                return WcAnomaly::SegmentDown { first: *slave };
+            }
+        }
+        WcAnomaly::SlavesMissing { deficit }
+    }
+}
+
+/// Tracks the working counter of a domain in the cyclic thread.
+pub(crate) struct HealthTracker {
+    layout: WcLayout,
+    health: DomainHealth,
+    received: u64,
+    // dropouts in the current and the previous window
+    window_dropouts: [u32; 2],
+    failing: bool,
+}
+
+impl HealthTracker {
+    pub fn new(layout: WcLayout) -> Self {
+        let expected = layout.expected();
+        Self {
+            layout,
+            health: DomainHealth {
+                expected_working_counter: expected,
+                working_counter: expected,
+                anomaly: WcAnomaly::None,
+                error_cycles: 0,
+                dropouts: 0,
+            },
+            received: 0,
+            window_dropouts: [0; 2],
+            failing: false,
+        }
+    }
+
+    pub fn health(&self) -> &DomainHealth {
+        &self.health
+    }
+
+    pub fn update(&mut self, working_counter: u32) -> &DomainHealth {
+        if self.received % INTERMITTENT_WINDOW == 0 {
+            self.window_dropouts = [0, self.window_dropouts[0]];
+        }
+        self.received += 1;
+        let failing = working_counter < self.health.expected_working_counter;
+        if failing {
+            self.health.error_cycles += 1;
+            if !self.failing {
+                self.health.dropouts += 1;
+                self.window_dropouts[0] += 1;
+            }
+        }
+        let recent = self.window_dropouts[0] + self.window_dropouts[1];
+        let changed = failing != self.failing || working_counter != self.health.working_counter;
+        if recent >= INTERMITTENT_DROPOUTS {
+            self.health.anomaly = WcAnomaly::Intermittent { dropouts: recent };
+        } else if changed {
+            self.health.anomaly = self.layout.classify(working_counter);
+        }
+        self.failing = failing;
+        self.health.working_counter = working_counter;
+        &self.health
+    }
+}
+
+#[test]
+fn test_domain_health() {
+    let pos = SlavePos::from;
+    let layout = WcLayout::new(vec![(pos(3), 1), (pos(1), 3), (pos(2), 2)]);
+    assert_eq!(layout.expected(), 6);
+    assert_eq!(layout.classify(6), WcAnomaly::None);
+    assert_eq!(
+        layout.classify(4),
+        WcAnomaly::SlaveMissing { slave: pos(2) }
+    );
+    assert_eq!(
+        layout.classify(3),
+        WcAnomaly::SlaveMissing { slave: pos(1) }
+    );
+    assert_eq!(layout.classify(0), WcAnomaly::SegmentDown { first: pos(1) });
+    let layout = WcLayout::new(vec![(pos(0), 3), (pos(1), 3), (pos(2), 3)]);
+    assert_eq!(layout.classify(3), WcAnomaly::SegmentDown { first: pos(1) });
+    assert_eq!(layout.classify(1), WcAnomaly::SlavesMissing { deficit: 8 });
+
+    let mut tracker = HealthTracker::new(layout);
+    assert_eq!(tracker.update(9).anomaly, WcAnomaly::None);
+    assert_eq!(
+        tracker.update(6).anomaly,
+        WcAnomaly::SlaveMissing { slave: pos(0) }
+    );
+    for _ in 0..2 {
+        tracker.update(9);
+        tracker.update(6);
+    }
+    let health = tracker.update(9);
+    assert_eq!(health.anomaly, WcAnomaly::Intermittent { dropouts: 3 });
+    assert_eq!((health.error_cycles, health.dropouts), (3, 3));
+}
@@ -0,0 +1,103 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{
+    channel::{command_channel, CommandReceiver, CommandSender},
+    health::WcAnomaly,
+};
+use crate::types::*;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Timing or bus incident reported by the executor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExecutorEvent {
+    /// The cycle started later than scheduled by more than the threshold.
+    Jitter { cycle: u64, latency: Duration },
+    /// The exchange did not finish before the next cycle was due.
+    DeadlineMiss { cycle: u64, overrun: Duration },
+    /// The working counter of a domain was not complete for the configured
+    /// number of consecutive cycles.
+    WorkingCounter {
+        cycle: u64,
+        domain: DomainIdx,
+        working_counter: u32,
+        wc_state: WcState,
+        anomaly: WcAnomaly,
+    },
+}
+
+/// Limits above which [`ExecutorEvent`]s are raised.
+#[derive(Debug, Clone, Copy)]
+pub struct EventThresholds {
+    pub jitter: Duration,
+    /// Consecutive cycles with an incomplete working counter.
+    pub wc_error_cycles: u32,
+}
+
+impl Default for EventThresholds {
+    fn default() -> Self {
+        Self {
+            jitter: Duration::from_micros(100),
+            wc_error_cycles: 3,
+        }
+    }
+}
+
+pub(crate) type Hook = Box<dyn FnMut(&ExecutorEvent) + Send>;
+
+/// Thread calling the registered hooks for events sent by the cyclic thread.
+pub(crate) struct HookRunner {
+    tx: CommandSender<ExecutorEvent>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HookRunner {
+    pub fn spawn(mut hooks: Vec<Hook>) -> std::io::Result<Self> {
+        let (tx, mut rx): (_, CommandReceiver<ExecutorEvent>) = command_channel(256);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::Builder::new()
+            .name("ethercat-hooks".into())
+            .spawn(move || loop {
+                let stopping = thread_stop.load(Ordering::Acquire);
+                for event in rx.drain() {
+                    for hook in &mut hooks {
+                        hook(&event);
+                    }
+                }
+                if stopping {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(1));
+            })?;
+        Ok(Self {
+            tx,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Hand an event to the hook thread without blocking.
+    ///
+    /// If the hooks cannot keep up, the event is dropped.
+    pub fn notify(&self, event: ExecutorEvent) {
+        let _ = self.tx.try_send(event);
+    }
+}
+
+impl Drop for HookRunner {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,368 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use crate::{
+    backend::{Backend, SdoBatch},
+    types::*,
+};
+use std::{
+    panic,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Slave maintenance configuration.
+#[derive(Debug, Clone)]
+pub struct MaintenanceCfg {
+    /// Time between two scans of the bus.
+    pub interval: Duration,
+    /// The startup SDOs, downloaded again to a slave that comes back.
+    pub startup: SdoBatch,
+    /// State requested from a slave once its SDOs are downloaded.
+    pub target_state: AlState,
+}
+
+impl MaintenanceCfg {
+    pub const fn new(startup: SdoBatch) -> Self {
+        Self {
+            interval: Duration::from_millis(250),
+            startup,
+            target_state: AlState::Op,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Condition {
+    /// Out of INIT, as far as we know configured.
+    Running,
+    /// Gone from the bus, or back in INIT.
+    Lost,
+    /// Back on the bus, waiting for PREOP to download the startup SDOs.
+    Starting,
+    /// Startup SDOs downloaded, on the way to the target state.
+    Rising,
+}
+
+#[derive(Debug, Clone)]
+struct Tracked {
+    slave: SlavePos,
+    id: SlaveId,
+    startup: SdoBatch,
+    condition: Condition,
+}
+
+/// Brings slaves back into operation after they went offline, e.g. when
+/// the power of a drive was cycled.
+///
+/// A slave that lost power comes back in INIT with the default values of
+/// its parameters. Once a slave with the same identity is found at its
+/// position again, it is taken to PREOP, its startup SDOs are downloaded
+/// and the target state is requested, one step per
+/// [`poll`](Self::poll).
+///
+/// [`Maintenance`] runs this in a background thread; an application with
+/// its own supervision loop can poll it directly.
+#[derive(Debug, Clone)]
+pub struct Reconfigurator {
+    slaves: Vec<Tracked>,
+    target_state: AlState,
+}
+
+impl Reconfigurator {
+    /// Track the slaves on the bus now, which are considered configured.
+    ///
+    /// The downloads of `startup` are replayed per slave, in the order they
+    /// were queued.
+    pub fn from_bus(
+        backend: &mut dyn Backend,
+        startup: &SdoBatch,
+        target_state: AlState,
+    ) -> Result<Self> {
+        let count = backend.slave_count()?;
+        let mut slaves = vec![];
+        for pos in 0..count as u16 {
+            let slave = SlavePos::from(pos);
+            slaves.push(Tracked {
+                slave,
+                id: backend.slave_info(slave)?.id,
+                startup: startup.of_slave(slave),
+                condition: Condition::Running,
+            });
+        }
+        Ok(Self {
+            slaves,
+            target_state,
+        })
+    }
+
+    /// The slave is back in operation, or was never lost.
+    pub fn is_running(&self, slave: SlavePos) -> bool {
+        self.slaves
+            .iter()
+            .any(|t| t.slave == slave && t.condition == Condition::Running)
+    }
+
+    /// Check the tracked slaves and advance the reconfiguration of the ones
+    /// that came back.
+    ///
+    /// A failed SDO download is retried by the next call, as is a slave
+    /// whose check failed, which is logged. Returns the slaves that went
+    /// offline (`false`) or reached the target state again (`true`).
+    pub fn poll(&mut self, backend: &mut dyn Backend) -> Result<Vec<(SlavePos, bool)>> {
+        let count = backend.slave_count()?;
+        let mut changes = vec![];
+        for t in &mut self.slaves {
+            match t.advance(backend, count, self.target_state) {
+                Ok((condition, change)) => {
+                    t.condition = condition;
+                    changes.extend(change.map(|running| (t.slave, running)));
+                }
+                Err(err) => log::warn!(
+                    "Maintenance of slave {} failed: {}",
+                    u16::from(t.slave),
+                    err
+                ),
+            }
+        }
+        Ok(changes)
+    }
+}
+
+impl Tracked {
+    /// The next condition of the slave, and whether it went offline
+    /// (`false`) or reached the target state again (`true`).
+    fn advance(
+        &self,
+        backend: &mut dyn Backend,
+        count: usize,
+        target_state: AlState,
+    ) -> Result<(Condition, Option<bool>)> {
+        let pos = u16::from(self.slave);
+        let state = if usize::from(pos) < count {
+            let info = backend.slave_info(self.slave)?;
+            Some(info.al_state).filter(|_| info.id == self.id)
+        } else {
+            None
+        };
+        Ok(match (self.condition, state) {
+            (Condition::Running, Some(AlState::Init | AlState::Boot) | None) => {
+                log::warn!("Slave {} went offline", pos);
+                (Condition::Lost, Some(false))
+            }
+            (_, None) => (Condition::Lost, None),
+            (Condition::Running, Some(_)) => (Condition::Running, None),
+            (Condition::Rising, Some(AlState::Init | AlState::Boot)) => {
+                log::warn!("Slave {} fell back to INIT while reconfiguring", pos);
+                (Condition::Lost, None)
+            }
+            (Condition::Lost, Some(_)) => {
+                log::info!("Slave {} is back, reconfiguring", pos);
+                backend.request_state(self.slave, AlState::PreOp)?;
+                (Condition::Starting, None)
+            }
+            (Condition::Starting, Some(AlState::PreOp)) => {
+                match self.startup.run(backend).into_iter().find_map(Result::err) {
+                    Some(err) => {
+                        log::warn!("Startup SDOs of slave {} failed: {}", pos, err);
+                        (Condition::Starting, None)
+                    }
+                    None => {
+                        let next = next_state(AlState::PreOp, target_state);
+                        backend.request_state(self.slave, next)?;
+                        (Condition::Rising, None)
+                    }
+                }
+            }
+            (Condition::Starting, Some(_)) => {
+                backend.request_state(self.slave, AlState::PreOp)?;
+                (Condition::Starting, None)
+            }
+            (Condition::Rising, Some(state)) if state == target_state => {
+                log::info!("Slave {} is back in {:?}", pos, state);
+                (Condition::Running, Some(true))
+            }
+            (Condition::Rising, Some(state)) => {
+                backend.request_state(self.slave, next_state(state, target_state))?;
+                (Condition::Rising, None)
+            }
+        })
+    }
+}
+
+/// The state to request on the way from `state` to `target`, as a slave
+/// only goes from PREOP to OP through SAFEOP.
+fn next_state(state: AlState, target: AlState) -> AlState {
+    match (state, target) {
+        (AlState::PreOp, AlState::Op) => AlState::SafeOp,
+        _ => target,
+    }
+}
+
+/// An opt-in background thread that reconfigures slaves coming back after
+/// a power loss, see [`Reconfigurator`].
+///
+/// It works on its own backend, which should be a separate handle of the
+/// bus, so the cyclic thread is not involved:
+///
+/// ```ignore
+/// let mut startup = SdoBatch::new();
+/// startup.download(SlavePos::from(3), SdoIdx::new(0x8010, 0x01), false, &[0x10, 0x00]);
+/// for result in startup.run(&mut master) {
+///     result?;
+/// }
+/// let maintenance = Maintenance::spawn(
+///     Master::open(0, MasterAccess::ReadWrite)?,
+///     MaintenanceCfg::new(startup),
+/// )?;
+/// ```
+pub struct Maintenance<B> {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<B>>,
+}
+
+struct Shared {
+    reconfigured: AtomicU64,
+    stop: AtomicBool,
+}
+
+impl<B: Backend + Send + 'static> Maintenance<B> {
+    /// Track the slaves on the bus now and start maintaining them.
+    pub fn spawn(mut backend: B, cfg: MaintenanceCfg) -> Result<Self> {
+        let mut reconfigurator =
+            Reconfigurator::from_bus(&mut backend, &cfg.startup, cfg.target_state)?;
+        let shared = Arc::new(Shared {
+            reconfigured: AtomicU64::new(0),
+            stop: AtomicBool::new(false),
+        });
+        let thread_shared = shared.clone();
+        let thread = thread::Builder::new()
+            .name("ethercat-maintenance".into())
+            .spawn(move || {
+                while !thread_shared.stop.load(Ordering::Acquire) {
+                    thread::sleep(cfg.interval);
+                    match reconfigurator.poll(&mut backend) {
+                        Ok(changes) => {
+                            let back = changes.iter().filter(|(_, running)| *running).count();
+                            thread_shared
+                                .reconfigured
+                                .fetch_add(back as u64, Ordering::Relaxed);
+                        }
+                        Err(err) => log::warn!("Slave maintenance failed: {}", err),
+                    }
+                }
+                backend
+            })?;
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Number of times a slave was brought back into operation.
+    pub fn reconfigured(&self) -> u64 {
+        self.shared.reconfigured.load(Ordering::Relaxed)
+    }
+
+    /// Stop the thread and return its backend.
+    ///
+    /// A panic of the thread is propagated.
+    pub fn stop(mut self) -> B {
+        self.shared.stop.store(true, Ordering::Release);
+        let thread = self.thread.take().expect("the thread runs until stopped");
+        thread
+            .join()
+            .unwrap_or_else(|payload| panic::resume_unwind(payload))
+    }
+}
+
+impl<B> Drop for Maintenance<B> {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[test]
+fn test_reconfigurator() {
+    use crate::backend::{SimMaster, SimSlave};
+
+    let gain = SdoIdx::new(0x8010, 0x01);
+    let mut master = SimMaster::new(
+        (0..3)
+            .map(|_| SimSlave::new("EL7201", SlaveId::new(2, 0x1c21_3052)).object(gain, &[0, 0]))
+            .collect(),
+    );
+    master.activate().unwrap();
+    let mut startup = SdoBatch::new();
+    for pos in 0..3 {
+        startup.download(SlavePos::from(pos), gain, false, &[0x10 + pos as u8, 0]);
+    }
+    assert!(startup.run(&mut master).iter().all(Result::is_ok));
+    for pos in 0..3 {
+        for state in [AlState::PreOp, AlState::SafeOp, AlState::Op] {
+            master.request_state(SlavePos::from(pos), state).unwrap();
+        }
+    }
+    let mut reconfigurator = Reconfigurator::from_bus(&mut master, &startup, AlState::Op).unwrap();
+    assert!(reconfigurator.poll(&mut master).unwrap().is_empty());
+
+    // power cycle of the second slave
+    let slave = SlavePos::from(1);
+    master.slave_mut(slave).unwrap().set_object(gain, &[0, 0]);
+    master.request_state(slave, AlState::Init).unwrap();
+    assert_eq!(reconfigurator.poll(&mut master).unwrap(), [(slave, false)]);
+    assert!(!reconfigurator.is_running(slave));
+
+    let mut polls = 0;
+    while reconfigurator.poll(&mut master).unwrap().is_empty() {
+        polls += 1;
+        assert!(polls < 10);
+    }
+    assert!(reconfigurator.is_running(slave));
+    assert_eq!(master.slave_info(slave).unwrap().al_state, AlState::Op);
+    for pos in 1..3 {
+        let slave = master.slave(SlavePos::from(pos)).unwrap();
+        assert_eq!(slave.object_data(gain), Some(&[0x10 + pos as u8, 0][..]));
+    }
+}
+
+#[test]
+fn test_reconfigurator_errors() {
+    use crate::backend::{reg, SimMaster, SimSlave};
+
+    let mut master = SimMaster::new(
+        (0..3)
+            .map(|_| SimSlave::new("EL7201", SlaveId::new(2, 0x1c21_3052)))
+            .collect(),
+    );
+    master.activate().unwrap();
+    let mut reconfigurator =
+        Reconfigurator::from_bus(&mut master, &SdoBatch::new(), AlState::PreOp).unwrap();
+    for pos in 0..3 {
+        master
+            .request_state(SlavePos::from(pos), AlState::PreOp)
+            .unwrap();
+    }
+
+    // the first and last slaves cannot be read, the one between is lost
+    for pos in [0, 2] {
+        let slave = SlavePos::from(pos);
+        master
+            .write_register(slave, reg::AL_STATUS, &[0x05, 0])
+            .unwrap();
+        assert!(master.slave_info(slave).is_err());
+    }
+    let lost = SlavePos::from(1);
+    master.request_state(lost, AlState::Init).unwrap();
+    assert_eq!(reconfigurator.poll(&mut master).unwrap(), [(lost, false)]);
+    assert!(reconfigurator.is_running(SlavePos::from(0)));
+    assert!(reconfigurator.is_running(SlavePos::from(2)));
+    assert!(!reconfigurator.is_running(lost));
+}
@@ -0,0 +1,95 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use crate::types::*;
+use std::{io, ptr};
+
+const PAGE_SIZE: usize = 4096;
+const STACK_CHUNK: usize = 64 * 1024;
+
+/// Amount of memory prepared by [`lock_memory_with`].
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryLockCfg {
+    /// Bytes of stack to prefault in the calling thread.
+    pub stack_size: usize,
+    /// Bytes of heap to allocate, touch and keep in the process.
+    pub heap_size: usize,
+}
+
+impl Default for MemoryLockCfg {
+    fn default() -> Self {
+        Self {
+            stack_size: 512 * 1024,
+            heap_size: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// Lock the process memory and prefault stack and heap with default sizes.
+///
+/// See [`lock_memory_with`].
+pub fn lock_memory() -> Result<()> {
+    lock_memory_with(&MemoryLockCfg::default())
+}
+
+/// Avoid page faults in the cyclic thread.
+///
+/// All current and future pages are locked into RAM, the stack of the calling
+/// thread is prefaulted and a heap arena is preheated so that later
+/// allocations do not need fresh pages. Finally it is verified that touching
+/// the prepared memory again does not cause page faults.
+pub fn lock_memory_with(cfg: &MemoryLockCfg) -> Result<()> {
+    #[cfg(target_env = "gnu")]
+    unsafe {
+        // keep freed memory in the process and never serve allocations by mmap
+        libc::mallopt(libc::M_TRIM_THRESHOLD, -1);
+        libc::mallopt(libc::M_MMAP_MAX, 0);
+    }
+    if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } != 0 {
+        return Err(Error::Io(io::Error::last_os_error()));
+    }
+    prefault_stack(cfg.stack_size);
+    let mut heap = vec![0_u8; cfg.heap_size];
+    touch(&mut heap);
+
+    let faults = minor_faults();
+    prefault_stack(cfg.stack_size);
+    touch(&mut heap);
+    let new_faults = minor_faults() - faults;
+    if new_faults > 0 {
+        log::warn!(
+            "Memory is not fully locked: {} page faults after prefaulting",
+            new_faults
+        );
+    } else {
+        log::debug!(
+            "Locked memory, prefaulted {} KiB stack and {} KiB heap",
+            cfg.stack_size / 1024,
+            cfg.heap_size / 1024
+        );
+    }
+    Ok(())
+}
+
+fn touch(buf: &mut [u8]) {
+    for i in (0..buf.len()).step_by(PAGE_SIZE) {
+        unsafe { ptr::write_volatile(buf.as_mut_ptr().add(i), 0) };
+    }
+}
+
+#[inline(never)]
+fn prefault_stack(size: usize) {
+    let mut chunk = [0_u8; STACK_CHUNK];
+    touch(&mut chunk);
+    if size > STACK_CHUNK {
+        prefault_stack(size - STACK_CHUNK);
+    }
+    // keep the frame alive until the recursion returned
+    unsafe { ptr::read_volatile(chunk.as_ptr()) };
+}
+
+fn minor_faults() -> i64 {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    unsafe { libc::getrusage(libc::RUSAGE_THREAD, &mut usage) };
+    usage.ru_minflt as i64
+}
@@ -0,0 +1,234 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::{
+    channel::{command_channel, CommandReceiver, CommandSender},
+    time,
+};
+use std::{
+    fmt::{self, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const MAX_ARGS: usize = 6;
+
+/// Log to an [`RtLogger`] from the cyclic thread.
+///
+/// Only the format string and the (copyable) arguments are stored; the
+/// formatting happens on the drain thread. At most six arguments are
+/// recorded, further ones are ignored.
+///
+/// ```ignore
+/// rtlog!(logger, log::Level::Warn, "slave {} lost, wc = {}", pos, wc);
+/// ```
+#[macro_export]
+macro_rules! rtlog {
+    ($logger:expr, $level:expr, $fmt:literal $(, $arg:expr)* $(,)?) => {
+        $logger.log(
+            $level,
+            module_path!(),
+            $fmt,
+            &[$($crate::runtime::RtArg::from($arg)),*],
+        )
+    };
+}
+
+/// An argument of an [`rtlog!`] record.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RtArg {
+    None,
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Str(&'static str),
+}
+
+macro_rules! rt_arg_from {
+    ($variant:ident, $target:ty, $($t:ty),*) => {
+        $(impl From<$t> for RtArg {
+            fn from(v: $t) -> Self {
+                RtArg::$variant(v as $target)
+            }
+        })*
+    };
+}
+
+rt_arg_from!(I64, i64, i8, i16, i32, i64, isize);
+rt_arg_from!(U64, u64, u8, u16, u32, u64, usize);
+rt_arg_from!(F64, f64, f32, f64);
+
+impl From<bool> for RtArg {
+    fn from(v: bool) -> Self {
+        RtArg::Bool(v)
+    }
+}
+
+impl From<&'static str> for RtArg {
+    fn from(v: &'static str) -> Self {
+        RtArg::Str(v)
+    }
+}
+
+impl fmt::Display for RtArg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RtArg::None => Ok(()),
+            RtArg::Bool(v) => v.fmt(f),
+            RtArg::I64(v) => v.fmt(f),
+            RtArg::U64(v) => v.fmt(f),
+            RtArg::F64(v) => v.fmt(f),
+            RtArg::Str(v) => v.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RtRecord {
+    level: log::Level,
+    target: &'static str,
+    fmt: &'static str,
+    args: [RtArg; MAX_ARGS],
+    time: Duration,
+}
+
+/// Create a realtime logger with room for `capacity` pending records.
+///
+/// The returned drain thread forwards the records to the `log` crate.
+pub fn rt_logger(capacity: usize) -> std::io::Result<(RtLogger, RtLogDrain)> {
+    let (tx, rx) = command_channel(capacity);
+    let dropped = Arc::new(AtomicU64::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread = {
+        let dropped = dropped.clone();
+        let stop = stop.clone();
+        thread::Builder::new()
+            .name("ethercat-rtlog".into())
+            .spawn(move || drain(rx, &dropped, &stop))?
+    };
+    Ok((
+        RtLogger { tx, dropped },
+        RtLogDrain {
+            stop,
+            thread: Some(thread),
+        },
+    ))
+}
+
+/// Wait-free logger handle for the cyclic thread.
+#[derive(Clone)]
+pub struct RtLogger {
+    tx: CommandSender<RtRecord>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// The thread formatting the records of an [`RtLogger`].
+///
+/// Pending records are flushed when it is dropped.
+pub struct RtLogDrain {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RtLogger {
+    /// Enqueue a record; use the [`rtlog!`] macro instead of calling this
+    /// directly.
+    pub fn log(&self, level: log::Level, target: &'static str, fmt: &'static str, args: &[RtArg]) {
+        if level > log::max_level() {
+            return;
+        }
+        let mut record = RtRecord {
+            level,
+            target,
+            fmt,
+            args: [RtArg::None; MAX_ARGS],
+            time: time::monotonic_now(),
+        };
+        for (slot, arg) in record.args.iter_mut().zip(args) {
+            *slot = *arg;
+        }
+        if self.tx.try_send(record).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Number of records lost because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for RtLogDrain {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn drain(mut rx: CommandReceiver<RtRecord>, dropped: &AtomicU64, stop: &AtomicBool) {
+    let mut reported = 0;
+    let mut line = String::new();
+    loop {
+        let stopping = stop.load(Ordering::Acquire);
+        for record in rx.drain() {
+            line.clear();
+            format_record(&mut line, record.fmt, &record.args);
+            log::log!(
+                target: record.target,
+                record.level,
+                "[{}.{:06}] {}",
+                record.time.as_secs(),
+                record.time.subsec_micros(),
+                line
+            );
+        }
+        let lost = dropped.load(Ordering::Relaxed);
+        if lost != reported {
+            log::warn!("rtlog: {} records dropped", lost - reported);
+            reported = lost;
+        }
+        if stopping {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Substitute the `{}` placeholders of `fmt` by `args`.
+fn format_record(out: &mut String, fmt: &str, args: &[RtArg]) {
+    let mut args = args.iter();
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                out.push(c);
+                chars.next();
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                if let Some(arg) = args.next() {
+                    let _ = write!(out, "{}", arg);
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+}
+
+#[test]
+fn test_format_record() {
+    let mut out = String::new();
+    let args = [RtArg::from(3_u16), RtArg::from(-1.5), RtArg::from("op")];
+    format_record(&mut out, "slave {} at {} in {{{}}}", &args);
+    assert_eq!(out, "slave 3 at -1.5 in {op}");
+    out.clear();
+    format_record(&mut out, "{} {}", &[RtArg::from(true)]);
+    assert_eq!(out, "true ");
+}
@@ -0,0 +1,339 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use super::snapshot::Snapshot;
+use crate::{
+    field::{Field, PdoData},
+    types::*,
+};
+use std::{
+    convert::TryFrom,
+    fs::{self, OpenOptions},
+    io,
+    mem::size_of,
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    slice,
+    sync::atomic::{fence, AtomicU64, Ordering},
+};
+
+const MAGIC: [u8; 8] = *b"ECATSHM1";
+const HEADER: usize = 64;
+
+/// Start of a shared image segment.
+///
+/// All numbers are little endian; the image follows at byte 64, padded to a
+/// multiple of 8 bytes, then the layout descriptor.
+#[repr(C)]
+struct Header {
+    magic: [u8; 8],
+    /// Odd while the image is being written.
+    lock: AtomicU64,
+    seq: AtomicU64,
+    dc_time: AtomicU64,
+    image_size: u64,
+    layout_size: u64,
+    _reserved: [u64; 2],
+}
+
+/// A named PDO entry in the layout descriptor of a shared image.
+///
+/// The descriptor is text, one entry per line with tab-separated name, byte
+/// and bit offset, bit length and type (`u16`, `f32`, `bool`, ...).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutEntry {
+    pub name: String,
+    pub offset: Offset,
+    pub bit_len: u32,
+    pub data_type: String,
+}
+
+/// Builder for a [`SharedImage`].
+#[derive(Debug, Clone)]
+pub struct SharedImageBuilder {
+    name: String,
+    domain: DomainIdx,
+    layout: Vec<LayoutEntry>,
+}
+
+/// Publishes the process image of a domain into a named shared-memory
+/// segment (`/dev/shm/<name>`), for other processes on the same machine.
+///
+/// Like [`SnapshotWriter`](super::SnapshotWriter), the writer never blocks
+/// and readers retry if the writer overtook them. The segment is removed when
+/// the writer is dropped.
+pub struct SharedImage {
+    domain: DomainIdx,
+    path: PathBuf,
+    size: usize,
+    map: memmap::MmapMut,
+}
+
+/// Reads a [`SharedImage`] published by another process.
+pub struct SharedImageReader {
+    size: usize,
+    layout: Vec<LayoutEntry>,
+    map: memmap::Mmap,
+}
+
+fn segment_path(name: &str) -> PathBuf {
+    PathBuf::from("/dev/shm").join(name.trim_start_matches('/'))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl SharedImageBuilder {
+    /// Describe a PDO entry in the layout descriptor.
+    ///
+    /// Panics if the field belongs to another domain.
+    pub fn field<T: PdoData>(mut self, name: impl Into<String>, field: Field<T>) -> Self {
+        assert_eq!(field.domain, self.domain, "field of another domain");
+        self.layout.push(LayoutEntry {
+            name: name.into(),
+            offset: field.offset,
+            bit_len: T::BITS,
+            data_type: std::any::type_name::<T>().into(),
+        });
+        self
+    }
+
+    pub const fn domain(&self) -> DomainIdx {
+        self.domain
+    }
+
+    /// Create the segment for an image of `size` bytes, replacing an
+    /// existing one of the same name.
+    pub fn create(self, size: usize) -> io::Result<SharedImage> {
+        let layout = self
+            .layout
+            .iter()
+            .map(|e| {
+                format!(
+                    "{}\t{}\t{}\t{}\t{}\n",
+                    e.name, e.offset.byte, e.offset.bit, e.bit_len, e.data_type
+                )
+            })
+            .collect::<String>();
+        let padded = (size + 7) / 8 * 8;
+        let path = segment_path(&self.name);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o644)
+            .open(&path)?;
+        file.set_len((HEADER + padded + layout.len()) as u64)?;
+        let mut map = unsafe { memmap::MmapMut::map_mut(&file)? };
+        map[HEADER + padded..].copy_from_slice(layout.as_bytes());
+        map[..8].copy_from_slice(&MAGIC);
+        map[32..40].copy_from_slice(&(size as u64).to_le_bytes());
+        map[40..48].copy_from_slice(&(layout.len() as u64).to_le_bytes());
+        Ok(SharedImage {
+            domain: self.domain,
+            path,
+            size,
+            map,
+        })
+    }
+}
+
+impl SharedImage {
+    /// Start describing the segment `name` for the image of `domain`.
+    pub fn builder(name: impl Into<String>, domain: DomainIdx) -> SharedImageBuilder {
+        SharedImageBuilder {
+            name: name.into(),
+            domain,
+            layout: vec![],
+        }
+    }
+
+    pub const fn domain(&self) -> DomainIdx {
+        self.domain
+    }
+
+    pub const fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Publish `data` as the image of cycle `seq`, sent at `dc_time`.
+    ///
+    /// `data` is truncated or zero-padded to the image size.
+    pub fn publish(&mut self, seq: u64, dc_time: u64, data: &[u8]) {
+        let (header, words) = parts(self.map.as_mut_ptr(), self.map.len(), self.size);
+        header.lock.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        let data = &data[..data.len().min(self.size)];
+        for (i, word) in words.iter().enumerate() {
+            let mut bytes = [0; 8];
+            let chunk = data.get(i * 8..).unwrap_or(&[]);
+            let n = chunk.len().min(8);
+            bytes[..n].copy_from_slice(&chunk[..n]);
+            word.store(u64::from_le_bytes(bytes), Ordering::Relaxed);
+        }
+        header.seq.store(seq, Ordering::Relaxed);
+        header.dc_time.store(dc_time, Ordering::Relaxed);
+        header.lock.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl Drop for SharedImage {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// End of the image words of a segment with an image of `size` bytes.
+fn words_end(size: usize) -> Option<usize> {
+    let padded = size.checked_add(7)? / 8 * 8;
+    padded.checked_add(HEADER)
+}
+
+/// The header and image words of a mapped segment of `len` bytes.
+fn parts<'a>(ptr: *const u8, len: usize, size: usize) -> (&'a Header, &'a [AtomicU64]) {
+    assert!(
+        words_end(size).map_or(false, |end| end <= len),
+        "image outside the mapped segment"
+    );
+    // the mapping is page aligned, and only accessed through atomics
+    unsafe {
+        (
+            &*(ptr as *const Header),
+            slice::from_raw_parts(ptr.add(HEADER) as *const AtomicU64, (size + 7) / 8),
+        )
+    }
+}
+
+impl SharedImageReader {
+    /// Open the segment published under `name`.
+    pub fn open(name: &str) -> io::Result<Self> {
+        let file = fs::File::open(segment_path(name))?;
+        let map = unsafe { memmap::Mmap::map(&file)? };
+        if map.len() < HEADER || map[..8] != MAGIC {
+            return Err(invalid("not a shared process image"));
+        }
+        // the header comes from another process, trust no length
+        let len_at = |pos: usize| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&map[pos..pos + 8]);
+            usize::try_from(u64::from_le_bytes(bytes)).ok()
+        };
+        let truncated = || invalid("truncated shared process image");
+        let size = len_at(32).ok_or_else(truncated)?;
+        let start = words_end(size)
+            .filter(|end| *end <= map.len())
+            .ok_or_else(truncated)?;
+        let layout = len_at(40)
+            .and_then(|len| start.checked_add(len))
+            .and_then(|end| map.get(start..end))
+            .ok_or_else(truncated)?;
+        let layout = std::str::from_utf8(layout)
+            .map_err(|_| invalid("invalid layout descriptor"))?
+            .lines()
+            .map(parse_layout_entry)
+            .collect::<Option<_>>()
+            .ok_or_else(|| invalid("invalid layout descriptor"))?;
+        Ok(Self { size, layout, map })
+    }
+
+    pub const fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The PDO entries described by the writer.
+    pub fn layout(&self) -> &[LayoutEntry] {
+        &self.layout
+    }
+
+    /// Copy the latest published image into `target` and return its
+    /// sequence number and DC time.
+    pub fn read_into(&self, target: &mut [u8]) -> (u64, u64) {
+        let (header, words) = parts(self.map.as_ptr(), self.map.len(), self.size);
+        loop {
+            let before = header.lock.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let seq = header.seq.load(Ordering::Relaxed);
+            let dc_time = header.dc_time.load(Ordering::Relaxed);
+            for (i, word) in words.iter().enumerate() {
+                let bytes = word.load(Ordering::Relaxed).to_le_bytes();
+                if let Some(chunk) = target.get_mut(i * 8..) {
+                    let n = chunk.len().min(8);
+                    chunk[..n].copy_from_slice(&bytes[..n]);
+                }
+            }
+            fence(Ordering::Acquire);
+            if header.lock.load(Ordering::Relaxed) == before {
+                return (seq, dc_time);
+            }
+        }
+    }
+
+    pub fn read(&self) -> Snapshot {
+        let mut data = vec![0; self.size];
+        let (seq, dc_time) = self.read_into(&mut data);
+        Snapshot { seq, dc_time, data }
+    }
+}
+
+fn parse_layout_entry(line: &str) -> Option<LayoutEntry> {
+    let mut parts = line.split('\t');
+    let entry = LayoutEntry {
+        name: parts.next()?.into(),
+        offset: Offset {
+            byte: parts.next()?.parse().ok()?,
+            bit: parts.next()?.parse().ok()?,
+        },
+        bit_len: parts.next()?.parse().ok()?,
+        data_type: parts.next()?.into(),
+    };
+    Some(entry)
+}
+
+const _: () = assert!(size_of::<Header>() == HEADER);
+
+#[test]
+fn test_shared_image() {
+    let name = format!("ethercat-test-{}", std::process::id());
+    let mut image = SharedImage::builder(name.as_str(), 0.into())
+        .field(
+            "status",
+            Field::<u16>::new(0.into(), Offset { byte: 0, bit: 0 }),
+        )
+        .field(
+            "ready",
+            Field::<bool>::new(0.into(), Offset { byte: 2, bit: 3 }),
+        )
+        .create(11)
+        .unwrap();
+    image.publish(5, 1000, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+
+    let reader = SharedImageReader::open(&name).unwrap();
+    assert_eq!(reader.size(), 11);
+    assert_eq!(reader.layout().len(), 2);
+    assert_eq!(reader.layout()[1].offset, Offset { byte: 2, bit: 3 });
+    assert_eq!(reader.layout()[1].data_type, "bool");
+    let snap = reader.read();
+    assert_eq!((snap.seq, snap.dc_time), (5, 1000));
+    assert_eq!(snap.data, (1..=11).collect::<Vec<u8>>());
+
+    image.publish(6, 2000, &[9; 3]);
+    assert_eq!(reader.read().data, [9, 9, 9, 0, 0, 0, 0, 0, 0, 0, 0]);
+    drop(image);
+    assert!(SharedImageReader::open(&name).is_err());
+
+    // sizes in the header that exceed the segment
+    for (size, layout) in [(u64::MAX - 3, 0), (1 << 20, 0), (8, u64::MAX)] {
+        let mut header = vec![0; HEADER + 8];
+        header[..8].copy_from_slice(&MAGIC);
+        header[32..40].copy_from_slice(&size.to_le_bytes());
+        header[40..48].copy_from_slice(&layout.to_le_bytes());
+        fs::write(segment_path(&name), header).unwrap();
+        assert!(SharedImageReader::open(&name).is_err());
+    }
+    fs::remove_file(segment_path(&name)).unwrap();
+}
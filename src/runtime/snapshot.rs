@@ -0,0 +1,202 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use crate::{
+    field::{Field, PdoData},
+    types::{DomainIdx, Error, Result},
+};
+use std::sync::{
+    atomic::{fence, AtomicU64, AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Create a double buffer for process images of `size` bytes.
+///
+/// The writer never blocks; readers retry if the writer overtook them.
+pub fn snapshot_buffer(size: usize) -> (SnapshotWriter, SnapshotReader) {
+    let shared = Arc::new(DoubleBuffer {
+        size,
+        latest: AtomicUsize::new(0),
+        sides: [Side::new(size), Side::new(size)],
+    });
+    (
+        SnapshotWriter {
+            shared: shared.clone(),
+        },
+        SnapshotReader { shared },
+    )
+}
+
+/// A consistent copy of a process image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Sequence number of the cycle the data was taken from.
+    pub seq: u64,
+    /// DC time in ns at which the image was sent.
+    pub dc_time: u64,
+    pub data: Vec<u8>,
+}
+
+/// Writing side of a snapshot double buffer, owned by the cyclic thread.
+pub struct SnapshotWriter {
+    shared: Arc<DoubleBuffer>,
+}
+
+/// Reading side of a snapshot double buffer, for non-realtime consumers.
+#[derive(Clone)]
+pub struct SnapshotReader {
+    shared: Arc<DoubleBuffer>,
+}
+
+struct DoubleBuffer {
+    size: usize,
+    latest: AtomicUsize,
+    sides: [Side; 2],
+}
+
+struct Side {
+    // odd while being written
+    lock: AtomicU64,
+    seq: AtomicU64,
+    dc_time: AtomicU64,
+    words: Box<[AtomicU64]>,
+}
+
+impl Side {
+    fn new(size: usize) -> Self {
+        Self {
+            lock: AtomicU64::new(0),
+            seq: AtomicU64::new(0),
+            dc_time: AtomicU64::new(0),
+            words: (0..(size + 7) / 8).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+}
+
+impl SnapshotWriter {
+    pub fn size(&self) -> usize {
+        self.shared.size
+    }
+
+    /// Publish `data` as the image of cycle `seq`, sent at `dc_time`.
+    ///
+    /// `data` is truncated or zero-padded to the buffer size.
+    pub fn publish(&mut self, seq: u64, dc_time: u64, data: &[u8]) {
+        let buf = &*self.shared;
+        let target = 1 - buf.latest.load(Ordering::Relaxed);
+        let side = &buf.sides[target];
+        side.lock.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        let data = &data[..data.len().min(buf.size)];
+        for (i, word) in side.words.iter().enumerate() {
+            let mut bytes = [0; 8];
+            let chunk = data.get(i * 8..).unwrap_or(&[]);
+            let n = chunk.len().min(8);
+            bytes[..n].copy_from_slice(&chunk[..n]);
+            word.store(u64::from_le_bytes(bytes), Ordering::Relaxed);
+        }
+        side.seq.store(seq, Ordering::Relaxed);
+        side.dc_time.store(dc_time, Ordering::Relaxed);
+        side.lock.fetch_add(1, Ordering::Release);
+        buf.latest.store(target, Ordering::Release);
+    }
+}
+
+impl SnapshotReader {
+    pub fn size(&self) -> usize {
+        self.shared.size
+    }
+
+    /// Copy the latest published image into `target` and return its
+    /// sequence number and DC time.
+    pub fn read_into(&self, target: &mut [u8]) -> (u64, u64) {
+        let buf = &*self.shared;
+        loop {
+            let side = &buf.sides[buf.latest.load(Ordering::Acquire)];
+            let before = side.lock.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let seq = side.seq.load(Ordering::Relaxed);
+            let dc_time = side.dc_time.load(Ordering::Relaxed);
+            for (i, word) in side.words.iter().enumerate() {
+                let bytes = word.load(Ordering::Relaxed).to_le_bytes();
+                if let Some(chunk) = target.get_mut(i * 8..) {
+                    let n = chunk.len().min(8);
+                    chunk[..n].copy_from_slice(&bytes[..n]);
+                }
+            }
+            fence(Ordering::Acquire);
+            if side.lock.load(Ordering::Relaxed) == before {
+                return (seq, dc_time);
+            }
+        }
+    }
+
+    pub fn read(&self) -> Snapshot {
+        let mut data = vec![0; self.shared.size];
+        let (seq, dc_time) = self.read_into(&mut data);
+        Snapshot { seq, dc_time, data }
+    }
+}
+
+/// Index of the reader of the domain of `field` in `readers`, for servers
+/// that publish fields from the snapshots of several domains.
+///
+/// Fails with [`Error::NoDomain`] if there is no reader of the domain, or
+/// [`Error::OutsideSnapshot`] if the field is outside of its snapshots.
+pub fn snapshot_index<T: PdoData>(
+    readers: &[(DomainIdx, SnapshotReader)],
+    field: &Field<T>,
+) -> Result<usize> {
+    let index = readers
+        .iter()
+        .position(|(idx, _)| *idx == field.domain)
+        .ok_or(Error::NoDomain)?;
+    if field.end() > readers[index].1.size() {
+        return Err(Error::OutsideSnapshot(usize::from(field.domain)));
+    }
+    Ok(index)
+}
+
+#[test]
+fn test_snapshot_buffer() {
+    let (mut writer, reader) = snapshot_buffer(11);
+    assert_eq!(
+        reader.read(),
+        Snapshot {
+            seq: 0,
+            dc_time: 0,
+            data: vec![0; 11]
+        }
+    );
+    writer.publish(1, 1000, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+    assert_eq!(reader.read().data, (1..=11).collect::<Vec<u8>>());
+    writer.publish(2, 2000, &[9; 3]);
+    let snap = reader.read();
+    assert_eq!(snap.seq, 2);
+    assert_eq!(snap.dc_time, 2000);
+    assert_eq!(snap.data, [9, 9, 9, 0, 0, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn test_snapshot_index() {
+    use crate::types::Offset;
+
+    let readers = [
+        (DomainIdx::from(2), snapshot_buffer(4).1),
+        (DomainIdx::from(0), snapshot_buffer(2).1),
+    ];
+    let field = |domain: usize, byte| Field::<u16>::new(domain.into(), Offset { byte, bit: 0 });
+    assert_eq!(snapshot_index(&readers, &field(0, 0)).unwrap(), 1);
+    assert_eq!(snapshot_index(&readers, &field(2, 2)).unwrap(), 0);
+    assert!(matches!(
+        snapshot_index(&readers, &field(0, 1)),
+        Err(Error::OutsideSnapshot(0))
+    ));
+    assert!(matches!(
+        snapshot_index(&readers, &field(1, 0)),
+        Err(Error::NoDomain)
+    ));
+}
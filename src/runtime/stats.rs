@@ -0,0 +1,130 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use std::time::Duration;
+
+/// A phase of the cyclic exchange.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    Receive,
+    Process,
+    User,
+    Queue,
+    Send,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 5] = [
+        Phase::Receive,
+        Phase::Process,
+        Phase::User,
+        Phase::Queue,
+        Phase::Send,
+    ];
+}
+
+/// Busy time statistics of one phase over the recorded window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PhaseSummary {
+    pub last: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+}
+
+/// Rolling busy time statistics of the phases of the last cycles.
+///
+/// The storage is preallocated, so recording does not allocate.
+#[derive(Debug, Clone)]
+pub struct CycleStats {
+    samples: [Box<[u32]>; 5],
+    sums: [u64; 5],
+    pos: usize,
+    len: usize,
+}
+
+impl CycleStats {
+    /// Create statistics covering the last `window` cycles.
+    pub fn new(window: usize) -> Self {
+        let window = window.max(1);
+        let buf = || vec![0; window].into_boxed_slice();
+        Self {
+            samples: [buf(), buf(), buf(), buf(), buf()],
+            sums: [0; 5],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    /// Record the busy times of one cycle, indexed like [`Phase::ALL`].
+    pub fn record(&mut self, times: [Duration; 5]) {
+        let window = self.samples[0].len();
+        for (i, time) in times.iter().enumerate() {
+            let ns = time.as_nanos().min(u128::from(u32::MAX)) as u32;
+            let old = std::mem::replace(&mut self.samples[i][self.pos], ns);
+            if self.len == window {
+                self.sums[i] -= u64::from(old);
+            }
+            self.sums[i] += u64::from(ns);
+        }
+        self.pos = (self.pos + 1) % window;
+        self.len = (self.len + 1).min(window);
+    }
+
+    /// Number of cycles currently covered.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn phase(&self, phase: Phase) -> PhaseSummary {
+        let i = phase as usize;
+        if self.len == 0 {
+            return PhaseSummary::default();
+        }
+        let window = self.samples[i].len();
+        let samples = &self.samples[i][..self.len];
+        let last = self.samples[i][(self.pos + window - 1) % window];
+        let ns = |v: u32| Duration::from_nanos(u64::from(v));
+        PhaseSummary {
+            last: ns(last),
+            min: ns(samples.iter().copied().min().unwrap_or(0)),
+            max: ns(samples.iter().copied().max().unwrap_or(0)),
+            mean: Duration::from_nanos(self.sums[i] / self.len as u64),
+        }
+    }
+
+    /// Busy time of the whole cycle, summed over all phases.
+    pub fn total(&self) -> PhaseSummary {
+        Phase::ALL
+            .iter()
+            .map(|p| self.phase(*p))
+            .fold(PhaseSummary::default(), |acc, s| PhaseSummary {
+                last: acc.last + s.last,
+                min: acc.min + s.min,
+                max: acc.max + s.max,
+                mean: acc.mean + s.mean,
+            })
+    }
+}
+
+#[test]
+fn test_cycle_stats() {
+    let us = Duration::from_micros;
+    let mut stats = CycleStats::new(3);
+    assert!(stats.is_empty());
+    for i in 1..=4 {
+        stats.record([us(i), us(10 * i), us(0), us(0), us(0)]);
+    }
+    assert_eq!(stats.len(), 3);
+    let receive = stats.phase(Phase::Receive);
+    assert_eq!(receive.last, us(4));
+    assert_eq!(receive.min, us(2));
+    assert_eq!(receive.max, us(4));
+    assert_eq!(receive.mean, us(3));
+    assert_eq!(stats.phase(Phase::Process).mean, us(30));
+    assert_eq!(stats.total().last, us(44));
+}
@@ -0,0 +1,67 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+#[cfg(target_os = "linux")]
+use std::io;
+use std::time::Duration;
+
+/// Current time of the monotonic clock.
+#[cfg(unix)]
+pub(crate) fn monotonic_now() -> Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+/// Current time since the Unix epoch; there is no monotonic clock with a
+/// fixed origin in `std`.
+#[cfg(not(unix))]
+pub(crate) fn monotonic_now() -> Duration {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Sleep until the monotonic clock reaches `deadline`.
+#[cfg(target_os = "linux")]
+pub(crate) fn sleep_until(deadline: Duration) -> io::Result<()> {
+    let ts = libc::timespec {
+        tv_sec: deadline.as_secs() as libc::time_t,
+        tv_nsec: deadline.subsec_nanos() as _,
+    };
+    loop {
+        let res = unsafe {
+            libc::clock_nanosleep(
+                libc::CLOCK_MONOTONIC,
+                libc::TIMER_ABSTIME,
+                &ts,
+                std::ptr::null_mut(),
+            )
+        };
+        match res {
+            0 => return Ok(()),
+            libc::EINTR => continue,
+            err => return Err(io::Error::from_raw_os_error(err)),
+        }
+    }
+}
+
+/// Seconds between the Unix epoch and the EtherCAT epoch (2000-01-01).
+#[cfg(target_os = "linux")]
+const EC_EPOCH_OFFSET: u64 = 946_684_800;
+
+/// Current wall clock time in nanoseconds since the EtherCAT epoch, as used
+/// for the application time of distributed clocks.
+#[cfg(target_os = "linux")]
+pub(crate) fn dc_now() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut ts) };
+    (ts.tv_sec as u64 - EC_EPOCH_OFFSET) * 1_000_000_000 + ts.tv_nsec as u64
+}
@@ -0,0 +1,235 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+use crate::{
+    backend::Backend,
+    master::{Master, MasterAccess},
+    types::*,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Process data watchdog configuration.
+#[derive(Debug, Clone)]
+pub struct WatchdogCfg {
+    pub master_idx: MasterIdx,
+    /// Expected time between two process data exchanges.
+    pub cycle_time: Duration,
+    /// Number of cycles without exchange that are tolerated.
+    pub max_missed_cycles: u32,
+    /// Slaves to put into SafeOp; `None` means all slaves on the bus.
+    pub slaves: Option<Vec<SlavePos>>,
+}
+
+impl WatchdogCfg {
+    pub const fn new(master_idx: MasterIdx, cycle_time: Duration, max_missed_cycles: u32) -> Self {
+        Self {
+            master_idx,
+            cycle_time,
+            max_missed_cycles,
+            slaves: None,
+        }
+    }
+}
+
+/// A supervisor thread that requests SafeOp if the application stops
+/// exchanging process data.
+///
+/// This is a software safety net on top of the sync manager watchdogs of the
+/// slaves: it uses its own handle to the master, so it keeps working if the
+/// application thread hangs.
+pub struct Watchdog {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Cheap handle to feed a [`Watchdog`] from the cyclic thread.
+#[derive(Clone)]
+pub struct WatchdogFeeder {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    exchanges: AtomicU64,
+    tripped: AtomicBool,
+    rearm: AtomicBool,
+    stop: AtomicBool,
+}
+
+impl Watchdog {
+    pub fn spawn(cfg: WatchdogCfg) -> Result<Self> {
+        let mut master = Master::open(cfg.master_idx, MasterAccess::ReadWrite)?;
+        let shared = Arc::new(Shared {
+            exchanges: AtomicU64::new(0),
+            tripped: AtomicBool::new(false),
+            rearm: AtomicBool::new(false),
+            stop: AtomicBool::new(false),
+        });
+        let thread_shared = shared.clone();
+        let thread = thread::Builder::new()
+            .name("ethercat-watchdog".into())
+            .spawn(move || supervise(&mut master, &cfg, &thread_shared))?;
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Signal that process data has been exchanged.
+    pub fn feed(&self) {
+        self.shared.exchanges.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn feeder(&self) -> WatchdogFeeder {
+        WatchdogFeeder {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Returns `true` once the watchdog has requested SafeOp.
+    pub fn is_tripped(&self) -> bool {
+        self.shared.tripped.load(Ordering::Acquire)
+    }
+
+    /// Re-enable supervision after a trip.
+    ///
+    /// Bringing the slaves back to Op is left to the application.
+    pub fn rearm(&self) {
+        self.shared.rearm.store(true, Ordering::Release);
+    }
+}
+
+impl WatchdogFeeder {
+    pub fn feed(&self) {
+        self.shared.exchanges.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn supervise(master: &mut Master, cfg: &WatchdogCfg, shared: &Shared) {
+    let mut monitor = MissMonitor::new(cfg.max_missed_cycles);
+    while !shared.stop.load(Ordering::Acquire) {
+        thread::sleep(cfg.cycle_time);
+        if shared.rearm.swap(false, Ordering::AcqRel) {
+            monitor.reset();
+            shared.tripped.store(false, Ordering::Release);
+        }
+        let exchanges = shared.exchanges.load(Ordering::Relaxed);
+        if monitor.update(exchanges) && !shared.tripped.load(Ordering::Acquire) {
+            log::error!(
+                "No process data exchanged for {} cycles: requesting SafeOp",
+                monitor.missed
+            );
+            trace_event!(ERROR, missed = monitor.missed, "watchdog tripped");
+            let errors = request_safe_op(master, cfg.slaves.as_deref());
+            for err in &errors {
+                log::error!("Watchdog could not request SafeOp: {}", err);
+            }
+            // retried in the next period unless all requests went out
+            shared.tripped.store(errors.is_empty(), Ordering::Release);
+        }
+    }
+}
+
+/// Request SafeOp from all slaves, also after a failed request, and return
+/// the errors.
+fn request_safe_op(backend: &mut dyn Backend, slaves: Option<&[SlavePos]>) -> Vec<Error> {
+    let slaves = match slaves {
+        Some(slaves) => slaves.to_vec(),
+        None => match backend.slave_count() {
+            Ok(count) => (0..count as u16).map(SlavePos::from).collect(),
+            Err(err) => return vec![err],
+        },
+    };
+    slaves
+        .into_iter()
+        .filter_map(|slave| backend.request_state(slave, AlState::SafeOp).err())
+        .collect()
+}
+
+struct MissMonitor {
+    max_missed: u32,
+    missed: u32,
+    last: Option<u64>,
+}
+
+impl MissMonitor {
+    const fn new(max_missed: u32) -> Self {
+        Self {
+            max_missed,
+            missed: 0,
+            last: None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.missed = 0;
+        self.last = None;
+    }
+
+    /// Returns `true` if too many cycles passed without an exchange.
+    fn update(&mut self, exchanges: u64) -> bool {
+        if self.last == Some(exchanges) {
+            self.missed = self.missed.saturating_add(1);
+        } else {
+            self.missed = 0;
+            self.last = Some(exchanges);
+        }
+        self.missed > self.max_missed
+    }
+}
+
+#[test]
+fn test_miss_monitor() {
+    let mut monitor = MissMonitor::new(2);
+    assert!(!monitor.update(0));
+    assert!(!monitor.update(0));
+    assert!(!monitor.update(0));
+    assert!(monitor.update(0));
+    assert!(!monitor.update(1));
+    assert!(!monitor.update(1));
+    monitor.reset();
+    assert!(!monitor.update(1));
+    assert!(!monitor.update(1));
+}
+
+#[test]
+fn test_request_safe_op() {
+    use crate::backend::{SimMaster, SimSlave};
+
+    let mut master = SimMaster::new(
+        (0..3)
+            .map(|_| SimSlave::new("EL2004", SlaveId::new(2, 0x07d4_3052)))
+            .collect(),
+    );
+    master.activate().unwrap();
+    for pos in 0..3 {
+        for state in [AlState::PreOp, AlState::SafeOp, AlState::Op] {
+            master.request_state(SlavePos::from(pos), state).unwrap();
+        }
+    }
+    // a failing slave does not keep the others in Op
+    let slaves: Vec<_> = [0, 7, 2].iter().copied().map(SlavePos::from).collect();
+    assert_eq!(request_safe_op(&mut master, Some(&slaves)).len(), 1);
+    let state =
+        |master: &mut SimMaster, pos| master.slave_info(SlavePos::from(pos)).unwrap().al_state;
+    assert_eq!(state(&mut master, 0), AlState::SafeOp);
+    assert_eq!(state(&mut master, 1), AlState::Op);
+    assert_eq!(state(&mut master, 2), AlState::SafeOp);
+    assert!(request_safe_op(&mut master, None).is_empty());
+    assert_eq!(state(&mut master, 1), AlState::SafeOp);
+}
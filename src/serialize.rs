@@ -0,0 +1,190 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Serde support for the fields whose types come from `ethercat-types`,
+//! which has no serde support itself.
+//!
+//! Such fields are annotated with `#[serde(with = "crate::serialize::repr")]`
+//! and (de)serialized through a plain representation.
+
+use crate::types::*;
+use serde::{Deserialize, Serialize};
+
+/// A type that is (de)serialized through another one.
+pub(crate) trait Repr: Sized {
+    type Repr: Serialize + for<'de> Deserialize<'de>;
+
+    fn to_repr(&self) -> Self::Repr;
+
+    fn from_repr(repr: Self::Repr) -> std::result::Result<Self, String>;
+}
+
+pub(crate) mod repr {
+    use super::Repr;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T: Repr, S: Serializer>(value: &T, s: S) -> Result<S::Ok, S::Error> {
+        value.to_repr().serialize(s)
+    }
+
+    pub fn deserialize<'de, T: Repr, D: Deserializer<'de>>(d: D) -> Result<T, D::Error> {
+        T::from_repr(T::Repr::deserialize(d)?).map_err(D::Error::custom)
+    }
+}
+
+macro_rules! newtype_repr {
+    ($($t:ty: $repr:ty),*) => {
+        $(impl Repr for $t {
+            type Repr = $repr;
+
+            fn to_repr(&self) -> $repr {
+                <$repr>::from(*self)
+            }
+
+            fn from_repr(repr: $repr) -> std::result::Result<Self, String> {
+                Ok(Self::from(repr))
+            }
+        })*
+    };
+}
+
+newtype_repr!(SlavePos: u16, PdoIdx: u16, SmIdx: u8, DomainIdx: usize);
+
+/// AL states by name, e.g. `"PreOp"`.
+impl Repr for AlState {
+    type Repr = String;
+
+    fn to_repr(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    fn from_repr(repr: String) -> std::result::Result<Self, String> {
+        Ok(match repr.as_str() {
+            "Init" => AlState::Init,
+            "PreOp" => AlState::PreOp,
+            "Boot" => AlState::Boot,
+            "SafeOp" => AlState::SafeOp,
+            "Op" => AlState::Op,
+            _ => return Err(format!("invalid AL state {:?}", repr)),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PdoEntry {
+    pos: u8,
+    index: u16,
+    subindex: u8,
+    bit_len: u8,
+    name: String,
+}
+
+impl Repr for PdoEntryInfo {
+    type Repr = PdoEntry;
+
+    fn to_repr(&self) -> PdoEntry {
+        PdoEntry {
+            pos: self.pos.into(),
+            index: self.entry_idx.idx.into(),
+            subindex: self.entry_idx.sub_idx.into(),
+            bit_len: self.bit_len,
+            name: self.name.clone(),
+        }
+    }
+
+    fn from_repr(repr: PdoEntry) -> std::result::Result<Self, String> {
+        Ok(PdoEntryInfo {
+            pos: repr.pos.into(),
+            entry_idx: PdoEntryIdx::new(repr.index, repr.subindex),
+            bit_len: repr.bit_len,
+            name: repr.name,
+        })
+    }
+}
+
+impl Repr for (DomainIdx, DomainState) {
+    type Repr = (usize, DomainState);
+
+    fn to_repr(&self) -> Self::Repr {
+        (self.0.into(), self.1.clone())
+    }
+
+    fn from_repr((idx, state): Self::Repr) -> std::result::Result<Self, String> {
+        Ok((idx.into(), state))
+    }
+}
+
+impl<T: Repr> Repr for Option<T> {
+    type Repr = Option<T::Repr>;
+
+    fn to_repr(&self) -> Self::Repr {
+        self.as_ref().map(T::to_repr)
+    }
+
+    fn from_repr(repr: Self::Repr) -> std::result::Result<Self, String> {
+        repr.map(T::from_repr).transpose()
+    }
+}
+
+impl<T: Repr> Repr for Vec<T> {
+    type Repr = Vec<T::Repr>;
+
+    fn to_repr(&self) -> Self::Repr {
+        self.iter().map(T::to_repr).collect()
+    }
+
+    fn from_repr(repr: Self::Repr) -> std::result::Result<Self, String> {
+        repr.into_iter().map(T::from_repr).collect()
+    }
+}
+
+impl<A: smallvec::Array> Repr for smallvec::SmallVec<A>
+where
+    A::Item: Repr,
+{
+    type Repr = Vec<<A::Item as Repr>::Repr>;
+
+    fn to_repr(&self) -> Self::Repr {
+        self.iter().map(Repr::to_repr).collect()
+    }
+
+    fn from_repr(repr: Self::Repr) -> std::result::Result<Self, String> {
+        repr.into_iter().map(A::Item::from_repr).collect()
+    }
+}
+
+#[test]
+fn test_serde() {
+    let mut pdo = PdoCfg::new(PdoIdx::new(0x1A00));
+    pdo.entries.push(PdoEntryInfo {
+        pos: 0.into(),
+        entry_idx: PdoEntryIdx::new(0x6041, 0),
+        bit_len: 16,
+        name: "Statusword".into(),
+    });
+    let json = serde_json::to_string(&pdo).unwrap();
+    assert_eq!(
+        json,
+        r#"{"idx":6656,"entries":[{"pos":0,"index":24641,"subindex":0,"bit_len":16,"name":"Statusword"}]}"#
+    );
+    let back: PdoCfg = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.entries, pdo.entries);
+
+    let state = SlaveConfigState {
+        online: true,
+        operational: false,
+        al_state: AlState::SafeOp,
+    };
+    let json = serde_json::to_string(&state).unwrap();
+    assert_eq!(
+        json,
+        r#"{"online":true,"operational":false,"al_state":"SafeOp"}"#
+    );
+    let json = json.replace("SafeOp", "Safe");
+    assert!(serde_json::from_str::<SlaveConfigState>(&json).is_err());
+
+    let sm: SmCfg =
+        serde_json::from_str(r#"{"idx":3,"watchdog_mode":"Default","direction":"Input"}"#).unwrap();
+    assert_eq!(u8::from(sm.idx), 3);
+    assert_eq!(sm.direction, SyncDirection::Input);
+}
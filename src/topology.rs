@@ -0,0 +1,260 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+#[cfg(target_os = "linux")]
+use crate::master::Master;
+use crate::types::*;
+
+/// `next_slave` value of a port without a connected slave.
+const NO_SLAVE: u16 = 0xFFFF;
+
+/// A connection from a port of one slave to the input port of another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Link {
+    pub slave: SlavePos,
+    /// Port index, 0 to 3 for ports A to D.
+    pub port: usize,
+}
+
+/// A slave and its connections in the [`Topology`].
+#[derive(Debug, Clone)]
+pub struct TopologyNode {
+    pub slave: SlavePos,
+    /// The port of the upstream slave this slave is connected to.
+    pub parent: Option<Link>,
+    /// Downstream slaves, with the local port they are connected to, in
+    /// processing order.
+    pub children: Vec<Link>,
+    pub ports: [SlavePortInfo; MAX_PORTS],
+}
+
+/// The bus topology, reconstructed from the port information of the slaves.
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    nodes: Vec<TopologyNode>,
+}
+
+/// Name of a port as printed on the devices.
+pub fn port_name(port: usize) -> char {
+    (b'A' + port as u8) as char
+}
+
+impl Topology {
+    /// Read the slave information from the master and build the topology.
+    #[cfg(target_os = "linux")]
+    pub fn read<S>(master: &Master<S>) -> Result<Self> {
+        let count = master.get_info()?.slave_count as u16;
+        let slaves = (0..count)
+            .map(|i| master.get_slave_info(SlavePos::from(i)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::from_slaves(&slaves))
+    }
+
+    pub fn from_slaves(slaves: &[SlaveInfo]) -> Self {
+        let mut nodes: Vec<TopologyNode> = slaves
+            .iter()
+            .map(|s| TopologyNode {
+                slave: s.ring_pos,
+                parent: None,
+                children: vec![],
+                ports: s.ports,
+            })
+            .collect();
+        // Frames are forwarded from port A to D, B and C, in this order.
+        const ORDER: [usize; 3] = [3, 1, 2];
+        for i in 0..nodes.len() {
+            let parent = nodes[i].slave;
+            for port in ORDER {
+                let next = nodes[i].ports[port].next_slave;
+                if next == NO_SLAVE {
+                    continue;
+                }
+                let link = Link {
+                    slave: SlavePos::from(next),
+                    port,
+                };
+                nodes[i].children.push(link);
+                if let Some(child) = nodes.iter_mut().find(|n| u16::from(n.slave) == next) {
+                    child.parent = Some(Link {
+                        slave: parent,
+                        port,
+                    });
+                }
+            }
+        }
+        Self { nodes }
+    }
+
+    pub fn nodes(&self) -> &[TopologyNode] {
+        &self.nodes
+    }
+
+    pub fn node(&self, slave: SlavePos) -> Option<&TopologyNode> {
+        self.nodes.iter().find(|n| n.slave == slave)
+    }
+
+    /// The slave and port the given slave is connected to.
+    pub fn upstream(&self, slave: SlavePos) -> Option<Link> {
+        self.node(slave).and_then(|n| n.parent)
+    }
+
+    pub fn children(&self, slave: SlavePos) -> &[Link] {
+        self.node(slave).map(|n| &n.children[..]).unwrap_or(&[])
+    }
+
+    /// Slaves without upstream slave, normally only the first one.
+    pub fn roots(&self) -> impl Iterator<Item = SlavePos> + '_ {
+        self.nodes
+            .iter()
+            .filter(|n| n.parent.is_none())
+            .map(|n| n.slave)
+    }
+
+    /// Slaves with more than one downstream connection, e.g. junctions.
+    pub fn branches(&self) -> impl Iterator<Item = SlavePos> + '_ {
+        self.nodes
+            .iter()
+            .filter(|n| n.children.len() > 1)
+            .map(|n| n.slave)
+    }
+
+    /// Slaves without downstream connection.
+    pub fn ends(&self) -> impl Iterator<Item = SlavePos> + '_ {
+        self.nodes
+            .iter()
+            .filter(|n| n.children.is_empty())
+            .map(|n| n.slave)
+    }
+
+    /// The chain of links from the root to the given slave.
+    pub fn path(&self, slave: SlavePos) -> Vec<Link> {
+        let mut path = vec![];
+        let mut current = slave;
+        while let Some(link) = self.upstream(current) {
+            if path.len() > self.nodes.len() {
+                break; // inconsistent data, avoid looping forever
+            }
+            path.push(link);
+            current = link.slave;
+        }
+        path.reverse();
+        path
+    }
+
+    /// Render the topology in the DOT language of Graphviz.
+    ///
+    /// Nodes are labeled with the names and aliases from `slaves`, junctions
+    /// are drawn bold. Links are labeled with the ports they connect, the
+    /// port type and the DC propagation delay to the next slave.
+    pub fn to_dot(&self, slaves: &[SlaveInfo]) -> String {
+        let mut dot = String::from("digraph ethercat {\n    rankdir=LR;\n    node [shape=box];\n");
+        for node in &self.nodes {
+            let pos = u16::from(node.slave);
+            let info = slaves.iter().find(|s| s.ring_pos == node.slave);
+            let mut label = format!("{}", pos);
+            if let Some(info) = info {
+                label += &format!(": {}", escape(&info.name));
+                if info.alias != 0 {
+                    label += &format!("\\nalias {}", info.alias);
+                }
+            }
+            let style = if node.children.len() > 1 {
+                ", style=bold"
+            } else {
+                ""
+            };
+            dot += &format!("    s{} [label=\"{}\"{}];\n", pos, label, style);
+        }
+        for node in &self.nodes {
+            for child in &node.children {
+                let port = &node.ports[child.port];
+                let has_dc = slaves
+                    .iter()
+                    .any(|s| s.ring_pos == node.slave && s.has_dc_system_time);
+                let mut label = format!("{:?}", port.desc);
+                if has_dc {
+                    label += &format!("\\n{} ns", port.delay_to_next_dc);
+                }
+                dot += &format!(
+                    "    s{} -> s{} [taillabel=\"{}\", headlabel=\"A\", label=\"{}\"];\n",
+                    u16::from(node.slave),
+                    u16::from(child.slave),
+                    port_name(child.port),
+                    label
+                );
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+}
+
+/// Escape a string for a quoted DOT label.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+pub(crate) fn test_slave(pos: u16, next: [u16; 4]) -> SlaveInfo {
+    let mut ports = [SlavePortInfo::default(); MAX_PORTS];
+    for (port, next) in ports.iter_mut().zip(next.iter()) {
+        port.next_slave = *next;
+        port.desc = if *next == NO_SLAVE {
+            SlavePortType::NotConfigured
+        } else {
+            SlavePortType::EBus
+        };
+    }
+    SlaveInfo {
+        name: format!("slave {}", pos),
+        ring_pos: SlavePos::from(pos),
+        id: SlaveId::new(2, 0x044c_2c52),
+        rev: SlaveRev::new(0, 0),
+        alias: 0,
+        current_on_ebus: 0,
+        al_state: AlState::Op,
+        error_flag: 0,
+        sync_count: 0,
+        sdo_count: 0,
+        ports,
+        has_dc_system_time: true,
+        sii_nwords: 0,
+        mailbox_protocols: 0,
+    }
+}
+
+#[test]
+fn test_topology() {
+    const N: u16 = NO_SLAVE;
+    // 0 -> junction 1, which has 2 on port D and 3 on port B
+    let slaves = [
+        test_slave(0, [N, 1, N, N]),
+        test_slave(1, [0, 3, N, 2]),
+        test_slave(2, [1, N, N, N]),
+        test_slave(3, [1, N, N, N]),
+    ];
+    let topo = Topology::from_slaves(&slaves);
+    let pos = SlavePos::from;
+    assert_eq!(topo.roots().collect::<Vec<_>>(), [pos(0)]);
+    assert_eq!(topo.branches().collect::<Vec<_>>(), [pos(1)]);
+    assert_eq!(topo.ends().collect::<Vec<_>>(), [pos(2), pos(3)]);
+    assert_eq!(
+        topo.children(pos(1)),
+        [
+            Link {
+                slave: pos(2),
+                port: 3
+            },
+            Link {
+                slave: pos(3),
+                port: 1
+            }
+        ]
+    );
+    let up = topo.upstream(pos(3)).unwrap();
+    assert_eq!((up.slave, port_name(up.port)), (pos(1), 'B'));
+    assert_eq!(topo.path(pos(2)).len(), 2);
+    let dot = topo.to_dot(&slaves);
+    assert!(dot.contains("s1 [label=\"1: slave 1\", style=bold];"));
+    assert!(dot.contains("s1 -> s2 [taillabel=\"D\", headlabel=\"A\", label=\"EBus\\n0 ns\"];"));
+}
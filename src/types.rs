@@ -1,9 +1,10 @@
 // Part of ethercat-rs. Copyright 2018-2022 by the authors.
 // This work is dual-licensed under Apache 2.0 and MIT terms.
 
-use crate::ec;
+use crate::validation::ConfigProblem;
 use derive_new::new;
-use std::io;
+use smallvec::SmallVec;
+use std::{collections::BTreeMap, convert::TryFrom, fmt, io};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -22,23 +23,138 @@ pub enum Error {
     NotActivated,
     #[error("Invalid AL state 0x{0:X}")]
     InvalidAlState(u8),
+    #[error("Invalid working counter state {0}")]
+    InvalidWcState(u32),
+    #[error("Invalid port type {0}")]
+    InvalidPortType(u32),
     #[error("SDO/VoE/register request failed")]
     RequestFailed,
+    #[error("Distributed clocks did not settle in time")]
+    DcTimeout,
+    #[error("SoE request failed with error code 0x{0:04X}")]
+    SoeError(u16),
+    #[error("Field outside of the snapshots of domain {0}")]
+    OutsideSnapshot(usize),
+    #[error("Invalid configuration: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    InvalidConfig(Vec<ConfigProblem>),
+    #[error("ioctl {op} failed{context}: {source}")]
+    Ioctl {
+        /// Name of the ioctl, e.g. `SLAVE_SDO_UPLOAD`.
+        op: &'static str,
+        context: ErrorContext,
+        source: io::Error,
+    },
+    #[error(transparent)]
+    SdoAbort(SdoAbort),
     #[error(transparent)]
     Io(#[from] io::Error),
 }
 
+impl Error {
+    /// The I/O error behind this error, e.g. to check its kind or errno.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            Error::Io(e) | Error::Ioctl { source: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl From<Error> for io::Error {
     fn from(e: Error) -> Self {
         io::Error::new(io::ErrorKind::Other, e)
     }
 }
 
+/// What a failed request to the master was about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorContext {
+    Master,
+    Slave(SlavePos),
+    Domain(DomainIdx),
+    Config(SlaveConfigIdx),
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorContext::Master => Ok(()),
+            ErrorContext::Slave(slave) => write!(f, " for slave {}", u16::from(slave)),
+            ErrorContext::Domain(domain) => write!(f, " for domain {}", usize::from(domain)),
+            ErrorContext::Config(config) => write!(f, " for config {}", config),
+        }
+    }
+}
+
+/// An SDO transfer aborted by a slave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SdoAbort {
+    pub slave: SlavePos,
+    pub sdo: SdoIdx,
+    /// Abort code of CiA 301.
+    pub code: u32,
+}
+
+impl SdoAbort {
+    /// Description of the abort code, if it is a standard one.
+    pub fn message(&self) -> Option<&'static str> {
+        Some(match self.code {
+            0x0503_0000 => "toggle bit not alternated",
+            0x0504_0000 => "SDO protocol timed out",
+            0x0504_0001 => "client/server command specifier not valid or unknown",
+            0x0504_0005 => "out of memory",
+            0x0601_0000 => "unsupported access to an object",
+            0x0601_0001 => "attempt to read a write only object",
+            0x0601_0002 => "attempt to write a read only object",
+            0x0602_0000 => "object does not exist in the object dictionary",
+            0x0604_0041 => "object cannot be mapped to the PDO",
+            0x0604_0042 => "the mapped objects would exceed the PDO length",
+            0x0604_0043 => "general parameter incompatibility",
+            0x0604_0047 => "general internal incompatibility in the device",
+            0x0606_0000 => "access failed due to a hardware error",
+            0x0607_0010 => "data type does not match, length of service parameter does not match",
+            0x0607_0012 => "data type does not match, length of service parameter too high",
+            0x0607_0013 => "data type does not match, length of service parameter too low",
+            0x0609_0011 => "subindex does not exist",
+            0x0609_0030 => "value range of parameter exceeded",
+            0x0609_0031 => "value of parameter written too high",
+            0x0609_0032 => "value of parameter written too low",
+            0x0609_0036 => "maximum value is less than minimum value",
+            0x0800_0000 => "general error",
+            0x0800_0020 => "data cannot be transferred or stored to the application",
+            0x0800_0021 => "data cannot be transferred or stored because of local control",
+            0x0800_0022 => "data cannot be transferred or stored in the present device state",
+            0x0800_0023 => "no object dictionary present",
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for SdoAbort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "SDO 0x{:04X}:{:02X} of slave {} aborted with 0x{:08X}",
+            u16::from(self.sdo.idx),
+            u8::from(self.sdo.sub_idx),
+            u16::from(self.slave),
+            self.code
+        )?;
+        if let Some(message) = self.message() {
+            write!(f, " ({})", message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SdoAbort {}
+
 pub use ethercat_types::*;
 
 pub type Result<T> = std::result::Result<T, Error>;
 pub type MasterIdx = u32;
 
+#[cfg(target_os = "linux")]
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct DomainDataPlacement {
     pub offset: usize,
@@ -48,14 +164,16 @@ pub(crate) struct DomainDataPlacement {
 pub type SlaveConfigIdx = u32;
 
 /// An EtherCAT slave identification, consisting of vendor ID and product code.
-#[derive(Debug, Clone, Copy, new)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, new)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SlaveId {
     pub vendor_id: u32,
     pub product_code: u32,
 }
 
 /// An EtherCAT slave revision identification.
-#[derive(Debug, Clone, Copy, new)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, new)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SlaveRev {
     pub revision_number: u32,
     pub serial_number: u32,
@@ -64,11 +182,13 @@ pub struct SlaveRev {
 /// An EtherCAT slave, which is specified either by absolute position in the
 /// ring or by offset from a given alias.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SlaveAddr {
     ByPos(u16),
     ByAlias(u16, u16),
 }
 
+#[cfg(target_os = "linux")]
 impl SlaveAddr {
     pub(crate) fn as_pair(self) -> (u16, u16) {
         match self {
@@ -79,48 +199,137 @@ impl SlaveAddr {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MasterInfo {
     pub slave_count: u32,
+    pub config_count: u32,
+    pub domain_count: u32,
     pub link_up: bool,
     pub scan_busy: bool,
     pub app_time: u64,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MasterState {
     pub slaves_responding: u32,
     pub al_states: u8,
     pub link_up: bool,
 }
 
+/// Summary of the state of the bus, see [`Master::health`](crate::Master::health).
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BusHealth {
+    pub link_up: bool,
+    pub slaves_responding: u32,
+    pub slaves_configured: u32,
+    /// Lowest AL state of the responding slaves.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::repr"))]
+    pub worst_al_state: Option<AlState>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::repr"))]
+    pub domains: Vec<(DomainIdx, DomainState)>,
+    /// Largest absolute system time difference of the DC slaves in ns.
+    pub dc_deviation: Option<u32>,
+    /// Emergencies lost because the rings of the slave configs were full.
+    pub emergency_overruns: u32,
+    /// Faults of the power supplies, reported by the drivers of power and
+    /// diagnostic terminals.
+    pub power_faults: Vec<PowerFault>,
+}
+
+impl BusHealth {
+    /// All configured slaves respond and are in Op, and all domains are
+    /// complete.
+    pub fn is_ok(&self) -> bool {
+        self.link_up
+            && self.slaves_responding >= self.slaves_configured
+            && self.worst_al_state == Some(AlState::Op)
+            && self
+                .domains
+                .iter()
+                .all(|(_, d)| d.wc_state == WcState::Complete)
+            && self.power_faults.is_empty()
+    }
+}
+
+/// A fault of a supply, e.g. reported by an EL9410 power terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PowerFault {
+    /// The terminal reporting the fault.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::repr"))]
+    pub slave: SlavePos,
+    /// The supply or output channel of the terminal, counted from 0.
+    pub channel: usize,
+    pub kind: PowerFaultKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PowerFaultKind {
+    Undervoltage,
+    Overload,
+    /// An electronic fuse switched the output off.
+    Tripped,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConfigInfo {
     pub alias: u16,
     pub position: u16,
     pub id: SlaveId,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::repr"))]
     pub slave_position: Option<SlavePos>,
     pub sdo_count: u32,
     pub idn_count: u32,
+    /// AssignActivate word of the DC configuration, 0 if DC is not used.
+    pub dc_assign_activate: u16,
+    /// The configured sync managers.
+    pub syncs: Vec<ConfigSyncInfo>,
     // TODO: more attributes are returned:
-    // syncs[*], watchdog_*, dc_*
+    // watchdog_*, dc_sync
+}
+
+/// A sync manager of a slave configuration.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConfigSyncInfo {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::repr"))]
+    pub idx: SmIdx,
+    pub direction: SyncDirection,
+    /// Number of PDOs assigned to the sync manager.
+    pub pdo_count: u32,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SlaveInfo {
     pub name: String,
-    pub ring_pos: u16,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::repr"))]
+    pub ring_pos: SlavePos,
     pub id: SlaveId,
     pub rev: SlaveRev,
     pub alias: u16,
     pub current_on_ebus: i16,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::repr"))]
     pub al_state: AlState,
     pub error_flag: u8,
     pub sync_count: u8,
     pub sdo_count: u16,
-    pub ports: [SlavePortInfo; ec::EC_MAX_PORTS as usize],
+    pub ports: [SlavePortInfo; MAX_PORTS],
+    /// The slave supports distributed clocks with a 64 bit system time.
+    pub has_dc_system_time: bool,
+    /// Size of the SII (EEPROM) image in 16 bit words.
+    pub sii_nwords: u32,
+    /// Supported mailbox protocols as a bit set: AoE 0x01, EoE 0x02,
+    /// CoE 0x04, FoE 0x08, SoE 0x10, VoE 0x20.
+    pub mailbox_protocols: u16,
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SlavePortType {
     NotImplemented,
     NotConfigured,
@@ -136,13 +345,18 @@ impl Default for SlavePortType {
 }
 
 #[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SlavePortLink {
     pub link_up: bool,
     pub loop_closed: bool,
     pub signal_detected: bool,
 }
 
+/// Number of ports of a slave, as in the IgH master.
+pub const MAX_PORTS: usize = 4;
+
 #[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SlavePortInfo {
     pub desc: SlavePortType,
     pub link: SlavePortLink,
@@ -152,13 +366,16 @@ pub struct SlavePortInfo {
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SlaveConfigState {
     pub online: bool,
     pub operational: bool,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::repr"))]
     pub al_state: AlState,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SyncDirection {
     Invalid,
     Output,
@@ -166,6 +383,7 @@ pub enum SyncDirection {
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum WatchdogMode {
     Default,
     Enable,
@@ -174,7 +392,9 @@ pub enum WatchdogMode {
 
 /// Sync Manager Info
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SmInfo {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::repr"))]
     pub idx: SmIdx,
     pub start_addr: u16,
     pub default_size: u16,
@@ -185,7 +405,9 @@ pub struct SmInfo {
 
 /// Sync Manager Config
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SmCfg {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::repr"))]
     pub idx: SmIdx,
     pub watchdog_mode: WatchdogMode,
     pub direction: SyncDirection,
@@ -208,18 +430,28 @@ impl SmCfg {
     }
 }
 
+/// The entries of a PDO.
+///
+/// Most PDOs map only a few entries, which are stored inline, so setting up
+/// a bus with hundreds of PDOs does not need an allocation for each of them.
+/// Build it with `.collect()`, `push`, or from a `Vec` with `into()`.
+pub type PdoEntries = SmallVec<[PdoEntryInfo; 4]>;
+
 /// PDO Config
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PdoCfg {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::repr"))]
     pub idx: PdoIdx,
-    pub entries: Vec<PdoEntryInfo>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::repr"))]
+    pub entries: PdoEntries,
 }
 
 impl PdoCfg {
     pub const fn new(idx: PdoIdx) -> PdoCfg {
         Self {
             idx,
-            entries: vec![],
+            entries: SmallVec::new_const(),
         }
     }
 }
@@ -254,19 +486,61 @@ impl SdoData for &'_ [u8] {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DomainState {
     pub working_counter: u32,
     pub wc_state: WcState,
     pub redundancy_active: bool,
 }
 
+/// SDO transfer statistics of a slave, see
+/// [`Master::sdo_stats`](crate::Master::sdo_stats).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SdoStats {
+    pub attempts: u64,
+    /// Transfers of an object whose previous transfer failed.
+    pub retries: u64,
+    pub timeouts: u64,
+    pub aborts: u64,
+    /// Failures that are neither timeouts nor aborts.
+    pub errors: u64,
+    /// Number of aborts per abort code.
+    pub abort_codes: BTreeMap<u32, u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DomainInfo {
+    pub data_size: usize,
+    pub logical_base_address: u32,
+    pub working_counter: u16,
+    pub expected_working_counter: u16,
+    pub fmmu_count: u32,
+}
+
+/// An FMMU mapping process data of a slave into a domain.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DomainFmmuInfo {
+    pub slave_config_alias: u16,
+    pub slave_config_position: u16,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::repr"))]
+    pub sync_index: SmIdx,
+    pub direction: SyncDirection,
+    pub logical_address: u32,
+    pub data_size: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum WcState {
     Zero = 0,
     Incomplete,
     Complete,
 }
 
+#[cfg(target_os = "linux")]
 pub(crate) fn get_sdo_entry_access(read: [u8; 3], write: [u8; 3]) -> SdoEntryAccess {
     SdoEntryAccess {
         pre_op: access(read[0], write[0]),
@@ -275,6 +549,7 @@ pub(crate) fn get_sdo_entry_access(read: [u8; 3], write: [u8; 3]) -> SdoEntryAcc
     }
 }
 
+#[cfg(target_os = "linux")]
 fn access(read: u8, write: u8) -> Access {
     match (read, write) {
         (1, 0) => Access::ReadOnly,
@@ -284,13 +559,38 @@ fn access(read: u8, write: u8) -> Access {
     }
 }
 
-impl From<u32> for WcState {
-    fn from(st: u32) -> Self {
+impl TryFrom<u32> for WcState {
+    type Error = Error;
+
+    fn try_from(st: u32) -> Result<Self> {
         match st {
-            0 => WcState::Zero,
-            1 => WcState::Incomplete,
-            2 => WcState::Complete,
-            x => panic!("invalid state {}", x),
+            0 => Ok(WcState::Zero),
+            1 => Ok(WcState::Incomplete),
+            2 => Ok(WcState::Complete),
+            x => Err(Error::InvalidWcState(x)),
         }
     }
 }
+
+#[test]
+fn test_error_messages() {
+    let e = Error::Ioctl {
+        op: "SLAVE_REG_READ",
+        context: ErrorContext::Slave(SlavePos::from(3)),
+        source: io::Error::from_raw_os_error(5),
+    };
+    assert!(e
+        .to_string()
+        .starts_with("ioctl SLAVE_REG_READ failed for slave 3: "));
+    assert_eq!(e.io_error().and_then(io::Error::raw_os_error), Some(5));
+    let e = Error::SdoAbort(SdoAbort {
+        slave: SlavePos::from(1),
+        sdo: SdoIdx::new(0x8000, 0x06),
+        code: 0x0609_0011,
+    });
+    assert_eq!(
+        e.to_string(),
+        "SDO 0x8000:06 of slave 1 aborted with 0x06090011 (subindex does not exist)"
+    );
+    assert!(e.io_error().is_none());
+}
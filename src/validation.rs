@@ -0,0 +1,243 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! Checks of the configuration before activation.
+//!
+//! The IgH master accepts any configuration and only complains on the bus,
+//! e.g. with a working counter that never matches. The problems found here
+//! can be reported at once, with the configuration they belong to.
+
+use crate::types::*;
+use std::fmt;
+
+/// A problem of the configuration, found before activation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigProblem {
+    /// No slave on the bus matches the address and identity of a
+    /// configuration.
+    Unmatched {
+        config: SlaveConfigIdx,
+        alias: u16,
+        position: u16,
+    },
+    /// A sync manager is assigned without any PDO.
+    EmptySyncManager { config: SlaveConfigIdx, sm: SmIdx },
+    /// A registered PDO entry exceeds the process image of its domain.
+    EntryOutsideDomain {
+        config: SlaveConfigIdx,
+        entry: PdoEntryIdx,
+        domain: DomainIdx,
+        offset: Offset,
+        domain_size: usize,
+    },
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigProblem::Unmatched {
+                config,
+                alias,
+                position,
+            } => write!(
+                f,
+                "config {}: no matching slave at alias {} position {}",
+                config, alias, position
+            ),
+            ConfigProblem::EmptySyncManager { config, sm } => write!(
+                f,
+                "config {}: sync manager {} has no PDO",
+                config,
+                u8::from(sm)
+            ),
+            ConfigProblem::EntryOutsideDomain {
+                config,
+                entry,
+                domain,
+                offset,
+                domain_size,
+            } => write!(
+                f,
+                "config {}: entry {:#06x}:{:02x} at byte {} bit {} exceeds the {} bytes of domain {}",
+                config,
+                u16::from(entry.idx),
+                u8::from(entry.sub_idx),
+                offset.byte,
+                offset.bit,
+                domain_size,
+                usize::from(domain)
+            ),
+        }
+    }
+}
+
+/// A PDO entry registered in a domain.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RegisteredEntry {
+    pub config: SlaveConfigIdx,
+    pub entry: PdoEntryIdx,
+    pub domain: DomainIdx,
+    pub offset: Offset,
+}
+
+/// Check the configurations, the entries registered in the domains and the
+/// sizes of the domains.
+///
+/// Only the sync managers in `assigned`, whose PDOs the application set,
+/// must have a PDO: the default configuration of a slave has sync managers
+/// without any, e.g. for the mailbox.
+///
+/// `mapped` holds the configured PDO entries with their sizes in bits. An
+/// entry of the default mapping of a slave is not known there, it must at
+/// least start in its domain.
+pub(crate) fn check(
+    configs: &[(SlaveConfigIdx, ConfigInfo)],
+    assigned: &[(SlaveConfigIdx, SmIdx)],
+    mapped: &[(SlaveConfigIdx, PdoEntryIdx, u8)],
+    registered: &[RegisteredEntry],
+    domain_sizes: &[usize],
+) -> Vec<ConfigProblem> {
+    let mut problems = vec![];
+    for (idx, config) in configs {
+        if config.slave_position.is_none() {
+            problems.push(ConfigProblem::Unmatched {
+                config: *idx,
+                alias: config.alias,
+                position: config.position,
+            });
+        }
+        let empty = config
+            .syncs
+            .iter()
+            .filter(|s| s.pdo_count == 0 && assigned.contains(&(*idx, s.idx)));
+        for sync in empty {
+            problems.push(ConfigProblem::EmptySyncManager {
+                config: *idx,
+                sm: sync.idx,
+            });
+        }
+    }
+    for reg in registered {
+        let bits = mapped
+            .iter()
+            .find(|(config, entry, _)| *config == reg.config && *entry == reg.entry)
+            .map_or(1, |(_, _, bits)| usize::from(*bits));
+        let domain_size = domain_sizes
+            .get(usize::from(reg.domain))
+            .copied()
+            .unwrap_or(0);
+        if reg.offset.byte * 8 + reg.offset.bit as usize + bits > domain_size * 8 {
+            problems.push(ConfigProblem::EntryOutsideDomain {
+                config: reg.config,
+                entry: reg.entry,
+                domain: reg.domain,
+                offset: reg.offset,
+                domain_size,
+            });
+        }
+    }
+    problems
+}
+
+#[test]
+fn test_check_configuration() {
+    let config = |position, matched, pdo_counts: &[u32]| ConfigInfo {
+        alias: 0,
+        position,
+        id: SlaveId::new(2, 0x07d4_3052),
+        slave_position: if matched {
+            Some(SlavePos::from(position))
+        } else {
+            None
+        },
+        sdo_count: 0,
+        idn_count: 0,
+        dc_assign_activate: 0,
+        syncs: pdo_counts
+            .iter()
+            .enumerate()
+            .map(|(i, n)| ConfigSyncInfo {
+                idx: SmIdx::from(2 + i as u8),
+                direction: SyncDirection::Output,
+                pdo_count: *n,
+            })
+            .collect(),
+    };
+    let configs = [
+        (0, config(0, true, &[1, 2])),
+        (1, config(1, false, &[1, 0])),
+    ];
+    let assigned: Vec<_> = (0..2)
+        .flat_map(|config| (2..4).map(move |sm| (config, SmIdx::from(sm))))
+        .collect();
+    let entry = PdoEntryIdx::new(0x7000, 1);
+    let mapped = [(0, entry, 16), (1, entry, 1)];
+    let registered = |config, byte, bit| RegisteredEntry {
+        config,
+        entry,
+        domain: DomainIdx::from(0),
+        offset: Offset { byte, bit },
+    };
+    let problems = check(
+        &configs,
+        &assigned,
+        &mapped,
+        &[
+            registered(0, 2, 0),
+            registered(0, 3, 0),
+            registered(1, 3, 7),
+            registered(1, 4, 0),
+        ],
+        &[4],
+    );
+    let lines: Vec<_> = problems.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        lines,
+        [
+            "config 1: no matching slave at alias 0 position 1",
+            "config 1: sync manager 3 has no PDO",
+            "config 0: entry 0x7000:01 at byte 3 bit 0 exceeds the 4 bytes of domain 0",
+            "config 1: entry 0x7000:01 at byte 4 bit 0 exceeds the 4 bytes of domain 0",
+        ]
+    );
+    assert!(Error::InvalidConfig(problems)
+        .to_string()
+        .starts_with("Invalid configuration: config 1: no matching slave"));
+}
+
+#[test]
+fn test_check_default_syncs() {
+    // the default configuration of a mailbox slave with inputs only
+    let syncs = [
+        (0, SyncDirection::Output, 0),
+        (1, SyncDirection::Input, 0),
+        (2, SyncDirection::Output, 0),
+        (3, SyncDirection::Input, 1),
+    ];
+    let config = ConfigInfo {
+        alias: 0,
+        position: 0,
+        id: SlaveId::new(2, 0x0c1e_3052),
+        slave_position: Some(SlavePos::from(0)),
+        sdo_count: 0,
+        idn_count: 0,
+        dc_assign_activate: 0,
+        syncs: syncs
+            .iter()
+            .map(|&(idx, direction, pdo_count)| ConfigSyncInfo {
+                idx: SmIdx::from(idx),
+                direction,
+                pdo_count,
+            })
+            .collect(),
+    };
+    let configs = [(0, config)];
+    assert!(check(&configs, &[], &[], &[], &[]).is_empty());
+
+    // unless the application assigned the PDOs of a sync manager itself
+    let sm = SmIdx::from(2);
+    assert_eq!(
+        check(&configs, &[(0, sm)], &[], &[], &[]),
+        [ConfigProblem::EmptySyncManager { config: 0, sm }]
+    );
+}
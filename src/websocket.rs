@@ -0,0 +1,303 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! WebSocket endpoint for live monitoring, e.g. from a browser dashboard.
+//!
+//! Every period, all connected clients receive a text message with a JSON
+//! object like
+//!
+//! ```json
+//! {"time":1650000000.5,
+//!  "cycles":[{"domain":0,"seq":1200,"dc_time":715000000000}],
+//!  "fields":{"position":1024,"ready":true},
+//!  "health":{"ok":true,"link_up":true,...}}
+//! ```
+//!
+//! Field values come from [`SnapshotReader`]s and the health from a closure,
+//! usually calling [`MasterMonitor::health`](crate::MasterMonitor::health),
+//! so the monitor never touches the cyclic thread. That health has no
+//! emergency overruns, which only the [`Master`](crate::Master) can read.
+//! Messages from clients are ignored.
+
+use crate::{
+    field::Field,
+    json,
+    logging::LogValue,
+    runtime::{snapshot_index, Snapshot, SnapshotReader},
+    types::*,
+};
+use std::{
+    io::{self, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tungstenite::{Message, WebSocket};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+
+type WriteValue = Box<dyn Fn(&[u8], &mut dyn Write) -> io::Result<()> + Send>;
+type HealthFn = Box<dyn FnMut() -> Result<BusHealth> + Send>;
+
+struct MonitorField {
+    name: String,
+    /// Index into the snapshot readers.
+    domain: usize,
+    write: WriteValue,
+}
+
+/// Builder for a [`WebSocketMonitor`].
+pub struct WebSocketMonitorBuilder {
+    snapshots: Vec<(DomainIdx, SnapshotReader)>,
+    fields: Vec<MonitorField>,
+    health: Option<HealthFn>,
+    period: Duration,
+}
+
+/// A WebSocket server streaming field values and bus health.
+pub struct WebSocketMonitor {
+    listener: TcpListener,
+    snapshots: Vec<(DomainIdx, SnapshotReader)>,
+    fields: Vec<MonitorField>,
+    health: Option<HealthFn>,
+    period: Duration,
+    clients: Vec<WebSocket<TcpStream>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl WebSocketMonitorBuilder {
+    /// Publish the snapshots of a domain, for the fields of this domain.
+    pub fn snapshot(mut self, domain: DomainIdx, reader: SnapshotReader) -> Self {
+        self.snapshots.push((domain, reader));
+        self
+    }
+
+    /// Stream a PDO entry under a name.
+    ///
+    /// Fails if no snapshot of the field's domain was added, see
+    /// [`snapshot_index`].
+    pub fn field<T: LogValue>(mut self, name: impl Into<String>, field: Field<T>) -> Result<Self> {
+        let domain = snapshot_index(&self.snapshots, &field)?;
+        self.fields.push(MonitorField {
+            name: name.into(),
+            domain,
+            write: Box::new(move |data, out| write_value(out, field.get(data))),
+        });
+        Ok(self)
+    }
+
+    /// Stream the bus health returned by `f`.
+    pub fn health<F>(mut self, f: F) -> Self
+    where
+        F: FnMut() -> Result<BusHealth> + Send + 'static,
+    {
+        self.health = Some(Box::new(f));
+        self
+    }
+
+    /// Interval between two messages, 100 ms by default.
+    pub fn period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    /// Listen on `addr`; clients connect to any path.
+    pub fn bind<A: ToSocketAddrs>(self, addr: A) -> io::Result<WebSocketMonitor> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(WebSocketMonitor {
+            listener,
+            snapshots: self.snapshots,
+            fields: self.fields,
+            health: self.health,
+            period: self.period,
+            clients: vec![],
+            stop: Arc::new(AtomicBool::new(false)),
+        })
+    }
+}
+
+impl WebSocketMonitor {
+    pub fn builder() -> WebSocketMonitorBuilder {
+        WebSocketMonitorBuilder {
+            snapshots: vec![],
+            fields: vec![],
+            health: None,
+            period: Duration::from_millis(100),
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Returns a flag that makes [`run`](Self::run) return when set.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Accept clients and send them messages until the stop flag is set.
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut next = Instant::now();
+        while !self.stop.load(Ordering::Acquire) {
+            self.accept()?;
+            if !self.clients.is_empty() {
+                let message = self.message()?;
+                self.clients = self
+                    .clients
+                    .drain(..)
+                    .filter_map(|mut client| {
+                        let sent = client.write_message(Message::Text(message.clone()));
+                        sent.ok().map(|_| client)
+                    })
+                    .collect();
+            }
+            next += self.period;
+            thread::sleep(next.saturating_duration_since(Instant::now()));
+        }
+        Ok(())
+    }
+
+    fn accept(&mut self) -> io::Result<()> {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            // a slow client must not hold up the others for long
+            stream.set_nonblocking(false)?;
+            stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+            stream.set_write_timeout(Some(self.period))?;
+            match tungstenite::accept(stream) {
+                Ok(client) => self.clients.push(client),
+                Err(e) => log::debug!("WebSocket handshake failed: {}", e),
+            }
+        }
+    }
+
+    /// The JSON message with the current values.
+    fn message(&mut self) -> io::Result<String> {
+        let snapshots: Vec<Snapshot> = self.snapshots.iter().map(|(_, r)| r.read()).collect();
+        let health = match &mut self.health {
+            Some(f) => match f() {
+                Ok(health) => Some(health),
+                Err(e) => {
+                    log::debug!("Could not read the bus health: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let mut out = vec![];
+        write_message(
+            &mut out,
+            &self.snapshots,
+            &snapshots,
+            &self.fields,
+            health.as_ref(),
+        )?;
+        Ok(String::from_utf8(out).expect("JSON is UTF-8"))
+    }
+}
+
+fn write_message(
+    out: &mut dyn Write,
+    readers: &[(DomainIdx, SnapshotReader)],
+    snapshots: &[Snapshot],
+    fields: &[MonitorField],
+    health: Option<&BusHealth>,
+) -> io::Result<()> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    write!(out, "{{\"time\":{},\"cycles\":[", time)?;
+    for (i, ((idx, _), snapshot)) in readers.iter().zip(snapshots).enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        write!(
+            out,
+            "{{\"domain\":{},\"seq\":{},\"dc_time\":{}}}",
+            usize::from(*idx),
+            snapshot.seq,
+            snapshot.dc_time
+        )?;
+    }
+    write!(out, "],\"fields\":{{")?;
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        json::write_str(out, &field.name)?;
+        write!(out, ":")?;
+        (field.write)(&snapshots[field.domain].data, out)?;
+    }
+    write!(out, "}}")?;
+    if let Some(health) = health {
+        write!(out, ",\"health\":")?;
+        json::write_health(out, health)?;
+    }
+    write!(out, "}}")
+}
+
+fn write_value<T: LogValue>(out: &mut dyn Write, value: T) -> io::Result<()> {
+    // JSON has no NaN or infinity
+    let text = value.to_string();
+    if matches!(text.as_str(), "NaN" | "inf" | "-inf") {
+        write!(out, "null")
+    } else {
+        write!(out, "{}", text)
+    }
+}
+
+#[test]
+fn test_websocket_monitor() {
+    use crate::runtime::snapshot_buffer;
+
+    let (mut writer, reader) = snapshot_buffer(4);
+    writer.publish(3, 500, &[0x34, 0x12, 0, 0]);
+    let mut monitor = WebSocketMonitor::builder()
+        .snapshot(DomainIdx::from(0), reader)
+        .field(
+            "word",
+            Field::<u16>::new(0.into(), Offset { byte: 0, bit: 0 }),
+        )
+        .unwrap()
+        .field(
+            "ready",
+            Field::<bool>::new(0.into(), Offset { byte: 2, bit: 0 }),
+        )
+        .unwrap()
+        .health(|| {
+            Ok(BusHealth {
+                link_up: true,
+                slaves_responding: 1,
+                slaves_configured: 1,
+                worst_al_state: Some(AlState::Op),
+                domains: vec![],
+                dc_deviation: None,
+                emergency_overruns: 0,
+                power_faults: vec![],
+            })
+        })
+        .period(Duration::from_millis(10))
+        .bind("127.0.0.1:0")
+        .unwrap();
+    let addr = monitor.local_addr().unwrap();
+    let stop = monitor.stop_handle();
+    let server = thread::spawn(move || monitor.run());
+
+    let (mut client, _) = tungstenite::connect(format!("ws://{}/", addr)).unwrap();
+    let message = client.read_message().unwrap().into_text().unwrap();
+    assert!(message.contains(r#""cycles":[{"domain":0,"seq":3,"dc_time":500}]"#));
+    assert!(message.contains(r#""fields":{"word":4660,"ready":false}"#));
+    assert!(message.contains(r#""health":{"ok":true,"#));
+    stop.store(true, Ordering::Release);
+    server.join().unwrap().unwrap();
+}
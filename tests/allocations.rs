@@ -0,0 +1,141 @@
+// Part of ethercat-rs. Copyright 2018-2022 by the authors.
+// This work is dual-licensed under Apache 2.0 and MIT terms.
+
+//! The cyclic exchange must not allocate: a realtime thread cannot wait for
+//! the allocator. The exchange with the IgH master needs the kernel module,
+//! so the same path is run on a simulated bus: receive, field and driver
+//! access, send.
+//!
+//! For the same reason, [`Executor`](ethercat::runtime::Executor) itself is
+//! not run here, only the bookkeeping it does in each cycle around the
+//! exchange: statistics, events for the hooks and the publication of the
+//! process image.
+
+use ethercat::{
+    backend::{Backend, SimMaster, SimSlave},
+    devices::{AnalogOutputs, DeviceRegistry, DigitalOutputs},
+    runtime::{command_channel, snapshot_buffer, CycleStats, ExecutorEvent},
+    DomainIdx, Field, Offset, PdoCfg, PdoEntryIdx, PdoEntryInfo, PdoEntryPos, PdoIdx, SlaveId,
+    SlavePos,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    time::Duration,
+};
+
+/// Counts the allocations of the current thread.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = Cell::new(0);
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn test_cycle_does_not_allocate() {
+    let pdo = |idx: u16, entry: u16, sub, bit_len| {
+        let mut pdo = PdoCfg::new(PdoIdx::from(idx));
+        pdo.entries = vec![PdoEntryInfo {
+            entry_idx: PdoEntryIdx::new(entry, sub),
+            bit_len,
+            name: String::new(),
+            pos: PdoEntryPos::from(0),
+        }]
+        .into();
+        pdo
+    };
+    let el1002 = SimSlave::new("EL1002", SlaveId::new(2, 0x03ea_3052))
+        .tx_pdo(pdo(0x1A00, 0x6000, 1, 1))
+        .tx_pdo(pdo(0x1A01, 0x6010, 1, 1));
+    let el2004 =
+        SimSlave::new("EL2004", SlaveId::new(2, 0x07d4_3052)).rx_pdo(pdo(0x1600, 0x7000, 1, 4));
+    let el4132 = SimSlave::new("EL4132", SlaveId::new(2, 0x1024_3052))
+        .rx_pdo(pdo(0x1600, 0x7000, 0x11, 16))
+        .rx_pdo(pdo(0x1601, 0x7010, 0x11, 16));
+    let mut master = SimMaster::new(vec![el1002, el2004, el4132]);
+    master.activate().unwrap();
+    let mut devices = DeviceRegistry::with_builtin().bind(&mut master).unwrap();
+    let domain = DomainIdx::from(0);
+    let input = Field::<bool>::new(domain, Offset { byte: 0, bit: 1 });
+    let analog = Field::<i16>::new(domain, Offset { byte: 2, bit: 0 });
+
+    let mut cycle = |n: u8| {
+        master.receive().unwrap();
+        let mut view = master.domain_view(domain).unwrap();
+        let on = view.get(&input);
+        devices
+            .get_mut::<DigitalOutputs>(SlavePos::from(1))
+            .unwrap()
+            .set(0, on);
+        devices
+            .get_mut::<AnalogOutputs>(SlavePos::from(2))
+            .unwrap()
+            .set(0, f64::from(n) / 10.0);
+        devices.process(domain, view.data_mut());
+        assert_eq!(view.get(&analog) > 0, n > 0);
+        master.send().unwrap();
+    };
+    // the first exchange may set up the backend
+    cycle(0);
+    assert_eq!(allocations(|| (1..100).for_each(&mut cycle)), 0);
+}
+
+#[test]
+fn test_executor_bookkeeping_does_not_allocate() {
+    let mut stats = CycleStats::new(100);
+    let (events, mut hooks) = command_channel(16);
+    let (mut writer, reader) = snapshot_buffer(64);
+    #[cfg(target_os = "linux")]
+    let name = format!("ethercat-allocations-{}", std::process::id());
+    #[cfg(target_os = "linux")]
+    let (mut image, barrier) = (
+        ethercat::runtime::SharedImage::builder(name.as_str(), DomainIdx::from(0))
+            .create(64)
+            .unwrap(),
+        ethercat::runtime::CycleBarrier::create(&name).unwrap(),
+    );
+    let data = [0x5A; 64];
+
+    let mut cycle = |n: u64| {
+        let latency = Duration::from_micros(n);
+        stats.record([latency; 5]);
+        // dropped once full, like the executor does if the hooks lag
+        let _ = events.try_send(ExecutorEvent::Jitter { cycle: n, latency });
+        writer.publish(n, n * 1000, &data);
+        #[cfg(target_os = "linux")]
+        {
+            image.publish(n, n * 1000, &data);
+            barrier.signal(n);
+        }
+    };
+    cycle(0);
+    assert_eq!(allocations(|| (1..1000).for_each(&mut cycle)), 0);
+    assert_eq!(stats.len(), 100);
+    assert_eq!(hooks.drain().count(), 16);
+    assert_eq!(reader.read().seq, 999);
+}